@@ -0,0 +1,162 @@
+//! Generated tags, shaped like the ones found in real libraries, for the benchmarks to share
+
+// Not every benchmark uses every kind of tag
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// An ID3v2.4 tag holding the frames, without padding
+pub fn tag(frames: &[(&[u8; 4], Vec<u8>)]) -> Vec<u8> {
+   let mut body = Vec::new();
+   for (name, payload) in frames {
+      body.extend_from_slice(*name);
+      body.extend_from_slice(&synchsafe(payload.len()));
+      body.extend_from_slice(&[0, 0]);
+      body.extend_from_slice(payload);
+   }
+   let mut tag = b"ID3\x04\x00\x00".to_vec();
+   tag.extend_from_slice(&synchsafe(body.len()));
+   tag.extend(body);
+   tag
+}
+
+fn synchsafe(size: usize) -> [u8; 4] {
+   let size = size as u32;
+   [
+      (size >> 21) as u8 & 0x7f,
+      (size >> 14) as u8 & 0x7f,
+      (size >> 7) as u8 & 0x7f,
+      size as u8 & 0x7f,
+   ]
+}
+
+/// The payload of a text frame. Mostly ASCII, like nearly all real tags, with a few bytes that have to be converted.
+pub fn latin1(repeat: usize) -> Vec<u8> {
+   let mut text = vec![0];
+   for _ in 0..repeat {
+      text.extend_from_slice(b"Caf\xe9 del Mar \x96 Volumen Cinco, \x93Chill Out Session\x94 ");
+   }
+   text
+}
+
+/// The payload of a text frame, in little endian UTF-16 with a byte order mark
+pub fn utf16(repeat: usize) -> Vec<u8> {
+   let mut text = vec![1, 0xff, 0xfe];
+   for _ in 0..repeat {
+      for unit in "Caf\u{e9} del Mar \u{2013} Volumen Cinco ".encode_utf16() {
+         text.extend_from_slice(&unit.to_le_bytes());
+      }
+   }
+   text
+}
+
+/// The payload of a TXXX frame holding many short values, so most of the time goes to finding the terminators
+pub fn many_values(encoding: u8, count: usize) -> Vec<u8> {
+   let mut text = vec![encoding];
+   for i in 0..count {
+      let value = format!("Artist Number {}", i);
+      if encoding == 0 {
+         text.extend_from_slice(value.as_bytes());
+         text.push(0);
+      } else {
+         for unit in value.encode_utf16() {
+            text.extend_from_slice(&unit.to_be_bytes());
+         }
+         text.extend_from_slice(&[0, 0]);
+      }
+   }
+   text
+}
+
+fn utf8(text: &str) -> Vec<u8> {
+   let mut payload = vec![3];
+   payload.extend_from_slice(text.as_bytes());
+   payload
+}
+
+fn user_text(description: &str, value: &str) -> Vec<u8> {
+   let mut payload = utf8(description);
+   payload.push(0);
+   payload.extend_from_slice(value.as_bytes());
+   payload
+}
+
+/// The usual frames of a ripped track, and nothing else
+pub fn small() -> Vec<u8> {
+   tag(&small_frames())
+}
+
+/// The frames of `small`, plus a 500 KB front cover
+pub fn artwork() -> Vec<u8> {
+   let mut picture = b"\x00image/jpeg\x00\x03\x00".to_vec();
+   picture.extend_from_slice(b"\xff\xd8\xff\xe0");
+   picture.extend((0..500 * 1024).map(|x| (x * 7 % 251) as u8));
+   let mut frames = small_frames();
+   frames.push((b"APIC", picture));
+   tag(&frames)
+}
+
+/// The frames of `small`, plus the dozens of TXXX frames taggers like MusicBrainz Picard add
+pub fn many_txxx() -> Vec<u8> {
+   let mut frames = small_frames();
+   for i in 0..60 {
+      frames.push((
+         b"TXXX",
+         user_text(
+            &format!("MusicBrainz Custom Id {}", i),
+            "5c2d1b7f-7e4c-4f45-9ca2-6f3c1c4a6f0e",
+         ),
+      ));
+   }
+   tag(&frames)
+}
+
+/// Every text frame in UTF-16, as some Windows taggers write them
+pub fn utf16_heavy() -> Vec<u8> {
+   let names: [&[u8; 4]; 8] = [b"TIT2", b"TPE1", b"TPE2", b"TALB", b"TCOM", b"TCON", b"TPUB", b"TENC"];
+   let frames: Vec<_> = names.iter().map(|name| (*name, utf16(4))).collect();
+   tag(&frames)
+}
+
+fn small_frames() -> Vec<(&'static [u8; 4], Vec<u8>)> {
+   vec![
+      (b"TIT2", utf8("Roygbiv")),
+      (b"TPE1", utf8("Boards of Canada")),
+      (b"TALB", utf8("Music Has the Right to Children")),
+      (b"TRCK", utf8("5/18")),
+      (b"TDRC", utf8("1998")),
+      (b"TCON", utf8("Electronic")),
+   ]
+}
+
+/// Writes `count` MP3 files (a tag, then a single silent frame) into a fresh directory under the system's temporary
+/// directory, cycling through the kinds of tags. Returns the directory and the files.
+pub fn write_library(name: &str, count: usize) -> (PathBuf, Vec<PathBuf>) {
+   let dir = std::env::temp_dir().join(format!("walnut-bench-{}-{}", name, std::process::id()));
+   let _ = fs::remove_dir_all(&dir);
+   fs::create_dir_all(&dir).unwrap();
+
+   let tags = [small(), many_txxx(), utf16_heavy(), artwork()];
+   let files = (0..count)
+      .map(|i| {
+         // Artwork is in one file in eight, rather than one in four
+         let tag = match i % 8 {
+            3 => &tags[3],
+            x => &tags[x % 3],
+         };
+         let path = dir.join(format!("{:05}.mp3", i));
+         let mut bytes = tag.clone();
+         bytes.extend_from_slice(b"\xff\xfb\x90\x00");
+         bytes.extend_from_slice(&[0; 413]);
+         fs::write(&path, bytes).unwrap();
+         path
+      })
+      .collect();
+   (dir, files)
+}
+
+/// Removes a directory made by `write_library`
+pub fn remove_library(dir: &Path) {
+   let _ = fs::remove_dir_all(dir);
+}
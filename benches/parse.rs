@@ -0,0 +1,56 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use std::io::Cursor;
+use walnut::id3::{self, ParseOptions};
+
+mod corpus;
+
+fn corpus() -> Vec<(&'static str, Vec<u8>)> {
+   vec![
+      ("small", corpus::small()),
+      ("artwork", corpus::artwork()),
+      ("many TXXX", corpus::many_txxx()),
+      ("UTF-16", corpus::utf16_heavy()),
+   ]
+}
+
+fn parse_source(c: &mut Criterion) {
+   for (name, tag) in corpus() {
+      c.bench_function(&format!("parse_source, {}", name), |b| {
+         b.iter(|| {
+            let parser = id3::parse_source(&mut black_box(&tag[..]), ParseOptions::default()).unwrap();
+            parser.map(|frame| frame.unwrap()).collect::<Vec<_>>()
+         })
+      });
+   }
+}
+
+fn parse_slice(c: &mut Criterion) {
+   for (name, tag) in corpus() {
+      c.bench_function(&format!("parse_slice, {}", name), |b| {
+         b.iter(|| {
+            let parser = id3::parse_slice(black_box(&tag), ParseOptions::default()).unwrap();
+            parser.map(|frame| frame.unwrap()).collect::<Vec<_>>()
+         })
+      });
+   }
+}
+
+// Reading only the headers, as a scan that skips artwork would
+fn parse_stream(c: &mut Criterion) {
+   for (name, tag) in corpus() {
+      c.bench_function(&format!("parse_stream headers, {}", name), |b| {
+         b.iter(|| {
+            let mut parser = id3::parse_stream(Cursor::new(black_box(&tag)), ParseOptions::default()).unwrap();
+            let mut headers = 0;
+            while let Some(header) = parser.next_header() {
+               black_box(header.unwrap());
+               headers += 1;
+            }
+            headers
+         })
+      });
+   }
+}
+
+criterion_group!(benches, parse_source, parse_slice, parse_stream);
+criterion_main!(benches);
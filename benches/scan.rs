@@ -0,0 +1,32 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use walnut::scan::{self, ScanOptions};
+
+mod corpus;
+
+// The whole pipeline of `walnut report`: reading each file, parsing its tags, and adding it to the report
+fn report(c: &mut Criterion) {
+   let (dir, files) = corpus::write_library("scan", 400);
+
+   let mut group = c.benchmark_group("report 400 files");
+   group.sample_size(10);
+   group.bench_function("one thread", |b| {
+      b.iter(|| {
+         scan::report(
+            files.clone(),
+            &ScanOptions {
+               threads: 1,
+               ..ScanOptions::default()
+            },
+         )
+      })
+   });
+   group.bench_function("one thread per CPU", |b| {
+      b.iter(|| scan::report(files.clone(), &ScanOptions::default()))
+   });
+   group.finish();
+
+   corpus::remove_library(&dir);
+}
+
+criterion_group!(benches, report);
+criterion_main!(benches);
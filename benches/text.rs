@@ -0,0 +1,34 @@
+use corpus::{latin1, many_values, tag, utf16};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use walnut::id3::{self, ParseOptions};
+
+mod corpus;
+
+fn parse(tag: &[u8]) -> usize {
+   let mut frames = 0;
+   for frame in id3::parse_slice(tag, ParseOptions::default()).unwrap() {
+      black_box(frame.unwrap());
+      frames += 1;
+   }
+   frames
+}
+
+fn text_decoding(c: &mut Criterion) {
+   let short = tag(&[(b"TIT2", latin1(1)), (b"TPE1", latin1(1)), (b"TALB", latin1(1))]);
+   c.bench_function("latin-1, short", |b| b.iter(|| parse(black_box(&short))));
+
+   let long = tag(&[(b"TIT2", latin1(200))]);
+   c.bench_function("latin-1, long", |b| b.iter(|| parse(black_box(&long))));
+
+   let long = tag(&[(b"TIT2", utf16(200))]);
+   c.bench_function("utf-16, long", |b| b.iter(|| parse(black_box(&long))));
+
+   let values = tag(&[(b"TXXX", many_values(0, 500))]);
+   c.bench_function("latin-1, many values", |b| b.iter(|| parse(black_box(&values))));
+
+   let values = tag(&[(b"TXXX", many_values(2, 500))]);
+   c.bench_function("utf-16, many values", |b| b.iter(|| parse(black_box(&values))));
+}
+
+criterion_group!(benches, text_decoding);
+criterion_main!(benches);
@@ -0,0 +1,30 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use std::io::Cursor;
+use walnut::id3::{self, ParseOptions, Strictness};
+
+fuzz_target!(|data: &[u8]| {
+   for strictness in [Strictness::Strict, Strictness::Lenient].iter() {
+      let options = ParseOptions {
+         strictness: *strictness,
+         search_window: 64,
+         ..Default::default()
+      };
+      if let Ok(parser) = id3::parse_raw(data, options.clone()) {
+         for _ in parser {}
+      }
+      if let Ok(parser) = id3::parse_slice(data, options.clone()) {
+         for _ in parser {}
+      }
+      if let Ok(parser) = id3::parse_bytes(data, options.clone()) {
+         for _ in parser {}
+      }
+      if let Ok(parser) = id3::parse_source(&mut Cursor::new(data), options.clone()) {
+         for _ in parser {}
+      }
+      if let Ok(parser) = id3::parse_stream(Cursor::new(data), options) {
+         for _ in parser {}
+      }
+   }
+});
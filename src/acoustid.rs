@@ -0,0 +1,268 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+#[cfg(feature = "acoustid")]
+use std::time::{Duration, Instant};
+
+/// Where AcoustID lookups go
+pub const LOOKUP_URL: &str = "https://api.acoustid.org/v2/lookup";
+
+// AcoustID allows three requests a second
+#[cfg(feature = "acoustid")]
+const REQUEST_INTERVAL: Duration = Duration::from_millis(334);
+
+/// A Chromaprint fingerprint, compressed and base64 encoded the way AcoustID wants it
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fingerprint {
+   /// In seconds
+   pub duration: u32,
+   pub fingerprint: String,
+}
+
+#[derive(Debug)]
+pub enum AcoustIdError {
+   /// fpcalc couldn't be run, most likely because it isn't installed
+   Fpcalc(io::Error),
+   /// fpcalc ran, but couldn't fingerprint the file. Holds what it printed.
+   FpcalcFailed(String),
+   Http(String),
+   /// The lookup was refused, e.g. for an invalid API key. Holds AcoustID's message.
+   Api(String),
+   InvalidResponse,
+}
+
+impl fmt::Display for AcoustIdError {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      match self {
+         AcoustIdError::Fpcalc(e) => write!(f, "failed to run fpcalc: {}", e),
+         AcoustIdError::FpcalcFailed(message) => write!(f, "fpcalc failed: {}", message),
+         AcoustIdError::Http(message) => write!(f, "lookup failed: {}", message),
+         AcoustIdError::Api(message) => write!(f, "AcoustID refused the lookup: {}", message),
+         AcoustIdError::InvalidResponse => write!(f, "AcoustID sent a response that couldn't be understood"),
+      }
+   }
+}
+
+impl Error for AcoustIdError {
+   fn source(&self) -> Option<&(dyn Error + 'static)> {
+      match self {
+         AcoustIdError::Fpcalc(e) => Some(e),
+         _ => None,
+      }
+   }
+}
+
+/// Fingerprints the audio of the file with fpcalc, which comes with Chromaprint and must be on the PATH. fpcalc
+/// decodes the audio itself (with FFmpeg), so any format it can play can be fingerprinted.
+pub fn fingerprint(path: &Path) -> Result<Fingerprint, AcoustIdError> {
+   let output = Command::new("fpcalc")
+      .arg(path)
+      .output()
+      .map_err(AcoustIdError::Fpcalc)?;
+   let stdout = String::from_utf8_lossy(&output.stdout);
+   match parse_fpcalc(&stdout) {
+      Some(fingerprint) if output.status.success() => Ok(fingerprint),
+      _ => Err(AcoustIdError::FpcalcFailed(
+         String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+      )),
+   }
+}
+
+// fpcalc prints lines like "DURATION=215" and "FINGERPRINT=AQADtE..."
+fn parse_fpcalc(output: &str) -> Option<Fingerprint> {
+   let mut duration = None;
+   let mut fingerprint = None;
+   for line in output.lines() {
+      let mut parts = line.splitn(2, '=');
+      match (parts.next(), parts.next()) {
+         (Some("DURATION"), Some(value)) => duration = value.trim().parse::<f64>().ok(),
+         (Some("FINGERPRINT"), Some(value)) if !value.trim().is_empty() => fingerprint = Some(value.trim()),
+         _ => (),
+      }
+   }
+   Some(Fingerprint {
+      duration: duration?.round() as u32,
+      fingerprint: fingerprint?.to_owned(),
+   })
+}
+
+/// A fingerprint in AcoustID's database, with the MusicBrainz recordings that have been linked to it
+#[derive(Clone, Debug, PartialEq)]
+pub struct Match {
+   pub id: String,
+   /// How close the fingerprint is, from 0 to 1
+   pub score: f32,
+   pub recordings: Vec<Recording>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Recording {
+   /// The MusicBrainz recording ID
+   pub id: String,
+   pub title: Option<String>,
+   /// Joined with the join phrases MusicBrainz credits them with, e.g. "Artist feat. Other Artist"
+   pub artist: Option<String>,
+   /// In seconds
+   pub duration: Option<u32>,
+   pub releases: Vec<Release>,
+}
+
+/// A release that a recording is on, and where
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Release {
+   /// The MusicBrainz release ID
+   pub id: String,
+   pub title: Option<String>,
+   pub artist: Option<String>,
+   pub year: Option<u16>,
+   pub track_number: Option<u32>,
+   pub track_total: Option<u32>,
+   pub disc_number: Option<u32>,
+   pub disc_total: Option<u32>,
+}
+
+/// Looks fingerprints up with the AcoustID web service
+#[cfg(feature = "acoustid")]
+pub struct Client {
+   api_key: String,
+   last_request: Option<Instant>,
+}
+
+#[cfg(feature = "acoustid")]
+impl Client {
+   /// `api_key` is the key of an application registered with AcoustID
+   pub fn new(api_key: String) -> Client {
+      Client {
+         api_key,
+         last_request: None,
+      }
+   }
+
+   /// The matches for the fingerprint, best first. Lookups are spaced out to stay within AcoustID's rate limit.
+   pub fn lookup(&mut self, fingerprint: &Fingerprint) -> Result<Vec<Match>, AcoustIdError> {
+      if let Some(last) = self.last_request {
+         let elapsed = last.elapsed();
+         if elapsed < REQUEST_INTERVAL {
+            std::thread::sleep(REQUEST_INTERVAL - elapsed);
+         }
+      }
+      self.last_request = Some(Instant::now());
+
+      // Fingerprints are too long for a query string
+      let duration = fingerprint.duration.to_string();
+      let response = ureq::post(LOOKUP_URL)
+         .set("User-Agent", concat!("walnut/", env!("CARGO_PKG_VERSION")))
+         .timeout(Duration::from_secs(30))
+         .send_form(&[
+            ("client", &self.api_key),
+            ("format", "json"),
+            ("meta", "recordings releases tracks"),
+            ("duration", &duration),
+            ("fingerprint", &fingerprint.fingerprint),
+         ]);
+      if let Some(e) = response.synthetic_error() {
+         return Err(AcoustIdError::Http(e.to_string()));
+      }
+      let body = response.into_string().map_err(|e| AcoustIdError::Http(e.to_string()))?;
+      parse_lookup(&body)
+   }
+}
+
+#[cfg(feature = "acoustid")]
+fn parse_lookup(body: &str) -> Result<Vec<Match>, AcoustIdError> {
+   use serde_json::Value;
+
+   let text = |x: &Value| x.as_str().map(str::to_owned);
+   let number = |x: &Value| x.as_u64().map(|x| x as u32);
+   // MusicBrainz artist credits, e.g. [{"name": "A", "joinphrase": " & "}, {"name": "B"}]
+   let artist = |x: &Value| {
+      let artists = x.as_array()?;
+      let mut credit = String::new();
+      for artist in artists {
+         credit.push_str(artist["name"].as_str()?);
+         credit.push_str(artist["joinphrase"].as_str().unwrap_or(""));
+      }
+      Some(credit)
+   };
+
+   let response: Value = serde_json::from_str(body).map_err(|_| AcoustIdError::InvalidResponse)?;
+   if response["status"] != "ok" {
+      let message = response["error"]["message"].as_str().unwrap_or("unknown error");
+      return Err(AcoustIdError::Api(message.to_owned()));
+   }
+   let results = response["results"].as_array().ok_or(AcoustIdError::InvalidResponse)?;
+
+   let mut matches = Vec::with_capacity(results.len());
+   for result in results {
+      let recordings = result["recordings"].as_array().map(Vec::as_slice).unwrap_or(&[]);
+      matches.push(Match {
+         id: text(&result["id"]).ok_or(AcoustIdError::InvalidResponse)?,
+         score: result["score"].as_f64().unwrap_or(0.0) as f32,
+         recordings: recordings
+            .iter()
+            .filter_map(|recording| {
+               let releases = recording["releases"].as_array().map(Vec::as_slice).unwrap_or(&[]);
+               Some(Recording {
+                  id: text(&recording["id"])?,
+                  title: text(&recording["title"]),
+                  artist: artist(&recording["artists"]),
+                  duration: number(&recording["duration"]),
+                  releases: releases
+                     .iter()
+                     .filter_map(|release| {
+                        let medium = &release["mediums"][0];
+                        Some(Release {
+                           id: text(&release["id"])?,
+                           title: text(&release["title"]),
+                           artist: artist(&release["artists"]),
+                           year: release["date"]["year"].as_u64().map(|x| x as u16),
+                           track_number: number(&medium["tracks"][0]["position"]),
+                           track_total: number(&medium["track_count"]),
+                           disc_number: number(&medium["position"]),
+                           disc_total: number(&release["medium_count"]),
+                        })
+                     })
+                     .collect(),
+               })
+            })
+            .collect(),
+      });
+   }
+   matches.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
+   Ok(matches)
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+
+   #[test]
+   fn parse_output() {
+      let fingerprint = parse_fpcalc("FILE=song.flac\nDURATION=215.48\nFINGERPRINT=AQADtEmUaEkSRZEGAA\n").unwrap();
+      assert_eq!(fingerprint.duration, 215);
+      assert_eq!(fingerprint.fingerprint, "AQADtEmUaEkSRZEGAA");
+      assert_eq!(parse_fpcalc("DURATION=215\n"), None);
+
+      #[cfg(feature = "acoustid")]
+      {
+         let body = r#"{"status": "ok", "results": [{"id": "9ff43b6a", "score": 0.94, "recordings": [{
+            "id": "cd2e7c47", "title": "Ready Lets Go", "duration": 60,
+            "artists": [{"id": "69158f97", "name": "Boards of Canada"}],
+            "releases": [{"id": "f5093c06", "title": "Geogaddi", "date": {"year": 2002}, "medium_count": 1,
+               "mediums": [{"position": 1, "track_count": 23, "tracks": [{"position": 1}]}]}]}]}]}"#;
+         let matches = parse_lookup(body).unwrap();
+         let recording = &matches[0].recordings[0];
+         assert_eq!(recording.artist.as_ref().map(String::as_str), Some("Boards of Canada"));
+         assert_eq!(recording.releases[0].year, Some(2002));
+         assert_eq!(recording.releases[0].track_total, Some(23));
+
+         let refused = parse_lookup(r#"{"status": "error", "error": {"code": 4, "message": "invalid API key"}}"#);
+         assert!(match refused {
+            Err(AcoustIdError::Api(message)) => message == "invalid API key",
+            _ => false,
+         });
+      }
+   }
+}
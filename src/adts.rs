@@ -0,0 +1,321 @@
+use crate::id3::v24::FrameData;
+use crate::id3::{self, Id3Tag, TagParseError};
+use crate::tag::Tag;
+use byteorder::{BigEndian, ByteOrder};
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::time::Duration;
+
+const SAMPLE_RATES: [u32; 13] = [
+   96000, 88200, 64000, 48000, 44100, 32000, 24000, 22050, 16000, 12000, 11025, 8000, 7350,
+];
+
+/// The owner of the PRIV frame HLS puts in the tag at the start of each packed audio segment
+pub const TIMESTAMP_OWNER: &str = "com.apple.streaming.transportStreamTimestamp";
+
+/// The 7 byte header at the start of every ADTS frame, which wraps a raw AAC frame
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FrameHeader {
+   /// MPEG-2 AAC rather than MPEG-4
+   pub mpeg2: bool,
+   /// Whether the header is followed by a CRC
+   pub protected: bool,
+   /// The MPEG-4 audio object type: 1 is AAC Main, 2 AAC LC, 3 AAC SSR, and 4 AAC LTP
+   pub object_type: u8,
+   /// In Hz
+   pub sample_rate: u32,
+   /// 0 if the channel layout is given in the stream instead
+   pub channels: u8,
+   /// The whole frame, header included
+   pub frame_len: u32,
+   /// How many AAC frames of 1024 samples this frame holds
+   pub raw_frames: u8,
+}
+
+impl FrameHeader {
+   /// Returns `None` if the bytes aren't a valid frame header
+   pub fn parse(bytes: [u8; 7]) -> Option<FrameHeader> {
+      // A 12 bit sync word, then the layer, which is always 0
+      if bytes[0] != 0xff || bytes[1] & 0xf6 != 0xf0 {
+         return None;
+      }
+      let sample_rate = *SAMPLE_RATES.get(usize::from(bytes[2] >> 2 & 0x0f))?;
+      let frame_len = u32::from(bytes[3] & 0x03) << 11 | u32::from(bytes[4]) << 3 | u32::from(bytes[5] >> 5);
+      let protected = bytes[1] & 0x01 == 0;
+      if frame_len < if protected { 9 } else { 7 } {
+         return None;
+      }
+      Some(FrameHeader {
+         mpeg2: bytes[1] & 0x08 != 0,
+         protected,
+         object_type: (bytes[2] >> 6) + 1,
+         sample_rate,
+         channels: (bytes[2] & 0x01) << 2 | bytes[3] >> 6,
+         frame_len,
+         raw_frames: (bytes[6] & 0x03) + 1,
+      })
+   }
+
+   pub fn samples(&self) -> u32 {
+      u32::from(self.raw_frames) * 1024
+   }
+}
+
+/// What the frame headers say about the audio, taken from the first and counted over all of them
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Properties {
+   pub first_frame: FrameHeader,
+   pub frames: u64,
+   pub samples: u64,
+   /// The bytes of every frame, headers included
+   pub audio_len: u64,
+}
+
+impl Properties {
+   pub fn duration(&self) -> Duration {
+      let sample_rate = u64::from(self.first_frame.sample_rate);
+      Duration::from_secs(self.samples / sample_rate)
+         + Duration::from_nanos(self.samples % sample_rate * 1_000_000_000 / sample_rate)
+   }
+}
+
+/// An ID3 tag between frames of the stream, as HLS uses for timed metadata such as the title of what's playing
+#[derive(Clone, Debug)]
+pub struct TimedTag {
+   /// Where the tag is in the file
+   pub offset: u64,
+   /// How much audio comes before the tag
+   pub position: Duration,
+   pub tag: Id3Tag,
+}
+
+impl TimedTag {
+   /// The MPEG-2 transport stream timestamp of the segment, in 90kHz ticks, from the PRIV frame HLS writes
+   pub fn timestamp(&self) -> Option<u64> {
+      transport_stream_timestamp(&self.tag)
+   }
+}
+
+#[derive(Clone, Debug)]
+pub struct Metadata {
+   /// The tag at the start of the stream (empty if there is none)
+   pub tag: Id3Tag,
+   /// The tags that come after the audio starts
+   pub timed_tags: Vec<TimedTag>,
+   /// The tags after the audio starts that failed to parse, with where each is in the file
+   pub failed_tags: Vec<(u64, TagParseError)>,
+   /// `None` if no frames were found
+   pub properties: Option<Properties>,
+}
+
+impl Metadata {
+   /// The transport stream timestamp of the tag at the start of the stream, as in HLS segments (see
+   /// `TimedTag::timestamp`)
+   pub fn timestamp(&self) -> Option<u64> {
+      transport_stream_timestamp(&self.tag)
+   }
+
+   /// The titles the timed tags give, with how far into the stream each starts, e.g. the songs of a radio stream
+   pub fn stream_titles(&self) -> Vec<(Duration, &str)> {
+      self
+         .timed_tags
+         .iter()
+         .filter_map(|x| Some((x.position, x.tag.title()?)))
+         .collect()
+   }
+}
+
+fn transport_stream_timestamp(tag: &Id3Tag) -> Option<u64> {
+   tag.frames.iter().find_map(|x| match &x.data {
+      // Only the low 33 bits are the timestamp
+      FrameData::PRIV(x) if x.owner == TIMESTAMP_OWNER && x.data.len() == 8 => {
+         Some(BigEndian::read_u64(&x.data) & 0x1_ffff_ffff)
+      }
+      _ => None,
+   })
+}
+
+#[derive(Debug)]
+pub enum AdtsParseError {
+   NotAdts,
+   Id3(TagParseError),
+   Io(io::Error),
+}
+
+impl fmt::Display for AdtsParseError {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      match self {
+         AdtsParseError::NotAdts => write!(f, "not an ADTS stream"),
+         AdtsParseError::Id3(e) => write!(f, "failed to parse the ID3 tag: {}", e),
+         AdtsParseError::Io(e) => write!(f, "I/O error: {}", e),
+      }
+   }
+}
+
+impl Error for AdtsParseError {
+   fn source(&self) -> Option<&(dyn Error + 'static)> {
+      match self {
+         AdtsParseError::Id3(e) => Some(e),
+         AdtsParseError::Io(e) => Some(e),
+         _ => None,
+      }
+   }
+}
+
+impl From<TagParseError> for AdtsParseError {
+   fn from(e: TagParseError) -> AdtsParseError {
+      AdtsParseError::Id3(e)
+   }
+}
+
+impl From<io::Error> for AdtsParseError {
+   fn from(e: io::Error) -> AdtsParseError {
+      AdtsParseError::Io(e)
+   }
+}
+
+/// Walks the stream frame by frame, reading the tag at the start and any tags between frames. Bytes that are neither
+/// are skipped until the next frame or tag. A timed tag that fails to parse is left out, and its error kept in
+/// `Metadata::failed_tags`.
+pub fn read_metadata<S: Read + Seek>(source: &mut S) -> Result<Metadata, AdtsParseError> {
+   let len = source.seek(SeekFrom::End(0))?;
+   let mut metadata = Metadata {
+      tag: Id3Tag::default(),
+      timed_tags: Vec::new(),
+      failed_tags: Vec::new(),
+      properties: None,
+   };
+   let mut pos = 0;
+   let mut header = [0u8; 10];
+   while pos + 7 <= len {
+      source.seek(SeekFrom::Start(pos))?;
+      let header_len = (len - pos).min(10) as usize;
+      source.read_exact(&mut header[..header_len])?;
+
+      if let Some(tag_len) = id3::tag_len(&header[..header_len]) {
+         source.seek(SeekFrom::Start(pos))?;
+         let tag = Id3Tag::read(&mut Read::by_ref(source).take(tag_len), id3::ParseOptions::default());
+         match (metadata.properties, tag) {
+            (None, tag) if pos == 0 => metadata.tag = tag?,
+            (properties, Ok(tag)) => metadata.timed_tags.push(TimedTag {
+               offset: pos,
+               position: properties.map_or(Duration::from_secs(0), |x| x.duration()),
+               tag,
+            }),
+            (_, Err(e)) => metadata.failed_tags.push((pos, e)),
+         }
+         pos += tag_len;
+         continue;
+      }
+
+      let mut frame_header = [0u8; 7];
+      frame_header.copy_from_slice(&header[..7]);
+      match FrameHeader::parse(frame_header) {
+         Some(frame) => {
+            let properties = metadata.properties.get_or_insert(Properties {
+               first_frame: frame,
+               frames: 0,
+               samples: 0,
+               audio_len: 0,
+            });
+            properties.frames += 1;
+            properties.samples += u64::from(frame.samples());
+            properties.audio_len += u64::from(frame.frame_len);
+            pos += u64::from(frame.frame_len);
+         }
+         None => pos += 1,
+      }
+   }
+   if metadata.properties.is_none() && metadata.tag.frames.is_empty() && metadata.timed_tags.is_empty() {
+      return Err(AdtsParseError::NotAdts);
+   }
+   Ok(metadata)
+}
+
+impl Tag for Metadata {
+   fn title(&self) -> Option<&str> {
+      self.tag.title()
+   }
+
+   fn artist(&self) -> Option<&str> {
+      self.tag.artist()
+   }
+
+   fn album(&self) -> Option<&str> {
+      self.tag.album()
+   }
+
+   fn album_artist(&self) -> Option<&str> {
+      self.tag.album_artist()
+   }
+
+   fn genre(&self) -> Option<&str> {
+      self.tag.genre()
+   }
+
+   fn year(&self) -> Option<u16> {
+      self.tag.year()
+   }
+
+   fn track_number(&self) -> Option<u32> {
+      self.tag.track_number()
+   }
+
+   fn track_total(&self) -> Option<u32> {
+      self.tag.track_total()
+   }
+
+   fn disc_number(&self) -> Option<u32> {
+      self.tag.disc_number()
+   }
+
+   fn disc_total(&self) -> Option<u32> {
+      self.tag.disc_total()
+   }
+
+   fn comment(&self) -> Option<&str> {
+      self.tag.comment()
+   }
+
+   fn artist_values(&self) -> Vec<&str> {
+      self.tag.artist_values()
+   }
+
+   fn genre_values(&self) -> Vec<&str> {
+      self.tag.genre_values()
+   }
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+
+   #[test]
+   fn timed_tags() {
+      // AAC LC, 44.1kHz, stereo, 16 byte frames
+      let frame = b"\xff\xf1\x50\x80\x02\x1f\xfc\x00\x00\x00\x00\x00\x00\x00\x00\x00";
+      let header = FrameHeader::parse(*b"\xff\xf1\x50\x80\x02\x1f\xfc").unwrap();
+      assert_eq!((header.object_type, header.sample_rate), (2, 44100));
+      assert_eq!((header.channels, header.frame_len), (2, 16));
+
+      let mut stream = b"ID3\x04\x00\x00\x00\x00\x00\x3fPRIV\x00\x00\x00\x35\x00\x00".to_vec();
+      stream.extend_from_slice(TIMESTAMP_OWNER.as_bytes());
+      stream.extend_from_slice(b"\x00\x00\x00\x00\x01\x00\x00\x00\x0a");
+      for _ in 0..43 {
+         stream.extend_from_slice(frame);
+      }
+      stream.extend_from_slice(b"ID3\x04\x00\x00\x00\x00\x00\x12TIT2\x00\x00\x00\x08\x00\x00\x03Song 2\x00");
+      stream.extend_from_slice(frame);
+
+      let metadata = read_metadata(&mut io::Cursor::new(stream)).unwrap();
+      assert_eq!(metadata.timestamp(), Some(0x1_0000_000a));
+      let properties = metadata.properties.unwrap();
+      assert_eq!((properties.frames, properties.audio_len), (44, 44 * 16));
+      assert_eq!(properties.duration().as_millis(), 1021);
+      assert_eq!(
+         metadata.stream_titles(),
+         [(Duration::from_nanos(998_458_049), "Song 2")]
+      );
+   }
+}
@@ -0,0 +1,195 @@
+use crate::id3::{self, Id3Tag, TagParseError};
+use crate::riff::decode_text;
+use crate::tag::Tag;
+use byteorder::{BigEndian, ByteOrder};
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom};
+
+/// The tags of an AIFF or AIFF-C file. When both are present, the ID3 tag takes precedence through the `Tag` trait.
+#[derive(Clone, Debug, Default)]
+pub struct Metadata {
+   /// From the NAME chunk
+   pub name: Option<String>,
+   /// From the AUTH chunk
+   pub author: Option<String>,
+   /// From the `(c) ` chunk
+   pub copyright: Option<String>,
+   /// From the ANNO chunks
+   pub annotations: Vec<String>,
+   /// From an `ID3 ` or `id3 ` chunk
+   pub id3: Option<Id3Tag>,
+   /// Why the ID3 chunk couldn't be read, e.g. it holds an ID3v2.2 tag. The other chunks are read regardless.
+   pub id3_error: Option<TagParseError>,
+}
+
+#[derive(Debug)]
+pub enum AiffParseError {
+   NotAiff,
+   InvalidChunk,
+   Io(io::Error),
+}
+
+impl fmt::Display for AiffParseError {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      match self {
+         AiffParseError::NotAiff => write!(f, "not an AIFF file"),
+         AiffParseError::InvalidChunk => write!(f, "chunk extends past the end of its parent"),
+         AiffParseError::Io(e) => write!(f, "I/O error: {}", e),
+      }
+   }
+}
+
+impl Error for AiffParseError {
+   fn source(&self) -> Option<&(dyn Error + 'static)> {
+      match self {
+         AiffParseError::Io(e) => Some(e),
+         _ => None,
+      }
+   }
+}
+
+impl From<io::Error> for AiffParseError {
+   fn from(e: io::Error) -> AiffParseError {
+      AiffParseError::Io(e)
+   }
+}
+
+/// Reads the text and ID3 chunks of an AIFF file. Every other chunk (including the audio) is skipped by seeking.
+pub fn read_metadata<S: Read + Seek>(source: &mut S) -> Result<Metadata, AiffParseError> {
+   let mut header = [0u8; 12];
+   source.seek(SeekFrom::Start(0))?;
+   source.read_exact(&mut header)?;
+   if &header[0..4] != b"FORM" || (&header[8..12] != b"AIFF" && &header[8..12] != b"AIFC") {
+      return Err(AiffParseError::NotAiff);
+   }
+   // The size covers everything after itself
+   let end = 8 + u64::from(BigEndian::read_u32(&header[4..8]));
+
+   let mut metadata = Metadata::default();
+   let mut pos = 12;
+   while pos + 8 <= end {
+      let mut chunk_header = [0u8; 8];
+      source.read_exact(&mut chunk_header)?;
+      let size = u64::from(BigEndian::read_u32(&chunk_header[4..8]));
+      if pos + 8 + size > end {
+         return Err(AiffParseError::InvalidChunk);
+      }
+
+      match &chunk_header[0..4] {
+         b"NAME" => metadata.name = Some(read_text(source, size)?),
+         b"AUTH" => metadata.author = Some(read_text(source, size)?),
+         b"(c) " => metadata.copyright = Some(read_text(source, size)?),
+         b"ANNO" => metadata.annotations.push(read_text(source, size)?),
+         b"ID3 " | b"id3 " => match Id3Tag::read(&mut source.take(size), id3::ParseOptions::default()) {
+            Ok(tag) => metadata.id3 = Some(tag),
+            Err(e) => metadata.id3_error = Some(e),
+         },
+         _ => (),
+      }
+
+      // Chunks are padded to an even size
+      pos += 8 + size + (size & 1);
+      source.seek(SeekFrom::Start(pos))?;
+   }
+
+   Ok(metadata)
+}
+
+fn read_text<S: Read>(source: &mut S, size: u64) -> Result<String, AiffParseError> {
+   let mut chunk = Vec::new();
+   source.take(size).read_to_end(&mut chunk)?;
+   Ok(decode_text(&chunk))
+}
+
+impl Tag for Metadata {
+   fn title(&self) -> Option<&str> {
+      self
+         .id3
+         .as_ref()
+         .and_then(Tag::title)
+         .or_else(|| self.name.as_ref().map(String::as_str))
+   }
+
+   fn artist(&self) -> Option<&str> {
+      self
+         .id3
+         .as_ref()
+         .and_then(Tag::artist)
+         .or_else(|| self.author.as_ref().map(String::as_str))
+   }
+
+   fn album(&self) -> Option<&str> {
+      self.id3.as_ref().and_then(Tag::album)
+   }
+
+   fn album_artist(&self) -> Option<&str> {
+      self.id3.as_ref().and_then(Tag::album_artist)
+   }
+
+   fn genre(&self) -> Option<&str> {
+      self.id3.as_ref().and_then(Tag::genre)
+   }
+
+   fn year(&self) -> Option<u16> {
+      self.id3.as_ref().and_then(Tag::year)
+   }
+
+   fn track_number(&self) -> Option<u32> {
+      self.id3.as_ref().and_then(Tag::track_number)
+   }
+
+   fn track_total(&self) -> Option<u32> {
+      self.id3.as_ref().and_then(Tag::track_total)
+   }
+
+   fn disc_number(&self) -> Option<u32> {
+      self.id3.as_ref().and_then(Tag::disc_number)
+   }
+
+   fn disc_total(&self) -> Option<u32> {
+      self.id3.as_ref().and_then(Tag::disc_total)
+   }
+
+   fn comment(&self) -> Option<&str> {
+      self
+         .id3
+         .as_ref()
+         .and_then(Tag::comment)
+         .or_else(|| self.annotations.first().map(String::as_str))
+   }
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+
+   #[test]
+   fn text_and_id3_chunks() {
+      // An ID3 tag with only a TIT2 frame
+      let mut id3 = Vec::from(&b"ID3\x04\x00\x00\x00\x00\x00\x11"[..]);
+      id3.extend_from_slice(b"TIT2\x00\x00\x00\x07\x00\x00\x03Title\x00");
+
+      let mut file = Vec::from(&b"FORM\x00\x00\x00\x00AIFF"[..]);
+      // Odd sized, so followed by a pad byte
+      file.extend_from_slice(b"NAME\x00\x00\x00\x05Other\x00");
+      file.extend_from_slice(b"AUTH\x00\x00\x00\x06Author");
+      file.extend_from_slice(b"ID3 ");
+      file.extend_from_slice(&(id3.len() as u32).to_be_bytes());
+      let id3_start = file.len();
+      file.extend(id3);
+      let form_size = file.len() as u32 - 8;
+      file[4..8].copy_from_slice(&form_size.to_be_bytes());
+
+      let metadata = read_metadata(&mut io::Cursor::new(&file)).unwrap();
+      assert_eq!(metadata.name.as_ref().map(String::as_str), Some("Other"));
+      assert_eq!(metadata.title(), Some("Title"));
+      assert_eq!(metadata.artist(), Some("Author"));
+
+      // An ID3v2.2 tag can't be read, but the text chunks still are
+      file[id3_start + 3] = 2;
+      let metadata = read_metadata(&mut io::Cursor::new(file)).unwrap();
+      assert_eq!(metadata.title(), Some("Other"));
+      assert!(metadata.id3_error.is_some());
+   }
+}
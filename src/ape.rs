@@ -0,0 +1,525 @@
+use crate::art::ImageFormat;
+use crate::id3::v24::{Frame, FrameData, LangDescriptionText, Txxx};
+use crate::id3::values::Values;
+use crate::id3::write::Picture;
+use crate::id3::Id3Tag;
+use crate::query::frame_texts;
+use crate::strip;
+use crate::tag::{self, Tag};
+use byteorder::{ByteOrder, LittleEndian};
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::Path;
+
+const HEADER_LEN: usize = 32;
+const VERSION: u32 = 2000;
+// In the header and footer flags
+const HAS_HEADER: u32 = 1 << 31;
+const IS_HEADER: u32 = 1 << 29;
+// Bits 1 and 2 of the item flags
+const TEXT: u32 = 0;
+const BINARY: u32 = 1;
+const LOCATOR: u32 = 2;
+
+// APE keys and the ID3v2.4 frames that hold the same thing. Keys are compared without regard to case, and where two
+// keys map to the same frame, the first is the one written.
+const FRAMES: &[(&str, [u8; 4])] = &[
+   ("Title", *b"TIT2"),
+   ("Subtitle", *b"TIT3"),
+   ("Grouping", *b"TIT1"),
+   ("Artist", *b"TPE1"),
+   ("Album Artist", *b"TPE2"),
+   ("AlbumArtist", *b"TPE2"),
+   ("Album", *b"TALB"),
+   ("Composer", *b"TCOM"),
+   ("Conductor", *b"TPE3"),
+   ("Lyricist", *b"TEXT"),
+   ("Original Artist", *b"TOPE"),
+   ("Genre", *b"TCON"),
+   ("Year", *b"TDRC"),
+   ("Track", *b"TRCK"),
+   ("Disc", *b"TPOS"),
+   ("Publisher", *b"TPUB"),
+   ("Label", *b"TPUB"),
+   ("Copyright", *b"TCOP"),
+   ("ISRC", *b"TSRC"),
+   ("BPM", *b"TBPM"),
+   ("Mood", *b"TMOO"),
+   ("Encoded By", *b"TENC"),
+   ("Compilation", *b"TCMP"),
+];
+
+// The binary items that hold pictures, with their APIC picture types
+const COVERS: &[(&str, u8)] = &[("Cover Art (Front)", 3), ("Cover Art (Back)", 4)];
+
+#[derive(Debug)]
+pub enum ApeError {
+   /// The items don't fit in the size the footer gives
+   InvalidTag,
+   /// Keys are 2 to 255 printable ASCII characters, and can't be "ID3", "TAG", "OggS", or "MP+"
+   InvalidKey(String),
+   TagTooLarge,
+   Io(io::Error),
+}
+
+impl fmt::Display for ApeError {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      match self {
+         ApeError::InvalidTag => write!(f, "APE tag items are truncated"),
+         ApeError::InvalidKey(key) => write!(f, "{:?} can't be an APE item key", key),
+         ApeError::TagTooLarge => write!(f, "tag is larger than the 4 GiB an APE size can hold"),
+         ApeError::Io(e) => write!(f, "I/O error: {}", e),
+      }
+   }
+}
+
+impl Error for ApeError {
+   fn source(&self) -> Option<&(dyn Error + 'static)> {
+      match self {
+         ApeError::Io(e) => Some(e),
+         _ => None,
+      }
+   }
+}
+
+impl From<io::Error> for ApeError {
+   fn from(e: io::Error) -> ApeError {
+      ApeError::Io(e)
+   }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ApeValue {
+   /// UTF-8. An item can hold several values, which are separated by nulls in the tag.
+   Text(Vec<String>),
+   Binary(Vec<u8>),
+   /// A URL or file name
+   Locator(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ApeItem {
+   pub key: String,
+   pub value: ApeValue,
+}
+
+/// An APEv2 tag, as written by foobar2000 and Monkey's Audio at the end of the file (before an ID3v1 tag, if there is
+/// one). Keys are case insensitive, and each appears once. APEv1 tags are read as well, but only APEv2 is written.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ApeTag {
+   pub items: Vec<ApeItem>,
+}
+
+impl ApeTag {
+   pub fn get(&self, key: &str) -> Option<&ApeValue> {
+      self
+         .items
+         .iter()
+         .find(|x| x.key.eq_ignore_ascii_case(key))
+         .map(|x| &x.value)
+   }
+
+   /// The first value of a text item
+   pub fn text(&self, key: &str) -> Option<&str> {
+      match self.get(key) {
+         Some(ApeValue::Text(values)) => values.first().map(String::as_str),
+         _ => None,
+      }
+   }
+
+   /// Every value of a text item
+   pub fn texts(&self, key: &str) -> Vec<&str> {
+      match self.get(key) {
+         Some(ApeValue::Text(values)) => values.iter().map(String::as_str).collect(),
+         _ => Vec::new(),
+      }
+   }
+
+   /// Replaces the item with the same key, if there is one
+   pub fn set<K: Into<String>>(&mut self, key: K, value: ApeValue) {
+      let key = key.into();
+      match self.items.iter_mut().find(|x| x.key.eq_ignore_ascii_case(&key)) {
+         Some(item) => item.value = value,
+         None => self.items.push(ApeItem { key, value }),
+      }
+   }
+
+   pub fn remove(&mut self, key: &str) {
+      self.items.retain(|x| !x.key.eq_ignore_ascii_case(key));
+   }
+
+   /// Reads the APE tag at the end of the source, if there is one
+   pub fn read<S: Read + Seek>(source: &mut S) -> Result<Option<ApeTag>, ApeError> {
+      let range = locate(source)?;
+      if range.start == range.end {
+         return Ok(None);
+      }
+      let mut bytes = vec![0u8; (range.end - range.start) as usize];
+      source.seek(SeekFrom::Start(range.start))?;
+      source.read_exact(&mut bytes)?;
+
+      let (rest, footer) = bytes.split_at(bytes.len() - HEADER_LEN);
+      let count = LittleEndian::read_u32(&footer[16..20]);
+      let flags = LittleEndian::read_u32(&footer[20..24]);
+      let items = if flags & HAS_HEADER != 0 {
+         rest.get(HEADER_LEN..).ok_or(ApeError::InvalidTag)?
+      } else {
+         rest
+      };
+      let items = parse_items(items, count).ok_or(ApeError::InvalidTag)?;
+      Ok(Some(ApeTag { items }))
+   }
+
+   /// The whole tag, with a header and a footer
+   pub fn to_bytes(&self) -> Result<Vec<u8>, ApeError> {
+      let mut items = Vec::new();
+      for item in &self.items {
+         if !is_valid_key(&item.key) {
+            return Err(ApeError::InvalidKey(item.key.clone()));
+         }
+         let (kind, value) = match &item.value {
+            ApeValue::Text(values) => (TEXT, Cow::Owned(values.join("\0").into_bytes())),
+            ApeValue::Binary(data) => (BINARY, Cow::Borrowed(&data[..])),
+            ApeValue::Locator(location) => (LOCATOR, Cow::Borrowed(location.as_bytes())),
+         };
+         let mut item_header = [0u8; 8];
+         LittleEndian::write_u32(
+            &mut item_header[0..4],
+            u32::try_from(value.len()).map_err(|_| ApeError::TagTooLarge)?,
+         );
+         LittleEndian::write_u32(&mut item_header[4..8], kind << 1);
+         items.extend_from_slice(&item_header);
+         items.extend_from_slice(item.key.as_bytes());
+         items.push(0);
+         items.extend_from_slice(&value);
+      }
+
+      // The size covers the items and the footer, but not the header
+      let size = u32::try_from(items.len() + HEADER_LEN).map_err(|_| ApeError::TagTooLarge)?;
+      let count = self.items.len() as u32;
+      let mut bytes = header(size, count, HAS_HEADER | IS_HEADER).to_vec();
+      bytes.extend_from_slice(&items);
+      bytes.extend_from_slice(&header(size, count, HAS_HEADER));
+      Ok(bytes)
+   }
+}
+
+/// Replaces the APE tag of the file with the given tag, or adds it before the ID3v1 tag (or at the end) if the file
+/// has none. A tag with no items removes the APE tag. The file is replaced as with `strip::rewrite_file`.
+pub fn write_to_file<P: AsRef<Path>>(path: P, tag: &ApeTag) -> Result<(), ApeError> {
+   let bytes = if tag.items.is_empty() {
+      Vec::new()
+   } else {
+      tag.to_bytes()?
+   };
+   let path = path.as_ref();
+   let mut source = File::open(path)?;
+   let range = locate(&mut source)?;
+   if range.start == range.end && bytes.is_empty() {
+      return Ok(());
+   }
+
+   strip::rewrite_file(path, source, |source, temp| {
+      source.seek(SeekFrom::Start(0))?;
+      io::copy(&mut Read::by_ref(source).take(range.start), temp)?;
+      temp.write_all(&bytes)?;
+      source.seek(SeekFrom::Start(range.end))?;
+      io::copy(source, temp)?;
+      Ok(())
+   })?;
+   Ok(())
+}
+
+// Where the APE tag at the end of the source is. If there is none, the range is empty, and at the ID3v1 tag (or the
+// end of the source) where one would go.
+fn locate<S: Read + Seek>(source: &mut S) -> io::Result<Range<u64>> {
+   let mut end = source.seek(SeekFrom::End(0))?;
+   if end >= 128 && strip::marker_at(source, end - 128, b"TAG")? {
+      end -= 128;
+   }
+   Ok(match strip::ape_len(source, end, end)? {
+      Some(len) if len <= end => end - len..end,
+      _ => end..end,
+   })
+}
+
+fn parse_items(mut rest: &[u8], count: u32) -> Option<Vec<ApeItem>> {
+   // We don't trust the count enough to allocate it up front
+   let mut items = Vec::new();
+   for _ in 0..count {
+      let size = LittleEndian::read_u32(rest.get(0..4)?) as usize;
+      let flags = LittleEndian::read_u32(rest.get(4..8)?);
+      let key_end = 8 + rest.get(8..)?.iter().position(|x| *x == 0)?;
+      let key = String::from_utf8_lossy(&rest[8..key_end]).into_owned();
+      let value_end = (key_end + 1).checked_add(size)?;
+      let value = rest.get(key_end + 1..value_end)?;
+      let value = match (flags >> 1) & 3 {
+         TEXT => ApeValue::Text(String::from_utf8_lossy(value).split('\0').map(str::to_owned).collect()),
+         BINARY => ApeValue::Binary(value.to_vec()),
+         // 3 is reserved, and treated as a locator
+         _ => ApeValue::Locator(String::from_utf8_lossy(value).into_owned()),
+      };
+      items.push(ApeItem { key, value });
+      rest = &rest[value_end..];
+   }
+   Some(items)
+}
+
+fn header(size: u32, count: u32, flags: u32) -> [u8; HEADER_LEN] {
+   let mut bytes = [0u8; HEADER_LEN];
+   bytes[0..8].copy_from_slice(b"APETAGEX");
+   LittleEndian::write_u32(&mut bytes[8..12], VERSION);
+   LittleEndian::write_u32(&mut bytes[12..16], size);
+   LittleEndian::write_u32(&mut bytes[16..20], count);
+   LittleEndian::write_u32(&mut bytes[20..24], flags);
+   bytes
+}
+
+pub fn is_valid_key(key: &str) -> bool {
+   let reserved = ["ID3", "TAG", "OggS", "MP+"];
+   key.len() >= 2
+      && key.len() <= 255
+      && key.bytes().all(|x| x == b' ' || x.is_ascii_graphic())
+      && !reserved.iter().any(|x| x.eq_ignore_ascii_case(key))
+}
+
+#[derive(Clone, Debug)]
+pub struct ToId3 {
+   pub tag: Id3Tag,
+   /// Items that have no frame to go in: locators, and binary items other than cover art
+   pub dropped: Vec<ApeItem>,
+}
+
+/// Converts an APE tag to ID3v2.4 frames. Items with a matching frame go in it, unless their text can't be read as
+/// that frame (a "Year" that isn't a date, say), and the other text items become TXXX frames with the key as their
+/// description. "Comment" and "Lyrics" become COMM and USLT frames, and front and back cover art becomes APIC frames.
+pub fn to_id3(tag: &ApeTag) -> ToId3 {
+   let mut frames = Vec::new();
+   let mut dropped = Vec::new();
+   for item in &tag.items {
+      let data = match &item.value {
+         ApeValue::Text(values) => Some(text_frame(&item.key, values)),
+         ApeValue::Binary(data) => COVERS
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(&item.key))
+            .and_then(|(_, picture_type)| cover_picture(*picture_type, data)),
+         ApeValue::Locator(_) => None,
+      };
+      match data {
+         Some(data) => frames.push(Frame::new(data)),
+         None => dropped.push(item.clone()),
+      }
+   }
+   ToId3 {
+      tag: Id3Tag { frames },
+      dropped,
+   }
+}
+
+fn text_frame(key: &str, values: &[String]) -> FrameData<'static> {
+   let text = || values.iter().map(|x| Cow::Owned(x.clone())).collect::<Values<_>>();
+   let lang_text = || LangDescriptionText {
+      iso_639_2_lang: *b"XXX",
+      description: Cow::Borrowed(""),
+      text: text(),
+   };
+   if key.eq_ignore_ascii_case("Comment") {
+      return FrameData::COMM(lang_text());
+   } else if key.eq_ignore_ascii_case("Lyrics") {
+      return FrameData::USLT(lang_text());
+   }
+   FRAMES
+      .iter()
+      .find(|(x, _)| x.eq_ignore_ascii_case(key))
+      .and_then(|(_, name)| FrameData::from_text(*name, &values.join("\0")).ok())
+      .unwrap_or_else(|| {
+         FrameData::TXXX(Txxx {
+            description: Cow::Owned(key.to_owned()),
+            text: text(),
+         })
+      })
+}
+
+// Cover art items hold a file name, a null, and then the image
+fn cover_picture(picture_type: u8, data: &[u8]) -> Option<FrameData<'static>> {
+   let image = &data[data.iter().position(|x| *x == 0)? + 1..];
+   let picture = Picture {
+      mime_type: Cow::Borrowed(ImageFormat::sniff(image).map_or("image/", ImageFormat::mime_type)),
+      picture_type,
+      description: Cow::Borrowed(""),
+      data: Cow::Borrowed(image),
+   };
+   Some(picture.to_frame_data())
+}
+
+#[derive(Clone, Debug)]
+pub struct ToApe {
+   pub tag: ApeTag,
+   /// Frames that have no APE key, such as a second comment, or one of the many frames that aren't text
+   pub dropped: Vec<Frame<'static>>,
+}
+
+/// Converts ID3v2 frames to an APE tag, the reverse of `to_id3`. A frame whose key is already taken by an earlier frame
+/// is dropped, as are comments and lyrics with a description, and pictures other than the front and back cover.
+pub fn from_id3(tag: &Id3Tag) -> ToApe {
+   let mut ape = ApeTag::default();
+   let mut dropped = Vec::new();
+   for frame in &tag.frames {
+      let strs = |values: &Values<Cow<str>>| values.iter().map(|x| x.to_string()).collect();
+      let item = match &frame.data {
+         FrameData::TXXX(x) if is_valid_key(&x.description) => {
+            Some((x.description.to_string(), ApeValue::Text(strs(&x.text))))
+         }
+         FrameData::COMM(x) if x.description.is_empty() => Some(("Comment".to_owned(), ApeValue::Text(strs(&x.text)))),
+         FrameData::USLT(x) if x.description.is_empty() => Some(("Lyrics".to_owned(), ApeValue::Text(strs(&x.text)))),
+         FrameData::TXXX(_) | FrameData::COMM(_) | FrameData::USLT(_) => None,
+         data => match FRAMES.iter().find(|(_, name)| *name == data.name()) {
+            Some((key, _)) => Some(((*key).to_owned(), ApeValue::Text(frame_texts(data)))),
+            None => Picture::from_frame_data(data).and_then(|x| cover_item(&x)),
+         },
+      };
+      match item {
+         Some((key, value)) if ape.get(&key).is_none() => ape.items.push(ApeItem { key, value }),
+         _ => dropped.push(frame.clone().into_owned()),
+      }
+   }
+   ToApe { tag: ape, dropped }
+}
+
+fn cover_item(picture: &Picture) -> Option<(String, ApeValue)> {
+   let (key, _) = COVERS.iter().find(|(_, x)| *x == picture.picture_type)?;
+   let extension = ImageFormat::sniff(&picture.data)
+      .or_else(|| ImageFormat::from_mime_type(&picture.mime_type))
+      .map_or("bin", ImageFormat::extension);
+   let mut data = format!("cover.{}\0", extension).into_bytes();
+   data.extend_from_slice(&picture.data);
+   Some(((*key).to_owned(), ApeValue::Binary(data)))
+}
+
+impl Tag for ApeTag {
+   fn title(&self) -> Option<&str> {
+      self.text("Title")
+   }
+
+   fn artist(&self) -> Option<&str> {
+      self.text("Artist")
+   }
+
+   fn album(&self) -> Option<&str> {
+      self.text("Album")
+   }
+
+   fn album_artist(&self) -> Option<&str> {
+      self.text("Album Artist").or_else(|| self.text("AlbumArtist"))
+   }
+
+   fn genre(&self) -> Option<&str> {
+      self.text("Genre")
+   }
+
+   fn year(&self) -> Option<u16> {
+      self.text("Year").and_then(tag::parse_year)
+   }
+
+   fn track_number(&self) -> Option<u32> {
+      self.text("Track").and_then(|x| tag::parse_number_pair(x).0)
+   }
+
+   fn track_total(&self) -> Option<u32> {
+      self.text("Track").and_then(|x| tag::parse_number_pair(x).1)
+   }
+
+   fn disc_number(&self) -> Option<u32> {
+      self.text("Disc").and_then(|x| tag::parse_number_pair(x).0)
+   }
+
+   fn disc_total(&self) -> Option<u32> {
+      self.text("Disc").and_then(|x| tag::parse_number_pair(x).1)
+   }
+
+   fn comment(&self) -> Option<&str> {
+      self.text("Comment")
+   }
+
+   fn artist_values(&self) -> Vec<&str> {
+      self.texts("Artist")
+   }
+
+   fn genre_values(&self) -> Vec<&str> {
+      self.texts("Genre")
+   }
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+   #[cfg(test)]
+   use std::io::Cursor;
+
+   #[test]
+   fn round_trip() {
+      let text = |x: &[&str]| ApeValue::Text(x.iter().map(|x| (*x).to_owned()).collect());
+      let mut tag = ApeTag::default();
+      tag.set("Title", text(&["Roygbiv"]));
+      tag.set("Artist", text(&["Boards of Canada"]));
+      tag.set("Track", text(&["5/17"]));
+      tag.set("Year", text(&["1998"]));
+      tag.set("REPLAYGAIN_TRACK_GAIN", text(&["-6.20 dB"]));
+      tag.set("Comment", text(&["one", "two"]));
+      tag.set("Cover Art (Front)", ApeValue::Binary(b"front.png\0\x89PNG".to_vec()));
+      tag.set("Related", ApeValue::Locator("http://example.com".to_owned()));
+
+      let mut file = vec![0xffu8; 100];
+      file.extend_from_slice(&tag.to_bytes().unwrap());
+      file.extend_from_slice(b"TAG");
+      file.resize(file.len() + 125, 0);
+      let read = ApeTag::read(&mut Cursor::new(&file)).unwrap().unwrap();
+      assert_eq!(read, tag);
+      assert_eq!((read.track_number(), read.track_total()), (Some(5), Some(17)));
+      let layout = strip::find_tags(&mut Cursor::new(&file), &strip::StripOptions { ape: true }).unwrap();
+      assert_eq!(layout.audio_end, 100);
+
+      let id3 = to_id3(&tag);
+      assert_eq!(id3.dropped.len(), 1);
+      let names: Vec<_> = id3.tag.frames.iter().map(|x| x.data.name()).collect();
+      assert_eq!(
+         names,
+         [*b"TIT2", *b"TPE1", *b"TRCK", *b"TDRC", *b"TXXX", *b"COMM", *b"APIC"]
+      );
+      assert_eq!(id3.tag.track_total(), Some(17));
+
+      let ape = from_id3(&id3.tag);
+      assert!(ape.dropped.is_empty());
+      tag.remove("Related");
+      tag.set("Cover Art (Front)", ApeValue::Binary(b"cover.png\0\x89PNG".to_vec()));
+      assert_eq!(ape.tag, tag);
+
+      let path = std::env::temp_dir().join(format!("walnut_ape_round_trip_{}.mp3", std::process::id()));
+      std::fs::write(&path, &file).unwrap();
+      write_to_file(&path, &ApeTag::default()).unwrap();
+      let stripped = std::fs::read(&path).unwrap();
+      assert_eq!((&stripped[..100], &stripped[100..103]), (&file[..100], &b"TAG"[..]));
+      write_to_file(&path, &tag).unwrap();
+      let read = ApeTag::read(&mut File::open(&path).unwrap()).unwrap();
+      std::fs::remove_file(&path).unwrap();
+      assert_eq!(read, Some(tag));
+   }
+
+   #[test]
+   fn items_like_a_header() {
+      // No header, so the items are all that comes before the footer, even though they start like one
+      let mut file = vec![0xffu8; 100];
+      file.extend_from_slice(b"APETAGEX");
+      file.extend_from_slice(b"APETAGEX\xd0\x07\x00\x00\x28\x00\x00\x00\x01\x00\x00\x00\x00\x00\x00\x00");
+      file.extend_from_slice(&[0; 8]);
+      match ApeTag::read(&mut Cursor::new(&file)) {
+         Err(ApeError::InvalidTag) => (),
+         x => panic!("expected an invalid tag, got {:?}", x),
+      }
+   }
+}
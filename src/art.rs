@@ -0,0 +1,358 @@
+use crate::id3::v24::Frame;
+use crate::id3::{write, Id3Tag};
+use crate::mp4;
+use crate::musicbrainz::{self, Identifier};
+use crate::probe::Metadata;
+#[cfg(feature = "art-resize")]
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt;
+
+/// The picture type of a front cover, in ID3 APIC and FLAC PICTURE terms
+pub const FRONT_COVER: u32 = 3;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ImageFormat {
+   Jpeg,
+   Png,
+   Gif,
+   Bmp,
+   Webp,
+}
+
+impl ImageFormat {
+   /// Recognizes the format from the first bytes of the image
+   pub fn sniff(data: &[u8]) -> Option<ImageFormat> {
+      Some(match data {
+         [0xff, 0xd8, 0xff, ..] => ImageFormat::Jpeg,
+         [0x89, b'P', b'N', b'G', ..] => ImageFormat::Png,
+         [b'G', b'I', b'F', b'8', ..] => ImageFormat::Gif,
+         [b'B', b'M', ..] => ImageFormat::Bmp,
+         [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => ImageFormat::Webp,
+         _ => return None,
+      })
+   }
+
+   /// Also accepts the bare subtypes ("jpg", "png") that some taggers write
+   pub fn from_mime_type(mime_type: &str) -> Option<ImageFormat> {
+      let mime_type = mime_type.trim().to_ascii_lowercase();
+      Some(match mime_type.trim_start_matches("image/") {
+         "jpeg" | "jpg" | "pjpeg" => ImageFormat::Jpeg,
+         "png" => ImageFormat::Png,
+         "gif" => ImageFormat::Gif,
+         "bmp" | "x-ms-bmp" => ImageFormat::Bmp,
+         "webp" => ImageFormat::Webp,
+         _ => return None,
+      })
+   }
+
+   pub fn mime_type(self) -> &'static str {
+      match self {
+         ImageFormat::Jpeg => "image/jpeg",
+         ImageFormat::Png => "image/png",
+         ImageFormat::Gif => "image/gif",
+         ImageFormat::Bmp => "image/bmp",
+         ImageFormat::Webp => "image/webp",
+      }
+   }
+
+   /// Without the dot
+   pub fn extension(self) -> &'static str {
+      match self {
+         ImageFormat::Jpeg => "jpg",
+         ImageFormat::Png => "png",
+         ImageFormat::Gif => "gif",
+         ImageFormat::Bmp => "bmp",
+         ImageFormat::Webp => "webp",
+      }
+   }
+}
+
+/// An embedded picture, from whichever format the file is
+#[derive(Clone, Debug)]
+pub struct Artwork {
+   /// See `FRONT_COVER`. MP4 cover art has no type, so it's always taken to be the front cover.
+   pub picture_type: u32,
+   /// As stored in the file, which isn't always right; see `format`
+   pub mime_type: String,
+   pub description: String,
+   pub data: Vec<u8>,
+}
+
+impl Artwork {
+   /// The format of the image, going by its contents when they disagree with the MIME type
+   pub fn format(&self) -> Option<ImageFormat> {
+      ImageFormat::sniff(&self.data).or_else(|| ImageFormat::from_mime_type(&self.mime_type))
+   }
+}
+
+/// Every picture embedded in the file: APIC frames, FLAC PICTURE blocks, Vorbis METADATA_BLOCK_PICTURE comments,
+/// and MP4 covr atoms
+pub fn pictures(metadata: &Metadata) -> Vec<Artwork> {
+   match metadata {
+      Metadata::Mpeg(x) => id3_pictures(x),
+      Metadata::Wav(x) => x.id3.as_ref().map(id3_pictures).unwrap_or_default(),
+      Metadata::Dsd(x) => x.id3.as_ref().map(id3_pictures).unwrap_or_default(),
+      Metadata::Adts(x) => id3_pictures(&x.tag),
+      Metadata::Flac(x) => x.pictures.iter().map(flac_picture).collect(),
+      Metadata::Ogg(x) => x.pictures.iter().map(flac_picture).collect(),
+      Metadata::Mp4(x) => x.pictures().filter_map(mp4_picture).collect(),
+      Metadata::Aiff(_) | Metadata::Asf(_) => Vec::new(),
+   }
+}
+
+/// The first picture of the given type. If there's no front cover, the first picture of any type stands in for it,
+/// as plenty of taggers don't set the type at all.
+pub fn choose(pictures: &[Artwork], picture_type: u32) -> Option<&Artwork> {
+   pictures.iter().find(|x| x.picture_type == picture_type).or_else(|| {
+      if picture_type == FRONT_COVER {
+         pictures.first()
+      } else {
+         None
+      }
+   })
+}
+
+/// Adds the picture to the tag as an APIC frame, replacing any pictures of the same type
+pub fn embed(tag: &mut Id3Tag, picture: &write::Picture) {
+   tag.frames.retain(|x| match write::Picture::from_frame_data(&x.data) {
+      Some(existing) => existing.picture_type != picture.picture_type,
+      None => true,
+   });
+   tag.frames.push(Frame::new(picture.to_frame_data()));
+}
+
+/// Limits on pictures to be embedded, to keep tags small
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ArtLimits {
+   /// Of the longer side, in pixels
+   pub max_dimension: Option<u32>,
+   pub max_bytes: Option<usize>,
+}
+
+impl ArtLimits {
+   pub fn is_unlimited(&self) -> bool {
+      self.max_dimension.is_none() && self.max_bytes.is_none()
+   }
+}
+
+/// Makes the image fit within the limits. An image that already fits is returned as it is. Anything else is scaled
+/// down (never up) and re-encoded as JPEG, at lower qualities and then smaller sizes until it fits the byte budget,
+/// or until it's as small as is still useful.
+#[cfg(feature = "art-resize")]
+pub fn fit<'a>(data: &'a [u8], limits: &ArtLimits) -> Result<(Cow<'a, [u8]>, ImageFormat), image::ImageError> {
+   use image::{DynamicImage, FilterType, GenericImageView, ImageOutputFormat};
+
+   // Below this, smaller images aren't worth having
+   const MIN_DIMENSION: u32 = 64;
+
+   let fits_bytes = |len: usize| limits.max_bytes.map(|x| len <= x).unwrap_or(true);
+   let image = image::load_from_memory(data)?;
+   let (width, height) = image.dimensions();
+   let longest = width.max(height);
+   let fits_dimension = limits.max_dimension.map(|x| longest <= x).unwrap_or(true);
+   if let (true, true, Some(format)) = (fits_bytes(data.len()), fits_dimension, ImageFormat::sniff(data)) {
+      return Ok((Cow::Borrowed(data), format));
+   }
+
+   // JPEG has no alpha channel
+   let image = DynamicImage::ImageRgb8(image.to_rgb());
+   let mut dimension = limits.max_dimension.unwrap_or(longest).min(longest).max(1);
+   loop {
+      let scaled = if dimension < longest {
+         image.resize(dimension, dimension, FilterType::Lanczos3)
+      } else {
+         image.clone()
+      };
+      let mut encoded = Vec::new();
+      for quality in [90, 80, 70, 60].iter() {
+         encoded.clear();
+         scaled.write_to(&mut encoded, ImageOutputFormat::JPEG(*quality))?;
+         if fits_bytes(encoded.len()) {
+            return Ok((Cow::Owned(encoded), ImageFormat::Jpeg));
+         }
+      }
+      if dimension <= MIN_DIMENSION {
+         return Ok((Cow::Owned(encoded), ImageFormat::Jpeg));
+      }
+      dimension = (dimension * 3 / 4).max(MIN_DIMENSION);
+   }
+}
+
+/// Where `cover_art_url` points
+pub const COVER_ART_ARCHIVE_URL: &str = "https://coverartarchive.org";
+
+/// The sizes the Cover Art Archive has of every image
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CoverSize {
+   /// 250 pixels on the longer side
+   Small,
+   /// 500 pixels
+   Large,
+   /// 1200 pixels
+   ExtraLarge,
+   /// As it was uploaded, which can be tens of megabytes
+   Original,
+}
+
+/// The front cover of the MusicBrainz release in the Cover Art Archive, which redirects to the image itself
+pub fn cover_art_url(release_id: &str, size: CoverSize) -> String {
+   let suffix = match size {
+      CoverSize::Small => "-250",
+      CoverSize::Large => "-500",
+      CoverSize::ExtraLarge => "-1200",
+      CoverSize::Original => "",
+   };
+   format!("{}/release/{}/front{}", COVER_ART_ARCHIVE_URL, release_id, suffix)
+}
+
+/// The MusicBrainz release ID of a file that has one but no pictures, so could have its cover fetched
+pub fn release_without_art(metadata: &Metadata) -> Option<&str> {
+   if !pictures(metadata).is_empty() {
+      return None;
+   }
+   musicbrainz::get(metadata, Identifier::Release)
+}
+
+#[derive(Debug)]
+pub enum FetchError {
+   Http(String),
+   /// The image is bigger than the limit given to `fetch_front_cover`
+   TooLarge,
+   NotAnImage,
+}
+
+impl fmt::Display for FetchError {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      match self {
+         FetchError::Http(message) => write!(f, "download failed: {}", message),
+         FetchError::TooLarge => write!(f, "the image is too large"),
+         FetchError::NotAnImage => write!(f, "the download isn't a JPEG, PNG, GIF, BMP, or WebP image"),
+      }
+   }
+}
+
+impl Error for FetchError {}
+
+/// Downloads the front cover of the MusicBrainz release from the Cover Art Archive. `None` if the release has no
+/// front cover there. Downloads are cut off at `max_bytes`, if given.
+#[cfg(feature = "cover-art-archive")]
+pub fn fetch_front_cover(
+   release_id: &str,
+   size: CoverSize,
+   max_bytes: Option<usize>,
+) -> Result<Option<(Vec<u8>, ImageFormat)>, FetchError> {
+   use std::io::Read;
+   use std::time::Duration;
+
+   let response = ureq::get(&cover_art_url(release_id, size))
+      .set("User-Agent", concat!("walnut/", env!("CARGO_PKG_VERSION")))
+      .timeout(Duration::from_secs(60))
+      .call();
+   if let Some(e) = response.synthetic_error() {
+      return Err(FetchError::Http(e.to_string()));
+   }
+   match response.status() {
+      200 => (),
+      404 => return Ok(None),
+      _ => return Err(FetchError::Http(response.status_line().to_owned())),
+   }
+
+   let mut data = Vec::new();
+   let limit = max_bytes.map(|x| x as u64 + 1).unwrap_or(std::u64::MAX);
+   response
+      .into_reader()
+      .take(limit)
+      .read_to_end(&mut data)
+      .map_err(|e| FetchError::Http(e.to_string()))?;
+   if max_bytes.map_or(false, |x| data.len() > x) {
+      return Err(FetchError::TooLarge);
+   }
+   let format = ImageFormat::sniff(&data).ok_or(FetchError::NotAnImage)?;
+   Ok(Some((data, format)))
+}
+
+fn id3_pictures(tag: &Id3Tag) -> Vec<Artwork> {
+   tag.frames
+      .iter()
+      .filter_map(|x| write::Picture::from_frame_data(&x.data))
+      .map(|x| Artwork {
+         picture_type: u32::from(x.picture_type),
+         mime_type: x.mime_type.into_owned(),
+         description: x.description.into_owned(),
+         data: x.data.into_owned(),
+      })
+      .collect()
+}
+
+fn flac_picture(picture: &crate::flac::Picture) -> Artwork {
+   Artwork {
+      picture_type: picture.picture_type,
+      mime_type: picture.mime_type.clone(),
+      description: picture.description.clone(),
+      data: picture.data.clone(),
+   }
+}
+
+fn mp4_picture(data: &mp4::Data) -> Option<Artwork> {
+   let (format, data) = match data {
+      mp4::Data::Jpeg(x) => (Some(ImageFormat::Jpeg), x),
+      mp4::Data::Png(x) => (Some(ImageFormat::Png), x),
+      mp4::Data::Bmp(x) => (Some(ImageFormat::Bmp), x),
+      // Untyped covr data is still an image, as long as it looks like one
+      mp4::Data::Other { data, .. } => (ImageFormat::sniff(data), data),
+      _ => return None,
+   };
+   Some(Artwork {
+      picture_type: FRONT_COVER,
+      mime_type: format.map(ImageFormat::mime_type).unwrap_or_default().to_owned(),
+      description: String::new(),
+      data: data.clone(),
+   })
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+   #[cfg(test)]
+   use crate::id3::TagBuilder;
+
+   #[test]
+   fn front_cover_from_apic() {
+      let png = b"\x89PNG\r\n\x1a\n";
+      let tag = TagBuilder::new()
+         .apic(write::Picture {
+            mime_type: "image/jpeg".into(),
+            picture_type: 4,
+            description: "Back".into(),
+            data: (&[0xff, 0xd8, 0xff, 0xe0][..]).into(),
+         })
+         .apic(write::Picture {
+            // Wrong, as it often is
+            mime_type: "image/jpeg".into(),
+            picture_type: 3,
+            description: "Fr\u{f6}nt".into(),
+            data: (&png[..]).into(),
+         })
+         .build()
+         .unwrap();
+
+      let found = pictures(&Metadata::Mpeg(tag));
+      assert_eq!(found.len(), 2);
+      let cover = choose(&found, FRONT_COVER).unwrap();
+      assert_eq!(cover.description, "Fr\u{f6}nt");
+      assert_eq!(cover.data, png);
+      assert_eq!(cover.format(), Some(ImageFormat::Png));
+      assert_eq!(choose(&found, 4).unwrap().format(), Some(ImageFormat::Jpeg));
+      assert!(choose(&found, 5).is_none());
+
+      let mut tag = Id3Tag::default();
+      embed(&mut tag, &write::Picture::front_cover("image/png", &png[..]));
+      embed(
+         &mut tag,
+         &write::Picture::front_cover("image/jpeg", &[0xff, 0xd8, 0xff][..]),
+      );
+      assert_eq!(tag.frames.len(), 1);
+      assert_eq!(pictures(&Metadata::Mpeg(tag))[0].format(), Some(ImageFormat::Jpeg));
+   }
+}
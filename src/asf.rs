@@ -0,0 +1,290 @@
+use crate::tag::{self, Tag};
+use byteorder::{ByteOrder, LittleEndian};
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom};
+
+// GUIDs as they are stored, with the first three fields little endian
+const HEADER_OBJECT: [u8; 16] = *b"\x30\x26\xb2\x75\x8e\x66\xcf\x11\xa6\xd9\x00\xaa\x00\x62\xce\x6c";
+const CONTENT_DESCRIPTION: [u8; 16] = *b"\x33\x26\xb2\x75\x8e\x66\xcf\x11\xa6\xd9\x00\xaa\x00\x62\xce\x6c";
+const EXTENDED_CONTENT_DESCRIPTION: [u8; 16] = *b"\x40\xa4\xd0\xd2\x07\xe3\xd2\x11\x97\xf0\x00\xa0\xc9\x5e\xa8\x50";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+   Text(String),
+   Bytes(Vec<u8>),
+   Bool(bool),
+   /// DWORD, QWORD, and WORD attributes
+   Integer(u64),
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Metadata {
+   /// The fixed fields of the Content Description object
+   pub title: Option<String>,
+   pub author: Option<String>,
+   pub copyright: Option<String>,
+   pub description: Option<String>,
+   pub rating: Option<String>,
+   /// The attributes of the Extended Content Description object, e.g. WM/AlbumTitle
+   pub attributes: Vec<(String, Value)>,
+}
+
+impl Metadata {
+   /// The first value of the given attribute
+   pub fn get(&self, name: &str) -> Option<&Value> {
+      self
+         .attributes
+         .iter()
+         .find(|(k, _)| k.eq_ignore_ascii_case(name))
+         .map(|(_, v)| v)
+   }
+
+   pub fn text(&self, name: &str) -> Option<&str> {
+      match self.get(name)? {
+         Value::Text(text) => Some(text.as_str()),
+         _ => None,
+      }
+   }
+
+   // Numbers are written as text by some rippers and as integers by others
+   fn number_pair(&self, name: &str) -> (Option<u32>, Option<u32>) {
+      match self.get(name) {
+         Some(Value::Text(text)) => tag::parse_number_pair(text),
+         Some(Value::Integer(v)) if *v <= u64::from(std::u32::MAX) => (Some(*v as u32), None),
+         _ => (None, None),
+      }
+   }
+}
+
+impl Tag for Metadata {
+   fn title(&self) -> Option<&str> {
+      self.title.as_ref().map(String::as_str)
+   }
+
+   fn artist(&self) -> Option<&str> {
+      self.author.as_ref().map(String::as_str)
+   }
+
+   fn album(&self) -> Option<&str> {
+      self.text("WM/AlbumTitle")
+   }
+
+   fn album_artist(&self) -> Option<&str> {
+      self.text("WM/AlbumArtist")
+   }
+
+   fn genre(&self) -> Option<&str> {
+      self.text("WM/Genre")
+   }
+
+   fn year(&self) -> Option<u16> {
+      self.text("WM/Year").and_then(tag::parse_year)
+   }
+
+   fn track_number(&self) -> Option<u32> {
+      self.number_pair("WM/TrackNumber").0
+   }
+
+   fn track_total(&self) -> Option<u32> {
+      self.number_pair("WM/TrackNumber").1
+   }
+
+   fn disc_number(&self) -> Option<u32> {
+      self.number_pair("WM/PartOfSet").0
+   }
+
+   fn disc_total(&self) -> Option<u32> {
+      self.number_pair("WM/PartOfSet").1
+   }
+
+   fn comment(&self) -> Option<&str> {
+      self.description.as_ref().map(String::as_str)
+   }
+}
+
+#[derive(Debug)]
+pub enum AsfParseError {
+   NotAsf,
+   InvalidObject,
+   Io(io::Error),
+}
+
+impl fmt::Display for AsfParseError {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      match self {
+         AsfParseError::NotAsf => write!(f, "not an ASF file"),
+         AsfParseError::InvalidObject => write!(f, "header object is truncated"),
+         AsfParseError::Io(e) => write!(f, "I/O error: {}", e),
+      }
+   }
+}
+
+impl Error for AsfParseError {
+   fn source(&self) -> Option<&(dyn Error + 'static)> {
+      match self {
+         AsfParseError::Io(e) => Some(e),
+         _ => None,
+      }
+   }
+}
+
+impl From<io::Error> for AsfParseError {
+   fn from(e: io::Error) -> AsfParseError {
+      AsfParseError::Io(e)
+   }
+}
+
+/// Reads the description objects of an ASF (WMA/WMV) file. Other header objects are skipped by seeking.
+pub fn read_metadata<S: Read + Seek>(source: &mut S) -> Result<Metadata, AsfParseError> {
+   let mut header = [0u8; 30];
+   source.seek(SeekFrom::Start(0))?;
+   source.read_exact(&mut header)?;
+   if header[0..16] != HEADER_OBJECT {
+      return Err(AsfParseError::NotAsf);
+   }
+   let end = LittleEndian::read_u64(&header[16..24]);
+   let object_count = LittleEndian::read_u32(&header[24..28]);
+
+   let mut metadata = Metadata::default();
+   let mut pos = 30;
+   for _ in 0..object_count {
+      if pos + 24 > end {
+         break;
+      }
+      let mut object_header = [0u8; 24];
+      source.seek(SeekFrom::Start(pos))?;
+      source.read_exact(&mut object_header)?;
+      let size = LittleEndian::read_u64(&object_header[16..24]);
+      if size < 24 || size > end - pos {
+         return Err(AsfParseError::InvalidObject);
+      }
+
+      if object_header[0..16] == CONTENT_DESCRIPTION || object_header[0..16] == EXTENDED_CONTENT_DESCRIPTION {
+         let mut object = Vec::new();
+         source.take(size - 24).read_to_end(&mut object)?;
+         if object_header[0..16] == CONTENT_DESCRIPTION {
+            parse_content_description(&object, &mut metadata).ok_or(AsfParseError::InvalidObject)?;
+         } else {
+            metadata.attributes = parse_extended_content_description(&object).ok_or(AsfParseError::InvalidObject)?;
+         }
+      }
+
+      pos += size;
+   }
+
+   Ok(metadata)
+}
+
+// Five lengths, then the five strings
+fn parse_content_description(object: &[u8], metadata: &mut Metadata) -> Option<()> {
+   let mut rest = object.get(10..)?;
+   let mut fields = Vec::with_capacity(5);
+   for i in 0..5 {
+      let len = LittleEndian::read_u16(&object[i * 2..i * 2 + 2]) as usize;
+      let text = decode_utf16(rest.get(0..len)?);
+      rest = &rest[len..];
+      fields.push(if text.is_empty() { None } else { Some(text) });
+   }
+
+   let mut fields = fields.into_iter();
+   metadata.title = fields.next()?;
+   metadata.author = fields.next()?;
+   metadata.copyright = fields.next()?;
+   metadata.description = fields.next()?;
+   metadata.rating = fields.next()?;
+   Some(())
+}
+
+fn parse_extended_content_description(object: &[u8]) -> Option<Vec<(String, Value)>> {
+   let count = LittleEndian::read_u16(object.get(0..2)?);
+   let mut rest = &object[2..];
+
+   // We don't trust the count enough to allocate it up front
+   let mut attributes = Vec::new();
+   for _ in 0..count {
+      let name_len = LittleEndian::read_u16(rest.get(0..2)?) as usize;
+      let name = decode_utf16(rest.get(2..2 + name_len)?);
+      rest = &rest[2 + name_len..];
+
+      let value_type = LittleEndian::read_u16(rest.get(0..2)?);
+      let value_len = LittleEndian::read_u16(rest.get(2..4)?) as usize;
+      let value = rest.get(4..4 + value_len)?;
+      rest = &rest[4 + value_len..];
+
+      let value = match value_type {
+         0 => Value::Text(decode_utf16(value)),
+         2 => Value::Bool(value.iter().any(|x| *x != 0)),
+         3 | 4 | 5 if !value.is_empty() && value.len() <= 8 => {
+            Value::Integer(LittleEndian::read_uint(value, value.len()))
+         }
+         _ => Value::Bytes(value.to_vec()),
+      };
+      attributes.push((name, value));
+   }
+
+   Some(attributes)
+}
+
+// Strings are UTF-16LE and usually include a NUL terminator
+fn decode_utf16(bytes: &[u8]) -> String {
+   let units: Vec<u16> = bytes
+      .chunks_exact(2)
+      .map(LittleEndian::read_u16)
+      .take_while(|x| *x != 0)
+      .collect();
+   String::from_utf16_lossy(&units)
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+
+   #[cfg(test)]
+   fn utf16(text: &str) -> Vec<u8> {
+      text
+         .encode_utf16()
+         .chain(Some(0))
+         .flat_map(|x| x.to_le_bytes().to_vec())
+         .collect()
+   }
+
+   #[test]
+   fn description_objects() {
+      let title = utf16("Title");
+      let mut content = Vec::new();
+      content.extend_from_slice(&(title.len() as u16).to_le_bytes());
+      content.extend_from_slice(&[0; 8]);
+      content.extend(title);
+
+      let mut extended = vec![2, 0];
+      for (name, value_type, value) in vec![
+         ("WM/AlbumTitle", 0u16, utf16("Album")),
+         ("WM/TrackNumber", 3, vec![7, 0, 0, 0]),
+      ] {
+         let name = utf16(name);
+         extended.extend_from_slice(&(name.len() as u16).to_le_bytes());
+         extended.extend(name);
+         extended.extend_from_slice(&value_type.to_le_bytes());
+         extended.extend_from_slice(&(value.len() as u16).to_le_bytes());
+         extended.extend(value);
+      }
+
+      let mut file = HEADER_OBJECT.to_vec();
+      file.extend_from_slice(&[0; 8]);
+      file.extend_from_slice(&[2, 0, 0, 0, 1, 2]);
+      for (guid, object) in vec![(CONTENT_DESCRIPTION, content), (EXTENDED_CONTENT_DESCRIPTION, extended)] {
+         file.extend_from_slice(&guid);
+         file.extend_from_slice(&(object.len() as u64 + 24).to_le_bytes());
+         file.extend(object);
+      }
+      let header_size = file.len() as u64;
+      file[16..24].copy_from_slice(&header_size.to_le_bytes());
+
+      let metadata = read_metadata(&mut io::Cursor::new(file)).unwrap();
+      assert_eq!(metadata.title(), Some("Title"));
+      assert_eq!(metadata.artist(), None);
+      assert_eq!(metadata.album(), Some("Album"));
+      assert_eq!(metadata.track_number(), Some(7));
+   }
+}
@@ -0,0 +1,391 @@
+use crate::id3::v24::FrameData;
+use crate::id3::{self, write, Id3Tag};
+use crate::query::Query;
+use crate::report::Report;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+const MAGIC: &[u8; 4] = b"WNSC";
+/// Bumped whenever the layout changes. Caches of other versions are thrown away, not converted.
+pub const VERSION: u8 = 2;
+
+/// What was found in each file the last time it was scanned, so that files that haven't changed since needn't be
+/// read again. A file counts as changed if its size or modification time is different.
+///
+/// Every file's tags are kept (as ID3v2.4 frames; see `copy::to_id3`), which makes the cache a database of the
+/// library that can be searched without reading any files (see `query`). Pictures and other binary frames are left
+/// out, as they'd make the cache nearly as big as the tags of the whole library.
+#[derive(Clone, Debug, Default)]
+pub struct ScanCache {
+   entries: HashMap<PathBuf, Entry>,
+}
+
+#[derive(Clone, Debug)]
+struct Entry {
+   /// Since the Unix epoch, to the nanosecond where the file system keeps that
+   modified: (u64, u32),
+   size: u64,
+   /// A report of this one file
+   report: Report,
+   tag: Id3Tag,
+}
+
+#[derive(Debug)]
+pub enum CacheError {
+   NotACache,
+   UnsupportedVersion(u8),
+   /// Includes a cache that ends early
+   Io(io::Error),
+}
+
+impl fmt::Display for CacheError {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      match self {
+         CacheError::NotACache => write!(f, "not a scan cache"),
+         CacheError::UnsupportedVersion(v) => write!(f, "unsupported scan cache version {}", v),
+         CacheError::Io(e) => write!(f, "I/O error: {}", e),
+      }
+   }
+}
+
+impl Error for CacheError {
+   fn source(&self) -> Option<&(dyn Error + 'static)> {
+      match self {
+         CacheError::Io(e) => Some(e),
+         _ => None,
+      }
+   }
+}
+
+impl From<io::Error> for CacheError {
+   fn from(e: io::Error) -> CacheError {
+      CacheError::Io(e)
+   }
+}
+
+impl ScanCache {
+   pub fn len(&self) -> usize {
+      self.entries.len()
+   }
+
+   pub fn is_empty(&self) -> bool {
+      self.entries.is_empty()
+   }
+
+   /// The report of the file, if it was cached and hasn't changed since. `metadata` is the file's current metadata.
+   pub fn get(&self, path: &Path, metadata: &fs::Metadata) -> Option<&Report> {
+      let entry = self.entries.get(path)?;
+      if entry.size == metadata.len() && Some(entry.modified) == modified(metadata) {
+         Some(&entry.report)
+      } else {
+         None
+      }
+   }
+
+   /// Caches the report and tags of one file. Files whose modification time isn't known aren't cached, as there'd be
+   /// no telling whether they changed.
+   pub fn insert(&mut self, path: PathBuf, metadata: &fs::Metadata, report: Report, mut tag: Id3Tag) {
+      tag.frames.retain(|x| !is_binary(&x.data));
+      if let Some(modified) = modified(metadata) {
+         self.entries.insert(
+            path,
+            Entry {
+               modified,
+               size: metadata.len(),
+               report,
+               tag,
+            },
+         );
+      }
+   }
+
+   /// The tags of the file as of when it was cached, whether or not it has changed since
+   pub fn tag(&self, path: &Path) -> Option<&Id3Tag> {
+      self.entries.get(path).map(|x| &x.tag)
+   }
+
+   /// The files whose tags match the query, by path
+   pub fn query<'a>(&'a self, query: &Query) -> Vec<(&'a Path, &'a Id3Tag)> {
+      let mut found: Vec<(&Path, &Id3Tag)> = self
+         .entries
+         .iter()
+         .filter(|(path, entry)| query.matches(path, &entry.tag))
+         .map(|(path, entry)| (path.as_path(), &entry.tag))
+         .collect();
+      found.sort_by_key(|x| x.0);
+      found
+   }
+
+   /// Forgets files that no longer exist
+   pub fn prune(&mut self) {
+      self.entries.retain(|path, _| path.is_file());
+   }
+
+   pub fn read<R: Read>(mut source: R) -> Result<ScanCache, CacheError> {
+      let mut magic = [0u8; 4];
+      source.read_exact(&mut magic)?;
+      if &magic != MAGIC {
+         return Err(CacheError::NotACache);
+      }
+      let version = source.read_u8()?;
+      if version != VERSION {
+         return Err(CacheError::UnsupportedVersion(version));
+      }
+
+      let count = source.read_u64::<LittleEndian>()?;
+      let mut entries = HashMap::new();
+      for _ in 0..count {
+         let path = PathBuf::from(read_string(&mut source)?);
+         let modified = (source.read_u64::<LittleEndian>()?, source.read_u32::<LittleEndian>()?);
+         let size = source.read_u64::<LittleEndian>()?;
+         let report = read_report(&mut source)?;
+         let tag = read_tag(&mut source)?;
+         entries.insert(
+            path,
+            Entry {
+               modified,
+               size,
+               report,
+               tag,
+            },
+         );
+      }
+      Ok(ScanCache { entries })
+   }
+
+   /// Paths that aren't valid UTF-8 are left out
+   pub fn write<W: Write>(&self, mut dest: W) -> io::Result<()> {
+      let entries: Vec<(&str, &Entry)> = self
+         .entries
+         .iter()
+         .filter_map(|(path, entry)| path.to_str().map(|x| (x, entry)))
+         .collect();
+      dest.write_all(MAGIC)?;
+      dest.write_u8(VERSION)?;
+      dest.write_u64::<LittleEndian>(entries.len() as u64)?;
+      for (path, entry) in entries {
+         write_string(&mut dest, path)?;
+         dest.write_u64::<LittleEndian>(entry.modified.0)?;
+         dest.write_u32::<LittleEndian>(entry.modified.1)?;
+         dest.write_u64::<LittleEndian>(entry.size)?;
+         write_report(&mut dest, &entry.report)?;
+         write_tag(&mut dest, &entry.tag)?;
+      }
+      dest.flush()
+   }
+
+   /// Reads the cache at `path`. A cache that doesn't exist yet is empty.
+   pub fn load<P: AsRef<Path>>(path: P) -> Result<ScanCache, CacheError> {
+      match File::open(path) {
+         Ok(f) => ScanCache::read(BufReader::new(f)),
+         Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(ScanCache::default()),
+         Err(e) => Err(e.into()),
+      }
+   }
+
+   /// Writes the cache to a temporary file next to `path`, which then replaces it, so that a scan that's cut short
+   /// doesn't leave a broken cache behind
+   pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+      let path = path.as_ref();
+      let mut temp_name = path.file_name().unwrap_or_default().to_owned();
+      temp_name.push(".tmp");
+      let temp_path = path.with_file_name(temp_name);
+      let result = File::create(&temp_path)
+         .and_then(|f| self.write(BufWriter::new(f)))
+         .and_then(|_| fs::rename(&temp_path, path));
+      if result.is_err() {
+         let _ = fs::remove_file(&temp_path);
+      }
+      result
+   }
+}
+
+fn modified(metadata: &fs::Metadata) -> Option<(u64, u32)> {
+   let since_epoch = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+   Some((since_epoch.as_secs(), since_epoch.subsec_nanos()))
+}
+
+fn read_string<R: Read>(source: &mut R) -> io::Result<String> {
+   let len = source.read_u32::<LittleEndian>()?;
+   let mut bytes = Vec::new();
+   source.take(u64::from(len)).read_to_end(&mut bytes)?;
+   if bytes.len() != len as usize {
+      return Err(io::ErrorKind::UnexpectedEof.into());
+   }
+   String::from_utf8(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn write_string<W: Write>(dest: &mut W, value: &str) -> io::Result<()> {
+   dest.write_u32::<LittleEndian>(value.len() as u32)?;
+   dest.write_all(value.as_bytes())
+}
+
+fn read_counts<R: Read>(source: &mut R) -> io::Result<BTreeMap<String, u64>> {
+   let len = source.read_u32::<LittleEndian>()?;
+   let mut counts = BTreeMap::new();
+   for _ in 0..len {
+      let name = read_string(source)?;
+      counts.insert(name, source.read_u64::<LittleEndian>()?);
+   }
+   Ok(counts)
+}
+
+fn write_counts<W: Write>(dest: &mut W, counts: &BTreeMap<String, u64>) -> io::Result<()> {
+   dest.write_u32::<LittleEndian>(counts.len() as u32)?;
+   for (name, count) in counts.iter() {
+      write_string(dest, name)?;
+      dest.write_u64::<LittleEndian>(*count)?;
+   }
+   Ok(())
+}
+
+// The counts, then the totals in the order `Report` declares them
+fn read_report<R: Read>(source: &mut R) -> io::Result<Report> {
+   let tag_versions = read_counts(source)?;
+   let encodings = read_counts(source)?;
+   let genres = read_counts(source)?;
+   let mut totals = [0u64; 9];
+   source.read_u64_into::<LittleEndian>(&mut totals)?;
+   Ok(Report {
+      files: totals[0],
+      unreadable: totals[1],
+      tag_versions,
+      encodings,
+      genres,
+      missing_title: totals[2],
+      missing_artist: totals[3],
+      missing_album: totals[4],
+      missing_art: totals[5],
+      id3v2_tags: totals[6],
+      id3v2_bytes: totals[7],
+      artwork_bytes: totals[8],
+   })
+}
+
+fn write_report<W: Write>(dest: &mut W, report: &Report) -> io::Result<()> {
+   write_counts(dest, &report.tag_versions)?;
+   write_counts(dest, &report.encodings)?;
+   write_counts(dest, &report.genres)?;
+   let totals = [
+      report.files,
+      report.unreadable,
+      report.missing_title,
+      report.missing_artist,
+      report.missing_album,
+      report.missing_art,
+      report.id3v2_tags,
+      report.id3v2_bytes,
+      report.artwork_bytes,
+   ];
+   for total in totals.iter() {
+      dest.write_u64::<LittleEndian>(*total)?;
+   }
+   Ok(())
+}
+
+// Pictures, embedded files, and private data, none of which a query can look at
+fn is_binary(data: &FrameData) -> bool {
+   match data {
+      FrameData::PRIV(_) => true,
+      FrameData::Unknown(x) => &x.name == b"APIC" || &x.name == b"GEOB",
+      _ => false,
+   }
+}
+
+// As a whole ID3v2.4 tag, after its length
+fn read_tag<R: Read>(source: &mut R) -> io::Result<Id3Tag> {
+   let len = source.read_u32::<LittleEndian>()?;
+   if len == 0 {
+      return Ok(Id3Tag::default());
+   }
+   let mut bytes = Vec::new();
+   source.take(u64::from(len)).read_to_end(&mut bytes)?;
+   if bytes.len() != len as usize {
+      return Err(io::ErrorKind::UnexpectedEof.into());
+   }
+   Id3Tag::read(&mut &bytes[..], id3::ParseOptions::default())
+      .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+}
+
+// A tag too big to write is left out, which loses it from queries but not from reports
+fn write_tag<W: Write>(dest: &mut W, tag: &Id3Tag) -> io::Result<()> {
+   let bytes = if tag.frames.is_empty() {
+      Vec::new()
+   } else {
+      write::encode_tag(tag, 0).unwrap_or_default()
+   };
+   dest.write_u32::<LittleEndian>(bytes.len() as u32)?;
+   dest.write_all(&bytes)
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+   #[cfg(test)]
+   use crate::id3::TagBuilder;
+   #[cfg(test)]
+   use crate::tag::Tag;
+
+   #[test]
+   fn round_trip() {
+      let mut report = Report {
+         files: 1,
+         missing_art: 1,
+         id3v2_bytes: 4096,
+         ..Report::default()
+      };
+      report.genres.insert("Rock".to_owned(), 1);
+
+      let mut cache = ScanCache::default();
+      cache.entries.insert(
+         PathBuf::from("music/a.mp3"),
+         Entry {
+            modified: (1_550_000_000, 123),
+            size: 5_000_000,
+            report: report.clone(),
+            tag: TagBuilder::new().title("Title").build().unwrap(),
+         },
+      );
+      let mut bytes = Vec::new();
+      cache.write(&mut bytes).unwrap();
+      let read = ScanCache::read(&bytes[..]).unwrap();
+      let entry = &read.entries[Path::new("music/a.mp3")];
+      assert_eq!((entry.modified, entry.size), ((1_550_000_000, 123), 5_000_000));
+      assert_eq!(entry.report, report);
+      assert_eq!(entry.tag.title(), Some("Title"));
+
+      match ScanCache::read(&bytes[..bytes.len() - 1]) {
+         Err(CacheError::Io(_)) => (),
+         x => panic!("expected an I/O error for a truncated cache, got {:?}", x),
+      }
+      bytes[4] = VERSION + 1;
+      match ScanCache::read(&bytes[..]) {
+         Err(CacheError::UnsupportedVersion(_)) => (),
+         x => panic!("expected an unsupported version, got {:?}", x),
+      }
+   }
+
+   #[test]
+   fn pictures_not_cached() {
+      let tag = TagBuilder::new()
+         .title("Title")
+         .frame(write::Picture::front_cover("image/png", vec![0; 100_000]).to_frame_data())
+         .build()
+         .unwrap();
+      let mut cache = ScanCache::default();
+      let path = std::env::temp_dir().join(format!("walnut_pictures_not_cached_{}.mp3", std::process::id()));
+      fs::write(&path, b"audio").unwrap();
+      let metadata = fs::metadata(&path).unwrap();
+      fs::remove_file(&path).unwrap();
+      cache.insert(path.clone(), &metadata, Report::default(), tag);
+      let cached = cache.tag(&path).unwrap();
+      assert_eq!(cached.frames.len(), 1);
+      assert_eq!(cached.title(), Some("Title"));
+   }
+}
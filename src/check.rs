@@ -0,0 +1,392 @@
+use crate::id3::normalize::{self, Cleanups};
+use crate::id3::synchsafe::{is_synchsafe, synchsafe_u32_to_u32};
+use crate::id3::v24::FrameFlags;
+use crate::id3::{self, Id3Tag, ParseOptions, Strictness};
+use crate::tag::Tag;
+use byteorder::{BigEndian, ByteOrder};
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom};
+
+/// Padding beyond this many bytes is flagged. Some taggers reserve megabytes, which every player then has to read.
+pub const MAX_PADDING: u64 = 64 * 1024;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+   /// Worth knowing, but not wrong
+   Info,
+   /// Allowed by the spec, but likely to cause trouble
+   Warning,
+   /// Against the spec
+   Error,
+}
+
+impl fmt::Display for Severity {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      match self {
+         Severity::Info => write!(f, "info"),
+         Severity::Warning => write!(f, "warning"),
+         Severity::Error => write!(f, "error"),
+      }
+   }
+}
+
+/// One problem with a file's tags
+#[derive(Clone, Debug, PartialEq)]
+pub struct Finding {
+   pub severity: Severity,
+   /// Identifies the kind of problem, e.g. "non-synchsafe-size". These don't change, so they can be matched on.
+   pub rule: &'static str,
+   /// The frame the finding is about, if it's about one
+   pub frame: Option<[u8; 4]>,
+   pub message: String,
+}
+
+impl Finding {
+   fn new<M: Into<String>>(severity: Severity, rule: &'static str, frame: Option<[u8; 4]>, message: M) -> Finding {
+      Finding {
+         severity,
+         rule,
+         frame,
+         message: message.into(),
+      }
+   }
+}
+
+/// Checks the ID3v2 tag at the start of the source and the ID3v1 tag at the end against the spec, and against the
+/// rules of thumb that keep players happy. Only ID3v2.4 tags are checked frame by frame.
+pub fn check<S: Read + Seek>(source: &mut S) -> io::Result<Vec<Finding>> {
+   let mut findings = Vec::new();
+   let file_len = source.seek(SeekFrom::End(0))?;
+
+   let mut header = Vec::new();
+   source.seek(SeekFrom::Start(0))?;
+   source.take(10).read_to_end(&mut header)?;
+   let tag = match id3::tag_len(&header) {
+      Some(len) if len <= file_len => {
+         let mut bytes = Vec::new();
+         source.seek(SeekFrom::Start(0))?;
+         source.take(len).read_to_end(&mut bytes)?;
+         check_id3v2(&bytes, &mut findings)
+      }
+      Some(_) => {
+         findings.push(Finding::new(
+            Severity::Error,
+            "truncated-tag",
+            None,
+            "the ID3v2 tag is larger than the file",
+         ));
+         None
+      }
+      None => None,
+   };
+
+   if file_len >= 128 {
+      let mut v1 = [0u8; 128];
+      source.seek(SeekFrom::Start(file_len - 128))?;
+      source.read_exact(&mut v1)?;
+      if &v1[0..3] == b"TAG" {
+         if let Some(tag) = &tag {
+            compare_id3v1(&v1, tag, &mut findings);
+         }
+      }
+   }
+
+   Ok(findings)
+}
+
+// Returns the decoded tag, if it could be read
+fn check_id3v2(bytes: &[u8], findings: &mut Vec<Finding>) -> Option<Id3Tag> {
+   let version = bytes[3];
+   if version != 4 {
+      findings.push(Finding::new(
+         Severity::Info,
+         "unchecked-version",
+         None,
+         format!("ID3v2.{} tags aren't checked", version),
+      ));
+      return None;
+   }
+   if !is_synchsafe(BigEndian::read_u32(&bytes[6..10])) {
+      findings.push(Finding::new(
+         Severity::Error,
+         "non-synchsafe-size",
+         None,
+         "the tag size isn't a synchsafe integer",
+      ));
+   }
+
+   let options = ParseOptions {
+      strictness: Strictness::Lenient,
+      ..ParseOptions::default()
+   };
+   let raw = match id3::parse_raw(bytes, options.clone()) {
+      Ok(v) => v,
+      Err(e) => {
+         findings.push(Finding::new(Severity::Error, "unreadable-tag", None, e.to_string()));
+         return None;
+      }
+   };
+   if raw.info().crc_valid == Some(false) {
+      findings.push(Finding::new(
+         Severity::Error,
+         "crc-mismatch",
+         None,
+         "the frames don't match the CRC in the extended header",
+      ));
+   }
+
+   let mut frames_end = 0;
+   for frame in raw {
+      let frame = match frame {
+         Ok(v) => v,
+         Err(e) => {
+            findings.push(Finding::new(
+               Severity::Error,
+               "invalid-frame",
+               Some(e.name),
+               e.reason.to_string(),
+            ));
+            continue;
+         }
+      };
+      frames_end = frames_end.max(u64::from(frame.offset) + u64::from(frame.size));
+
+      // The size in the frame header, which the lenient parser may have read as a plain integer
+      let start = frame.offset as usize;
+      let stored_size = BigEndian::read_u32(&bytes[start + 4..start + 8]);
+      if !is_synchsafe(stored_size) || synchsafe_u32_to_u32(stored_size) != frame.size - 10 {
+         findings.push(Finding::new(
+            Severity::Error,
+            "non-synchsafe-size",
+            Some(frame.name),
+            "the frame size isn't a synchsafe integer, as ID3v2.3 taggers write it",
+         ));
+      }
+
+      let has_encoding = frame.name[0] == b'T' || [b"COMM", b"USLT"].contains(&&frame.name);
+      let is_opaque = frame.flags.intersects(FrameFlags::COMPRESSION | FrameFlags::ENCRYPTION);
+      if has_encoding && !is_opaque {
+         check_encoding(frame.name, &frame.raw_bytes(), findings);
+      }
+   }
+
+   // The footer flag
+   let footer_len = if bytes[5] & 0b0001_0000 != 0 { 10 } else { 0 };
+   let padding = (bytes.len() as u64)
+      .saturating_sub(footer_len)
+      .saturating_sub(frames_end);
+   if padding > MAX_PADDING {
+      findings.push(Finding::new(
+         Severity::Warning,
+         "oversized-padding",
+         None,
+         format!("{} bytes of padding", padding),
+      ));
+   }
+
+   let tag = Id3Tag::read(&mut &bytes[..], options).ok()?;
+   check_frames(&tag, findings);
+   Some(tag)
+}
+
+fn check_encoding(name: [u8; 4], payload: &[u8], findings: &mut Vec<Finding>) {
+   let text = payload.get(1..).unwrap_or(&[]);
+   match payload.first() {
+      Some(0) if !text.is_ascii() && std::str::from_utf8(text).is_ok() => findings.push(Finding::new(
+         Severity::Warning,
+         "utf8-as-latin1",
+         Some(name),
+         "the text is marked ISO-8859-1, but looks like UTF-8",
+      )),
+      Some(1) if text.len() >= 2 && text[0..2] != [0xff, 0xfe] && text[0..2] != [0xfe, 0xff] => {
+         findings.push(Finding::new(
+            Severity::Error,
+            "missing-bom",
+            Some(name),
+            "UTF-16 text is missing its byte order mark",
+         ))
+      }
+      Some(x) if *x > 3 => findings.push(Finding::new(
+         Severity::Error,
+         "unknown-encoding",
+         Some(name),
+         format!("unknown text encoding {}", x),
+      )),
+      _ => (),
+   }
+}
+
+fn check_frames(tag: &Id3Tag, findings: &mut Vec<Finding>) {
+   let mut counts: HashMap<([u8; 4], String), usize> = HashMap::new();
+   for frame in tag.frames.iter() {
+      if let Some(key) = id3::unique_key(&frame.data) {
+         *counts.entry(key).or_insert(0) += 1;
+      }
+   }
+   let mut duplicates: Vec<_> = counts.into_iter().filter(|(_, count)| *count > 1).collect();
+   duplicates.sort();
+   for ((name, discriminator), count) in duplicates {
+      let message = if discriminator.is_empty() {
+         format!("{} copies of a frame that can only appear once", count)
+      } else {
+         format!(
+            "{} copies of a frame that can only appear once per {:?}",
+            count, discriminator
+         )
+      };
+      findings.push(Finding::new(Severity::Error, "duplicate-frame", Some(name), message));
+   }
+
+   let pairs = [
+      (*b"TRCK", "track", tag.track_number(), tag.track_total()),
+      (*b"TPOS", "disc", tag.disc_number(), tag.disc_total()),
+   ];
+   for (name, what, number, total) in pairs.iter() {
+      match (number, total) {
+         (Some(0), _) => findings.push(Finding::new(
+            Severity::Warning,
+            "zero-number",
+            Some(*name),
+            format!("the {} number is 0", what),
+         )),
+         (Some(number), Some(total)) if number > total => findings.push(Finding::new(
+            Severity::Warning,
+            "number-exceeds-total",
+            Some(*name),
+            format!("{} {} of {}", what, number, total),
+         )),
+         _ => (),
+      }
+   }
+
+   // `walnut check --fix` writes these corrections back
+   for correction in normalize::normalize_tag(&mut tag.clone()) {
+      let cleanups = [
+         (Cleanups::COMPOSED, "not NFC"),
+         (Cleanups::STRAY_BOM, "stray byte order mark"),
+         (Cleanups::TRAILING_WHITESPACE, "trailing whitespace"),
+         (Cleanups::DOUBLED_SPACES, "doubled spaces"),
+      ];
+      let reasons: Vec<&str> = cleanups
+         .iter()
+         .filter(|(x, _)| correction.cleanups.contains(*x))
+         .map(|(_, reason)| *reason)
+         .collect();
+      findings.push(Finding::new(
+         Severity::Info,
+         "unnormalized-text",
+         Some(correction.frame),
+         format!("{:?} ({})", correction.old.join("/"), reasons.join(", ")),
+      ));
+   }
+}
+
+// ID3v1 fields are truncated to 30 bytes, so an ID3v1 value that starts the ID3v2 one agrees with it
+fn compare_id3v1(v1: &[u8; 128], tag: &Id3Tag, findings: &mut Vec<Finding>) {
+   let field = |range: std::ops::Range<usize>| -> String {
+      let bytes = &v1[range];
+      let end = bytes.iter().position(|x| *x == 0).unwrap_or_else(|| bytes.len());
+      bytes[..end]
+         .iter()
+         .map(|x| char::from(*x))
+         .collect::<String>()
+         .trim_end()
+         .to_owned()
+   };
+   let texts = [
+      (*b"TIT2", field(3..33), tag.title()),
+      (*b"TPE1", field(33..63), tag.artist()),
+      (*b"TALB", field(63..93), tag.album()),
+   ];
+   for (name, v1_value, v2_value) in texts.iter() {
+      let agrees = match v2_value {
+         _ if v1_value.is_empty() => true,
+         Some(v2_value) => v2_value.starts_with(v1_value.as_str()),
+         None => false,
+      };
+      if !agrees {
+         findings.push(Finding::new(
+            Severity::Warning,
+            "id3v1-mismatch",
+            Some(*name),
+            format!("ID3v1 has {:?}, but ID3v2 has {:?}", v1_value, v2_value.unwrap_or("")),
+         ));
+      }
+   }
+
+   let v1_year = field(93..97).parse::<u16>().ok();
+   if let (Some(v1_year), Some(v2_year)) = (v1_year, tag.year()) {
+      if v1_year != v2_year {
+         findings.push(Finding::new(
+            Severity::Warning,
+            "id3v1-mismatch",
+            Some(*b"TDRC"),
+            format!("ID3v1 has the year {}, but ID3v2 has {}", v1_year, v2_year),
+         ));
+      }
+   }
+   // ID3v1.1 keeps the track number in the last byte of the comment
+   let v1_track = if v1[125] == 0 && v1[126] != 0 {
+      Some(u32::from(v1[126]))
+   } else {
+      None
+   };
+   if let (Some(v1_track), Some(v2_track)) = (v1_track, tag.track_number()) {
+      if v1_track != v2_track {
+         findings.push(Finding::new(
+            Severity::Warning,
+            "id3v1-mismatch",
+            Some(*b"TRCK"),
+            format!("ID3v1 has track {}, but ID3v2 has {}", v1_track, v2_track),
+         ));
+      }
+   }
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+   #[cfg(test)]
+   use crate::id3::v24::{Frame, FrameData};
+   #[cfg(test)]
+   use crate::id3::{write, TagBuilder};
+   #[cfg(test)]
+   use std::io::Cursor;
+
+   #[test]
+   fn findings() {
+      let mut tag = TagBuilder::new().title("Title").build().unwrap();
+      tag.frames.push(Frame::new(FrameData::title("Other")));
+      tag.frames
+         .push(Frame::new(FrameData::from_text(*b"TRCK", "5/3").unwrap()));
+      let mut file = write::encode_tag(&tag, 100_000).unwrap();
+      // TPE1 with a plain size of 0x80, which isn't synchsafe, and Latin-1 text that's really UTF-8
+      let mut artist = b"TPE1\x00\x00\x00\x80\x00\x00\x00".to_vec();
+      artist.extend_from_slice("Bj\u{f6}rk".as_bytes());
+      artist.resize(10 + 0x80, 0);
+      file.splice(10..10, artist);
+      let size = id3::synchsafe::u32_to_synchsafe_u32(file.len() as u32 - 10).unwrap();
+      BigEndian::write_u32(&mut file[6..10], size);
+      file.extend_from_slice(&[0xff, 0xfb, 0x90, 0x64]);
+      let mut v1 = b"TAGTitle".to_vec();
+      v1.resize(33, 0);
+      v1.extend_from_slice(b"Someone else");
+      v1.resize(128, 0);
+      file.extend_from_slice(&v1);
+
+      let findings = check(&mut Cursor::new(&file)).unwrap();
+      let rules: Vec<(&str, Option<[u8; 4]>)> = findings.iter().map(|x| (x.rule, x.frame)).collect();
+      assert_eq!(
+         rules,
+         [
+            ("non-synchsafe-size", Some(*b"TPE1")),
+            ("utf8-as-latin1", Some(*b"TPE1")),
+            ("oversized-padding", None),
+            ("duplicate-frame", Some(*b"TIT2")),
+            ("number-exceeds-total", Some(*b"TRCK")),
+            ("id3v1-mismatch", Some(*b"TPE1")),
+         ]
+      );
+   }
+}
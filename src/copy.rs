@@ -0,0 +1,202 @@
+use crate::art;
+use crate::id3::convert::{self, Version};
+use crate::id3::v24::{Date, Frame, FrameData};
+use crate::id3::write::{self, WriteError};
+use crate::id3::{self, Id3Tag, TagBuilder, TagParseError};
+use crate::probe::{self, Metadata, ProbeError};
+use crate::tag::Tag;
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+
+/// Which frames `copy_tag` copies, and what happens to the frames the destination already has
+#[derive(Clone, Debug, Default)]
+pub struct CopyFilter {
+   /// If set, only frames with these IDs are copied
+   pub frames: Option<Vec<[u8; 4]>>,
+   /// Keep the destination's frames, other than the ones that copied frames replace.
+   /// Otherwise, the destination ends up with only the copied frames.
+   pub preserve: bool,
+}
+
+impl CopyFilter {
+   fn wants(&self, frame: &Frame) -> bool {
+      match &self.frames {
+         Some(names) => names.contains(&frame.data.name()),
+         None => true,
+      }
+   }
+}
+
+#[derive(Debug)]
+pub enum CopyError {
+   Source(ProbeError),
+   Destination(TagParseError),
+   Write(WriteError),
+}
+
+impl fmt::Display for CopyError {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      match self {
+         CopyError::Source(e) => write!(f, "failed to read the source: {}", e),
+         CopyError::Destination(e) => write!(f, "failed to read the destination: {}", e),
+         CopyError::Write(e) => write!(f, "failed to write the destination: {}", e),
+      }
+   }
+}
+
+impl Error for CopyError {
+   fn source(&self) -> Option<&(dyn Error + 'static)> {
+      match self {
+         CopyError::Source(e) => Some(e),
+         CopyError::Destination(e) => Some(e),
+         CopyError::Write(e) => Some(e),
+      }
+   }
+}
+
+/// The metadata as ID3v2.4 frames. ID3 tags (including those in WAV and DSD files) are taken whole. Other formats
+/// only have their common fields (see `Tag`) and their pictures converted.
+pub fn to_id3(metadata: &Metadata) -> Id3Tag {
+   let id3 = match metadata {
+      Metadata::Mpeg(x) => Some(x),
+      Metadata::Wav(x) => x.id3.as_ref(),
+      Metadata::Dsd(x) => x.id3.as_ref(),
+      Metadata::Adts(x) => Some(&x.tag),
+      _ => None,
+   };
+   if let Some(tag) = id3 {
+      return convert::convert(tag.clone(), Version::V24).tag;
+   }
+
+   let mut builder = TagBuilder::new();
+   if let Some(title) = metadata.title() {
+      builder = builder.title(title);
+   }
+   if let Some(artist) = metadata.artist() {
+      builder = builder.artist(artist);
+   }
+   if let Some(album) = metadata.album() {
+      builder = builder.album(album);
+   }
+   if let Some(album_artist) = metadata.album_artist() {
+      builder = builder.album_artist(album_artist);
+   }
+   if let Some(genre) = metadata.genre() {
+      builder = builder.genre(genre);
+   }
+   if let Some(number) = metadata.track_number() {
+      builder = builder.track(u64::from(number), metadata.track_total().map(u64::from));
+   }
+   if let Some(number) = metadata.disc_number() {
+      builder = builder.disc(u64::from(number), metadata.disc_total().map(u64::from));
+   }
+   if let Some(year) = metadata.year() {
+      builder = builder.recording_date(Date {
+         year,
+         month: None,
+         day: None,
+         hour: None,
+         minutes: None,
+         seconds: None,
+      });
+   }
+   if let Some(comment) = metadata.comment() {
+      builder = builder.comment("XXX", comment);
+   }
+   // A value that doesn't fit in ID3 (like a track number over 65535) leaves out everything, so fall back to the
+   // pictures alone rather than copying nothing
+   let mut tag = builder.build().unwrap_or_default();
+   for picture in art::pictures(metadata).iter() {
+      art::embed(
+         &mut tag,
+         &write::Picture {
+            mime_type: picture.mime_type.as_str().into(),
+            picture_type: picture.picture_type.min(255) as u8,
+            description: picture.description.as_str().into(),
+            data: picture.data.as_slice().into(),
+         },
+      );
+   }
+   tag
+}
+
+/// Copies the frames the filter selects from `source` into `dest`, and returns how many were copied
+pub fn merge(source: &Id3Tag, dest: &mut Id3Tag, filter: &CopyFilter) -> usize {
+   let copied: Vec<&Frame> = source.frames.iter().filter(|x| filter.wants(x)).collect();
+   if filter.preserve {
+      dest
+         .frames
+         .retain(|existing| !copied.iter().any(|x| replaces(&x.data, &existing.data)));
+   } else {
+      dest.frames.clear();
+   }
+   dest.frames.extend(copied.iter().map(|x| (*x).clone()));
+   copied.len()
+}
+
+// Whether copying `new` over should remove `existing`
+fn replaces(new: &FrameData, existing: &FrameData) -> bool {
+   if let (Some(a), Some(b)) = (id3::unique_key(new), id3::unique_key(existing)) {
+      return a == b;
+   }
+   match (
+      write::Picture::from_frame_data(new),
+      write::Picture::from_frame_data(existing),
+   ) {
+      (Some(a), Some(b)) => a.picture_type == b.picture_type,
+      _ => match (new, existing) {
+         (FrameData::Unknown(a), FrameData::Unknown(b)) => a.name == b.name && a.data == b.data,
+         _ => false,
+      },
+   }
+}
+
+/// Copies the tag of one file to the ID3 tag of another (see `to_id3` and `merge`), and returns how many frames were
+/// copied. The source can be any supported format, but the destination has to be MPEG audio.
+pub fn copy_tag<P: AsRef<Path>, Q: AsRef<Path>>(source: P, dest: Q, filter: &CopyFilter) -> Result<usize, CopyError> {
+   let source = to_id3(&probe::probe_path(source).map_err(CopyError::Source)?);
+   let mut tag = write::read_tag_for_update(&dest).map_err(CopyError::Destination)?;
+   let copied = merge(&source, &mut tag, filter);
+   write::write_tag_to_file(&dest, &tag, write::WriteOptions::default()).map_err(CopyError::Write)?;
+   Ok(copied)
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+
+   #[test]
+   fn merge_frames() {
+      let source = TagBuilder::new()
+         .title("New")
+         .artist("Artist")
+         .apic(write::Picture::front_cover("image/png", &b"\x89PNG"[..]))
+         .build()
+         .unwrap();
+      let dest = TagBuilder::new()
+         .title("Old")
+         .album("Album")
+         .apic(write::Picture::front_cover("image/jpeg", &b"\xff\xd8\xff"[..]))
+         .build()
+         .unwrap();
+
+      let mut merged = dest.clone();
+      let filter = CopyFilter {
+         frames: Some(vec![*b"TIT2", *b"APIC"]),
+         preserve: true,
+      };
+      assert_eq!(merge(&source, &mut merged, &filter), 2);
+      assert_eq!(merged.title(), Some("New"));
+      assert_eq!(merged.artist(), None);
+      assert_eq!(merged.album(), Some("Album"));
+      let pictures = art::pictures(&Metadata::Mpeg(merged));
+      assert_eq!(pictures.len(), 1);
+      assert_eq!(pictures[0].mime_type, "image/png");
+
+      let mut replaced = dest.clone();
+      assert_eq!(merge(&source, &mut replaced, &CopyFilter::default()), 3);
+      assert_eq!(replaced.album(), None);
+      assert_eq!(replaced.frames.len(), 3);
+   }
+}
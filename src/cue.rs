@@ -0,0 +1,445 @@
+use crate::id3::v24::windows_1252_char;
+use crate::tag::{self, Tag};
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read};
+use std::time::Duration;
+
+/// CD frames, the unit of cue sheet times
+pub const FRAMES_PER_SECOND: u32 = 75;
+
+/// A parsed cue sheet. Commands that don't describe the tracks (CATALOG, CDTEXTFILE, FLAGS, and so on) are skipped.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CueSheet {
+   pub title: Option<String>,
+   pub performer: Option<String>,
+   pub songwriter: Option<String>,
+   /// REM comments that look like fields, e.g. ("GENRE", "Electronic") or ("DATE", "2002"), with their names
+   /// uppercased
+   pub remarks: Vec<(String, String)>,
+   pub files: Vec<CueFile>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CueFile {
+   /// As written, usually relative to the cue sheet
+   pub name: String,
+   /// e.g. "WAVE", "MP3", or "BINARY"
+   pub file_type: String,
+   pub tracks: Vec<CueTrack>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CueTrack {
+   pub number: u32,
+   pub title: Option<String>,
+   pub performer: Option<String>,
+   pub songwriter: Option<String>,
+   pub isrc: Option<String>,
+   pub indexes: Vec<CueIndex>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CueIndex {
+   /// 0 is the pregap, 1 is the start of the track proper
+   pub number: u32,
+   /// From the start of the file, in CD frames (see `FRAMES_PER_SECOND`)
+   pub frames: u32,
+}
+
+impl CueIndex {
+   pub fn offset(self) -> Duration {
+      Duration::from_millis(u64::from(self.frames) * 1000 / u64::from(FRAMES_PER_SECOND))
+   }
+}
+
+impl CueTrack {
+   /// Where the track starts in its file: at INDEX 01, or the first index if there's no INDEX 01
+   pub fn start(&self) -> Option<CueIndex> {
+      self
+         .indexes
+         .iter()
+         .find(|x| x.number == 1)
+         .or_else(|| self.indexes.first())
+         .cloned()
+   }
+}
+
+#[derive(Debug)]
+pub enum CueError {
+   /// A command with arguments that can't be right
+   InvalidCommand {
+      line: usize,
+   },
+   /// A TRACK before any FILE, or an INDEX before any TRACK
+   OutOfPlace {
+      line: usize,
+   },
+   Io(io::Error),
+}
+
+impl fmt::Display for CueError {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      match self {
+         CueError::InvalidCommand { line } => write!(f, "line {}: invalid command", line),
+         CueError::OutOfPlace { line } => write!(f, "line {}: command outside of a FILE or TRACK", line),
+         CueError::Io(e) => write!(f, "I/O error: {}", e),
+      }
+   }
+}
+
+impl Error for CueError {
+   fn source(&self) -> Option<&(dyn Error + 'static)> {
+      match self {
+         CueError::Io(e) => Some(e),
+         _ => None,
+      }
+   }
+}
+
+impl From<io::Error> for CueError {
+   fn from(e: io::Error) -> CueError {
+      CueError::Io(e)
+   }
+}
+
+/// Reads a cue sheet. Cue sheets that aren't UTF-8 are nearly always Windows-1252, and are decoded as such.
+pub fn read_cue<R: Read>(mut source: R) -> Result<CueSheet, CueError> {
+   let mut bytes = Vec::new();
+   source.read_to_end(&mut bytes)?;
+   let bytes = bytes
+      .get(3..)
+      .filter(|_| bytes.starts_with(b"\xef\xbb\xbf"))
+      .unwrap_or(&bytes);
+   match std::str::from_utf8(bytes) {
+      Ok(text) => parse(text),
+      Err(_) => parse(&bytes.iter().map(|x| windows_1252_char(*x)).collect::<String>()),
+   }
+}
+
+pub fn parse(text: &str) -> Result<CueSheet, CueError> {
+   let mut sheet = CueSheet::default();
+   for (i, line) in text.lines().enumerate() {
+      let line_number = i + 1;
+      let invalid = || CueError::InvalidCommand { line: line_number };
+      let out_of_place = || CueError::OutOfPlace { line: line_number };
+      let words = split_words(line);
+      let (command, args) = match words.split_first() {
+         Some((command, args)) => (command.to_ascii_uppercase(), args),
+         None => continue,
+      };
+      let track = sheet.files.last_mut().and_then(|x| x.tracks.last_mut());
+
+      match (command.as_str(), args) {
+         ("REM", [name, value, ..]) => sheet.remarks.push((name.to_ascii_uppercase(), value.clone())),
+         ("REM", _) => (),
+         ("TITLE", [value, ..]) | ("PERFORMER", [value, ..]) | ("SONGWRITER", [value, ..]) => {
+            let field = match (track, command.as_str()) {
+               (Some(track), "TITLE") => &mut track.title,
+               (Some(track), "PERFORMER") => &mut track.performer,
+               (Some(track), _) => &mut track.songwriter,
+               (None, "TITLE") => &mut sheet.title,
+               (None, "PERFORMER") => &mut sheet.performer,
+               (None, _) => &mut sheet.songwriter,
+            };
+            *field = Some(value.clone());
+         }
+         ("FILE", [name, ..]) => sheet.files.push(CueFile {
+            name: name.clone(),
+            file_type: args.get(1).cloned().unwrap_or_default(),
+            tracks: Vec::new(),
+         }),
+         ("TRACK", [number, ..]) => {
+            let number = number.parse().map_err(|_| invalid())?;
+            let file = sheet.files.last_mut().ok_or_else(out_of_place)?;
+            file.tracks.push(CueTrack {
+               number,
+               ..CueTrack::default()
+            });
+         }
+         ("INDEX", [number, time, ..]) => {
+            let index = CueIndex {
+               number: number.parse().map_err(|_| invalid())?,
+               frames: parse_time(time).ok_or_else(invalid)?,
+            };
+            track.ok_or_else(out_of_place)?.indexes.push(index);
+         }
+         ("ISRC", [value, ..]) => track.ok_or_else(out_of_place)?.isrc = Some(value.clone()),
+         ("TITLE", _) | ("PERFORMER", _) | ("SONGWRITER", _) | ("FILE", _) | ("TRACK", _) | ("INDEX", _) => {
+            return Err(invalid())
+         }
+         _ => (),
+      }
+   }
+   Ok(sheet)
+}
+
+/// A track of a cue sheet, with its tags merged with the tag of the file it's in. Fields of the track win, then fields
+/// of the sheet, then fields of the file's tag, except that the title of the file's tag (which is usually the
+/// album's) is never taken for the track's.
+pub struct VirtualTrack<'a> {
+   pub sheet: &'a CueSheet,
+   pub file: &'a CueFile,
+   pub track: &'a CueTrack,
+   /// The tag of the file, if it has one
+   pub physical: Option<&'a dyn Tag>,
+   /// From the start of the file
+   pub start: Duration,
+   /// Where the next track in the file starts, if there is one. Pregaps (INDEX 00) belong to the track before them,
+   /// as they do when a CD plays straight through.
+   pub end: Option<Duration>,
+}
+
+impl CueSheet {
+   /// Every track, merged with the tags of the files (see `VirtualTrack`). `physical` holds the tag of each file, in
+   /// the order of `files`; `None` (or leaving the file out) is for a file that has no tag, or couldn't be read.
+   pub fn virtual_tracks<'a>(&'a self, physical: &[Option<&'a dyn Tag>]) -> Vec<VirtualTrack<'a>> {
+      let mut tracks = Vec::new();
+      for (file, physical) in self
+         .files
+         .iter()
+         .zip(physical.iter().cloned().chain(std::iter::repeat(None)))
+      {
+         for (i, track) in file.tracks.iter().enumerate() {
+            let start = match track.start() {
+               Some(v) => v.offset(),
+               // A track with no indexes can't be played
+               None => continue,
+            };
+            let end = file.tracks[i + 1..]
+               .iter()
+               .find_map(CueTrack::start)
+               .map(CueIndex::offset);
+            tracks.push(VirtualTrack {
+               sheet: self,
+               file,
+               track,
+               physical,
+               start,
+               end,
+            });
+         }
+      }
+      tracks
+   }
+
+   /// A REM field by name, e.g. "GENRE"
+   pub fn remark(&self, name: &str) -> Option<&str> {
+      self
+         .remarks
+         .iter()
+         .find(|(x, _)| x.eq_ignore_ascii_case(name))
+         .map(|(_, value)| value.as_str())
+   }
+
+   fn track_count(&self) -> usize {
+      self.files.iter().map(|x| x.tracks.len()).sum()
+   }
+}
+
+impl<'a> VirtualTrack<'a> {
+   pub fn duration(&self) -> Option<Duration> {
+      self.end.map(|end| end.checked_sub(self.start).unwrap_or_default())
+   }
+}
+
+impl<'a> Tag for VirtualTrack<'a> {
+   fn title(&self) -> Option<&str> {
+      self.track.title.as_ref().map(String::as_str)
+   }
+
+   fn artist(&self) -> Option<&str> {
+      self
+         .track
+         .performer
+         .as_ref()
+         .or_else(|| self.sheet.performer.as_ref())
+         .map(String::as_str)
+         .or_else(|| self.physical.and_then(|x| x.artist()))
+   }
+
+   fn album(&self) -> Option<&str> {
+      self
+         .sheet
+         .title
+         .as_ref()
+         .map(String::as_str)
+         .or_else(|| self.physical.and_then(|x| x.album()))
+   }
+
+   fn album_artist(&self) -> Option<&str> {
+      self
+         .sheet
+         .performer
+         .as_ref()
+         .map(String::as_str)
+         .or_else(|| self.physical.and_then(|x| x.album_artist()))
+   }
+
+   fn genre(&self) -> Option<&str> {
+      self
+         .sheet
+         .remark("GENRE")
+         .or_else(|| self.physical.and_then(|x| x.genre()))
+   }
+
+   fn year(&self) -> Option<u16> {
+      self
+         .sheet
+         .remark("DATE")
+         .and_then(tag::parse_year)
+         .or_else(|| self.physical.and_then(|x| x.year()))
+   }
+
+   fn track_number(&self) -> Option<u32> {
+      Some(self.track.number)
+   }
+
+   fn track_total(&self) -> Option<u32> {
+      Some(self.sheet.track_count() as u32)
+   }
+
+   fn disc_number(&self) -> Option<u32> {
+      self
+         .sheet
+         .remark("DISCNUMBER")
+         .and_then(|x| x.parse().ok())
+         .or_else(|| self.physical.and_then(|x| x.disc_number()))
+   }
+
+   fn disc_total(&self) -> Option<u32> {
+      self
+         .sheet
+         .remark("TOTALDISCS")
+         .and_then(|x| x.parse().ok())
+         .or_else(|| self.physical.and_then(|x| x.disc_total()))
+   }
+
+   fn comment(&self) -> Option<&str> {
+      self
+         .sheet
+         .remark("COMMENT")
+         .or_else(|| self.physical.and_then(|x| x.comment()))
+   }
+}
+
+// mm:ss:ff, in CD frames. Minutes can go past 99 for long files.
+fn parse_time(text: &str) -> Option<u32> {
+   let mut parts = text.split(':').map(|x| x.parse::<u32>().ok());
+   let (minutes, seconds, frames) = (parts.next()??, parts.next()??, parts.next()??);
+   if parts.next().is_some() || seconds >= 60 || frames >= FRAMES_PER_SECOND {
+      return None;
+   }
+   // A malformed time can be too long to count in frames
+   minutes
+      .checked_mul(60)?
+      .checked_add(seconds)?
+      .checked_mul(FRAMES_PER_SECOND)?
+      .checked_add(frames)
+}
+
+// Words separated by whitespace, where double quotes keep a word together
+fn split_words(line: &str) -> Vec<String> {
+   let mut words = Vec::new();
+   let mut chars = line.trim().chars().peekable();
+   while let Some(c) = chars.next() {
+      match c {
+         _ if c.is_whitespace() => (),
+         '"' => {
+            let mut word = String::new();
+            while let Some(c) = chars.next() {
+               if c == '"' {
+                  break;
+               }
+               word.push(c);
+            }
+            words.push(word);
+         }
+         _ => {
+            let mut word = c.to_string();
+            while let Some(c) = chars.peek() {
+               if c.is_whitespace() {
+                  break;
+               }
+               word.push(*c);
+               chars.next();
+            }
+            words.push(word);
+         }
+      }
+   }
+   words
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+   #[cfg(test)]
+   use crate::id3::v24::Date;
+   #[cfg(test)]
+   use crate::id3::TagBuilder;
+
+   #[test]
+   fn virtual_tracks() {
+      let cue = "\u{feff}REM GENRE Electronic\r\n\
+                 REM DATE 2002\r\n\
+                 PERFORMER \"Boards of Canada\"\r\n\
+                 TITLE \"Geogaddi\"\r\n\
+                 FILE \"Geogaddi.flac\" WAVE\r\n\
+                 \x20 TRACK 01 AUDIO\r\n\
+                 \x20   TITLE \"Ready Lets Go\"\r\n\
+                 \x20   INDEX 01 00:00:00\r\n\
+                 \x20 TRACK 02 AUDIO\r\n\
+                 \x20   TITLE \"Music Is Math\"\r\n\
+                 \x20   INDEX 00 00:58:20\r\n\
+                 \x20   INDEX 01 00:59:37\r\n";
+      let sheet = read_cue(cue.as_bytes()).unwrap();
+      assert_eq!(sheet.files[0].name, "Geogaddi.flac");
+      assert_eq!(sheet.files[0].tracks[1].indexes.len(), 2);
+
+      let physical = TagBuilder::new()
+         .title("Geogaddi")
+         .comment("eng", "Rip")
+         .recording_date(Date {
+            year: 2001,
+            month: None,
+            day: None,
+            hour: None,
+            minutes: None,
+            seconds: None,
+         })
+         .build()
+         .unwrap();
+      let tracks = sheet.virtual_tracks(&[Some(&physical as &dyn Tag)]);
+      assert_eq!(tracks.len(), 2);
+      assert_eq!(tracks[0].title(), Some("Ready Lets Go"));
+      assert_eq!(tracks[0].artist(), Some("Boards of Canada"));
+      assert_eq!(tracks[0].genre(), Some("Electronic"));
+      assert_eq!(tracks[0].year(), Some(2002));
+      assert_eq!(tracks[0].comment(), Some("Rip"));
+      assert_eq!(tracks[1].track_number(), Some(2));
+      assert_eq!(tracks[1].track_total(), Some(2));
+      // 59 seconds and 37 frames
+      assert_eq!(tracks[0].duration(), Some(Duration::from_millis(59_493)));
+      assert_eq!(tracks[1].end, None);
+
+      match parse("TRACK 01 AUDIO") {
+         Err(CueError::OutOfPlace { line: 1 }) => (),
+         x => panic!("expected a track outside of a file, got {:?}", x),
+      }
+      match parse("FILE a.wav WAVE\nTRACK 01 AUDIO\nINDEX 01 00:61:00") {
+         Err(CueError::InvalidCommand { line: 3 }) => (),
+         x => panic!("expected an invalid index, got {:?}", x),
+      }
+      match parse("FILE a.wav WAVE\nTRACK 01 AUDIO\nINDEX 01 99999999:00:00") {
+         Err(CueError::InvalidCommand { line: 3 }) => (),
+         x => panic!("expected an invalid index, got {:?}", x),
+      }
+   }
+
+   #[test]
+   fn windows_1252() {
+      let sheet = read_cue(&b"TITLE \"\x93Quoted\x94\"\n"[..]).unwrap();
+      assert_eq!(sheet.title.as_ref().map(String::as_str), Some("\u{201c}Quoted\u{201d}"));
+   }
+}
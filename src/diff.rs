@@ -0,0 +1,210 @@
+use crate::id3::v24::{Frame, FrameData};
+use crate::id3::write::{self, encode_frame_data};
+use crate::id3::{self, Id3Tag};
+use std::collections::HashMap;
+
+/// How one frame differs between two tags
+#[derive(Clone, Debug)]
+pub enum FrameDiff<'a> {
+   Added(&'a Frame<'static>),
+   Removed(&'a Frame<'static>),
+   Changed {
+      old: &'a Frame<'static>,
+      new: &'a Frame<'static>,
+      /// Where the encoded frames differ (see `byte_changes`)
+      bytes: Vec<ByteChange>,
+   },
+}
+
+/// A run of bytes that differs between two encoded frames
+#[derive(Clone, Debug, PartialEq)]
+pub struct ByteChange {
+   pub offset: usize,
+   pub old: Vec<u8>,
+   pub new: Vec<u8>,
+}
+
+/// The frames that were added, removed, or changed between two tags, in the order they appear in the tags. Frames
+/// are matched up by what makes them unique (the description of a TXXX frame, the type of a picture, and so on), and
+/// repeated frames by their order. Frames that only moved, or that differ only in how they're encoded, are the same.
+pub fn diff<'a>(old: &'a Id3Tag, new: &'a Id3Tag) -> Vec<FrameDiff<'a>> {
+   let old_keys = keys(old);
+   let new_keys = keys(new);
+   let new_by_key: HashMap<&FrameKey, &Frame> = new_keys.iter().zip(new.frames.iter()).collect();
+   let old_by_key: HashMap<&FrameKey, &Frame> = old_keys.iter().zip(old.frames.iter()).collect();
+
+   let mut diffs = Vec::new();
+   for (key, old_frame) in old_keys.iter().zip(old.frames.iter()) {
+      match new_by_key.get(key) {
+         Some(new_frame) => {
+            let (old_bytes, new_bytes) = (encode_frame_data(&old_frame.data), encode_frame_data(&new_frame.data));
+            if old_bytes != new_bytes {
+               diffs.push(FrameDiff::Changed {
+                  old: old_frame,
+                  new: new_frame,
+                  bytes: byte_changes(&old_bytes, &new_bytes),
+               });
+            }
+         }
+         None => diffs.push(FrameDiff::Removed(old_frame)),
+      }
+   }
+   for (key, new_frame) in new_keys.iter().zip(new.frames.iter()) {
+      if !old_by_key.contains_key(key) {
+         diffs.push(FrameDiff::Added(new_frame));
+      }
+   }
+   diffs
+}
+
+/// Frames with no text to speak of, which are best compared byte by byte
+pub fn is_binary(data: &FrameData) -> bool {
+   match data {
+      FrameData::PRIV(_) | FrameData::RVA2(_) | FrameData::EQU2(_) | FrameData::RVRB(_) => true,
+      FrameData::Unknown(_) => true,
+      _ => false,
+   }
+}
+
+/// The runs of bytes that differ. Equal-length data is compared byte for byte, with runs less than 8 bytes apart
+/// joined together. Otherwise, there's one run between the longest common prefix and suffix.
+pub fn byte_changes(old: &[u8], new: &[u8]) -> Vec<ByteChange> {
+   // Joining runs this close together reads better than a run every other byte
+   const GAP: usize = 8;
+
+   if old.len() != new.len() {
+      let prefix = old.iter().zip(new.iter()).take_while(|(a, b)| a == b).count();
+      let max_suffix = old.len().min(new.len()) - prefix;
+      let suffix = old
+         .iter()
+         .rev()
+         .zip(new.iter().rev())
+         .take(max_suffix)
+         .take_while(|(a, b)| a == b)
+         .count();
+      return vec![ByteChange {
+         offset: prefix,
+         old: old[prefix..old.len() - suffix].to_vec(),
+         new: new[prefix..new.len() - suffix].to_vec(),
+      }];
+   }
+
+   let mut changes: Vec<ByteChange> = Vec::new();
+   let mut run_end = 0;
+   for i in (0..old.len()).filter(|i| old[*i] != new[*i]) {
+      match changes.last_mut() {
+         Some(change) if i - run_end < GAP => {
+            change.old.extend_from_slice(&old[run_end..=i]);
+            change.new.extend_from_slice(&new[run_end..=i]);
+         }
+         _ => changes.push(ByteChange {
+            offset: i,
+            old: vec![old[i]],
+            new: vec![new[i]],
+         }),
+      }
+      run_end = i + 1;
+   }
+   changes
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+struct FrameKey {
+   name: [u8; 4],
+   discriminator: String,
+   /// Among frames with the same name and discriminator
+   occurrence: usize,
+}
+
+fn keys(tag: &Id3Tag) -> Vec<FrameKey> {
+   let mut seen: HashMap<([u8; 4], String), usize> = HashMap::new();
+   tag.frames
+      .iter()
+      .map(|frame| {
+         let (name, discriminator) = id3::unique_key(&frame.data).unwrap_or_else(|| {
+            let discriminator = match write::Picture::from_frame_data(&frame.data) {
+               Some(picture) => picture.picture_type.to_string(),
+               None => String::new(),
+            };
+            (frame.data.name(), discriminator)
+         });
+         let occurrence = seen.entry((name, discriminator.clone())).or_insert(0);
+         *occurrence += 1;
+         FrameKey {
+            name,
+            discriminator,
+            occurrence: *occurrence - 1,
+         }
+      })
+      .collect()
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+   #[cfg(test)]
+   use crate::id3::TagBuilder;
+
+   #[test]
+   fn frame_diffs() {
+      let old = TagBuilder::new()
+         .title("Title")
+         .artist("Artist")
+         .user_text("MOOD", "Calm")
+         .frame(FrameData::Unknown(crate::id3::v24::Unknown {
+            name: *b"PCNT",
+            data: vec![0, 0, 0, 1].into(),
+         }))
+         .build()
+         .unwrap();
+      let new = TagBuilder::new()
+         .user_text("MOOD", "Calm")
+         .title("Title (Remastered)")
+         .album("Album")
+         .frame(FrameData::Unknown(crate::id3::v24::Unknown {
+            name: *b"PCNT",
+            data: vec![0, 0, 0, 2].into(),
+         }))
+         .build()
+         .unwrap();
+
+      let diffs = diff(&old, &new);
+      let summary: Vec<(&str, [u8; 4])> = diffs
+         .iter()
+         .map(|x| match x {
+            FrameDiff::Added(frame) => ("added", frame.data.name()),
+            FrameDiff::Removed(frame) => ("removed", frame.data.name()),
+            FrameDiff::Changed { new, .. } => ("changed", new.data.name()),
+         })
+         .collect();
+      assert_eq!(
+         summary,
+         [
+            ("changed", *b"TIT2"),
+            ("removed", *b"TPE1"),
+            ("changed", *b"PCNT"),
+            ("added", *b"TALB"),
+         ]
+      );
+      match &diffs[2] {
+         FrameDiff::Changed { bytes, .. } => assert_eq!(
+            *bytes,
+            [ByteChange {
+               offset: 3,
+               old: vec![1],
+               new: vec![2],
+            }]
+         ),
+         _ => unreachable!(),
+      }
+
+      assert_eq!(
+         byte_changes(b"abcdef", b"abXYZdef"),
+         [ByteChange {
+            offset: 2,
+            old: b"c".to_vec(),
+            new: b"XYZ".to_vec(),
+         }]
+      );
+   }
+}
@@ -0,0 +1,222 @@
+use crate::id3::{self, Id3Tag, TagParseError};
+use crate::tag::Tag;
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Container {
+   /// Sony's DSD Stream File (.dsf)
+   Dsf,
+   /// Philips' DSDIFF (.dff)
+   Dff,
+}
+
+#[derive(Clone, Debug)]
+pub struct Metadata {
+   pub container: Container,
+   pub id3: Option<Id3Tag>,
+   /// Why the ID3 tag couldn't be read, e.g. it's ID3v2.2. The container is still known.
+   pub id3_error: Option<TagParseError>,
+}
+
+#[derive(Debug)]
+pub enum DsdParseError {
+   NotDsd,
+   InvalidChunk,
+   Io(io::Error),
+}
+
+impl fmt::Display for DsdParseError {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      match self {
+         DsdParseError::NotDsd => write!(f, "not a DSF or DSDIFF file"),
+         DsdParseError::InvalidChunk => write!(f, "chunk extends past the end of its parent"),
+         DsdParseError::Io(e) => write!(f, "I/O error: {}", e),
+      }
+   }
+}
+
+impl Error for DsdParseError {
+   fn source(&self) -> Option<&(dyn Error + 'static)> {
+      match self {
+         DsdParseError::Io(e) => Some(e),
+         _ => None,
+      }
+   }
+}
+
+impl From<io::Error> for DsdParseError {
+   fn from(e: io::Error) -> DsdParseError {
+      DsdParseError::Io(e)
+   }
+}
+
+/// Finds and reads the ID3 tag of a DSF or DSDIFF file. A tag that fails to parse is left out, with its error kept
+/// in `Metadata::id3_error`.
+pub fn read_metadata<S: Read + Seek>(source: &mut S) -> Result<Metadata, DsdParseError> {
+   let mut header = [0u8; 16];
+   source.seek(SeekFrom::Start(0))?;
+   source.read_exact(&mut header)?;
+
+   match &header[0..4] {
+      b"DSD " => read_dsf(source),
+      b"FRM8" if &header[12..16] == b"DSD " => read_dff(source, 12 + BigEndian::read_u64(&header[4..12])),
+      _ => Err(DsdParseError::NotDsd),
+   }
+}
+
+// The DSD chunk holds the offset of the tag (0 if there is none), which is always at the end of the file
+fn read_dsf<S: Read + Seek>(source: &mut S) -> Result<Metadata, DsdParseError> {
+   let mut dsd_chunk = [0u8; 28];
+   source.seek(SeekFrom::Start(0))?;
+   source.read_exact(&mut dsd_chunk)?;
+   let file_len = LittleEndian::read_u64(&dsd_chunk[12..20]);
+   let tag_offset = LittleEndian::read_u64(&dsd_chunk[20..28]);
+
+   let mut metadata = Metadata {
+      container: Container::Dsf,
+      id3: None,
+      id3_error: None,
+   };
+   if tag_offset != 0 {
+      if tag_offset >= file_len {
+         return Err(DsdParseError::InvalidChunk);
+      }
+      source.seek(SeekFrom::Start(tag_offset))?;
+      read_id3(source, &mut metadata);
+   }
+   Ok(metadata)
+}
+
+// DSDIFF has no official tag, but taggers agree on an `ID3 ` chunk at the top level
+fn read_dff<S: Read + Seek>(source: &mut S, end: u64) -> Result<Metadata, DsdParseError> {
+   let mut metadata = Metadata {
+      container: Container::Dff,
+      id3: None,
+      id3_error: None,
+   };
+   let mut pos = 16;
+   while pos + 12 <= end {
+      let mut chunk_header = [0u8; 12];
+      source.seek(SeekFrom::Start(pos))?;
+      source.read_exact(&mut chunk_header)?;
+      let size = BigEndian::read_u64(&chunk_header[4..12]);
+      if size > end - pos - 12 {
+         return Err(DsdParseError::InvalidChunk);
+      }
+
+      if &chunk_header[0..4] == b"ID3 " {
+         read_id3(&mut source.take(size), &mut metadata);
+      }
+
+      // Chunks are padded to an even size
+      pos += 12 + size + (size & 1);
+   }
+
+   Ok(metadata)
+}
+
+fn read_id3<S: Read>(source: &mut S, metadata: &mut Metadata) {
+   match Id3Tag::read(source, id3::ParseOptions::default()) {
+      Ok(tag) => metadata.id3 = Some(tag),
+      Err(e) => metadata.id3_error = Some(e),
+   }
+}
+
+impl Tag for Metadata {
+   fn title(&self) -> Option<&str> {
+      self.id3.as_ref()?.title()
+   }
+
+   fn artist(&self) -> Option<&str> {
+      self.id3.as_ref()?.artist()
+   }
+
+   fn album(&self) -> Option<&str> {
+      self.id3.as_ref()?.album()
+   }
+
+   fn album_artist(&self) -> Option<&str> {
+      self.id3.as_ref()?.album_artist()
+   }
+
+   fn genre(&self) -> Option<&str> {
+      self.id3.as_ref()?.genre()
+   }
+
+   fn year(&self) -> Option<u16> {
+      self.id3.as_ref()?.year()
+   }
+
+   fn track_number(&self) -> Option<u32> {
+      self.id3.as_ref()?.track_number()
+   }
+
+   fn track_total(&self) -> Option<u32> {
+      self.id3.as_ref()?.track_total()
+   }
+
+   fn disc_number(&self) -> Option<u32> {
+      self.id3.as_ref()?.disc_number()
+   }
+
+   fn disc_total(&self) -> Option<u32> {
+      self.id3.as_ref()?.disc_total()
+   }
+
+   fn comment(&self) -> Option<&str> {
+      self.id3.as_ref()?.comment()
+   }
+
+   fn artist_values(&self) -> Vec<&str> {
+      self.id3.as_ref().map_or_else(Vec::new, |x| x.artist_values())
+   }
+
+   fn genre_values(&self) -> Vec<&str> {
+      self.id3.as_ref().map_or_else(Vec::new, |x| x.genre_values())
+   }
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+
+   #[cfg(test)]
+   const TAG: &[u8] = b"ID3\x04\x00\x00\x00\x00\x00\x11TIT2\x00\x00\x00\x07\x00\x00\x03Title\x00";
+
+   #[test]
+   fn dsf_tag_pointer() {
+      let mut file = Vec::from(&b"DSD \x1c\x00\x00\x00\x00\x00\x00\x00"[..]);
+      file.extend_from_slice(&(28 + 16 + TAG.len() as u64).to_le_bytes());
+      file.extend_from_slice(&(28u64 + 16).to_le_bytes());
+      file.extend_from_slice(b"data\x10\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00");
+      file.extend_from_slice(TAG);
+
+      let metadata = read_metadata(&mut io::Cursor::new(&file)).unwrap();
+      assert_eq!(metadata.container, Container::Dsf);
+      assert_eq!(metadata.title(), Some("Title"));
+
+      // An ID3v2.2 tag can't be read, but the file still can
+      file[28 + 16 + 3] = 2;
+      let metadata = read_metadata(&mut io::Cursor::new(file)).unwrap();
+      assert_eq!(metadata.container, Container::Dsf);
+      assert!(metadata.id3.is_none() && metadata.id3_error.is_some());
+   }
+
+   #[test]
+   fn dff_id3_chunk() {
+      let mut file = Vec::from(&b"FRM8\x00\x00\x00\x00\x00\x00\x00\x00DSD "[..]);
+      file.extend_from_slice(b"FVER\x00\x00\x00\x00\x00\x00\x00\x04\x01\x05\x00\x00");
+      file.extend_from_slice(b"ID3 ");
+      file.extend_from_slice(&(TAG.len() as u64).to_be_bytes());
+      file.extend_from_slice(TAG);
+      let form_size = file.len() as u64 - 12;
+      file[4..12].copy_from_slice(&form_size.to_be_bytes());
+
+      let metadata = read_metadata(&mut io::Cursor::new(file)).unwrap();
+      assert_eq!(metadata.container, Container::Dff);
+      assert_eq!(metadata.title(), Some("Title"));
+   }
+}
@@ -0,0 +1,202 @@
+use crate::hash::{self, Fnv64};
+use crate::mpeg;
+use crate::probe::{self, Metadata, ProbeError};
+use crate::tag::Tag;
+use std::collections::HashMap;
+use std::fs::File;
+use std::hash::Hasher;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// What duplicate detection knows about a file
+#[derive(Clone, Debug)]
+pub struct TrackInfo {
+   pub path: PathBuf,
+   /// Of the whole file, in bytes
+   pub size: u64,
+   /// See `hash::hash_audio`
+   pub audio_hash: u64,
+   pub artist: Option<String>,
+   pub title: Option<String>,
+   pub duration: Option<Duration>,
+   /// The average, in kbit/s
+   pub bitrate: Option<u32>,
+}
+
+impl TrackInfo {
+   pub fn read<P: AsRef<Path>>(path: P) -> Result<TrackInfo, ProbeError> {
+      let path = path.as_ref();
+      let mut file = File::open(path)?;
+      let size = file.metadata()?.len();
+      let metadata = probe::probe(&mut file)?;
+
+      let (duration, audio_len) = match &metadata {
+         Metadata::Mpeg(_) => match mpeg::read_properties(&mut file) {
+            Ok(p) => (Some(p.duration()), p.audio_len),
+            Err(_) => (None, size),
+         },
+         Metadata::Flac(x) => (Some(x.stream_info.duration()), size),
+         Metadata::Adts(x) => match x.properties {
+            Some(p) => (Some(p.duration()), p.audio_len),
+            None => (None, size),
+         },
+         _ => (None, size),
+      };
+      let bitrate = duration
+         .map(|x| x.as_millis() as u64)
+         .filter(|ms| *ms > 0)
+         .map(|ms| (audio_len * 8 / ms) as u32);
+
+      let mut hasher = Fnv64::default();
+      hash::hash_audio(&mut file, &mut hasher)?;
+
+      Ok(TrackInfo {
+         path: path.to_owned(),
+         size,
+         audio_hash: hasher.finish(),
+         artist: metadata.artist().map(str::to_owned),
+         title: metadata.title().map(str::to_owned),
+         duration,
+         bitrate,
+      })
+   }
+
+   // Files whose artist, title, and duration (to the second) match are probably the same recording
+   fn tag_key(&self) -> Option<(String, String, u64)> {
+      let duration = self.duration?;
+      let seconds = (duration.as_millis() as u64 + 500) / 1000;
+      Some((
+         normalize(self.artist.as_ref()?),
+         normalize(self.title.as_ref()?),
+         seconds,
+      ))
+   }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct DupeOptions {
+   /// Files with identical audio are duplicates
+   pub audio: bool,
+   /// Files with the same artist, title, and duration are duplicates, even if they were encoded differently
+   pub tags: bool,
+}
+
+impl Default for DupeOptions {
+   fn default() -> DupeOptions {
+      DupeOptions {
+         audio: true,
+         tags: true,
+      }
+   }
+}
+
+/// Groups the files that are duplicates of each other by any of the enabled measures.
+/// Each group is sorted from the highest bitrate down, and the groups from the most reclaimable space down.
+pub fn find_duplicates<'a>(tracks: &'a [TrackInfo], options: &DupeOptions) -> Vec<Vec<&'a TrackInfo>> {
+   // A union-find over the tracks, so that A ~ B by audio and B ~ C by tags puts all three together
+   let mut parents: Vec<usize> = (0..tracks.len()).collect();
+   if options.audio {
+      let mut first_by_hash = HashMap::new();
+      for (i, track) in tracks.iter().enumerate() {
+         let first = *first_by_hash.entry(track.audio_hash).or_insert(i);
+         union(&mut parents, first, i);
+      }
+   }
+   if options.tags {
+      let mut first_by_tags = HashMap::new();
+      for (i, track) in tracks.iter().enumerate() {
+         if let Some(key) = track.tag_key() {
+            let first = *first_by_tags.entry(key).or_insert(i);
+            union(&mut parents, first, i);
+         }
+      }
+   }
+
+   let mut groups: HashMap<usize, Vec<&TrackInfo>> = HashMap::new();
+   for (i, track) in tracks.iter().enumerate() {
+      let root = find(&mut parents, i);
+      groups.entry(root).or_insert_with(Vec::new).push(track);
+   }
+   let mut groups: Vec<Vec<&TrackInfo>> = groups.into_iter().map(|(_, x)| x).filter(|x| x.len() > 1).collect();
+   for group in groups.iter_mut() {
+      group.sort_by(|a, b| {
+         b.bitrate
+            .cmp(&a.bitrate)
+            .then(b.size.cmp(&a.size))
+            .then(a.path.cmp(&b.path))
+      });
+   }
+   groups.sort_by(|a, b| reclaimable(b).cmp(&reclaimable(a)).then(a[0].path.cmp(&b[0].path)));
+   groups
+}
+
+/// How many bytes deleting all but the first file of a group would free
+pub fn reclaimable(group: &[&TrackInfo]) -> u64 {
+   group.iter().skip(1).map(|x| x.size).sum()
+}
+
+fn find(parents: &mut [usize], mut i: usize) -> usize {
+   while parents[i] != i {
+      parents[i] = parents[parents[i]];
+      i = parents[i];
+   }
+   i
+}
+
+fn union(parents: &mut [usize], a: usize, b: usize) {
+   let (a, b) = (find(parents, a), find(parents, b));
+   parents[b] = a;
+}
+
+// Case and spacing differences don't make a different recording
+fn normalize(text: &str) -> String {
+   text
+      .split_whitespace()
+      .map(str::to_lowercase)
+      .collect::<Vec<_>>()
+      .join(" ")
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+
+   #[cfg(test)]
+   fn track(path: &str, audio_hash: u64, title: &str, seconds: u64, bitrate: u32) -> TrackInfo {
+      TrackInfo {
+         path: PathBuf::from(path),
+         size: u64::from(bitrate) * seconds * 125,
+         audio_hash,
+         artist: Some("Artist".to_owned()),
+         title: Some(title.to_owned()),
+         duration: Some(Duration::from_secs(seconds)),
+         bitrate: Some(bitrate),
+      }
+   }
+
+   #[test]
+   fn groups() {
+      let tracks = vec![
+         track("a.mp3", 1, "Song", 200, 128),
+         track("b.mp3", 1, "Song (tagged differently)", 200, 128),
+         track("c.mp3", 2, "  song ", 200, 320),
+         track("d.mp3", 3, "Other", 200, 320),
+      ];
+
+      let groups = find_duplicates(&tracks, &DupeOptions::default());
+      assert_eq!(groups.len(), 1);
+      let paths: Vec<&str> = groups[0].iter().map(|x| x.path.to_str().unwrap()).collect();
+      assert_eq!(paths, ["c.mp3", "a.mp3", "b.mp3"]);
+      assert_eq!(reclaimable(&groups[0]), 2 * 128 * 200 * 125);
+
+      let by_audio = find_duplicates(
+         &tracks,
+         &DupeOptions {
+            audio: true,
+            tags: false,
+         },
+      );
+      assert_eq!(by_audio.len(), 1);
+      assert_eq!(by_audio[0].len(), 2);
+   }
+}
@@ -0,0 +1,343 @@
+use crate::id3::v24::{Frame, FrameData, FrameParseError, LangDescriptionText};
+use crate::id3::values::Values;
+use crate::id3::Id3Tag;
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read};
+use std::path::PathBuf;
+
+/// What a column of an edits file holds, going by its header
+#[derive(Clone, Debug, PartialEq)]
+pub enum Column {
+   /// "path", "file", or "filename"
+   Path,
+   /// A text or URL frame, e.g. "TIT2" or "WOAR"
+   Frame([u8; 4]),
+   /// "TXXX:DESCRIPTION"
+   UserText(String),
+   /// "COMM", "COMM:description", "USLT", or "USLT:description"
+   LangText { name: [u8; 4], description: String },
+}
+
+impl Column {
+   pub fn parse(header: &str) -> Result<Column, EditsError> {
+      let header = header.trim();
+      let unknown = || EditsError::UnknownColumn(header.to_owned());
+      let mut parts = header.splitn(2, ':');
+      let id = parts.next().unwrap_or("");
+      let description = parts.next();
+      if description.is_none() && ["path", "file", "filename"].iter().any(|x| x.eq_ignore_ascii_case(id)) {
+         return Ok(Column::Path);
+      }
+      let name = match id.as_bytes() {
+         [a, b, c, d] => [*a, *b, *c, *d],
+         _ => return Err(unknown()),
+      };
+      Ok(match (&name, description) {
+         (b"TXXX", Some(description)) => Column::UserText(description.to_owned()),
+         (b"COMM", description) | (b"USLT", description) => Column::LangText {
+            name,
+            description: description.unwrap_or("").to_owned(),
+         },
+         (b"TXXX", None) | (b"WXXX", _) => return Err(unknown()),
+         ([b'T', ..], None) | ([b'W', ..], None)
+            if name.iter().all(|x| x.is_ascii_uppercase() || x.is_ascii_digit()) =>
+         {
+            Column::Frame(name)
+         }
+         _ => return Err(unknown()),
+      })
+   }
+
+   /// Whether the frame is one that this column sets
+   pub fn matches(&self, data: &FrameData) -> bool {
+      match (self, data) {
+         (Column::Frame(name), _) => data.name() == *name,
+         (Column::UserText(description), FrameData::TXXX(x)) => x.description.eq_ignore_ascii_case(description),
+         (Column::LangText { name, description }, FrameData::COMM(x))
+         | (Column::LangText { name, description }, FrameData::USLT(x)) => {
+            data.name() == *name && x.description == description.as_str()
+         }
+         _ => false,
+      }
+   }
+
+   /// The frame that sets this column to the value. Not for the path column.
+   pub fn frame(&self, value: &str) -> Result<FrameData<'static>, FrameParseError> {
+      Ok(match self {
+         Column::Path => unreachable!(),
+         Column::Frame(name) => FrameData::from_text(*name, value)?,
+         Column::UserText(description) => FrameData::user_text(description.clone(), value.to_owned()),
+         Column::LangText { name, description } => {
+            let text = LangDescriptionText {
+               // Replaced with the language of the frame being replaced, if there is one
+               iso_639_2_lang: *b"XXX",
+               description: Cow::Owned(description.clone()),
+               text: Values::one(Cow::Owned(value.to_owned())),
+            };
+            if name == b"COMM" {
+               FrameData::COMM(text)
+            } else {
+               FrameData::USLT(text)
+            }
+         }
+      })
+   }
+}
+
+#[derive(Clone, Debug)]
+pub struct Edit {
+   pub column: Column,
+   /// `None` removes the frame
+   pub frame: Option<FrameData<'static>>,
+}
+
+/// The edits for one row of an edits file
+#[derive(Clone, Debug)]
+pub struct FileEdits {
+   /// As written in the file; relative paths are up to the caller to resolve
+   pub path: PathBuf,
+   /// Where the row starts in the edits file, for error messages
+   pub line: usize,
+   pub edits: Vec<Edit>,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct EditOptions {
+   /// b',' for CSV, b'\t' for TSV
+   pub delimiter: u8,
+   /// Whether an empty cell removes the frame. Otherwise, empty cells leave the frame as it is.
+   pub clear_empty: bool,
+}
+
+impl Default for EditOptions {
+   fn default() -> EditOptions {
+      EditOptions {
+         delimiter: b',',
+         clear_empty: false,
+      }
+   }
+}
+
+#[derive(Debug)]
+pub enum EditsError {
+   /// A quoted field that never ends
+   UnterminatedQuote {
+      line: usize,
+   },
+   NoPathColumn,
+   UnknownColumn(String),
+   /// A row with more fields than there are columns
+   TooManyFields {
+      line: usize,
+   },
+   InvalidValue {
+      line: usize,
+      error: FrameParseError,
+   },
+   Io(io::Error),
+}
+
+impl fmt::Display for EditsError {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      match self {
+         EditsError::UnterminatedQuote { line } => write!(f, "line {}: quoted field never ends", line),
+         EditsError::NoPathColumn => write!(f, "no path column; name one \"path\""),
+         EditsError::UnknownColumn(x) => write!(f, "unknown column {:?}", x),
+         EditsError::TooManyFields { line } => write!(f, "line {}: more fields than columns", line),
+         EditsError::InvalidValue { line, error } => write!(f, "line {}: {}", line, error),
+         EditsError::Io(e) => write!(f, "I/O error: {}", e),
+      }
+   }
+}
+
+impl Error for EditsError {
+   fn source(&self) -> Option<&(dyn Error + 'static)> {
+      match self {
+         EditsError::InvalidValue { error, .. } => Some(error),
+         EditsError::Io(e) => Some(e),
+         _ => None,
+      }
+   }
+}
+
+impl From<io::Error> for EditsError {
+   fn from(e: io::Error) -> EditsError {
+      EditsError::Io(e)
+   }
+}
+
+/// Reads an edits file: a CSV (or TSV) file whose first row names the columns, one of which is the path. Every value
+/// is checked here, so that a mistake on the last row is caught before any file is changed.
+pub fn read_edits<R: Read>(mut source: R, options: &EditOptions) -> Result<Vec<FileEdits>, EditsError> {
+   let mut text = String::new();
+   source.read_to_string(&mut text)?;
+   // Spreadsheets like to start UTF-8 files with a byte order mark
+   let text = text.trim_start_matches('\u{feff}');
+   let mut records = parse_records(text, char::from(options.delimiter))?.into_iter();
+
+   let columns = match records.next() {
+      Some((_, header)) => header.iter().map(|x| Column::parse(x)).collect::<Result<Vec<_>, _>>()?,
+      None => return Ok(Vec::new()),
+   };
+   let path_index = columns
+      .iter()
+      .position(|x| *x == Column::Path)
+      .ok_or(EditsError::NoPathColumn)?;
+
+   let mut rows = Vec::new();
+   for (line, fields) in records {
+      if fields.len() > columns.len() {
+         return Err(EditsError::TooManyFields { line });
+      }
+      // Spreadsheets write blank rows as a row of empty fields
+      if fields.iter().all(|x| x.is_empty()) {
+         continue;
+      }
+      let mut edits = Vec::new();
+      for (column, value) in columns.iter().zip(fields.iter()) {
+         let frame = match (column, value.is_empty()) {
+            (Column::Path, _) => continue,
+            (_, true) if !options.clear_empty => continue,
+            (_, true) => None,
+            (_, false) => Some(
+               column
+                  .frame(value)
+                  .map_err(|error| EditsError::InvalidValue { line, error })?,
+            ),
+         };
+         edits.push(Edit {
+            column: column.clone(),
+            frame,
+         });
+      }
+      rows.push(FileEdits {
+         path: PathBuf::from(fields.get(path_index).cloned().unwrap_or_default()),
+         line,
+         edits,
+      });
+   }
+   Ok(rows)
+}
+
+/// Makes the edits to the tag. A frame that's replaced keeps its place among the other frames.
+pub fn apply(tag: &mut Id3Tag, edits: &[Edit]) {
+   for edit in edits.iter() {
+      let position = tag.frames.iter().position(|x| edit.column.matches(&x.data));
+      let language = position.and_then(|i| match &tag.frames[i].data {
+         FrameData::COMM(x) | FrameData::USLT(x) => Some(x.iso_639_2_lang),
+         _ => None,
+      });
+      tag.frames.retain(|x| !edit.column.matches(&x.data));
+
+      let mut data = match &edit.frame {
+         Some(v) => v.clone(),
+         None => continue,
+      };
+      match (&mut data, language) {
+         (FrameData::COMM(x), Some(language)) | (FrameData::USLT(x), Some(language)) => x.iso_639_2_lang = language,
+         _ => (),
+      }
+      let position = position.unwrap_or_else(|| tag.frames.len()).min(tag.frames.len());
+      tag.frames.insert(position, Frame::new(data));
+   }
+}
+
+// RFC 4180, with the line each record starts on. Quoted fields can hold delimiters, newlines, and doubled quotes.
+fn parse_records(text: &str, delimiter: char) -> Result<Vec<(usize, Vec<String>)>, EditsError> {
+   let mut records = Vec::new();
+   let mut record = Vec::new();
+   let mut field = String::new();
+   let mut line = 1;
+   let mut record_line = 1;
+   let mut in_quotes = false;
+   let mut chars = text.chars().peekable();
+   while let Some(c) = chars.next() {
+      match c {
+         '"' if in_quotes && chars.peek() == Some(&'"') => {
+            chars.next();
+            field.push('"');
+         }
+         '"' if in_quotes => in_quotes = false,
+         '"' if field.is_empty() => in_quotes = true,
+         '\n' if in_quotes => {
+            line += 1;
+            field.push(c);
+         }
+         _ if in_quotes => field.push(c),
+         _ if c == delimiter => record.push(std::mem::replace(&mut field, String::new())),
+         '\r' if chars.peek() == Some(&'\n') => (),
+         '\n' => {
+            record.push(std::mem::replace(&mut field, String::new()));
+            records.push((record_line, std::mem::replace(&mut record, Vec::new())));
+            line += 1;
+            record_line = line;
+         }
+         _ => field.push(c),
+      }
+   }
+   if in_quotes {
+      return Err(EditsError::UnterminatedQuote { line: record_line });
+   }
+   if !field.is_empty() || !record.is_empty() {
+      record.push(field);
+      records.push((record_line, record));
+   }
+   Ok(records)
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+   #[cfg(test)]
+   use crate::id3::TagBuilder;
+   #[cfg(test)]
+   use crate::tag::Tag;
+
+   #[test]
+   fn apply_rows() {
+      let csv = "\u{feff}path,TIT2,TRCK,TXXX:MOOD,COMM\r\n\
+                 a.mp3,\"Title, with \"\"quotes\"\"\",3/12,,\"Two\nlines\"\r\n\
+                 ,,,,\n";
+      let rows = read_edits(csv.as_bytes(), &EditOptions::default()).unwrap();
+      assert_eq!(rows.len(), 1);
+      assert_eq!(rows[0].path, PathBuf::from("a.mp3"));
+      assert_eq!(rows[0].edits.len(), 3);
+
+      let mut tag = TagBuilder::new()
+         .comment("eng", "Old")
+         .title("Old")
+         .user_text("mood", "Calm")
+         .build()
+         .unwrap();
+      apply(&mut tag, &rows[0].edits);
+      assert_eq!(tag.title(), Some("Title, with \"quotes\""));
+      assert_eq!(tag.track_number(), Some(3));
+      assert_eq!(tag.track_total(), Some(12));
+      // The empty MOOD cell leaves it alone
+      assert_eq!(tag.frames.len(), 4);
+      match &tag.frames[0].data {
+         FrameData::COMM(x) => {
+            assert_eq!(x.iso_639_2_lang, *b"eng");
+            assert_eq!(&x.text[..], ["Two\nlines"]);
+         }
+         _ => panic!("expected the comment to stay first"),
+      }
+
+      let options = EditOptions {
+         delimiter: b'\t',
+         clear_empty: true,
+      };
+      let rows = read_edits("file\tTXXX:MOOD\nb.mp3\t\n".as_bytes(), &options).unwrap();
+      apply(&mut tag, &rows[0].edits);
+      assert_eq!(tag.frames.len(), 3);
+
+      match read_edits("path,TRCK\na.mp3,x\n".as_bytes(), &EditOptions::default()) {
+         Err(EditsError::InvalidValue { line: 2, .. }) => (),
+         x => panic!("expected an invalid value on line 2, got {:?}", x),
+      }
+      assert!(Column::parse("TIT").is_err());
+      assert!(Column::parse("APIC").is_err());
+   }
+}
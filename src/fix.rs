@@ -0,0 +1,385 @@
+use crate::copy::{self, CopyFilter};
+use crate::id3::v24::{Date, FrameData, Txxx, Unknown};
+use crate::id3::{Id3Tag, TagBuilder};
+use crate::musicbrainz::{self, Identifier};
+#[cfg(feature = "musicbrainz-lookup")]
+use crate::tag::Tag;
+#[cfg(feature = "musicbrainz-lookup")]
+use serde_json::Value;
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt;
+#[cfg(feature = "musicbrainz-lookup")]
+use std::time::{Duration, Instant};
+
+/// The root of the MusicBrainz web service
+pub const WEB_SERVICE_URL: &str = "https://musicbrainz.org/ws/2";
+
+// MusicBrainz allows one request a second
+#[cfg(feature = "musicbrainz-lookup")]
+const REQUEST_INTERVAL: Duration = Duration::from_millis(1000);
+
+/// A MusicBrainz recording that a file might be, and the release it's on
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Suggestion {
+   /// How sure MusicBrainz is of the match, from 0 to 100. Recordings looked up by ID are 100.
+   pub score: u8,
+   pub recording_id: String,
+   pub title: Option<String>,
+   pub artist: Option<String>,
+   pub artist_ids: Vec<String>,
+   pub release: Option<SuggestedRelease>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SuggestedRelease {
+   pub id: String,
+   pub release_group_id: Option<String>,
+   pub title: Option<String>,
+   pub artist: Option<String>,
+   pub artist_ids: Vec<String>,
+   pub year: Option<u16>,
+   pub track_number: Option<u32>,
+   pub track_total: Option<u32>,
+   pub disc_number: Option<u32>,
+   pub disc_total: Option<u32>,
+}
+
+impl Suggestion {
+   /// The suggestion as ID3v2.4 frames, including the MusicBrainz identifiers that Picard would write
+   pub fn to_id3(&self) -> Id3Tag {
+      let mut builder = TagBuilder::new();
+      if let Some(title) = &self.title {
+         builder = builder.title(title.as_str());
+      }
+      if let Some(artist) = &self.artist {
+         builder = builder.artist(artist.as_str());
+      }
+      let mut ufid = musicbrainz::UFID_OWNER.as_bytes().to_vec();
+      ufid.push(0);
+      ufid.extend_from_slice(self.recording_id.as_bytes());
+      builder = builder.frame(FrameData::Unknown(Unknown {
+         name: *b"UFID",
+         data: Cow::Owned(ufid),
+      }));
+      builder = builder.frame(ids_frame(Identifier::Artist, &self.artist_ids));
+
+      if let Some(release) = &self.release {
+         if let Some(title) = &release.title {
+            builder = builder.album(title.as_str());
+         }
+         if let Some(artist) = &release.artist {
+            builder = builder.album_artist(artist.as_str());
+         }
+         if let Some(year) = release.year {
+            builder = builder.recording_date(Date {
+               year,
+               month: None,
+               day: None,
+               hour: None,
+               minutes: None,
+               seconds: None,
+            });
+         }
+         // Totals that MusicBrainz got wrong are left out rather than failing the whole tag
+         if let Some(number) = release.track_number {
+            let total = release.track_total.filter(|x| *x >= number);
+            builder = builder.track(u64::from(number), total.map(u64::from));
+         }
+         if let Some(number) = release.disc_number {
+            let total = release.disc_total.filter(|x| *x >= number);
+            builder = builder.disc(u64::from(number), total.map(u64::from));
+         }
+         builder = builder.frame(ids_frame(Identifier::Release, &[release.id.clone()]));
+         builder = builder.frame(ids_frame(Identifier::AlbumArtist, &release.artist_ids));
+         if let Some(id) = &release.release_group_id {
+            builder = builder.frame(ids_frame(Identifier::ReleaseGroup, &[id.clone()]));
+         }
+      }
+
+      let mut tag = builder.build().unwrap_or_default();
+      // Identifiers we don't know are left out
+      tag.frames.retain(|x| match &x.data {
+         FrameData::TXXX(txxx) => !txxx.text.is_empty(),
+         _ => true,
+      });
+      tag
+   }
+}
+
+fn ids_frame(id: Identifier, values: &[String]) -> FrameData<'static> {
+   FrameData::TXXX(Txxx {
+      description: Cow::Borrowed(id.txxx_description().expect("only the recording has no TXXX frame")),
+      text: values.iter().map(|x| Cow::Owned(x.clone())).collect(),
+   })
+}
+
+/// The tag with the suggestion applied: frames that the suggestion has replace those of the tag, and the rest of the
+/// tag is kept
+pub fn propose(current: &Id3Tag, suggestion: &Suggestion) -> Id3Tag {
+   let suggested = suggestion.to_id3();
+   let mut proposed = current.clone();
+   // UFID frames are told apart by owner, which `copy::merge` doesn't know about
+   proposed.frames.retain(|x| match &x.data {
+      FrameData::Unknown(x) if &x.name == b"UFID" => !x.data.starts_with(musicbrainz::UFID_OWNER.as_bytes()),
+      _ => true,
+   });
+   let filter = CopyFilter {
+      frames: None,
+      preserve: true,
+   };
+   copy::merge(&suggested, &mut proposed, &filter);
+   proposed
+}
+
+/// The MusicBrainz recording ID of the tag, if it has one
+pub fn recording_id(tag: &Id3Tag) -> Option<&str> {
+   musicbrainz::ufid(tag, musicbrainz::UFID_OWNER)
+}
+
+#[derive(Debug)]
+pub enum LookupError {
+   /// A search needs at least a title
+   NotEnoughToSearch,
+   Http(String),
+   /// MusicBrainz refused the request. Holds its message.
+   Api(String),
+   InvalidResponse,
+}
+
+impl fmt::Display for LookupError {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      match self {
+         LookupError::NotEnoughToSearch => write!(f, "the file has no title to search for"),
+         LookupError::Http(message) => write!(f, "lookup failed: {}", message),
+         LookupError::Api(message) => write!(f, "MusicBrainz refused the lookup: {}", message),
+         LookupError::InvalidResponse => write!(f, "MusicBrainz sent a response that couldn't be understood"),
+      }
+   }
+}
+
+impl Error for LookupError {}
+
+/// Searches and looks up recordings with the MusicBrainz web service
+#[cfg(feature = "musicbrainz-lookup")]
+pub struct Client {
+   user_agent: String,
+   last_request: Option<Instant>,
+}
+
+#[cfg(feature = "musicbrainz-lookup")]
+impl Client {
+   /// MusicBrainz asks that applications identify themselves with a contact, e.g. "walnut/0.1 (you@example.com)"
+   pub fn new(user_agent: String) -> Client {
+      Client {
+         user_agent,
+         last_request: None,
+      }
+   }
+
+   /// Recordings that match the title, artist, and album of the tag, best first
+   pub fn search(&mut self, tag: &dyn Tag) -> Result<Vec<Suggestion>, LookupError> {
+      let title = tag.title().ok_or(LookupError::NotEnoughToSearch)?;
+      let mut query = format!("recording:{}", phrase(title));
+      if let Some(artist) = tag.artist() {
+         query.push_str(&format!(" AND artist:{}", phrase(artist)));
+      }
+      if let Some(album) = tag.album() {
+         query.push_str(&format!(" AND release:{}", phrase(album)));
+      }
+      let response = self.get("recording", &[("query", &query), ("limit", "5")])?;
+      let recordings = response["recordings"].as_array().ok_or(LookupError::InvalidResponse)?;
+      let mut suggestions: Vec<Suggestion> = recordings
+         .iter()
+         .filter_map(|x| parse_recording(x, tag.album()))
+         .collect();
+      suggestions.sort_by(|a, b| b.score.cmp(&a.score));
+      Ok(suggestions)
+   }
+
+   /// The recording with the given MusicBrainz ID. `album` picks which of its releases to suggest, if it's on more
+   /// than one.
+   pub fn recording(&mut self, id: &str, album: Option<&str>) -> Result<Suggestion, LookupError> {
+      let response = self.get(
+         &format!("recording/{}", id),
+         &[("inc", "artist-credits releases release-groups media")],
+      )?;
+      parse_recording(&response, album).ok_or(LookupError::InvalidResponse)
+   }
+
+   fn get(&mut self, path: &str, query: &[(&str, &str)]) -> Result<Value, LookupError> {
+      if let Some(last) = self.last_request {
+         let elapsed = last.elapsed();
+         if elapsed < REQUEST_INTERVAL {
+            std::thread::sleep(REQUEST_INTERVAL - elapsed);
+         }
+      }
+      self.last_request = Some(Instant::now());
+
+      let mut request = ureq::get(&format!("{}/{}", WEB_SERVICE_URL, path));
+      request
+         .set("User-Agent", &self.user_agent)
+         .query("fmt", "json")
+         .timeout(Duration::from_secs(30));
+      for (name, value) in query {
+         request.query(name, value);
+      }
+      let response = request.call();
+      if let Some(e) = response.synthetic_error() {
+         return Err(LookupError::Http(e.to_string()));
+      }
+      let ok = response.ok();
+      let body = response.into_string().map_err(|e| LookupError::Http(e.to_string()))?;
+      let value: Value = serde_json::from_str(&body).map_err(|_| LookupError::InvalidResponse)?;
+      if !ok {
+         let message = value["error"].as_str().unwrap_or("unknown error");
+         return Err(LookupError::Api(message.to_owned()));
+      }
+      Ok(value)
+   }
+}
+
+// A quoted Lucene phrase
+#[cfg(feature = "musicbrainz-lookup")]
+fn phrase(text: &str) -> String {
+   format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// A recording from a search or a lookup, on the release whose title is `album` if there is one
+#[cfg(feature = "musicbrainz-lookup")]
+fn parse_recording(recording: &Value, album: Option<&str>) -> Option<Suggestion> {
+   let releases = recording["releases"].as_array().map(Vec::as_slice).unwrap_or(&[]);
+   let release = album
+      .and_then(|album| {
+         releases
+            .iter()
+            .find(|x| x["title"].as_str().map_or(false, |x| x.eq_ignore_ascii_case(album)))
+      })
+      .or_else(|| releases.first());
+
+   Some(Suggestion {
+      // Lookups have no score, and older versions of the search sent it as a string
+      score: match &recording["score"] {
+         Value::Number(x) => x.as_u64().unwrap_or(0).min(100) as u8,
+         Value::String(x) => x.parse().unwrap_or(0),
+         _ => 100,
+      },
+      recording_id: recording["id"].as_str()?.to_owned(),
+      title: text(&recording["title"]),
+      artist: credit(&recording["artist-credit"]),
+      artist_ids: credit_ids(&recording["artist-credit"]),
+      release: release.and_then(parse_release),
+   })
+}
+
+#[cfg(feature = "musicbrainz-lookup")]
+fn parse_release(release: &Value) -> Option<SuggestedRelease> {
+   // Only the medium with the recording on it is sent. Searches call its tracks "track", lookups "tracks".
+   let medium = &release["media"][0];
+   let track = if medium["tracks"].is_array() {
+      &medium["tracks"][0]
+   } else {
+      &medium["track"][0]
+   };
+   let number = |x: &Value| match x {
+      Value::Number(x) => x.as_u64().map(|x| x as u32),
+      Value::String(x) => x.parse().ok(),
+      _ => None,
+   };
+   Some(SuggestedRelease {
+      id: release["id"].as_str()?.to_owned(),
+      release_group_id: text(&release["release-group"]["id"]),
+      title: text(&release["title"]),
+      artist: credit(&release["artist-credit"]),
+      artist_ids: credit_ids(&release["artist-credit"]),
+      // Dates are "2002", "2002-02", or "2002-02-18"
+      year: release["date"].as_str().and_then(crate::tag::parse_year),
+      track_number: number(&track["position"]).or_else(|| number(&track["number"])),
+      track_total: number(&medium["track-count"]),
+      disc_number: number(&medium["position"]),
+      disc_total: number(&release["medium-count"]),
+   })
+}
+
+#[cfg(feature = "musicbrainz-lookup")]
+fn text(value: &Value) -> Option<String> {
+   value.as_str().filter(|x| !x.is_empty()).map(str::to_owned)
+}
+
+// An artist credit, e.g. [{"name": "A", "joinphrase": " & ", "artist": {"id": ...}}, {"name": "B", ...}]
+#[cfg(feature = "musicbrainz-lookup")]
+fn credit(value: &Value) -> Option<String> {
+   let mut credit = String::new();
+   for artist in value.as_array()? {
+      credit.push_str(artist["name"].as_str()?);
+      credit.push_str(artist["joinphrase"].as_str().unwrap_or(""));
+   }
+   Some(credit)
+}
+
+#[cfg(feature = "musicbrainz-lookup")]
+fn credit_ids(value: &Value) -> Vec<String> {
+   let artists = value.as_array().map(Vec::as_slice).unwrap_or(&[]);
+   artists.iter().filter_map(|x| text(&x["artist"]["id"])).collect()
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+
+   #[test]
+   fn propose_frames() {
+      let current = TagBuilder::new()
+         .title("ready lets go")
+         .comment("eng", "ripped in 2004")
+         .frame(FrameData::Unknown(Unknown {
+            name: *b"UFID",
+            data: Cow::Borrowed(b"http://musicbrainz.org\x00old"),
+         }))
+         .build()
+         .unwrap();
+      let suggestion = Suggestion {
+         score: 100,
+         recording_id: "cd2e7c47".to_owned(),
+         title: Some("Ready Lets Go".to_owned()),
+         artist: Some("Boards of Canada".to_owned()),
+         artist_ids: vec!["69158f97".to_owned()],
+         release: Some(SuggestedRelease {
+            id: "f5093c06".to_owned(),
+            title: Some("Geogaddi".to_owned()),
+            track_number: Some(1),
+            track_total: Some(23),
+            ..SuggestedRelease::default()
+         }),
+      };
+
+      let proposed = propose(&current, &suggestion);
+      assert_eq!(recording_id(&proposed), Some("cd2e7c47"));
+      let metadata = crate::probe::Metadata::Mpeg(proposed.clone());
+      assert_eq!(musicbrainz::get(&metadata, Identifier::Release), Some("f5093c06"));
+      assert_eq!(musicbrainz::get(&metadata, Identifier::AlbumArtist), None);
+      let changed = crate::diff::diff(&current, &proposed);
+      // The title and UFID change, and the artist, album, track, and two identifiers are added
+      assert_eq!(changed.len(), 7);
+      assert!(proposed.frames.iter().any(|x| x.data.name() == *b"COMM"));
+
+      #[cfg(feature = "musicbrainz-lookup")]
+      {
+         let search: Value = serde_json::from_str(
+            r#"{"id": "cd2e7c47", "score": 97, "title": "Ready Lets Go",
+               "artist-credit": [{"name": "Boards of Canada", "artist": {"id": "69158f97"}}],
+               "releases": [{"id": "0b6b12a4", "title": "Other"}, {"id": "f5093c06", "title": "Geogaddi",
+                  "date": "2002-02-18", "release-group": {"id": "0e8ff4b8"},
+                  "media": [{"position": 1, "track-count": 23, "track": [{"number": "1"}]}]}]}"#,
+         )
+         .unwrap();
+         let found = parse_recording(&search, Some("geogaddi")).unwrap();
+         let release = found.release.unwrap();
+         assert_eq!(
+            (found.score, release.id.as_str(), release.year),
+            (97, "f5093c06", Some(2002))
+         );
+         assert_eq!((release.track_number, release.track_total), (Some(1), Some(23)));
+      }
+   }
+}
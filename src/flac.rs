@@ -0,0 +1,333 @@
+use crate::id3;
+use crate::tag::Tag;
+use crate::vorbis::VorbisComment;
+use byteorder::{BigEndian, ByteOrder};
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::time::Duration;
+
+const STREAMINFO: u8 = 0;
+const VORBIS_COMMENT: u8 = 4;
+const PICTURE: u8 = 6;
+
+#[derive(Clone, Debug)]
+pub struct StreamInfo {
+   /// In samples
+   pub min_block_size: u16,
+   /// In samples
+   pub max_block_size: u16,
+   /// In bytes, 0 if unknown
+   pub min_frame_size: u32,
+   /// In bytes, 0 if unknown
+   pub max_frame_size: u32,
+   /// In Hz
+   pub sample_rate: u32,
+   pub channels: u8,
+   pub bits_per_sample: u8,
+   /// Per channel, 0 if unknown
+   pub total_samples: u64,
+   /// Of the unencoded audio
+   pub md5: [u8; 16],
+}
+
+impl StreamInfo {
+   fn parse(block: &[u8]) -> Option<StreamInfo> {
+      let block = block.get(0..34)?;
+      // 20 bits of sample rate, 3 of channels, 5 of bits per sample, and 36 of total samples
+      let packed = BigEndian::read_u64(&block[10..18]);
+      let mut md5 = [0u8; 16];
+      md5.copy_from_slice(&block[18..34]);
+
+      Some(StreamInfo {
+         min_block_size: BigEndian::read_u16(&block[0..2]),
+         max_block_size: BigEndian::read_u16(&block[2..4]),
+         min_frame_size: BigEndian::read_u24(&block[4..7]),
+         max_frame_size: BigEndian::read_u24(&block[7..10]),
+         sample_rate: (packed >> 44) as u32,
+         channels: ((packed >> 41) & 0b111) as u8 + 1,
+         bits_per_sample: ((packed >> 36) & 0b1_1111) as u8 + 1,
+         total_samples: packed & 0x0f_ff_ff_ff_ff,
+         md5,
+      })
+   }
+
+   pub fn duration(&self) -> Duration {
+      if self.sample_rate == 0 {
+         return Duration::from_secs(0);
+      }
+      let sample_rate = u64::from(self.sample_rate);
+      Duration::from_secs(self.total_samples / sample_rate)
+         + Duration::from_nanos(self.total_samples % sample_rate * 1_000_000_000 / sample_rate)
+   }
+}
+
+/// An embedded picture, as found in FLAC PICTURE blocks and Vorbis METADATA_BLOCK_PICTURE comments
+#[derive(Clone, Debug)]
+pub struct Picture {
+   /// Same meaning as the ID3 APIC picture type, e.g. 3 is the front cover
+   pub picture_type: u32,
+   pub mime_type: String,
+   pub description: String,
+   pub width: u32,
+   pub height: u32,
+   /// Bits per pixel
+   pub depth: u32,
+   /// For indexed-color pictures, otherwise 0
+   pub colors: u32,
+   pub data: Vec<u8>,
+}
+
+impl Picture {
+   /// Returns `None` if the picture block is truncated
+   pub fn parse(block: &[u8]) -> Option<Picture> {
+      let mut rest = block;
+      let picture_type = read_u32(&mut rest)?;
+      let mime_type = String::from_utf8_lossy(read_bytes(&mut rest)?).into_owned();
+      let description = String::from_utf8_lossy(read_bytes(&mut rest)?).into_owned();
+      let width = read_u32(&mut rest)?;
+      let height = read_u32(&mut rest)?;
+      let depth = read_u32(&mut rest)?;
+      let colors = read_u32(&mut rest)?;
+      let data = read_bytes(&mut rest)?.to_vec();
+
+      Some(Picture {
+         picture_type,
+         mime_type,
+         description,
+         width,
+         height,
+         depth,
+         colors,
+         data,
+      })
+   }
+}
+
+fn read_u32(rest: &mut &[u8]) -> Option<u32> {
+   let value = BigEndian::read_u32(rest.get(0..4)?);
+   *rest = &rest[4..];
+   Some(value)
+}
+
+// Prefixed by their length as a 32 bit big endian integer
+fn read_bytes<'a>(rest: &mut &'a [u8]) -> Option<&'a [u8]> {
+   let len = read_u32(rest)? as usize;
+   let bytes = rest.get(0..len)?;
+   *rest = &rest[len..];
+   Some(bytes)
+}
+
+#[derive(Clone, Debug)]
+pub struct Metadata {
+   pub stream_info: StreamInfo,
+   pub comments: Option<VorbisComment>,
+   pub pictures: Vec<Picture>,
+}
+
+#[derive(Debug)]
+pub enum FlacParseError {
+   NotFlac,
+   InvalidStreamInfo,
+   InvalidComment,
+   InvalidPicture,
+   Io(io::Error),
+}
+
+impl fmt::Display for FlacParseError {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      match self {
+         FlacParseError::NotFlac => write!(f, "not a FLAC stream"),
+         FlacParseError::InvalidStreamInfo => write!(f, "missing or truncated STREAMINFO block"),
+         FlacParseError::InvalidComment => write!(f, "truncated VORBIS_COMMENT block"),
+         FlacParseError::InvalidPicture => write!(f, "truncated PICTURE block"),
+         FlacParseError::Io(e) => write!(f, "I/O error: {}", e),
+      }
+   }
+}
+
+impl Error for FlacParseError {
+   fn source(&self) -> Option<&(dyn Error + 'static)> {
+      match self {
+         FlacParseError::Io(e) => Some(e),
+         _ => None,
+      }
+   }
+}
+
+impl From<io::Error> for FlacParseError {
+   fn from(e: io::Error) -> FlacParseError {
+      FlacParseError::Io(e)
+   }
+}
+
+/// Reads the metadata blocks at the start of a FLAC file. Blocks we don't decode are skipped by seeking.
+pub fn read_metadata<S: Read + Seek>(source: &mut S) -> Result<Metadata, FlacParseError> {
+   // Some taggers put an ID3v2 tag in front of the stream
+   let mut header = Vec::new();
+   source.seek(SeekFrom::Start(0))?;
+   source.take(10).read_to_end(&mut header)?;
+   let stream_start = id3::tag_len(&header).unwrap_or(0);
+
+   let mut marker = [0u8; 4];
+   source.seek(SeekFrom::Start(stream_start))?;
+   source.read_exact(&mut marker)?;
+   if &marker != b"fLaC" {
+      return Err(FlacParseError::NotFlac);
+   }
+
+   let mut stream_info = None;
+   let mut comments = None;
+   let mut pictures = Vec::new();
+   loop {
+      let mut block_header = [0u8; 4];
+      source.read_exact(&mut block_header)?;
+      let is_last = block_header[0] & 0x80 != 0;
+      let block_type = block_header[0] & 0x7f;
+      let len = BigEndian::read_u24(&block_header[1..4]);
+
+      match block_type {
+         STREAMINFO | VORBIS_COMMENT | PICTURE => {
+            let block = read_block(source, len)?;
+            match block_type {
+               STREAMINFO => stream_info = StreamInfo::parse(&block),
+               VORBIS_COMMENT => comments = Some(VorbisComment::parse(&block).ok_or(FlacParseError::InvalidComment)?),
+               _ => pictures.push(Picture::parse(&block).ok_or(FlacParseError::InvalidPicture)?),
+            }
+         }
+         _ => {
+            source.seek(SeekFrom::Current(i64::from(len)))?;
+         }
+      }
+
+      if is_last {
+         break;
+      }
+   }
+
+   Ok(Metadata {
+      stream_info: stream_info.ok_or(FlacParseError::InvalidStreamInfo)?,
+      comments,
+      pictures,
+   })
+}
+
+/// Skips the "fLaC" marker at the source's position and the metadata blocks after it, returning where the first
+/// audio frame starts
+pub fn skip_metadata<S: Read + Seek>(source: &mut S) -> io::Result<u64> {
+   source.seek(SeekFrom::Current(4))?;
+   loop {
+      let mut block_header = [0u8; 4];
+      source.read_exact(&mut block_header)?;
+      let len = BigEndian::read_u24(&block_header[1..4]);
+      let end = source.seek(SeekFrom::Current(i64::from(len)))?;
+      if block_header[0] & 0x80 != 0 {
+         return Ok(end);
+      }
+   }
+}
+
+fn read_block<S: Read>(source: &mut S, len: u32) -> Result<Vec<u8>, FlacParseError> {
+   let mut block = Vec::new();
+   source.take(u64::from(len)).read_to_end(&mut block)?;
+   if block.len() < len as usize {
+      return Err(FlacParseError::Io(io::Error::from(io::ErrorKind::UnexpectedEof)));
+   }
+   Ok(block)
+}
+
+impl Tag for Metadata {
+   fn title(&self) -> Option<&str> {
+      self.comments.as_ref()?.title()
+   }
+
+   fn artist(&self) -> Option<&str> {
+      self.comments.as_ref()?.artist()
+   }
+
+   fn album(&self) -> Option<&str> {
+      self.comments.as_ref()?.album()
+   }
+
+   fn album_artist(&self) -> Option<&str> {
+      self.comments.as_ref()?.album_artist()
+   }
+
+   fn genre(&self) -> Option<&str> {
+      self.comments.as_ref()?.genre()
+   }
+
+   fn year(&self) -> Option<u16> {
+      self.comments.as_ref()?.year()
+   }
+
+   fn track_number(&self) -> Option<u32> {
+      self.comments.as_ref()?.track_number()
+   }
+
+   fn track_total(&self) -> Option<u32> {
+      self.comments.as_ref()?.track_total()
+   }
+
+   fn disc_number(&self) -> Option<u32> {
+      self.comments.as_ref()?.disc_number()
+   }
+
+   fn disc_total(&self) -> Option<u32> {
+      self.comments.as_ref()?.disc_total()
+   }
+
+   fn comment(&self) -> Option<&str> {
+      self.comments.as_ref()?.comment()
+   }
+
+   fn artist_values(&self) -> Vec<&str> {
+      self.comments.as_ref().map_or_else(Vec::new, |x| x.artist_values())
+   }
+
+   fn genre_values(&self) -> Vec<&str> {
+      self.comments.as_ref().map_or_else(Vec::new, |x| x.genre_values())
+   }
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+
+   #[test]
+   fn metadata_blocks() {
+      let mut file = Vec::from(&b"fLaC"[..]);
+      // STREAMINFO: 44.1kHz, stereo, 16 bits, 441000 samples
+      file.extend_from_slice(&[STREAMINFO, 0, 0, 34]);
+      file.extend_from_slice(&[0x10, 0x00, 0x10, 0x00, 0, 0, 0, 0, 0, 0]);
+      file.extend_from_slice(&[0x0a, 0xc4, 0x42, 0xf0, 0x00, 0x06, 0xba, 0xa8]);
+      file.extend_from_slice(&[0; 16]);
+      // PADDING
+      file.extend_from_slice(&[1, 0, 0, 2, 0, 0]);
+      file.extend_from_slice(&[0x80 | VORBIS_COMMENT, 0, 0, 41]);
+      file.extend_from_slice(b"\x03\x00\x00\x00abc\x02\x00\x00\x00");
+      file.extend_from_slice(b"\x07\x00\x00\x00TITLE=x\x0f\x00\x00\x00TRACKNUMBER=3/9");
+
+      let metadata = read_metadata(&mut io::Cursor::new(file)).unwrap();
+      assert_eq!(metadata.stream_info.sample_rate, 44100);
+      assert_eq!(metadata.stream_info.channels, 2);
+      assert_eq!(metadata.stream_info.bits_per_sample, 16);
+      assert_eq!(metadata.stream_info.duration().as_secs(), 10);
+      assert_eq!(metadata.title(), Some("x"));
+      assert_eq!((metadata.track_number(), metadata.track_total()), (Some(3), Some(9)));
+   }
+
+   #[test]
+   fn most_samples() {
+      let mut file = Vec::from(&b"fLaC"[..]);
+      // STREAMINFO: 44.1kHz, stereo, 16 bits, and the most samples 36 bits can count
+      file.extend_from_slice(&[0x80 | STREAMINFO, 0, 0, 34]);
+      file.extend_from_slice(&[0x10, 0x00, 0x10, 0x00, 0, 0, 0, 0, 0, 0]);
+      file.extend_from_slice(&[0x0a, 0xc4, 0x42, 0xff, 0xff, 0xff, 0xff, 0xff]);
+      file.extend_from_slice(&[0; 16]);
+
+      let metadata = read_metadata(&mut io::Cursor::new(file)).unwrap();
+      assert_eq!(metadata.stream_info.total_samples, 0xf_ffff_ffff);
+      assert_eq!(metadata.stream_info.duration(), Duration::new(1_558_264, 778_571_428));
+   }
+}
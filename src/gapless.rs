@@ -0,0 +1,168 @@
+use crate::id3::v24::{Frame, FrameData};
+use crate::mpeg::AudioProperties;
+
+/// The description of the comment (or TXXX frame) iTunes keeps its gapless values in
+pub const ITUNSMPB: &str = "iTunSMPB";
+
+// MP3 decoders put out this many samples before the first ones the encoder took in
+const MPEG_DECODER_DELAY: u32 = 529;
+
+/// Where gapless values were found
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Source {
+   /// The LAME header of the first MPEG frame
+   Lame,
+   /// An iTunSMPB comment or TXXX frame, as written by iTunes
+   ITunSmpb,
+}
+
+/// How much of the decoded audio is silence the encoder added, for players to drop. Counts are in samples per
+/// channel of the decoder's output, so for MPEG audio they include the delay of the decoder as well as the encoder's.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GaplessInfo {
+   /// Samples to drop from the start
+   pub delay: u32,
+   /// Samples to drop from the end
+   pub padding: u32,
+   /// The length of the audio that was encoded, which is what's left once both are dropped. Only known if the source
+   /// gives it, or for LAME, if the Xing header has a frame count.
+   pub samples: Option<u64>,
+   pub source: Source,
+}
+
+/// Looks for gapless values in the LAME header (if the audio properties are given), then in an iTunSMPB comment or
+/// TXXX frame. The LAME header comes first as it's written along with the audio, where a tag can be copied from
+/// another encode of the same track. A LAME header with no delay or padding is taken as not having them, as FFmpeg
+/// writes zeros when it doesn't know.
+pub fn gapless_info(frames: &[Frame], properties: Option<&AudioProperties>) -> Option<GaplessInfo> {
+   properties
+      .and_then(lame_gapless)
+      .or_else(|| itunsmpb_value(frames).and_then(parse_itunsmpb))
+}
+
+fn lame_gapless(properties: &AudioProperties) -> Option<GaplessInfo> {
+   let xing = properties.xing.as_ref()?;
+   let lame = xing.lame.as_ref()?;
+   if lame.encoder_delay == 0 && lame.padding == 0 {
+      return None;
+   }
+   let delay = u32::from(lame.encoder_delay);
+   let padding = u32::from(lame.padding);
+   let samples = xing
+      .frames
+      .and_then(|x| (u64::from(x) * u64::from(properties.samples_per_frame)).checked_sub(u64::from(delay + padding)));
+   Some(GaplessInfo {
+      delay: delay + MPEG_DECODER_DELAY,
+      padding: padding.saturating_sub(MPEG_DECODER_DELAY),
+      samples,
+      source: Source::Lame,
+   })
+}
+
+fn itunsmpb_value<'a>(frames: &'a [Frame]) -> Option<&'a str> {
+   frames.iter().find_map(|frame| match &frame.data {
+      FrameData::COMM(x) if x.description == ITUNSMPB => x.text.first().map(|x| x.as_ref()),
+      FrameData::TXXX(x) if x.description == ITUNSMPB => x.text.first().map(|x| x.as_ref()),
+      _ => None,
+   })
+}
+
+/// Parses the value of an iTunSMPB comment, e.g. " 00000000 00000840 000001C0 0000000000046E00 ...": hex fields of
+/// which the second is the delay, the third the padding, and the fourth the length of the audio. The rest don't
+/// matter for playback.
+pub fn parse_itunsmpb(text: &str) -> Option<GaplessInfo> {
+   let fields: Vec<&str> = text.split_whitespace().collect();
+   let delay = u32::from_str_radix(fields.get(1)?, 16).ok()?;
+   let padding = u32::from_str_radix(fields.get(2)?, 16).ok()?;
+   let samples = u64::from_str_radix(fields.get(3)?, 16).ok()?;
+   Some(GaplessInfo {
+      delay,
+      padding,
+      samples: Some(samples),
+      source: Source::ITunSmpb,
+   })
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+   #[cfg(test)]
+   use crate::mpeg::{BitrateMode, ChannelMode, LameHeader, Layer, Version, XingHeader};
+
+   #[test]
+   fn itunsmpb() {
+      let value = " 00000000 00000840 000001C0 0000000000046E00 00000000 00000000 00000000";
+      let tag = crate::id3::TagBuilder::new()
+         .frame(FrameData::comment("eng", ITUNSMPB, value).unwrap())
+         .build()
+         .unwrap();
+      let info = gapless_info(&tag.frames, None).unwrap();
+      assert_eq!(
+         info,
+         GaplessInfo {
+            delay: 2112,
+            padding: 448,
+            samples: Some(290_304),
+            source: Source::ITunSmpb,
+         }
+      );
+      assert_eq!(parse_itunsmpb("00000000 00000840"), None);
+   }
+
+   #[test]
+   fn lame() {
+      let properties = |encoder_delay: u16, padding: u16, frames: Option<u32>| AudioProperties {
+         version: Version::Mpeg1,
+         layer: Layer::Layer3,
+         bitrate: 128,
+         sample_rate: 44100,
+         channel_mode: ChannelMode::JointStereo,
+         bitrate_mode: BitrateMode::Constant,
+         first_frame_offset: 0,
+         audio_len: 0,
+         samples_per_frame: 1152,
+         xing: Some(XingHeader {
+            vbr: false,
+            frames,
+            bytes: None,
+            toc: None,
+            quality: None,
+            lame: Some(LameHeader {
+               encoder: "LAME3.100".to_owned(),
+               revision: 0,
+               vbr_method: 1,
+               lowpass: None,
+               peak: None,
+               track_gain: None,
+               album_gain: None,
+               encoder_delay,
+               padding,
+               music_length: 0,
+            }),
+         }),
+         vbri: None,
+      };
+
+      // The decoder's delay moves the encoder's padding earlier, by as much as there is
+      assert_eq!(
+         gapless_info(&[], Some(&properties(576, 1000, Some(100)))),
+         Some(GaplessInfo {
+            delay: 1105,
+            padding: 471,
+            samples: Some(113_624),
+            source: Source::Lame,
+         })
+      );
+      let info = gapless_info(&[], Some(&properties(576, 300, None))).unwrap();
+      assert_eq!((info.delay, info.padding, info.samples), (1105, 0, None));
+
+      // FFmpeg's zeros mean the values aren't known, so the tag is used instead
+      let tag = crate::id3::TagBuilder::new()
+         .frame(FrameData::comment("eng", ITUNSMPB, " 00000000 00000840 000001C0 0000000000046E00").unwrap())
+         .build()
+         .unwrap();
+      let info = gapless_info(&tag.frames, Some(&properties(0, 0, Some(100)))).unwrap();
+      assert_eq!((info.delay, info.source), (2112, Source::ITunSmpb));
+      assert_eq!(gapless_info(&[], Some(&properties(0, 0, Some(100)))), None);
+   }
+}
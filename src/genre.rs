@@ -0,0 +1,287 @@
+use std::borrow::Cow;
+
+/// The ID3v1 genres (0-79) followed by the Winamp extensions (80-191), which every tagger since has adopted.
+/// ID3v2 TCON frames and MP4 gnre atoms refer to genres by these indices.
+pub const GENRES: [&str; 192] = [
+   "Blues",
+   "Classic Rock",
+   "Country",
+   "Dance",
+   "Disco",
+   "Funk",
+   "Grunge",
+   "Hip-Hop",
+   "Jazz",
+   "Metal",
+   "New Age",
+   "Oldies",
+   "Other",
+   "Pop",
+   "R&B",
+   "Rap",
+   "Reggae",
+   "Rock",
+   "Techno",
+   "Industrial",
+   "Alternative",
+   "Ska",
+   "Death Metal",
+   "Pranks",
+   "Soundtrack",
+   "Euro-Techno",
+   "Ambient",
+   "Trip-Hop",
+   "Vocal",
+   "Jazz+Funk",
+   "Fusion",
+   "Trance",
+   "Classical",
+   "Instrumental",
+   "Acid",
+   "House",
+   "Game",
+   "Sound Clip",
+   "Gospel",
+   "Noise",
+   "AlternRock",
+   "Bass",
+   "Soul",
+   "Punk",
+   "Space",
+   "Meditative",
+   "Instrumental Pop",
+   "Instrumental Rock",
+   "Ethnic",
+   "Gothic",
+   "Darkwave",
+   "Techno-Industrial",
+   "Electronic",
+   "Pop-Folk",
+   "Eurodance",
+   "Dream",
+   "Southern Rock",
+   "Comedy",
+   "Cult",
+   "Gangsta",
+   "Top 40",
+   "Christian Rap",
+   "Pop/Funk",
+   "Jungle",
+   "Native American",
+   "Cabaret",
+   "New Wave",
+   "Psychedelic",
+   "Rave",
+   "Showtunes",
+   "Trailer",
+   "Lo-Fi",
+   "Tribal",
+   "Acid Punk",
+   "Acid Jazz",
+   "Polka",
+   "Retro",
+   "Musical",
+   "Rock & Roll",
+   "Hard Rock",
+   // Winamp extensions
+   "Folk",
+   "Folk-Rock",
+   "National Folk",
+   "Swing",
+   "Fast Fusion",
+   "Bebop",
+   "Latin",
+   "Revival",
+   "Celtic",
+   "Bluegrass",
+   "Avantgarde",
+   "Gothic Rock",
+   "Progressive Rock",
+   "Psychedelic Rock",
+   "Symphonic Rock",
+   "Slow Rock",
+   "Big Band",
+   "Chorus",
+   "Easy Listening",
+   "Acoustic",
+   "Humour",
+   "Speech",
+   "Chanson",
+   "Opera",
+   "Chamber Music",
+   "Sonata",
+   "Symphony",
+   "Booty Bass",
+   "Primus",
+   "Porn Groove",
+   "Satire",
+   "Slow Jam",
+   "Club",
+   "Tango",
+   "Samba",
+   "Folklore",
+   "Ballad",
+   "Power Ballad",
+   "Rhythmic Soul",
+   "Freestyle",
+   "Duet",
+   "Punk Rock",
+   "Drum Solo",
+   "A capella",
+   "Euro-House",
+   "Dance Hall",
+   "Goa",
+   "Drum & Bass",
+   "Club-House",
+   "Hardcore Techno",
+   "Terror",
+   "Indie",
+   "BritPop",
+   "Afro-Punk",
+   "Polsk Punk",
+   "Beat",
+   "Christian Gangsta Rap",
+   "Heavy Metal",
+   "Black Metal",
+   "Crossover",
+   "Contemporary Christian",
+   "Christian Rock",
+   "Merengue",
+   "Salsa",
+   "Thrash Metal",
+   "Anime",
+   "JPop",
+   "Synthpop",
+   "Abstract",
+   "Art Rock",
+   "Baroque",
+   "Bhangra",
+   "Big Beat",
+   "Breakbeat",
+   "Chillout",
+   "Downtempo",
+   "Dub",
+   "EBM",
+   "Eclectic",
+   "Electro",
+   "Electroclash",
+   "Emo",
+   "Experimental",
+   "Garage",
+   "Global",
+   "IDM",
+   "Illbient",
+   "Industro-Goth",
+   "Jam Band",
+   "Krautrock",
+   "Leftfield",
+   "Lounge",
+   "Math Rock",
+   "New Romantic",
+   "Nu-Breakz",
+   "Post-Punk",
+   "Post-Rock",
+   "Psytrance",
+   "Shoegaze",
+   "Space Rock",
+   "Trop Rock",
+   "World Music",
+   "Neoclassical",
+   "Audiobook",
+   "Audio Theatre",
+   "Neue Deutsche Welle",
+   "Podcast",
+   "Indie Rock",
+   "G-Funk",
+   "Dubstep",
+   "Garage Rock",
+   "Psybient",
+];
+
+pub fn name(index: u8) -> Option<&'static str> {
+   GENRES.get(index as usize).cloned()
+}
+
+/// The reverse of `name`, for writing ID3v1 tags. Case insensitive.
+pub fn index(name: &str) -> Option<u8> {
+   GENRES
+      .iter()
+      .position(|x| x.eq_ignore_ascii_case(name.trim()))
+      .map(|x| x as u8)
+}
+
+/// Resolves a numeric genre reference like "17", leaving anything else alone
+pub fn resolve(text: &str) -> Option<&'static str> {
+   if text.is_empty() || !text.bytes().all(|x| x.is_ascii_digit()) {
+      return None;
+   }
+   text.parse().ok().and_then(name)
+}
+
+/// Splits ID3v2.3 style genres like "(17)Rock" or "(RX)(4)" into the referenced genre names, followed by the
+/// refinement text if it adds anything. A leading "((" escapes a refinement that starts with a parenthesis.
+/// Bare ID3v2.4 references like "17" and "RX" are resolved as well.
+pub fn parse_references(text: &str) -> Vec<Cow<'_, str>> {
+   let mut genres = Vec::new();
+   let mut rest = text;
+   while rest.starts_with('(') && !rest.starts_with("((") {
+      let close = match rest.find(')') {
+         Some(v) => v,
+         None => break,
+      };
+      let reference = &rest[1..close];
+      match resolve_reference(reference) {
+         Some(name) => genres.push(Cow::Borrowed(name)),
+         // Keep numbers we don't know rather than losing them
+         None if !reference.is_empty() && reference.bytes().all(|x| x.is_ascii_digit()) => {
+            genres.push(Cow::Borrowed(reference))
+         }
+         None => break,
+      }
+      rest = &rest[close + 1..];
+   }
+
+   if rest.starts_with("((") {
+      rest = &rest[1..];
+   }
+   if genres.is_empty() {
+      genres.push(Cow::Borrowed(resolve_reference(rest).unwrap_or(rest)));
+   } else if !rest.is_empty() && !genres.iter().any(|x| x.eq_ignore_ascii_case(rest)) {
+      genres.push(Cow::Borrowed(rest));
+   }
+   genres
+}
+
+fn resolve_reference(text: &str) -> Option<&'static str> {
+   match text {
+      "RX" => Some("Remix"),
+      "CR" => Some("Cover"),
+      x => resolve(x),
+   }
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+
+   #[test]
+   fn lookup() {
+      assert_eq!(name(79), Some("Hard Rock"));
+      assert_eq!(name(191), Some("Psybient"));
+      assert_eq!(name(192), None);
+      assert_eq!(index("dubstep"), Some(189));
+      assert_eq!(resolve("17"), Some("Rock"));
+      assert_eq!(resolve("+17"), None);
+   }
+
+   #[test]
+   fn references() {
+      assert_eq!(parse_references("(17)Rock"), vec!["Rock"]);
+      assert_eq!(parse_references("(RX)(4)"), vec!["Remix", "Disco"]);
+      assert_eq!(parse_references("(4)Eurodisco"), vec!["Disco", "Eurodisco"]);
+      assert_eq!(parse_references("((Fake) Rock"), vec!["(Fake) Rock"]);
+      assert_eq!(parse_references("(255)"), vec!["255"]);
+      assert_eq!(parse_references("17"), vec!["Rock"]);
+      assert_eq!(parse_references("Shoegaze"), vec!["Shoegaze"]);
+   }
+}
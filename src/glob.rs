@@ -0,0 +1,232 @@
+use std::error::Error;
+use std::fmt;
+use std::path::{Component, Path};
+use std::str::FromStr;
+
+/// The file a directory's ignore patterns are read from. The patterns apply to everything under the directory.
+pub const IGNORE_FILE: &str = ".walnutignore";
+
+/// A shell-style pattern for paths, like `**/*.mp3` or `**/podcasts/**`.
+///
+/// `*` matches any run of characters and `?` any one character, but neither matches `/`. `[abc]`, `[a-z]`, and
+/// `[!abc]` match one character that is (or isn't) in the class. A component that is exactly `**` matches any number
+/// of components, none included, so `music/**` matches `music` itself as well as everything under it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Glob {
+   pattern: String,
+   components: Vec<PatternComponent>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum PatternComponent {
+   AnyComponents,
+   Segment(Vec<Token>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+   Char(char),
+   AnyChars,
+   AnyChar,
+   Class { negated: bool, ranges: Vec<(char, char)> },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum GlobError {
+   /// A `[` at this position is never closed
+   UnclosedClass { position: usize },
+}
+
+impl fmt::Display for GlobError {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      match self {
+         GlobError::UnclosedClass { position } => write!(f, "character class at {} never ends", position),
+      }
+   }
+}
+
+impl Error for GlobError {}
+
+impl FromStr for Glob {
+   type Err = GlobError;
+
+   fn from_str(s: &str) -> Result<Glob, GlobError> {
+      let mut components = Vec::new();
+      let mut position = 0;
+      for component in s.split('/') {
+         if component == "**" {
+            components.push(PatternComponent::AnyComponents);
+         } else if !component.is_empty() {
+            components.push(PatternComponent::Segment(segment(component, position)?));
+         }
+         position += component.len() + 1;
+      }
+      Ok(Glob {
+         pattern: s.to_owned(),
+         components,
+      })
+   }
+}
+
+fn segment(s: &str, offset: usize) -> Result<Vec<Token>, GlobError> {
+   let mut tokens = Vec::new();
+   let mut chars = s.char_indices().peekable();
+   while let Some((i, c)) = chars.next() {
+      tokens.push(match c {
+         '*' => Token::AnyChars,
+         '?' => Token::AnyChar,
+         '[' => {
+            let negated = chars.peek().map(|x| x.1) == Some('!');
+            if negated {
+               chars.next();
+            }
+            let mut ranges = Vec::new();
+            loop {
+               // A ']' straight after the '[' is part of the class
+               let start = match chars.next() {
+                  Some((_, ']')) if !ranges.is_empty() => break,
+                  Some((_, x)) => x,
+                  None => return Err(GlobError::UnclosedClass { position: offset + i }),
+               };
+               let end = match (chars.peek().map(|x| x.1), chars.clone().nth(1).map(|x| x.1)) {
+                  (Some('-'), Some(end)) if end != ']' => {
+                     chars.next();
+                     chars.next();
+                     end
+                  }
+                  _ => start,
+               };
+               ranges.push((start, end));
+            }
+            Token::Class { negated, ranges }
+         }
+         c => Token::Char(c),
+      });
+   }
+   Ok(tokens)
+}
+
+impl Glob {
+   /// Whether the pattern matches the path, whose components are compared one by one. Paths given to the filters
+   /// here are relative to the directory being searched.
+   pub fn matches<P: AsRef<Path>>(&self, path: P) -> bool {
+      let names: Vec<String> = path
+         .as_ref()
+         .components()
+         .filter_map(|x| match x {
+            Component::Normal(x) => Some(x.to_string_lossy().into_owned()),
+            _ => None,
+         })
+         .collect();
+      let names: Vec<&str> = names.iter().map(|x| &x[..]).collect();
+      matches_components(&self.components, &names)
+   }
+
+   pub fn as_str(&self) -> &str {
+      &self.pattern
+   }
+}
+
+fn matches_components(pattern: &[PatternComponent], names: &[&str]) -> bool {
+   match pattern.split_first() {
+      None => names.is_empty(),
+      Some((PatternComponent::AnyComponents, rest)) => (0..=names.len()).any(|i| matches_components(rest, &names[i..])),
+      Some((PatternComponent::Segment(tokens), rest)) => match names.split_first() {
+         Some((name, names)) => {
+            let chars: Vec<char> = name.chars().collect();
+            matches_segment(tokens, &chars) && matches_components(rest, names)
+         }
+         None => false,
+      },
+   }
+}
+
+fn matches_segment(tokens: &[Token], chars: &[char]) -> bool {
+   match tokens.split_first() {
+      None => chars.is_empty(),
+      Some((Token::AnyChars, rest)) => (0..=chars.len()).any(|i| matches_segment(rest, &chars[i..])),
+      Some((token, rest)) => match chars.split_first() {
+         Some((c, chars)) => {
+            let matched = match token {
+               Token::Char(x) => x == c,
+               Token::Class { negated, ranges } => ranges.iter().any(|(start, end)| start <= c && c <= end) != *negated,
+               _ => true,
+            };
+            matched && matches_segment(rest, chars)
+         }
+         None => false,
+      },
+   }
+}
+
+/// Which files a directory search keeps, from `--include` and `--exclude` patterns and the ignore files it finds
+#[derive(Clone, Debug, Default)]
+pub struct PathFilter {
+   /// If there are any, only files matching one of them are kept. Directories are searched either way.
+   pub include: Vec<Glob>,
+   /// Files and directories matching any of these are left out, directories with everything under them
+   pub exclude: Vec<Glob>,
+}
+
+impl PathFilter {
+   pub fn is_empty(&self) -> bool {
+      self.include.is_empty() && self.exclude.is_empty()
+   }
+
+   /// Whether to leave out a file or directory. `path` is relative to the directory being searched.
+   pub fn excludes<P: AsRef<Path>>(&self, path: P, is_dir: bool) -> bool {
+      let path = path.as_ref();
+      self.exclude.iter().any(|x| x.matches(path))
+         || (!is_dir && !self.include.is_empty() && !self.include.iter().any(|x| x.matches(path)))
+   }
+}
+
+/// Reads the patterns of an ignore file: one per line, skipping blank lines and lines starting with `#`. As in a
+/// gitignore file, a pattern without a `/` matches a name at any depth, and one with a `/` is relative to the
+/// directory holding the ignore file. A trailing `/` is dropped, so it matches files as well as directories.
+pub fn parse_ignore_file(text: &str) -> Result<Vec<Glob>, GlobError> {
+   text
+      .lines()
+      .map(str::trim)
+      .filter(|x| !x.is_empty() && !x.starts_with('#'))
+      .map(|x| {
+         let x = x.trim_end_matches('/');
+         if x.contains('/') {
+            x.trim_start_matches('/').parse()
+         } else {
+            format!("**/{}", x).parse()
+         }
+      })
+      .collect()
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+
+   #[test]
+   fn matching() {
+      let glob = |x: &str| x.parse::<Glob>().unwrap();
+      assert!(glob("**/*.mp3").matches("a/b/c.mp3"));
+      assert!(glob("**/*.mp3").matches("c.mp3"));
+      assert!(!glob("**/*.mp3").matches("a/c.flac"));
+      assert!(!glob("*.mp3").matches("a/c.mp3"));
+      assert!(glob("**/podcasts/**").matches("podcasts"));
+      assert!(glob("**/podcasts/**").matches("x/podcasts/y/z.mp3"));
+      assert!(!glob("**/podcasts/**").matches("x/podcasts.mp3"));
+      assert!(glob("disc [0-9]/track??.[!f]*").matches("disc 2/track01.mp3"));
+      assert!(!glob("disc [0-9]/track??.[!f]*").matches("disc 2/track01.flac"));
+      assert_eq!("a/[bc".parse::<Glob>(), Err(GlobError::UnclosedClass { position: 2 }));
+
+      let ignored = parse_ignore_file("# Not music\nPodcasts/\n\n/Live/*.mp3\n").unwrap();
+      let filter = PathFilter {
+         include: vec![glob("**/*.mp3")],
+         exclude: ignored,
+      };
+      assert!(filter.excludes("a/Podcasts", true));
+      assert!(filter.excludes("Live/x.mp3", false));
+      assert!(!filter.excludes("a/Live/x.mp3", false));
+      assert!(filter.excludes("a/cover.jpg", false));
+      assert!(!filter.excludes("a", true));
+   }
+}
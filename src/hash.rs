@@ -0,0 +1,162 @@
+use crate::strip::{self, StripOptions};
+use crate::{flac, mp4, ogg};
+use std::fs::File;
+use std::hash::Hasher;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// 64 bit FNV-1a. Unlike `DefaultHasher`, the result is the same with every build, so it can be stored.
+#[derive(Copy, Clone, Debug)]
+pub struct Fnv64(u64);
+
+impl Default for Fnv64 {
+   fn default() -> Fnv64 {
+      Fnv64(0xcb_f2_9c_e4_84_22_23_25)
+   }
+}
+
+impl Hasher for Fnv64 {
+   fn write(&mut self, bytes: &[u8]) {
+      for byte in bytes.iter() {
+         self.0 ^= u64::from(*byte);
+         self.0 = self.0.wrapping_mul(0x100_0000_01b3);
+      }
+   }
+
+   fn finish(&self) -> u64 {
+      self.0
+   }
+}
+
+/// Feeds the audio of the source to the hasher, leaving out the ID3v2, ID3v1, and APE tags around it (see
+/// `strip::find_tags`). Two rips of the same audio hash the same however differently they're tagged.
+///
+/// FLAC, MP4, and Ogg files keep their tags inside the stream, so only their audio frames, the mdat atom, and the
+/// audio packets are hashed. Any other format is hashed whole between those tags, so tags of its own, such as the
+/// LIST chunk of a WAV file, count as audio.
+pub fn hash_audio<S: Read + Seek, H: Hasher>(source: &mut S, hasher: &mut H) -> io::Result<()> {
+   let layout = strip::find_tags(source, &StripOptions { ape: true })?;
+   let mut magic = [0u8; 8];
+   source.seek(SeekFrom::Start(layout.audio_start))?;
+   // Files too short to hold one are hashed whole
+   source.take(8).read_exact(&mut magic).ok();
+   source.seek(SeekFrom::Start(layout.audio_start))?;
+
+   let mut audio_start = layout.audio_start;
+   let mut audio_end = layout.audio_end;
+   if magic.starts_with(b"fLaC") {
+      audio_start = flac::skip_metadata(source)?;
+   } else if &magic[4..8] == b"ftyp" {
+      if let Ok(Some((start, end))) = mp4::find_mdat(source) {
+         audio_start = start;
+         audio_end = end;
+      }
+   } else if magic.starts_with(b"OggS") {
+      let mut stream = source.take(layout.audio_end - layout.audio_start);
+      return ogg::read_audio(&mut stream, |x| hasher.write(x)).map_err(|e| match e {
+         ogg::OggParseError::Io(e) => e,
+         e => io::Error::new(io::ErrorKind::InvalidData, e.to_string()),
+      });
+   }
+
+   source.seek(SeekFrom::Start(audio_start))?;
+   let mut audio = source.take(audio_end.saturating_sub(audio_start));
+   let mut buf = vec![0; 64 * 1024];
+   loop {
+      let read = audio.read(&mut buf)?;
+      if read == 0 {
+         return Ok(());
+      }
+      hasher.write(&buf[..read]);
+   }
+}
+
+/// `hash_audio` of the file, with `Fnv64`
+pub fn audio_hash<P: AsRef<Path>>(path: P) -> io::Result<u64> {
+   let mut hasher = Fnv64::default();
+   hash_audio(&mut File::open(path)?, &mut hasher)?;
+   Ok(hasher.finish())
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+   #[cfg(test)]
+   use std::io::Cursor;
+
+   #[test]
+   fn tags_are_not_hashed() {
+      let audio = [0xffu8, 0xfb, 0x90, 0x64, 0x00, 0x01, 0x02];
+      let hash = |file: &[u8]| {
+         let mut hasher = Fnv64::default();
+         hash_audio(&mut Cursor::new(file), &mut hasher).unwrap();
+         hasher.finish()
+      };
+
+      let mut tagged = b"ID3\x04\x00\x00\x00\x00\x00\x10TIT2\x00\x00\x00\x06\x00\x00\x03Title".to_vec();
+      tagged.extend_from_slice(&audio);
+      let mut id3v1 = b"TAG".to_vec();
+      id3v1.resize(128, b'x');
+      tagged.extend_from_slice(&id3v1);
+
+      assert_eq!(hash(&tagged), hash(&audio));
+      assert_ne!(hash(&audio[1..]), hash(&audio));
+
+      let mut fnv = Fnv64::default();
+      fnv.write(b"a");
+      assert_eq!(fnv.finish(), 0xaf_63_dc_4c_86_01_ec_8c);
+   }
+
+   #[test]
+   fn stream_tags_are_not_hashed() {
+      let hash = |file: &[u8]| {
+         let mut hasher = Fnv64::default();
+         hash_audio(&mut Cursor::new(file), &mut hasher).unwrap();
+         hasher.finish()
+      };
+      let audio = b"\xff\xf8audio";
+
+      let flac = |comment: &[u8]| {
+         let mut file = b"fLaC\x00\x00\x00\x02si".to_vec();
+         file.extend_from_slice(&[0x84, 0, 0, comment.len() as u8]);
+         file.extend_from_slice(comment);
+         file.extend_from_slice(audio);
+         file
+      };
+      assert_eq!(hash(&flac(b"TITLE=a")), hash(&flac(b"TITLE=something else")));
+      assert_eq!(hash(&flac(b"")), hash(audio));
+
+      let mp4 = |ilst: &[u8]| {
+         let mut file = b"\x00\x00\x00\x08ftyp".to_vec();
+         file.extend_from_slice(&(8 + ilst.len() as u32).to_be_bytes());
+         file.extend_from_slice(b"moov");
+         file.extend_from_slice(ilst);
+         file.extend_from_slice(&(8 + audio.len() as u32).to_be_bytes());
+         file.extend_from_slice(b"mdat");
+         file.extend_from_slice(audio);
+         file
+      };
+      assert_eq!(hash(&mp4(b"a")), hash(&mp4(b"something else")));
+      assert_eq!(hash(&mp4(b"")), hash(audio));
+
+      let page = |lacing: &[u8], body: &[u8]| {
+         let mut page = b"OggS".to_vec();
+         page.resize(26, 0);
+         page.push(lacing.len() as u8);
+         page.extend_from_slice(lacing);
+         page.extend_from_slice(body);
+         page
+      };
+      let opus = |tags: &[u8]| {
+         let mut file = page(&[8], b"OpusHead");
+         // The tags end partway through a page, and the audio starts after them
+         let mut body = tags.to_vec();
+         body.extend_from_slice(audio);
+         file.extend(page(&[tags.len() as u8, audio.len() as u8], &body));
+         file.extend(page(&[audio.len() as u8], audio));
+         file
+      };
+      assert_eq!(hash(&opus(b"OpusTags a")), hash(&opus(b"OpusTags something else")));
+      assert_eq!(hash(&opus(b"OpusTags")), hash(&[&audio[..], &audio[..]].concat()));
+   }
+}
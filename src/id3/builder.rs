@@ -0,0 +1,138 @@
+use super::v24::{Date, Frame, FrameData, FrameValueError};
+use super::write::{self, Picture, WriteError};
+use super::{unique_key, Id3Tag};
+use std::io::Write;
+
+/// Assembles a tag one frame at a time, e.g.
+/// `TagBuilder::new().title("Title").artist("Artist").apic(picture).to_bytes()`.
+///
+/// Setting a frame that can only appear once (per description, language, and so on) replaces the earlier one.
+/// Invalid values, like a track number of 0, are reported when the tag is built.
+#[derive(Clone, Debug)]
+pub struct TagBuilder {
+   frames: Vec<FrameData<'static>>,
+   padding: u32,
+   error: Option<FrameValueError>,
+}
+
+impl Default for TagBuilder {
+   fn default() -> TagBuilder {
+      TagBuilder::new()
+   }
+}
+
+impl TagBuilder {
+   /// Room for the tag to be edited in place later without rewriting the whole file
+   pub const DEFAULT_PADDING: u32 = 1024;
+
+   pub fn new() -> TagBuilder {
+      TagBuilder {
+         frames: Vec::new(),
+         padding: TagBuilder::DEFAULT_PADDING,
+         error: None,
+      }
+   }
+
+   pub fn title<T: Into<String>>(self, title: T) -> TagBuilder {
+      self.frame(FrameData::title(title.into()))
+   }
+
+   pub fn artist<T: Into<String>>(self, artist: T) -> TagBuilder {
+      self.frame(FrameData::artist(artist.into()))
+   }
+
+   pub fn album<T: Into<String>>(self, album: T) -> TagBuilder {
+      self.frame(FrameData::album(album.into()))
+   }
+
+   pub fn album_artist<T: Into<String>>(self, album_artist: T) -> TagBuilder {
+      self.frame(FrameData::album_artist(album_artist.into()))
+   }
+
+   pub fn composer<T: Into<String>>(self, composer: T) -> TagBuilder {
+      self.frame(FrameData::composer(composer.into()))
+   }
+
+   pub fn genre<T: Into<String>>(self, genre: T) -> TagBuilder {
+      self.frame(FrameData::genre(genre.into()))
+   }
+
+   pub fn track(self, number: u64, total: Option<u64>) -> TagBuilder {
+      self.checked_frame(FrameData::track(number, total))
+   }
+
+   pub fn disc(self, number: u64, total: Option<u64>) -> TagBuilder {
+      self.checked_frame(FrameData::disc(number, total))
+   }
+
+   pub fn recording_date(self, date: Date) -> TagBuilder {
+      self.checked_frame(FrameData::recording_date(date))
+   }
+
+   pub fn copyright<T: Into<String>>(self, year: u16, message: T) -> TagBuilder {
+      self.checked_frame(FrameData::copyright(year, message.into()))
+   }
+
+   /// `lang` is an ISO-639-2 code like "eng", or "XXX" if the language is unknown
+   pub fn comment<T: Into<String>>(self, lang: &str, text: T) -> TagBuilder {
+      self.checked_frame(FrameData::comment(lang, "", text.into()))
+   }
+
+   /// `lang` is an ISO-639-2 code like "eng", or "XXX" if the language is unknown
+   pub fn lyrics<T: Into<String>>(self, lang: &str, text: T) -> TagBuilder {
+      self.checked_frame(FrameData::lyrics(lang, "", text.into()))
+   }
+
+   pub fn user_text<D: Into<String>, T: Into<String>>(self, description: D, text: T) -> TagBuilder {
+      self.frame(FrameData::user_text(description.into(), text.into()))
+   }
+
+   pub fn apic(self, picture: Picture) -> TagBuilder {
+      self.frame(picture.to_frame_data())
+   }
+
+   /// Adds any frame, replacing an earlier one that can only appear once
+   pub fn frame(mut self, data: FrameData<'static>) -> TagBuilder {
+      if let Some(key) = unique_key(&data) {
+         self.frames.retain(|x| unique_key(x).as_ref() != Some(&key));
+      }
+      self.frames.push(data);
+      self
+   }
+
+   /// How many zero bytes to write after the frames
+   pub fn padding(mut self, padding: u32) -> TagBuilder {
+      self.padding = padding;
+      self
+   }
+
+   /// The frames, in the order they were added, or the first invalid value
+   pub fn build(self) -> Result<Id3Tag, FrameValueError> {
+      if let Some(e) = self.error {
+         return Err(e);
+      }
+      let frames = self.frames.into_iter().map(Frame::new).collect();
+      Ok(Id3Tag { frames })
+   }
+
+   /// Encodes the tag as ID3v2.4 (see `write::encode_tag`)
+   pub fn to_bytes(self) -> Result<Vec<u8>, WriteError> {
+      let padding = self.padding;
+      write::encode_tag(&self.build()?, padding)
+   }
+
+   pub fn write<W: Write>(self, dest: &mut W) -> Result<(), WriteError> {
+      dest.write_all(&self.to_bytes()?)?;
+      Ok(())
+   }
+
+   fn checked_frame(mut self, data: Result<FrameData<'static>, FrameValueError>) -> TagBuilder {
+      match data {
+         Ok(data) => self.frame(data),
+         Err(e) => {
+            self.error = self.error.or(Some(e));
+            self
+         }
+      }
+   }
+}
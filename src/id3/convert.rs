@@ -0,0 +1,451 @@
+use super::v24::{self, Date, Frame, FrameData, FrameFlags, Unknown};
+use super::values::Values;
+use super::write::{self, Picture};
+use super::{v22, v23, Id3Tag, ParseOptions};
+use std::borrow::Cow;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Version {
+   V22,
+   V23,
+   V24,
+}
+
+#[derive(Clone, Debug)]
+pub struct Conversion {
+   pub tag: Id3Tag,
+   /// Frames that have no equivalent in the target version, or that failed to convert
+   pub dropped: Vec<Frame<'static>>,
+}
+
+/// Converts the frames of a tag to the frame set of the given version.
+///
+/// Frames are always represented as v2.4 `FrameData`, so frames that only exist in v2.3 (TYER, IPLS, RVAD, and so on)
+/// are held as `Unknown` frames containing their v2.3 encoding. Since v2.3 has neither UTF-8 nor UTF-16BE, text,
+/// comment, lyrics, and picture frames are re-encoded as ISO-8859-1 or UTF-16 on the way down, and held the same
+/// way, with multiple values joined by slashes. v2.2 frames are held as `Unknown` frames too, under their 3
+/// character IDs (see `v22::is_v22_name`), and are otherwise the same as their v2.3 successors, apart from PIC.
+///
+/// Upgrading renames v2.2 frames, then decodes every `Unknown` frame that isn't compressed or encrypted back into
+/// its v2.4 equivalent, merging the separate date frames into TDRC.
+pub fn convert(tag: Id3Tag, target: Version) -> Conversion {
+   match target {
+      Version::V22 => downgrade_v22(tag),
+      Version::V23 => downgrade(tag),
+      Version::V24 => upgrade(tag),
+   }
+}
+
+enum Outcome {
+   Keep,
+   Replace(Vec<FrameData<'static>>),
+   Drop,
+   // Needs the other frames of its kind before it can be converted
+   Merge,
+}
+
+// From here on, v2.2 frames are converted the same way as the v2.3 frames that replaced them
+fn upgrade_v22(frames: Vec<Frame<'static>>, dropped: &mut Vec<Frame<'static>>) -> Vec<Frame<'static>> {
+   let mut v23_frames = Vec::new();
+   for frame in frames {
+      let outcome = match &frame.data {
+         FrameData::Unknown(x) if v22::is_v22_name(x.name) => match v22::upgrade_frame(x) {
+            Some(data) => Outcome::Replace(vec![data]),
+            None => Outcome::Drop,
+         },
+         _ => Outcome::Keep,
+      };
+      apply(frame, outcome, &mut v23_frames, dropped, &mut Vec::new());
+   }
+   v23_frames
+}
+
+fn upgrade(tag: Id3Tag) -> Conversion {
+   let mut dropped = Vec::new();
+   let v23_frames = upgrade_v22(tag.frames, &mut dropped);
+
+   let has_frame = |name: &[u8; 4]| v23_frames.iter().any(|x| x.data.name() == *name);
+   let has_tdrc = has_frame(b"TDRC");
+   let has_tdor = has_frame(b"TDOR");
+
+   let mut frames = Vec::new();
+   let mut date_frames = Vec::new();
+   for frame in v23_frames {
+      let outcome = match &frame.data {
+         FrameData::Unknown(_) if frame.flags.intersects(FrameFlags::COMPRESSION | FrameFlags::ENCRYPTION) => {
+            Outcome::Keep
+         }
+         FrameData::Unknown(x) => upgrade_unknown(x, has_tdrc, has_tdor),
+         _ => Outcome::Keep,
+      };
+      apply(frame, outcome, &mut frames, &mut dropped, &mut date_frames);
+   }
+
+   if !date_frames.is_empty() {
+      let text = |name: &[u8; 4]| {
+         date_frames.iter().find_map(|x| match &x.data {
+            FrameData::Unknown(u) if u.name == *name => first_text(&u.data),
+            _ => None,
+         })
+      };
+      let (tyer, tdat, time, trda) = (text(b"TYER"), text(b"TDAT"), text(b"TIME"), text(b"TRDA"));
+      let date = v23::recording_date(
+         tyer.as_ref().map(String::as_str),
+         tdat.as_ref().map(String::as_str),
+         time.as_ref().map(String::as_str),
+         trda.as_ref().map(String::as_str),
+      );
+      match date {
+         Some(date) => frames.push(Frame {
+            data: FrameData::TDRC(Values::one(date)),
+            ..date_frames[0]
+         }),
+         None => dropped.extend(date_frames),
+      }
+   }
+
+   Conversion {
+      tag: Id3Tag { frames },
+      dropped,
+   }
+}
+
+fn upgrade_unknown(frame: &Unknown, has_tdrc: bool, has_tdor: bool) -> Outcome {
+   match &frame.name {
+      b"TYER" | b"TDAT" | b"TIME" | b"TRDA" if !has_tdrc => Outcome::Merge,
+      b"TORY" if !has_tdor => match first_text(&frame.data).and_then(|x| v23::original_release_date(&x)) {
+         Some(date) => Outcome::Replace(vec![FrameData::TDOR(Values::one(date))]),
+         None => Outcome::Drop,
+      },
+      b"IPLS" | b"RVAD" | b"EQUA" => decode(frame.name, &frame.data),
+      // Unofficial v2.3 sort frames, from before TSOA/TSOP/TSOT existed
+      b"XSOA" => decode(*b"TSOA", &frame.data),
+      b"XSOP" => decode(*b"TSOP", &frame.data),
+      b"XSOT" => decode(*b"TSOT", &frame.data),
+      // TSIZ was deprecated, and the date frames are redundant when the tag already has the v2.4 frame
+      b"TYER" | b"TDAT" | b"TIME" | b"TRDA" | b"TORY" | b"TSIZ" => Outcome::Drop,
+      // Including the frames that were re-encoded for v2.3. Frames that don't decode are kept as they are.
+      _ => match v23::decode_frame(frame.name, &frame.data, &ParseOptions::default()) {
+         Ok(FrameData::Unknown(_)) | Err(_) => Outcome::Keep,
+         Ok(data) => Outcome::Replace(vec![data.into_owned()]),
+      },
+   }
+}
+
+fn decode(name: [u8; 4], data: &[u8]) -> Outcome {
+   match v23::decode_frame(name, data, &ParseOptions::default()) {
+      Ok(data) => Outcome::Replace(vec![data.into_owned()]),
+      Err(_) => Outcome::Drop,
+   }
+}
+
+fn downgrade(tag: Id3Tag) -> Conversion {
+   let mut frames = Vec::new();
+   let mut dropped = Vec::new();
+   let mut people_frames = Vec::new();
+   let mut seen_rva2 = false;
+   let mut seen_equ2 = false;
+   for frame in upgrade_v22(tag.frames, &mut dropped) {
+      let outcome = match &frame.data {
+         // v2.3 has room for a single date
+         FrameData::TDRC(dates) => match dates.first() {
+            Some(date) => Outcome::Replace(split_date(date)),
+            None => Outcome::Drop,
+         },
+         FrameData::TDOR(dates) => match dates.first() {
+            Some(date) => Outcome::Replace(vec![text_frame(*b"TORY", &[&format!("{:04}", date.year)])]),
+            None => Outcome::Drop,
+         },
+         // v2.3 has one list for both
+         FrameData::TIPL(_) | FrameData::TMCL(_) => Outcome::Merge,
+         FrameData::RVA2(x) if !seen_rva2 => {
+            seen_rva2 = true;
+            Outcome::Replace(vec![unknown(*b"RVAD", v23::encode_rvad_frame(x))])
+         }
+         FrameData::EQU2(x) if !seen_equ2 => {
+            seen_equ2 = true;
+            Outcome::Replace(vec![unknown(*b"EQUA", v23::encode_equa_frame(x))])
+         }
+         FrameData::TSOA(x) => Outcome::Replace(vec![joined_text_frame(*b"XSOA", x)]),
+         FrameData::TSOP(x) => Outcome::Replace(vec![joined_text_frame(*b"XSOP", x)]),
+         FrameData::TSOT(x) => Outcome::Replace(vec![joined_text_frame(*b"XSOT", x)]),
+         FrameData::RVA2(_)
+         | FrameData::EQU2(_)
+         | FrameData::TDEN(_)
+         | FrameData::TDRL(_)
+         | FrameData::TDTG(_)
+         | FrameData::TMOO(_)
+         | FrameData::TPRO(_)
+         | FrameData::TSST(_) => Outcome::Drop,
+         FrameData::Unknown(x) if &x.name == b"ASPI" || &x.name == b"SEEK" || &x.name == b"SIGN" => Outcome::Drop,
+         FrameData::TXXX(x) => Outcome::Replace(vec![text_frame(*b"TXXX", &[&x.description, &join(&x.text)])]),
+         FrameData::COMM(x) | FrameData::USLT(x) => {
+            let mut data = encode_texts(&[&x.description, &join(&x.text)]);
+            data.splice(1..1, x.iso_639_2_lang.iter().cloned());
+            Outcome::Replace(vec![unknown(frame.data.name(), data)])
+         }
+         FrameData::Unknown(x) if &x.name == b"APIC" && x.data.first().map_or(false, |x| *x > 1) => {
+            match Picture::from_frame_data(&frame.data) {
+               Some(picture) => Outcome::Replace(vec![picture_frame(&picture)]),
+               None => Outcome::Drop,
+            }
+         }
+         data if is_text_frame(data) => {
+            let body = write::encode_frame_data(data);
+            match v24::decode_text_frame(&body, &v24::DecodeOptions::new(&ParseOptions::default())) {
+               Ok(texts) => Outcome::Replace(vec![joined_text_frame(data.name(), &texts)]),
+               Err(_) => Outcome::Drop,
+            }
+         }
+         _ => Outcome::Keep,
+      };
+      apply(frame, outcome, &mut frames, &mut dropped, &mut people_frames);
+   }
+
+   if !people_frames.is_empty() {
+      let mut people: Vec<(&str, &str)> = people_frames
+         .iter()
+         .flat_map(|x| match &x.data {
+            FrameData::TIPL(map) | FrameData::TMCL(map) => map.iter().map(|(k, v)| (k.as_ref(), v.as_ref())).collect(),
+            _ => Vec::new(),
+         })
+         .collect();
+      // The maps have no order, but the output should be the same every time
+      people.sort();
+      let texts: Vec<&str> = people.iter().flat_map(|(k, v)| vec![*k, *v]).collect();
+      let ipls = text_frame(*b"IPLS", &texts);
+      frames.push(Frame {
+         data: ipls,
+         ..people_frames[0]
+      });
+   }
+
+   Conversion {
+      tag: Id3Tag { frames },
+      dropped,
+   }
+}
+
+// v2.2 frames have no flags, and there are no v2.2 frames for anything that only exists in v2.4
+fn downgrade_v22(tag: Id3Tag) -> Conversion {
+   let Conversion { tag, mut dropped } = downgrade(tag);
+   let mut frames = Vec::new();
+   for frame in tag.frames {
+      let renamed = match &frame.data {
+         FrameData::Unknown(_) if frame.flags.intersects(FrameFlags::COMPRESSION | FrameFlags::ENCRYPTION) => None,
+         FrameData::Unknown(x) => v22::downgrade_frame(x),
+         data => v22::downgrade_frame(&Unknown {
+            name: data.name(),
+            data: Cow::Owned(write::encode_frame_data(data)),
+         }),
+      };
+      match renamed {
+         Some(data) => frames.push(Frame {
+            data,
+            group: None,
+            flags: FrameFlags::empty(),
+            ..frame
+         }),
+         None => dropped.push(frame),
+      }
+   }
+   Conversion {
+      tag: Id3Tag { frames },
+      dropped,
+   }
+}
+
+// The frames that hold a list of text values, encoded like T*** frames
+fn is_text_frame(data: &FrameData) -> bool {
+   match data {
+      FrameData::Unknown(_) | FrameData::TXXX(_) => false,
+      FrameData::MVIN(_) | FrameData::MVNM(_) | FrameData::WFED(_) => true,
+      _ => data.name()[0] == b'T',
+   }
+}
+
+fn apply(
+   frame: Frame<'static>,
+   outcome: Outcome,
+   frames: &mut Vec<Frame<'static>>,
+   dropped: &mut Vec<Frame<'static>>,
+   merged: &mut Vec<Frame<'static>>,
+) {
+   match outcome {
+      Outcome::Keep => frames.push(frame),
+      Outcome::Replace(data) => frames.extend(data.into_iter().map(|data| Frame { data, ..frame })),
+      Outcome::Drop => dropped.push(frame),
+      Outcome::Merge => merged.push(frame),
+   }
+}
+
+// TYER (yyyy), TDAT (DDMM), and TIME (HHMM)
+fn split_date(date: &Date) -> Vec<FrameData<'static>> {
+   let mut frames = vec![text_frame(*b"TYER", &[&format!("{:04}", date.year)])];
+   if let (Some(day), Some(month)) = (date.day, date.month) {
+      frames.push(text_frame(*b"TDAT", &[&format!("{:02}{:02}", day, month)]));
+   }
+   if let (Some(hour), Some(minutes)) = (date.hour, date.minutes) {
+      frames.push(text_frame(*b"TIME", &[&format!("{:02}{:02}", hour, minutes)]));
+   }
+   frames
+}
+
+// v2.3 text frames hold a single value, with multiple values separated by slashes
+fn joined_text_frame(name: [u8; 4], texts: &[Cow<str>]) -> FrameData<'static> {
+   text_frame(name, &[&join(texts)])
+}
+
+fn join(texts: &[Cow<str>]) -> String {
+   let texts: Vec<&str> = texts.iter().map(|x| x.as_ref()).collect();
+   texts.join("/")
+}
+
+fn text_frame(name: [u8; 4], texts: &[&str]) -> FrameData<'static> {
+   unknown(name, encode_texts(texts))
+}
+
+// The MIME type and picture type go between the encoding and the description
+fn picture_frame(picture: &Picture) -> FrameData<'static> {
+   let mut data = encode_texts(&[&picture.description]);
+   let mut header: Vec<u8> = write::latin1_lossy(&picture.mime_type).collect();
+   header.push(0);
+   header.push(picture.picture_type);
+   data.splice(1..1, header);
+   data.extend_from_slice(if data[0] == 0 { &[0] } else { &[0, 0] });
+   data.extend_from_slice(&picture.data);
+   unknown(*b"APIC", data)
+}
+
+// The encoding, then the null separated texts. ISO-8859-1 if it can hold the text, otherwise UTF-16 with a BOM,
+// since v2.3 has neither UTF-8 nor UTF-16BE.
+fn encode_texts(texts: &[&str]) -> Vec<u8> {
+   let mut data = Vec::new();
+   if texts.iter().all(|x| x.chars().all(|c| (c as u32) < 0x100)) {
+      data.push(0);
+      for (i, text) in texts.iter().enumerate() {
+         if i > 0 {
+            data.push(0);
+         }
+         data.extend(text.chars().map(|c| c as u8));
+      }
+   } else {
+      data.push(1);
+      for (i, text) in texts.iter().enumerate() {
+         if i > 0 {
+            data.extend_from_slice(&[0, 0]);
+         }
+         data.extend_from_slice(&[0xff, 0xfe]);
+         data.extend(text.encode_utf16().flat_map(|x| x.to_le_bytes().to_vec()));
+      }
+   }
+   data
+}
+
+fn unknown(name: [u8; 4], data: Vec<u8>) -> FrameData<'static> {
+   FrameData::Unknown(Unknown {
+      name,
+      data: Cow::Owned(data),
+   })
+}
+
+fn first_text(data: &[u8]) -> Option<String> {
+   v24::decode_text_frame(data, &v24::DecodeOptions::new(&ParseOptions::default()))
+      .ok()?
+      .into_iter()
+      .next()
+      .map(Cow::into_owned)
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+   #[cfg(test)]
+   use std::collections::HashMap;
+
+   #[cfg(test)]
+   fn frame(data: FrameData<'static>) -> Frame<'static> {
+      Frame {
+         data,
+         group: None,
+         flags: v24::FrameFlags::empty(),
+         offset: 0,
+         size: 0,
+      }
+   }
+
+   #[test]
+   fn round_trip_v23() {
+      let mut people = HashMap::new();
+      people.insert(Cow::Borrowed("producer"), Cow::Borrowed("Someone"));
+      let tag = Id3Tag {
+         frames: vec![
+            frame(FrameData::TIT2(Values::one(Cow::Borrowed("Title")))),
+            frame(FrameData::TDRC(Values::one("1996-06-04T21:30".parse().unwrap()))),
+            frame(FrameData::TIPL(people)),
+            frame(FrameData::TMOO(Values::one(Cow::Borrowed("Calm")))),
+         ],
+      };
+
+      let v23 = convert(tag, Version::V23);
+      let names: Vec<[u8; 4]> = v23.tag.frames.iter().map(|x| x.data.name()).collect();
+      assert_eq!(names, vec![*b"TIT2", *b"TYER", *b"TDAT", *b"TIME", *b"IPLS"]);
+      assert_eq!(v23.dropped.len(), 1);
+
+      let v24 = convert(v23.tag, Version::V24);
+      assert!(v24.dropped.is_empty());
+      let names: Vec<[u8; 4]> = v24.tag.frames.iter().map(|x| x.data.name()).collect();
+      assert_eq!(names, vec![*b"TIT2", *b"TIPL", *b"TDRC"]);
+      match &v24.tag.frames[2].data {
+         FrameData::TDRC(x) => assert_eq!(
+            (x[0].year, x[0].month, x[0].day, x[0].hour, x[0].minutes),
+            (1996, Some(6), Some(4), Some(21), Some(30))
+         ),
+         x => panic!("{:?}", x),
+      }
+   }
+
+   #[test]
+   fn round_trip_v22() {
+      let mut frames = Vec::new();
+      let mut push = |name: &[u8; 3], data: &[u8]| {
+         frames.extend_from_slice(name);
+         frames.extend_from_slice(&(data.len() as u32).to_be_bytes()[1..]);
+         frames.extend_from_slice(data);
+      };
+      push(b"TT2", b"\0Title");
+      push(b"TYE", b"\01996");
+      push(b"PIC", b"\0PNG\x03\0picture");
+      // Encrypted meta frames have no successor
+      push(b"CRM", b"owner\0");
+      let mut bytes = vec![b'I', b'D', b'3', 2, 0, 0, 0, 0, 0, frames.len() as u8];
+      bytes.extend(frames);
+      let tag = v22::read_tag(&mut &bytes[..], &ParseOptions::default()).unwrap();
+      assert_eq!(tag.frames[1].byte_range(0), 22..33);
+
+      let mut v24 = convert(tag, Version::V24);
+      assert_eq!(v24.dropped.len(), 1);
+      let names: Vec<[u8; 4]> = v24.tag.frames.iter().map(|x| x.data.name()).collect();
+      assert_eq!(names, vec![*b"TIT2", *b"APIC", *b"TDRC"]);
+      let picture = Picture::from_frame_data(&v24.tag.frames[1].data).unwrap();
+      assert_eq!(
+         (picture.mime_type.as_ref(), &picture.data[..]),
+         ("image/png", &b"picture"[..])
+      );
+
+      // v2.2 has no UTF-8
+      v24.tag.frames[0].data = FrameData::TIT2(Values::one(Cow::Borrowed("Заголовок")));
+      let v22 = convert(v24.tag, Version::V22);
+      assert!(v22.dropped.is_empty());
+      let names: Vec<[u8; 4]> = v22.tag.frames.iter().map(|x| x.data.name()).collect();
+      assert_eq!(names, vec![*b"TT2\0", *b"PIC\0", *b"TYE\0"]);
+      match &v22.tag.frames[0].data {
+         FrameData::Unknown(x) => assert_eq!(&x.data[..3], &[1, 0xff, 0xfe]),
+         x => panic!("{:?}", x),
+      }
+      match &v22.tag.frames[1].data {
+         FrameData::Unknown(x) => assert_eq!(&x.data[..], b"\0PNG\x03\0picture"),
+         x => panic!("{:?}", x),
+      }
+   }
+}
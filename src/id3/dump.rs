@@ -0,0 +1,242 @@
+use super::synchsafe::{is_synchsafe, synchsafe_u32_to_u32};
+use super::{find_header, resynchronize, ParseOptions, TagParseError};
+use byteorder::{BigEndian, ByteOrder};
+use std::io::{Read, Seek, SeekFrom};
+
+/// The layout of an ID3v2 tag as it is stored: the header, the extended header, and every frame header, read
+/// without decoding anything. Unlike the parsers, this doesn't stop at sizes that don't add up, so it shows where a
+/// broken tag goes wrong.
+///
+/// Offsets are relative to the start of the tag header. In ID3v2.2 and ID3v2.3 tags that are unsynchronized as a
+/// whole, the frames are resynchronized first, so their offsets are into the resynchronized bytes.
+#[derive(Clone, Debug)]
+pub struct RawTag {
+   /// Where the tag header is in the file
+   pub tag_start: u64,
+   pub version: u8,
+   pub revision: u8,
+   pub flags: u8,
+   /// The size in the header, which doesn't count the header or the footer
+   pub size: u32,
+   pub size_is_synchsafe: bool,
+   /// How much of that size the file actually holds
+   pub available: u32,
+   pub extended_header: Option<RawExtendedHeader>,
+   pub frames: Vec<RawFrameHeader>,
+   /// Where the frames end
+   pub rest_offset: u32,
+   /// Whatever follows the frames: padding if it's all zeros, otherwise junk or a frame we couldn't make sense of
+   pub rest: Vec<u8>,
+}
+
+#[derive(Clone, Debug)]
+pub struct RawExtendedHeader {
+   pub offset: u32,
+   /// As stored. ID3v2.4 counts the size field itself, ID3v2.3 doesn't.
+   pub size: u32,
+   /// The whole extended header, size field included
+   pub bytes: Vec<u8>,
+}
+
+#[derive(Clone, Debug)]
+pub struct RawFrameHeader {
+   pub offset: u32,
+   /// 3 characters in ID3v2.2, 4 after
+   pub name: String,
+   /// Always 0 in ID3v2.2, which has no frame flags
+   pub flags: u16,
+   /// The size in the frame header, not counting the header
+   pub size: u32,
+   /// ID3v2.4 sizes are synchsafe. If this one isn't, it was read as a plain integer, as iTunes writes them.
+   pub size_is_synchsafe: bool,
+   /// The payload, which is shorter than the size if the frame runs past the end of the tag
+   pub payload: Vec<u8>,
+}
+
+impl RawTag {
+   /// Reads the first tag in the source. The search for the header is the same as the parsers'.
+   pub fn read<S: Read + Seek>(source: &mut S, options: &ParseOptions) -> Result<RawTag, TagParseError> {
+      let mut header = [0u8; 10];
+      source.seek(SeekFrom::Start(0))?;
+      source.read_exact(&mut header)?;
+      let tag_start = if &header[0..3] != b"ID3" {
+         find_header(source, &mut header, options.search_window)?
+      } else {
+         0
+      };
+
+      let raw_size = BigEndian::read_u32(&header[6..10]);
+      let size_is_synchsafe = is_synchsafe(raw_size);
+      let size = if size_is_synchsafe {
+         synchsafe_u32_to_u32(raw_size)
+      } else {
+         raw_size
+      };
+      let mut body = Vec::new();
+      source.take(u64::from(size)).read_to_end(&mut body)?;
+
+      let mut tag = RawTag {
+         tag_start,
+         version: header[3],
+         revision: header[4],
+         flags: header[5],
+         size,
+         size_is_synchsafe,
+         available: body.len() as u32,
+         extended_header: None,
+         frames: Vec::new(),
+         rest_offset: 10,
+         rest: Vec::new(),
+      };
+      if tag.version < 2 || tag.version > 4 {
+         tag.rest = body;
+         return Ok(tag);
+      }
+      if tag.version < 4 && tag.flags & 0x80 != 0 {
+         body = resynchronize(&body);
+      }
+
+      let mut cursor = 0;
+      if tag.version > 2 && tag.flags & 0x40 != 0 && body.len() >= 4 {
+         let size = BigEndian::read_u32(&body[0..4]);
+         let len = if tag.version == 4 {
+            synchsafe_u32_to_u32(size) as usize
+         } else {
+            size as usize + 4
+         };
+         let len = len.min(body.len());
+         tag.extended_header = Some(RawExtendedHeader {
+            offset: 10,
+            size,
+            bytes: body[..len].to_vec(),
+         });
+         cursor = len;
+      }
+
+      let (header_len, name_len) = if tag.version == 2 { (6, 3) } else { (10, 4) };
+      while body.len() - cursor >= header_len {
+         let bytes = &body[cursor..cursor + header_len];
+         let name = &bytes[..name_len];
+         if !name.iter().all(|x| x.is_ascii_uppercase() || x.is_ascii_digit()) {
+            break;
+         }
+         let (size, flags) = if tag.version == 2 {
+            (BigEndian::read_u24(&bytes[3..6]), 0)
+         } else {
+            (BigEndian::read_u32(&bytes[4..8]), BigEndian::read_u16(&bytes[8..10]))
+         };
+         let size_is_synchsafe = tag.version != 4 || is_synchsafe(size);
+         let size = if tag.version == 4 && size_is_synchsafe {
+            synchsafe_u32_to_u32(size)
+         } else {
+            size
+         };
+         let start = cursor + header_len;
+         let end = start + (size as usize).min(body.len() - start);
+         tag.frames.push(RawFrameHeader {
+            offset: 10 + cursor as u32,
+            name: String::from_utf8_lossy(name).into_owned(),
+            flags,
+            size,
+            size_is_synchsafe,
+            payload: body[start..end].to_vec(),
+         });
+         cursor = end;
+      }
+      tag.rest_offset = 10 + cursor as u32;
+      tag.rest = body.split_off(cursor);
+      Ok(tag)
+   }
+
+   /// Whether what follows the frames is padding, rather than something we couldn't read as a frame
+   pub fn is_padding(&self) -> bool {
+      self.rest.iter().all(|x| *x == 0)
+   }
+
+   /// The names of the tag header flags that are set
+   pub fn flag_names(&self) -> Vec<&'static str> {
+      let names: &[(u8, &str)] = match self.version {
+         2 => &[(0x80, "unsynchronized"), (0x40, "compressed")],
+         3 => &[
+            (0x80, "unsynchronized"),
+            (0x40, "extended header"),
+            (0x20, "experimental"),
+         ],
+         _ => &[
+            (0x80, "unsynchronized"),
+            (0x40, "extended header"),
+            (0x20, "experimental"),
+            (0x10, "footer"),
+         ],
+      };
+      set_flags(u16::from(self.flags), names)
+   }
+
+   /// The names of the frame header flags that are set, which moved around between ID3v2.3 and ID3v2.4
+   pub fn frame_flag_names(&self, flags: u16) -> Vec<&'static str> {
+      let names: &[(u16, &str)] = match self.version {
+         2 => &[],
+         3 => &[
+            (0x8000, "discard on tag alter"),
+            (0x4000, "discard on file alter"),
+            (0x2000, "read only"),
+            (0x0080, "compressed"),
+            (0x0040, "encrypted"),
+            (0x0020, "grouped"),
+         ],
+         _ => &[
+            (0x4000, "discard on tag alter"),
+            (0x2000, "discard on file alter"),
+            (0x1000, "read only"),
+            (0x0040, "grouped"),
+            (0x0008, "compressed"),
+            (0x0004, "encrypted"),
+            (0x0002, "unsynchronized"),
+            (0x0001, "data length indicator"),
+         ],
+      };
+      set_flags(flags, names)
+   }
+}
+
+fn set_flags<T: Copy + Into<u16>>(flags: u16, names: &[(T, &'static str)]) -> Vec<&'static str> {
+   names
+      .iter()
+      .filter(|(bit, _)| flags & (*bit).into() != 0)
+      .map(|(_, name)| *name)
+      .collect()
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+   #[cfg(test)]
+   use std::io::Cursor;
+
+   #[test]
+   fn broken_sizes() {
+      // The TPE1 size is a plain integer (0x80 isn't synchsafe), and the TALB size runs past the end of the tag
+      let mut tag = b"ID3\x04\x00\x00\x00\x00\x02\x00".to_vec();
+      tag.extend_from_slice(b"TIT2\x00\x00\x00\x03\x00\x00\x00Hi");
+      tag.extend_from_slice(b"TPE1\x00\x00\x00\x80\x00\x00\x00");
+      tag.extend_from_slice(&[b'a'; 0x7f]);
+      tag.extend_from_slice(b"TALB\x00\x00\x01\x00\x00\x00\x00Album");
+      let dump = RawTag::read(&mut Cursor::new(&tag), &ParseOptions::default()).unwrap();
+
+      assert_eq!((dump.size, dump.available), (0x100, tag.len() as u32 - 10));
+      let frames: Vec<_> = dump
+         .frames
+         .iter()
+         .map(|x| (x.offset, &x.name[..], x.size, x.size_is_synchsafe, x.payload.len()))
+         .collect();
+      assert_eq!(
+         frames,
+         [
+            (10, "TIT2", 3, true, 3),
+            (23, "TPE1", 0x80, false, 0x80),
+            (161, "TALB", 0x80, true, 6)
+         ]
+      );
+      assert!(dump.rest.is_empty());
+   }
+}
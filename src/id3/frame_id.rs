@@ -0,0 +1,176 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct UnknownFrameId(pub [u8; 4]);
+
+impl fmt::Display for UnknownFrameId {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      write!(f, "unknown frame ID {}", String::from_utf8_lossy(&self.0))
+   }
+}
+
+impl Error for UnknownFrameId {}
+
+impl fmt::Display for FrameId {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      write!(f, "{}", String::from_utf8_lossy(&self.as_bytes()))
+   }
+}
+
+// The identifiers double as the frame IDs, so each ID is only written once
+macro_rules! frame_ids {
+   ($($id:ident => $description:expr,)*) => {
+      /// Every frame ID defined by ID3v2.4, plus the iTunes and chapter extensions that taggers commonly write
+      #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+      #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+      pub enum FrameId {
+         $($id,)*
+      }
+
+      impl FrameId {
+         pub const ALL: &'static [FrameId] = &[$(FrameId::$id,)*];
+
+         pub fn as_bytes(self) -> [u8; 4] {
+            let name = match self {
+               $(FrameId::$id => stringify!($id),)*
+            }
+            .as_bytes();
+            [name[0], name[1], name[2], name[3]]
+         }
+
+         /// What the frame holds, e.g. "Title" for TIT2
+         pub fn description(self) -> &'static str {
+            match self {
+               $(FrameId::$id => $description,)*
+            }
+         }
+      }
+
+      impl TryFrom<[u8; 4]> for FrameId {
+         type Error = UnknownFrameId;
+
+         fn try_from(name: [u8; 4]) -> Result<FrameId, UnknownFrameId> {
+            match std::str::from_utf8(&name) {
+               $(Ok(stringify!($id)) => Ok(FrameId::$id),)*
+               _ => Err(UnknownFrameId(name)),
+            }
+         }
+      }
+   };
+}
+
+frame_ids! {
+   AENC => "Audio encryption",
+   APIC => "Attached picture",
+   ASPI => "Audio seek point index",
+   CHAP => "Chapter",
+   COMM => "Comment",
+   COMR => "Commercial frame",
+   CTOC => "Table of contents",
+   ENCR => "Encryption method registration",
+   EQU2 => "Equalisation",
+   ETCO => "Event timing codes",
+   GEOB => "General encapsulated object",
+   GRID => "Group identification registration",
+   LINK => "Linked information",
+   MCDI => "Music CD identifier",
+   MLLT => "MPEG location lookup table",
+   MVIN => "Movement",
+   MVNM => "Movement name",
+   OWNE => "Ownership frame",
+   PCNT => "Play counter",
+   PCST => "Podcast",
+   POPM => "Popularimeter",
+   POSS => "Position synchronisation",
+   PRIV => "Private frame",
+   RBUF => "Recommended buffer size",
+   RVA2 => "Relative volume adjustment",
+   RVRB => "Reverb",
+   SEEK => "Seek frame",
+   SIGN => "Signature frame",
+   SYLT => "Synchronised lyrics",
+   SYTC => "Synchronised tempo codes",
+   TALB => "Album",
+   TBPM => "BPM",
+   TCMP => "Compilation",
+   TCOM => "Composer",
+   TCON => "Genre",
+   TCOP => "Copyright",
+   TDEN => "Encoding date",
+   TDES => "Podcast description",
+   TDLY => "Playlist delay",
+   TDOR => "Original release date",
+   TDRC => "Recording date",
+   TDRL => "Release date",
+   TDTG => "Tagging date",
+   TENC => "Encoded by",
+   TEXT => "Lyricist/Text writer",
+   TFLT => "File type",
+   TGID => "Podcast ID",
+   TIPL => "Involved people",
+   TIT1 => "Content group description",
+   TIT2 => "Title",
+   TIT3 => "Subtitle/Description refinement",
+   TKEY => "Initial key",
+   TKWD => "Podcast keywords",
+   TLAN => "Language",
+   TLEN => "Length",
+   TMCL => "Musician credits",
+   TMED => "Media type",
+   TMOO => "Mood",
+   TOAL => "Original album title",
+   TOFN => "Original filename",
+   TOLY => "Original lyricist/Text writer",
+   TOPE => "Original artist",
+   TOWN => "File owner/Licensee",
+   TPE1 => "Artist",
+   TPE2 => "Album artist",
+   TPE3 => "Conductor",
+   TPE4 => "Interpreted, remixed, or otherwise modified by",
+   TPOS => "Part of a set",
+   TPRO => "Produced notice",
+   TPUB => "Publisher",
+   TRCK => "Track",
+   TRSN => "Internet radio station name",
+   TRSO => "Internet radio station owner",
+   TSO2 => "Album artist for sorting",
+   TSOA => "Album for sorting",
+   TSOC => "Composer for sorting",
+   TSOP => "Artist for sorting",
+   TSOT => "Title for sorting",
+   TSRC => "ISRC",
+   TSSE => "Encoding settings",
+   TSST => "Set subtitle",
+   TXXX => "User defined text",
+   UFID => "Unique file identifier",
+   USER => "Terms of use",
+   USLT => "Lyrics",
+   WCOM => "Commercial information URL",
+   WCOP => "Copyright/Legal information URL",
+   WFED => "Podcast feed URL",
+   WOAF => "Audio file URL",
+   WOAR => "Artist/Performer URL",
+   WOAS => "Audio source URL",
+   WORS => "Internet radio station URL",
+   WPAY => "Payment URL",
+   WPUB => "Publisher URL",
+   WXXX => "User defined URL",
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+
+   #[test]
+   fn ids_round_trip() {
+      for id in FrameId::ALL.iter() {
+         assert_eq!(FrameId::try_from(id.as_bytes()), Ok(*id));
+      }
+      assert_eq!(FrameId::TIT2.description(), "Title");
+      assert_eq!(FrameId::try_from(*b"TYER"), Err(UnknownFrameId(*b"TYER")));
+   }
+}
@@ -1,11 +1,30 @@
+use crate::tag::Tag;
 use byteorder::{BigEndian, ByteOrder, ReadBytesExt};
-use log::warn;
+#[cfg(feature = "async")]
+use futures::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt};
 use std;
-use std::io::{self, Read, Seek};
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom};
 
-mod v22;
-mod v23;
+pub use self::builder::TagBuilder;
+pub use self::frame_id::{FrameId, UnknownFrameId};
+use self::synchsafe::{is_synchsafe, synchsafe_u32_to_u32, synchsafe_u40_to_u32};
+
+mod builder;
+pub mod convert;
+pub mod dump;
+mod frame_id;
+pub mod normalize;
+pub mod synchsafe;
+pub mod v1;
+pub mod v22;
+pub mod v23;
 pub mod v24;
+pub mod values;
+pub mod write;
 
 enum TagFlags {
    V24(v24::TagFlags),
@@ -13,103 +32,1077 @@ enum TagFlags {
    V22(v22::TagFlags),
 }
 
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Strictness {
+   /// Reject anything that violates the spec
+   Strict,
+   /// Apply the heuristics needed to read tags written by buggy (but popular) software
+   Lenient,
+}
+
+impl Default for Strictness {
+   fn default() -> Strictness {
+      Strictness::Lenient
+   }
+}
+
+/// How to decode text frames that claim to be ISO-8859-1
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Latin1Decoding {
+   /// Decode 0x80-0x9F as the Windows-1252 punctuation (smart quotes, dashes, €) that taggers actually write
+   Windows1252,
+   /// Decode exactly as the spec says, where 0x80-0x9F are C1 control characters
+   Iso8859_1,
+   /// Guess the charset from the text itself, for the many old tags that are really Shift-JIS, GBK,
+   /// Windows-1251, and so on. Falls back to Windows-1252.
+   #[cfg(feature = "charset-detection")]
+   Detect,
+}
+
+impl Default for Latin1Decoding {
+   fn default() -> Latin1Decoding {
+      Latin1Decoding::Windows1252
+   }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ParseOptions {
+   pub strictness: Strictness,
+   /// If set, only frames with these IDs are decoded, and every other frame is skipped
+   pub frame_filter: Option<Vec<[u8; 4]>>,
+   /// If set, a tag whose extended header CRC doesn't match its frames fails with `TagParseError::CrcMismatch`
+   pub verify_crc: bool,
+   /// How many bytes of junk to skip looking for the tag header when the source doesn't start with one.
+   /// The search reads a byte at a time, so buffer the source if this is large.
+   pub search_window: u32,
+   pub latin1: Latin1Decoding,
+   /// If set, invalid UTF-8 and UTF-16 text is decoded with replacement characters instead of failing the frame.
+   /// `RawFrame::raw_bytes` has the undamaged text.
+   pub lossy_text: bool,
+   /// If set, frames that fail to decode are returned as `v24::FrameData::Unknown` instead of as errors, so a tag
+   /// that is read to be written back doesn't lose them
+   pub keep_undecodable: bool,
+   /// If set, decoded text is cleaned up and normalized to NFC (see `normalize::clean`)
+   pub normalize_text: bool,
+}
+
+impl ParseOptions {
+   /// Restricts parsing to the given frame IDs, e.g. `ParseOptions::default().only_frames(&[b"TIT2", b"TPE1"])`
+   pub fn only_frames(mut self, ids: &[&[u8; 4]]) -> ParseOptions {
+      self.frame_filter = Some(ids.iter().map(|id| **id).collect());
+      self
+   }
+
+   fn wants_frame(&self, name: &[u8; 4]) -> bool {
+      match self.frame_filter {
+         Some(ref ids) => ids.contains(name),
+         None => true,
+      }
+   }
+}
+
 #[derive(Debug)]
 pub enum TagParseError {
    NoTag,
    TagTooSmall,
+   InvalidSize,
    UnsupportedVersion(u8),
+   CrcMismatch { expected: u32, actual: u32 },
    Io(io::Error),
 }
 
+impl fmt::Display for TagParseError {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      match self {
+         TagParseError::NoTag => write!(f, "no ID3 tag found"),
+         TagParseError::TagTooSmall => write!(f, "tag is too small to hold its headers"),
+         TagParseError::InvalidSize => write!(f, "tag size is not a synchsafe integer"),
+         TagParseError::UnsupportedVersion(ver) => write!(f, "ID3v2.{} is not supported", ver),
+         TagParseError::CrcMismatch { expected, actual } => write!(
+            f,
+            "tag CRC is {:08x}, but the extended header says {:08x}",
+            actual, expected
+         ),
+         TagParseError::Io(e) => write!(f, "I/O error: {}", e),
+      }
+   }
+}
+
+impl Error for TagParseError {
+   fn source(&self) -> Option<&(dyn Error + 'static)> {
+      match self {
+         TagParseError::Io(e) => Some(e),
+         _ => None,
+      }
+   }
+}
+
 impl From<io::Error> for TagParseError {
    fn from(e: io::Error) -> TagParseError {
       TagParseError::Io(e)
    }
 }
 
+// So that metadata holding an error can be cloned. `io::Error` isn't `Clone`, so a copy keeps its kind and message.
+impl Clone for TagParseError {
+   fn clone(&self) -> TagParseError {
+      match self {
+         TagParseError::NoTag => TagParseError::NoTag,
+         TagParseError::TagTooSmall => TagParseError::TagTooSmall,
+         TagParseError::InvalidSize => TagParseError::InvalidSize,
+         TagParseError::UnsupportedVersion(x) => TagParseError::UnsupportedVersion(*x),
+         TagParseError::CrcMismatch { expected, actual } => TagParseError::CrcMismatch {
+            expected: *expected,
+            actual: *actual,
+         },
+         TagParseError::Io(e) => TagParseError::Io(io::Error::new(e.kind(), e.to_string())),
+      }
+   }
+}
+
+/// Something about a tag that's worth knowing, but didn't stop it from being read. The parsers return these (see
+/// `TagInfo::diagnostics`) rather than logging them, so they can be tied to the file they came from.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Diagnostic {
+   /// A revision of ID3v2.4 newer than the one we know, which might have data we miss
+   UnknownRevision(u8),
+   /// The tag is flagged as experimental, so it might not follow the spec
+   Experimental,
+   /// A SEEK frame points to this offset, but no tag could be read there, so any tags after it were missed
+   BadSeek(u64),
+   /// A footer says a tag was appended at this offset, but it couldn't be read
+   BadAppendedTag(u64),
+}
+
+impl fmt::Display for Diagnostic {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      match self {
+         Diagnostic::UnknownRevision(x) => write!(f, "unknown revision ({}); proceeding anyway but may miss data", x),
+         Diagnostic::Experimental => write!(f, "tag is marked as experimental; proceeding anyway but may miss data"),
+         Diagnostic::BadSeek(x) => write!(f, "no tag at offset {} that a SEEK frame points to; ignoring it", x),
+         Diagnostic::BadAppendedTag(x) => write!(f, "couldn't read the tag appended at offset {}; ignoring it", x),
+      }
+   }
+}
+
+/// What the headers say about a tag. Every parser has one (see `Parser::info`).
+#[derive(Clone, Debug, Default)]
+pub struct TagInfo {
+   /// Position of the tag header, relative to where the source started. Only nonzero when junk in front of the tag
+   /// was skipped (see `ParseOptions::search_window`). Pass it to `v24::Frame::byte_range` to find a frame in the file.
+   pub tag_start: u64,
+   /// Whether the frames match the CRC in the extended header, or `None` if the tag has no CRC. Checking the CRC means
+   /// reading the whole tag, so `parse_stream` leaves this `None` unless `verify_crc` was set.
+   pub crc_valid: Option<bool>,
+   /// The restrictions from the extended header, if the tag declares any
+   pub restrictions: Option<v24::TagRestrictions>,
+   /// Whether the tag is an update of an earlier tag in the file, rather than a replacement. See `Id3Tag::read_all`.
+   pub is_update: bool,
+   /// Things about the tag worth knowing that didn't stop it from being read
+   pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Iterates over the frames of a tag, decoding each. Like every parser (and the frames they return), it is `Send` and
+/// `Sync`, so tags can be parsed on one thread and used on another.
 pub struct Parser {
-   inner: Box<dyn Iterator<Item = Result<v24::Frame, v24::FrameParseError>>>,
+   inner: v24::Parser,
+   info: TagInfo,
+}
+
+impl Parser {
+   /// What the headers say about the tag
+   pub fn info(&self) -> &TagInfo {
+      &self.info
+   }
+
+   /// How many bytes of padding follow the frames, which is room the tag has to grow. Only known once every frame
+   /// has been read, and `None` until then, or if the frames are followed by junk rather than zeros.
+   pub fn padding(&self) -> Option<u32> {
+      self.inner.padding()
+   }
 }
 
 impl Iterator for Parser {
-   type Item = Result<v24::Frame, v24::FrameParseError>;
+   type Item = Result<v24::Frame<'static>, v24::FrameParseError>;
 
-   fn next(&mut self) -> Option<Result<v24::Frame, v24::FrameParseError>> {
+   fn next(&mut self) -> Option<Result<v24::Frame<'static>, v24::FrameParseError>> {
       self.inner.next()
    }
 }
 
-pub fn parse_source<S: Read + Seek>(source: &mut S) -> Result<Parser, TagParseError> {
-   let mut header: &mut [u8] = &mut [0u8; 10];
+/// Like `Parser`, but the frames borrow from the buffer given to `parse_slice`
+pub struct SliceParser<'a> {
+   inner: v24::SliceParser<'a>,
+   info: TagInfo,
+}
+
+impl<'a> SliceParser<'a> {
+   /// What the headers say about the tag
+   pub fn info(&self) -> &TagInfo {
+      &self.info
+   }
+
+   /// How many bytes of padding follow the frames (see `Parser::padding`)
+   pub fn padding(&self) -> Option<u32> {
+      self.inner.padding()
+   }
+}
+
+impl<'a> Iterator for SliceParser<'a> {
+   type Item = Result<v24::Frame<'a>, v24::FrameParseError>;
+
+   fn next(&mut self) -> Option<Result<v24::Frame<'a>, v24::FrameParseError>> {
+      self.inner.next()
+   }
+}
+
+/// Like `SliceParser`, but the frames borrow from the arena given to `parse_slice_in` as well. Unlike the other
+/// parsers it's neither `Send` nor `Sync`, because the arena isn't.
+#[cfg(feature = "arena")]
+pub struct ArenaParser<'a> {
+   inner: SliceParser<'a>,
+   arena: &'a bumpalo::Bump,
+}
+
+#[cfg(feature = "arena")]
+impl<'a> ArenaParser<'a> {
+   /// What the headers say about the tag
+   pub fn info(&self) -> &TagInfo {
+      &self.inner.info
+   }
+
+   /// How many bytes of padding follow the frames (see `Parser::padding`)
+   pub fn padding(&self) -> Option<u32> {
+      self.inner.padding()
+   }
+}
+
+#[cfg(feature = "arena")]
+impl<'a> Iterator for ArenaParser<'a> {
+   type Item = Result<v24::Frame<'a>, v24::FrameParseError>;
+
+   fn next(&mut self) -> Option<Result<v24::Frame<'a>, v24::FrameParseError>> {
+      self.inner.inner.next_in(self.arena)
+   }
+}
+
+/// Like `Parser`, but frames are read from the source one at a time.
+/// Frames whose payloads aren't read are skipped over by seeking, so large frames such as
+/// attached pictures never have to be held in memory.
+pub struct StreamingParser<S> {
+   inner: v24::StreamingParser<S>,
+   info: TagInfo,
+}
+
+impl<S: Read + Seek> StreamingParser<S> {
+   /// What the headers say about the tag
+   pub fn info(&self) -> &TagInfo {
+      &self.info
+   }
+
+   /// Like `Parser::padding`, but unless parsing strictly, the padding is skipped unread and taken to be zeros
+   pub fn padding(&self) -> Option<u32> {
+      self.inner.padding()
+   }
+
+   /// Reads the header of the next frame, skipping the payload of the previous frame if it wasn't read
+   pub fn next_header(&mut self) -> Option<Result<v24::FrameHeader, v24::FrameParseError>> {
+      self.inner.next_header()
+   }
+
+   /// Reads and decodes the payload of the frame whose header was returned last.
+   /// Returns `None` if there is no such frame, or if it was already read.
+   pub fn read_frame(&mut self) -> Option<Result<v24::Frame<'static>, v24::FrameParseError>> {
+      self.inner.read_frame()
+   }
+}
+
+impl<S: Read + Seek> Iterator for StreamingParser<S> {
+   type Item = Result<v24::Frame<'static>, v24::FrameParseError>;
+
+   fn next(&mut self) -> Option<Result<v24::Frame<'static>, v24::FrameParseError>> {
+      self.inner.next()
+   }
+}
+
+/// Iterates over the frames of a tag without decoding them
+pub struct RawParser<'a> {
+   inner: v24::RawParser<'a>,
+   info: TagInfo,
+}
+
+impl<'a> RawParser<'a> {
+   /// What the headers say about the tag
+   pub fn info(&self) -> &TagInfo {
+      &self.info
+   }
+
+   /// How many bytes of padding follow the frames (see `Parser::padding`)
+   pub fn padding(&self) -> Option<u32> {
+      self.inner.padding()
+   }
+}
+
+impl<'a> Iterator for RawParser<'a> {
+   type Item = Result<v24::RawFrame<'a>, v24::FrameParseError>;
+
+   fn next(&mut self) -> Option<Result<v24::RawFrame<'a>, v24::FrameParseError>> {
+      self.inner.next()
+   }
+}
+
+/// What reading a whole tag turned up that didn't stop it from being read (see `Id3Tag::read_with_report`)
+#[derive(Clone, Debug, Default)]
+pub struct ReadReport {
+   pub diagnostics: Vec<Diagnostic>,
+   /// The frames that failed to parse, which were left out of the tag
+   pub frame_errors: Vec<v24::FrameParseError>,
+}
+
+impl ReadReport {
+   pub fn is_empty(&self) -> bool {
+      self.diagnostics.is_empty() && self.frame_errors.is_empty()
+   }
+
+   // Collects the frames that parse, and records the rest
+   fn collect_frames<I: IntoIterator<Item = Result<v24::Frame<'static>, v24::FrameParseError>>>(
+      &mut self,
+      parsed: I,
+   ) -> Vec<v24::Frame<'static>> {
+      let mut frames = Vec::new();
+      for frame in parsed {
+         match frame {
+            Ok(frame) => frames.push(frame),
+            Err(e) => self.frame_errors.push(e),
+         }
+      }
+      frames
+   }
+}
+
+/// Every frame of a tag, collected so it can be queried through the `Tag` trait
+#[derive(Clone, Debug, Default)]
+pub struct Id3Tag {
+   pub frames: Vec<v24::Frame<'static>>,
+}
+
+impl Id3Tag {
+   /// Reads the whole tag. Frames that fail to parse are left out; use `read_with_report` to find out which.
+   ///
+   /// Unlike the parsers, this reads ID3v2.3 tags too, decoding their frames into v2.4 frames (IPLS becomes TIPL,
+   /// RVAD becomes RVA2, and EQUA becomes EQU2). The date frames (TYER, TDAT, and so on) are kept as `Unknown` frames,
+   /// which `convert::convert` can merge into TDRC.
+   pub fn read<S: Read>(source: &mut S, options: ParseOptions) -> Result<Id3Tag, TagParseError> {
+      Id3Tag::read_with_report(source, options).map(|x| x.0)
+   }
+
+   /// Reads the whole tag, along with the tag's diagnostics and the errors of the frames that were left out
+   pub fn read_with_report<S: Read>(
+      source: &mut S,
+      options: ParseOptions,
+   ) -> Result<(Id3Tag, ReadReport), TagParseError> {
+      let mut report = ReadReport::default();
+      let (frames, _) = read_whole_tag(source, options, &mut report)?;
+      Ok((Id3Tag { frames }, report))
+   }
+
+   /// Reads every tag in the file: the one at the start, any reached through SEEK frames, and one appended
+   /// to the end (found by its footer). A tag flagged as an update is merged into the tags before it
+   /// (see `apply_update`), and any other tag replaces them. Frames that fail to parse are left out; use
+   /// `read_all_with_report` to find out which. A SEEK frame or footer that leads nowhere is reported as a diagnostic,
+   /// and the tags read before it are kept.
+   pub fn read_all<S: Read + Seek>(source: &mut S, options: ParseOptions) -> Result<Id3Tag, TagParseError> {
+      Id3Tag::read_all_with_report(source, options).map(|x| x.0)
+   }
+
+   /// Like `read_all`, along with the diagnostics and frame errors of every tag that was read
+   pub fn read_all_with_report<S: Read + Seek>(
+      source: &mut S,
+      options: ParseOptions,
+   ) -> Result<(Id3Tag, ReadReport), TagParseError> {
+      let mut report = ReadReport::default();
+      let file_len = source.seek(SeekFrom::End(0))?;
+      let mut merged: Option<Id3Tag> = None;
+      let mut read_offsets = Vec::new();
+
+      let mut next_offset = Some(0);
+      while let Some(offset) = next_offset.take() {
+         let tag = match read_tag_at(source, offset, &options, &mut report) {
+            Ok(v) => v,
+            Err(TagParseError::NoTag) if offset == 0 => break,
+            Err(e) if offset == 0 => return Err(e),
+            // The tags read so far are still good, so the chain just ends here
+            Err(_) => {
+               report.diagnostics.push(Diagnostic::BadSeek(offset));
+               break;
+            }
+         };
+         read_offsets.push(offset);
+         // SEEK frames can only point forward, so this can't loop
+         next_offset = tag.seek.map(|x| tag.end + x).filter(|x| *x < file_len);
+         merge_tag(&mut merged, tag);
+      }
+
+      if let Some(offset) = find_appended_tag(source, file_len)? {
+         if !read_offsets.contains(&offset) {
+            match read_tag_at(source, offset, &options, &mut report) {
+               Ok(tag) => merge_tag(&mut merged, tag),
+               Err(_) => report.diagnostics.push(Diagnostic::BadAppendedTag(offset)),
+            }
+         }
+      }
+
+      merged.map(|x| (x, report)).ok_or(TagParseError::NoTag)
+   }
+
+   /// Merges a tag flagged as an update into this one. Frames that can only appear once (per description,
+   /// language, and so on) replace the ones already here, and the rest are added.
+   pub fn apply_update(&mut self, update: Id3Tag) {
+      for frame in update.frames {
+         if let Some(key) = unique_key(&frame.data) {
+            self.frames.retain(|x| unique_key(&x.data).as_ref() != Some(&key));
+         }
+         self.frames.push(frame);
+      }
+   }
+
+   fn find_map<'a, T, F: Fn(&'a v24::FrameData<'static>) -> Option<T>>(&'a self, f: F) -> Option<T> {
+      self.frames.iter().find_map(|frame| f(&frame.data))
+   }
+}
+
+struct FoundTag {
+   tag: Id3Tag,
+   is_update: bool,
+   // Where the tag ends, including any footer
+   end: u64,
+   // From a SEEK frame, relative to the end of the tag
+   seek: Option<u64>,
+}
+
+fn read_tag_at<S: Read + Seek>(
+   source: &mut S,
+   offset: u64,
+   options: &ParseOptions,
+   report: &mut ReadReport,
+) -> Result<FoundTag, TagParseError> {
+   let mut header = Vec::new();
+   source.seek(SeekFrom::Start(offset))?;
+   source.take(10).read_to_end(&mut header)?;
+   let len = tag_len(&header).ok_or(TagParseError::NoTag)?;
+   source.seek(SeekFrom::Start(offset))?;
+
+   let (frames, is_update) = read_whole_tag(source, options.clone(), report)?;
+   let seek = frames.iter().find_map(|x| match &x.data {
+      v24::FrameData::Unknown(u) if &u.name == b"SEEK" && u.data.len() >= 4 => {
+         Some(u64::from(BigEndian::read_u32(&u.data)))
+      }
+      _ => None,
+   });
+
+   Ok(FoundTag {
+      tag: Id3Tag { frames },
+      is_update,
+      end: offset + len,
+      seek,
+   })
+}
+
+// Reads the tag for `Id3Tag`, returning its frames and whether it's an update. The parsers only know v2.4 frames, so
+// v2.3 frames are read by `v23::read_frames` instead.
+fn read_whole_tag<S: Read>(
+   source: &mut S,
+   options: ParseOptions,
+   report: &mut ReadReport,
+) -> Result<(Vec<v24::Frame<'static>>, bool), TagParseError> {
+   let frame_area = read_headers(source, &options)?;
+   let frames = read_frames(source, frame_area.size)?;
+   report.diagnostics.extend_from_slice(&frame_area.info.diagnostics);
+   if frame_area.version == 3 {
+      let parsed = v23::read_frames(&frames, frame_area.offset, frame_area.unsynchronized, &options);
+      return Ok((report.collect_frames(parsed), false));
+   }
+
+   let is_update = frame_area.info.is_update;
+   let parser = new_parser(frames, frame_area, options)?;
+   Ok((report.collect_frames(parser), is_update))
+}
+
+// An appended tag ends with a footer, and may be followed by an ID3v1 tag
+fn find_appended_tag<S: Read + Seek>(source: &mut S, file_len: u64) -> Result<Option<u64>, TagParseError> {
+   let mut end = file_len;
+   if end >= 128 {
+      let mut marker = [0u8; 3];
+      source.seek(SeekFrom::Start(end - 128))?;
+      source.read_exact(&mut marker)?;
+      if &marker == b"TAG" {
+         end -= 128;
+      }
+   }
+   if end < 20 {
+      return Ok(None);
+   }
+
+   let mut footer = [0u8; 10];
+   source.seek(SeekFrom::Start(end - 10))?;
+   source.read_exact(&mut footer)?;
+   if &footer[0..3] != b"3DI" {
+      return Ok(None);
+   }
+   let size = u64::from(synchsafe_u32_to_u32(BigEndian::read_u32(&footer[6..10])));
+   Ok(end.checked_sub(20 + size))
+}
+
+fn merge_tag(merged: &mut Option<Id3Tag>, found: FoundTag) {
+   match merged {
+      Some(tag) if found.is_update => tag.apply_update(found.tag),
+      _ => *merged = Some(found.tag),
+   }
+}
+
+// What makes a frame unique within a tag, or `None` for frames that can repeat freely
+pub(crate) fn unique_key(data: &v24::FrameData) -> Option<([u8; 4], String)> {
+   let discriminator = match data {
+      v24::FrameData::TXXX(x) => x.description.to_string(),
+      v24::FrameData::COMM(x) | v24::FrameData::USLT(x) => {
+         format!("{}\0{}", String::from_utf8_lossy(&x.iso_639_2_lang), x.description)
+      }
+      v24::FrameData::PRIV(x) => x.owner.to_string(),
+      v24::FrameData::RVA2(x) => x.identification.to_string(),
+      // Several of these can exist as long as the URLs differ
+      v24::FrameData::WCOM(x) | v24::FrameData::WOAR(x) => x.to_string(),
+      // A text frame that failed to decode is still the only one of its kind
+      v24::FrameData::Unknown(x) if x.name[0] == b'T' && &x.name != b"TXXX" => String::new(),
+      v24::FrameData::Unknown(_) => return None,
+      _ => String::new(),
+   };
+   Some((data.name(), discriminator))
+}
+
+fn first_str<'a>(strs: &'a [Cow<'static, str>]) -> Option<&'a str> {
+   strs.first().map(|x| x.as_ref())
+}
+
+impl Tag for Id3Tag {
+   fn title(&self) -> Option<&str> {
+      self.find_map(|data| match data {
+         v24::FrameData::TIT2(x) => first_str(x),
+         _ => None,
+      })
+   }
+
+   fn artist(&self) -> Option<&str> {
+      self.find_map(|data| match data {
+         v24::FrameData::TPE1(x) => first_str(x),
+         _ => None,
+      })
+   }
+
+   fn album(&self) -> Option<&str> {
+      self.find_map(|data| match data {
+         v24::FrameData::TALB(x) => first_str(x),
+         _ => None,
+      })
+   }
+
+   fn album_artist(&self) -> Option<&str> {
+      self.find_map(|data| match data {
+         v24::FrameData::TPE2(x) => first_str(x),
+         _ => None,
+      })
+   }
+
+   fn genre(&self) -> Option<&str> {
+      self.find_map(|data| match data {
+         v24::FrameData::TCON(x) => first_str(x),
+         _ => None,
+      })
+   }
+
+   fn year(&self) -> Option<u16> {
+      self.find_map(|data| match data {
+         v24::FrameData::TDRC(x) => x.first().map(|date| date.year),
+         _ => None,
+      })
+   }
+
+   fn track_number(&self) -> Option<u32> {
+      self.find_map(|data| match data {
+         v24::FrameData::TRCK(x) => x.first().and_then(|track| u32::try_from(track.number).ok()),
+         _ => None,
+      })
+   }
+
+   fn track_total(&self) -> Option<u32> {
+      self.find_map(|data| match data {
+         v24::FrameData::TRCK(x) => x
+            .first()
+            .and_then(|track| track.max)
+            .and_then(|max| u32::try_from(max).ok()),
+         _ => None,
+      })
+   }
+
+   fn disc_number(&self) -> Option<u32> {
+      self.find_map(|data| match data {
+         v24::FrameData::TPOS(x) => x.first().and_then(|disc| u32::try_from(disc.number).ok()),
+         _ => None,
+      })
+   }
+
+   fn disc_total(&self) -> Option<u32> {
+      self.find_map(|data| match data {
+         v24::FrameData::TPOS(x) => x
+            .first()
+            .and_then(|disc| disc.max)
+            .and_then(|max| u32::try_from(max).ok()),
+         _ => None,
+      })
+   }
+
+   fn comment(&self) -> Option<&str> {
+      self.find_map(|data| match data {
+         v24::FrameData::COMM(x) => first_str(&x.text),
+         _ => None,
+      })
+   }
+
+   fn artist_values(&self) -> Vec<&str> {
+      self
+         .frames
+         .iter()
+         .flat_map(|frame| match &frame.data {
+            v24::FrameData::TPE1(x) => &x[..],
+            _ => &[],
+         })
+         .map(|x| x.as_ref())
+         .collect()
+   }
+
+   fn genre_values(&self) -> Vec<&str> {
+      self
+         .frames
+         .iter()
+         .flat_map(|frame| match &frame.data {
+            v24::FrameData::TCON(x) => &x[..],
+            _ => &[],
+         })
+         .map(|x| x.as_ref())
+         .collect()
+   }
+}
+
+/// Parses the tag held in an in-memory buffer, such as one received over the network
+pub fn parse_bytes(mut bytes: &[u8], options: ParseOptions) -> Result<Parser, TagParseError> {
+   parse_source(&mut bytes, options)
+}
+
+/// Parses the tag held in an in-memory buffer without copying it.
+/// Text and binary data are borrowed from `bytes` whenever the encoding allows it.
+pub fn parse_slice<'a>(bytes: &'a [u8], options: ParseOptions) -> Result<SliceParser<'a>, TagParseError> {
+   let (frames, frame_area) = split_frames(bytes, &options)?;
+   let crc_valid = check_crc(&frame_area, frames, &options)?;
+
+   Ok(SliceParser {
+      inner: v24::SliceParser::new(frames, frame_area.offset, frame_area.unsynchronized, options),
+      info: TagInfo {
+         crc_valid,
+         ..frame_area.info
+      },
+   })
+}
+
+/// Like `parse_slice`, but text that has to be converted to UTF-8 and payloads that have to be resynchronized are
+/// allocated in `arena` instead of on the heap, so the frames borrow from both. When scanning many files whose frames
+/// are thrown away once they've been looked at, resetting one arena between files is much cheaper than freeing each
+/// string. The lists that hold multiple values (such as the strings of a text frame) are still on the heap.
+#[cfg(feature = "arena")]
+pub fn parse_slice_in<'a>(
+   bytes: &'a [u8],
+   options: ParseOptions,
+   arena: &'a bumpalo::Bump,
+) -> Result<ArenaParser<'a>, TagParseError> {
+   Ok(ArenaParser {
+      inner: parse_slice(bytes, options)?,
+      arena,
+   })
+}
+
+/// A file mapped into memory by `parse_file_mmap`. Frames parsed from it borrow from the mapping.
+#[cfg(feature = "mmap")]
+pub struct MappedFile {
+   map: memmap2::Mmap,
+   options: ParseOptions,
+}
+
+#[cfg(feature = "mmap")]
+impl MappedFile {
+   /// Parses the tag without copying it, as `parse_slice` does. Only the pages that hold the tag are read from disk.
+   pub fn parser(&self) -> Result<SliceParser<'_>, TagParseError> {
+      parse_slice(&self.map, self.options.clone())
+   }
+
+   /// The whole file
+   pub fn bytes(&self) -> &[u8] {
+      &self.map
+   }
+}
+
+/// Maps the file into memory, to be parsed with `MappedFile::parser`. For big tags (mostly those with artwork) this
+/// saves copying the tag into a buffer of its own, and then copying every frame out of that.
+///
+/// # Safety
+///
+/// Nothing may change or truncate the file while the `MappedFile` is alive. Frames borrow text from the mapping that
+/// was checked to be UTF-8 when it was parsed, so a change underneath them is undefined behavior, and reading a
+/// mapping past the end of a truncated file raises SIGBUS. Use `parse_source` for files that may be written to.
+#[cfg(feature = "mmap")]
+pub unsafe fn parse_file_mmap<P: AsRef<std::path::Path>>(
+   path: P,
+   options: ParseOptions,
+) -> Result<MappedFile, TagParseError> {
+   let file = std::fs::File::open(path)?;
+   // Empty files can't be mapped
+   if file.metadata()?.len() == 0 {
+      return Err(TagParseError::Io(io::Error::from(io::ErrorKind::UnexpectedEof)));
+   }
+   let map = memmap2::Mmap::map(&file)?;
+   Ok(MappedFile { map, options })
+}
+
+/// Splits the tag held in an in-memory buffer into frames, without decoding them.
+/// Useful for copying or stripping frames, as decode errors in the payloads don't get in the way.
+/// `options.frame_filter` is ignored.
+pub fn parse_raw<'a>(bytes: &'a [u8], options: ParseOptions) -> Result<RawParser<'a>, TagParseError> {
+   let (frames, frame_area) = split_frames(bytes, &options)?;
+   let crc_valid = check_crc(&frame_area, frames, &options)?;
+
+   Ok(RawParser {
+      inner: v24::RawParser::new(frames, frame_area.offset, frame_area.unsynchronized, options),
+      info: TagInfo {
+         crc_valid,
+         ..frame_area.info
+      },
+   })
+}
+
+fn split_frames<'a>(mut bytes: &'a [u8], options: &ParseOptions) -> Result<(&'a [u8], FrameArea), TagParseError> {
+   let frame_area = read_v24_headers(&mut bytes, options)?;
+   match bytes.get(..frame_area.size as usize) {
+      Some(frames) => Ok((frames, frame_area)),
+      None => Err(TagParseError::Io(io::Error::from(io::ErrorKind::UnexpectedEof))),
+   }
+}
+
+pub fn parse_source<S: Read>(source: &mut S, options: ParseOptions) -> Result<Parser, TagParseError> {
+   let frame_area = read_v24_headers(source, &options)?;
+   let frames = read_frames(source, frame_area.size)?;
+   new_parser(frames, frame_area, options)
+}
+
+fn new_parser(frames: Box<[u8]>, frame_area: FrameArea, options: ParseOptions) -> Result<Parser, TagParseError> {
+   let crc_valid = check_crc(&frame_area, &frames, &options)?;
+
+   Ok(Parser {
+      inner: v24::Parser::new(frames, frame_area.offset, frame_area.unsynchronized, options),
+      info: TagInfo {
+         crc_valid,
+         ..frame_area.info
+      },
+   })
+}
+
+/// Like `parse_source`, for sources that can't be read without blocking, e.g. a `futures::io::AllowStdIo` or a
+/// Tokio stream through `tokio_util::compat`. The tag is read into memory and then decoded as `parse_source` would,
+/// and the source is left just after the tag.
+#[cfg(feature = "async")]
+pub async fn parse_source_async<S: AsyncRead + AsyncSeek + Unpin>(
+   source: &mut S,
+   options: ParseOptions,
+) -> Result<Parser, TagParseError> {
+   let mut bytes = vec![0u8; 10];
+   source.read_exact(&mut bytes).await?;
+
+   // The same search as `find_header`, over the whole window at once
+   let start = if &bytes[0..3] == b"ID3" {
+      0
+   } else {
+      (&mut *source)
+         .take(u64::from(options.search_window))
+         .read_to_end(&mut bytes)
+         .await?;
+      (1..bytes.len().saturating_sub(9))
+         .find(|&i| <&[u8; 10]>::try_from(&bytes[i..i + 10]).map_or(false, is_plausible_header))
+         .ok_or(TagParseError::NoTag)?
+   };
+
+   let len = tag_len(&bytes[start..]).expect("the tag starts with a header") as usize;
+   let read = bytes.len() - start;
+   if read < len {
+      (&mut *source).take((len - read) as u64).read_to_end(&mut bytes).await?;
+   } else {
+      // We read past the end of the tag while searching for it
+      source.seek(SeekFrom::Current(-((read - len) as i64))).await?;
+      bytes.truncate(start + len);
+   }
+   let mut parser = parse_source(&mut &bytes[start..], options)?;
+   parser.info.tag_start = start as u64;
+   Ok(parser)
+}
+
+/// Parses a tag without reading the whole tag into memory up front. See `StreamingParser`.
+pub fn parse_stream<S: Read + Seek>(mut source: S, options: ParseOptions) -> Result<StreamingParser<S>, TagParseError> {
+   let frame_area = read_v24_headers(&mut source, &options)?;
+
+   let crc_valid = if options.verify_crc && frame_area.crc.is_some() {
+      let frames = read_frames(&mut source, frame_area.size)?;
+      source.seek(SeekFrom::Current(-(frames.len() as i64)))?;
+      check_crc(&frame_area, &frames, &options)?
+   } else {
+      None
+   };
+
+   Ok(StreamingParser {
+      inner: v24::StreamingParser::new(
+         source,
+         frame_area.size,
+         frame_area.offset,
+         frame_area.unsynchronized,
+         options,
+      ),
+      info: TagInfo {
+         crc_valid,
+         ..frame_area.info
+      },
+   })
+}
+
+/// Returns the size of the ID3v2 tag starting with the given header, including the header and footer.
+/// Returns `None` if the bytes don't start with a tag header.
+pub fn tag_len(header: &[u8]) -> Option<u64> {
+   if header.len() < 10 || &header[0..3] != b"ID3" {
+      return None;
+   }
+   let size = u64::from(synchsafe_u32_to_u32(BigEndian::read_u32(&header[6..10])));
+   let footer_size =
+      if header[3] == 4 && v24::TagFlags::from_bits_truncate(header[5]).contains(v24::TagFlags::FOOTER_PRESENT) {
+         10
+      } else {
+         0
+      };
+   Some(10 + size + footer_size)
+}
+
+// Everything we need to know about the frames that follow the header(s)
+struct FrameArea {
+   // 3 or 4, as v2.2 tags are only read by `v22::read_tag`
+   version: u8,
+   // Relative to the start of the tag header
+   offset: u32,
+   size: u32,
+   unsynchronized: bool,
+   // From the extended header
+   crc: Option<u32>,
+   // Everything but `crc_valid`, which is only known once the frames are read
+   info: TagInfo,
+}
+
+// The CRC covers the frames and padding, after undoing unsynchronization
+fn check_crc(frame_area: &FrameArea, frames: &[u8], options: &ParseOptions) -> Result<Option<bool>, TagParseError> {
+   let expected = match frame_area.crc {
+      Some(v) => v,
+      None => return Ok(None),
+   };
+   let actual = if frame_area.unsynchronized {
+      crc32(&resynchronize(frames))
+   } else {
+      crc32(frames)
+   };
+
+   if actual != expected && options.verify_crc {
+      return Err(TagParseError::CrcMismatch { expected, actual });
+   }
+   Ok(Some(actual == expected))
+}
+
+fn read_headers<S: Read>(source: &mut S, options: &ParseOptions) -> Result<FrameArea, TagParseError> {
+   let mut header = [0u8; 10];
    source.read_exact(&mut header)?;
 
-   // TODO: search for ID3 from top of file
-   let header = if &header[0..3] == b"ID3" {
-      parse_header(&header[3..])
+   let tag_start = if &header[0..3] != b"ID3" {
+      find_header(source, &mut header, options.search_window)?
    } else {
-      // TODO: search for 3DI from bottom of file
-      Err(TagParseError::NoTag)
-   }?;
+      0
+   };
+   let header = parse_header(&header[3..], options)?;
 
    let mut size_of_frames = header.size;
+   let mut frames_offset = 10;
+   let mut crc = None;
+   let mut restrictions = None;
+   let mut is_update = false;
+   let mut diagnostics = Vec::new();
 
    match header.flags {
       TagFlags::V24(flags) => {
          if header.revision > 0 {
-            warn!(
-               "Unknown revision ({}); proceeding anyway but may miss data",
-               header.revision
-            );
-         }
-
-         if flags.contains(v24::TagFlags::UNSYNCHRONIZED) {
-            unimplemented!();
+            diagnostics.push(Diagnostic::UnknownRevision(header.revision));
          }
 
          // TODO: for performance, we might be able to get away with wrapping sub
          // because we have to do bound checks later anyway
          if flags.contains(v24::TagFlags::EXTENDED_HEADER) {
-            let eh_size = synchsafe_u32_to_u32(source.read_u32::<BigEndian>()?);
+            let eh_size = read_synchsafe_size(source.read_u32::<BigEndian>()?, options)?;
 
             if eh_size < 6 {
                return Err(TagParseError::TagTooSmall);
             }
 
             size_of_frames = size_of_frames.saturating_sub(eh_size);
+            frames_offset += eh_size;
             // we have to make sure to sub 4, as eh_size includes itself
             let mut eh_bytes = vec![0u8; (eh_size - 4) as usize].into_boxed_slice();
             source.read_exact(&mut eh_bytes)?;
             // eh_bytes[0] is always (supposed to be) set to 1
-            let _eh_flags = v24::ExtendedHeaderFlags::from_bits_truncate(eh_bytes[1]);
+            let eh_flags = v24::ExtendedHeaderFlags::from_bits_truncate(eh_bytes[1]);
+
+            // Each flag that is set has its data in order, prefixed by the length of that data
+            let mut flag_data = &eh_bytes[2..];
+            if eh_flags.contains(v24::ExtendedHeaderFlags::TAG_IS_UPDATE) {
+               is_update = true;
+               take_flag_data(&mut flag_data);
+            }
+            if eh_flags.contains(v24::ExtendedHeaderFlags::CRC_DATA_PRESENT) {
+               crc = take_flag_data(&mut flag_data)
+                  .filter(|x| x.len() == 5)
+                  .map(|x| synchsafe_u40_to_u32(BigEndian::read_uint(x, 5)));
+            }
+            if eh_flags.contains(v24::ExtendedHeaderFlags::TAG_RESTRICTIONS) {
+               restrictions = take_flag_data(&mut flag_data)
+                  .and_then(|x| x.first())
+                  .map(|x| v24::TagRestrictions::from(*x));
+            }
          }
 
          if flags.contains(v24::TagFlags::EXPERIMENTAL_INDICATOR) {
-            warn!("Tag is marked as experimental; proceeding anyway but may miss data");
+            diagnostics.push(Diagnostic::Experimental);
          }
 
-         if flags.contains(v24::TagFlags::FOOTER_PRESENT) {
-            unimplemented!();
+         // The footer is a copy of the header placed after the frames, so there is nothing for us to read there
+
+         Ok(FrameArea {
+            version: 4,
+            offset: frames_offset,
+            size: size_of_frames,
+            unsynchronized: flags.contains(v24::TagFlags::UNSYNCHRONIZED),
+            crc,
+            info: TagInfo {
+               tag_start,
+               crc_valid: None,
+               restrictions,
+               is_update,
+               diagnostics,
+            },
+         })
+      }
+      TagFlags::V23(flags) => {
+         // The extended header's size is a plain integer that leaves itself out. All it can hold that we'd use is a
+         // CRC, and as that leaves out the padding (whose size is also in there), it isn't checked.
+         if flags.contains(v23::TagFlags::EXTENDED_HEADER) {
+            let eh_size = source.read_u32::<BigEndian>()?;
+            if eh_size < 6 {
+               return Err(TagParseError::TagTooSmall);
+            }
+            io::copy(&mut source.take(u64::from(eh_size)), &mut io::sink())?;
+            size_of_frames = size_of_frames.saturating_sub(eh_size.saturating_add(4));
+            frames_offset = frames_offset.saturating_add(eh_size.saturating_add(4));
          }
 
-         let mut frames = vec![0u8; size_of_frames as usize].into_boxed_slice();
-         source.read_exact(&mut frames)?;
+         if flags.contains(v23::TagFlags::EXPERIMENTAL_INDICATOR) {
+            diagnostics.push(Diagnostic::Experimental);
+         }
 
-         Ok(Parser {
-            inner: Box::new(v24::Parser::new(frames)),
+         Ok(FrameArea {
+            version: 3,
+            offset: frames_offset,
+            size: size_of_frames,
+            unsynchronized: flags.contains(v23::TagFlags::UNSYNCHRONIZED),
+            crc: None,
+            info: TagInfo {
+               tag_start,
+               diagnostics,
+               ..TagInfo::default()
+            },
          })
       }
-      TagFlags::V23(_flags) => Err(TagParseError::UnsupportedVersion(3)),
       TagFlags::V22(_flags) => Err(TagParseError::UnsupportedVersion(2)),
    }
 }
 
+// The parsers only decode v2.4 frames. v2.3 tags are left to `Id3Tag::read`.
+fn read_v24_headers<S: Read>(source: &mut S, options: &ParseOptions) -> Result<FrameArea, TagParseError> {
+   let frame_area = read_headers(source, options)?;
+   if frame_area.version != 4 {
+      return Err(TagParseError::UnsupportedVersion(frame_area.version));
+   }
+   Ok(frame_area)
+}
+
+// Slides the header along until it holds something that looks like a tag header, so that junk
+// (or the remains of a broken tag) in front of the tag is skipped. Returns how many bytes were skipped.
+fn find_header<S: Read>(source: &mut S, header: &mut [u8; 10], window: u32) -> Result<u64, TagParseError> {
+   for skipped in 1..=u64::from(window) {
+      header.rotate_left(1);
+      match source.read_exact(&mut header[9..]) {
+         Ok(()) => (),
+         Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+         Err(e) => return Err(e.into()),
+      }
+      if is_plausible_header(header) {
+         return Ok(skipped);
+      }
+   }
+   Err(TagParseError::NoTag)
+}
+
+// Unknown versions and flags are rejected here, because "ID3" can show up by chance
+fn is_plausible_header(header: &[u8; 10]) -> bool {
+   let unused_flags = match header[3] {
+      2 => 0b0011_1111,
+      3 => 0b0001_1111,
+      4 => 0b0000_1111,
+      _ => return false,
+   };
+   &header[0..3] == b"ID3"
+      && header[4] != 0xff
+      && header[5] & unused_flags == 0
+      && is_synchsafe(BigEndian::read_u32(&header[6..10]))
+}
+
+fn take_flag_data<'a>(rest: &mut &'a [u8]) -> Option<&'a [u8]> {
+   let len = *rest.first()? as usize;
+   let data = rest.get(1..1 + len)?;
+   *rest = &rest[1 + len..];
+   Some(data)
+}
+
+// We don't allocate the whole claimed size up front, as a corrupt header can claim up to 256MB
+fn read_frames<S: Read>(source: &mut S, size_of_frames: u32) -> Result<Box<[u8]>, TagParseError> {
+   let mut frames = Vec::new();
+   source.take(u64::from(size_of_frames)).read_to_end(&mut frames)?;
+   if frames.len() < size_of_frames as usize {
+      return Err(TagParseError::Io(io::Error::from(io::ErrorKind::UnexpectedEof)));
+   }
+   Ok(frames.into_boxed_slice())
+}
+
 struct Header {
    flags: TagFlags,
    revision: u8,
    size: u32,
 }
 
-fn parse_header(header: &[u8]) -> Result<Header, TagParseError> {
+fn parse_header(header: &[u8], options: &ParseOptions) -> Result<Header, TagParseError> {
    let major_version = header[0];
    let revision = header[1];
    let raw_flags = header[2];
@@ -123,37 +1116,924 @@ fn parse_header(header: &[u8]) -> Result<Header, TagParseError> {
    Ok(Header {
       flags,
       revision,
-      size: synchsafe_u32_to_u32(BigEndian::read_u32(&header[3..7])),
+      size: read_synchsafe_size(BigEndian::read_u32(&header[3..7]), options)?,
    })
 }
 
-fn synchsafe_u32_to_u32(sync_int: u32) -> u32 {
-   let low = (sync_int & 0x00_00_00_ff) | (sync_int & 0x00_00_01_00) >> 1;
-   let mid_low = (sync_int & 0x00_00_fe_00) >> 1 | (sync_int & 0x00_03_00_00) >> 2;
-   let mid_high = (sync_int & 0x00_fc_00_00) >> 2 | (sync_int & 0x07_00_00_00) >> 3;
-   let high = (sync_int & 0xf8_00_00_00) >> 3;
-   high | mid_high | mid_low | low
+fn read_synchsafe_size(sync_int: u32, options: &ParseOptions) -> Result<u32, TagParseError> {
+   if options.strictness == Strictness::Strict && !is_synchsafe(sync_int) {
+      return Err(TagParseError::InvalidSize);
+   }
+   Ok(synchsafe_u32_to_u32(sync_int))
 }
 
-/*
-Potentially need this for CRC in the future
+/// Reverses the unsynchronization scheme, which inserts a 0x00 after every 0xFF
+fn resynchronize(bytes: &[u8]) -> Vec<u8> {
+   let mut output = Vec::with_capacity(bytes.len());
+   output.extend(resynchronized_bytes(bytes));
+   output
+}
+
+// The bytes without the 0x00 inserted after each 0xFF
+fn resynchronized_bytes<'a>(bytes: &'a [u8]) -> impl Iterator<Item = u8> + 'a {
+   let mut last_byte_was_ff = false;
+   bytes.iter().cloned().filter(move |byte| {
+      let keep = !(last_byte_was_ff && *byte == 0x00);
+      last_byte_was_ff = *byte == 0xFF;
+      keep
+   })
+}
 
-fn synchsafe_u40_to_u32(sync_int: u64) -> u32 {
-   let low = (sync_int & 0x00_00_00_ff) | (sync_int & 0x00_00_01_00) >> 1;
-   let mid_low = (sync_int & 0x00_00_fe_00) >> 1 | (sync_int & 0x00_03_00_00) >> 2;
-   let mid_high = (sync_int & 0x00_fc_00_00) >> 2 | (sync_int & 0x07_00_00_00) >> 3;
-   let high = (sync_int & 0xf8_00_00_00) >> 3 | (sync_int & 0x0f_00_00_00_0) >> 4;
-   let highest = (sync_int & 0xf0_00_00_00_00) >> 4;
-   (highest | high | mid_high | mid_low | low) as u32
-} */
+/// The CRC-32 used by ID3v2 (the same one as zlib and PNG)
+fn crc32(bytes: &[u8]) -> u32 {
+   let mut crc = !0u32;
+   for byte in bytes.iter() {
+      crc ^= u32::from(*byte);
+      for _ in 0..8 {
+         crc = if crc & 1 == 1 {
+            crc >> 1 ^ 0xed_b8_83_20
+         } else {
+            crc >> 1
+         };
+      }
+   }
+   !crc
+}
 
 mod test {
    #[cfg(test)]
    use super::*;
 
+   // Fails to compile, rather than to run, if a parser or a frame can't be sent to or shared with another thread
+   #[test]
+   fn send_and_sync() {
+      fn assert_send_sync<T: Send + Sync>() {}
+      assert_send_sync::<Parser>();
+      assert_send_sync::<SliceParser<'static>>();
+      assert_send_sync::<RawParser<'static>>();
+      assert_send_sync::<StreamingParser<std::io::Cursor<Vec<u8>>>>();
+      assert_send_sync::<v24::Frame<'static>>();
+      assert_send_sync::<v24::RawFrame<'static>>();
+      assert_send_sync::<v24::FrameParseError>();
+      assert_send_sync::<Id3Tag>();
+      assert_send_sync::<TagParseError>();
+      assert_send_sync::<crate::probe::Metadata>();
+      assert_send_sync::<crate::probe::ProbeError>();
+   }
+
    #[test]
    fn synchsafe_conversions() {
       assert_eq!(synchsafe_u32_to_u32(0x7f_7f_7f_7f), 0x0f_ff_ff_ff);
-      //assert_eq!(synchsafe_u40_to_u32(0x7f_7f_7f_7f_7f), 0xff_ff_ff_ff);
+      assert_eq!(synchsafe_u40_to_u32(0x7f_7f_7f_7f_7f), 0xff_ff_ff_ff);
+   }
+
+   #[test]
+   fn resynchronization() {
+      assert_eq!(
+         resynchronize(&[0xff, 0x00, 0xe0, 0xff, 0x00, 0x00]),
+         [0xff, 0xe0, 0xff, 0x00]
+      );
+   }
+
+   #[test]
+   fn borrowed_text() {
+      let tag = b"ID3\x04\x00\x00\x00\x00\x00\x10TIT2\x00\x00\x00\x06\x00\x00\x03Title";
+      let frame = parse_slice(tag, ParseOptions::default())
+         .unwrap()
+         .next()
+         .unwrap()
+         .unwrap();
+      match frame.data {
+         v24::FrameData::TIT2(titles) => match titles[0] {
+            std::borrow::Cow::Borrowed(title) => assert_eq!(title, "Title"),
+            _ => panic!("expected the title to be borrowed"),
+         },
+         _ => panic!("expected a TIT2 frame"),
+      }
+   }
+
+   #[test]
+   fn tag_after_junk() {
+      let mut file = Vec::from(&b"junk ID3\x09 more junk"[..]);
+      file.extend_from_slice(b"ID3\x04\x00\x00\x00\x00\x00\x10TIT2\x00\x00\x00\x06\x00\x00\x03Title");
+
+      match parse_slice(&file, ParseOptions::default()) {
+         Err(TagParseError::NoTag) => (),
+         _ => panic!("expected no tag without a search window"),
+      }
+      let options = ParseOptions {
+         search_window: 64,
+         ..ParseOptions::default()
+      };
+      let parser = parse_slice(&file, options.clone()).unwrap();
+      assert_eq!(parser.info().tag_start, 19);
+      let frames: Vec<_> = parser.map(Result::unwrap).collect();
+      assert_eq!(frames[0].data.name(), *b"TIT2");
+      assert_eq!(frames[0].byte_range(19), 29..45);
+      assert_eq!(parse_bytes(&file, options.clone()).unwrap().count(), 1);
+
+      let too_far = ParseOptions {
+         search_window: 8,
+         ..ParseOptions::default()
+      };
+      assert!(parse_slice(&file, too_far).is_err());
+
+      #[cfg(feature = "mmap")]
+      {
+         let path = std::env::temp_dir().join("walnut_tag_after_junk.mp3");
+         std::fs::write(&path, &file).unwrap();
+         // Nothing else has the file
+         let mapped = unsafe { parse_file_mmap(&path, options.clone()) }.unwrap();
+         let titles: Vec<_> = mapped.parser().unwrap().map(Result::unwrap).collect();
+         assert_eq!(titles[0].data.name(), *b"TIT2");
+         std::fs::remove_file(&path).unwrap();
+      }
+
+      #[cfg(feature = "async")]
+      {
+         file.extend_from_slice(b"audio");
+         let mut source = futures::io::Cursor::new(&file[..]);
+         let parser = futures::executor::block_on(parse_source_async(&mut source, options)).unwrap();
+         assert_eq!(parser.count(), 1);
+         assert_eq!(source.position(), file.len() as u64 - 5);
+      }
+   }
+
+   #[test]
+   fn windows_1252_text() {
+      let tag = b"ID3\x04\x00\x00\x00\x00\x00\x10TIT2\x00\x00\x00\x06\x00\x00\x00\x93Hi\x94\x80";
+      let title = |options| match parse_slice(tag, options).unwrap().next().unwrap().unwrap().data {
+         v24::FrameData::TIT2(titles) => titles[0].to_string(),
+         _ => panic!("expected a TIT2 frame"),
+      };
+      assert_eq!(title(ParseOptions::default()), "\u{201c}Hi\u{201d}\u{20ac}");
+      let iso = ParseOptions {
+         latin1: Latin1Decoding::Iso8859_1,
+         ..ParseOptions::default()
+      };
+      assert_eq!(title(iso), "\u{93}Hi\u{94}\u{80}");
+   }
+
+   #[cfg(feature = "arena")]
+   #[test]
+   fn arena() {
+      // An unsynchronized frame, with "\u{93}Hi\u{94}\u{ff}" in Windows-1252
+      let tag = b"ID3\x04\x00\x00\x00\x00\x00\x11TIT2\x00\x00\x00\x07\x00\x02\x00\x93Hi\x94\xff\x00";
+      let arena = bumpalo::Bump::new();
+      match parse_slice_in(tag, ParseOptions::default(), &arena)
+         .unwrap()
+         .next()
+         .unwrap()
+         .unwrap()
+         .data
+      {
+         v24::FrameData::TIT2(titles) => match &titles[0] {
+            Cow::Borrowed(title) => assert_eq!(*title, "\u{201c}Hi\u{201d}\u{ff}"),
+            Cow::Owned(_) => panic!("expected the title to be in the arena"),
+         },
+         _ => panic!("expected a TIT2 frame"),
+      }
+      assert!(arena.allocated_bytes() > 0);
+   }
+
+   #[cfg(test)]
+   fn utf16_titles(encoding: u8, text: &[u8]) -> Result<Vec<String>, v24::FrameParseError> {
+      let frame_size = text.len() as u8 + 1;
+      let mut tag = Vec::from(&b"ID3\x04\x00\x00\x00\x00\x00"[..]);
+      tag.push(frame_size + 10);
+      tag.extend_from_slice(b"TIT2\x00\x00\x00");
+      tag.extend_from_slice(&[frame_size, 0, 0, encoding]);
+      tag.extend_from_slice(text);
+      let frame = parse_slice(&tag, ParseOptions::default()).unwrap().next().unwrap()?;
+      match frame.data {
+         v24::FrameData::TIT2(titles) => Ok(titles.into_iter().map(|x| x.into_owned()).collect()),
+         _ => panic!("expected a TIT2 frame"),
+      }
+   }
+
+   #[test]
+   fn utf16_text() {
+      let text = "caf\u{e9} \u{1f3b5}";
+      let le: Vec<u8> = text.encode_utf16().flat_map(|x| x.to_le_bytes().to_vec()).collect();
+      let be: Vec<u8> = text.encode_utf16().flat_map(|x| x.to_be_bytes().to_vec()).collect();
+
+      let mut with_boms = vec![0xff, 0xfe];
+      with_boms.extend_from_slice(&le);
+      with_boms.extend_from_slice(&[0, 0, 0xfe, 0xff]);
+      with_boms.extend_from_slice(&be);
+      assert_eq!(utf16_titles(1, &with_boms).unwrap(), vec![text, text]);
+
+      // No BOM means big endian
+      assert_eq!(utf16_titles(1, &be).unwrap(), vec![text]);
+      assert_eq!(utf16_titles(2, &be).unwrap(), vec![text]);
+      assert_eq!(utf16_titles(1, &[0xff, 0xfe]).unwrap(), vec![""]);
+
+      // A lone surrogate
+      assert!(utf16_titles(2, &[0xd8, 0x3c, 0x00, 0x41]).is_err());
+      assert!(utf16_titles(2, &be[1..]).is_err());
+   }
+
+   #[test]
+   fn long_text() {
+      // Long enough that the ASCII and terminators are searched for a word at a time
+      let text = "Caf\u{e9} del Mar \u{2013} Volumen Cinco \u{201c}Chill Out\u{201d}";
+      let latin1: Vec<u8> = text
+         .chars()
+         .map(|c| match c {
+            '\u{2013}' => 0x96,
+            '\u{201c}' => 0x93,
+            '\u{201d}' => 0x94,
+            c => c as u8,
+         })
+         .collect();
+      let mut tag = Vec::from(&b"ID3\x04\x00\x00\x00\x00\x00"[..]);
+      tag.push(latin1.len() as u8 + 11);
+      tag.extend_from_slice(b"TIT2\x00\x00\x00");
+      tag.extend_from_slice(&[latin1.len() as u8 + 1, 0, 0, 0]);
+      tag.extend_from_slice(&latin1);
+      match parse_slice(&tag, ParseOptions::default())
+         .unwrap()
+         .next()
+         .unwrap()
+         .unwrap()
+         .data
+      {
+         v24::FrameData::TIT2(titles) => assert_eq!(&titles[..], [text]),
+         _ => panic!("expected a TIT2 frame"),
+      }
+
+      // Two nulls that straddle code units don't end the text
+      let units = [0x41, 0x4100, 0x42, 0x43, 0x44, 0x45, 0, 0x46];
+      let be: Vec<u8> = units.iter().flat_map(|x: &u16| x.to_be_bytes().to_vec()).collect();
+      assert_eq!(utf16_titles(2, &be).unwrap(), vec!["A\u{4100}BCDE", "F"]);
+   }
+
+   #[test]
+   fn lossy_text() {
+      let tag = b"ID3\x04\x00\x00\x00\x00\x00\x0eTIT2\x00\x00\x00\x04\x00\x00\x03a\xffb";
+      assert!(parse_slice(tag, ParseOptions::default())
+         .unwrap()
+         .next()
+         .unwrap()
+         .is_err());
+
+      let lossy = ParseOptions {
+         lossy_text: true,
+         ..ParseOptions::default()
+      };
+      match parse_slice(tag, lossy).unwrap().next().unwrap().unwrap().data {
+         v24::FrameData::TIT2(titles) => assert_eq!(&titles[..], ["a\u{fffd}b"]),
+         _ => panic!("expected a TIT2 frame"),
+      }
+
+      let raw = parse_raw(tag, ParseOptions::default())
+         .unwrap()
+         .next()
+         .unwrap()
+         .unwrap();
+      assert_eq!(&*raw.raw_bytes(), b"\x03a\xffb");
+   }
+
+   #[cfg(feature = "charset-detection")]
+   #[test]
+   fn detected_charset() {
+      // "日本語のタイトル" in Shift-JIS
+      let title = b"\x93\xfa\x96\x7b\x8c\xea\x82\xcc\x83\x5e\x83\x43\x83\x67\x83\x8b";
+      let mut tag = Vec::from(&b"ID3\x04\x00\x00\x00\x00\x00"[..]);
+      tag.push(10 + 1 + title.len() as u8);
+      tag.extend_from_slice(b"TIT2\x00\x00\x00");
+      tag.extend_from_slice(&[1 + title.len() as u8, 0, 0, 0]);
+      tag.extend_from_slice(title);
+
+      let detect = ParseOptions {
+         latin1: Latin1Decoding::Detect,
+         ..ParseOptions::default()
+      };
+      let frame = parse_slice(&tag, detect).unwrap().next().unwrap().unwrap();
+      match frame.data {
+         v24::FrameData::TIT2(titles) => assert_eq!(
+            &titles[..],
+            ["\u{65e5}\u{672c}\u{8a9e}\u{306e}\u{30bf}\u{30a4}\u{30c8}\u{30eb}"]
+         ),
+         _ => panic!("expected a TIT2 frame"),
+      }
+   }
+
+   #[test]
+   fn copyright_text() {
+      let copyrights = |text: &str, strictness| {
+         let mut tag = Vec::from(&b"ID3\x04\x00\x00\x00\x00\x00"[..]);
+         tag.push(10 + 1 + text.len() as u8);
+         tag.extend_from_slice(b"TCOP\x00\x00\x00");
+         tag.extend_from_slice(&[1 + text.len() as u8, 0, 0, 3]);
+         tag.extend_from_slice(text.as_bytes());
+         let options = ParseOptions {
+            strictness,
+            ..ParseOptions::default()
+         };
+         let frame = parse_slice(&tag, options).unwrap().next().unwrap();
+         frame.map(|x| match x.data {
+            v24::FrameData::TCOP(x) => x
+               .into_iter()
+               .map(|x| (x.year, x.message.into_owned()))
+               .collect::<Vec<_>>(),
+            _ => panic!("expected a TCOP frame"),
+         })
+      };
+
+      assert_eq!(
+         copyrights("2001 \u{dc}n\u{ef}c\u{f8}de", Strictness::Strict).unwrap(),
+         [(2001, "\u{dc}n\u{ef}c\u{f8}de".to_owned())]
+      );
+      assert_eq!(copyrights("1999", Strictness::Strict).unwrap(), [(1999, String::new())]);
+      assert_eq!(
+         copyrights("1999Label", Strictness::Lenient).unwrap(),
+         [(1999, "Label".to_owned())]
+      );
+      assert!(copyrights("1999Label", Strictness::Strict).is_err());
+      assert!(copyrights("\u{a9}1999 Label", Strictness::Lenient).is_err());
+      assert!(copyrights("+999 Label", Strictness::Lenient).is_err());
+   }
+
+   #[test]
+   fn date_order_and_validation() {
+      let date = |x: &str| x.parse::<v24::Date>();
+      assert!(date("1996").unwrap() < date("1996-01").unwrap());
+      assert!(date("1996-01-31T23:59").unwrap() < date("1997").unwrap());
+      assert_eq!(date("2000-02-29T08:05").unwrap().to_string(), "2000-02-29T08:05");
+      assert!(date("1999-02-29").is_err());
+      assert!(date("1999-99").is_err());
+      assert!(date("1999-01-01T24").is_err());
+   }
+
+   #[test]
+   fn display_round_trips() {
+      for text in ["3", "3/12"].iter() {
+         assert_eq!(text.parse::<v24::Track>().unwrap().to_string(), *text);
+      }
+      for text in ["1999", "2001 \u{dc}ber Records"].iter() {
+         assert_eq!(text.parse::<v24::Copyright>().unwrap().to_string(), *text);
+      }
+      assert!("1999Label".parse::<v24::Copyright>().is_err());
+
+      let rvrb = b"\x01\x00\x00\x20\x01\x02\x03\x04\x05\x06\x07\x08";
+      let mut tag = Vec::from(&b"ID3\x04\x00\x00\x00\x00\x00\x16RVRB\x00\x00\x00\x0c\x00\x00"[..]);
+      tag.extend_from_slice(rvrb);
+      let frame = parse_slice(&tag, ParseOptions::default())
+         .unwrap()
+         .next()
+         .unwrap()
+         .unwrap();
+      match frame.data {
+         v24::FrameData::RVRB(reverb) => assert_eq!(&reverb.to_bytes(), rvrb),
+         _ => panic!("expected a RVRB frame"),
+      }
+   }
+
+   #[test]
+   fn frame_constructors() {
+      match v24::FrameData::title("Title") {
+         v24::FrameData::TIT2(x) => assert_eq!(&x[..], ["Title"]),
+         _ => panic!("expected a TIT2 frame"),
+      }
+      match v24::FrameData::track(3, Some(12)).unwrap() {
+         v24::FrameData::TRCK(x) => assert_eq!(x[0].to_string(), "3/12"),
+         _ => panic!("expected a TRCK frame"),
+      }
+      assert!(v24::FrameData::comment("eng", "", "Text").is_ok());
+
+      assert_eq!(
+         v24::FrameData::track(13, Some(12)).unwrap_err(),
+         v24::FrameValueError::InvalidPosition
+      );
+      assert_eq!(
+         v24::FrameData::disc(0, None).unwrap_err(),
+         v24::FrameValueError::InvalidPosition
+      );
+      assert_eq!(
+         v24::FrameData::comment("English", "", "").unwrap_err(),
+         v24::FrameValueError::InvalidLanguage
+      );
+      assert_eq!(
+         v24::FrameData::copyright(12345, "").unwrap_err(),
+         v24::FrameValueError::InvalidDate
+      );
+      let bad_date = v24::Date {
+         year: 2000,
+         month: Some(13),
+         day: None,
+         hour: None,
+         minutes: None,
+         seconds: None,
+      };
+      assert_eq!(
+         v24::FrameData::recording_date(bad_date).unwrap_err(),
+         v24::FrameValueError::InvalidDate
+      );
+   }
+
+   #[test]
+   fn builder_round_trip() {
+      let bytes = TagBuilder::new()
+         .title("Caf\u{e9}")
+         .artist("\u{201c}Quoted\u{201d}")
+         .title("Title")
+         .track(3, Some(12))
+         .comment("eng", "Comment")
+         .apic(write::Picture::front_cover("image/png", &b"\x89PNG"[..]))
+         .padding(16)
+         .to_bytes()
+         .unwrap();
+      assert_eq!(tag_len(&bytes), Some(bytes.len() as u64));
+      assert!(bytes.ends_with(&[0; 16]));
+
+      let options = ParseOptions {
+         strictness: Strictness::Strict,
+         ..ParseOptions::default()
+      };
+      let frames: Vec<v24::Frame> = parse_slice(&bytes, options).unwrap().map(Result::unwrap).collect();
+      let names: Vec<[u8; 4]> = frames.iter().map(|x| x.data.name()).collect();
+      assert_eq!(names, [*b"TPE1", *b"TIT2", *b"TRCK", *b"COMM", *b"APIC"]);
+      match &frames[0].data {
+         v24::FrameData::TPE1(x) => assert_eq!(&x[..], &["\u{201c}Quoted\u{201d}"]),
+         _ => panic!("expected a TPE1 frame"),
+      }
+      match &frames[2].data {
+         v24::FrameData::TRCK(x) => assert_eq!(x[0].to_string(), "3/12"),
+         _ => panic!("expected a TRCK frame"),
+      }
+      match &frames[4].data {
+         v24::FrameData::Unknown(x) => assert_eq!(&x.data[..], b"\x00image/png\x00\x03\x00\x89PNG"),
+         _ => panic!("expected an unknown APIC frame"),
+      }
+
+      // The text that fits in ISO-8859-1 is written as such
+      let title = b"TIT2\x00\x00\x00\x06\x00\x00\x00Title";
+      assert!(bytes.windows(title.len()).any(|x| x == title));
+
+      assert_eq!(
+         TagBuilder::new().title("Title").track(0, None).build().unwrap_err(),
+         v24::FrameValueError::InvalidPosition
+      );
+   }
+
+   #[test]
+   fn truncated_frame() {
+      // The frame claims to be larger than the tag
+      let tag = b"ID3\x04\x00\x00\x00\x00\x00\x0bTIT2\x00\x00\x00\x7f\x00\x00\x03";
+      let mut parser = parse_bytes(tag, ParseOptions::default()).unwrap();
+      match parser.next() {
+         Some(Err(e)) => match e.reason {
+            v24::FrameParseErrorReason::Truncated => (),
+            _ => panic!("expected a truncated frame"),
+         },
+         _ => panic!("expected a truncated frame"),
+      }
+      assert!(parser.next().is_none());
+   }
+
+   #[test]
+   fn streaming_skips_unread_frames() {
+      let mut tag = Vec::from(&b"ID3\x04\x00\x00\x00\x00\x00\x29"[..]);
+      tag.extend_from_slice(b"PRIV\x00\x00\x00\x08\x00\x00owner\0\x01\x02");
+      tag.extend_from_slice(b"TPE1\x00\x00\x00\x02\x00\x00\x03b");
+      tag.extend_from_slice(&[0; 11]);
+
+      let mut parser = parse_stream(io::Cursor::new(tag), ParseOptions::default()).unwrap();
+      assert_eq!(&parser.next_header().unwrap().unwrap().name, b"PRIV");
+      let header = parser.next_header().unwrap().unwrap();
+      assert_eq!((&header.name, header.size), (b"TPE1", 2));
+      match parser.read_frame().unwrap().unwrap().data {
+         v24::FrameData::TPE1(artists) => assert_eq!(&artists[..], ["b"]),
+         _ => panic!("expected a TPE1 frame"),
+      }
+      assert!(parser.next_header().is_none());
+      assert_eq!(parser.padding(), Some(11));
+   }
+
+   #[test]
+   fn frame_position() {
+      let mut tag = Vec::from(&b"ID3\x04\x00\x00\x00\x00\x00\x1e"[..]);
+      tag.extend_from_slice(b"PRIV\x00\x00\x00\x08\x00\x00owner\0\x01\x02");
+      tag.extend_from_slice(b"TPE1\x00\x00\x00\x02\x20\x00\x03b");
+
+      let frames: Vec<_> = parse_slice(&tag, ParseOptions::default())
+         .unwrap()
+         .map(Result::unwrap)
+         .collect();
+      assert_eq!((frames[0].offset, frames[0].size), (10, 18));
+      assert_eq!((frames[1].offset, frames[1].size), (28, 12));
+      assert!(frames[1].flags.contains(v24::FrameFlags::FILE_ALTER_PRESERVATION));
+
+      let streamed: Vec<_> = parse_stream(io::Cursor::new(&tag), ParseOptions::default())
+         .unwrap()
+         .map(Result::unwrap)
+         .collect();
+      assert_eq!((streamed[1].offset, streamed[1].size), (28, 12));
+   }
+
+   #[test]
+   fn podcast_frame() {
+      // As iTunes writes it
+      let podcast = Id3Tag {
+         frames: vec![v24::Frame::new(v24::FrameData::PCST)],
+      };
+      let bytes = write::encode_tag(&podcast, 0).unwrap();
+      assert_eq!(&bytes[10..], b"PCST\x00\x00\x00\x04\x00\x00\x00\x00\x00\x00");
+      let read = Id3Tag::read(&mut &bytes[..], ParseOptions::default()).unwrap();
+      match read.frames[0].data {
+         v24::FrameData::PCST => (),
+         _ => panic!("expected a PCST frame"),
+      }
+   }
+
+   #[test]
+   fn patch_frame_in_place() {
+      let tag = Id3Tag {
+         frames: vec![
+            v24::Frame::new(v24::FrameData::title("Title")),
+            v24::Frame::new(v24::FrameData::artist("Artist")),
+         ],
+      };
+      let mut file = write::encode_tag(&tag, 8).unwrap();
+      file.extend_from_slice(b"audio");
+      let len = file.len();
+      let mut file = io::Cursor::new(file);
+      let strict = ParseOptions {
+         strictness: Strictness::Strict,
+         ..ParseOptions::default()
+      };
+      let read = |file: &io::Cursor<Vec<u8>>| {
+         assert!(file.get_ref().ends_with(b"audio"));
+         Id3Tag::read(&mut &file.get_ref()[..], strict.clone()).unwrap()
+      };
+
+      let mut title = read(&file).frames.remove(0);
+      title.data = v24::FrameData::title("Title, longer");
+      assert!(write::patch_frame_in_place(&mut file, 0, &title).unwrap());
+      assert_eq!(read(&file).title(), Some("Title, longer"));
+      assert_eq!(read(&file).artist(), Some("Artist"));
+
+      // The old size says where the frame was
+      assert!(write::patch_frame_in_place(&mut file, 0, &title).is_err());
+      let mut title = read(&file).frames.remove(0);
+      title.data = v24::FrameData::title("Title, longer than the padding");
+      assert!(!write::patch_frame_in_place(&mut file, 0, &title).unwrap());
+      title.data = v24::FrameData::title("T");
+      assert!(write::patch_frame_in_place(&mut file, 0, &title).unwrap());
+      assert_eq!(read(&file).title(), Some("T"));
+      assert_eq!(read(&file).artist(), Some("Artist"));
+      assert_eq!(file.get_ref().len(), len);
+   }
+
+   #[test]
+   fn padded_writes() {
+      let path = std::env::temp_dir().join(format!("walnut_padded_writes_{}.mp3", std::process::id()));
+      let title = |title: &str| Id3Tag {
+         frames: vec![v24::Frame::new(v24::FrameData::title(title.to_owned()))],
+      };
+      let mut file = write::encode_tag(&title("Title"), 100).unwrap();
+      file.extend_from_slice(b"\xff\xfb\x90\x00");
+      file.extend_from_slice(&[0; 413]);
+      std::fs::write(&path, &file).unwrap();
+      let padding = |path: &std::path::Path| {
+         let bytes = std::fs::read(path).unwrap();
+         assert!(bytes.ends_with(&file[file.len() - 417..]));
+         let mut parser = parse_slice(&bytes, ParseOptions::default()).unwrap();
+         assert_eq!(parser.by_ref().count(), 1);
+         (tag_len(&bytes).unwrap(), parser.padding().unwrap())
+      };
+      let mut options = write::WriteOptions {
+         padding: write::Padding { chunk: 512, max: 1000 },
+         ..write::WriteOptions::default()
+      };
+
+      // Grows into the padding
+      write::write_tag_to_file(&path, &title("Title, longer"), options).unwrap();
+      assert_eq!(padding(&path), (file.len() as u64 - 417, 92));
+
+      // Too big for it, so grown by a chunk
+      write::write_tag_to_file(&path, &title(&"Long ".repeat(40)), options).unwrap();
+      assert_eq!(padding(&path), (512, 291));
+
+      // Fits, but would leave too much padding
+      options.padding.max = 100;
+      write::write_tag_to_file(&path, &title("T"), options).unwrap();
+      assert_eq!(padding(&path), (10 + 12 + 100, 100));
+      std::fs::remove_file(&path).unwrap();
+   }
+
+   #[test]
+   fn synced_id3v1() {
+      let path = std::env::temp_dir().join(format!("walnut_synced_id3v1_{}.mp3", std::process::id()));
+      let mut file = b"\xff\xfb\x90\x00".to_vec();
+      file.extend_from_slice(&[0; 413]);
+      std::fs::write(&path, &file).unwrap();
+      let options = write::WriteOptions {
+         sync_v1: true,
+         ..write::WriteOptions::default()
+      };
+      let read_v1 = |path: &std::path::Path| v1::read(&mut std::fs::File::open(path).unwrap()).unwrap().unwrap();
+
+      let tag = TagBuilder::new().title("First").genre("Jazz").build().unwrap();
+      write::write_tag_to_file(&path, &tag, options).unwrap();
+      assert_eq!(read_v1(&path).title(), Some("First"));
+      let len = std::fs::metadata(&path).unwrap().len();
+
+      // Replaced, rather than added again
+      let tag = TagBuilder::new().title("Second").genre("Jazz").build().unwrap();
+      write::write_tag_to_file(&path, &tag, options).unwrap();
+      let v1 = read_v1(&path);
+      assert_eq!((v1.title(), v1.genre()), (Some("Second"), Some("Jazz")));
+      assert_eq!(std::fs::metadata(&path).unwrap().len(), len);
+
+      // The ID3v2 tag no longer fits, so the file is rewritten
+      let tag = TagBuilder::new().title("Third".repeat(1000)).build().unwrap();
+      write::write_tag_to_file(&path, &tag, options).unwrap();
+      assert_eq!(read_v1(&path).title(), Some("ThirdThirdThirdThirdThirdThird"));
+      let written = std::fs::read(&path).unwrap();
+      std::fs::remove_file(&path).unwrap();
+      assert!(written[..written.len() - 128].ends_with(&file));
+   }
+
+   #[test]
+   fn edits_replace_appended_tags() {
+      // With padding, so the edit would fit in place
+      let mut file = Vec::from(&b"ID3\x04\x00\x00\x00\x00\x00\x74"[..]);
+      file.extend_from_slice(b"TIT2\x00\x00\x00\x06\x00\x00\x03First");
+      file.extend_from_slice(&[0; 100]);
+      let tag_end = file.len();
+      for _ in 0..4 {
+         file.extend_from_slice(b"\xff\xfb\x90\x00");
+         file.extend_from_slice(&[0; 413]);
+      }
+      let audio_end = file.len();
+      // Not an update, so it replaces the first
+      file.extend_from_slice(b"ID3\x04\x00\x10\x00\x00\x00\x10");
+      file.extend_from_slice(b"TIT2\x00\x00\x00\x06\x00\x00\x03Later");
+      file.extend_from_slice(b"3DI\x04\x00\x10\x00\x00\x00\x10");
+      let mut id3v1 = b"TAG".to_vec();
+      id3v1.resize(128, 0);
+      file.extend_from_slice(&id3v1);
+      let path = std::env::temp_dir().join(format!("walnut_appended_edits_{}.mp3", std::process::id()));
+      std::fs::write(&path, &file).unwrap();
+
+      let mut tag = write::read_tag_for_update(&path).unwrap();
+      assert_eq!(tag.title(), Some("Later"));
+      tag.frames.retain(|x| x.data.name() != *b"TIT2");
+      tag.frames.push(v24::Frame::new(v24::FrameData::title("Edited")));
+      write::write_tag_to_file(&path, &tag, write::WriteOptions::default()).unwrap();
+
+      assert_eq!(write::read_tag_for_update(&path).unwrap().title(), Some("Edited"));
+      let written = std::fs::read(&path).unwrap();
+      std::fs::remove_file(&path).unwrap();
+      let mut kept = file[tag_end..audio_end].to_vec();
+      kept.extend_from_slice(&id3v1);
+      assert!(written.ends_with(&kept));
+      assert_eq!(written.len() as u64, tag_len(&written).unwrap() + kept.len() as u64);
+   }
+
+   #[test]
+   fn unknown_frames_survive_edits() {
+      // Every body is shorter than 128 bytes, so its size is synchsafe as it is
+      let frame = |name: &[u8; 4], flags: [u8; 2], body: &[u8]| {
+         let mut frame = name.to_vec();
+         frame.extend_from_slice(&(body.len() as u32).to_be_bytes());
+         frame.extend_from_slice(&flags);
+         frame.extend_from_slice(body);
+         frame
+      };
+      let serato = frame(
+         b"GEOB",
+         [0, 0],
+         b"\x00application/octet-stream\x00\x00Serato Markers2\x00\x01\x01\xff\x00\xff\xe0",
+      );
+      let proprietary = frame(b"NCON", [0, 0], b"MusicMatch\xff\x00\x01");
+      let undecodable = frame(b"TBPM", [0, 0], b"\x00fast");
+      // Compressed, with a data length indicator
+      let compressed = frame(b"PRIV", [0, 0b1001], b"\x00\x00\x00\x20x\x9c\x03\x00\x00\x00\x00\x01");
+      // Asks to be discarded once the tag changes
+      let temporary = frame(b"XTMP", [0b0100_0000, 0], b"temporary");
+
+      let frames = [
+         frame(b"TIT2", [0, 0], b"\x03Old"),
+         serato.clone(),
+         proprietary.clone(),
+         undecodable.clone(),
+         compressed.clone(),
+         temporary,
+      ];
+      let size: usize = frames.iter().map(Vec::len).sum();
+      let mut file = b"ID3\x04\x00\x00".to_vec();
+      file.extend_from_slice(&synchsafe::u32_to_synchsafe_u32(size as u32).unwrap().to_be_bytes());
+      file.extend(frames.iter().flatten());
+      file.extend_from_slice(b"\xff\xfb\x90\x00");
+      file.extend_from_slice(&[0; 413]);
+      let path = std::env::temp_dir().join(format!("walnut_unknown_frames_{}.mp3", std::process::id()));
+      std::fs::write(&path, &file).unwrap();
+
+      let mut tag = write::read_tag_for_update(&path).unwrap();
+      tag.frames.retain(|x| x.data.name() != *b"TIT2");
+      tag.frames.push(v24::Frame::new(v24::FrameData::title("New")));
+      write::write_tag_to_file(&path, &tag, write::WriteOptions::default()).unwrap();
+
+      let written = std::fs::read(&path).unwrap();
+      std::fs::remove_file(&path).unwrap();
+      assert!(written.ends_with(&file[file.len() - 417..]));
+      let raw: Vec<_> = parse_raw(&written, ParseOptions::default())
+         .unwrap()
+         .map(Result::unwrap)
+         .collect();
+      let stored = |name: &[u8; 4]| {
+         raw.iter().find(|x| x.name == *name).map(|x| {
+            let range = x.byte_range(0);
+            written[range.start as usize..range.end as usize].to_vec()
+         })
+      };
+      assert_eq!(stored(b"GEOB"), Some(serato));
+      assert_eq!(stored(b"NCON"), Some(proprietary));
+      assert_eq!(stored(b"TBPM"), Some(undecodable));
+      assert_eq!(stored(b"PRIV"), Some(compressed));
+      assert_eq!(stored(b"XTMP"), None);
+      assert_eq!(stored(b"TIT2"), Some(b"TIT2\x00\x00\x00\x04\x00\x00\x00New".to_vec()));
+   }
+
+   #[test]
+   fn raw_frames_skip_decoding() {
+      // The TALB frame has an invalid text encoding
+      let mut tag = Vec::from(&b"ID3\x04\x00\x00\x00\x00\x00\x18"[..]);
+      tag.extend_from_slice(b"TALB\x00\x00\x00\x02\x00\x00\x09a");
+      tag.extend_from_slice(b"TPE1\x00\x00\x00\x02\x00\x00\x03b");
+
+      let frames: Vec<_> = parse_raw(&tag, ParseOptions::default())
+         .unwrap()
+         .map(Result::unwrap)
+         .collect();
+      assert_eq!(&frames[0].name, b"TALB");
+      assert_eq!(frames[0].payload, b"\x09a");
+      assert_eq!(frames[1].payload, b"\x03b");
+
+      // Decoded, the TALB frame is left out, but reported
+      let (read, report) = Id3Tag::read_with_report(&mut &tag[..], ParseOptions::default()).unwrap();
+      assert_eq!(read.frames.len(), 1);
+      assert_eq!(report.frame_errors.len(), 1);
+      assert_eq!(&report.frame_errors[0].name, b"TALB");
+   }
+
+   #[test]
+   fn frame_filter() {
+      let mut tag = Vec::from(&b"ID3\x04\x00\x00\x00\x00\x00\x1e"[..]);
+      tag.extend_from_slice(b"PRIV\x00\x00\x00\x08\x00\x00owner\0\x01\x02");
+      tag.extend_from_slice(b"TPE1\x00\x00\x00\x02\x00\x00\x03b");
+
+      let options = ParseOptions::default().only_frames(&[b"TPE1"]);
+      let names: Vec<[u8; 4]> = parse_slice(&tag, options.clone())
+         .unwrap()
+         .map(|x| x.unwrap().data)
+         .map(|data| match data {
+            v24::FrameData::TPE1(_) => *b"TPE1",
+            _ => *b"????",
+         })
+         .collect();
+      assert_eq!(names, [*b"TPE1"]);
+      assert_eq!(parse_stream(io::Cursor::new(&tag), options).unwrap().count(), 1);
+   }
+
+   #[test]
+   fn non_synchsafe_frame_size() {
+      // A 200 byte TIT2 frame with its size written as a plain integer, followed by a TPE1 frame
+      let mut tag = Vec::from(&b"ID3\x04\x00\x00\x00\x00\x01\x5e"[..]);
+      tag.extend_from_slice(b"TIT2\x00\x00\x00\xc8\x00\x00\x03");
+      tag.extend_from_slice(&[b'a'; 199]);
+      tag.extend_from_slice(b"TPE1\x00\x00\x00\x02\x00\x00\x03b");
+
+      let lenient = parse_bytes(&tag, ParseOptions::default()).unwrap();
+      assert_eq!(lenient.filter(|x| x.is_ok()).count(), 2);
+
+      let strict_options = ParseOptions {
+         strictness: Strictness::Strict,
+         ..Default::default()
+      };
+      let mut strict = parse_bytes(&tag, strict_options).unwrap();
+      assert!(strict.next().unwrap().is_err());
+   }
+
+   #[test]
+   fn extended_header_crc() {
+      let frames = b"TIT2\x00\x00\x00\x06\x00\x00\x03Title";
+      let crc = crc32(frames);
+      assert_eq!(crc32(b"123456789"), 0xcb_f4_39_26);
+
+      let mut tag = Vec::from(&b"ID3\x04\x00\x40\x00\x00\x00\x1c\x00\x00\x00\x0c\x01\x20\x05"[..]);
+      for i in (0..5).rev() {
+         tag.push((u64::from(crc) >> (i * 7)) as u8 & 0x7f);
+      }
+      tag.extend_from_slice(frames);
+
+      let verify = ParseOptions {
+         verify_crc: true,
+         ..Default::default()
+      };
+      assert_eq!(parse_slice(&tag, verify.clone()).unwrap().info().crc_valid, Some(true));
+      assert_eq!(parse_stream(io::Cursor::new(&tag), verify.clone()).unwrap().count(), 1);
+
+      let last = tag.len() - 1;
+      tag[last] = b'X';
+      assert_eq!(
+         parse_slice(&tag, ParseOptions::default()).unwrap().info().crc_valid,
+         Some(false)
+      );
+      match parse_bytes(&tag, verify) {
+         Err(TagParseError::CrcMismatch { .. }) => (),
+         _ => panic!("expected a CRC mismatch"),
+      }
+   }
+
+   #[test]
+   fn extended_header_restrictions() {
+      let mut tag = Vec::from(&b"ID3\x04\x00\x40\x00\x00\x00\x18\x00\x00\x00\x08\x01\x10\x01\x6a"[..]);
+      tag.extend_from_slice(b"TIT2\x00\x00\x00\x06\x00\x00\x03Title");
+
+      let restrictions = parse_slice(&tag, ParseOptions::default())
+         .unwrap()
+         .info()
+         .restrictions
+         .unwrap();
+      assert_eq!(restrictions.max_frames, 64);
+      assert!(restrictions.latin1_or_utf8_only);
+      assert_eq!(restrictions.max_text_len, Some(1024));
+      assert!(!restrictions.png_or_jpeg_only);
+      assert_eq!(restrictions.image_size, v24::ImageSizeRestriction::Max64);
+   }
+
+   #[test]
+   fn appended_update_tag() {
+      let mut file = Vec::from(&b"ID3\x04\x00\x00\x00\x00\x00\x20"[..]);
+      file.extend_from_slice(b"TIT2\x00\x00\x00\x06\x00\x00\x03First");
+      file.extend_from_slice(b"TPE1\x00\x00\x00\x06\x00\x00\x03Cream");
+      file.extend_from_slice(&[0xff, 0xfb, 0x90, 0x00]);
+      // Flagged as an update, with a footer
+      file.extend_from_slice(b"ID3\x04\x00\x50\x00\x00\x00\x16\x00\x00\x00\x06\x01\x40");
+      file.extend_from_slice(b"TIT2\x00\x00\x00\x06\x00\x00\x03Later");
+      file.extend_from_slice(b"3DI\x04\x00\x50\x00\x00\x00\x16");
+
+      let tag = Id3Tag::read_all(&mut io::Cursor::new(file), ParseOptions::default()).unwrap();
+      assert_eq!(tag.frames.len(), 2);
+      assert_eq!(tag.title(), Some("Later"));
+      assert_eq!(tag.artist(), Some("Cream"));
+   }
+
+   #[test]
+   fn seek_that_misses() {
+      let file = |seek: u8| {
+         let mut file = Vec::from(&b"ID3\x04\x00\x00\x00\x00\x00\x1e"[..]);
+         file.extend_from_slice(b"TIT2\x00\x00\x00\x06\x00\x00\x03First");
+         file.extend_from_slice(b"SEEK\x00\x00\x00\x04\x00\x00\x00\x00\x00");
+         file.push(seek);
+         file.extend_from_slice(&[0xff, 0xfb, 0x90, 0x00]);
+         file.extend_from_slice(b"ID3\x04\x00\x00\x00\x00\x00\x10");
+         file.extend_from_slice(b"TIT2\x00\x00\x00\x06\x00\x00\x03Later");
+         io::Cursor::new(file)
+      };
+
+      let (tag, report) = Id3Tag::read_all_with_report(&mut file(4), ParseOptions::default()).unwrap();
+      assert_eq!(tag.title(), Some("Later"));
+      assert!(report.diagnostics.is_empty());
+
+      // Two bytes past the second tag, so the first is all there is
+      let (tag, report) = Id3Tag::read_all_with_report(&mut file(6), ParseOptions::default()).unwrap();
+      assert_eq!(tag.title(), Some("First"));
+      assert_eq!(report.diagnostics, vec![Diagnostic::BadSeek(46)]);
+   }
+
+   #[test]
+   fn group_and_data_length_indicator() {
+      let mut tag = Vec::from(&b"ID3\x04\x00\x00\x00\x00\x00\x25"[..]);
+      tag.extend_from_slice(b"TIT2\x00\x00\x00\x0b\x00\x41\x05\x00\x00\x00\x06\x03Title");
+      tag.extend_from_slice(b"TPE1\x00\x00\x00\x06\x00\x00\x03Cream");
+
+      let raw: Vec<_> = parse_raw(&tag, ParseOptions::default())
+         .unwrap()
+         .map(Result::unwrap)
+         .collect();
+      assert_eq!(raw[0].group, Some(5));
+      assert_eq!(raw[0].data_length, Some(6));
+      assert_eq!(raw[0].payload, b"\x03Title");
+      assert_eq!(raw[1].offset, 31);
+
+      for frames in vec![
+         parse_bytes(&tag, ParseOptions::default()).unwrap().collect::<Vec<_>>(),
+         parse_stream(io::Cursor::new(&tag), ParseOptions::default())
+            .unwrap()
+            .collect(),
+      ] {
+         let tag = Id3Tag {
+            frames: frames.into_iter().map(Result::unwrap).collect(),
+         };
+         assert_eq!(tag.frames[0].group, Some(5));
+         assert_eq!(tag.frames[0].size, 21);
+         assert_eq!(tag.title(), Some("Title"));
+         assert_eq!(tag.artist(), Some("Cream"));
+      }
    }
 }
@@ -0,0 +1,138 @@
+use super::v24::FrameData;
+use super::Id3Tag;
+use crate::query::frame_texts;
+use bitflags::bitflags;
+use std::borrow::Cow;
+use unicode_normalization::{is_nfc, UnicodeNormalization};
+
+bitflags! {
+   /// What `clean` changed about a piece of text
+   pub struct Cleanups: u8 {
+      /// Decomposed characters were composed (NFC), e.g. "e" followed by a combining acute accent became "é"
+      const COMPOSED = 0b0001;
+      /// A byte order mark was found inside the text, usually left behind by a tagger joining UTF-16 strings
+      const STRAY_BOM = 0b0010;
+      /// Whitespace or nulls were trimmed from the end
+      const TRAILING_WHITESPACE = 0b0100;
+      /// Runs of spaces were collapsed into one
+      const DOUBLED_SPACES = 0b1000;
+   }
+}
+
+/// Cleans up decoded text: byte order marks are removed, trailing whitespace and nulls are trimmed, runs of spaces
+/// are collapsed, and the result is normalized to NFC. Text that's already clean is borrowed.
+pub fn clean(text: &str) -> (Cow<'_, str>, Cleanups) {
+   let mut cleanups = Cleanups::empty();
+   let mut text = Cow::Borrowed(text);
+   if text.contains('\u{feff}') {
+      cleanups |= Cleanups::STRAY_BOM;
+      text = Cow::Owned(text.replace('\u{feff}', ""));
+   }
+   let trimmed_len = text.trim_end_matches(|x: char| x.is_whitespace() || x == '\0').len();
+   if trimmed_len != text.len() {
+      cleanups |= Cleanups::TRAILING_WHITESPACE;
+      text = match text {
+         Cow::Borrowed(x) => Cow::Borrowed(&x[..trimmed_len]),
+         Cow::Owned(mut x) => {
+            x.truncate(trimmed_len);
+            Cow::Owned(x)
+         }
+      };
+   }
+   if text.contains("  ") {
+      cleanups |= Cleanups::DOUBLED_SPACES;
+      let mut collapsed = String::with_capacity(text.len());
+      for c in text.chars() {
+         if c != ' ' || !collapsed.ends_with(' ') {
+            collapsed.push(c);
+         }
+      }
+      text = Cow::Owned(collapsed);
+   }
+   if !is_nfc(&text) {
+      cleanups |= Cleanups::COMPOSED;
+      text = Cow::Owned(text.nfc().collect());
+   }
+   (text, cleanups)
+}
+
+/// A frame whose text `normalize_tag` cleaned up
+#[derive(Clone, Debug, PartialEq)]
+pub struct Correction {
+   pub frame: [u8; 4],
+   /// The texts of the frame as they were, and as they are now. A frame with several values has one of each.
+   pub old: Vec<String>,
+   pub new: Vec<String>,
+   pub cleanups: Cleanups,
+}
+
+/// Cleans the text of every text frame, TXXX frame, comment, and lyrics frame in the tag (see `clean`), and returns
+/// what was changed, e.g. for writing the tag back
+pub fn normalize_tag(tag: &mut Id3Tag) -> Vec<Correction> {
+   let mut corrections = Vec::new();
+   for frame in tag.frames.iter_mut() {
+      let name = frame.data.name();
+      let mut cleanups = Cleanups::empty();
+      let mut clean_all = |texts: &mut [Cow<'static, str>]| -> (Vec<String>, Vec<String>) {
+         let old: Vec<String> = texts.iter().map(|x| x.to_string()).collect();
+         for text in texts.iter_mut() {
+            let (cleaned, changes) = clean(text);
+            if !changes.is_empty() {
+               cleanups |= changes;
+               *text = Cow::Owned(cleaned.into_owned());
+            }
+         }
+         (old, texts.iter().map(|x| x.to_string()).collect())
+      };
+
+      let (old, new) = match &mut frame.data {
+         FrameData::TXXX(x) => clean_all(&mut x.text),
+         FrameData::COMM(x) | FrameData::USLT(x) => clean_all(&mut x.text),
+         data if name[0] == b'T' => {
+            let mut texts: Vec<Cow<str>> = frame_texts(data).into_iter().map(Cow::Owned).collect();
+            let (old, new) = clean_all(&mut texts);
+            if old != new {
+               // Read back the way it would be from a tag, so that typed frames like TRCK stay typed
+               match FrameData::from_text(name, &new.join("\0")) {
+                  Ok(cleaned) => *data = cleaned,
+                  Err(_) => continue,
+               }
+            }
+            (old, new)
+         }
+         _ => continue,
+      };
+      if !cleanups.is_empty() {
+         corrections.push(Correction {
+            frame: name,
+            old,
+            new,
+            cleanups,
+         });
+      }
+   }
+   corrections
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+
+   #[test]
+   fn cleanups() {
+      assert_eq!(clean("Clean"), (Cow::Borrowed("Clean"), Cleanups::empty()));
+      assert_eq!(clean("Sigur Ro\u{301}s  \u{feff}Live \0\0").0, "Sigur R\u{f3}s Live");
+      assert_eq!(clean("Sigur Ro\u{301}s  \u{feff}Live \0\0").1, Cleanups::all());
+
+      let mut tag = crate::id3::TagBuilder::new()
+         .title("Title ")
+         .artist("Artist")
+         .build()
+         .unwrap();
+      let corrections = normalize_tag(&mut tag);
+      assert_eq!(corrections.len(), 1);
+      assert_eq!(corrections[0].frame, *b"TIT2");
+      assert_eq!(corrections[0].new, ["Title"]);
+      assert_eq!(crate::tag::Tag::title(&tag), Some("Title"));
+   }
+}
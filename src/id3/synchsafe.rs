@@ -0,0 +1,108 @@
+//! Synchsafe integers store 7 bits in each byte and leave the high bit clear, so that no size or CRC in a tag
+//! can look like the 0xFF of an MPEG frame sync.
+
+/// The largest value that fits in a 4 byte synchsafe integer, which is what tag and frame sizes are
+pub const U28_MAX: u32 = 0x0f_ff_ff_ff;
+
+/// The largest value that fits in a 5 byte synchsafe integer, like the CRC in the extended header
+pub const U35_MAX: u64 = 0x07_ff_ff_ff_ff;
+
+/// Whether the high bit of every byte is clear
+pub fn is_synchsafe(sync_int: u32) -> bool {
+   sync_int & 0x80_80_80_80 == 0
+}
+
+/// Decodes a 4 byte synchsafe integer. The high bit of each byte is expected to be clear, and is ignored.
+pub fn synchsafe_u32_to_u32(sync_int: u32) -> u32 {
+   let low = (sync_int & 0x00_00_00_ff) | (sync_int & 0x00_00_01_00) >> 1;
+   let mid_low = (sync_int & 0x00_00_fe_00) >> 1 | (sync_int & 0x00_03_00_00) >> 2;
+   let mid_high = (sync_int & 0x00_fc_00_00) >> 2 | (sync_int & 0x07_00_00_00) >> 3;
+   let high = (sync_int & 0xf8_00_00_00) >> 3;
+   high | mid_high | mid_low | low
+}
+
+/// Encodes a 4 byte synchsafe integer, or returns `None` if the value is larger than `U28_MAX`
+pub fn u32_to_synchsafe_u32(int: u32) -> Option<u32> {
+   if int > U28_MAX {
+      return None;
+   }
+   let low = int & 0x00_00_00_7f;
+   let mid_low = (int & 0x00_00_3f_80) << 1;
+   let mid_high = (int & 0x00_1f_c0_00) << 2;
+   let high = (int & 0x0f_e0_00_00) << 3;
+   Some(high | mid_high | mid_low | low)
+}
+
+/// Decodes a 5 byte synchsafe integer, held in the low 40 bits
+pub fn synchsafe_u40_to_u64(sync_int: u64) -> u64 {
+   let mut result = 0;
+   for i in 0..5 {
+      result |= ((sync_int >> (i * 8)) & 0x7f) << (i * 7);
+   }
+   result
+}
+
+/// Like `synchsafe_u40_to_u64`, but keeps only the low 32 bits, which is all a CRC-32 needs
+pub fn synchsafe_u40_to_u32(sync_int: u64) -> u32 {
+   synchsafe_u40_to_u64(sync_int) as u32
+}
+
+/// Encodes a 5 byte synchsafe integer in the low 40 bits, or returns `None` if the value is larger than `U35_MAX`
+pub fn u64_to_synchsafe_u40(int: u64) -> Option<u64> {
+   if int > U35_MAX {
+      return None;
+   }
+   let mut result = 0;
+   for i in 0..5 {
+      result |= ((int >> (i * 7)) & 0x7f) << (i * 8);
+   }
+   Some(result)
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+
+   #[test]
+   fn u32_round_trip() {
+      // Every value with the top or bottom 14 bits set arbitrarily, which covers every byte boundary
+      for high in 0..(1 << 14) {
+         for low in [0, 1, 0x7f, 0x80, 0x2a5a, 0x3fff].iter() {
+            let int = high << 14 | low;
+            let sync_int = u32_to_synchsafe_u32(int).unwrap();
+            assert!(is_synchsafe(sync_int));
+            assert_eq!(synchsafe_u32_to_u32(sync_int), int);
+         }
+      }
+      for bit in 0..28 {
+         let sync_int = u32_to_synchsafe_u32(1 << bit).unwrap();
+         assert_eq!(sync_int.count_ones(), 1);
+         assert_eq!(synchsafe_u32_to_u32(sync_int), 1 << bit);
+      }
+
+      assert_eq!(u32_to_synchsafe_u32(U28_MAX), Some(0x7f_7f_7f_7f));
+      assert_eq!(synchsafe_u32_to_u32(0x7f_7f_7f_7f), U28_MAX);
+      assert_eq!(u32_to_synchsafe_u32(U28_MAX + 1), None);
+      assert_eq!(u32_to_synchsafe_u32(std::u32::MAX), None);
+      assert!(!is_synchsafe(0x00_00_00_80));
+   }
+
+   #[test]
+   fn u40_round_trip() {
+      for bit in 0..35 {
+         for int in [1u64 << bit, (1 << bit) - 1, (1 << bit) | 0x55].iter() {
+            let sync_int = u64_to_synchsafe_u40(*int).unwrap();
+            assert_eq!(sync_int & !0x7f_7f_7f_7f_7f, 0);
+            assert_eq!(synchsafe_u40_to_u64(sync_int), *int);
+         }
+      }
+
+      assert_eq!(u64_to_synchsafe_u40(U35_MAX), Some(0x7f_7f_7f_7f_7f));
+      assert_eq!(u64_to_synchsafe_u40(U35_MAX + 1), None);
+      assert_eq!(synchsafe_u40_to_u32(0x7f_7f_7f_7f_7f), 0xff_ff_ff_ff);
+      assert_eq!(
+         synchsafe_u40_to_u32(u64_to_synchsafe_u40(0xdead_beef).unwrap()),
+         0xdead_beef
+      );
+   }
+}
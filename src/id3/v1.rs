@@ -0,0 +1,200 @@
+use super::write::latin1_lossy;
+use crate::genre;
+use crate::tag::Tag;
+use std::convert::TryFrom;
+use std::io::{self, Read, Seek, SeekFrom};
+
+/// An ID3v1.1 tag, the fixed 128 bytes at the very end of the file. Text is ISO-8859-1, and each field holds at most
+/// 30 characters (28 for the comment, when there is a track number).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Id3v1Tag {
+   pub title: String,
+   pub artist: String,
+   pub album: String,
+   pub year: Option<u16>,
+   pub comment: String,
+   /// Only ID3v1.1 has a track number, and it has to be from 1 to 255
+   pub track: Option<u8>,
+   /// An index into `genre::GENRES`
+   pub genre: Option<u8>,
+}
+
+impl Id3v1Tag {
+   pub const LEN: u64 = 128;
+
+   /// Takes what fits from another tag, e.g. an ID3v2 tag the ID3v1 tag is kept in sync with. Text is cut short
+   /// and characters ISO-8859-1 doesn't have become '?'. A genre that isn't in the ID3v1 list is left out.
+   pub fn from_tag(tag: &dyn Tag) -> Id3v1Tag {
+      let text = |x: Option<&str>, len: usize| {
+         x.map_or_else(String::new, |x| latin1_lossy(x).take(len).map(char::from).collect())
+      };
+      let track = tag
+         .track_number()
+         .and_then(|x| u8::try_from(x).ok())
+         .filter(|x| *x != 0);
+      Id3v1Tag {
+         title: text(tag.title(), 30),
+         artist: text(tag.artist(), 30),
+         album: text(tag.album(), 30),
+         year: tag.year().filter(|x| *x <= 9999),
+         comment: text(tag.comment(), if track.is_some() { 28 } else { 30 }),
+         track,
+         genre: tag.genre().and_then(genre::index),
+      }
+   }
+
+   /// Returns `None` if the bytes aren't an ID3v1 tag
+   pub fn parse(bytes: &[u8; 128]) -> Option<Id3v1Tag> {
+      if &bytes[0..3] != b"TAG" {
+         return None;
+      }
+      // ID3v1.1 keeps the track number in the last byte of the comment
+      let (comment, track) = if bytes[125] == 0 && bytes[126] != 0 {
+         (&bytes[97..125], Some(bytes[126]))
+      } else {
+         (&bytes[97..127], None)
+      };
+      Some(Id3v1Tag {
+         title: field(&bytes[3..33]),
+         artist: field(&bytes[33..63]),
+         album: field(&bytes[63..93]),
+         year: field(&bytes[93..97]).parse().ok(),
+         comment: field(comment),
+         track,
+         genre: Some(bytes[127]).filter(|x| genre::name(*x).is_some()),
+      })
+   }
+
+   /// Fields that are too long are cut short, as in `from_tag`
+   pub fn to_bytes(&self) -> [u8; 128] {
+      let mut bytes = [0u8; 128];
+      bytes[0..3].copy_from_slice(b"TAG");
+      put_field(&mut bytes[3..33], &self.title);
+      put_field(&mut bytes[33..63], &self.artist);
+      put_field(&mut bytes[63..93], &self.album);
+      if let Some(year) = self.year {
+         put_field(&mut bytes[93..97], &format!("{:04}", year));
+      }
+      match self.track {
+         Some(track) => {
+            put_field(&mut bytes[97..125], &self.comment);
+            bytes[126] = track;
+         }
+         None => put_field(&mut bytes[97..127], &self.comment),
+      }
+      bytes[127] = self.genre.unwrap_or(255);
+      bytes
+   }
+}
+
+/// Reads the ID3v1 tag at the end of the source, if there is one
+pub fn read<S: Read + Seek>(source: &mut S) -> io::Result<Option<Id3v1Tag>> {
+   let len = source.seek(SeekFrom::End(0))?;
+   if len < Id3v1Tag::LEN {
+      return Ok(None);
+   }
+   let mut bytes = [0u8; 128];
+   source.seek(SeekFrom::Start(len - Id3v1Tag::LEN))?;
+   source.read_exact(&mut bytes)?;
+   Ok(Id3v1Tag::parse(&bytes))
+}
+
+// Padded with nulls, though some taggers pad with spaces
+fn field(bytes: &[u8]) -> String {
+   let end = bytes.iter().position(|x| *x == 0).unwrap_or_else(|| bytes.len());
+   let text: String = bytes[..end].iter().map(|x| char::from(*x)).collect();
+   text.trim_end().to_owned()
+}
+
+fn put_field(dest: &mut [u8], text: &str) {
+   for (dest, byte) in dest.iter_mut().zip(latin1_lossy(text)) {
+      *dest = byte;
+   }
+}
+
+fn non_empty(text: &str) -> Option<&str> {
+   if text.is_empty() {
+      None
+   } else {
+      Some(text)
+   }
+}
+
+impl Tag for Id3v1Tag {
+   fn title(&self) -> Option<&str> {
+      non_empty(&self.title)
+   }
+
+   fn artist(&self) -> Option<&str> {
+      non_empty(&self.artist)
+   }
+
+   fn album(&self) -> Option<&str> {
+      non_empty(&self.album)
+   }
+
+   fn album_artist(&self) -> Option<&str> {
+      None
+   }
+
+   fn genre(&self) -> Option<&str> {
+      self.genre.and_then(genre::name)
+   }
+
+   fn year(&self) -> Option<u16> {
+      self.year
+   }
+
+   fn track_number(&self) -> Option<u32> {
+      self.track.map(u32::from)
+   }
+
+   fn track_total(&self) -> Option<u32> {
+      None
+   }
+
+   fn disc_number(&self) -> Option<u32> {
+      None
+   }
+
+   fn disc_total(&self) -> Option<u32> {
+      None
+   }
+
+   fn comment(&self) -> Option<&str> {
+      non_empty(&self.comment)
+   }
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+
+   #[test]
+   fn round_trip() {
+      use crate::id3::v24::{Frame, FrameData};
+      use crate::id3::Id3Tag;
+
+      let v2 = Id3Tag {
+         frames: vec![
+            Frame::new(FrameData::title("A title that is much too long for ID3v1")),
+            Frame::new(FrameData::artist("Sigur R\u{f3}s \u{2013} Live")),
+            Frame::new(FrameData::genre("Post-Rock")),
+            Frame::new(FrameData::track(7, Some(9)).unwrap()),
+         ],
+      };
+      let v1 = Id3v1Tag::from_tag(&v2);
+      assert_eq!(v1.title, "A title that is much too long ");
+      assert_eq!(v1.artist, "Sigur R\u{f3}s ? Live");
+      assert_eq!(v1.genre, genre::index("Post-Rock"));
+      assert_eq!(v1.track, Some(7));
+
+      let bytes = v1.to_bytes();
+      assert_eq!(bytes[33 + 7], 0xf3);
+      let parsed = Id3v1Tag::parse(&bytes).unwrap();
+      assert_eq!(parsed.title, "A title that is much too long");
+      assert_eq!(parsed.genre(), Some("Post-Rock"));
+      assert_eq!(parsed.track_number(), Some(7));
+      assert_eq!(parsed.year(), None);
+   }
+}
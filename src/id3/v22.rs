@@ -1,4 +1,11 @@
+use super::v24::{Frame, FrameData, Unknown};
+use super::{
+   find_header, parse_header, read_frames, resynchronize, Id3Tag, ParseOptions, TagFlags as Flags, TagParseError,
+};
 use bitflags::bitflags;
+use byteorder::{BigEndian, ByteOrder};
+use std::borrow::Cow;
+use std::io::Read;
 
 bitflags! {
    pub(super) struct TagFlags: u8 {
@@ -6,3 +13,220 @@ bitflags! {
       const COMPRESSED = 0b0100_0000;
    }
 }
+
+// Each v2.2 frame and the v2.3 frame that replaced it. Some are iTunes' own, as iTunes kept writing v2.2 tags long
+// after v2.3 came out. CRM (encrypted meta frame) has no successor.
+const FRAME_IDS: [(&[u8; 3], &[u8; 4]); 74] = [
+   (b"BUF", b"RBUF"),
+   (b"CNT", b"PCNT"),
+   (b"COM", b"COMM"),
+   (b"CRA", b"AENC"),
+   (b"EQU", b"EQUA"),
+   (b"ETC", b"ETCO"),
+   (b"GEO", b"GEOB"),
+   (b"GP1", b"GRP1"),
+   (b"IPL", b"IPLS"),
+   (b"LNK", b"LINK"),
+   (b"MCI", b"MCDI"),
+   (b"MLL", b"MLLT"),
+   (b"MVI", b"MVIN"),
+   (b"MVN", b"MVNM"),
+   (b"PCS", b"PCST"),
+   (b"PIC", b"APIC"),
+   (b"POP", b"POPM"),
+   (b"REV", b"RVRB"),
+   (b"RVA", b"RVAD"),
+   (b"SLT", b"SYLT"),
+   (b"STC", b"SYTC"),
+   (b"TAL", b"TALB"),
+   (b"TBP", b"TBPM"),
+   (b"TCM", b"TCOM"),
+   (b"TCO", b"TCON"),
+   (b"TCP", b"TCMP"),
+   (b"TCR", b"TCOP"),
+   (b"TDA", b"TDAT"),
+   (b"TDS", b"TDES"),
+   (b"TDY", b"TDLY"),
+   (b"TEN", b"TENC"),
+   (b"TFT", b"TFLT"),
+   (b"TID", b"TGID"),
+   (b"TIM", b"TIME"),
+   (b"TKE", b"TKEY"),
+   (b"TLA", b"TLAN"),
+   (b"TLE", b"TLEN"),
+   (b"TMT", b"TMED"),
+   (b"TOA", b"TOPE"),
+   (b"TOF", b"TOFN"),
+   (b"TOL", b"TOLY"),
+   (b"TOR", b"TORY"),
+   (b"TOT", b"TOAL"),
+   (b"TP1", b"TPE1"),
+   (b"TP2", b"TPE2"),
+   (b"TP3", b"TPE3"),
+   (b"TP4", b"TPE4"),
+   (b"TPA", b"TPOS"),
+   (b"TPB", b"TPUB"),
+   (b"TRC", b"TSRC"),
+   (b"TRD", b"TRDA"),
+   (b"TRK", b"TRCK"),
+   (b"TS2", b"TSO2"),
+   (b"TSA", b"XSOA"),
+   (b"TSC", b"TSOC"),
+   (b"TSI", b"TSIZ"),
+   (b"TSP", b"XSOP"),
+   (b"TSS", b"TSSE"),
+   (b"TST", b"XSOT"),
+   (b"TT1", b"TIT1"),
+   (b"TT2", b"TIT2"),
+   (b"TT3", b"TIT3"),
+   (b"TXT", b"TEXT"),
+   (b"TXX", b"TXXX"),
+   (b"TYE", b"TYER"),
+   (b"UFI", b"UFID"),
+   (b"ULT", b"USLT"),
+   (b"WAF", b"WOAF"),
+   (b"WAR", b"WOAR"),
+   (b"WAS", b"WOAS"),
+   (b"WCM", b"WCOM"),
+   (b"WCP", b"WCOP"),
+   (b"WPB", b"WPUB"),
+   (b"WXX", b"WXXX"),
+];
+
+/// Whether the name is a v2.2 frame ID. They are 3 characters, so they're held in `Unknown::name` with a null after
+/// them, e.g. `b"TT2\0"`.
+pub fn is_v22_name(name: [u8; 4]) -> bool {
+   name[3] == 0
+}
+
+/// The v2.3 frame that replaced a v2.2 one, e.g. TIT2 for `b"TT2\0"`
+pub fn upgrade_id(name: [u8; 4]) -> Option<[u8; 4]> {
+   FRAME_IDS
+      .iter()
+      .find(|(v22, _)| v22[..] == name[..3] && is_v22_name(name))
+      .map(|(_, v23)| **v23)
+}
+
+/// The v2.2 frame that a v2.3 one replaced, e.g. `b"TT2\0"` for TIT2
+pub fn downgrade_id(name: [u8; 4]) -> Option<[u8; 4]> {
+   FRAME_IDS
+      .iter()
+      .find(|(_, v23)| **v23 == name)
+      .map(|(v22, _)| [v22[0], v22[1], v22[2], 0])
+}
+
+/// Converts a v2.2 frame to the v2.3 frame that replaced it. PIC names the image format with 3 characters, where
+/// APIC has a MIME type, so its payload is rewritten; everything else is only renamed. Returns `None` for frames
+/// with no successor and malformed PICs.
+pub fn upgrade_frame(frame: &Unknown) -> Option<FrameData<'static>> {
+   let name = upgrade_id(frame.name)?;
+   let data = if &name == b"APIC" {
+      pic_to_apic(&frame.data)?
+   } else {
+      frame.data.to_vec()
+   };
+   Some(FrameData::Unknown(Unknown {
+      name,
+      data: Cow::Owned(data),
+   }))
+}
+
+/// The reverse of `upgrade_frame`, for a frame held in its v2.3 encoding
+pub fn downgrade_frame(frame: &Unknown) -> Option<FrameData<'static>> {
+   let name = downgrade_id(frame.name)?;
+   let data = if &frame.name == b"APIC" {
+      apic_to_pic(&frame.data)?
+   } else {
+      frame.data.to_vec()
+   };
+   Some(FrameData::Unknown(Unknown {
+      name,
+      data: Cow::Owned(data),
+   }))
+}
+
+// Both start with the text encoding, and end with the picture type, description, and picture
+fn pic_to_apic(pic: &[u8]) -> Option<Vec<u8>> {
+   if pic.len() < 5 {
+      return None;
+   }
+   let format = String::from_utf8_lossy(&pic[1..4]).to_ascii_lowercase();
+   let mime_type = match format.trim_end() {
+      "jpg" => "image/jpeg".to_owned(),
+      // The picture is a URL
+      "-->" => "-->".to_owned(),
+      x => format!("image/{}", x),
+   };
+   let mut apic = vec![pic[0]];
+   apic.extend(mime_type.bytes());
+   apic.push(0);
+   apic.extend_from_slice(&pic[4..]);
+   Some(apic)
+}
+
+fn apic_to_pic(apic: &[u8]) -> Option<Vec<u8>> {
+   let mime_end = apic.iter().skip(1).position(|x| *x == 0)? + 1;
+   let mime_type = String::from_utf8_lossy(&apic[1..mime_end]).to_ascii_lowercase();
+   let format = match mime_type.as_str() {
+      "image/jpeg" | "image/jpg" => "JPG".to_owned(),
+      "-->" => "-->".to_owned(),
+      // Padded or cut to 3 characters, e.g. PNG
+      x => format!("{:<3.3}", x.trim_start_matches("image/").to_ascii_uppercase()),
+   };
+   let mut pic = vec![apic[0]];
+   pic.extend(format.bytes());
+   pic.extend_from_slice(&apic[mime_end + 1..]);
+   Some(pic)
+}
+
+/// Reads an ID3v2.2 tag, which the parsers don't. Every frame is held as an `Unknown` frame with its v2.2 ID (see
+/// `is_v22_name`), so the tag is meant for `convert::convert` to turn into v2.4 frames.
+///
+/// v2.2 never defined its compression scheme, so a compressed tag is rejected. The frames of an unsynchronized tag
+/// have no position, because it applies to the whole tag rather than each frame.
+pub fn read_tag<S: Read>(source: &mut S, options: &ParseOptions) -> Result<Id3Tag, TagParseError> {
+   let mut header = [0u8; 10];
+   source.read_exact(&mut header)?;
+   if &header[0..3] != b"ID3" {
+      find_header(source, &mut header, options.search_window)?;
+   }
+   let header = parse_header(&header[3..], options)?;
+   let flags = match header.flags {
+      Flags::V22(flags) if !flags.contains(TagFlags::COMPRESSED) => flags,
+      Flags::V22(_) => return Err(TagParseError::UnsupportedVersion(2)),
+      Flags::V23(_) => return Err(TagParseError::UnsupportedVersion(3)),
+      Flags::V24(_) => return Err(TagParseError::UnsupportedVersion(4)),
+   };
+
+   let unsynchronized = flags.contains(TagFlags::UNSYNCHRONIZED);
+   let mut bytes = read_frames(source, header.size)?.into_vec();
+   if unsynchronized {
+      bytes = resynchronize(&bytes);
+   }
+
+   // Each frame header is a 3 character ID and a 24 bit size
+   let mut frames = Vec::new();
+   let mut rest = &bytes[..];
+   while rest.len() >= 6 && rest[..3].iter().all(|x| x.is_ascii_uppercase() || x.is_ascii_digit()) {
+      let size = BigEndian::read_u24(&rest[3..6]) as usize;
+      let data = match rest.get(6..6 + size) {
+         Some(v) => v,
+         None => break,
+      };
+      let frame = Frame::new(FrameData::Unknown(Unknown {
+         name: [rest[0], rest[1], rest[2], 0],
+         data: Cow::Owned(data.to_vec()),
+      }));
+      frames.push(if unsynchronized {
+         frame
+      } else {
+         Frame {
+            offset: 10 + (bytes.len() - rest.len()) as u32,
+            size: 6 + size as u32,
+            ..frame
+         }
+      });
+      rest = &rest[6 + size..];
+   }
+   Ok(Id3Tag { frames })
+}
@@ -1,4 +1,12 @@
+use super::v24::{
+   self, Date, Equ2, Equ2Point, Frame, FrameData, FrameParseError, FrameParseErrorReason, HeaderExtra, Rva2,
+   Rva2Channel, Unknown,
+};
+use super::{resynchronize, ParseOptions, Strictness};
 use bitflags::bitflags;
+use byteorder::{BigEndian, ByteOrder};
+use std::borrow::Cow;
+use std::str::FromStr;
 
 bitflags! {
    pub(super) struct FrameFlags: u16 {
@@ -14,6 +22,24 @@ bitflags! {
    }
 }
 
+impl FrameFlags {
+   // v2.4 moved each flag one bit down, and requires a data length indicator on compressed frames, which holds what
+   // v2.3 calls the decompressed size
+   fn to_v24(self) -> v24::FrameFlags {
+      let mut flags = v24::FrameFlags::from_bits_truncate((self.bits() & 0xe000) >> 1);
+      if self.contains(FrameFlags::COMPRESSION) {
+         flags |= v24::FrameFlags::COMPRESSION | v24::FrameFlags::DATA_LENGTH_INDICATOR;
+      }
+      if self.contains(FrameFlags::ENCRYPTION) {
+         flags |= v24::FrameFlags::ENCRYPTION;
+      }
+      if self.contains(FrameFlags::GROUPING_IDENTITY) {
+         flags |= v24::FrameFlags::GROUPING_IDENTITY;
+      }
+      flags
+   }
+}
+
 bitflags! {
    pub(super) struct TagFlags: u8 {
       const UNSYNCHRONIZED = 0b1000_0000;
@@ -21,3 +47,473 @@ bitflags! {
       const EXPERIMENTAL_INDICATOR = 0b0010_0000;
    }
 }
+
+/// Decodes a v2.3 frame into its v2.4 representation. IPLS becomes TIPL, RVAD becomes RVA2, and EQUA becomes EQU2.
+/// Every other frame has the same layout in both versions.
+pub fn decode_frame<'a>(
+   name: [u8; 4],
+   frame_bytes: &'a [u8],
+   options: &ParseOptions,
+) -> Result<FrameData<'a>, FrameParseErrorReason> {
+   match &name {
+      b"IPLS" => Ok(FrameData::TIPL(v24::decode_text_map_frame(
+         frame_bytes,
+         &v24::DecodeOptions::new(options),
+      )?)),
+      b"RVAD" => Ok(FrameData::RVA2(decode_rvad_frame(frame_bytes)?)),
+      b"EQUA" => Ok(FrameData::EQU2(decode_equa_frame(frame_bytes)?)),
+      _ => v24::decode_frame(name, frame_bytes, options),
+   }
+}
+
+/// Splits the frames of an ID3v2.3 tag (everything after its headers) and decodes them into v2.4 frames, which is how
+/// `Id3Tag::read` reads v2.3 tags. Frame sizes are plain integers rather than synchsafe ones, and the flags are laid
+/// out differently, which is why the v2.4 parsers can't. Each frame is decoded by `decode_frame`, so IPLS, RVAD, and
+/// EQUA come out as TIPL, RVA2, and EQU2. The date frames need each other to be converted, so they're kept as
+/// `Unknown` frames, for `convert::convert` to merge.
+///
+/// Unsynchronization applies to the whole tag rather than each frame, so the frames of an unsynchronized tag have no
+/// position.
+pub(super) fn read_frames(
+   bytes: &[u8],
+   frames_offset: u32,
+   unsynchronized: bool,
+   options: &ParseOptions,
+) -> Vec<Result<Frame<'static>, FrameParseError>> {
+   let resynchronized;
+   let bytes = if unsynchronized {
+      resynchronized = resynchronize(bytes);
+      &resynchronized[..]
+   } else {
+      bytes
+   };
+
+   // Each frame header is a 4 character ID, a 32 bit size, and 2 bytes of flags
+   let mut frames = Vec::new();
+   let mut pos = 0;
+   while let Some(header) = bytes.get(pos..pos + 10) {
+      let mut name = [0u8; 4];
+      name.copy_from_slice(&header[0..4]);
+      if &name == b"\0\0\0\0" {
+         // Padding
+         break;
+      }
+      if !v24::is_valid_frame_id(&name) {
+         if options.strictness == Strictness::Strict {
+            frames.push(Err(FrameParseError {
+               name,
+               reason: FrameParseErrorReason::InvalidFrameId,
+            }));
+         }
+         break;
+      }
+
+      let size = BigEndian::read_u32(&header[4..8]) as usize;
+      let flags = FrameFlags::from_bits_truncate(BigEndian::read_u16(&header[8..10]));
+      let frame_start = pos;
+      pos = pos.saturating_add(10).saturating_add(size);
+      let payload = match bytes.get(frame_start + 10..pos) {
+         Some(v) => v,
+         None => {
+            frames.push(Err(FrameParseError {
+               name,
+               reason: FrameParseErrorReason::Truncated,
+            }));
+            break;
+         }
+      };
+      if !options.wants_frame(&name) {
+         continue;
+      }
+
+      let frame = read_frame(name, flags, payload, options).map_err(|reason| FrameParseError { name, reason });
+      frames.push(frame.map(|frame| {
+         if unsynchronized {
+            frame
+         } else {
+            Frame {
+               offset: frames_offset.saturating_add(frame_start as u32),
+               size: (10 + size) as u32,
+               ..frame
+            }
+         }
+      }));
+   }
+   frames
+}
+
+fn read_frame(
+   name: [u8; 4],
+   flags: FrameFlags,
+   payload: &[u8],
+   options: &ParseOptions,
+) -> Result<Frame<'static>, FrameParseErrorReason> {
+   // The bytes the format flags add come before the payload: the decompressed size, encryption method, then group
+   let mut rest = payload;
+   let mut take = |n: usize| {
+      if rest.len() < n {
+         return Err(FrameParseErrorReason::FrameTooSmall);
+      }
+      let (taken, left) = rest.split_at(n);
+      rest = left;
+      Ok(taken)
+   };
+   let data_length = if flags.contains(FrameFlags::COMPRESSION) {
+      Some(BigEndian::read_u32(take(4)?))
+   } else {
+      None
+   };
+   let encryption_method = if flags.contains(FrameFlags::ENCRYPTION) {
+      Some(take(1)?[0])
+   } else {
+      None
+   };
+   let group = if flags.contains(FrameFlags::GROUPING_IDENTITY) {
+      Some(take(1)?[0])
+   } else {
+      None
+   };
+
+   let data = if flags.intersects(FrameFlags::COMPRESSION | FrameFlags::ENCRYPTION) {
+      let extra = HeaderExtra {
+         group,
+         encryption_method,
+         data_length,
+      };
+      v24::opaque_frame(name, &extra, rest)
+   } else {
+      match decode_frame(name, rest, options) {
+         Ok(data) => data.into_owned(),
+         Err(_) if options.keep_undecodable => FrameData::Unknown(Unknown {
+            name,
+            data: Cow::Owned(rest.to_vec()),
+         }),
+         Err(e) => return Err(e),
+      }
+   };
+   Ok(Frame {
+      group,
+      flags: flags.to_v24(),
+      ..Frame::new(data)
+   })
+}
+
+// (RVA2 channel, increment bit, adjustment field, peak field)
+// The fields are right, left, peak right, peak left, then the optional back, centre, and bass groups
+const RVAD_CHANNELS: [(u8, u8, usize, usize); 6] = [
+   (2, 0, 0, 2),
+   (3, 1, 1, 3),
+   (4, 2, 4, 6),
+   (5, 3, 5, 7),
+   (6, 4, 8, 9),
+   (8, 5, 10, 11),
+];
+
+fn decode_rvad_frame(frame: &[u8]) -> Result<Rva2<'static>, FrameParseErrorReason> {
+   if frame.len() < 2 {
+      return Err(FrameParseErrorReason::FrameTooSmall);
+   }
+   let increments = frame[0];
+   let bits = frame[1];
+   if bits == 0 || bits > 64 {
+      return Err(FrameParseErrorReason::InvalidBitWidth(bits));
+   }
+
+   let width = (bits as usize + 7) / 8;
+   let values: Vec<u64> = frame[2..]
+      .chunks_exact(width)
+      .map(|x| BigEndian::read_uint(x, width))
+      .collect();
+   if values.len() < 2 {
+      return Err(FrameParseErrorReason::FrameTooSmall);
+   }
+
+   let channels = RVAD_CHANNELS
+      .iter()
+      .filter(|(_, _, adjustment, _)| *adjustment < values.len())
+      .map(|(channel, increment_bit, adjustment, peak)| Rva2Channel {
+         channel: *channel,
+         adjustment: fraction_to_db(values[*adjustment], bits, increments & (1 << increment_bit) != 0),
+         peak: values.get(*peak).map(|x| (*x as f64 / max_value(bits)) as f32),
+      })
+      .collect();
+
+   // v2.3 has no identification, and the frame was only ever written for the track
+   Ok(Rva2 {
+      identification: Cow::Borrowed("track"),
+      channels,
+   })
+}
+
+// Each point is the increment bit and a 15 bit frequency in Hz, then the adjustment
+fn decode_equa_frame(frame: &[u8]) -> Result<Equ2<'static>, FrameParseErrorReason> {
+   if frame.is_empty() {
+      return Err(FrameParseErrorReason::FrameTooSmall);
+   }
+   let bits = frame[0];
+   if bits == 0 || bits > 64 {
+      return Err(FrameParseErrorReason::InvalidBitWidth(bits));
+   }
+
+   let width = (bits as usize + 7) / 8;
+   let rest = &frame[1..];
+   if rest.len() % (2 + width) != 0 {
+      return Err(FrameParseErrorReason::FrameTooSmall);
+   }
+   let points = rest
+      .chunks_exact(2 + width)
+      .map(|x| {
+         let frequency = BigEndian::read_u16(&x[0..2]);
+         Equ2Point {
+            frequency: f32::from(frequency & 0x7fff),
+            adjustment: fraction_to_db(BigEndian::read_uint(&x[2..], width), bits, frequency & 0x8000 != 0),
+         }
+      })
+      .collect();
+
+   // Each adjustment applies from its frequency up to the next one
+   Ok(Equ2 {
+      interpolation: 0,
+      identification: Cow::Borrowed(""),
+      points,
+   })
+}
+
+/// Encodes the body of an RVAD frame, using 16 bit values. The master volume channel stands in for the left and
+/// right channels when they are missing, and channels that RVAD can't hold are left out.
+pub(super) fn encode_rvad_frame(rva2: &Rva2) -> Vec<u8> {
+   let find = |channel: u8| rva2.channels.iter().find(|x| x.channel == channel);
+   let master = find(1);
+   let slots: Vec<Option<&Rva2Channel>> = RVAD_CHANNELS
+      .iter()
+      .map(|(channel, ..)| find(*channel).or(if *channel == 2 || *channel == 3 { master } else { None }))
+      .collect();
+
+   // The groups are right/left, back right/left, centre, and bass, and a group requires the ones before it
+   let used = match slots.iter().rposition(Option::is_some).unwrap_or(0) {
+      0..=1 => 2,
+      2..=3 => 4,
+      x => x + 1,
+   };
+
+   let mut increments = 0;
+   let mut values = [0u64; 12];
+   for (slot, (_, increment_bit, adjustment, peak)) in slots.iter().zip(RVAD_CHANNELS.iter()).take(used) {
+      if let Some(channel) = slot {
+         let (value, increment) = db_to_fraction(channel.adjustment, 16);
+         if increment {
+            increments |= 1 << increment_bit;
+         }
+         values[*adjustment] = value;
+         values[*peak] = (f64::from(channel.peak.unwrap_or(0.0)).max(0.0).min(1.0) * max_value(16)).round() as u64;
+      }
+   }
+
+   let mut frame = vec![increments, 16];
+   // Each channel has an adjustment and a peak
+   for value in values.iter().take(used * 2) {
+      frame.extend_from_slice(&(*value as u16).to_be_bytes());
+   }
+   frame
+}
+
+/// Encodes the body of an EQUA frame, using 16 bit values. Frequencies above 32767 Hz can't be stored and are left out.
+pub(super) fn encode_equa_frame(equ2: &Equ2) -> Vec<u8> {
+   let mut frame = vec![16];
+   for point in equ2
+      .points
+      .iter()
+      .filter(|x| x.frequency >= 0.0 && x.frequency < 32767.5)
+   {
+      let (value, increment) = db_to_fraction(point.adjustment, 16);
+      let frequency = point.frequency.round() as u16 | if increment { 0x8000 } else { 0 };
+      frame.extend_from_slice(&frequency.to_be_bytes());
+      frame.extend_from_slice(&(value as u16).to_be_bytes());
+   }
+   frame
+}
+
+// The inverse of `fraction_to_db`, clamped to what fits in the given number of bits
+fn db_to_fraction(db: f32, bits: u8) -> (u64, bool) {
+   let factor = 10f64.powf(f64::from(db) / 20.0);
+   let fraction = (factor - 1.0).abs().min(1.0);
+   ((fraction * max_value(bits)).round() as u64, factor >= 1.0)
+}
+
+// v2.3 doesn't give the adjustments a unit. Like most taggers, we read them as a fraction of full scale.
+fn fraction_to_db(value: u64, bits: u8, increment: bool) -> f32 {
+   let fraction = value as f64 / max_value(bits);
+   let factor = if increment { 1.0 + fraction } else { 1.0 - fraction };
+   (20.0 * factor.log10()) as f32
+}
+
+fn max_value(bits: u8) -> f64 {
+   2f64.powi(i32::from(bits)) - 1.0
+}
+
+/// Builds a v2.4 style recording date out of the v2.3 TYER (yyyy), TDAT (DDMM), and TIME (HHMM) frames.
+/// TRDA is free text like "4th-7th June 1996", so it is only used for the year, when TYER is missing.
+/// The day and time are dropped if they don't parse, as they are meaningless without the year.
+pub fn recording_date(tyer: Option<&str>, tdat: Option<&str>, time: Option<&str>, trda: Option<&str>) -> Option<Date> {
+   let year = match tyer {
+      Some(tyer) => parse_digits(tyer.trim())?,
+      None => year_in_text(trda?)?,
+   };
+
+   let (day, month) = match tdat.and_then(|x| split_pair(x.trim())) {
+      Some((day, month)) => (Some(day), Some(month)),
+      None => (None, None),
+   };
+   let (hour, minutes) = match time.and_then(|x| split_pair(x.trim())) {
+      Some((hour, minutes)) => (Some(hour), Some(minutes)),
+      None => (None, None),
+   };
+
+   Some(Date {
+      year,
+      month,
+      day,
+      hour,
+      minutes,
+      seconds: None,
+   })
+}
+
+/// Builds a v2.4 style original release date (TDOR) out of the v2.3 TORY frame, which only holds the year
+pub fn original_release_date(tory: &str) -> Option<Date> {
+   Some(Date {
+      year: parse_digits(tory.trim())?,
+      month: None,
+      day: None,
+      hour: None,
+      minutes: None,
+      seconds: None,
+   })
+}
+
+// Only a plain run of digits, so "+123" and "12 4" are rejected
+fn parse_digits<T: FromStr>(text: &str) -> Option<T> {
+   if text.is_empty() || !text.bytes().all(|x| x.is_ascii_digit()) {
+      return None;
+   }
+   text.parse().ok()
+}
+
+// "DDMM" or "HHMM"
+fn split_pair(text: &str) -> Option<(u8, u8)> {
+   if text.len() != 4 {
+      return None;
+   }
+   Some((parse_digits(&text[0..2])?, parse_digits(&text[2..4])?))
+}
+
+fn year_in_text(text: &str) -> Option<u16> {
+   text
+      .split(|c: char| !c.is_ascii_digit())
+      .find(|x| x.len() == 4)
+      .and_then(parse_digits)
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+   #[cfg(test)]
+   use crate::id3::convert::{convert, Version};
+   #[cfg(test)]
+   use crate::id3::{parse_source, Id3Tag, TagParseError};
+
+   #[test]
+   fn v23_dates() {
+      let date = recording_date(Some("1996"), Some("0406"), Some("2130"), None).unwrap();
+      assert_eq!(
+         (date.year, date.month, date.day, date.hour, date.minutes),
+         (1996, Some(6), Some(4), Some(21), Some(30))
+      );
+
+      let date = recording_date(None, Some("bad"), None, Some("4th-7th June 1996")).unwrap();
+      assert_eq!((date.year, date.month, date.day), (1996, None, None));
+
+      assert!(recording_date(None, Some("0406"), None, None).is_none());
+      assert_eq!(original_release_date("1972").unwrap().year, 1972);
+      assert!(original_release_date("72?").is_none());
+   }
+
+   #[test]
+   fn v23_only_frames() {
+      let options = ParseOptions::default();
+
+      match decode_frame(*b"IPLS", b"\x00producer\x00Someone\x00", &options).unwrap() {
+         FrameData::TIPL(x) => assert_eq!(x.get("producer").map(|x| x.as_ref()), Some("Someone")),
+         x => panic!("{:?}", x),
+      }
+
+      // Right up by half of full scale, left down by half, and peaks of 0xffff
+      let rvad = b"\x01\x10\x7f\xff\x7f\xff\xff\xff\xff\xff";
+      match decode_frame(*b"RVAD", rvad, &options).unwrap() {
+         FrameData::RVA2(x) => {
+            assert_eq!(x.channels.len(), 2);
+            assert_eq!((x.channels[0].channel, x.channels[1].channel), (2, 3));
+            assert!((x.channels[0].adjustment - 3.52).abs() < 0.01);
+            assert!((x.channels[1].adjustment + 6.02).abs() < 0.01);
+            assert_eq!(x.channels[0].peak, Some(1.0));
+         }
+         x => panic!("{:?}", x),
+      }
+
+      match decode_frame(*b"EQUA", b"\x10\x83\xe8\x00\x00", &options).unwrap() {
+         FrameData::EQU2(x) => {
+            assert_eq!(x.points.len(), 1);
+            assert_eq!(x.points[0].frequency, 1000.0);
+            assert_eq!(x.points[0].adjustment, 0.0);
+         }
+         x => panic!("{:?}", x),
+      }
+      assert!(decode_frame(*b"EQUA", b"\x10\x83\xe8\x00", &options).is_err());
+   }
+
+   #[test]
+   fn read_v23_tag() {
+      let mut frames = Vec::new();
+      let mut push = |name: &[u8; 4], flags: u16, data: &[u8]| {
+         frames.extend_from_slice(name);
+         frames.extend_from_slice(&(data.len() as u32).to_be_bytes());
+         frames.extend_from_slice(&flags.to_be_bytes());
+         frames.extend_from_slice(data);
+      };
+      // 129 bytes, which isn't a synchsafe size
+      let title = format!("\0{}", "a".repeat(128));
+      push(b"TIT2", 0, title.as_bytes());
+      push(b"TYER", 0, b"\01996");
+      push(b"IPLS", 0, b"\0producer\0Someone\0");
+      // Grouped, and flagged to be discarded if the file changes
+      push(b"TPE1", 0x4020, b"\x07\0Artist");
+      assert_eq!(frames.len(), 200);
+      // The tag size is still synchsafe, and covers 10 bytes of padding
+      let mut bytes = vec![b'I', b'D', b'3', 3, 0, 0, 0, 0, 1, 82];
+      bytes.extend(frames);
+      bytes.resize(bytes.len() + 10, 0);
+
+      match parse_source(&mut &bytes[..], ParseOptions::default()) {
+         Err(TagParseError::UnsupportedVersion(3)) => (),
+         x => panic!("{:?}", x.map(|_| ())),
+      }
+      let tag = Id3Tag::read(&mut &bytes[..], ParseOptions::default()).unwrap();
+      let names: Vec<[u8; 4]> = tag.frames.iter().map(|x| x.data.name()).collect();
+      assert_eq!(names, vec![*b"TIT2", *b"TYER", *b"TIPL", *b"TPE1"]);
+      match &tag.frames[0].data {
+         FrameData::TIT2(x) => assert_eq!(x[0].len(), 128),
+         x => panic!("{:?}", x),
+      }
+      assert_eq!(tag.frames[1].byte_range(0), 149..164);
+      assert_eq!(tag.frames[3].group, Some(7));
+      assert_eq!(
+         tag.frames[3].flags,
+         v24::FrameFlags::FILE_ALTER_PRESERVATION | v24::FrameFlags::GROUPING_IDENTITY
+      );
+
+      let v24 = convert(tag, Version::V24);
+      assert!(v24.dropped.is_empty());
+      let names: Vec<[u8; 4]> = v24.tag.frames.iter().map(|x| x.data.name()).collect();
+      assert_eq!(names, vec![*b"TIT2", *b"TIPL", *b"TPE1", *b"TDRC"]);
+   }
+}
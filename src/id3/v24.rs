@@ -1,14 +1,30 @@
-use super::synchsafe_u32_to_u32;
+use super::synchsafe::{is_synchsafe, synchsafe_u32_to_u32, u32_to_synchsafe_u32};
+use super::values::Values;
+use super::write::Picture;
+use super::{resynchronize, FrameId, Latin1Decoding, ParseOptions, Strictness};
+use crate::genre;
 use bitflags::bitflags;
-use byteorder::{BigEndian, ByteOrder};
+#[cfg(feature = "arena")]
+use bumpalo::Bump as Arena;
+use byteorder::{BigEndian, ByteOrder, LittleEndian, NativeEndian};
+use memchr::memchr;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom};
 use std::num::ParseIntError;
+use std::ops::Range;
 use std::str::{FromStr, Utf8Error};
 use std::string::FromUtf16Error;
 
 bitflags! {
-   pub(super) struct FrameFlags: u16 {
+   /// The status and format flags of a frame header
+   #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+   pub struct FrameFlags: u16 {
       // Status
       const TAG_ALTER_PRESERVATION = 0b0100_0000_0000_0000;
       const FILE_ALTER_PRESERVATION = 0b0010_0000_0000_0000;
@@ -40,108 +56,1376 @@ bitflags! {
    }
 }
 
+/// Limits the tagger promised to respect when writing the tag, from the extended header
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TagRestrictions {
+   pub max_frames: u32,
+   /// In bytes
+   pub max_tag_size: u32,
+   /// If set, text is only encoded as ISO-8859-1 or UTF-8
+   pub latin1_or_utf8_only: bool,
+   /// In characters
+   pub max_text_len: Option<u32>,
+   /// If set, images are only PNG or JPEG
+   pub png_or_jpeg_only: bool,
+   pub image_size: ImageSizeRestriction,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ImageSizeRestriction {
+   None,
+   /// At most 256x256 pixels
+   Max256,
+   /// At most 64x64 pixels
+   Max64,
+   /// Exactly 64x64 pixels, unless required otherwise
+   Exactly64,
+}
+
+impl From<u8> for TagRestrictions {
+   // The byte is %ppqrrstt
+   fn from(byte: u8) -> TagRestrictions {
+      let (max_frames, max_tag_size) = match byte >> 6 {
+         0b00 => (128, 1024 * 1024),
+         0b01 => (64, 128 * 1024),
+         0b10 => (32, 40 * 1024),
+         _ => (32, 4 * 1024),
+      };
+      let max_text_len = match (byte >> 3) & 0b11 {
+         0b00 => None,
+         0b01 => Some(1024),
+         0b10 => Some(128),
+         _ => Some(30),
+      };
+      let image_size = match byte & 0b11 {
+         0b00 => ImageSizeRestriction::None,
+         0b01 => ImageSizeRestriction::Max256,
+         0b10 => ImageSizeRestriction::Max64,
+         _ => ImageSizeRestriction::Exactly64,
+      };
+
+      TagRestrictions {
+         max_frames,
+         max_tag_size,
+         latin1_or_utf8_only: byte & 0b0010_0000 != 0,
+         max_text_len,
+         png_or_jpeg_only: byte & 0b0000_0100 != 0,
+         image_size,
+      }
+   }
+}
+
+// The cursor and settings shared by the owned and borrowed parsers
+struct ParseState {
+   cursor: usize,
+   // Where the frame area starts, relative to the start of the tag
+   frames_offset: u32,
+   unsynchronized: bool,
+   options: ParseOptions,
+   // Measured once the frames run out
+   padding: Option<u32>,
+}
+
 pub(super) struct Parser {
    content: Box<[u8]>,
-   cursor: usize,
+   state: ParseState,
 }
 
 impl Parser {
-   pub fn new(content: Box<[u8]>) -> Parser {
-      Parser { content, cursor: 0 }
+   pub fn padding(&self) -> Option<u32> {
+      self.state.padding
+   }
+
+   pub fn new(content: Box<[u8]>, frames_offset: u32, unsynchronized: bool, options: ParseOptions) -> Parser {
+      Parser {
+         content,
+         state: ParseState {
+            cursor: 0,
+            frames_offset,
+            unsynchronized,
+            options,
+            padding: None,
+         },
+      }
+   }
+}
+
+impl Iterator for Parser {
+   type Item = Result<Frame<'static>, FrameParseError>;
+
+   fn next(&mut self) -> Option<Result<Frame<'static>, FrameParseError>> {
+      self
+         .state
+         .next_frame(&self.content, None)
+         .map(|x| x.map(Frame::into_owned))
+   }
+}
+
+pub(super) struct SliceParser<'a> {
+   content: &'a [u8],
+   state: ParseState,
+}
+
+impl<'a> SliceParser<'a> {
+   pub fn padding(&self) -> Option<u32> {
+      self.state.padding
+   }
+
+   pub fn new(content: &'a [u8], frames_offset: u32, unsynchronized: bool, options: ParseOptions) -> SliceParser<'a> {
+      SliceParser {
+         content,
+         state: ParseState {
+            cursor: 0,
+            frames_offset,
+            unsynchronized,
+            options,
+            padding: None,
+         },
+      }
+   }
+
+   #[cfg(feature = "arena")]
+   pub fn next_in(&mut self, arena: &'a Arena) -> Option<Result<Frame<'a>, FrameParseError>> {
+      self.state.next_frame(self.content, Some(arena))
+   }
+}
+
+impl<'a> Iterator for SliceParser<'a> {
+   type Item = Result<Frame<'a>, FrameParseError>;
+
+   fn next(&mut self) -> Option<Result<Frame<'a>, FrameParseError>> {
+      self.state.next_frame(self.content, None)
+   }
+}
+
+pub(super) struct RawParser<'a> {
+   content: &'a [u8],
+   state: ParseState,
+}
+
+impl<'a> RawParser<'a> {
+   pub fn padding(&self) -> Option<u32> {
+      self.state.padding
+   }
+
+   pub fn new(content: &'a [u8], frames_offset: u32, unsynchronized: bool, options: ParseOptions) -> RawParser<'a> {
+      RawParser {
+         content,
+         state: ParseState {
+            cursor: 0,
+            frames_offset,
+            unsynchronized,
+            options,
+            padding: None,
+         },
+      }
    }
 }
 
+impl<'a> Iterator for RawParser<'a> {
+   type Item = Result<RawFrame<'a>, FrameParseError>;
+
+   fn next(&mut self) -> Option<Result<RawFrame<'a>, FrameParseError>> {
+      self.state.next_raw_frame(self.content)
+   }
+}
+
+pub(super) struct StreamingParser<S> {
+   source: S,
+   size_of_frames: u64,
+   frames_offset: u32,
+   // Bytes of the frame area between the current position of the source and the end of the tag
+   remaining: u64,
+   // The frame whose header was returned last, if its payload hasn't been read or skipped yet
+   pending: Option<PendingFrame>,
+   unsynchronized: bool,
+   options: ParseOptions,
+   padding: Option<u32>,
+}
+
+struct PendingFrame {
+   header: FrameHeader,
+   extra: HeaderExtra,
+   offset: u32,
+   stored_size: u32,
+}
+
+impl<S: Read + Seek> StreamingParser<S> {
+   pub fn new(
+      source: S,
+      size_of_frames: u32,
+      frames_offset: u32,
+      unsynchronized: bool,
+      options: ParseOptions,
+   ) -> StreamingParser<S> {
+      StreamingParser {
+         source,
+         size_of_frames: u64::from(size_of_frames),
+         frames_offset,
+         remaining: u64::from(size_of_frames),
+         pending: None,
+         unsynchronized,
+         options,
+         padding: None,
+      }
+   }
+
+   pub fn padding(&self) -> Option<u32> {
+      self.padding
+   }
+
+   pub fn next_header(&mut self) -> Option<Result<FrameHeader, FrameParseError>> {
+      if let Some(frame) = self.pending.take() {
+         let size = u64::from(frame.header.size);
+         if let Err(e) = self.source.seek(SeekFrom::Current(size as i64)) {
+            return self.stop_with_error(frame.header.name, e.into());
+         }
+         self.remaining -= size;
+      }
+
+      // Each frame must be at least 10 bytes
+      if self.remaining < 10 {
+         if self.options.strictness == Strictness::Strict {
+            let mut rest = [0u8; 10];
+            let rest = &mut rest[..self.remaining as usize];
+            if let Err(e) = self.source.read_exact(rest) {
+               return self.stop_with_error([0; 4], e.into());
+            }
+            if !looks_like_frame_start(rest) {
+               return self.stop_with_error([0; 4], FrameParseErrorReason::TrailingJunk);
+            }
+         }
+         self.padding = Some(self.remaining as u32);
+         return None;
+      }
+
+      let frame_start = self.size_of_frames - self.remaining;
+      let mut header = [0u8; 10];
+      if let Err(e) = self.source.read_exact(&mut header) {
+         return self.stop_with_error([0; 4], e.into());
+      }
+      self.remaining -= 10;
+
+      let mut name: [u8; 4] = [0; 4];
+      name.copy_from_slice(&header[0..4]);
+      if &name == b"\0\0\0\0" {
+         // Padding
+         let padding = self.remaining as u32 + 10;
+         if self.options.strictness == Strictness::Strict {
+            match self.rest_is_padding(&header[4..]) {
+               Ok(true) => (),
+               Ok(false) => return self.stop_with_error(name, FrameParseErrorReason::TrailingJunk),
+               Err(e) => return self.stop_with_error(name, e.into()),
+            }
+         }
+         self.padding = Some(padding);
+         return None;
+      }
+
+      if !is_valid_frame_id(&name) {
+         // We've run into garbage, and there's no way to know where the next frame is
+         return match self.options.strictness {
+            Strictness::Strict => self.stop_with_error(name, FrameParseErrorReason::InvalidFrameId),
+            Strictness::Lenient => None,
+         };
+      }
+
+      let raw_size = BigEndian::read_u32(&header[4..8]);
+      let strictness = self.options.strictness;
+      let frame_size = match decode_frame_size(raw_size, strictness, |size| self.is_frame_boundary_at(size)) {
+         Ok(v) => v,
+         Err(reason) => return self.stop_with_error(name, reason),
+      };
+      let flags = FrameFlags::from_bits_truncate(BigEndian::read_u16(&header[8..10]));
+
+      if u64::from(frame_size) > self.remaining {
+         return self.stop_with_error(name, FrameParseErrorReason::Truncated);
+      }
+
+      let extra_len = HeaderExtra::len(flags);
+      if (frame_size as usize) < extra_len {
+         // Skip the frame, so the next one is still found
+         if let Err(e) = self.source.seek(SeekFrom::Current(i64::from(frame_size))) {
+            return self.stop_with_error(name, e.into());
+         }
+         self.remaining -= u64::from(frame_size);
+         return Some(Err(FrameParseError {
+            reason: FrameParseErrorReason::FrameTooSmall,
+            name,
+         }));
+      }
+      let mut extra_bytes = [0u8; HeaderExtra::MAX_LEN];
+      let extra_bytes = &mut extra_bytes[..extra_len];
+      if let Err(e) = self.source.read_exact(extra_bytes) {
+         return self.stop_with_error(name, e.into());
+      }
+      self.remaining -= extra_len as u64;
+      let extra = HeaderExtra::parse(flags, extra_bytes);
+      let payload_size = frame_size - extra_len as u32;
+
+      let header = FrameHeader {
+         name,
+         size: payload_size,
+         flags,
+      };
+      self.pending = Some(PendingFrame {
+         header,
+         extra,
+         offset: self.frames_offset + frame_start as u32,
+         stored_size: 10 + frame_size,
+      });
+      Some(Ok(header))
+   }
+
+   pub fn read_frame(&mut self) -> Option<Result<Frame<'static>, FrameParseError>> {
+      let frame = self.pending.take()?;
+      let name = frame.header.name;
+
+      // Like read_frames, we don't trust the claimed size enough to allocate it up front
+      let mut frame_bytes = Vec::new();
+      let size = u64::from(frame.header.size);
+      if let Err(e) = (&mut self.source).take(size).read_to_end(&mut frame_bytes) {
+         return self.stop_with_error(name, e.into());
+      }
+      if (frame_bytes.len() as u64) < size {
+         return self.stop_with_error(name, FrameParseErrorReason::Truncated);
+      }
+      self.remaining -= size;
+
+      if self.unsynchronized || frame.header.flags.contains(FrameFlags::UNSYNCHRONIZATION) {
+         frame_bytes = resynchronize(&frame_bytes);
+      }
+      let result = if frame
+         .header
+         .flags
+         .intersects(FrameFlags::COMPRESSION | FrameFlags::ENCRYPTION)
+      {
+         Ok(opaque_frame(name, &frame.extra, &frame_bytes))
+      } else {
+         match decode_frame(name, &frame_bytes, &self.options) {
+            Err(_) if self.options.keep_undecodable => Ok(FrameData::Unknown(Unknown {
+               name,
+               data: Cow::Borrowed(&frame_bytes),
+            })),
+            result => result,
+         }
+      };
+
+      Some(
+         result
+            .map(|data| Frame {
+               data: data.into_owned(),
+               group: frame.extra.group,
+               flags: frame.header.flags,
+               offset: frame.offset,
+               size: frame.stored_size,
+            })
+            .map_err(|e| FrameParseError { name, reason: e }),
+      )
+   }
+
+   // Peeks at the bytes following a payload of the given size, then seeks back to where we were
+   fn is_frame_boundary_at(&mut self, size: u32) -> bool {
+      let size = u64::from(size);
+      if size > self.remaining {
+         return false;
+      }
+
+      let mut next = [0u8; 4];
+      let next = &mut next[..std::cmp::min(4, self.remaining - size) as usize];
+      let start = match self.source.seek(SeekFrom::Current(0)) {
+         Ok(v) => v,
+         Err(_) => return false,
+      };
+      let peeked = self
+         .source
+         .seek(SeekFrom::Start(start + size))
+         .and_then(|_| self.source.read_exact(next));
+      if self.source.seek(SeekFrom::Start(start)).is_err() {
+         // We don't know where we are anymore, so don't try to read any further
+         self.remaining = 0;
+      }
+
+      peeked.is_ok() && looks_like_frame_start(next)
+   }
+
+   // `seen` is whatever part of the padding has already been read
+   fn rest_is_padding(&mut self, seen: &[u8]) -> io::Result<bool> {
+      if seen.iter().any(|x| *x != 0) {
+         return Ok(false);
+      }
+
+      let mut buf = [0u8; 512];
+      while self.remaining > 0 {
+         let chunk = &mut buf[..std::cmp::min(512, self.remaining) as usize];
+         self.source.read_exact(chunk)?;
+         self.remaining -= chunk.len() as u64;
+         if chunk.iter().any(|x| *x != 0) {
+            return Ok(false);
+         }
+      }
+      Ok(true)
+   }
+
+   fn stop_with_error<T>(
+      &mut self,
+      name: [u8; 4],
+      reason: FrameParseErrorReason,
+   ) -> Option<Result<T, FrameParseError>> {
+      self.remaining = 0;
+      self.pending = None;
+      Some(Err(FrameParseError { name, reason }))
+   }
+}
+
+impl<S: Read + Seek> Iterator for StreamingParser<S> {
+   type Item = Result<Frame<'static>, FrameParseError>;
+
+   fn next(&mut self) -> Option<Result<Frame<'static>, FrameParseError>> {
+      loop {
+         match self.next_header()? {
+            Ok(header) if !self.options.wants_frame(&header.name) => continue,
+            Ok(_) => return self.read_frame(),
+            Err(e) => return Some(Err(e)),
+         }
+      }
+   }
+}
+
+// Where the arena given to `SliceParser::next_in` would be, if the arena feature were enabled
+#[cfg(not(feature = "arena"))]
+enum Arena {}
+
+/// The options, plus where decoded text and resynchronized payloads go when they can't borrow from the tag: the
+/// arena, if the frames are being parsed into one, or otherwise the heap
+#[derive(Copy, Clone)]
+pub(super) struct DecodeOptions<'o, 'a> {
+   options: &'o ParseOptions,
+   arena: Option<&'a Arena>,
+}
+
+impl<'o, 'a> DecodeOptions<'o, 'a> {
+   pub fn new(options: &'o ParseOptions) -> DecodeOptions<'o, 'a> {
+      DecodeOptions { options, arena: None }
+   }
+
+   fn alloc_chars<I: Iterator<Item = char>>(&self, chars: I) -> Cow<'a, str> {
+      #[cfg(feature = "arena")]
+      {
+         if let Some(arena) = self.arena {
+            let mut text = bumpalo::collections::String::new_in(arena);
+            text.extend(chars);
+            return Cow::Borrowed(text.into_bump_str());
+         }
+      }
+      Cow::Owned(chars.collect())
+   }
+
+   fn alloc_transcoded(&self, bytes: &[u8], to_char: fn(u8) -> char) -> Cow<'a, str> {
+      #[cfg(feature = "arena")]
+      {
+         if let Some(arena) = self.arena {
+            let utf8 = arena.alloc_slice_fill_copy(transcoded_len(bytes, to_char), 0);
+            transcode_into(bytes, to_char, utf8);
+            return Cow::Borrowed(unsafe { std::str::from_utf8_unchecked(utf8) });
+         }
+      }
+      Cow::Owned(transcode(bytes, to_char))
+   }
+
+   fn alloc_str(&self, text: Cow<'a, str>) -> Cow<'a, str> {
+      match (text, self.arena) {
+         #[cfg(feature = "arena")]
+         (Cow::Owned(text), Some(arena)) => Cow::Borrowed(arena.alloc_str(&text)),
+         (text, _) => text,
+      }
+   }
+
+   fn resynchronize(&self, bytes: &[u8]) -> Cow<'a, [u8]> {
+      #[cfg(feature = "arena")]
+      {
+         if let Some(arena) = self.arena {
+            let mut resynchronized = bumpalo::collections::Vec::with_capacity_in(bytes.len(), arena);
+            resynchronized.extend(super::resynchronized_bytes(bytes));
+            return Cow::Borrowed(resynchronized.into_bump_slice());
+         }
+      }
+      Cow::Owned(resynchronize(bytes))
+   }
+}
+
+impl<'o, 'a> std::ops::Deref for DecodeOptions<'o, 'a> {
+   type Target = ParseOptions;
+
+   fn deref(&self) -> &ParseOptions {
+      self.options
+   }
+}
+
+impl ParseState {
+   fn read_frame_size(&self, content: &[u8], name: [u8; 4]) -> Result<u32, FrameParseError> {
+      let raw_size = BigEndian::read_u32(&content[self.cursor + 4..self.cursor + 8]);
+      let payload_start = self.cursor.saturating_add(10);
+      decode_frame_size(raw_size, self.options.strictness, |size| {
+         is_frame_boundary(content, payload_start.saturating_add(size as usize))
+      })
+      .map_err(|reason| FrameParseError { name, reason })
+   }
+
+   fn stop_with_error<T>(
+      &mut self,
+      content: &[u8],
+      name: [u8; 4],
+      reason: FrameParseErrorReason,
+   ) -> Option<Result<T, FrameParseError>> {
+      self.cursor = content.len();
+      Some(Err(FrameParseError { name, reason }))
+   }
+
+   // Whatever follows the last frame is padding, as long as it's all zeros
+   fn measure_padding(&mut self, content: &[u8]) {
+      let rest = content.get(self.cursor..).unwrap_or(&[]);
+      if rest.iter().all(|x| *x == 0) {
+         self.padding = Some(rest.len() as u32);
+      }
+   }
+
+   fn next_frame<'a>(
+      &mut self,
+      content: &'a [u8],
+      arena: Option<&'a Arena>,
+   ) -> Option<Result<Frame<'a>, FrameParseError>> {
+      // Frames the caller filtered out are skipped without being decoded
+      loop {
+         let raw = match self.next_raw_frame(content)? {
+            Ok(v) => v,
+            Err(e) => return Some(Err(e)),
+         };
+         if self.options.wants_frame(&raw.name) {
+            return Some(self.decode_raw_frame(raw, arena));
+         }
+      }
+   }
+
+   fn decode_raw_frame<'a>(&self, raw: RawFrame<'a>, arena: Option<&'a Arena>) -> Result<Frame<'a>, FrameParseError> {
+      let options = DecodeOptions {
+         options: &self.options,
+         arena,
+      };
+      let extra = HeaderExtra {
+         group: raw.group,
+         encryption_method: raw.encryption_method,
+         data_length: raw.data_length,
+      };
+      let result = if raw.flags.intersects(FrameFlags::COMPRESSION | FrameFlags::ENCRYPTION) {
+         Ok(opaque_frame(raw.name, &extra, &raw.raw_bytes()))
+      } else if raw.unsynchronized {
+         match options.resynchronize(raw.payload) {
+            Cow::Borrowed(resynchronized) => decode_frame_in(raw.name, resynchronized, &options),
+            Cow::Owned(resynchronized) => {
+               decode_frame_in(raw.name, &resynchronized, &options).map(FrameData::into_owned)
+            }
+         }
+      } else {
+         decode_frame_in(raw.name, raw.payload, &options)
+      };
+      let result = match result {
+         Err(_) if self.options.keep_undecodable => Ok(FrameData::Unknown(Unknown {
+            name: raw.name,
+            data: raw.raw_bytes(),
+         })),
+         result => result,
+      };
+
+      result
+         .map(|data| Frame {
+            data,
+            group: raw.group,
+            flags: raw.flags,
+            offset: raw.offset,
+            size: raw.size,
+         })
+         .map_err(|e| FrameParseError {
+            name: raw.name,
+            reason: e,
+         })
+   }
+
+   fn next_raw_frame<'a>(&mut self, content: &'a [u8]) -> Option<Result<RawFrame<'a>, FrameParseError>> {
+      let frame_start = self.cursor;
+
+      // Each frame must be at least 10 bytes
+      if content.len().saturating_sub(self.cursor) < 10 {
+         if self.options.strictness == Strictness::Strict && !is_frame_boundary(content, self.cursor) {
+            return self.stop_with_error(content, [0; 4], FrameParseErrorReason::TrailingJunk);
+         }
+         self.measure_padding(content);
+         return None;
+      }
+
+      let mut name: [u8; 4] = [0; 4];
+      name.copy_from_slice(&content[self.cursor..self.cursor + 4]);
+      if &name == b"\0\0\0\0" {
+         // Padding
+         if self.options.strictness == Strictness::Strict && content[self.cursor..].iter().any(|x| *x != 0) {
+            return self.stop_with_error(content, name, FrameParseErrorReason::TrailingJunk);
+         }
+         self.measure_padding(content);
+         return None;
+      }
+
+      if !is_valid_frame_id(&name) {
+         // We've run into garbage, and there's no way to know where the next frame is
+         return match self.options.strictness {
+            Strictness::Strict => self.stop_with_error(content, name, FrameParseErrorReason::InvalidFrameId),
+            Strictness::Lenient => None,
+         };
+      }
+
+      let frame_size = match self.read_frame_size(content, name) {
+         Ok(v) => v,
+         Err(e) => return self.stop_with_error(content, e.name, e.reason),
+      };
+      let frame_flags_raw = BigEndian::read_u16(&content[self.cursor + 8..self.cursor + 10]);
+      let frame_flags = FrameFlags::from_bits_truncate(frame_flags_raw);
+
+      // The frame size covers the extra header bytes and the payload, so the next frame always starts here
+      let frame_end = self.cursor.saturating_add(10).saturating_add(frame_size as usize);
+      let frame_bytes = content.get(self.cursor + 10..frame_end);
+      self.cursor = frame_end;
+
+      let frame_bytes = match frame_bytes {
+         Some(v) => v,
+         None => {
+            return Some(Err(FrameParseError {
+               reason: FrameParseErrorReason::Truncated,
+               name,
+            }))
+         }
+      };
+      let extra_len = HeaderExtra::len(frame_flags);
+      if frame_bytes.len() < extra_len {
+         return Some(Err(FrameParseError {
+            reason: FrameParseErrorReason::FrameTooSmall,
+            name,
+         }));
+      }
+      let extra = HeaderExtra::parse(frame_flags, &frame_bytes[..extra_len]);
+
+      Some(Ok(RawFrame {
+         name,
+         flags: frame_flags,
+         group: extra.group,
+         encryption_method: extra.encryption_method,
+         data_length: extra.data_length,
+         offset: self.frames_offset.saturating_add(frame_start as u32),
+         size: (self.cursor - frame_start) as u32,
+         unsynchronized: self.unsynchronized || frame_flags.contains(FrameFlags::UNSYNCHRONIZATION),
+         payload: &frame_bytes[extra_len..],
+      }))
+   }
+}
+
+// The bytes that some format flags add between the frame header and the payload, in the order they're stored
+pub(super) struct HeaderExtra {
+   pub(super) group: Option<u8>,
+   pub(super) encryption_method: Option<u8>,
+   pub(super) data_length: Option<u32>,
+}
+
+impl HeaderExtra {
+   const MAX_LEN: usize = 6;
+
+   fn len(flags: FrameFlags) -> usize {
+      let mut len = 0;
+      if flags.contains(FrameFlags::GROUPING_IDENTITY) {
+         len += 1;
+      }
+      if flags.contains(FrameFlags::ENCRYPTION) {
+         len += 1;
+      }
+      if flags.contains(FrameFlags::DATA_LENGTH_INDICATOR) {
+         len += 4;
+      }
+      len
+   }
+
+   // `bytes` must be exactly `len(flags)` long
+   fn parse(flags: FrameFlags, mut bytes: &[u8]) -> HeaderExtra {
+      let mut take = |n: usize| {
+         let (taken, rest) = bytes.split_at(n);
+         bytes = rest;
+         taken
+      };
+      let group = if flags.contains(FrameFlags::GROUPING_IDENTITY) {
+         Some(take(1)[0])
+      } else {
+         None
+      };
+      let encryption_method = if flags.contains(FrameFlags::ENCRYPTION) {
+         Some(take(1)[0])
+      } else {
+         None
+      };
+      let data_length = if flags.contains(FrameFlags::DATA_LENGTH_INDICATOR) {
+         Some(synchsafe_u32_to_u32(BigEndian::read_u32(take(4))))
+      } else {
+         None
+      };
+
+      HeaderExtra {
+         group,
+         encryption_method,
+         data_length,
+      }
+   }
+}
+
+// We can't decompress or decrypt, so the payload is kept as it is, along with the encryption method and data length
+// that come before it. Written back with the same flags, the frame is unchanged.
+pub(super) fn opaque_frame(name: [u8; 4], extra: &HeaderExtra, payload: &[u8]) -> FrameData<'static> {
+   let mut data = Vec::with_capacity(HeaderExtra::MAX_LEN + payload.len());
+   data.extend(extra.encryption_method);
+   if let Some(len) = extra.data_length {
+      data.extend_from_slice(&u32_to_synchsafe_u32(len).unwrap_or(0).to_be_bytes());
+   }
+   data.extend_from_slice(payload);
+   FrameData::Unknown(Unknown {
+      name,
+      data: Cow::Owned(data),
+   })
+}
+
+// `is_boundary` reports whether a payload of the given size would end on a frame boundary
+fn decode_frame_size<F: FnMut(u32) -> bool>(
+   raw_size: u32,
+   strictness: Strictness,
+   mut is_boundary: F,
+) -> Result<u32, FrameParseErrorReason> {
+   if !is_synchsafe(raw_size) {
+      // Old versions of iTunes and FFmpeg write v2.3 style (plain) sizes in v2.4 tags
+      return match strictness {
+         Strictness::Strict => Err(FrameParseErrorReason::InvalidFrameSize),
+         Strictness::Lenient => Ok(raw_size),
+      };
+   }
+
+   let synchsafe_size = synchsafe_u32_to_u32(raw_size);
+   if strictness == Strictness::Lenient && !is_boundary(synchsafe_size) && is_boundary(raw_size) {
+      // The size happened to be a valid synchsafe integer, but it was written as a plain integer
+      return Ok(raw_size);
+   }
+
+   Ok(synchsafe_size)
+}
+
+// Whether the given position looks like the start of a frame, padding, or the end of the tag
+fn is_frame_boundary(content: &[u8], pos: usize) -> bool {
+   match content.get(pos..) {
+      Some(rest) => looks_like_frame_start(rest),
+      None => false,
+   }
+}
+
+fn looks_like_frame_start(rest: &[u8]) -> bool {
+   if rest.len() >= 4 {
+      &rest[0..4] == b"\0\0\0\0" || is_valid_frame_id(&rest[0..4])
+   } else {
+      rest.iter().all(|x| *x == 0)
+   }
+}
+
+pub(super) fn is_valid_frame_id(name: &[u8]) -> bool {
+   name.iter().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+}
+
+/// The part of a frame that can be read without reading its payload
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FrameHeader {
+   pub name: [u8; 4],
+   /// Size of the payload, excluding the group byte, encryption method, and data length indicator
+   pub size: u32,
+   pub flags: FrameFlags,
+}
+
+/// A frame that has been split out of the tag, but not decoded
+#[derive(Copy, Clone, Debug)]
+pub struct RawFrame<'a> {
+   pub name: [u8; 4],
+   pub flags: FrameFlags,
+   pub group: Option<u8>,
+   /// Refers to an ENCR frame. We can't decrypt, so an encrypted frame is decoded as an `Unknown` frame.
+   pub encryption_method: Option<u8>,
+   /// The size of the payload once decoded, from the data length indicator
+   pub data_length: Option<u32>,
+   /// Position of the frame header, relative to the start of the tag header
+   pub offset: u32,
+   /// Size of the frame as stored in the tag, including its header
+   pub size: u32,
+   /// Whether the payload has to be resynchronized before it can be decoded,
+   /// either because of the frame's flags or because the whole tag was unsynchronized
+   pub unsynchronized: bool,
+   /// The payload as stored, without the group byte, encryption method, and data length indicator
+   pub payload: &'a [u8],
+}
+
+impl<'a> RawFrame<'a> {
+   /// Where the frame is in the file, header included, given where the tag starts (see `id3::TagInfo::tag_start`)
+   pub fn byte_range(&self, tag_start: u64) -> Range<u64> {
+      byte_range(tag_start, self.offset, self.size)
+   }
+
+   /// The payload with unsynchronization undone, which is exactly what the frame decoders see.
+   /// Useful for decoding text yourself when its encoding can't be trusted.
+   pub fn raw_bytes(&self) -> Cow<'a, [u8]> {
+      if self.unsynchronized {
+         Cow::Owned(resynchronize(self.payload))
+      } else {
+         Cow::Borrowed(self.payload)
+      }
+   }
+}
+
+fn byte_range(tag_start: u64, offset: u32, size: u32) -> Range<u64> {
+   let start = tag_start + u64::from(offset);
+   start..start + u64::from(size)
+}
+
 #[derive(Clone, Debug)]
-pub struct Frame {
-   pub data: FrameData,
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Frame<'a> {
+   pub data: FrameData<'a>,
    pub group: Option<u8>,
+   pub flags: FrameFlags,
+   /// Position of the frame header, relative to the start of the tag header
+   pub offset: u32,
+   /// Size of the frame as stored in the tag, including its header
+   pub size: u32,
 }
 
 #[derive(Clone, Debug)]
-pub enum FrameData {
-   COMM(LangDescriptionText),
-   PRIV(Priv),
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum FrameData<'a> {
+   COMM(LangDescriptionText<'a>),
+   EQU2(Equ2<'a>),
+   MVIN(Values<Track>),
+   MVNM(Values<Cow<'a, str>>),
+   /// Marks the file as a podcast. The frame holds no value; it being there is the mark.
+   PCST,
+   PRIV(Priv<'a>),
+   RVA2(Rva2<'a>),
    RVRB(Reverb),
-   TALB(Vec<String>),
-   TBPM(Vec<u64>),
-   TCOM(Vec<String>),
-   TCON(Vec<String>),
-   TCOP(Vec<Copyright>),
-   TDEN(Vec<Date>),
-   TDLY(Vec<u64>),
-   TDOR(Vec<Date>),
-   TDRC(Vec<Date>),
-   TDRL(Vec<Date>),
-   TDTG(Vec<Date>),
-   TENC(Vec<String>),
-   TEXT(Vec<String>),
-   TIPL(HashMap<String, String>),
-   TIT1(Vec<String>),
-   TIT2(Vec<String>),
-   TIT3(Vec<String>),
-   TLEN(Vec<u64>),
-   TMCL(HashMap<String, String>),
-   TMOO(Vec<String>),
-   TOAL(Vec<String>),
-   TOFN(Vec<String>),
-   TOLY(Vec<String>),
-   TOPE(Vec<String>),
-   TOWN(Vec<String>),
-   TPE1(Vec<String>),
-   TPE2(Vec<String>),
-   TPE3(Vec<String>),
-   TPE4(Vec<String>),
-   TPOS(Vec<Track>),
-   TPRO(Vec<Copyright>),
-   TPUB(Vec<String>),
-   TRCK(Vec<Track>),
-   TRSN(Vec<String>),
-   TRSO(Vec<String>),
-   TSOA(Vec<String>),
-   TSOP(Vec<String>),
-   TSOT(Vec<String>),
-   TSRC(Vec<String>),
-   TSSE(Vec<String>),
-   TSST(Vec<String>),
-   TXXX(Txxx),
-   USLT(LangDescriptionText),
-   WCOM(String),
-   WCOP(String),
-   WOAF(String),
-   WOAR(String),
-   WOAS(String),
-   WORS(String),
-   WPAY(String),
-   WPUB(String),
-   Unknown(Unknown),
+   TALB(Values<Cow<'a, str>>),
+   TBPM(Values<u64>),
+   TCMP(bool),
+   TCOM(Values<Cow<'a, str>>),
+   TCON(Values<Cow<'a, str>>),
+   TCOP(Values<Copyright<'a>>),
+   TDEN(Values<Date>),
+   TDES(Values<Cow<'a, str>>),
+   TDLY(Values<u64>),
+   TDOR(Values<Date>),
+   TDRC(Values<Date>),
+   TDRL(Values<Date>),
+   TDTG(Values<Date>),
+   TENC(Values<Cow<'a, str>>),
+   TEXT(Values<Cow<'a, str>>),
+   TGID(Values<Cow<'a, str>>),
+   TIPL(HashMap<Cow<'a, str>, Cow<'a, str>>),
+   TIT1(Values<Cow<'a, str>>),
+   TIT2(Values<Cow<'a, str>>),
+   TIT3(Values<Cow<'a, str>>),
+   TKEY(Values<Cow<'a, str>>),
+   TKWD(Values<Cow<'a, str>>),
+   TLEN(Values<u64>),
+   TMCL(HashMap<Cow<'a, str>, Cow<'a, str>>),
+   TMOO(Values<Cow<'a, str>>),
+   TOAL(Values<Cow<'a, str>>),
+   TOFN(Values<Cow<'a, str>>),
+   TOLY(Values<Cow<'a, str>>),
+   TOPE(Values<Cow<'a, str>>),
+   TOWN(Values<Cow<'a, str>>),
+   TPE1(Values<Cow<'a, str>>),
+   TPE2(Values<Cow<'a, str>>),
+   TPE3(Values<Cow<'a, str>>),
+   TPE4(Values<Cow<'a, str>>),
+   TPOS(Values<Track>),
+   TPRO(Values<Copyright<'a>>),
+   TPUB(Values<Cow<'a, str>>),
+   TRCK(Values<Track>),
+   TRSN(Values<Cow<'a, str>>),
+   TRSO(Values<Cow<'a, str>>),
+   TSO2(Values<Cow<'a, str>>),
+   TSOA(Values<Cow<'a, str>>),
+   TSOC(Values<Cow<'a, str>>),
+   TSOP(Values<Cow<'a, str>>),
+   TSOT(Values<Cow<'a, str>>),
+   TSRC(Values<Cow<'a, str>>),
+   TSSE(Values<Cow<'a, str>>),
+   TSST(Values<Cow<'a, str>>),
+   TXXX(Txxx<'a>),
+   USLT(LangDescriptionText<'a>),
+   WCOM(Cow<'a, str>),
+   WCOP(Cow<'a, str>),
+   WFED(Values<Cow<'a, str>>),
+   WOAF(Cow<'a, str>),
+   WOAR(Cow<'a, str>),
+   WOAS(Cow<'a, str>),
+   WORS(Cow<'a, str>),
+   WPAY(Cow<'a, str>),
+   WPUB(Cow<'a, str>),
+   Unknown(Unknown<'a>),
+}
+
+impl<'a> Frame<'a> {
+   /// A frame that wasn't read from a tag, so has no flags, group, or position
+   pub fn new(data: FrameData<'a>) -> Frame<'a> {
+      Frame {
+         data,
+         group: None,
+         flags: FrameFlags::empty(),
+         offset: 0,
+         size: 0,
+      }
+   }
+
+   /// Where the frame is in the file, header included, given where the tag starts (see `id3::TagInfo::tag_start`). The
+   /// extended header is accounted for, and since ID3v2.4 unsynchronizes each frame on its own, so is
+   /// unsynchronization. Frames that weren't read from a tag have an empty range.
+   pub fn byte_range(&self, tag_start: u64) -> Range<u64> {
+      byte_range(tag_start, self.offset, self.size)
+   }
+
+   pub fn into_owned(self) -> Frame<'static> {
+      Frame {
+         data: self.data.into_owned(),
+         group: self.group,
+         flags: self.flags,
+         offset: self.offset,
+         size: self.size,
+      }
+   }
+}
+
+impl<'a> FrameData<'a> {
+   /// The four character ID of the frame, e.g. `*b"TIT2"`
+   pub fn name(&self) -> [u8; 4] {
+      match self {
+         FrameData::COMM(_) => *b"COMM",
+         FrameData::EQU2(_) => *b"EQU2",
+         FrameData::MVIN(_) => *b"MVIN",
+         FrameData::MVNM(_) => *b"MVNM",
+         FrameData::PCST => *b"PCST",
+         FrameData::PRIV(_) => *b"PRIV",
+         FrameData::RVA2(_) => *b"RVA2",
+         FrameData::RVRB(_) => *b"RVRB",
+         FrameData::TALB(_) => *b"TALB",
+         FrameData::TBPM(_) => *b"TBPM",
+         FrameData::TCMP(_) => *b"TCMP",
+         FrameData::TCOM(_) => *b"TCOM",
+         FrameData::TCON(_) => *b"TCON",
+         FrameData::TCOP(_) => *b"TCOP",
+         FrameData::TDEN(_) => *b"TDEN",
+         FrameData::TDES(_) => *b"TDES",
+         FrameData::TDLY(_) => *b"TDLY",
+         FrameData::TDOR(_) => *b"TDOR",
+         FrameData::TDRC(_) => *b"TDRC",
+         FrameData::TDRL(_) => *b"TDRL",
+         FrameData::TDTG(_) => *b"TDTG",
+         FrameData::TENC(_) => *b"TENC",
+         FrameData::TEXT(_) => *b"TEXT",
+         FrameData::TGID(_) => *b"TGID",
+         FrameData::TIPL(_) => *b"TIPL",
+         FrameData::TIT1(_) => *b"TIT1",
+         FrameData::TIT2(_) => *b"TIT2",
+         FrameData::TIT3(_) => *b"TIT3",
+         FrameData::TKEY(_) => *b"TKEY",
+         FrameData::TKWD(_) => *b"TKWD",
+         FrameData::TLEN(_) => *b"TLEN",
+         FrameData::TMCL(_) => *b"TMCL",
+         FrameData::TMOO(_) => *b"TMOO",
+         FrameData::TOAL(_) => *b"TOAL",
+         FrameData::TOFN(_) => *b"TOFN",
+         FrameData::TOLY(_) => *b"TOLY",
+         FrameData::TOPE(_) => *b"TOPE",
+         FrameData::TOWN(_) => *b"TOWN",
+         FrameData::TPE1(_) => *b"TPE1",
+         FrameData::TPE2(_) => *b"TPE2",
+         FrameData::TPE3(_) => *b"TPE3",
+         FrameData::TPE4(_) => *b"TPE4",
+         FrameData::TPOS(_) => *b"TPOS",
+         FrameData::TPRO(_) => *b"TPRO",
+         FrameData::TPUB(_) => *b"TPUB",
+         FrameData::TRCK(_) => *b"TRCK",
+         FrameData::TRSN(_) => *b"TRSN",
+         FrameData::TRSO(_) => *b"TRSO",
+         FrameData::TSO2(_) => *b"TSO2",
+         FrameData::TSOA(_) => *b"TSOA",
+         FrameData::TSOC(_) => *b"TSOC",
+         FrameData::TSOP(_) => *b"TSOP",
+         FrameData::TSOT(_) => *b"TSOT",
+         FrameData::TSRC(_) => *b"TSRC",
+         FrameData::TSSE(_) => *b"TSSE",
+         FrameData::TSST(_) => *b"TSST",
+         FrameData::TXXX(_) => *b"TXXX",
+         FrameData::USLT(_) => *b"USLT",
+         FrameData::WCOM(_) => *b"WCOM",
+         FrameData::WCOP(_) => *b"WCOP",
+         FrameData::WFED(_) => *b"WFED",
+         FrameData::WOAF(_) => *b"WOAF",
+         FrameData::WOAR(_) => *b"WOAR",
+         FrameData::WOAS(_) => *b"WOAS",
+         FrameData::WORS(_) => *b"WORS",
+         FrameData::WPAY(_) => *b"WPAY",
+         FrameData::WPUB(_) => *b"WPUB",
+         FrameData::Unknown(x) => x.name,
+      }
+   }
+
+   pub fn title<T: Into<Cow<'a, str>>>(title: T) -> FrameData<'a> {
+      FrameData::TIT2(Values::one(title.into()))
+   }
+
+   pub fn artist<T: Into<Cow<'a, str>>>(artist: T) -> FrameData<'a> {
+      FrameData::TPE1(Values::one(artist.into()))
+   }
+
+   pub fn album<T: Into<Cow<'a, str>>>(album: T) -> FrameData<'a> {
+      FrameData::TALB(Values::one(album.into()))
+   }
+
+   pub fn album_artist<T: Into<Cow<'a, str>>>(album_artist: T) -> FrameData<'a> {
+      FrameData::TPE2(Values::one(album_artist.into()))
+   }
+
+   pub fn composer<T: Into<Cow<'a, str>>>(composer: T) -> FrameData<'a> {
+      FrameData::TCOM(Values::one(composer.into()))
+   }
+
+   pub fn genre<T: Into<Cow<'a, str>>>(genre: T) -> FrameData<'a> {
+      FrameData::TCON(Values::one(genre.into()))
+   }
+
+   /// Track numbers start at 1, and can't be past the total
+   pub fn track(number: u64, total: Option<u64>) -> Result<FrameData<'a>, FrameValueError> {
+      Ok(FrameData::TRCK(Values::one(checked_position(number, total)?)))
+   }
+
+   /// Disc numbers start at 1, and can't be past the total
+   pub fn disc(number: u64, total: Option<u64>) -> Result<FrameData<'a>, FrameValueError> {
+      Ok(FrameData::TPOS(Values::one(checked_position(number, total)?)))
+   }
+
+   pub fn recording_date(date: Date) -> Result<FrameData<'a>, FrameValueError> {
+      if date.year > 9999 || !date.is_valid() {
+         return Err(FrameValueError::InvalidDate);
+      }
+      Ok(FrameData::TDRC(Values::one(date)))
+   }
+
+   pub fn copyright<T: Into<Cow<'a, str>>>(year: u16, message: T) -> Result<FrameData<'a>, FrameValueError> {
+      if year > 9999 {
+         return Err(FrameValueError::InvalidDate);
+      }
+      Ok(FrameData::TCOP(Values::one(Copyright {
+         year,
+         message: message.into(),
+      })))
+   }
+
+   /// `lang` is an ISO-639-2 code like "eng", or "XXX" if the language is unknown
+   pub fn comment<D: Into<Cow<'a, str>>, T: Into<Cow<'a, str>>>(
+      lang: &str,
+      description: D,
+      text: T,
+   ) -> Result<FrameData<'a>, FrameValueError> {
+      Ok(FrameData::COMM(LangDescriptionText {
+         iso_639_2_lang: checked_language(lang)?,
+         description: description.into(),
+         text: Values::one(text.into()),
+      }))
+   }
+
+   /// `lang` is an ISO-639-2 code like "eng", or "XXX" if the language is unknown
+   pub fn lyrics<D: Into<Cow<'a, str>>, T: Into<Cow<'a, str>>>(
+      lang: &str,
+      description: D,
+      text: T,
+   ) -> Result<FrameData<'a>, FrameValueError> {
+      Ok(FrameData::USLT(LangDescriptionText {
+         iso_639_2_lang: checked_language(lang)?,
+         description: description.into(),
+         text: Values::one(text.into()),
+      }))
+   }
+
+   pub fn user_text<D: Into<Cow<'a, str>>, T: Into<Cow<'a, str>>>(description: D, text: T) -> FrameData<'a> {
+      FrameData::TXXX(Txxx {
+         description: description.into(),
+         text: Values::one(text.into()),
+      })
+   }
+
+   /// Reads a text (T***) or URL (W***) frame from its value, the way it would be read from a tag, so that numbers,
+   /// dates, and track numbers are checked. Text frames with several values separate them with nulls, as in a tag.
+   /// TXXX and WXXX have descriptions, so use `user_text` for those.
+   pub fn from_text(name: [u8; 4], text: &str) -> Result<FrameData<'static>, FrameParseError> {
+      let error = |reason| FrameParseError { name, reason };
+      let is_valid_id = name.iter().all(|x| x.is_ascii_uppercase() || x.is_ascii_digit());
+      let bytes = match &name {
+         b"TXXX" | b"WXXX" => return Err(error(FrameParseErrorReason::InvalidFrameId)),
+         // WFED is a text frame, despite the W
+         [b'T', ..] | b"WFED" if is_valid_id => {
+            let mut bytes = vec![TextEncoding::UTF8 as u8];
+            bytes.extend_from_slice(text.as_bytes());
+            bytes
+         }
+         // URLs are ISO-8859-1, so anything else is percent-encoded
+         [b'W', ..] if is_valid_id => text.bytes().fold(Vec::new(), |mut bytes, x| {
+            if x.is_ascii() {
+               bytes.push(x);
+            } else {
+               bytes.extend_from_slice(format!("%{:02X}", x).as_bytes());
+            }
+            bytes
+         }),
+         _ => return Err(error(FrameParseErrorReason::InvalidFrameId)),
+      };
+      decode_frame(name, &bytes, &ParseOptions::default())
+         .map(FrameData::into_owned)
+         .map_err(error)
+   }
+
+   /// The typed frame ID, or `None` for an unknown frame with an ID we don't recognize
+   pub fn id(&self) -> Option<FrameId> {
+      FrameId::try_from(self.name()).ok()
+   }
+
+   pub fn into_owned(self) -> FrameData<'static> {
+      match self {
+         FrameData::COMM(x) => FrameData::COMM(x.into_owned()),
+         FrameData::EQU2(x) => FrameData::EQU2(x.into_owned()),
+         FrameData::MVIN(x) => FrameData::MVIN(x),
+         FrameData::MVNM(x) => FrameData::MVNM(owned_strs(x)),
+         FrameData::PCST => FrameData::PCST,
+         FrameData::PRIV(x) => FrameData::PRIV(x.into_owned()),
+         FrameData::RVA2(x) => FrameData::RVA2(x.into_owned()),
+         FrameData::RVRB(x) => FrameData::RVRB(x),
+         FrameData::TALB(x) => FrameData::TALB(owned_strs(x)),
+         FrameData::TBPM(x) => FrameData::TBPM(x),
+         FrameData::TCMP(x) => FrameData::TCMP(x),
+         FrameData::TCOM(x) => FrameData::TCOM(owned_strs(x)),
+         FrameData::TCON(x) => FrameData::TCON(owned_strs(x)),
+         FrameData::TCOP(x) => FrameData::TCOP(x.into_iter().map(Copyright::into_owned).collect()),
+         FrameData::TDEN(x) => FrameData::TDEN(x),
+         FrameData::TDES(x) => FrameData::TDES(owned_strs(x)),
+         FrameData::TDLY(x) => FrameData::TDLY(x),
+         FrameData::TDOR(x) => FrameData::TDOR(x),
+         FrameData::TDRC(x) => FrameData::TDRC(x),
+         FrameData::TDRL(x) => FrameData::TDRL(x),
+         FrameData::TDTG(x) => FrameData::TDTG(x),
+         FrameData::TENC(x) => FrameData::TENC(owned_strs(x)),
+         FrameData::TEXT(x) => FrameData::TEXT(owned_strs(x)),
+         FrameData::TGID(x) => FrameData::TGID(owned_strs(x)),
+         FrameData::TIPL(x) => FrameData::TIPL(owned_str_map(x)),
+         FrameData::TIT1(x) => FrameData::TIT1(owned_strs(x)),
+         FrameData::TIT2(x) => FrameData::TIT2(owned_strs(x)),
+         FrameData::TIT3(x) => FrameData::TIT3(owned_strs(x)),
+         FrameData::TKEY(x) => FrameData::TKEY(owned_strs(x)),
+         FrameData::TKWD(x) => FrameData::TKWD(owned_strs(x)),
+         FrameData::TLEN(x) => FrameData::TLEN(x),
+         FrameData::TMCL(x) => FrameData::TMCL(owned_str_map(x)),
+         FrameData::TMOO(x) => FrameData::TMOO(owned_strs(x)),
+         FrameData::TOAL(x) => FrameData::TOAL(owned_strs(x)),
+         FrameData::TOFN(x) => FrameData::TOFN(owned_strs(x)),
+         FrameData::TOLY(x) => FrameData::TOLY(owned_strs(x)),
+         FrameData::TOPE(x) => FrameData::TOPE(owned_strs(x)),
+         FrameData::TOWN(x) => FrameData::TOWN(owned_strs(x)),
+         FrameData::TPE1(x) => FrameData::TPE1(owned_strs(x)),
+         FrameData::TPE2(x) => FrameData::TPE2(owned_strs(x)),
+         FrameData::TPE3(x) => FrameData::TPE3(owned_strs(x)),
+         FrameData::TPE4(x) => FrameData::TPE4(owned_strs(x)),
+         FrameData::TPOS(x) => FrameData::TPOS(x),
+         FrameData::TPRO(x) => FrameData::TPRO(x.into_iter().map(Copyright::into_owned).collect()),
+         FrameData::TPUB(x) => FrameData::TPUB(owned_strs(x)),
+         FrameData::TRCK(x) => FrameData::TRCK(x),
+         FrameData::TRSN(x) => FrameData::TRSN(owned_strs(x)),
+         FrameData::TRSO(x) => FrameData::TRSO(owned_strs(x)),
+         FrameData::TSO2(x) => FrameData::TSO2(owned_strs(x)),
+         FrameData::TSOA(x) => FrameData::TSOA(owned_strs(x)),
+         FrameData::TSOC(x) => FrameData::TSOC(owned_strs(x)),
+         FrameData::TSOP(x) => FrameData::TSOP(owned_strs(x)),
+         FrameData::TSOT(x) => FrameData::TSOT(owned_strs(x)),
+         FrameData::TSRC(x) => FrameData::TSRC(owned_strs(x)),
+         FrameData::TSSE(x) => FrameData::TSSE(owned_strs(x)),
+         FrameData::TSST(x) => FrameData::TSST(owned_strs(x)),
+         FrameData::TXXX(x) => FrameData::TXXX(x.into_owned()),
+         FrameData::USLT(x) => FrameData::USLT(x.into_owned()),
+         FrameData::WCOM(x) => FrameData::WCOM(owned_str(x)),
+         FrameData::WCOP(x) => FrameData::WCOP(owned_str(x)),
+         FrameData::WFED(x) => FrameData::WFED(owned_strs(x)),
+         FrameData::WOAF(x) => FrameData::WOAF(owned_str(x)),
+         FrameData::WOAR(x) => FrameData::WOAR(owned_str(x)),
+         FrameData::WOAS(x) => FrameData::WOAS(owned_str(x)),
+         FrameData::WORS(x) => FrameData::WORS(owned_str(x)),
+         FrameData::WPAY(x) => FrameData::WPAY(owned_str(x)),
+         FrameData::WPUB(x) => FrameData::WPUB(owned_str(x)),
+         FrameData::Unknown(x) => FrameData::Unknown(x.into_owned()),
+      }
+   }
 }
 
 #[derive(Clone, Debug)]
-pub struct LangDescriptionText {
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct LangDescriptionText<'a> {
    pub iso_639_2_lang: [u8; 3],
-   pub description: String,
-   pub text: Vec<String>,
+   pub description: Cow<'a, str>,
+   pub text: Values<Cow<'a, str>>,
+}
+
+impl<'a> LangDescriptionText<'a> {
+   pub fn into_owned(self) -> LangDescriptionText<'static> {
+      LangDescriptionText {
+         iso_639_2_lang: self.iso_639_2_lang,
+         description: owned_str(self.description),
+         text: owned_strs(self.text),
+      }
+   }
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Txxx<'a> {
+   pub description: Cow<'a, str>,
+   pub text: Values<Cow<'a, str>>,
+}
+
+impl<'a> Txxx<'a> {
+   pub fn into_owned(self) -> Txxx<'static> {
+      Txxx {
+         description: owned_str(self.description),
+         text: owned_strs(self.text),
+      }
+   }
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Priv<'a> {
+   pub owner: Cow<'a, str>,
+   pub data: Cow<'a, [u8]>,
+}
+
+impl<'a> Priv<'a> {
+   pub fn into_owned(self) -> Priv<'static> {
+      Priv {
+         owner: owned_str(self.owner),
+         data: Cow::Owned(self.data.into_owned()),
+      }
+   }
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Copyright<'a> {
+   pub year: u16,
+   pub message: Cow<'a, str>,
+}
+
+impl<'a> Copyright<'a> {
+   pub fn into_owned(self) -> Copyright<'static> {
+      Copyright {
+         year: self.year,
+         message: owned_str(self.message),
+      }
+   }
+}
+
+// "YYYY message", as stored in TCOP and TPRO
+impl<'a> fmt::Display for Copyright<'a> {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      if self.message.is_empty() {
+         write!(f, "{:04}", self.year)
+      } else {
+         write!(f, "{:04} {}", self.year, self.message)
+      }
+   }
+}
+
+impl FromStr for Copyright<'static> {
+   type Err = ParseCopyrightError;
+
+   fn from_str(s: &str) -> Result<Copyright<'static>, ParseCopyrightError> {
+      let options = ParseOptions {
+         strictness: Strictness::Strict,
+         ..ParseOptions::default()
+      };
+      decode_copyright(Cow::Owned(s.to_owned()), &options)
+   }
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Rva2<'a> {
+   /// Usually "track" or "album"
+   pub identification: Cow<'a, str>,
+   pub channels: Vec<Rva2Channel>,
+}
+
+impl<'a> Rva2<'a> {
+   pub fn into_owned(self) -> Rva2<'static> {
+      Rva2 {
+         identification: owned_str(self.identification),
+         channels: self.channels,
+      }
+   }
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Rva2Channel {
+   /// 0 = other, 1 = master volume, 2 = front right, 3 = front left, 4 = back right, 5 = back left,
+   /// 6 = front centre, 7 = back centre, 8 = subwoofer
+   pub channel: u8,
+   /// In dB
+   pub adjustment: f32,
+   /// 1.0 is full scale
+   pub peak: Option<f32>,
 }
 
 #[derive(Clone, Debug)]
-pub struct Txxx {
-   pub description: String,
-   pub text: Vec<String>,
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Equ2<'a> {
+   /// 0 = band (no interpolation between points), 1 = linear
+   pub interpolation: u8,
+   pub identification: Cow<'a, str>,
+   pub points: Vec<Equ2Point>,
 }
 
-#[derive(Clone, Debug)]
-pub struct Priv {
-   pub owner: String,
-   pub data: Box<[u8]>,
+impl<'a> Equ2<'a> {
+   pub fn into_owned(self) -> Equ2<'static> {
+      Equ2 {
+         interpolation: self.interpolation,
+         identification: owned_str(self.identification),
+         points: self.points,
+      }
+   }
 }
 
 #[derive(Clone, Debug)]
-pub struct Copyright {
-   pub year: u16,
-   pub message: String,
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Equ2Point {
+   /// In Hz
+   pub frequency: f32,
+   /// In dB
+   pub adjustment: f32,
 }
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Reverb {
    pub ms_left: u16,
    pub ms_right: u16,
@@ -155,7 +1439,50 @@ pub struct Reverb {
    pub premix_right_to_left: u8,
 }
 
-#[derive(Clone, Debug)]
+impl Reverb {
+   /// The frame body, in the order the fields are stored
+   pub fn to_bytes(&self) -> [u8; 12] {
+      let mut bytes = [0; 12];
+      BigEndian::write_u16(&mut bytes[0..2], self.ms_left);
+      BigEndian::write_u16(&mut bytes[2..4], self.ms_right);
+      bytes[4] = self.bounces_left;
+      bytes[5] = self.bounces_right;
+      bytes[6] = self.feedback_left_to_left;
+      bytes[7] = self.feedback_left_to_right;
+      bytes[8] = self.feedback_right_to_right;
+      bytes[9] = self.feedback_right_to_left;
+      bytes[10] = self.premix_left_to_right;
+      bytes[11] = self.premix_right_to_left;
+      bytes
+   }
+}
+
+// RVRB is binary, so this is only meant for people
+impl fmt::Display for Reverb {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      write!(
+         f,
+         "left {}ms x{}, right {}ms x{}, feedback {}/{}/{}/{} (LL/LR/RR/RL), premix {}/{} (LR/RL)",
+         self.ms_left,
+         self.bounces_left,
+         self.ms_right,
+         self.bounces_right,
+         self.feedback_left_to_left,
+         self.feedback_left_to_right,
+         self.feedback_right_to_right,
+         self.feedback_right_to_left,
+         self.premix_left_to_right,
+         self.premix_right_to_left
+      )
+   }
+}
+
+/// A timestamp as precise as the tagger cared to make it. Every field after the first missing one is also missing.
+///
+/// Dates are ordered field by field, and a missing field comes before any value,
+/// so 1996 < 1996-01 < 1996-01-01 < 1997.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Date {
    pub year: u16,
    pub month: Option<u8>,
@@ -224,18 +1551,99 @@ impl FromStr for Date {
          }
       };
 
-      Ok(Date {
+      let date = Date {
          year,
          month,
          day,
          hour,
          minutes,
          seconds,
-      })
+      };
+      if !date.is_valid() {
+         return Err(ParseDateError::OutOfRange);
+      }
+      Ok(date)
+   }
+}
+
+impl fmt::Display for Date {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      write!(f, "{:04}", self.year)?;
+      let rest = [
+         ('-', self.month),
+         ('-', self.day),
+         ('T', self.hour),
+         (':', self.minutes),
+         (':', self.seconds),
+      ];
+      for (separator, value) in rest.iter() {
+         match value {
+            Some(v) => write!(f, "{}{:02}", separator, v)?,
+            None => break,
+         }
+      }
+      Ok(())
+   }
+}
+
+impl Date {
+   /// Whether each field that is present is in range, including the day for the given month and year
+   pub fn is_valid(&self) -> bool {
+      let max_day = match self.month {
+         Some(month) => days_in_month(self.year, month),
+         None => 31,
+      };
+      self.month.map_or(true, |x| x >= 1 && x <= 12)
+         && self.day.map_or(true, |x| x >= 1 && x <= max_day)
+         && self.hour.map_or(true, |x| x <= 23)
+         && self.minutes.map_or(true, |x| x <= 59)
+         && self.seconds.map_or(true, |x| x <= 59)
+   }
+
+   /// Converts to a chrono timestamp, taking the earliest possible value for each missing field
+   #[cfg(feature = "chrono")]
+   pub fn to_naive_date_time(&self) -> Option<chrono::NaiveDateTime> {
+      chrono::NaiveDate::from_ymd_opt(
+         i32::from(self.year),
+         u32::from(self.month.unwrap_or(1)),
+         u32::from(self.day.unwrap_or(1)),
+      )?
+      .and_hms_opt(
+         u32::from(self.hour.unwrap_or(0)),
+         u32::from(self.minutes.unwrap_or(0)),
+         u32::from(self.seconds.unwrap_or(0)),
+      )
+   }
+}
+
+/// Years outside of 0-9999 can't be written in a tag, so they are clamped
+#[cfg(feature = "chrono")]
+impl From<chrono::NaiveDateTime> for Date {
+   fn from(date_time: chrono::NaiveDateTime) -> Date {
+      use chrono::{Datelike, Timelike};
+      Date {
+         year: date_time.year().max(0).min(9999) as u16,
+         month: Some(date_time.month() as u8),
+         day: Some(date_time.day() as u8),
+         hour: Some(date_time.hour() as u8),
+         minutes: Some(date_time.minute() as u8),
+         // chrono represents leap seconds in the nanoseconds instead
+         seconds: Some(date_time.second() as u8),
+      }
+   }
+}
+
+fn days_in_month(year: u16, month: u8) -> u8 {
+   match month {
+      2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+      2 => 28,
+      4 | 6 | 9 | 11 => 30,
+      _ => 31,
    }
 }
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Track {
    pub number: u64,
    pub max: Option<u64>,
@@ -261,176 +1669,160 @@ impl FromStr for Track {
    }
 }
 
+// "3" or "3/12"
+impl fmt::Display for Track {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      match self.max {
+         Some(max) => write!(f, "{}/{}", self.number, max),
+         None => write!(f, "{}", self.number),
+      }
+   }
+}
+
+/// A frame that isn't decoded, held as its payload with unsynchronization undone. Compressed and encrypted frames are
+/// held this way too, with the encryption method and data length in front of the payload, as are frames that failed
+/// to decode if `ParseOptions::keep_undecodable` was set. Written back, each is the same as it was read.
 #[derive(Clone, Debug)]
-pub struct Unknown {
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Unknown<'a> {
    pub name: [u8; 4],
-   pub data: Box<[u8]>,
+   pub data: Cow<'a, [u8]>,
 }
 
-fn map_parse<T: FromStr>(str_vec: Vec<String>) -> Result<Vec<T>, T::Err> {
-   let mut new_vec = Vec::new();
-   for item in str_vec {
-      new_vec.push(item.parse()?);
+impl<'a> Unknown<'a> {
+   pub fn into_owned(self) -> Unknown<'static> {
+      Unknown {
+         name: self.name,
+         data: Cow::Owned(self.data.into_owned()),
+      }
    }
-   Ok(new_vec)
 }
 
-impl Iterator for Parser {
-   type Item = Result<Frame, FrameParseError>;
-
-   fn next(&mut self) -> Option<Result<Frame, FrameParseError>> {
-      // Each frame must be at least 10 bytes
-      if self.content.len().saturating_sub(self.cursor) < 10 {
-         return None;
-      }
-
-      let mut name: [u8; 4] = [0; 4];
-      name.copy_from_slice(&self.content[self.cursor..self.cursor + 4]);
-      if &name == b"\0\0\0\0" {
-         // Padding
-         return None;
-      }
+fn checked_position(number: u64, total: Option<u64>) -> Result<Track, FrameValueError> {
+   if number == 0 || total.map_or(false, |x| number > x) {
+      return Err(FrameValueError::InvalidPosition);
+   }
+   Ok(Track { number, max: total })
+}
 
-      let mut frame_size = synchsafe_u32_to_u32(BigEndian::read_u32(&self.content[self.cursor + 4..self.cursor + 8]));
-      let frame_flags_raw = BigEndian::read_u16(&self.content[self.cursor + 8..self.cursor + 10]);
-      let frame_flags = FrameFlags::from_bits_truncate(frame_flags_raw);
+fn checked_language(lang: &str) -> Result<[u8; 3], FrameValueError> {
+   let bytes = lang.as_bytes();
+   if lang != "XXX" && (bytes.len() != 3 || !bytes.iter().all(u8::is_ascii_lowercase)) {
+      return Err(FrameValueError::InvalidLanguage);
+   }
+   let mut code = [0; 3];
+   code.copy_from_slice(bytes);
+   Ok(code)
+}
 
-      self.cursor += 10;
+fn owned_str(s: Cow<str>) -> Cow<'static, str> {
+   Cow::Owned(s.into_owned())
+}
 
-      let mut group = None;
-      if frame_flags.contains(FrameFlags::GROUPING_IDENTITY) {
-         let group_byte = if let Some(byte) = self.content.get(self.cursor) {
-            *byte
-         } else {
-            return Some(Err(FrameParseError {
-               reason: FrameParseErrorReason::FrameTooSmall,
-               name,
-            }));
-         };
-         group = Some(group_byte);
-         self.cursor += 1;
-         // frame size includes the flag data, so we have to adjust it, as the code after this
-         // assumes frame size == data size.
-         // saturating sub so we don't underflow on a bad frame size input
-         frame_size = frame_size.saturating_sub(1);
-      }
-
-      if frame_flags.contains(FrameFlags::DATA_LENGTH_INDICATOR) {
-         // TODO: we only need to use this when we implement compression,
-         // and some forms of encryption.
-         let dli_bytes = if let Some(bytes) = self.content.get(self.cursor..self.cursor.saturating_add(4)) {
-            bytes
-         } else {
-            return Some(Err(FrameParseError {
-               reason: FrameParseErrorReason::FrameTooSmall,
-               name,
-            }));
-         };
-         if dli_bytes.len() < 4 {
-            return Some(Err(FrameParseError {
-               reason: FrameParseErrorReason::FrameTooSmall,
-               name,
-            }));
-         }
-         frame_size = synchsafe_u32_to_u32(BigEndian::read_u32(dli_bytes));
-         self.cursor += 4;
-      }
+fn owned_strs(v: Values<Cow<str>>) -> Values<Cow<'static, str>> {
+   v.map(owned_str)
+}
 
-      let frame_bytes = if let Some(slice) = self
-         .content
-         .get(self.cursor..self.cursor.saturating_add(frame_size as usize))
-      {
-         slice
-      } else {
-         self.cursor = self.cursor.saturating_add(frame_size as usize);
-         return Some(Err(FrameParseError {
-            reason: FrameParseErrorReason::FrameTooSmall,
-            name,
-         }));
-      };
+fn owned_str_map(m: HashMap<Cow<str>, Cow<str>>) -> HashMap<Cow<'static, str>, Cow<'static, str>> {
+   m.into_iter().map(|(k, v)| (owned_str(k), owned_str(v))).collect()
+}
 
-      let result: Result<FrameData, FrameParseErrorReason> = try {
-         match &name {
-            b"COMM" => FrameData::COMM(decode_lang_description_text(frame_bytes)?),
-            b"PRIV" => decode_priv_frame(frame_bytes)?,
-            b"RVRB" => FrameData::RVRB(decode_reverb_frame(frame_bytes)?),
-            b"TALB" => FrameData::TALB(decode_text_frame(frame_bytes)?),
-            b"TBPM" => FrameData::TBPM(map_parse(decode_text_frame(frame_bytes)?)?),
-            b"TCOM" => FrameData::TCOM(decode_text_frame(frame_bytes)?),
-            b"TCON" => decode_genre_frame(frame_bytes)?,
-            b"TCOP" => FrameData::TCOP({
-               let mut new_vec = Vec::new();
-               for segment in decode_text_frame(frame_bytes)? {
-                  new_vec.push(decode_copyright_frame(segment)?);
-               }
-               new_vec
-            }),
-            b"TDEN" => FrameData::TDEN(map_parse(decode_text_frame(frame_bytes)?)?),
-            b"TDOR" => FrameData::TDOR(map_parse(decode_text_frame(frame_bytes)?)?),
-            b"TDLY" => FrameData::TDLY(map_parse(decode_text_frame(frame_bytes)?)?),
-            b"TDRC" => FrameData::TDRC(map_parse(decode_text_frame(frame_bytes)?)?),
-            b"TDRL" => FrameData::TDRL(map_parse(decode_text_frame(frame_bytes)?)?),
-            b"TDTG" => FrameData::TDTG(map_parse(decode_text_frame(frame_bytes)?)?),
-            b"TENC" => FrameData::TENC(decode_text_frame(frame_bytes)?),
-            b"TEXT" => FrameData::TEXT(decode_text_frame(frame_bytes)?),
-            b"TIPL" => FrameData::TIPL(decode_text_map_frame(frame_bytes)?),
-            b"TIT1" => FrameData::TIT1(decode_text_frame(frame_bytes)?),
-            b"TIT2" => FrameData::TIT2(decode_text_frame(frame_bytes)?),
-            b"TIT3" => FrameData::TIT3(decode_text_frame(frame_bytes)?),
-            b"TLEN" => FrameData::TLEN(map_parse(decode_text_frame(frame_bytes)?)?),
-            b"TMCL" => FrameData::TMCL(decode_text_map_frame(frame_bytes)?),
-            b"TMOO" => FrameData::TMOO(decode_text_frame(frame_bytes)?),
-            b"TOAL" => FrameData::TOAL(decode_text_frame(frame_bytes)?),
-            b"TOFN" => FrameData::TOFN(decode_text_frame(frame_bytes)?),
-            b"TOLY" => FrameData::TOLY(decode_text_frame(frame_bytes)?),
-            b"TOPE" => FrameData::TOPE(decode_text_frame(frame_bytes)?),
-            b"TOWN" => FrameData::TOWN(decode_text_frame(frame_bytes)?),
-            b"TPE1" => FrameData::TPE1(decode_text_frame(frame_bytes)?),
-            b"TPE2" => FrameData::TPE2(decode_text_frame(frame_bytes)?),
-            b"TPE3" => FrameData::TPE3(decode_text_frame(frame_bytes)?),
-            b"TPE4" => FrameData::TPE4(decode_text_frame(frame_bytes)?),
-            b"TPOS" => FrameData::TPOS(map_parse(decode_text_frame(frame_bytes)?)?),
-            b"TPRO" => FrameData::TPRO({
-               let mut new_vec = Vec::new();
-               for segment in decode_text_frame(frame_bytes)? {
-                  new_vec.push(decode_copyright_frame(segment)?);
-               }
-               new_vec
-            }),
-            b"TPUB" => FrameData::TPUB(decode_text_frame(frame_bytes)?),
-            b"TRCK" => FrameData::TRCK(map_parse(decode_text_frame(frame_bytes)?)?),
-            b"TRSN" => FrameData::TRSN(decode_text_frame(frame_bytes)?),
-            b"TRSO" => FrameData::TRSO(decode_text_frame(frame_bytes)?),
-            b"TSOA" => FrameData::TSOA(decode_text_frame(frame_bytes)?),
-            b"TSOP" => FrameData::TSOP(decode_text_frame(frame_bytes)?),
-            b"TSOT" => FrameData::TSOT(decode_text_frame(frame_bytes)?),
-            b"TSRC" => FrameData::TSRC(decode_text_frame(frame_bytes)?),
-            b"TSSE" => FrameData::TSSE(decode_text_frame(frame_bytes)?),
-            b"TSST" => FrameData::TSST(decode_text_frame(frame_bytes)?),
-            b"TXXX" => decode_txxx_frame(frame_bytes)?,
-            b"USLT" => FrameData::USLT(decode_lang_description_text(frame_bytes)?),
-            b"WCOM" => FrameData::WCOM(decode_url_frame(frame_bytes)),
-            b"WCOP" => FrameData::WCOP(decode_url_frame(frame_bytes)),
-            b"WOAF" => FrameData::WOAF(decode_url_frame(frame_bytes)),
-            b"WOAR" => FrameData::WOAR(decode_url_frame(frame_bytes)),
-            b"WOAS" => FrameData::WOAS(decode_url_frame(frame_bytes)),
-            b"WORS" => FrameData::WORS(decode_url_frame(frame_bytes)),
-            b"WPAY" => FrameData::WPAY(decode_url_frame(frame_bytes)),
-            b"WPUB" => FrameData::WPUB(decode_url_frame(frame_bytes)),
-            _ => FrameData::Unknown(Unknown {
-               name,
-               data: Box::from(frame_bytes),
-            }),
-         }
-      };
+fn map_parse<T: FromStr>(str_vec: Values<Cow<str>>) -> Result<Values<T>, T::Err> {
+   let mut new_vec = Values::new();
+   for item in str_vec {
+      new_vec.push(item.parse()?);
+   }
+   Ok(new_vec)
+}
 
-      self.cursor += frame_size as usize;
+pub(super) fn decode_frame<'a>(
+   name: [u8; 4],
+   frame_bytes: &'a [u8],
+   options: &ParseOptions,
+) -> Result<FrameData<'a>, FrameParseErrorReason> {
+   decode_frame_in(name, frame_bytes, &DecodeOptions::new(options))
+}
 
-      Some(
-         result
-            .map(|data| Frame { data, group })
-            .map_err(|e| FrameParseError { name, reason: e }),
-      )
+fn decode_frame_in<'a>(
+   name: [u8; 4],
+   frame_bytes: &'a [u8],
+   options: &DecodeOptions<'_, 'a>,
+) -> Result<FrameData<'a>, FrameParseErrorReason> {
+   try {
+      match &name {
+         b"COMM" => FrameData::COMM(decode_lang_description_text(frame_bytes, options)?),
+         b"EQU2" => FrameData::EQU2(decode_equ2_frame(frame_bytes, options)?),
+         b"MVIN" => FrameData::MVIN(map_parse(decode_text_frame(frame_bytes, options)?)?),
+         b"MVNM" => FrameData::MVNM(decode_text_frame(frame_bytes, options)?),
+         b"PCST" => decode_podcast_frame(frame_bytes)?,
+         b"PRIV" => decode_priv_frame(frame_bytes, options)?,
+         b"RVA2" => FrameData::RVA2(decode_rva2_frame(frame_bytes, options)?),
+         b"RVRB" => FrameData::RVRB(decode_reverb_frame(frame_bytes)?),
+         b"TALB" => FrameData::TALB(decode_text_frame(frame_bytes, options)?),
+         b"TBPM" => FrameData::TBPM(map_parse(decode_text_frame(frame_bytes, options)?)?),
+         b"TCMP" => FrameData::TCMP(decode_compilation_frame(frame_bytes, options)?),
+         b"TCOM" => FrameData::TCOM(decode_text_frame(frame_bytes, options)?),
+         b"TCON" => decode_genre_frame(frame_bytes, options)?,
+         b"TCOP" => FrameData::TCOP(decode_copyright_frame(frame_bytes, options)?),
+         b"TDEN" => FrameData::TDEN(map_parse(decode_text_frame(frame_bytes, options)?)?),
+         b"TDES" => FrameData::TDES(decode_text_frame(frame_bytes, options)?),
+         b"TDOR" => FrameData::TDOR(map_parse(decode_text_frame(frame_bytes, options)?)?),
+         b"TDLY" => FrameData::TDLY(map_parse(decode_text_frame(frame_bytes, options)?)?),
+         b"TDRC" => FrameData::TDRC(map_parse(decode_text_frame(frame_bytes, options)?)?),
+         b"TDRL" => FrameData::TDRL(map_parse(decode_text_frame(frame_bytes, options)?)?),
+         b"TDTG" => FrameData::TDTG(map_parse(decode_text_frame(frame_bytes, options)?)?),
+         b"TENC" => FrameData::TENC(decode_text_frame(frame_bytes, options)?),
+         b"TEXT" => FrameData::TEXT(decode_text_frame(frame_bytes, options)?),
+         b"TGID" => FrameData::TGID(decode_text_frame(frame_bytes, options)?),
+         b"TIPL" => FrameData::TIPL(decode_text_map_frame(frame_bytes, options)?),
+         b"TIT1" => FrameData::TIT1(decode_text_frame(frame_bytes, options)?),
+         b"TIT2" => FrameData::TIT2(decode_text_frame(frame_bytes, options)?),
+         b"TIT3" => FrameData::TIT3(decode_text_frame(frame_bytes, options)?),
+         b"TKEY" => FrameData::TKEY(decode_text_frame(frame_bytes, options)?),
+         b"TKWD" => FrameData::TKWD(decode_text_frame(frame_bytes, options)?),
+         b"TLEN" => FrameData::TLEN(map_parse(decode_text_frame(frame_bytes, options)?)?),
+         b"TMCL" => FrameData::TMCL(decode_text_map_frame(frame_bytes, options)?),
+         b"TMOO" => FrameData::TMOO(decode_text_frame(frame_bytes, options)?),
+         b"TOAL" => FrameData::TOAL(decode_text_frame(frame_bytes, options)?),
+         b"TOFN" => FrameData::TOFN(decode_text_frame(frame_bytes, options)?),
+         b"TOLY" => FrameData::TOLY(decode_text_frame(frame_bytes, options)?),
+         b"TOPE" => FrameData::TOPE(decode_text_frame(frame_bytes, options)?),
+         b"TOWN" => FrameData::TOWN(decode_text_frame(frame_bytes, options)?),
+         b"TPE1" => FrameData::TPE1(decode_text_frame(frame_bytes, options)?),
+         b"TPE2" => FrameData::TPE2(decode_text_frame(frame_bytes, options)?),
+         b"TPE3" => FrameData::TPE3(decode_text_frame(frame_bytes, options)?),
+         b"TPE4" => FrameData::TPE4(decode_text_frame(frame_bytes, options)?),
+         b"TPOS" => FrameData::TPOS(map_parse(decode_text_frame(frame_bytes, options)?)?),
+         b"TPRO" => FrameData::TPRO(decode_copyright_frame(frame_bytes, options)?),
+         b"TPUB" => FrameData::TPUB(decode_text_frame(frame_bytes, options)?),
+         b"TRCK" => FrameData::TRCK(map_parse(decode_text_frame(frame_bytes, options)?)?),
+         b"TRSN" => FrameData::TRSN(decode_text_frame(frame_bytes, options)?),
+         b"TRSO" => FrameData::TRSO(decode_text_frame(frame_bytes, options)?),
+         b"TSO2" => FrameData::TSO2(decode_text_frame(frame_bytes, options)?),
+         b"TSOA" => FrameData::TSOA(decode_text_frame(frame_bytes, options)?),
+         b"TSOC" => FrameData::TSOC(decode_text_frame(frame_bytes, options)?),
+         b"TSOP" => FrameData::TSOP(decode_text_frame(frame_bytes, options)?),
+         b"TSOT" => FrameData::TSOT(decode_text_frame(frame_bytes, options)?),
+         b"TSRC" => FrameData::TSRC(decode_text_frame(frame_bytes, options)?),
+         b"TSSE" => FrameData::TSSE(decode_text_frame(frame_bytes, options)?),
+         b"TSST" => FrameData::TSST(decode_text_frame(frame_bytes, options)?),
+         b"TXXX" => decode_txxx_frame(frame_bytes, options)?,
+         b"USLT" => FrameData::USLT(decode_lang_description_text(frame_bytes, options)?),
+         b"WCOM" => FrameData::WCOM(decode_url_frame(frame_bytes)),
+         b"WCOP" => FrameData::WCOP(decode_url_frame(frame_bytes)),
+         // Despite the W, iTunes writes the feed URL as a text frame (with an encoding byte)
+         b"WFED" => FrameData::WFED(decode_text_frame(frame_bytes, options)?),
+         b"WOAF" => FrameData::WOAF(decode_url_frame(frame_bytes)),
+         b"WOAR" => FrameData::WOAR(decode_url_frame(frame_bytes)),
+         b"WOAS" => FrameData::WOAS(decode_url_frame(frame_bytes)),
+         b"WORS" => FrameData::WORS(decode_url_frame(frame_bytes)),
+         b"WPAY" => FrameData::WPAY(decode_url_frame(frame_bytes)),
+         b"WPUB" => FrameData::WPUB(decode_url_frame(frame_bytes)),
+         _ => FrameData::Unknown(Unknown {
+            name,
+            data: Cow::Borrowed(frame_bytes),
+         }),
+      }
    }
 }
 
@@ -440,15 +1832,69 @@ pub struct FrameParseError {
    pub reason: FrameParseErrorReason,
 }
 
-#[derive(Clone, Debug)]
+impl fmt::Display for FrameParseError {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      write!(f, "{}: {}", String::from_utf8_lossy(&self.name), self.reason)
+   }
+}
+
+impl Error for FrameParseError {
+   fn source(&self) -> Option<&(dyn Error + 'static)> {
+      Some(&self.reason)
+   }
+}
+
+#[derive(Debug)]
 pub enum FrameParseErrorReason {
    FrameTooSmall,
+   InvalidBitWidth(u8),
+   InvalidFrameId,
+   InvalidFrameSize,
+   Io(io::Error),
    MissingNullTerminator,
    MissingValueInMapFrame,
+   ParseCopyrightError(ParseCopyrightError),
    ParseDateError(ParseDateError),
    ParseIntError(ParseIntError),
    ParseTrackError(ParseTrackError),
    TextDecodeError(TextDecodeError),
+   TrailingJunk,
+   Truncated,
+}
+
+impl fmt::Display for FrameParseErrorReason {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      match self {
+         FrameParseErrorReason::FrameTooSmall => write!(f, "frame is too small to hold its contents"),
+         FrameParseErrorReason::InvalidBitWidth(x) => write!(f, "unsupported {} bit value", x),
+         FrameParseErrorReason::InvalidFrameId => write!(f, "invalid frame ID"),
+         FrameParseErrorReason::InvalidFrameSize => write!(f, "frame size is not a synchsafe integer"),
+         FrameParseErrorReason::Io(e) => write!(f, "I/O error: {}", e),
+         FrameParseErrorReason::MissingNullTerminator => write!(f, "missing null terminator"),
+         FrameParseErrorReason::MissingValueInMapFrame => write!(f, "key is missing its value"),
+         FrameParseErrorReason::ParseCopyrightError(e) => write!(f, "invalid copyright: {}", e),
+         FrameParseErrorReason::ParseDateError(e) => write!(f, "invalid date: {}", e),
+         FrameParseErrorReason::ParseIntError(e) => write!(f, "invalid number: {}", e),
+         FrameParseErrorReason::ParseTrackError(e) => write!(f, "invalid track: {}", e),
+         FrameParseErrorReason::TextDecodeError(e) => write!(f, "invalid text: {}", e),
+         FrameParseErrorReason::TrailingJunk => write!(f, "junk after the last frame"),
+         FrameParseErrorReason::Truncated => write!(f, "frame extends past the end of the tag"),
+      }
+   }
+}
+
+impl Error for FrameParseErrorReason {
+   fn source(&self) -> Option<&(dyn Error + 'static)> {
+      match self {
+         FrameParseErrorReason::Io(e) => Some(e),
+         FrameParseErrorReason::ParseCopyrightError(e) => Some(e),
+         FrameParseErrorReason::ParseDateError(e) => Some(e),
+         FrameParseErrorReason::ParseIntError(e) => Some(e),
+         FrameParseErrorReason::ParseTrackError(e) => Some(e),
+         FrameParseErrorReason::TextDecodeError(e) => Some(e),
+         _ => None,
+      }
+   }
 }
 
 impl From<ParseIntError> for FrameParseErrorReason {
@@ -457,6 +1903,34 @@ impl From<ParseIntError> for FrameParseErrorReason {
    }
 }
 
+// As with `TagParseError`, a copy of an `io::Error` keeps its kind and message
+impl Clone for FrameParseErrorReason {
+   fn clone(&self) -> FrameParseErrorReason {
+      match self {
+         FrameParseErrorReason::FrameTooSmall => FrameParseErrorReason::FrameTooSmall,
+         FrameParseErrorReason::InvalidBitWidth(x) => FrameParseErrorReason::InvalidBitWidth(*x),
+         FrameParseErrorReason::InvalidFrameId => FrameParseErrorReason::InvalidFrameId,
+         FrameParseErrorReason::InvalidFrameSize => FrameParseErrorReason::InvalidFrameSize,
+         FrameParseErrorReason::Io(e) => FrameParseErrorReason::Io(io::Error::new(e.kind(), e.to_string())),
+         FrameParseErrorReason::MissingNullTerminator => FrameParseErrorReason::MissingNullTerminator,
+         FrameParseErrorReason::MissingValueInMapFrame => FrameParseErrorReason::MissingValueInMapFrame,
+         FrameParseErrorReason::ParseCopyrightError(e) => FrameParseErrorReason::ParseCopyrightError(e.clone()),
+         FrameParseErrorReason::ParseDateError(e) => FrameParseErrorReason::ParseDateError(e.clone()),
+         FrameParseErrorReason::ParseIntError(e) => FrameParseErrorReason::ParseIntError(e.clone()),
+         FrameParseErrorReason::ParseTrackError(e) => FrameParseErrorReason::ParseTrackError(e.clone()),
+         FrameParseErrorReason::TextDecodeError(e) => FrameParseErrorReason::TextDecodeError(e.clone()),
+         FrameParseErrorReason::TrailingJunk => FrameParseErrorReason::TrailingJunk,
+         FrameParseErrorReason::Truncated => FrameParseErrorReason::Truncated,
+      }
+   }
+}
+
+impl From<io::Error> for FrameParseErrorReason {
+   fn from(e: io::Error) -> FrameParseErrorReason {
+      FrameParseErrorReason::Io(e)
+   }
+}
+
 impl From<TextDecodeError> for FrameParseErrorReason {
    fn from(e: TextDecodeError) -> FrameParseErrorReason {
       FrameParseErrorReason::TextDecodeError(e)
@@ -469,6 +1943,12 @@ impl From<ParseTrackError> for FrameParseErrorReason {
    }
 }
 
+impl From<ParseCopyrightError> for FrameParseErrorReason {
+   fn from(e: ParseCopyrightError) -> FrameParseErrorReason {
+      FrameParseErrorReason::ParseCopyrightError(e)
+   }
+}
+
 impl From<ParseDateError> for FrameParseErrorReason {
    fn from(e: ParseDateError) -> FrameParseErrorReason {
       FrameParseErrorReason::ParseDateError(e)
@@ -479,9 +1959,23 @@ impl From<ParseDateError> for FrameParseErrorReason {
 pub enum TextDecodeError {
    InvalidUtf16,
    InvalidUtf8,
+   MissingByteOrderMark,
    UnknownEncoding(u8),
 }
 
+impl fmt::Display for TextDecodeError {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      match self {
+         TextDecodeError::InvalidUtf16 => write!(f, "invalid UTF-16"),
+         TextDecodeError::InvalidUtf8 => write!(f, "invalid UTF-8"),
+         TextDecodeError::MissingByteOrderMark => write!(f, "UTF-16 text is missing its byte order mark"),
+         TextDecodeError::UnknownEncoding(x) => write!(f, "unknown text encoding {}", x),
+      }
+   }
+}
+
+impl Error for TextDecodeError {}
+
 impl From<FromUtf16Error> for TextDecodeError {
    fn from(_: FromUtf16Error) -> TextDecodeError {
       TextDecodeError::InvalidUtf16
@@ -499,18 +1993,97 @@ pub enum ParseTrackError {
    InvalidTrackNumber(ParseIntError),
 }
 
+impl fmt::Display for ParseTrackError {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      match self {
+         ParseTrackError::InvalidTrackNumber(e) => write!(f, "invalid track number: {}", e),
+      }
+   }
+}
+
+impl Error for ParseTrackError {
+   fn source(&self) -> Option<&(dyn Error + 'static)> {
+      match self {
+         ParseTrackError::InvalidTrackNumber(e) => Some(e),
+      }
+   }
+}
+
 impl From<ParseIntError> for ParseTrackError {
    fn from(e: ParseIntError) -> ParseTrackError {
       ParseTrackError::InvalidTrackNumber(e)
    }
 }
 
+/// A value that a frame constructor can't put in a frame
+#[derive(Clone, Debug, PartialEq)]
+pub enum FrameValueError {
+   /// Not a lowercase three letter ISO-639-2 code, nor "XXX"
+   InvalidLanguage,
+   /// A track or disc number of 0, or one past the total
+   InvalidPosition,
+   /// A date with a field out of range, or a year past 9999
+   InvalidDate,
+}
+
+impl fmt::Display for FrameValueError {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      match self {
+         FrameValueError::InvalidLanguage => write!(f, "language is not an ISO-639-2 code"),
+         FrameValueError::InvalidPosition => write!(f, "number is 0 or past the total"),
+         FrameValueError::InvalidDate => write!(f, "date is out of range"),
+      }
+   }
+}
+
+impl Error for FrameValueError {}
+
+#[derive(Clone, Debug)]
+pub enum ParseCopyrightError {
+   /// The text doesn't start with a four digit year
+   InvalidYear,
+   /// The year isn't followed by a space
+   MissingSpace,
+}
+
+impl fmt::Display for ParseCopyrightError {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      match self {
+         ParseCopyrightError::InvalidYear => write!(f, "copyright doesn't start with a four digit year"),
+         ParseCopyrightError::MissingSpace => write!(f, "copyright year isn't followed by a space"),
+      }
+   }
+}
+
+impl Error for ParseCopyrightError {}
+
 #[derive(Clone, Debug)]
 pub enum ParseDateError {
    MissingYear,
+   /// A field such as the month or hour is out of range
+   OutOfRange,
    ParseIntError(ParseIntError),
 }
 
+impl fmt::Display for ParseDateError {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      match self {
+         ParseDateError::MissingYear => write!(f, "date is missing its year"),
+         ParseDateError::OutOfRange => write!(f, "date has a field out of range"),
+         ParseDateError::ParseIntError(e) => write!(f, "{}", e),
+      }
+   }
+}
+
+impl Error for ParseDateError {
+   fn source(&self) -> Option<&(dyn Error + 'static)> {
+      match self {
+         ParseDateError::MissingYear | ParseDateError::OutOfRange => None,
+         ParseDateError::ParseIntError(e) => Some(e),
+      }
+   }
+}
+
 impl From<ParseIntError> for ParseDateError {
    fn from(e: ParseIntError) -> ParseDateError {
       ParseDateError::ParseIntError(e)
@@ -519,7 +2092,7 @@ impl From<ParseIntError> for ParseDateError {
 
 #[repr(u8)]
 #[derive(Copy, Clone, Debug, PartialEq)]
-enum TextEncoding {
+pub(super) enum TextEncoding {
    ISO8859,
    UTF16BOM,
    UTF16BE,
@@ -552,158 +2125,318 @@ impl TextEncoding {
          b"\0"
       }
    }
+
+   // Where the first null terminator starts
+   fn find_terminator(self, bytes: &[u8]) -> Option<usize> {
+      if self.has_two_trailing_nulls() {
+         find_double_null(bytes)
+      } else {
+         memchr(0, bytes)
+      }
+   }
 }
 
-fn decode_text_segments(encoding: TextEncoding, mut text_slice: &[u8]) -> Result<Vec<String>, TextDecodeError> {
+// UTF-16 terminators have to line up with a code unit, so they can't be searched for with memchr. Instead we skip
+// ahead four code units at a time until one of them might be zero, then look at them one by one.
+fn find_double_null(bytes: &[u8]) -> Option<usize> {
+   const LOW_BITS: u64 = 0x0001_0001_0001_0001;
+   const HIGH_BITS: u64 = 0x8000_8000_8000_8000;
+   let words = bytes
+      .chunks_exact(8)
+      .map(NativeEndian::read_u64)
+      .take_while(|x| x.wrapping_sub(LOW_BITS) & !x & HIGH_BITS == 0)
+      .count();
+   let start = words * 8;
+   bytes[start..]
+      .chunks_exact(2)
+      .position(|x| x == b"\0\0")
+      .map(|x| start + x * 2)
+}
+
+fn decode_text_segments<'a>(
+   encoding: TextEncoding,
+   mut text_slice: &'a [u8],
+   options: &DecodeOptions<'_, 'a>,
+) -> Result<Values<Cow<'a, str>>, TextDecodeError> {
    let separator = encoding.get_trailing_null_slice();
-   let mut text_segments = Vec::new();
-   while let Some(pos) = text_slice
-      .chunks_exact(separator.len())
-      .position(|x| x == separator)
-      .map(|x| x * separator.len())
-   {
-      text_segments.push(decode_text_segment(encoding, &text_slice[..pos])?);
+   let mut text_segments = Values::new();
+   while let Some(pos) = encoding.find_terminator(text_slice) {
+      text_segments.push(decode_text_segment(encoding, &text_slice[..pos], options)?);
       text_slice = &text_slice[pos + separator.len()..];
    }
 
    if !text_slice.is_empty() {
       // There is more text, but no null terminator
       // We assume that it ends with the frame
-      text_segments.push(decode_text_segment(encoding, text_slice)?);
+      text_segments.push(decode_text_segment(encoding, text_slice, options)?);
    }
 
    Ok(text_segments)
 }
 
-fn decode_text_segment(encoding: TextEncoding, text_slice: &[u8]) -> Result<String, TextDecodeError> {
+fn decode_text_segment<'a>(
+   encoding: TextEncoding,
+   text_slice: &'a [u8],
+   options: &DecodeOptions<'_, 'a>,
+) -> Result<Cow<'a, str>, TextDecodeError> {
+   let text = decode_raw_text_segment(encoding, text_slice, options)?;
+   if !options.normalize_text {
+      return Ok(text);
+   }
+   let (cleaned, cleanups) = super::normalize::clean(&text);
+   if cleanups.is_empty() {
+      return Ok(text);
+   }
+   let cleaned = cleaned.into_owned();
+   Ok(options.alloc_str(Cow::Owned(cleaned)))
+}
+
+fn decode_raw_text_segment<'a>(
+   encoding: TextEncoding,
+   text_slice: &'a [u8],
+   options: &DecodeOptions<'_, 'a>,
+) -> Result<Cow<'a, str>, TextDecodeError> {
    if text_slice.len() == 0 {
-      return Ok(String::from(""));
+      return Ok(Cow::Borrowed(""));
    }
 
    match encoding {
-      TextEncoding::ISO8859 => Ok(text_slice.iter().map(|c| *c as char).collect()),
+      // ASCII is a subset of every charset we decode ISO-8859-1 as, so it can always be borrowed
+      TextEncoding::ISO8859 if text_slice.is_ascii() => Ok(decode_latin1(text_slice)),
+      TextEncoding::ISO8859 => Ok(match options.latin1 {
+         Latin1Decoding::Windows1252 => options.alloc_transcoded(text_slice, windows_1252_char),
+         Latin1Decoding::Iso8859_1 => options.alloc_transcoded(text_slice, latin1_char),
+         #[cfg(feature = "charset-detection")]
+         Latin1Decoding::Detect => options.alloc_str(decode_detected(text_slice)),
+      }),
       TextEncoding::UTF16BOM => {
          if text_slice.len() % 2 != 0 {
             return Err(TextDecodeError::InvalidUtf16);
          }
-         // @Speed this can be uninitialized
-         // The intermediate buffer is needed due to alignment concerns
-         let mut buffer = vec![0u16; text_slice.len() / 2].into_boxed_slice();
-         if text_slice[0..2] == [0xFE, 0xFF] {
-            text_slice.chunks(2).enumerate().for_each(|(i, c)| {
-               buffer[i] = (u16::from(c[1]) << 8) & u16::from(c[0]);
-            });
-         } else {
-            unsafe {
-               std::ptr::copy_nonoverlapping::<u8>(
-                  text_slice.as_ptr(),
-                  buffer.as_mut_ptr() as *mut u8,
-                  text_slice.len(),
-               )
-            };
+         match text_slice[0..2] {
+            [0xFE, 0xFF] => decode_utf16::<BigEndian>(&text_slice[2..], options),
+            [0xFF, 0xFE] => decode_utf16::<LittleEndian>(&text_slice[2..], options),
+            _ if options.strictness == Strictness::Strict => Err(TextDecodeError::MissingByteOrderMark),
+            // Unicode says that UTF-16 without a BOM is big endian
+            _ => decode_utf16::<BigEndian>(text_slice, options),
          }
-         Ok(String::from_utf16(&buffer[1..])?) // 1.. to skip BOM
       }
       TextEncoding::UTF16BE => {
          if text_slice.len() % 2 != 0 {
             return Err(TextDecodeError::InvalidUtf16);
          }
-         // @Speed this can be uninitialized
-         // The intermediate buffer is needed due to alignment concerns
-         let mut buffer = vec![0u16; text_slice.len() / 2].into_boxed_slice();
-         text_slice.chunks(2).enumerate().for_each(|(i, c)| {
-            buffer[i] = (u16::from(c[1]) << 8) & u16::from(c[0]);
-         });
-         Ok(String::from_utf16(&buffer)?) // No BOM
+         decode_utf16::<BigEndian>(text_slice, options)
+      }
+      TextEncoding::UTF8 if options.lossy_text => Ok(options.alloc_str(String::from_utf8_lossy(text_slice))),
+      TextEncoding::UTF8 => Ok(Cow::Borrowed(std::str::from_utf8(text_slice)?)),
+   }
+}
+
+// The bytes are read into u16s one at a time, as they aren't necessarily aligned.
+// `char::decode_utf16` takes care of surrogate pairs.
+fn decode_utf16<'a, B: ByteOrder>(
+   bytes: &[u8],
+   options: &DecodeOptions<'_, 'a>,
+) -> Result<Cow<'a, str>, TextDecodeError> {
+   let chars = std::char::decode_utf16(bytes.chunks_exact(2).map(B::read_u16));
+   if options.lossy_text {
+      return Ok(options.alloc_chars(chars.map(|x| x.unwrap_or(std::char::REPLACEMENT_CHARACTER))));
+   }
+   if chars.clone().any(|x| x.is_err()) {
+      return Err(TextDecodeError::InvalidUtf16);
+   }
+   Ok(options.alloc_chars(chars.filter_map(Result::ok)))
+}
+
+fn decode_latin1<'a>(bytes: &'a [u8]) -> Cow<'a, str> {
+   if bytes.is_ascii() {
+      // ASCII is a subset of both ISO-8859-1 and UTF-8, so no conversion is needed
+      Cow::Borrowed(unsafe { std::str::from_utf8_unchecked(bytes) })
+   } else {
+      Cow::Owned(transcode(bytes, latin1_char))
+   }
+}
+
+fn latin1_char(c: u8) -> char {
+   c as char
+}
+
+// Converts text in a single byte charset to UTF-8, with `to_char` mapping the bytes that aren't ASCII
+fn transcode(bytes: &[u8], to_char: fn(u8) -> char) -> String {
+   let mut utf8 = vec![0; transcoded_len(bytes, to_char)];
+   transcode_into(bytes, to_char, &mut utf8);
+   // Runs of ASCII are copied as is, and everything else comes from `char::encode_utf8`
+   unsafe { String::from_utf8_unchecked(utf8) }
+}
+
+fn transcoded_len(bytes: &[u8], to_char: fn(u8) -> char) -> usize {
+   bytes.len()
+      + bytes
+         .iter()
+         .filter(|c| !c.is_ascii())
+         .map(|c| to_char(*c).len_utf8() - 1)
+         .sum::<usize>()
+}
+
+// `utf8` must be `transcoded_len` bytes long. Most text is largely ASCII, so rather than converting each byte, we find
+// the runs of ASCII a word at a time and copy them whole.
+fn transcode_into(bytes: &[u8], to_char: fn(u8) -> char, utf8: &mut [u8]) {
+   let mut read = 0;
+   let mut written = 0;
+   while read < bytes.len() {
+      let ascii = ascii_len(&bytes[read..]);
+      utf8[written..written + ascii].copy_from_slice(&bytes[read..read + ascii]);
+      read += ascii;
+      written += ascii;
+      if let Some(c) = bytes.get(read) {
+         written += to_char(*c).encode_utf8(&mut utf8[written..]).len();
+         read += 1;
       }
-      TextEncoding::UTF8 => Ok(String::from(std::str::from_utf8(text_slice)?)),
    }
 }
 
-fn decode_text_frame(frame: &[u8]) -> Result<Vec<String>, FrameParseErrorReason> {
+// How many bytes at the start are ASCII, checked eight at a time
+fn ascii_len(bytes: &[u8]) -> usize {
+   let words = bytes
+      .chunks_exact(8)
+      .take_while(|x| NativeEndian::read_u64(x) & 0x8080_8080_8080_8080 == 0)
+      .count();
+   let start = words * 8;
+   start + bytes[start..].iter().take_while(|x| x.is_ascii()).count()
+}
+
+// The characters of 0x80-0x9F, which is the only part of Windows-1252 that differs from ISO-8859-1.
+// The five unassigned bytes decode as their C1 control characters, like browsers do.
+const WINDOWS_1252_HIGH: [char; 32] = [
+   '\u{20ac}', '\u{81}', '\u{201a}', '\u{192}', '\u{201e}', '\u{2026}', '\u{2020}', '\u{2021}', '\u{2c6}', '\u{2030}',
+   '\u{160}', '\u{2039}', '\u{152}', '\u{8d}', '\u{17d}', '\u{8f}', '\u{90}', '\u{2018}', '\u{2019}', '\u{201c}',
+   '\u{201d}', '\u{2022}', '\u{2013}', '\u{2014}', '\u{2dc}', '\u{2122}', '\u{161}', '\u{203a}', '\u{153}', '\u{9d}',
+   '\u{17e}', '\u{178}',
+];
+
+pub(crate) fn windows_1252_char(c: u8) -> char {
+   match c {
+      0x80..=0x9f => WINDOWS_1252_HIGH[(c - 0x80) as usize],
+      _ => c as char,
+   }
+}
+
+#[cfg(feature = "charset-detection")]
+fn decode_detected<'a>(bytes: &'a [u8]) -> Cow<'a, str> {
+   let mut detector = chardetng::EncodingDetector::new();
+   detector.feed(bytes, true);
+   let encoding = detector.guess(None, true);
+   encoding.decode_without_bom_handling(bytes).0
+}
+
+pub(super) fn decode_text_frame<'a>(
+   frame: &'a [u8],
+   options: &DecodeOptions<'_, 'a>,
+) -> Result<Values<Cow<'a, str>>, FrameParseErrorReason> {
    if frame.len() < 1 {
       return Err(FrameParseErrorReason::FrameTooSmall);
    }
    let encoding = TextEncoding::try_from(frame[0])?;
-   Ok(decode_text_segments(encoding, &frame[1..])?)
+   Ok(decode_text_segments(encoding, &frame[1..], options)?)
 }
 
-fn decode_text_map_frame(frame: &[u8]) -> Result<HashMap<String, String>, FrameParseErrorReason> {
+pub(super) fn decode_text_map_frame<'a>(
+   frame: &'a [u8],
+   options: &DecodeOptions<'_, 'a>,
+) -> Result<HashMap<Cow<'a, str>, Cow<'a, str>>, FrameParseErrorReason> {
    if frame.len() < 1 {
       return Err(FrameParseErrorReason::FrameTooSmall);
    }
    let encoding = TextEncoding::try_from(frame[0])?;
-   let separator = encoding.get_trailing_null_slice();
-   let mut start = 1;
-   let mut segment_iter = frame[1..]
-      .chunks_exact(separator.len())
-      .enumerate()
-      .filter(|(_, x)| *x == separator)
-      .map(|(i, _)| i * separator.len());
+   let mut segments = decode_text_segments(encoding, &frame[1..], options)?.into_iter();
    let mut map = HashMap::new();
-   loop {
-      let (opt_k_end, opt_v_end) = (segment_iter.next(), segment_iter.next());
-      match (opt_k_end, opt_v_end) {
-         (Some(k_end), Some(v_end)) => {
-            let key = decode_text_segment(encoding, &frame[start..k_end])?;
-            let value = decode_text_segment(encoding, &frame[k_end + separator.len()..v_end])?;
-            start = v_end + separator.len();
-            map.insert(key, value);
-         }
-         (Some(k_end), None) => {
-            if k_end + separator.len() == frame.len() {
-               return Err(FrameParseErrorReason::MissingValueInMapFrame);
-            }
-            let key = decode_text_segment(encoding, &frame[start..k_end])?;
-            let value = decode_text_segment(encoding, &frame[k_end + separator.len()..])?;
-            map.insert(key, value);
-            break;
-         }
-         (None, _) => break,
-      }
+   while let Some(key) = segments.next() {
+      let value = match segments.next() {
+         Some(v) => v,
+         None => return Err(FrameParseErrorReason::MissingValueInMapFrame),
+      };
+      map.insert(key, value);
    }
    Ok(map)
 }
 
-fn decode_priv_frame(frame_bytes: &[u8]) -> Result<FrameData, FrameParseErrorReason> {
-   let owner_end = match frame_bytes.iter().position(|x| *x == 0) {
+fn decode_priv_frame<'a>(
+   frame_bytes: &'a [u8],
+   options: &ParseOptions,
+) -> Result<FrameData<'a>, FrameParseErrorReason> {
+   let owner_end = match memchr(0, frame_bytes) {
       Some(v) => v,
+      None if options.strictness == Strictness::Lenient => frame_bytes.len(),
       None => return Err(FrameParseErrorReason::MissingNullTerminator),
    };
 
-   let data_ref = if owner_end + 1 == frame_bytes.len() {
+   let data_ref: &[u8] = if owner_end + 1 >= frame_bytes.len() {
       &[]
    } else {
       &frame_bytes[owner_end + 1..]
    };
 
    Ok(FrameData::PRIV(Priv {
-      owner: frame_bytes[0..owner_end].iter().map(|c| *c as char).collect(), // IS0 8859,
-      data: Box::from(data_ref),
+      owner: decode_latin1(&frame_bytes[0..owner_end]),
+      data: Cow::Borrowed(data_ref),
    }))
 }
 
-fn decode_description_text(
+// APIC isn't a FrameData variant, so this is only used to read `Unknown` APIC frames into pictures
+pub(super) fn decode_apic_frame<'a>(
+   frame_bytes: &'a [u8],
+   options: &ParseOptions,
+) -> Result<Picture<'a>, FrameParseErrorReason> {
+   if frame_bytes.len() < 4 {
+      return Err(FrameParseErrorReason::FrameTooSmall);
+   }
+
+   let encoding = TextEncoding::try_from(frame_bytes[0])?;
+   let mime_end = memchr(0, &frame_bytes[1..]).ok_or(FrameParseErrorReason::MissingNullTerminator)? + 1;
+   let mime_type = decode_latin1(&frame_bytes[1..mime_end]);
+   let picture_type = *frame_bytes
+      .get(mime_end + 1)
+      .ok_or(FrameParseErrorReason::FrameTooSmall)?;
+
+   let rest = &frame_bytes[mime_end + 2..];
+   let separator = encoding.get_trailing_null_slice();
+   let description_end = encoding
+      .find_terminator(rest)
+      .ok_or(FrameParseErrorReason::MissingNullTerminator)?;
+
+   Ok(Picture {
+      mime_type,
+      picture_type,
+      description: decode_text_segment(encoding, &rest[..description_end], &DecodeOptions::new(options))?,
+      data: Cow::Borrowed(&rest[description_end + separator.len()..]),
+   })
+}
+
+fn decode_description_text<'a>(
    encoding: TextEncoding,
-   bytes: &[u8],
-) -> Result<(String, Vec<String>), FrameParseErrorReason> {
+   bytes: &'a [u8],
+   options: &DecodeOptions<'_, 'a>,
+) -> Result<(Cow<'a, str>, Values<Cow<'a, str>>), FrameParseErrorReason> {
    let separator = encoding.get_trailing_null_slice();
-   let description_end = match bytes
-      .chunks_exact(separator.len())
-      .position(|x| x == separator)
-      .map(|x| x * separator.len())
-   {
+   let description_end = match encoding.find_terminator(bytes) {
       Some(v) => v,
+      None if options.strictness == Strictness::Lenient => {
+         // Treat everything as the description; there is no text
+         return Ok((decode_text_segment(encoding, bytes, options)?, Values::new()));
+      }
       None => return Err(FrameParseErrorReason::MissingNullTerminator),
    };
 
-   let description = decode_text_segment(encoding, &bytes[..description_end])?;
-   let text = decode_text_segments(encoding, &bytes[description_end + separator.len()..])?;
+   let description = decode_text_segment(encoding, &bytes[..description_end], options)?;
+   let text = decode_text_segments(encoding, &bytes[description_end + separator.len()..], options)?;
 
    Ok((description, text))
 }
 
-fn decode_lang_description_text(frame_bytes: &[u8]) -> Result<LangDescriptionText, FrameParseErrorReason> {
+fn decode_lang_description_text<'a>(
+   frame_bytes: &'a [u8],
+   options: &DecodeOptions<'_, 'a>,
+) -> Result<LangDescriptionText<'a>, FrameParseErrorReason> {
    if frame_bytes.len() < 5 {
       return Err(FrameParseErrorReason::FrameTooSmall);
    }
@@ -716,7 +2449,7 @@ fn decode_lang_description_text(frame_bytes: &[u8]) -> Result<LangDescriptionTex
       lang_code
    };
 
-   let (description, text) = decode_description_text(encoding, &frame_bytes[4..])?;
+   let (description, text) = decode_description_text(encoding, &frame_bytes[4..], options)?;
 
    Ok(LangDescriptionText {
       iso_639_2_lang,
@@ -725,141 +2458,173 @@ fn decode_lang_description_text(frame_bytes: &[u8]) -> Result<LangDescriptionTex
    })
 }
 
-fn decode_txxx_frame(frame_bytes: &[u8]) -> Result<FrameData, FrameParseErrorReason> {
+fn decode_txxx_frame<'a>(
+   frame_bytes: &'a [u8],
+   options: &DecodeOptions<'_, 'a>,
+) -> Result<FrameData<'a>, FrameParseErrorReason> {
    if frame_bytes.len() < 2 {
       return Err(FrameParseErrorReason::FrameTooSmall);
    }
 
    let encoding = TextEncoding::try_from(frame_bytes[0])?;
 
-   let (description, text) = decode_description_text(encoding, &frame_bytes[1..])?;
+   let (description, text) = decode_description_text(encoding, &frame_bytes[1..], options)?;
 
    Ok(FrameData::TXXX(Txxx { description, text }))
 }
 
-fn decode_genre_frame(frame_bytes: &[u8]) -> Result<FrameData, FrameParseErrorReason> {
-   let mut genres = decode_text_frame(frame_bytes)?;
-   for genre in genres.iter_mut() {
-      match genre.as_ref() {
-         "0" => *genre = String::from("Blues"),
-         "1" => *genre = String::from("Classic Rock"),
-         "2" => *genre = String::from("Country"),
-         "3" => *genre = String::from("Dance"),
-         "4" => *genre = String::from("Disco"),
-         "5" => *genre = String::from("Funk"),
-         "6" => *genre = String::from("Grunge"),
-         "7" => *genre = String::from("Hip-Hop"),
-         "8" => *genre = String::from("Jazz"),
-         "9" => *genre = String::from("Metal"),
-         "10" => *genre = String::from("New Age"),
-         "11" => *genre = String::from("Oldies"),
-         "12" => *genre = String::from("Other"),
-         "13" => *genre = String::from("Pop"),
-         "14" => *genre = String::from("R&B"),
-         "15" => *genre = String::from("Rap"),
-         "16" => *genre = String::from("Reggae"),
-         "17" => *genre = String::from("Rock"),
-         "18" => *genre = String::from("Techno"),
-         "19" => *genre = String::from("Industrial"),
-         "20" => *genre = String::from("Alternative"),
-         "21" => *genre = String::from("Ska"),
-         "22" => *genre = String::from("Death Metal"),
-         "23" => *genre = String::from("Pranks"),
-         "24" => *genre = String::from("Soundtrack"),
-         "25" => *genre = String::from("Euro-Techno"),
-         "26" => *genre = String::from("Ambient"),
-         "27" => *genre = String::from("Trip-Hop"),
-         "28" => *genre = String::from("Vocal"),
-         "29" => *genre = String::from("Jazz+Funk"),
-         "30" => *genre = String::from("Fusion"),
-         "31" => *genre = String::from("Trance"),
-         "32" => *genre = String::from("Classical"),
-         "33" => *genre = String::from("Instrumental"),
-         "34" => *genre = String::from("Acid"),
-         "35" => *genre = String::from("House"),
-         "36" => *genre = String::from("Game"),
-         "37" => *genre = String::from("Sound Clip"),
-         "38" => *genre = String::from("Gospel"),
-         "39" => *genre = String::from("Noise"),
-         "40" => *genre = String::from("AlternRock"),
-         "41" => *genre = String::from("Bass"),
-         "42" => *genre = String::from("Soul"),
-         "43" => *genre = String::from("Punk"),
-         "44" => *genre = String::from("Space"),
-         "45" => *genre = String::from("Meditative"),
-         "46" => *genre = String::from("Instrumental Pop"),
-         "47" => *genre = String::from("Instrumental Rock"),
-         "48" => *genre = String::from("Ethnic"),
-         "49" => *genre = String::from("Gothic"),
-         "50" => *genre = String::from("Darkwave"),
-         "51" => *genre = String::from("Techno-Industrial"),
-         "52" => *genre = String::from("Electronic"),
-         "53" => *genre = String::from("Pop-Folk"),
-         "54" => *genre = String::from("Eurodance"),
-         "55" => *genre = String::from("Dream"),
-         "56" => *genre = String::from("Southern Rock"),
-         "57" => *genre = String::from("Comedy"),
-         "58" => *genre = String::from("Cult"),
-         "59" => *genre = String::from("Gangsta"),
-         "60" => *genre = String::from("Top 40"),
-         "61" => *genre = String::from("Christian Rap"),
-         "62" => *genre = String::from("Pop/Funk"),
-         "63" => *genre = String::from("Jungle"),
-         "64" => *genre = String::from("Native American"),
-         "65" => *genre = String::from("Cabaret"),
-         "66" => *genre = String::from("New Wave"),
-         "67" => *genre = String::from("Psychedelic"),
-         "68" => *genre = String::from("Rave"),
-         "69" => *genre = String::from("Showtunes"),
-         "70" => *genre = String::from("Trailer"),
-         "71" => *genre = String::from("Lo-Fi"),
-         "72" => *genre = String::from("Tribal"),
-         "73" => *genre = String::from("Acid Punk"),
-         "74" => *genre = String::from("Acid Jazz"),
-         "75" => *genre = String::from("Polka"),
-         "76" => *genre = String::from("Retro"),
-         "77" => *genre = String::from("Musical"),
-         "78" => *genre = String::from("Rock & Roll"),
-         "79" => *genre = String::from("Hard Rock"),
-         "RX" => *genre = String::from("Remix"),
-         "CR" => *genre = String::from("Cover"),
-         _ => (),
-      };
+fn decode_genre_frame<'a>(
+   frame_bytes: &'a [u8],
+   options: &DecodeOptions<'_, 'a>,
+) -> Result<FrameData<'a>, FrameParseErrorReason> {
+   let mut genres = Values::new();
+   for text in decode_text_frame(frame_bytes, options)? {
+      match text {
+         Cow::Borrowed(x) => genres.extend(genre::parse_references(x)),
+         Cow::Owned(x) => genres.extend(
+            genre::parse_references(&x)
+               .into_iter()
+               .map(|y| Cow::Owned(y.into_owned())),
+         ),
+      }
    }
    Ok(FrameData::TCON(genres))
 }
 
-fn decode_copyright_frame(mut text: String) -> Result<Copyright, FrameParseErrorReason> {
-   // slicing into UTF-8 character
-   let year = if let Some(year_text) = text.get(0..4) {
-      year_text.parse()?
-   } else {
-      // could also be that we are slicing into a UTF-8 character,
-      // so the error message is slightly misleading in that case
-      return Err(FrameParseErrorReason::FrameTooSmall)
+// iTunes writes "1" for compilations, and either "0" or no frame at all otherwise
+fn decode_compilation_frame(frame_bytes: &[u8], options: &DecodeOptions) -> Result<bool, FrameParseErrorReason> {
+   let values: Values<u64> = map_parse(decode_text_frame(frame_bytes, options)?)?;
+   Ok(values.iter().any(|x| *x != 0))
+}
+
+// iTunes writes 4 zero bytes. The frame being there is what marks a podcast, so the value doesn't matter.
+fn decode_podcast_frame(frame_bytes: &[u8]) -> Result<FrameData<'static>, FrameParseErrorReason> {
+   if frame_bytes.len() < 4 {
+      return Err(FrameParseErrorReason::FrameTooSmall);
+   }
+
+   Ok(FrameData::PCST)
+}
+
+fn decode_copyright_frame<'a>(
+   frame_bytes: &'a [u8],
+   options: &DecodeOptions<'_, 'a>,
+) -> Result<Values<Copyright<'a>>, FrameParseErrorReason> {
+   let mut copyrights = Values::new();
+   for text in decode_text_frame(frame_bytes, options)? {
+      copyrights.push(decode_copyright(text, options)?);
+   }
+   Ok(copyrights)
+}
+
+// "YYYY message", where the message is optional. Lenient parsing also accepts a missing space.
+fn decode_copyright<'a>(text: Cow<'a, str>, options: &ParseOptions) -> Result<Copyright<'a>, ParseCopyrightError> {
+   let bytes = text.as_bytes();
+   let year_digits = match bytes.get(0..4) {
+      Some(v) if v.iter().all(u8::is_ascii_digit) => v,
+      _ => return Err(ParseCopyrightError::InvalidYear),
    };
-   let text_bytes = unsafe { text.as_mut_vec() };
-   unsafe {
-      if text_bytes.len() > 4 && text_bytes[4] == b' ' {
-         text_bytes.set_len(text_bytes.len() - 5);
-         std::ptr::copy(text_bytes.as_ptr().offset(5), text_bytes.as_mut_ptr(), text_bytes.len());
-      } else {
-         text_bytes.set_len(text_bytes.len() - 4);
-         std::ptr::copy(text_bytes.as_ptr().offset(4), text_bytes.as_mut_ptr(), text_bytes.len());
+   let year = year_digits.iter().fold(0, |acc, x| acc * 10 + u16::from(x - b'0'));
+
+   let message_start = match bytes.get(4) {
+      None => 4,
+      Some(b' ') => 5,
+      Some(_) if options.strictness == Strictness::Lenient => 4,
+      Some(_) => return Err(ParseCopyrightError::MissingSpace),
+   };
+   // The year is ASCII, so the message starts on a character boundary
+   let message = match text {
+      Cow::Borrowed(text) => Cow::Borrowed(&text[message_start..]),
+      Cow::Owned(mut text) => {
+         text.drain(..message_start);
+         Cow::Owned(text)
       }
-   }
-   Ok(Copyright { year, message: text })
+   };
+   Ok(Copyright { year, message })
 }
 
 // We don't do full URL parsing (for instance; with the URL crate)
 // because the id3 spec says that relative URLs are always ok
 // and that doesn't jive with general URL parsing
-fn decode_url_frame(mut frame: &[u8]) -> String {
+fn decode_url_frame<'a>(mut frame: &'a [u8]) -> Cow<'a, str> {
    if frame.len() > 0 && frame[frame.len() - 1] == 0 {
       frame = &frame[..frame.len() - 1];
    }
 
-   frame.iter().map(|c| *c as char).collect()
+   decode_latin1(frame)
+}
+
+fn decode_rva2_frame<'a>(frame: &'a [u8], options: &ParseOptions) -> Result<Rva2<'a>, FrameParseErrorReason> {
+   let identification_end = match memchr(0, frame) {
+      Some(v) => v,
+      None if options.strictness == Strictness::Lenient => frame.len(),
+      None => return Err(FrameParseErrorReason::MissingNullTerminator),
+   };
+
+   let mut channels = Vec::new();
+   let mut rest = frame.get(identification_end + 1..).unwrap_or(&[]);
+   while !rest.is_empty() {
+      if rest.len() < 4 {
+         return Err(FrameParseErrorReason::FrameTooSmall);
+      }
+      let peak_bits = rest[3];
+      let peak_len = (peak_bits as usize + 7) / 8;
+      let peak_bytes = match rest.get(4..4 + peak_len) {
+         Some(v) => v,
+         None => return Err(FrameParseErrorReason::FrameTooSmall),
+      };
+      // The peak is an unsigned integer where 1.0 is 2^(peak_bits - 1), and it can be up to 255 bits wide
+      let peak = if peak_bits == 0 || peak_len > 8 {
+         None
+      } else {
+         let value = peak_bytes.iter().fold(0u64, |acc, x| acc << 8 | u64::from(*x));
+         Some((value as f64 / 2f64.powi(i32::from(peak_bits) - 1)) as f32)
+      };
+
+      channels.push(Rva2Channel {
+         channel: rest[0],
+         // Fixed point, in 1/512 dB
+         adjustment: f32::from(BigEndian::read_i16(&rest[1..3])) / 512.0,
+         peak,
+      });
+      rest = &rest[4 + peak_len..];
+   }
+
+   Ok(Rva2 {
+      identification: decode_latin1(&frame[0..identification_end]),
+      channels,
+   })
+}
+
+fn decode_equ2_frame<'a>(frame: &'a [u8], options: &ParseOptions) -> Result<Equ2<'a>, FrameParseErrorReason> {
+   if frame.is_empty() {
+      return Err(FrameParseErrorReason::FrameTooSmall);
+   }
+   let identification_end = match memchr(0, &frame[1..]) {
+      Some(v) => v + 1,
+      None if options.strictness == Strictness::Lenient => frame.len(),
+      None => return Err(FrameParseErrorReason::MissingNullTerminator),
+   };
+
+   let rest = frame.get(identification_end + 1..).unwrap_or(&[]);
+   if rest.len() % 4 != 0 {
+      return Err(FrameParseErrorReason::FrameTooSmall);
+   }
+   let points = rest
+      .chunks_exact(4)
+      .map(|x| Equ2Point {
+         // In 1/2 Hz
+         frequency: f32::from(BigEndian::read_u16(&x[0..2])) / 2.0,
+         // Fixed point, in 1/512 dB
+         adjustment: f32::from(BigEndian::read_i16(&x[2..4])) / 512.0,
+      })
+      .collect();
+
+   Ok(Equ2 {
+      interpolation: frame[0],
+      identification: decode_latin1(&frame[1..identification_end]),
+      points,
+   })
 }
 
 fn decode_reverb_frame(frame: &[u8]) -> Result<Reverb, FrameParseErrorReason> {
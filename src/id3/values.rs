@@ -0,0 +1,187 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::iter::FromIterator;
+use std::ops::{Deref, DerefMut};
+
+/// The values of a frame that can hold several, such as the strings of a text frame. Nearly every frame holds exactly
+/// one, which is kept inline, so only frames with more than that need a heap allocation. Derefs to a slice.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Values<T>(Repr<T>);
+
+#[derive(Clone, Debug, PartialEq)]
+enum Repr<T> {
+   One(T),
+   // Empty, or more than one
+   Many(Vec<T>),
+}
+
+impl<T> Values<T> {
+   pub fn new() -> Values<T> {
+      Values(Repr::Many(Vec::new()))
+   }
+
+   pub fn one(value: T) -> Values<T> {
+      Values(Repr::One(value))
+   }
+
+   pub fn push(&mut self, value: T) {
+      let repr = std::mem::replace(&mut self.0, Repr::Many(Vec::new()));
+      self.0 = match repr {
+         Repr::Many(ref values) if values.is_empty() => Repr::One(value),
+         Repr::Many(mut values) => {
+            values.push(value);
+            Repr::Many(values)
+         }
+         Repr::One(first) => Repr::Many(vec![first, value]),
+      };
+   }
+
+   /// Converts each value, keeping them inline if they were
+   pub fn map<U, F: FnMut(T) -> U>(self, mut f: F) -> Values<U> {
+      Values(match self.0 {
+         Repr::One(x) => Repr::One(f(x)),
+         Repr::Many(x) => Repr::Many(x.into_iter().map(f).collect()),
+      })
+   }
+
+   pub fn into_vec(self) -> Vec<T> {
+      match self.0 {
+         Repr::One(x) => vec![x],
+         Repr::Many(x) => x,
+      }
+   }
+}
+
+impl<T> Default for Values<T> {
+   fn default() -> Values<T> {
+      Values::new()
+   }
+}
+
+impl<T> Deref for Values<T> {
+   type Target = [T];
+
+   fn deref(&self) -> &[T] {
+      match &self.0 {
+         Repr::One(x) => std::slice::from_ref(x),
+         Repr::Many(x) => x,
+      }
+   }
+}
+
+impl<T> DerefMut for Values<T> {
+   fn deref_mut(&mut self) -> &mut [T] {
+      match &mut self.0 {
+         Repr::One(x) => std::slice::from_mut(x),
+         Repr::Many(x) => x,
+      }
+   }
+}
+
+impl<T> From<Vec<T>> for Values<T> {
+   fn from(mut values: Vec<T>) -> Values<T> {
+      if values.len() == 1 {
+         Values(Repr::One(values.remove(0)))
+      } else {
+         Values(Repr::Many(values))
+      }
+   }
+}
+
+impl<T> FromIterator<T> for Values<T> {
+   fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Values<T> {
+      let mut values = Values::new();
+      for x in iter {
+         values.push(x);
+      }
+      values
+   }
+}
+
+impl<T> Extend<T> for Values<T> {
+   fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+      for x in iter {
+         self.push(x);
+      }
+   }
+}
+
+/// The owning iterator of `Values`
+pub struct IntoIter<T>(IntoIterRepr<T>);
+
+enum IntoIterRepr<T> {
+   One(std::option::IntoIter<T>),
+   Many(std::vec::IntoIter<T>),
+}
+
+impl<T> Iterator for IntoIter<T> {
+   type Item = T;
+
+   fn next(&mut self) -> Option<T> {
+      match &mut self.0 {
+         IntoIterRepr::One(x) => x.next(),
+         IntoIterRepr::Many(x) => x.next(),
+      }
+   }
+
+   fn size_hint(&self) -> (usize, Option<usize>) {
+      match &self.0 {
+         IntoIterRepr::One(x) => x.size_hint(),
+         IntoIterRepr::Many(x) => x.size_hint(),
+      }
+   }
+}
+
+impl<T> IntoIterator for Values<T> {
+   type Item = T;
+   type IntoIter = IntoIter<T>;
+
+   fn into_iter(self) -> IntoIter<T> {
+      IntoIter(match self.0 {
+         Repr::One(x) => IntoIterRepr::One(Some(x).into_iter()),
+         Repr::Many(x) => IntoIterRepr::Many(x.into_iter()),
+      })
+   }
+}
+
+impl<'a, T> IntoIterator for &'a Values<T> {
+   type Item = &'a T;
+   type IntoIter = std::slice::Iter<'a, T>;
+
+   fn into_iter(self) -> std::slice::Iter<'a, T> {
+      self.iter()
+   }
+}
+
+// Serialized as a plain list, however many there are
+#[cfg(feature = "serde")]
+impl<T: Serialize> Serialize for Values<T> {
+   fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+      serializer.collect_seq(self.iter())
+   }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Values<T> {
+   fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Values<T>, D::Error> {
+      Vec::deserialize(deserializer).map(Values::from)
+   }
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+
+   #[test]
+   fn one_or_many() {
+      let mut values = Values::new();
+      assert!(values.is_empty());
+      values.push("a");
+      assert_eq!(values, Values::one("a"));
+      values.push("b");
+      assert_eq!(&values[..], ["a", "b"]);
+      assert_eq!(values.clone().into_iter().collect::<Vec<_>>(), ["a", "b"]);
+      assert_eq!(Values::from(vec!["a"]), Values::one("a"));
+      assert_eq!(values.map(str::len).into_vec(), [1, 1]);
+   }
+}
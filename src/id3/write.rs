@@ -0,0 +1,582 @@
+use super::convert::{self, Version};
+use super::synchsafe::u32_to_synchsafe_u32;
+use super::v1::Id3v1Tag;
+use super::v24::{self, Frame, FrameData, FrameFlags, FrameValueError, TextEncoding, Unknown};
+use super::{Id3Tag, ParseOptions, Strictness, TagParseError};
+use crate::probe::{self, Format, ProbeError};
+use crate::sort::{self, SortOptions};
+use crate::strip::{self, StripOptions, TagLayout};
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum WriteError {
+   InvalidValue(FrameValueError),
+   FrameTooLarge([u8; 4]),
+   TagTooLarge,
+   /// Only MPEG audio and ADTS files can start with an ID3 tag
+   UnsupportedFormat,
+   /// The frame to patch isn't where it was parsed from, so the file has changed since
+   FrameNotFound,
+   Io(io::Error),
+}
+
+impl fmt::Display for WriteError {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      match self {
+         WriteError::InvalidValue(e) => write!(f, "invalid frame value: {}", e),
+         WriteError::FrameTooLarge(name) => write!(
+            f,
+            "frame {} is larger than the 256 MiB an ID3 size can hold",
+            String::from_utf8_lossy(name)
+         ),
+         WriteError::TagTooLarge => write!(f, "tag is larger than the 256 MiB an ID3 size can hold"),
+         WriteError::UnsupportedFormat => write!(f, "only MPEG audio and ADTS files can hold an ID3 tag at the start"),
+         WriteError::FrameNotFound => write!(f, "the frame is no longer where it was read from"),
+         WriteError::Io(e) => write!(f, "I/O error: {}", e),
+      }
+   }
+}
+
+impl Error for WriteError {
+   fn source(&self) -> Option<&(dyn Error + 'static)> {
+      match self {
+         WriteError::InvalidValue(e) => Some(e),
+         WriteError::Io(e) => Some(e),
+         _ => None,
+      }
+   }
+}
+
+impl From<FrameValueError> for WriteError {
+   fn from(e: FrameValueError) -> WriteError {
+      WriteError::InvalidValue(e)
+   }
+}
+
+impl From<io::Error> for WriteError {
+   fn from(e: io::Error) -> WriteError {
+      WriteError::Io(e)
+   }
+}
+
+/// An attached picture (an APIC frame)
+#[derive(Clone, Debug)]
+pub struct Picture<'a> {
+   pub mime_type: Cow<'a, str>,
+   /// 0 = other, 3 = front cover, 4 = back cover. The APIC section of the spec lists the rest.
+   pub picture_type: u8,
+   pub description: Cow<'a, str>,
+   pub data: Cow<'a, [u8]>,
+}
+
+impl<'a> Picture<'a> {
+   pub fn front_cover<M: Into<Cow<'a, str>>, D: Into<Cow<'a, [u8]>>>(mime_type: M, data: D) -> Picture<'a> {
+      Picture {
+         mime_type: mime_type.into(),
+         picture_type: 3,
+         description: Cow::Borrowed(""),
+         data: data.into(),
+      }
+   }
+
+   /// Reads the picture from an APIC frame. Returns `None` for any other frame, or if the frame is malformed.
+   pub fn from_frame_data(data: &'a FrameData) -> Option<Picture<'a>> {
+      let options = ParseOptions {
+         strictness: Strictness::Lenient,
+         lossy_text: true,
+         ..ParseOptions::default()
+      };
+      match data {
+         FrameData::Unknown(x) if x.name == *b"APIC" => v24::decode_apic_frame(&x.data, &options).ok(),
+         _ => None,
+      }
+   }
+
+   /// APIC isn't decoded by the parser, so the picture is held as an unknown frame
+   pub fn to_frame_data(&self) -> FrameData<'static> {
+      let encoding = choose_encoding(&[&self.description]);
+      let mut body = vec![encoding as u8];
+      body.extend(latin1_lossy(&self.mime_type));
+      body.push(0);
+      body.push(self.picture_type);
+      push_text(&mut body, encoding, &self.description);
+      body.push(0);
+      body.extend_from_slice(&self.data);
+      FrameData::Unknown(Unknown {
+         name: *b"APIC",
+         data: Cow::Owned(body),
+      })
+   }
+}
+
+/// Encodes the tag as ID3v2.4, followed by `padding` zero bytes.
+///
+/// Text is written as ISO-8859-1 when it can be, and as UTF-8 otherwise.
+pub fn encode_tag(tag: &Id3Tag, padding: u32) -> Result<Vec<u8>, WriteError> {
+   let mut frames = Vec::new();
+   for frame in tag.frames.iter() {
+      encode_frame(frame, &mut frames)?;
+   }
+   add_header(frames, padding)
+}
+
+fn add_header(frames: Vec<u8>, padding: u32) -> Result<Vec<u8>, WriteError> {
+   let size = u32::try_from(frames.len() as u64 + u64::from(padding))
+      .ok()
+      .and_then(u32_to_synchsafe_u32)
+      .ok_or(WriteError::TagTooLarge)?;
+
+   let mut bytes = Vec::with_capacity(10 + frames.len() + padding as usize);
+   // Version 2.4.0, with no flags
+   bytes.extend_from_slice(b"ID3\x04\x00\x00");
+   bytes.extend_from_slice(&size.to_be_bytes());
+   bytes.extend(frames);
+   bytes.resize(bytes.len() + padding as usize, 0);
+   Ok(bytes)
+}
+
+pub fn write_tag<W: Write>(dest: &mut W, tag: &Id3Tag, padding: u32) -> Result<(), WriteError> {
+   dest.write_all(&encode_tag(tag, padding)?)?;
+   Ok(())
+}
+
+/// Reads the tag of a file to be edited and written back with `write_tag_to_file`. Every tag in the file is read (see
+/// `Id3Tag::read_all`), and the frames are upgraded to ID3v2.4. Frames that can't be decoded are kept as `Unknown`
+/// frames, so they're written back unchanged, except for SEEK frames, since the tags they point to are merged in and
+/// then removed by `write_tag_to_file`. A file that doesn't start with a tag gives an empty one.
+pub fn read_tag_for_update<P: AsRef<Path>>(path: P) -> Result<Id3Tag, TagParseError> {
+   let mut file = File::open(path)?;
+   let mut header = Vec::new();
+   (&mut file).take(10).read_to_end(&mut header)?;
+   if super::tag_len(&header).is_none() {
+      return Ok(Id3Tag::default());
+   }
+   let options = ParseOptions {
+      keep_undecodable: true,
+      ..ParseOptions::default()
+   };
+   let mut tag = Id3Tag::read_all(&mut file, options)?;
+   tag.frames.retain(|x| match &x.data {
+      FrameData::Unknown(u) => &u.name != b"SEEK",
+      _ => true,
+   });
+   Ok(convert::convert(tag, Version::V24).tag)
+}
+
+/// How `write_tag_to_file` uses padding, the zeros after the frames that let a tag grow without moving the audio
+#[derive(Copy, Clone, Debug)]
+pub struct Padding {
+   /// When the tag no longer fits in the space the old tags took up, the file has to be rewritten, and the new tag
+   /// is grown to a multiple of this many bytes so the next few edits fit
+   pub chunk: u32,
+   /// A tag that fits is still rewritten if it would leave more padding than this, so space is given back once the
+   /// tag shrinks (e.g. when artwork is removed). A rewritten tag never gets more than this either.
+   pub max: u32,
+}
+
+impl Default for Padding {
+   fn default() -> Padding {
+      Padding {
+         chunk: 2048,
+         max: 64 * 1024,
+      }
+   }
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct WriteOptions {
+   pub padding: Padding,
+   /// If set, the ID3v1 tag at the end of the file is replaced with one made from the new tag (see
+   /// `Id3v1Tag::from_tag`), and one is added if there isn't one, for the players that only read ID3v1
+   pub sync_v1: bool,
+   /// If set, the sort frames the tag is missing are added (see `sort::add_sort_frames`)
+   pub sort_frames: Option<SortOptions>,
+}
+
+/// Replaces the ID3v2 tags at the start of the file with the given tag, and removes any appended to the end, since
+/// `read_tag_for_update` merges them into the tag being written. The audio and the ID3v1 tag are kept as they are,
+/// unless `options.sync_v1` is set. Since the tag has changed, unknown frames that ask to be discarded when it does
+/// are left out (see `discard_on_alter`).
+///
+/// If the tag fits in the space the old tags took up, it's written over them, and the rest of the space becomes
+/// padding. Otherwise the file is replaced as with `strip::rewrite_file`, with the padding `options.padding` asks for.
+pub fn write_tag_to_file<P: AsRef<Path>>(path: P, tag: &Id3Tag, options: WriteOptions) -> Result<(), WriteError> {
+   let path = path.as_ref();
+   let padding = options.padding;
+   let mut sorted_tag;
+   let tag = match options.sort_frames {
+      Some(sort_options) => {
+         sorted_tag = tag.clone();
+         sort::add_sort_frames(&mut sorted_tag, &sort_options);
+         &sorted_tag
+      }
+      None => tag,
+   };
+   let mut frames = Vec::new();
+   for frame in tag.frames.iter().filter(|x| !discard_on_alter(x, false)) {
+      encode_frame(frame, &mut frames)?;
+   }
+   let mut source = File::open(path)?;
+   match probe::sniff(&mut source) {
+      Ok(Format::Mpeg) | Ok(Format::Adts) => (),
+      Err(ProbeError::Io(e)) => return Err(e.into()),
+      _ => return Err(WriteError::UnsupportedFormat),
+   }
+   let layout = strip::find_tags(&mut source, &StripOptions::default())?;
+   let v1_tag = if options.sync_v1 {
+      Some(Id3v1Tag::from_tag(tag).to_bytes())
+   } else {
+      None
+   };
+   let file_len = source.seek(SeekFrom::End(0))?;
+   let old_v1 = find_v1_tag(&mut source, &layout, file_len)?;
+   // Appended ID3v2 tags can only be removed by rewriting the file
+   let appended = file_len - layout.audio_end > if old_v1.is_some() { Id3v1Tag::LEN } else { 0 };
+
+   let len = 10 + frames.len() as u64;
+   if !appended && len <= layout.audio_start && layout.audio_start - len <= u64::from(padding.max) {
+      let bytes = add_header(frames, (layout.audio_start - len) as u32)?;
+      drop(source);
+      let mut file = OpenOptions::new().write(true).open(path)?;
+      file.write_all(&bytes)?;
+      if let Some(v1_tag) = v1_tag {
+         // Over the old one if there is one
+         file.seek(SeekFrom::Start(old_v1.unwrap_or(file_len)))?;
+         file.write_all(&v1_tag)?;
+      }
+      return Ok(());
+   }
+
+   let chunk = u64::from(padding.chunk.max(1));
+   let grown = (len / chunk + 1) * chunk;
+   let bytes = add_header(frames, (grown - len).min(u64::from(padding.max)) as u32)?;
+   strip::rewrite_file(path, source, |source, temp| {
+      temp.write_all(&bytes)?;
+      source.seek(SeekFrom::Start(layout.audio_start))?;
+      io::copy(&mut source.take(layout.audio_end - layout.audio_start), temp)?;
+      match (v1_tag, old_v1) {
+         (Some(v1_tag), _) => temp.write_all(&v1_tag)?,
+         (None, Some(old_v1)) => {
+            source.seek(SeekFrom::Start(old_v1))?;
+            io::copy(&mut source.take(Id3v1Tag::LEN), temp)?;
+         }
+         (None, None) => (),
+      }
+      Ok(())
+   })?;
+   Ok(())
+}
+
+// Where the ID3v1 tag is among the tags at the end, the rest of which are appended ID3v2 tags (see `strip::find_tags`)
+fn find_v1_tag<S: Read + Seek>(source: &mut S, layout: &TagLayout, file_len: u64) -> io::Result<Option<u64>> {
+   if !layout.id3v1_tag {
+      return Ok(None);
+   }
+   let mut end = file_len;
+   while end > layout.audio_end {
+      let available = end - layout.audio_end;
+      if available >= Id3v1Tag::LEN && strip::marker_at(source, end - Id3v1Tag::LEN, b"TAG")? {
+         return Ok(Some(end - Id3v1Tag::LEN));
+      }
+      match strip::appended_id3v2_len(source, end, available)? {
+         Some(len) if len <= available => end -= len,
+         _ => break,
+      }
+   }
+   Ok(None)
+}
+
+/// Whether an unknown frame asks to be discarded now that the tag has changed, or if `audio_changed`, now that the
+/// audio has as well (its tag alter and file alter preservation flags). Frames that are decoded are always kept, since
+/// what they mean is known.
+pub fn discard_on_alter(frame: &Frame, audio_changed: bool) -> bool {
+   let flags = if audio_changed {
+      FrameFlags::TAG_ALTER_PRESERVATION | FrameFlags::FILE_ALTER_PRESERVATION
+   } else {
+      FrameFlags::TAG_ALTER_PRESERVATION
+   };
+   match frame.data {
+      FrameData::Unknown(_) => frame.flags.intersects(flags),
+      _ => false,
+   }
+}
+
+/// Overwrites one frame of an ID3v2.4 tag without rewriting the rest of the file. `frame` is a frame parsed from the
+/// tag starting at `tag_start` (see `id3::TagInfo::tag_start`), with its data changed; its offset and size say which frame
+/// to replace. The frames after it are moved to make room, taking from or giving back to the padding.
+///
+/// Returns `false`, leaving the file as it was, if the new frame doesn't fit in the frame and the padding, or if the
+/// tag is unsynchronized or has a CRC, which would have to be redone. `write_tag_to_file` can write it then.
+pub fn patch_frame_in_place<F: Read + Write + Seek>(
+   file: &mut F,
+   tag_start: u64,
+   frame: &Frame,
+) -> Result<bool, WriteError> {
+   let mut header = Vec::new();
+   file.seek(SeekFrom::Start(tag_start))?;
+   file.take(10).read_to_end(&mut header)?;
+   let tag_len = match super::tag_len(&header) {
+      Some(v) if header[3] == 4 => v,
+      _ => return Err(WriteError::FrameNotFound),
+   };
+   let flags = v24::TagFlags::from_bits_truncate(header[5]);
+   if flags.intersects(v24::TagFlags::UNSYNCHRONIZED | v24::TagFlags::FOOTER_PRESENT) {
+      return Ok(false);
+   }
+
+   let mut tag = Vec::new();
+   file.seek(SeekFrom::Start(tag_start))?;
+   file.take(tag_len).read_to_end(&mut tag)?;
+   let raw = super::parse_raw(&tag, ParseOptions::default()).map_err(|_| WriteError::FrameNotFound)?;
+   if raw.info().crc_valid.is_some() {
+      return Ok(false);
+   }
+
+   // Padding follows the last frame, up to the end of the tag
+   let mut found = false;
+   let mut frames_end = 0;
+   for raw_frame in raw {
+      let raw_frame = match raw_frame {
+         Ok(v) => v,
+         Err(_) => return Ok(false),
+      };
+      found |= raw_frame.offset == frame.offset && raw_frame.size == frame.size;
+      frames_end = frames_end.max(raw_frame.byte_range(0).end as usize);
+   }
+   if !found {
+      return Err(WriteError::FrameNotFound);
+   }
+
+   let old = frame.byte_range(0);
+   let (start, end) = (old.start as usize, old.end as usize);
+   let mut patch = Vec::new();
+   encode_frame(frame, &mut patch)?;
+   if patch.len() != end - start {
+      if patch.len() + (frames_end - end) > tag.len() - start {
+         return Ok(false);
+      }
+      // The frames after it move, and if the frame shrank, zeros cover where they used to end
+      patch.extend_from_slice(&tag[end..frames_end]);
+      let moved_len = patch.len().max(frames_end - start);
+      patch.resize(moved_len, 0);
+   }
+
+   file.seek(SeekFrom::Start(tag_start + old.start))?;
+   file.write_all(&patch)?;
+   Ok(true)
+}
+
+/// Appends the frame, with its header, to `out`
+pub fn encode_frame(frame: &Frame, out: &mut Vec<u8>) -> Result<(), WriteError> {
+   let body = encode_frame_data(&frame.data);
+   let size = u32::try_from(body.len() + if frame.group.is_some() { 1 } else { 0 })
+      .ok()
+      .and_then(u32_to_synchsafe_u32)
+      .ok_or_else(|| WriteError::FrameTooLarge(frame.data.name()))?;
+
+   // The body is always written plainly, so only the status flags carry over. An unknown frame that was compressed or
+   // encrypted is still, and holds the bytes those flags add (see `Unknown`), so they carry over too.
+   let mut flags =
+      frame.flags & (FrameFlags::TAG_ALTER_PRESERVATION | FrameFlags::FILE_ALTER_PRESERVATION | FrameFlags::READ_ONLY);
+   let opaque = FrameFlags::COMPRESSION | FrameFlags::ENCRYPTION;
+   if let FrameData::Unknown(_) = frame.data {
+      if frame.flags.intersects(opaque) {
+         flags |= frame.flags & (opaque | FrameFlags::DATA_LENGTH_INDICATOR);
+      }
+   }
+   if frame.group.is_some() {
+      flags |= FrameFlags::GROUPING_IDENTITY;
+   }
+
+   out.extend_from_slice(&frame.data.name());
+   out.extend_from_slice(&size.to_be_bytes());
+   out.extend_from_slice(&flags.bits().to_be_bytes());
+   if let Some(group) = frame.group {
+      out.push(group);
+   }
+   out.extend(body);
+   Ok(())
+}
+
+/// Encodes the body of a frame, without its header
+pub fn encode_frame_data(data: &FrameData) -> Vec<u8> {
+   match data {
+      FrameData::COMM(x) | FrameData::USLT(x) => description_text_frame(&x.iso_639_2_lang, &x.description, &x.text),
+      FrameData::EQU2(x) => {
+         let mut body = vec![x.interpolation];
+         body.extend(latin1_lossy(&x.identification));
+         body.push(0);
+         for point in x.points.iter() {
+            // In 1/2 Hz
+            let frequency = (f64::from(point.frequency) * 2.0).round().max(0.0).min(65535.0) as u16;
+            body.extend_from_slice(&frequency.to_be_bytes());
+            body.extend_from_slice(&fixed_point_db(point.adjustment).to_be_bytes());
+         }
+         body
+      }
+      FrameData::MVIN(x) | FrameData::TPOS(x) | FrameData::TRCK(x) => display_frame(x),
+      FrameData::PCST => vec![0; 4],
+      FrameData::PRIV(x) => {
+         let mut body: Vec<u8> = latin1_lossy(&x.owner).collect();
+         body.push(0);
+         body.extend_from_slice(&x.data);
+         body
+      }
+      FrameData::RVA2(x) => {
+         let mut body: Vec<u8> = latin1_lossy(&x.identification).collect();
+         body.push(0);
+         for channel in x.channels.iter() {
+            body.push(channel.channel);
+            body.extend_from_slice(&fixed_point_db(channel.adjustment).to_be_bytes());
+            match channel.peak {
+               // A 16 bit peak, where 1.0 is 2^15
+               Some(peak) => {
+                  body.push(16);
+                  let peak = (f64::from(peak) * 32768.0).round().max(0.0).min(65535.0) as u16;
+                  body.extend_from_slice(&peak.to_be_bytes());
+               }
+               None => body.push(0),
+            }
+         }
+         body
+      }
+      FrameData::RVRB(x) => x.to_bytes().to_vec(),
+      FrameData::TBPM(x) | FrameData::TDLY(x) | FrameData::TLEN(x) => display_frame(x),
+      FrameData::TCMP(x) => text_frame(&[if *x { "1" } else { "0" }]),
+      FrameData::TCOP(x) | FrameData::TPRO(x) => display_frame(x),
+      FrameData::TDEN(x) | FrameData::TDOR(x) | FrameData::TDRC(x) | FrameData::TDRL(x) | FrameData::TDTG(x) => {
+         display_frame(x)
+      }
+      FrameData::TIPL(x) | FrameData::TMCL(x) => {
+         let mut pairs: Vec<(&str, &str)> = x.iter().map(|(k, v)| (k.as_ref(), v.as_ref())).collect();
+         // The map has no order, but the output should be the same every time
+         pairs.sort();
+         let texts: Vec<&str> = pairs.iter().flat_map(|(k, v)| vec![*k, *v]).collect();
+         text_frame(&texts)
+      }
+      FrameData::TXXX(x) => description_text_frame(&[], &x.description, &x.text),
+      FrameData::MVNM(x)
+      | FrameData::TALB(x)
+      | FrameData::TCOM(x)
+      | FrameData::TCON(x)
+      | FrameData::TDES(x)
+      | FrameData::TENC(x)
+      | FrameData::TEXT(x)
+      | FrameData::TGID(x)
+      | FrameData::TIT1(x)
+      | FrameData::TIT2(x)
+      | FrameData::TIT3(x)
+      | FrameData::TKEY(x)
+      | FrameData::TKWD(x)
+      | FrameData::TMOO(x)
+      | FrameData::TOAL(x)
+      | FrameData::TOFN(x)
+      | FrameData::TOLY(x)
+      | FrameData::TOPE(x)
+      | FrameData::TOWN(x)
+      | FrameData::TPE1(x)
+      | FrameData::TPE2(x)
+      | FrameData::TPE3(x)
+      | FrameData::TPE4(x)
+      | FrameData::TPUB(x)
+      | FrameData::TRSN(x)
+      | FrameData::TRSO(x)
+      | FrameData::TSO2(x)
+      | FrameData::TSOA(x)
+      | FrameData::TSOC(x)
+      | FrameData::TSOP(x)
+      | FrameData::TSOT(x)
+      | FrameData::TSRC(x)
+      | FrameData::TSSE(x)
+      | FrameData::TSST(x)
+      | FrameData::WFED(x) => text_frame(x),
+      // URLs are always ISO-8859-1
+      FrameData::WCOM(x)
+      | FrameData::WCOP(x)
+      | FrameData::WOAF(x)
+      | FrameData::WOAR(x)
+      | FrameData::WOAS(x)
+      | FrameData::WORS(x)
+      | FrameData::WPAY(x)
+      | FrameData::WPUB(x) => latin1_lossy(x).collect(),
+      FrameData::Unknown(x) => x.data.to_vec(),
+   }
+}
+
+// Readers commonly decode ISO-8859-1 as Windows-1252, which would turn the C1 control characters into
+// punctuation, so text containing them is written as UTF-8 too
+fn is_latin1(c: char) -> bool {
+   match c {
+      '\u{0}'..='\u{7f}' | '\u{a0}'..='\u{ff}' => true,
+      _ => false,
+   }
+}
+
+fn choose_encoding<S: AsRef<str>>(texts: &[S]) -> TextEncoding {
+   if texts.iter().all(|x| x.as_ref().chars().all(is_latin1)) {
+      TextEncoding::ISO8859
+   } else {
+      TextEncoding::UTF8
+   }
+}
+
+fn push_text(out: &mut Vec<u8>, encoding: TextEncoding, text: &str) {
+   match encoding {
+      TextEncoding::ISO8859 => out.extend(text.chars().map(|c| c as u8)),
+      _ => out.extend_from_slice(text.as_bytes()),
+   }
+}
+
+// Both encodings we write terminate strings with a single null
+fn push_texts<S: AsRef<str>>(out: &mut Vec<u8>, encoding: TextEncoding, texts: &[S]) {
+   for (i, text) in texts.iter().enumerate() {
+      if i > 0 {
+         out.push(0);
+      }
+      push_text(out, encoding, text.as_ref());
+   }
+}
+
+fn text_frame<S: AsRef<str>>(texts: &[S]) -> Vec<u8> {
+   let encoding = choose_encoding(texts);
+   let mut body = vec![encoding as u8];
+   push_texts(&mut body, encoding, texts);
+   body
+}
+
+fn display_frame<T: fmt::Display>(values: &[T]) -> Vec<u8> {
+   let texts: Vec<String> = values.iter().map(|x| x.to_string()).collect();
+   text_frame(&texts)
+}
+
+// The encoding, then anything that comes before the description (COMM and USLT have a language), then the
+// null terminated description, and finally the text
+fn description_text_frame(prefix: &[u8], description: &str, texts: &[Cow<str>]) -> Vec<u8> {
+   let encoding = if choose_encoding(&[description]) == TextEncoding::ISO8859 {
+      choose_encoding(texts)
+   } else {
+      TextEncoding::UTF8
+   };
+   let mut body = vec![encoding as u8];
+   body.extend_from_slice(prefix);
+   push_text(&mut body, encoding, description);
+   body.push(0);
+   push_texts(&mut body, encoding, texts);
+   body
+}
+
+// For the fields that the spec requires to be ISO-8859-1, like URLs
+pub(super) fn latin1_lossy<'a>(text: &'a str) -> impl Iterator<Item = u8> + 'a {
+   text.chars().map(|c| if (c as u32) < 0x100 { c as u8 } else { b'?' })
+}
+
+// Fixed point, in 1/512 dB
+fn fixed_point_db(db: f32) -> i16 {
+   (f64::from(db) * 512.0).round().max(-32768.0).min(32767.0) as i16
+}
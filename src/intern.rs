@@ -0,0 +1,156 @@
+use crate::tag::Tag;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// Hands out one shared copy of each distinct string. In a library, the same artists, albums, and genres come up
+/// over and over, so keeping the tags of every file is much cheaper when their text is shared.
+pub trait Interner {
+   fn intern(&mut self, text: &str) -> Arc<str>;
+}
+
+/// The built-in interner. Strings are kept until the table is dropped or cleared, even once nothing else holds them.
+#[derive(Clone, Debug, Default)]
+pub struct StringTable {
+   strings: HashSet<Arc<str>>,
+}
+
+impl StringTable {
+   pub fn new() -> StringTable {
+      StringTable::default()
+   }
+
+   /// How many distinct strings have been interned
+   pub fn len(&self) -> usize {
+      self.strings.len()
+   }
+
+   pub fn is_empty(&self) -> bool {
+      self.strings.is_empty()
+   }
+
+   /// Forgets every string. Strings that were handed out stay valid, but are no longer shared with new ones.
+   pub fn clear(&mut self) {
+      self.strings.clear();
+   }
+}
+
+impl Interner for StringTable {
+   fn intern(&mut self, text: &str) -> Arc<str> {
+      if let Some(x) = self.strings.get(text) {
+         return x.clone();
+      }
+      let x: Arc<str> = Arc::from(text);
+      self.strings.insert(x.clone());
+      x
+   }
+}
+
+/// The common fields of a tag (see `Tag`), with the text interned. Made to be kept around, e.g. for every file of a
+/// library, long after the file and its tag are gone.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InternedTag {
+   pub title: Option<Arc<str>>,
+   pub artist: Option<Arc<str>>,
+   pub album: Option<Arc<str>>,
+   pub album_artist: Option<Arc<str>>,
+   pub genre: Option<Arc<str>>,
+   pub year: Option<u16>,
+   pub track_number: Option<u32>,
+   pub track_total: Option<u32>,
+   pub disc_number: Option<u32>,
+   pub disc_total: Option<u32>,
+   pub comment: Option<Arc<str>>,
+}
+
+impl InternedTag {
+   pub fn read(tag: &dyn Tag, interner: &mut dyn Interner) -> InternedTag {
+      let mut intern = |x: Option<&str>| x.map(|x| interner.intern(x));
+      InternedTag {
+         title: intern(tag.title()),
+         artist: intern(tag.artist()),
+         album: intern(tag.album()),
+         album_artist: intern(tag.album_artist()),
+         genre: intern(tag.genre()),
+         year: tag.year(),
+         track_number: tag.track_number(),
+         track_total: tag.track_total(),
+         disc_number: tag.disc_number(),
+         disc_total: tag.disc_total(),
+         comment: intern(tag.comment()),
+      }
+   }
+}
+
+impl Tag for InternedTag {
+   fn title(&self) -> Option<&str> {
+      self.title.as_ref().map(|x| &**x)
+   }
+
+   fn artist(&self) -> Option<&str> {
+      self.artist.as_ref().map(|x| &**x)
+   }
+
+   fn album(&self) -> Option<&str> {
+      self.album.as_ref().map(|x| &**x)
+   }
+
+   fn album_artist(&self) -> Option<&str> {
+      self.album_artist.as_ref().map(|x| &**x)
+   }
+
+   fn genre(&self) -> Option<&str> {
+      self.genre.as_ref().map(|x| &**x)
+   }
+
+   fn year(&self) -> Option<u16> {
+      self.year
+   }
+
+   fn track_number(&self) -> Option<u32> {
+      self.track_number
+   }
+
+   fn track_total(&self) -> Option<u32> {
+      self.track_total
+   }
+
+   fn disc_number(&self) -> Option<u32> {
+      self.disc_number
+   }
+
+   fn disc_total(&self) -> Option<u32> {
+      self.disc_total
+   }
+
+   fn comment(&self) -> Option<&str> {
+      self.comment.as_ref().map(|x| &**x)
+   }
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+
+   #[test]
+   fn shared_text() {
+      use crate::id3::v24::{Frame, FrameData};
+      use crate::id3::Id3Tag;
+
+      let track = |title: &str| Id3Tag {
+         frames: vec![
+            Frame::new(FrameData::title(title.to_owned())),
+            Frame::new(FrameData::artist("Boards of Canada")),
+            Frame::new(FrameData::album("Geogaddi")),
+         ],
+      };
+      let mut table = StringTable::new();
+      let first = InternedTag::read(&track("Ready Lets Go"), &mut table);
+      let second = InternedTag::read(&track("Music Is Math"), &mut table);
+      assert_eq!(second.title(), Some("Music Is Math"));
+      assert!(Arc::ptr_eq(
+         first.artist.as_ref().unwrap(),
+         second.artist.as_ref().unwrap()
+      ));
+      assert_eq!(table.len(), 4);
+   }
+}
@@ -0,0 +1,289 @@
+use crate::id3::v24::{Frame, FrameData};
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Letter {
+   A,
+   B,
+   C,
+   D,
+   E,
+   F,
+   G,
+}
+
+impl Letter {
+   // Semitones above C
+   fn pitch_class(self) -> u8 {
+      match self {
+         Letter::C => 0,
+         Letter::D => 2,
+         Letter::E => 4,
+         Letter::F => 5,
+         Letter::G => 7,
+         Letter::A => 9,
+         Letter::B => 11,
+      }
+   }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Accidental {
+   Natural,
+   Sharp,
+   Flat,
+}
+
+/// The musical key of a track, as a TKEY frame holds it. Keys are compared as written, so C♯ minor and D♭ minor
+/// differ; compare their `camelot` positions to treat them as the same.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Key {
+   Tonal {
+      tonic: Letter,
+      accidental: Accidental,
+      minor: bool,
+   },
+   /// The track doesn't stay in one key, written "o"
+   OffKey,
+}
+
+// The keys at each number of the Camelot wheel, spelled the way the wheel spells them
+const CAMELOT_MAJOR: [(Letter, Accidental); 12] = [
+   (Letter::B, Accidental::Natural),
+   (Letter::F, Accidental::Sharp),
+   (Letter::D, Accidental::Flat),
+   (Letter::A, Accidental::Flat),
+   (Letter::E, Accidental::Flat),
+   (Letter::B, Accidental::Flat),
+   (Letter::F, Accidental::Natural),
+   (Letter::C, Accidental::Natural),
+   (Letter::G, Accidental::Natural),
+   (Letter::D, Accidental::Natural),
+   (Letter::A, Accidental::Natural),
+   (Letter::E, Accidental::Natural),
+];
+const CAMELOT_MINOR: [(Letter, Accidental); 12] = [
+   (Letter::A, Accidental::Flat),
+   (Letter::E, Accidental::Flat),
+   (Letter::B, Accidental::Flat),
+   (Letter::F, Accidental::Natural),
+   (Letter::C, Accidental::Natural),
+   (Letter::G, Accidental::Natural),
+   (Letter::D, Accidental::Natural),
+   (Letter::A, Accidental::Natural),
+   (Letter::E, Accidental::Natural),
+   (Letter::B, Accidental::Natural),
+   (Letter::F, Accidental::Sharp),
+   (Letter::D, Accidental::Flat),
+];
+
+impl Key {
+   /// Where the key is on the Camelot wheel. Enharmonic keys, such as F♯ and G♭ major, have the same position. An
+   /// off-key track has none.
+   pub fn camelot(self) -> Option<Camelot> {
+      let (tonic, accidental, minor) = match self {
+         Key::Tonal {
+            tonic,
+            accidental,
+            minor,
+         } => (tonic, accidental, minor),
+         Key::OffKey => return None,
+      };
+      let pitch_class = match accidental {
+         Accidental::Natural => tonic.pitch_class(),
+         Accidental::Sharp => (tonic.pitch_class() + 1) % 12,
+         Accidental::Flat => (tonic.pitch_class() + 11) % 12,
+      };
+      // A minor key shares its number with the major key three semitones up, and each step around the wheel is a
+      // fifth (7 semitones), with C major at 8
+      let major = if minor { (pitch_class + 3) % 12 } else { pitch_class };
+      Some(Camelot {
+         number: (major * 7 + 7) % 12 + 1,
+         minor,
+      })
+   }
+}
+
+impl From<Camelot> for Key {
+   fn from(camelot: Camelot) -> Key {
+      let keys = if camelot.minor { &CAMELOT_MINOR } else { &CAMELOT_MAJOR };
+      let (tonic, accidental) = keys[usize::from((camelot.number + 11) % 12)];
+      Key::Tonal {
+         tonic,
+         accidental,
+         minor: camelot.minor,
+      }
+   }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseKeyError(pub String);
+
+impl fmt::Display for ParseKeyError {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      write!(f, "invalid key {:?}", self.0)
+   }
+}
+
+impl Error for ParseKeyError {}
+
+impl FromStr for Key {
+   type Err = ParseKeyError;
+
+   /// Parses a key the way TKEY writes it: a letter from A to G, then "#" or "b" for sharp or flat, then "m" for
+   /// minor, e.g. "C#m". "o" is off-key. "♯" and "♭" are read as well, as are "min", "minor", "maj", and "major".
+   fn from_str(s: &str) -> Result<Key, ParseKeyError> {
+      let error = || ParseKeyError(s.to_owned());
+      let text = s.trim();
+      if text == "o" {
+         return Ok(Key::OffKey);
+      }
+      let mut chars = text.chars();
+      let tonic = match chars.next().map(|x| x.to_ascii_uppercase()) {
+         Some('A') => Letter::A,
+         Some('B') => Letter::B,
+         Some('C') => Letter::C,
+         Some('D') => Letter::D,
+         Some('E') => Letter::E,
+         Some('F') => Letter::F,
+         Some('G') => Letter::G,
+         _ => return Err(error()),
+      };
+      let accidental = match chars.clone().next() {
+         Some('#') | Some('♯') => Accidental::Sharp,
+         Some('b') | Some('♭') => Accidental::Flat,
+         _ => Accidental::Natural,
+      };
+      if accidental != Accidental::Natural {
+         chars.next();
+      }
+      let minor = match chars.as_str().trim_start() {
+         "" | "maj" | "major" => false,
+         "m" | "min" | "minor" => true,
+         _ => return Err(error()),
+      };
+      Ok(Key::Tonal {
+         tonic,
+         accidental,
+         minor,
+      })
+   }
+}
+
+// As TKEY has it, e.g. "C#m"
+impl fmt::Display for Key {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      match self {
+         Key::Tonal {
+            tonic,
+            accidental,
+            minor,
+         } => {
+            write!(f, "{:?}", tonic)?;
+            match accidental {
+               Accidental::Natural => (),
+               Accidental::Sharp => write!(f, "#")?,
+               Accidental::Flat => write!(f, "b")?,
+            }
+            if *minor {
+               write!(f, "m")?;
+            }
+            Ok(())
+         }
+         Key::OffKey => write!(f, "o"),
+      }
+   }
+}
+
+/// A position on the Camelot wheel, as DJ software shows keys: the keys that mix well with one are next to it or at
+/// the same number, e.g. "8A" (A minor) with "7A", "9A", and "8B" (C major)
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Camelot {
+   /// From 1 to 12
+   pub number: u8,
+   /// "A" on the wheel, where major keys are "B"
+   pub minor: bool,
+}
+
+impl FromStr for Camelot {
+   type Err = ParseKeyError;
+
+   fn from_str(s: &str) -> Result<Camelot, ParseKeyError> {
+      let error = || ParseKeyError(s.to_owned());
+      let text = s.trim();
+      let minor = match text.chars().last().map(|x| x.to_ascii_uppercase()) {
+         Some('A') => true,
+         Some('B') => false,
+         _ => return Err(error()),
+      };
+      let number: u8 = text[..text.len() - 1].parse().map_err(|_| error())?;
+      if number < 1 || number > 12 {
+         return Err(error());
+      }
+      Ok(Camelot { number, minor })
+   }
+}
+
+impl fmt::Display for Camelot {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      write!(f, "{}{}", self.number, if self.minor { 'A' } else { 'B' })
+   }
+}
+
+/// The key in the TKEY frame, if there is one that parses. Some DJ software writes Camelot notation there instead,
+/// which is read too.
+pub fn key(frames: &[Frame]) -> Option<Key> {
+   let text = frames.iter().find_map(|frame| match &frame.data {
+      FrameData::TKEY(x) => x.first(),
+      _ => None,
+   })?;
+   text
+      .parse()
+      .ok()
+      .or_else(|| text.parse::<Camelot>().ok().map(Key::from))
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+
+   #[test]
+   fn keys() {
+      let c_sharp_minor: Key = "C#m".parse().unwrap();
+      assert_eq!(
+         c_sharp_minor,
+         Key::Tonal {
+            tonic: Letter::C,
+            accidental: Accidental::Sharp,
+            minor: true,
+         }
+      );
+      assert_eq!(c_sharp_minor.to_string(), "C#m");
+      assert_eq!("D♭ minor".parse::<Key>().unwrap().camelot(), c_sharp_minor.camelot());
+      assert_eq!("o".parse(), Ok(Key::OffKey));
+      assert!("H".parse::<Key>().is_err());
+      assert!("Cmaj7".parse::<Key>().is_err());
+
+      let camelot = |x: &str| x.parse::<Key>().unwrap().camelot().unwrap().to_string();
+      assert_eq!(camelot("C"), "8B");
+      assert_eq!(camelot("Am"), "8A");
+      assert_eq!(camelot("B"), "1B");
+      assert_eq!(camelot("G#m"), "1A");
+      assert_eq!(camelot("Bbm"), "3A");
+      assert_eq!(Key::OffKey.camelot(), None);
+      for number in 1..=12 {
+         for minor in [false, true].iter() {
+            let position = Camelot { number, minor: *minor };
+            assert_eq!(Key::from(position).camelot(), Some(position));
+         }
+      }
+
+      let tag = crate::id3::TagBuilder::new()
+         .frame(FrameData::from_text(*b"TKEY", "12A").unwrap())
+         .build()
+         .unwrap();
+      assert_eq!(key(&tag.frames).map(|x| x.to_string()), Some("Dbm".to_owned()));
+   }
+}
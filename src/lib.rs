@@ -0,0 +1,47 @@
+#![feature(try_blocks, try_from)]
+
+pub mod acoustid;
+pub mod adts;
+pub mod aiff;
+pub mod ape;
+pub mod art;
+pub mod asf;
+pub mod cache;
+pub mod check;
+pub mod copy;
+pub mod cue;
+pub mod diff;
+pub mod dsd;
+pub mod dupes;
+pub mod edits;
+pub mod fix;
+pub mod flac;
+pub mod gapless;
+pub mod genre;
+pub mod glob;
+pub mod hash;
+pub mod id3;
+pub mod intern;
+pub mod key;
+pub mod mp4;
+pub mod mpeg;
+pub mod mpegts;
+pub mod musicbrainz;
+pub mod ogg;
+pub mod organize;
+pub mod playlist;
+pub mod probe;
+pub mod query;
+pub mod remote;
+pub mod rename;
+pub mod replaygain;
+pub mod report;
+pub mod riff;
+pub mod scan;
+pub mod sort;
+pub mod strip;
+pub mod tag;
+pub mod validate;
+pub mod vorbis;
+
+pub use crate::probe::{probe, probe_path};
@@ -1,22 +1,61 @@
-#![feature(try_blocks, try_from)]
-
-mod id3;
-
 use log::{info, warn};
-use std::fs::File;
-use std::time::Instant;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsString;
+use std::fmt::Display;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 use walkdir::WalkDir;
+#[cfg(feature = "acoustid")]
+use walnut::acoustid;
+#[cfg(feature = "musicbrainz-lookup")]
+use walnut::fix;
+use walnut::tag::Tag;
+use walnut::{
+   art, cache, check, copy, cue, diff, dupes, edits, glob, hash, id3, mpeg, organize, playlist, probe, query, rename,
+   report, scan, sort, strip, tag, validate,
+};
 
 fn main() {
    pretty_env_logger::init();
 
-   // If a command line arg is given, parse and print that file only
-   for arg in std::env::args_os().skip(1) {
-      let mut f = File::open(arg).unwrap();
-      print_file(&mut f);
+   let mut args: Vec<OsString> = std::env::args_os().skip(1).collect();
+   let filter = match take_path_filter(&mut args) {
+      Ok(v) => v,
+      Err(e) => return eprintln!("{}", e),
+   };
+   match args.first().and_then(|x| x.to_str()) {
+      Some("read") => return read_files(&args[1..], &filter),
+      Some("write") => return write_files(&args[1..]),
+      Some("dump") => return dump_files(&args[1..]),
+      Some("strip") => return strip_files(&args[1..]),
+      Some("hash") => return hash_files(&args[1..]),
+      Some("dupes") => return find_dupes(&args[1..], &filter),
+      Some("compilations") => return find_compilations(&args[1..], &filter),
+      Some("albums") => return list_albums(&args[1..], &filter),
+      Some("rename") => return rename_files(&args[1..], &filter),
+      Some("organize") => return organize_files(&args[1..], &filter),
+      Some("art") => return art(&args[1..], &filter),
+      Some("apply") => return apply_edits(&args[1..]),
+      Some("copy") => return copy_tags(&args[1..]),
+      Some("diff") => return diff_tags(&args[1..]),
+      Some("check") => return check_files(&args[1..], &filter),
+      Some("validate") => return validate_files(&args[1..], &filter),
+      Some("verify") => return verify_files(&args[1..], &filter),
+      Some("report") => return report_files(&args[1..], &filter),
+      Some("query") => return query_library(&args[1..], &filter),
+      Some("playlist") => return write_playlist(&args[1..], &filter),
+      Some("cue") => return list_cue_tracks(&args[1..]),
+      Some("identify") => return identify_files(&args[1..], &filter),
+      Some("fix") => return fix_files(&args[1..], &filter),
+      _ => (),
    }
-   if std::env::args_os().len() > 1 {
-      return;
+
+   // If command line args are given, read those files only
+   if !args.is_empty() {
+      return read_files(&args, &filter);
    }
 
    // If no command line args given, parse and print every file in the music directory
@@ -32,21 +71,31 @@ fn main() {
             None
          }
       })
-      .filter(|v| v.file_type().is_file() && v.file_name().to_string_lossy().split('.').last() == Some("mp3"))
+      .filter(|v| v.file_type().is_file() && probe::guess_format(v.path()) == Some(probe::Format::Mpeg))
       .collect();
 
    let start = Instant::now();
    let mut ok_counter: u64 = 0;
    let mut ignored_counter: u64 = 0;
+   let mut library = report::Report::default();
    for entry in mp3_files.into_iter() {
       println!("{}", entry.path().display());
 
-      let mut f = File::open(entry.path()).unwrap();
+      let mut f = match File::open(entry.path()) {
+         Ok(f) => f,
+         Err(e) => {
+            warn!("Failed to open file: {}", e);
+            ignored_counter += 1;
+            continue;
+         }
+      };
       if print_file(&mut f) {
          ok_counter += 1;
       } else {
          ignored_counter += 1;
       }
+      // Failures were already reported by print_file
+      let _ = library.add(&mut f);
    }
 
    let elapsed = start.elapsed();
@@ -57,75 +106,1816 @@ fn main() {
       elapsed.as_millis() as f64 / ok_counter as f64
    );
    info!("Failed to parse {} mp3 files", ignored_counter);
+   print_report(&library);
+}
+
+// walnut read [--fields <field,...>] [--template <template>] <paths>
+// Prints a line per file: the fields separated by tabs (path, artist, album, and title by default), or the template
+// filled in. Fields are those of `query::Field`. Files of any format are read as ID3 tags (see `copy::to_id3`).
+fn read_files(args: &[OsString], filter: &glob::PathFilter) {
+   let mut fields = None;
+   let mut template = None;
+   let mut paths = Vec::new();
+   let mut args = args.iter();
+   while let Some(arg) = args.next() {
+      match arg.to_str() {
+         Some("--fields") => {
+            let parsed: Option<Result<Vec<query::Field>, _>> = args
+               .next()
+               .and_then(|x| x.to_str())
+               .map(|x| x.split(',').map(|x| x.trim().parse()).collect());
+            match parsed {
+               Some(Ok(v)) => fields = Some(v),
+               Some(Err(e)) => return eprintln!("Invalid field: {}", e),
+               None => return eprintln!("--fields needs a list of fields, e.g. artist,title,album"),
+            }
+         }
+         Some("--template") => match args.next().map(|x| x.to_string_lossy().parse::<query::Template>()) {
+            Some(Ok(v)) => template = Some(v),
+            Some(Err(e)) => return eprintln!("Invalid template: {}", e),
+            None => return eprintln!("--template needs a template, e.g. \"{{artist}} - {{title}}\""),
+         },
+         _ => paths.push(arg),
+      }
+   }
+   if paths.is_empty() {
+      return eprintln!("Usage: walnut read [--fields <field,...>] [--template <template>] <paths>");
+   }
+   let fields = fields.unwrap_or_else(|| {
+      vec![
+         query::Field::Path,
+         query::Field::Artist,
+         query::Field::Album,
+         query::Field::Title,
+      ]
+   });
+
+   for path in files_under(&paths, filter) {
+      let tag = match probe::probe_path(&path) {
+         Ok(metadata) => copy::to_id3(&metadata),
+         Err(e) => {
+            warn!("Failed to read {}: {}", path.display(), e);
+            continue;
+         }
+      };
+      match &template {
+         Some(template) => println!("{}", template.render(&path, &tag)),
+         None => println!(
+            "{}",
+            fields
+               .iter()
+               .map(|x| x.values(&path, &tag).join("; "))
+               .collect::<Vec<_>>()
+               .join("\t")
+         ),
+      }
+   }
+}
+
+// walnut dump [--raw] <files>
+// Prints every frame and the audio properties. With --raw, prints how the tag is laid out instead (see
+// `id3::dump::RawTag`), with the start of each payload in hex.
+fn dump_files(args: &[OsString]) {
+   let raw = args.iter().any(|x| x.to_str() == Some("--raw"));
+   let paths: Vec<&OsString> = args.iter().filter(|x| x.to_str() != Some("--raw")).collect();
+   if paths.is_empty() {
+      return eprintln!("Usage: walnut dump [--raw] <files>");
+   }
+
+   for path in paths {
+      println!("{}", path.to_string_lossy());
+      let mut f = match File::open(path) {
+         Ok(v) => v,
+         Err(e) => {
+            warn!("Failed to open {}: {}", path.to_string_lossy(), e);
+            continue;
+         }
+      };
+      if !raw {
+         print_file(&mut f);
+         continue;
+      }
+      match id3::dump::RawTag::read(&mut f, &id3::ParseOptions::default()) {
+         Ok(tag) => print_raw_tag(&tag),
+         Err(e) => println!("{}", e),
+      }
+   }
+}
+
+fn print_raw_tag(tag: &id3::dump::RawTag) {
+   let flags = |value: u16, names: Vec<&str>| format!("{:#06x} [{}]", value, names.join(", "));
+   println!(
+      "ID3v2.{}.{} at {}, flags {}, size {}{} ({} present)",
+      tag.version,
+      tag.revision,
+      tag.tag_start,
+      format!("{:#04x} [{}]", tag.flags, tag.flag_names().join(", ")),
+      tag.size,
+      if tag.size_is_synchsafe { "" } else { " (not synchsafe)" },
+      tag.available
+   );
+   if let Some(extended_header) = &tag.extended_header {
+      println!(
+         "{:>8}  extended header, size {}: {}",
+         extended_header.offset,
+         extended_header.size,
+         hex_preview(&extended_header.bytes)
+      );
+   }
+   for frame in tag.frames.iter() {
+      println!(
+         "{:>8}  {:<4}  flags {}, size {}{}, {} present: {}",
+         frame.offset,
+         frame.name,
+         flags(frame.flags, tag.frame_flag_names(frame.flags)),
+         frame.size,
+         if frame.size_is_synchsafe {
+            ""
+         } else {
+            " (not synchsafe)"
+         },
+         frame.payload.len(),
+         hex_preview(&frame.payload)
+      );
+   }
+   if !tag.rest.is_empty() {
+      println!(
+         "{:>8}  {} {} bytes: {}",
+         tag.rest_offset,
+         if tag.is_padding() { "padding," } else { "unreadable," },
+         tag.rest.len(),
+         hex_preview(&tag.rest)
+      );
+   }
+}
+
+// The first 16 bytes in hex, then as text with anything unprintable as a dot
+fn hex_preview(bytes: &[u8]) -> String {
+   let preview = &bytes[..bytes.len().min(16)];
+   let hex: Vec<_> = preview.iter().map(|x| format!("{:02x}", x)).collect();
+   let text: String = preview
+      .iter()
+      .map(|x| {
+         if x.is_ascii_graphic() || *x == b' ' {
+            char::from(*x)
+         } else {
+            '.'
+         }
+      })
+      .collect();
+   let more = if bytes.len() > preview.len() { " ..." } else { "" };
+   format!("{}{} |{}|", hex.join(" "), more, text)
+}
+
+// walnut strip [--ape] <files>
+fn strip_files(args: &[OsString]) {
+   let is_ape_flag = |x: &OsString| x.to_str() == Some("--ape");
+   let options = strip::StripOptions {
+      ape: args.iter().any(is_ape_flag),
+   };
+   for arg in args.iter().filter(|x| !is_ape_flag(x)) {
+      match strip::remove_tags(arg, &options) {
+         Ok(layout) => {
+            let mut removed = Vec::new();
+            if layout.id3v2_tags > 0 {
+               removed.push(format!("{} ID3v2", layout.id3v2_tags));
+            }
+            if layout.ape_tag {
+               removed.push("APE".to_owned());
+            }
+            if layout.id3v1_tag {
+               removed.push("ID3v1".to_owned());
+            }
+            if removed.is_empty() {
+               println!("{}: no tags", arg.to_string_lossy());
+            } else {
+               println!("{}: removed {}", arg.to_string_lossy(), removed.join(", "));
+            }
+         }
+         Err(e) => warn!("Failed to strip {}: {}", arg.to_string_lossy(), e),
+      }
+   }
+}
+
+// walnut hash <files>, in the style of sha256sum
+fn hash_files(args: &[OsString]) {
+   for arg in args.iter() {
+      match hash::audio_hash(arg) {
+         Ok(hash) => println!("{:016x}  {}", hash, arg.to_string_lossy()),
+         Err(e) => warn!("Failed to hash {}: {}", arg.to_string_lossy(), e),
+      }
+   }
+}
+
+// walnut dupes [--audio] [--tags] <paths>
+// Without either flag, files are compared both ways
+fn find_dupes(args: &[OsString], filter: &glob::PathFilter) {
+   let has_flag = |flag: &str| args.iter().any(|x| x.to_str() == Some(flag));
+   let options = match (has_flag("--audio"), has_flag("--tags")) {
+      (false, false) => dupes::DupeOptions::default(),
+      (audio, tags) => dupes::DupeOptions { audio, tags },
+   };
+   let paths: Vec<&OsString> = args.iter().filter(|x| !x.to_string_lossy().starts_with("--")).collect();
+
+   let tracks: Vec<dupes::TrackInfo> = files_under(&paths, filter)
+      .into_iter()
+      .filter_map(|path| match dupes::TrackInfo::read(&path) {
+         Ok(v) => Some(v),
+         Err(e) => {
+            warn!("Failed to read {}: {}", path.display(), e);
+            None
+         }
+      })
+      .collect();
+
+   let groups = dupes::find_duplicates(&tracks, &options);
+   for group in groups.iter() {
+      println!(
+         "{} copies, {:.1} MB reclaimable",
+         group.len(),
+         dupes::reclaimable(group) as f64 / 1_000_000.0
+      );
+      for track in group.iter() {
+         let bitrate = track
+            .bitrate
+            .map(|x| format!("{}kbps", x))
+            .unwrap_or_else(|| "?".to_owned());
+         println!(
+            "  {:>8.1} MB  {:>8}  {}",
+            track.size as f64 / 1_000_000.0,
+            bitrate,
+            track.path.display()
+         );
+      }
+   }
+   let total: u64 = groups.iter().map(|x| dupes::reclaimable(x)).sum();
+   println!(
+      "{} duplicate groups, {:.1} MB reclaimable",
+      groups.len(),
+      total as f64 / 1_000_000.0
+   );
+}
+
+// walnut albums <paths>
+// Lists the albums the files make up (see `scan::Library`), a disc per line, with the tracks missing from each
+fn list_albums(args: &[OsString], filter: &glob::PathFilter) {
+   if args.is_empty() {
+      return eprintln!("Usage: walnut albums <paths>");
+   }
+   let (library, failed) = scan::Library::scan(audio_files_under(args, filter), &scan::ScanOptions::default());
+   for (path, e) in failed.iter() {
+      warn!("Failed to read {}: {}", path.display(), e);
+   }
+
+   for album in library.albums.iter() {
+      let mut line = format!(
+         "{} - {}",
+         album.album_artist.as_ref().map_or("?", |x| x.as_str()),
+         album.album.as_ref().map_or("?", |x| x.as_str())
+      );
+      if let Some(disc) = album.disc {
+         line.push_str(&format!(" (disc {})", disc));
+      }
+      line.push_str(&format!(": {} tracks", album.tracks.len()));
+      if let Some(duration) = album.duration() {
+         line.push_str(&format!(", {}:{:02}", duration.as_secs() / 60, duration.as_secs() % 60));
+      }
+      let missing = album.missing_tracks();
+      if !missing.is_empty() {
+         let missing: Vec<String> = missing.iter().map(u32::to_string).collect();
+         line.push_str(&format!(", missing {}", missing.join(", ")));
+      }
+      if !album.has_art() {
+         line.push_str(", no art");
+      }
+      println!("{}", line);
+   }
+}
+
+// walnut compilations [--fix] <paths>
+// Lists the albums that look like compilations but aren't tagged as such (see `scan::find_compilations`). With
+// --fix, their tracks are tagged as compilations by "Various Artists", and what changed in each file is printed.
+fn find_compilations(args: &[OsString], filter: &glob::PathFilter) {
+   let fix = args.iter().any(|x| x.to_str() == Some("--fix"));
+   let paths: Vec<&OsString> = args.iter().filter(|x| !x.to_string_lossy().starts_with("--")).collect();
+   if paths.is_empty() {
+      return eprintln!("Usage: walnut compilations [--fix] <paths>");
+   }
+
+   let files = audio_files_under(&paths, filter);
+   let tags = scan::map(files.clone(), &scan::ScanOptions::default(), |path| {
+      probe::probe_path(path).map(|x| copy::to_id3(&x))
+   });
+   let mut tracks = Vec::new();
+   for (path, tag) in files.iter().zip(tags) {
+      match tag {
+         Ok(tag) => tracks.push((path.as_path(), tag)),
+         Err(e) => warn!("Failed to read {}: {}", path.display(), e),
+      }
+   }
+
+   let compilations = scan::find_compilations(tracks.iter().map(|x| (x.0, &x.1)), &tag::Separators::default());
+   for compilation in compilations.iter() {
+      println!(
+         "{} ({} tracks, {} artists: {})",
+         compilation.album,
+         compilation.paths.len(),
+         compilation.artists.len(),
+         compilation.artists.join(", ")
+      );
+      if !fix {
+         continue;
+      }
+      for path in compilation.paths.iter() {
+         let old = match id3::write::read_tag_for_update(path) {
+            Ok(v) => v,
+            Err(e) => {
+               warn!("Failed to read {}: {}", path.display(), e);
+               continue;
+            }
+         };
+         let mut new = old.clone();
+         compilation.update_tag(&mut new);
+         println!("{}", path.display());
+         print_diff(&old, &new);
+         if let Err(e) = id3::write::write_tag_to_file(path, &new, id3::write::WriteOptions::default()) {
+            warn!("Failed to write {}: {}", path.display(), e);
+         }
+      }
+   }
+}
+
+// walnut rename --pattern <pattern> [--to <dir>] [--dry-run] <paths>
+// Without --to, the pattern is relative to the directory each file is in
+fn rename_files(args: &[OsString], filter: &glob::PathFilter) {
+   let mut pattern = None;
+   let mut root = None;
+   let mut dry_run = false;
+   let mut paths = Vec::new();
+   let mut args = args.iter();
+   while let Some(arg) = args.next() {
+      match arg.to_str() {
+         Some("--pattern") => pattern = args.next().map(|x| x.to_string_lossy().parse::<rename::Pattern>()),
+         Some("--to") => root = args.next().map(PathBuf::from),
+         Some("--dry-run") => dry_run = true,
+         _ => paths.push(arg),
+      }
+   }
+   let pattern = match pattern {
+      Some(Ok(v)) => v,
+      Some(Err(e)) => return eprintln!("Invalid pattern: {}", e),
+      None => return eprintln!("Usage: walnut rename --pattern <pattern> [--to <dir>] [--dry-run] <paths>"),
+   };
+
+   let mut targets = Vec::new();
+   for path in files_under(&paths, filter) {
+      let metadata = match probe::probe_path(&path) {
+         Ok(v) => v,
+         Err(e) => {
+            warn!("Failed to read {}: {}", path.display(), e);
+            continue;
+         }
+      };
+      let extension = path
+         .extension()
+         .map(|x| x.to_string_lossy().to_lowercase())
+         .unwrap_or_default();
+      let relative = pattern.render(&metadata, &extension, rename::Sanitize::native());
+      let dir = root
+         .clone()
+         .or_else(|| path.parent().map(Path::to_owned))
+         .unwrap_or_default();
+      targets.push((path, dir.join(relative)));
+   }
+
+   for plan in rename::plan_renames(targets).iter() {
+      println!("{} -> {}", plan.from.display(), plan.to.display());
+      if !dry_run {
+         if let Err(e) = rename::rename_file(plan) {
+            warn!("Failed to rename {}: {}", plan.from.display(), e);
+         }
+      }
+   }
+}
+
+// walnut organize --to <library> [--pattern <pattern>] [--copy] [--dry-run] [--journal <file>] <paths>
+// walnut organize --undo <journal>
+fn organize_files(args: &[OsString], filter: &glob::PathFilter) {
+   let mut pattern = Ok(organize::DEFAULT_PATTERN.parse().unwrap());
+   let mut root = None;
+   let mut copy = false;
+   let mut dry_run = false;
+   let mut journal_path = None;
+   let mut undo_path = None;
+   let mut paths = Vec::new();
+   let mut args = args.iter();
+   while let Some(arg) = args.next() {
+      match arg.to_str() {
+         Some("--pattern") => {
+            pattern = args
+               .next()
+               .map(|x| x.to_string_lossy().parse::<rename::Pattern>())
+               .unwrap_or(pattern)
+         }
+         Some("--to") => root = args.next().map(PathBuf::from),
+         Some("--copy") => copy = true,
+         Some("--dry-run") => dry_run = true,
+         Some("--journal") => journal_path = args.next().map(PathBuf::from),
+         Some("--undo") => undo_path = args.next().map(PathBuf::from),
+         _ => paths.push(arg),
+      }
+   }
+
+   if let Some(undo_path) = undo_path {
+      let failures = File::open(&undo_path).and_then(|f| organize::undo(io::BufReader::new(f)));
+      match failures {
+         Ok(failures) => {
+            for (entry, e) in failures {
+               warn!("Failed to undo {:?}: {}", entry, e);
+            }
+         }
+         Err(e) => eprintln!("Failed to read journal {}: {}", undo_path.display(), e),
+      }
+      return;
+   }
+
+   let (pattern, root) = match (pattern, root) {
+      (Ok(pattern), Some(root)) => (pattern, root),
+      (Err(e), _) => return eprintln!("Invalid pattern: {}", e),
+      (_, None) => {
+         return eprintln!(
+         "Usage: walnut organize --to <library> [--pattern <pattern>] [--copy] [--dry-run] [--journal <file>] <paths>"
+      )
+      }
+   };
+
+   let mut targets = Vec::new();
+   for path in files_under(&paths, filter) {
+      match probe::probe_path(&path) {
+         Ok(metadata) => {
+            let extension = path
+               .extension()
+               .map(|x| x.to_string_lossy().to_lowercase())
+               .unwrap_or_default();
+            let relative = pattern.render(&metadata, &extension, rename::Sanitize::native());
+            targets.push((path, root.join(relative)));
+         }
+         Err(e) => warn!("Failed to read {}: {}", path.display(), e),
+      }
+   }
+   let plans = rename::plan_renames(targets);
+   if dry_run {
+      for plan in plans.iter() {
+         println!("{} -> {}", plan.from.display(), plan.to.display());
+      }
+      return;
+   }
+
+   // Named for the time, so that every run has its own journal
+   let journal_path = journal_path.unwrap_or_else(|| {
+      let now = SystemTime::now()
+         .duration_since(UNIX_EPOCH)
+         .map(|x| x.as_secs())
+         .unwrap_or(0);
+      root.join(format!(".walnut-organize-{}.journal", now))
+   });
+   let journal =
+      fs::create_dir_all(&root).and_then(|_| fs::OpenOptions::new().create(true).append(true).open(&journal_path));
+   let mut journal = match journal {
+      Ok(v) => v,
+      Err(e) => return eprintln!("Failed to create journal {}: {}", journal_path.display(), e),
+   };
+   for plan in plans.iter() {
+      println!("{} -> {}", plan.from.display(), plan.to.display());
+      if let Err(e) = organize::organize_file(plan, copy, &mut journal) {
+         warn!("Failed to organize {}: {}", plan.from.display(), e);
+      }
+   }
+   println!("Undo with: walnut organize --undo {}", journal_path.display());
+}
+
+fn art(args: &[OsString], filter: &glob::PathFilter) {
+   match args.first().and_then(|x| x.to_str()) {
+      Some("extract") => extract_art(&args[1..], filter),
+      Some("set") => set_art(&args[1..], filter),
+      Some("fetch") => fetch_art(&args[1..], filter),
+      _ => {
+         eprintln!("Usage: walnut art extract [--name <name>] [--type <picture type>] [--overwrite] <paths>");
+         eprintln!(
+            "       walnut art set --image <image> [--type <picture type>] [--max-size <pixels>] [--max-bytes <bytes>] <files>"
+         );
+         eprintln!(
+            "       walnut art fetch [--dry-run] [--size <250|500|1200|original>] [--max-size <pixels>] [--max-bytes <bytes>] <files>"
+         );
+      }
+   }
+}
+
+// walnut art set --image <image> [--type <picture type>] [--max-size <pixels>] [--max-bytes <bytes>] <files>
+fn set_art(args: &[OsString], filter: &glob::PathFilter) {
+   let mut image_path = None;
+   let mut picture_type = art::FRONT_COVER;
+   let mut limits = art::ArtLimits::default();
+   let mut paths = Vec::new();
+   let mut args = args.iter();
+   while let Some(arg) = args.next() {
+      let mut number = || args.next().and_then(|x| x.to_str()).and_then(|x| x.parse::<u64>().ok());
+      match arg.to_str() {
+         Some("--image") => image_path = args.next().map(PathBuf::from),
+         Some("--type") => match number() {
+            Some(v) if v <= u64::from(u8::max_value()) => picture_type = v as u32,
+            _ => return eprintln!("--type needs a picture type number, e.g. 3 for the front cover"),
+         },
+         Some("--max-size") => match number() {
+            Some(v) if v > 0 && v <= u64::from(u32::max_value()) => limits.max_dimension = Some(v as u32),
+            _ => return eprintln!("--max-size needs a size in pixels"),
+         },
+         Some("--max-bytes") => match number() {
+            Some(v) => limits.max_bytes = Some(v as usize),
+            _ => return eprintln!("--max-bytes needs a size in bytes"),
+         },
+         _ => paths.push(arg),
+      }
+   }
+
+   let image_path = match image_path {
+      Some(v) => v,
+      None => return eprintln!("Usage: walnut art set --image <image> <files>"),
+   };
+   let data = match fs::read(&image_path) {
+      Ok(v) => v,
+      Err(e) => return eprintln!("Failed to read {}: {}", image_path.display(), e),
+   };
+   let (data, format) = match fit_art(&data, &limits) {
+      Ok(v) => v,
+      Err(e) => return eprintln!("{}: {}", image_path.display(), e),
+   };
+   let picture = id3::write::Picture {
+      mime_type: format.mime_type().into(),
+      picture_type: picture_type as u8,
+      description: "".into(),
+      data,
+   };
+
+   for path in files_under(&paths, filter) {
+      let result = id3::write::read_tag_for_update(&path)
+         .map_err(Box::<dyn std::error::Error>::from)
+         .and_then(|mut tag| {
+            art::embed(&mut tag, &picture);
+            Ok(id3::write::write_tag_to_file(
+               &path,
+               &tag,
+               id3::write::WriteOptions::default(),
+            )?)
+         });
+      match result {
+         Ok(()) => println!(
+            "{}: set {} ({} bytes)",
+            path.display(),
+            format.extension(),
+            picture.data.len()
+         ),
+         Err(e) => warn!("Failed to set the picture of {}: {}", path.display(), e),
+      }
+   }
+}
+
+// walnut art fetch [--dry-run] [--size <250|500|1200|original>] [--max-size <pixels>] [--max-bytes <bytes>] <files>
+// Embeds front covers from the Cover Art Archive in MPEG files that have a MusicBrainz release ID but no pictures.
+// --dry-run lists what would be fetched without going online.
+fn fetch_art(args: &[OsString], filter: &glob::PathFilter) {
+   let mut dry_run = false;
+   let mut size = art::CoverSize::Large;
+   let mut limits = art::ArtLimits::default();
+   let mut paths = Vec::new();
+   let mut args = args.iter();
+   while let Some(arg) = args.next() {
+      let mut number = || args.next().and_then(|x| x.to_str()).and_then(|x| x.parse::<u64>().ok());
+      match arg.to_str() {
+         Some("--dry-run") => dry_run = true,
+         Some("--size") => match args.next().and_then(|x| x.to_str()) {
+            Some("250") => size = art::CoverSize::Small,
+            Some("500") => size = art::CoverSize::Large,
+            Some("1200") => size = art::CoverSize::ExtraLarge,
+            Some("original") => size = art::CoverSize::Original,
+            _ => return eprintln!("--size needs 250, 500, 1200, or original"),
+         },
+         Some("--max-size") => match number() {
+            Some(v) if v > 0 && v <= u64::from(u32::max_value()) => limits.max_dimension = Some(v as u32),
+            _ => return eprintln!("--max-size needs a size in pixels"),
+         },
+         Some("--max-bytes") => match number() {
+            Some(v) => limits.max_bytes = Some(v as usize),
+            _ => return eprintln!("--max-bytes needs a size in bytes"),
+         },
+         _ => paths.push(arg),
+      }
+   }
+   if paths.is_empty() {
+      return eprintln!(
+         "Usage: walnut art fetch [--dry-run] [--size <250|500|1200|original>] [--max-size <pixels>] [--max-bytes <bytes>] <files>"
+      );
+   }
+
+   // Albums share a cover, so each release is only downloaded once
+   let mut covers: HashMap<String, Option<(Vec<u8>, art::ImageFormat)>> = HashMap::new();
+   for path in files_under(&paths, filter) {
+      let release_id = match probe::probe_path(&path) {
+         Ok(metadata @ probe::Metadata::Mpeg(_)) => match art::release_without_art(&metadata) {
+            Some(v) => v.to_owned(),
+            None => continue,
+         },
+         Ok(_) => continue,
+         Err(e) => {
+            warn!("Failed to read {}: {}", path.display(), e);
+            continue;
+         }
+      };
+      if dry_run {
+         println!(
+            "{}: would fetch {}",
+            path.display(),
+            art::cover_art_url(&release_id, size)
+         );
+         continue;
+      }
+
+      if !covers.contains_key(&release_id) {
+         match download_cover(&release_id, size) {
+            Ok(v) => covers.insert(release_id.clone(), v),
+            Err(e) => {
+               warn!("Failed to fetch the cover of {}: {}", path.display(), e);
+               continue;
+            }
+         };
+      }
+      let (data, _) = match &covers[&release_id] {
+         Some(v) => v,
+         None => {
+            println!("{}: release {} has no front cover", path.display(), release_id);
+            continue;
+         }
+      };
+      let result = fit_art(data, &limits)
+         .map_err(Box::<dyn std::error::Error>::from)
+         .and_then(|(data, format)| {
+            let mut tag = id3::write::read_tag_for_update(&path)?;
+            art::embed(&mut tag, &id3::write::Picture::front_cover(format.mime_type(), data));
+            id3::write::write_tag_to_file(&path, &tag, id3::write::WriteOptions::default())?;
+            Ok(format)
+         });
+      match result {
+         Ok(format) => println!(
+            "{}: set {} from release {}",
+            path.display(),
+            format.extension(),
+            release_id
+         ),
+         Err(e) => warn!("Failed to set the cover of {}: {}", path.display(), e),
+      }
+   }
+}
+
+#[cfg(feature = "cover-art-archive")]
+fn download_cover(release_id: &str, size: art::CoverSize) -> Result<Option<(Vec<u8>, art::ImageFormat)>, String> {
+   // Originals can be huge, and anything this big is surely a mistake
+   const MAX_DOWNLOAD: usize = 32 * 1024 * 1024;
+   art::fetch_front_cover(release_id, size, Some(MAX_DOWNLOAD)).map_err(|e| e.to_string())
+}
+
+#[cfg(not(feature = "cover-art-archive"))]
+fn download_cover(_: &str, _: art::CoverSize) -> Result<Option<(Vec<u8>, art::ImageFormat)>, String> {
+   Err("fetching needs walnut to be built with the cover-art-archive feature".to_owned())
+}
+
+#[cfg(feature = "art-resize")]
+fn fit_art<'a>(data: &'a [u8], limits: &art::ArtLimits) -> Result<(Cow<'a, [u8]>, art::ImageFormat), String> {
+   art::fit(data, limits).map_err(|e| e.to_string())
+}
+
+#[cfg(not(feature = "art-resize"))]
+fn fit_art<'a>(data: &'a [u8], limits: &art::ArtLimits) -> Result<(Cow<'a, [u8]>, art::ImageFormat), String> {
+   if !limits.is_unlimited() {
+      return Err("resizing needs walnut to be built with the art-resize feature".to_owned());
+   }
+   match art::ImageFormat::sniff(data) {
+      Some(format) => Ok((Cow::Borrowed(data), format)),
+      None => Err("not a JPEG, PNG, GIF, BMP, or WebP image".to_owned()),
+   }
+}
+
+// walnut apply [--clear-empty] [--dry-run] <edits.csv or edits.tsv>
+// Paths in the edits file are relative to the directory it's in
+fn apply_edits(args: &[OsString]) {
+   let has_flag = |flag: &str| args.iter().any(|x| x.to_str() == Some(flag));
+   let edits_path = match args.iter().find(|x| !x.to_string_lossy().starts_with("--")) {
+      Some(v) => Path::new(v),
+      None => return eprintln!("Usage: walnut apply [--clear-empty] [--dry-run] <edits.csv or edits.tsv>"),
+   };
+   let is_tsv = edits_path
+      .extension()
+      .map(|x| x.eq_ignore_ascii_case("tsv") || x.eq_ignore_ascii_case("tab"))
+      .unwrap_or(false);
+   let options = edits::EditOptions {
+      delimiter: if is_tsv { b'\t' } else { b',' },
+      clear_empty: has_flag("--clear-empty"),
+   };
+   let rows = match File::open(edits_path)
+      .map_err(edits::EditsError::from)
+      .and_then(|f| edits::read_edits(io::BufReader::new(f), &options))
+   {
+      Ok(v) => v,
+      Err(e) => return eprintln!("{}: {}", edits_path.display(), e),
+   };
+
+   let base = edits_path.parent().unwrap_or_else(|| Path::new(""));
+   for row in rows {
+      let path = base.join(&row.path);
+      if has_flag("--dry-run") {
+         for edit in row.edits.iter() {
+            match &edit.frame {
+               Some(frame) => println!("{}: set {:?}", path.display(), frame),
+               None => println!("{}: remove {:?}", path.display(), edit.column),
+            }
+         }
+         continue;
+      }
+      let result = id3::write::read_tag_for_update(&path)
+         .map_err(Box::<dyn std::error::Error>::from)
+         .and_then(|mut tag| {
+            edits::apply(&mut tag, &row.edits);
+            Ok(id3::write::write_tag_to_file(
+               &path,
+               &tag,
+               id3::write::WriteOptions::default(),
+            )?)
+         });
+      match result {
+         Ok(()) => println!("{}: {} changes", path.display(), row.edits.len()),
+         Err(e) => warn!("Failed to edit {} (line {}): {}", path.display(), row.line, e),
+      }
+   }
+}
+
+// walnut write [--set <column>=<value>]... [--delete <column or frame ID>]... [--sort-keys <language>] [--dry-run]
+//    <files>
+// Columns are those of an edits file (see `edits::Column`), e.g. TIT2, TXXX:MOOD, or COMM:description. Deleting a bare
+// frame ID removes every frame with it, so `--delete COMM` removes all comments. --sort-keys adds the sort frames
+// (TSOP, TSOA, ...) a file is missing, following the rules of a language like "en" (see `sort::Locale`). Prints what
+// changed in each file.
+fn write_files(args: &[OsString]) {
+   let usage = "Usage: walnut write [--set <column>=<value>]... [--delete <column or frame ID>]... \
+                [--sort-keys <language>] [--dry-run] <files>";
+   let mut changes = Vec::new();
+   let mut deleted_names = Vec::new();
+   let mut sort_options = None;
+   let mut dry_run = false;
+   let mut paths = Vec::new();
+   let mut args = args.iter();
+   while let Some(arg) = args.next() {
+      match arg.to_str() {
+         Some("--set") => {
+            let assignment = args.next().map(|x| x.to_string_lossy()).unwrap_or_default();
+            let mut parts = assignment.splitn(2, '=');
+            let (column, value) = match (parts.next(), parts.next()) {
+               (Some(column), Some(value)) => (column, value),
+               _ => return eprintln!("--set needs a column and a value, e.g. TIT2=\"New Title\""),
+            };
+            let frame = match edits::Column::parse(column) {
+               Ok(edits::Column::Path) | Err(_) => return eprintln!("Unknown column {:?}", column),
+               Ok(column) => column.frame(value).map(|frame| edits::Edit {
+                  column,
+                  frame: Some(frame),
+               }),
+            };
+            match frame {
+               Ok(v) => changes.push(v),
+               Err(e) => return eprintln!("Invalid value for {}: {}", column, e),
+            }
+         }
+         Some("--delete") => {
+            let column = args.next().map(|x| x.to_string_lossy()).unwrap_or_default();
+            match (column.as_bytes(), edits::Column::parse(&column)) {
+               ([a, b, c, d], _) => deleted_names.push([*a, *b, *c, *d]),
+               (_, Ok(edits::Column::Path)) | (_, Err(_)) => return eprintln!("Unknown column {:?}", column),
+               (_, Ok(column)) => changes.push(edits::Edit { column, frame: None }),
+            }
+         }
+         Some("--sort-keys") => {
+            let language = args.next().map(|x| x.to_string_lossy()).unwrap_or_default();
+            match language.parse() {
+               Ok(locale) => {
+                  sort_options = Some(sort::SortOptions {
+                     locale,
+                     ..sort::SortOptions::default()
+                  })
+               }
+               Err(e) => return eprintln!("{}", e),
+            }
+         }
+         Some("--dry-run") => dry_run = true,
+         _ => paths.push(arg),
+      }
+   }
+   if paths.is_empty() || changes.is_empty() && deleted_names.is_empty() && sort_options.is_none() {
+      return eprintln!("{}", usage);
+   }
+
+   for path in paths {
+      let old = match id3::write::read_tag_for_update(path) {
+         Ok(v) => v,
+         Err(e) => {
+            warn!("Failed to read {}: {}", path.to_string_lossy(), e);
+            continue;
+         }
+      };
+      let mut new = old.clone();
+      new.frames.retain(|x| !deleted_names.contains(&x.data.name()));
+      edits::apply(&mut new, &changes);
+      if let Some(sort_options) = sort_options.as_ref() {
+         sort::add_sort_frames(&mut new, sort_options);
+      }
+
+      println!("{}", path.to_string_lossy());
+      print_diff(&old, &new);
+      if !dry_run {
+         if let Err(e) = id3::write::write_tag_to_file(path, &new, id3::write::WriteOptions::default()) {
+            warn!("Failed to write {}: {}", path.to_string_lossy(), e);
+         }
+      }
+   }
+}
+
+// walnut art extract [--name <name>] [--type <picture type>] [--overwrite] <paths>
+// Writes one image per directory, e.g. cover.jpg, from the first file in it that has one
+fn extract_art(args: &[OsString], filter: &glob::PathFilter) {
+   let mut name = "cover".to_owned();
+   let mut picture_type = art::FRONT_COVER;
+   let mut overwrite = false;
+   let mut paths = Vec::new();
+   let mut args = args.iter();
+   while let Some(arg) = args.next() {
+      match arg.to_str() {
+         Some("--name") => name = args.next().map(|x| x.to_string_lossy().into_owned()).unwrap_or(name),
+         Some("--type") => match args.next().and_then(|x| x.to_str()).map(str::parse) {
+            Some(Ok(v)) => picture_type = v,
+            _ => return eprintln!("--type needs a picture type number, e.g. 3 for the front cover"),
+         },
+         Some("--overwrite") => overwrite = true,
+         _ => paths.push(arg),
+      }
+   }
+
+   let mut files_by_dir: BTreeMap<PathBuf, Vec<PathBuf>> = BTreeMap::new();
+   for path in files_under(&paths, filter) {
+      let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+      files_by_dir.entry(dir).or_insert_with(Vec::new).push(path);
+   }
+
+   for (dir, files) in files_by_dir {
+      let found = files.iter().find_map(|path| {
+         let metadata = match probe::probe_path(path) {
+            Ok(v) => v,
+            Err(e) => {
+               warn!("Failed to read {}: {}", path.display(), e);
+               return None;
+            }
+         };
+         let pictures = art::pictures(&metadata);
+         let picture = art::choose(&pictures, picture_type)?;
+         match picture.format() {
+            Some(format) => Some((path, picture.data.clone(), format)),
+            None => {
+               warn!("{}: unrecognized image format {:?}", path.display(), picture.mime_type);
+               None
+            }
+         }
+      });
+      let (source, data, format) = match found {
+         Some(v) => v,
+         None => {
+            println!("{}: no picture found", dir.display());
+            continue;
+         }
+      };
+
+      let dest = dir.join(format!("{}.{}", name, format.extension()));
+      if dest.exists() && !overwrite {
+         println!("{}: already exists", dest.display());
+         continue;
+      }
+      match fs::write(&dest, &data) {
+         Ok(()) => println!("{} -> {}", source.display(), dest.display()),
+         Err(e) => warn!("Failed to write {}: {}", dest.display(), e),
+      }
+   }
+}
+
+// walnut copy [--frames <ID,ID,...>] [--preserve] <source> <destinations>
+fn copy_tags(args: &[OsString]) {
+   let mut filter = copy::CopyFilter::default();
+   let mut paths = Vec::new();
+   let mut args = args.iter();
+   while let Some(arg) = args.next() {
+      match arg.to_str() {
+         Some("--frames") => {
+            let names: Option<Vec<[u8; 4]>> = args.next().and_then(|x| x.to_str()).and_then(|x| {
+               x.split(',')
+                  .map(|name| match name.trim().as_bytes() {
+                     [a, b, c, d] => Some([*a, *b, *c, *d]),
+                     _ => None,
+                  })
+                  .collect()
+            });
+            match names {
+               Some(v) => filter.frames = Some(v),
+               None => return eprintln!("--frames needs a list of frame IDs, e.g. TIT2,TPE1,APIC"),
+            }
+         }
+         Some("--preserve") => filter.preserve = true,
+         _ => paths.push(arg),
+      }
+   }
+
+   let (source, dests) = match paths.split_first() {
+      Some((source, dests)) if !dests.is_empty() => (source, dests),
+      _ => return eprintln!("Usage: walnut copy [--frames <ID,ID,...>] [--preserve] <source> <destinations>"),
+   };
+   for dest in dests.iter() {
+      match copy::copy_tag(source, dest, &filter) {
+         Ok(copied) => println!("{}: copied {} frames", dest.to_string_lossy(), copied),
+         Err(e) => warn!("Failed to copy to {}: {}", dest.to_string_lossy(), e),
+      }
+   }
+}
+
+// walnut diff <old> <new>
+// Files of any format are compared as ID3 tags (see `copy::to_id3`)
+fn diff_tags(args: &[OsString]) {
+   let (old, new) = match args {
+      [old, new] => (old, new),
+      _ => return eprintln!("Usage: walnut diff <old> <new>"),
+   };
+   let read = |path: &OsString| match probe::probe_path(path) {
+      Ok(metadata) => Some(copy::to_id3(&metadata)),
+      Err(e) => {
+         eprintln!("Failed to read {}: {}", path.to_string_lossy(), e);
+         None
+      }
+   };
+   let (old, new) = match (read(old), read(new)) {
+      (Some(old), Some(new)) => (old, new),
+      _ => return,
+   };
+
+   print_diff(&old, &new);
+}
+
+fn print_diff(old: &id3::Id3Tag, new: &id3::Id3Tag) {
+   let hex = |bytes: &[u8]| bytes.iter().map(|x| format!("{:02x}", x)).collect::<Vec<_>>().join(" ");
+   for frame_diff in diff::diff(old, new) {
+      match frame_diff {
+         diff::FrameDiff::Added(frame) => println!("+ {:?}", frame.data),
+         diff::FrameDiff::Removed(frame) => println!("- {:?}", frame.data),
+         diff::FrameDiff::Changed { old, new, bytes } => {
+            if diff::is_binary(&new.data) {
+               println!("~ {}", String::from_utf8_lossy(&new.data.name()));
+               for change in bytes {
+                  println!(
+                     "    at {}: [{}] -> [{}]",
+                     change.offset,
+                     hex(&change.old),
+                     hex(&change.new)
+                  );
+               }
+            } else {
+               println!("~ {:?}\n  -> {:?}", old.data, new.data);
+            }
+         }
+      }
+   }
+}
+
+// walnut check [--json] [--fix] <paths>
+// Prints one finding per line, as tab-separated fields or as JSON objects. Exits with 1 if anything is an error. With
+// --fix, text that isn't normalized (see `id3::normalize`) is cleaned up and written back.
+fn check_files(args: &[OsString], filter: &glob::PathFilter) {
+   let has_flag = |flag: &str| args.iter().any(|x| x.to_str() == Some(flag));
+   let (json, fix) = (has_flag("--json"), has_flag("--fix"));
+   let paths: Vec<&OsString> = args.iter().filter(|x| !x.to_string_lossy().starts_with("--")).collect();
+   if paths.is_empty() {
+      return eprintln!("Usage: walnut check [--json] [--fix] <paths>");
+   }
+
+   let mut any_errors = false;
+   for path in files_under(&paths, filter) {
+      let findings = match File::open(&path).and_then(|mut f| check::check(&mut f)) {
+         Ok(v) => v,
+         Err(e) => {
+            warn!("Failed to check {}: {}", path.display(), e);
+            continue;
+         }
+      };
+      for finding in findings.iter() {
+         any_errors |= finding.severity == check::Severity::Error;
+         let frame = finding.frame.map(|x| String::from_utf8_lossy(&x).into_owned());
+         if json {
+            println!(
+               "{{\"path\":{},\"severity\":\"{}\",\"rule\":\"{}\",\"frame\":{},\"message\":{}}}",
+               json_string(&path.to_string_lossy()),
+               finding.severity,
+               finding.rule,
+               frame.map(|x| json_string(&x)).unwrap_or_else(|| "null".to_owned()),
+               json_string(&finding.message)
+            );
+         } else {
+            println!(
+               "{}\t{}\t{}\t{}\t{}",
+               path.display(),
+               finding.severity,
+               finding.rule,
+               frame.unwrap_or_default(),
+               finding.message
+            );
+         }
+      }
+
+      if fix && findings.iter().any(|x| x.rule == "unnormalized-text") {
+         let result = id3::write::read_tag_for_update(&path)
+            .map_err(Box::<dyn std::error::Error>::from)
+            .and_then(|mut tag| {
+               let corrections = id3::normalize::normalize_tag(&mut tag);
+               id3::write::write_tag_to_file(&path, &tag, id3::write::WriteOptions::default())?;
+               Ok(corrections.len())
+            });
+         match result {
+            Ok(count) => info!("Cleaned up the text of {} frames in {}", count, path.display()),
+            Err(e) => warn!("Failed to fix {}: {}", path.display(), e),
+         }
+      }
+   }
+   if any_errors {
+      std::process::exit(1);
+   }
+}
+
+// walnut verify <paths>
+// Walks the frames of every MPEG audio file (see `mpeg::verify`) and prints each problem on a line, after the path.
+// Exits with 1 if any file has problems or couldn't be read.
+fn verify_files(args: &[OsString], filter: &glob::PathFilter) {
+   if args.is_empty() {
+      return eprintln!("Usage: walnut verify <paths>");
+   }
+   let files: Vec<PathBuf> = files_under(args, filter)
+      .into_iter()
+      .filter(|x| probe::guess_format(x) == Some(probe::Format::Mpeg))
+      .collect();
+   let mut damaged = 0;
+   for path in files.iter() {
+      match File::open(path)
+         .map_err(mpeg::MpegParseError::from)
+         .and_then(|mut f| mpeg::verify(&mut f))
+      {
+         Ok(check) => {
+            for problem in check.problems.iter() {
+               println!("{}\t{}", path.display(), problem);
+            }
+            if !check.problems.is_empty() {
+               damaged += 1;
+            }
+         }
+         Err(e) => {
+            warn!("Failed to verify {}: {}", path.display(), e);
+            damaged += 1;
+         }
+      }
+   }
+   eprintln!("{} of {} files have problems", damaged, files.len());
+   if damaged > 0 {
+      std::process::exit(1);
+   }
+}
+
+// walnut validate [--json] [--allow-untagged] <paths>
+// Reads the ID3v2 tag of every file and prints what went wrong, a line per problem or one JSON summary. Exits with 0
+// if every tag was read cleanly, 1 if any tag is missing (unless allowed), unsupported, malformed, or has frames that
+// failed, and 2 if any file couldn't be read at all.
+fn validate_files(args: &[OsString], filter: &glob::PathFilter) {
+   let has_flag = |flag: &str| args.iter().any(|x| x.to_str() == Some(flag));
+   let (json, allow_untagged) = (has_flag("--json"), has_flag("--allow-untagged"));
+   let paths: Vec<&OsString> = args.iter().filter(|x| !x.to_string_lossy().starts_with("--")).collect();
+   if paths.is_empty() {
+      return eprintln!("Usage: walnut validate [--json] [--allow-untagged] <paths>");
+   }
+
+   // Paths that can't be walked are left out by `files_under`, so they're looked at separately
+   let mut files: Vec<PathBuf> = paths
+      .iter()
+      .map(PathBuf::from)
+      .filter(|x| fs::metadata(x).is_err())
+      .collect();
+   files.extend(files_under(&paths, filter));
+   let mut failed = Vec::new();
+   for path in files.iter() {
+      let errors: Vec<_> = match File::open(path) {
+         Ok(mut f) => validate::validate(&mut io::BufReader::new(&mut f), id3::ParseOptions::default()),
+         Err(e) => vec![validate::FileError {
+            category: validate::ErrorCategory::Io,
+            frame: None,
+            message: e.to_string(),
+         }],
+      };
+      let errors: Vec<_> = errors
+         .into_iter()
+         .filter(|x| !allow_untagged || x.category != validate::ErrorCategory::NoTag)
+         .collect();
+      if !errors.is_empty() {
+         failed.push((path, errors));
+      }
+   }
+
+   if json {
+      let errors: Vec<String> = failed
+         .iter()
+         .flat_map(|(path, errors)| errors.iter().map(move |x| (path, x)))
+         .map(|(path, x)| {
+            format!(
+               "{{\"path\":{},\"category\":\"{}\",\"frame\":{},\"message\":{}}}",
+               json_string(&path.to_string_lossy()),
+               x.category,
+               x.frame
+                  .map(|x| json_string(&String::from_utf8_lossy(&x)))
+                  .unwrap_or_else(|| "null".to_owned()),
+               json_string(&x.message)
+            )
+         })
+         .collect();
+      println!(
+         "{{\"files\":{},\"failed\":{},\"errors\":[{}]}}",
+         files.len(),
+         failed.len(),
+         errors.join(",")
+      );
+   } else {
+      for (path, errors) in failed.iter() {
+         for x in errors.iter() {
+            let frame = x.frame.map(|x| String::from_utf8_lossy(&x).into_owned());
+            println!(
+               "{}\t{}\t{}\t{}",
+               path.display(),
+               x.category,
+               frame.unwrap_or_default(),
+               x.message
+            );
+         }
+      }
+   }
+
+   let any = |category| {
+      failed
+         .iter()
+         .any(|(_, errors)| errors.iter().any(|x| x.category == category))
+   };
+   if any(validate::ErrorCategory::Io) {
+      std::process::exit(2);
+   } else if !failed.is_empty() {
+      std::process::exit(1);
+   }
+}
+
+// walnut report [--json] [--progress] [--threads <count>] [--cache <file>] <paths>
+fn report_files(args: &[OsString], filter: &glob::PathFilter) {
+   let mut json = false;
+   let mut options = scan::ScanOptions::default();
+   let mut cache_path = None;
+   let mut paths = Vec::new();
+   let mut args = args.iter();
+   while let Some(arg) = args.next() {
+      match arg.to_str() {
+         Some("--json") => json = true,
+         Some("--progress") => {
+            options.progress = Some(scan::Progress::new(|done, total| {
+               eprint!("\rScanned {}/{}", done, total)
+            }))
+         }
+         Some("--threads") => match args.next().and_then(|x| x.to_str()).map(str::parse) {
+            Some(Ok(v)) => options.threads = v,
+            _ => return eprintln!("--threads needs a number of threads (0 for one per CPU)"),
+         },
+         Some("--cache") => cache_path = args.next().map(PathBuf::from),
+         _ => paths.push(arg),
+      }
+   }
+   if paths.is_empty() {
+      return eprintln!("Usage: walnut report [--json] [--progress] [--threads <count>] [--cache <file>] <paths>");
+   }
+
+   let files = audio_files_under(&paths, filter);
+   let (library, failed) = match &cache_path {
+      Some(cache_path) => {
+         let mut scan_cache = cache::ScanCache::load(cache_path).unwrap_or_else(|e| {
+            warn!("Starting a new cache, as {} can't be used: {}", cache_path.display(), e);
+            cache::ScanCache::default()
+         });
+         let result = scan::report_cached(files, &options, &mut scan_cache);
+         scan_cache.prune();
+         if let Err(e) = scan_cache.save(cache_path) {
+            warn!("Failed to save the cache to {}: {}", cache_path.display(), e);
+         }
+         result
+      }
+      None => scan::report(files, &options),
+   };
+   if options.progress.is_some() {
+      eprintln!();
+   }
+   for (path, e) in failed.iter() {
+      warn!("Failed to read {}: {}", path.display(), e);
+   }
+   if json {
+      println!("{}", report_json(&library));
+   } else {
+      print_report(&library);
+   }
+}
+
+// walnut query --cache <file> [--threads <count>] <query> [paths]
+// Prints the path of every cached file that matches. Any paths given are scanned into the cache first.
+fn query_library(args: &[OsString], filter: &glob::PathFilter) {
+   let mut options = scan::ScanOptions::default();
+   let mut cache_path = None;
+   let mut rest = Vec::new();
+   let mut args = args.iter();
+   while let Some(arg) = args.next() {
+      match arg.to_str() {
+         Some("--threads") => match args.next().and_then(|x| x.to_str()).map(str::parse) {
+            Some(Ok(v)) => options.threads = v,
+            _ => return eprintln!("--threads needs a number of threads (0 for one per CPU)"),
+         },
+         Some("--cache") => cache_path = args.next().map(PathBuf::from),
+         _ => rest.push(arg),
+      }
+   }
+   let (cache_path, expression, paths) = match (cache_path, rest.split_first()) {
+      (Some(cache_path), Some((expression, paths))) => (cache_path, expression, paths),
+      _ => return eprintln!("Usage: walnut query --cache <file> [--threads <count>] <query> [paths]"),
+   };
+   let query: query::Query = match expression.to_string_lossy().parse() {
+      Ok(v) => v,
+      Err(e) => return eprintln!("Invalid query: {}", e),
+   };
+   let mut library = match cache::ScanCache::load(&cache_path) {
+      Ok(v) => v,
+      Err(e) => return eprintln!("Failed to read {}: {}", cache_path.display(), e),
+   };
+
+   if !paths.is_empty() {
+      let (_, failed) = scan::report_cached(audio_files_under(paths, filter), &options, &mut library);
+      for (path, e) in failed.iter() {
+         warn!("Failed to read {}: {}", path.display(), e);
+      }
+      library.prune();
+      if let Err(e) = library.save(&cache_path) {
+         warn!("Failed to save the cache to {}: {}", cache_path.display(), e);
+      }
+   }
+   for (path, _) in library.query(&query) {
+      println!("{}", path.display());
+   }
+}
+
+// walnut playlist --out <file> [--format <m3u8|pls|xspf>] [--absolute] [--cache <file> --query <query>] [paths]
+// The files are the ones under the paths, or the ones in the cache that match the query, in order of path
+fn write_playlist(args: &[OsString], filter: &glob::PathFilter) {
+   let usage = "Usage: walnut playlist --out <file> [--format <m3u8|pls|xspf>] [--absolute] \
+                [--cache <file> --query <query>] [paths]";
+   let mut out_path = None;
+   let mut format = None;
+   let mut absolute = false;
+   let mut cache_path = None;
+   let mut expression = None;
+   let mut paths = Vec::new();
+   let mut args = args.iter();
+   while let Some(arg) = args.next() {
+      match arg.to_str() {
+         Some("--out") => out_path = args.next().map(PathBuf::from),
+         Some("--format") => match args
+            .next()
+            .and_then(|x| x.to_str())
+            .map(playlist::PlaylistFormat::from_extension)
+         {
+            Some(Some(v)) => format = Some(v),
+            _ => return eprintln!("--format needs one of m3u8, pls, or xspf"),
+         },
+         Some("--absolute") => absolute = true,
+         Some("--cache") => cache_path = args.next().map(PathBuf::from),
+         Some("--query") => expression = args.next().map(|x| x.to_string_lossy().into_owned()),
+         _ => paths.push(PathBuf::from(arg)),
+      }
+   }
+   let out_path = match out_path {
+      Some(v) => v,
+      None => return eprintln!("{}", usage),
+   };
+   let format = match format.or_else(|| {
+      out_path
+         .extension()
+         .and_then(|x| x.to_str())
+         .and_then(playlist::PlaylistFormat::from_extension)
+   }) {
+      Some(v) => v,
+      None => {
+         return eprintln!(
+            "Can't tell the playlist format from {}; use --format",
+            out_path.display()
+         )
+      }
+   };
+   // Relative paths are relative to where the playlist is, so everything has to be absolute first
+   let current_dir = match std::env::current_dir() {
+      Ok(v) => v,
+      Err(e) => return eprintln!("Failed to get the current directory: {}", e),
+   };
+
+   let mut entries = Vec::new();
+   match (cache_path, expression) {
+      (Some(cache_path), Some(expression)) => {
+         let query: query::Query = match expression.parse() {
+            Ok(v) => v,
+            Err(e) => return eprintln!("Invalid query: {}", e),
+         };
+         let library = match cache::ScanCache::load(&cache_path) {
+            Ok(v) => v,
+            Err(e) => return eprintln!("Failed to read {}: {}", cache_path.display(), e),
+         };
+         for (path, tag) in library.query(&query) {
+            entries.push(playlist::PlaylistEntry::from_tag(current_dir.join(path), tag));
+         }
+      }
+      (None, None) if !paths.is_empty() => {
+         let mut files = files_under(&paths, filter);
+         files.sort();
+         for path in files {
+            match playlist::PlaylistEntry::read(current_dir.join(&path)) {
+               Ok(v) => entries.push(v),
+               Err(e) => warn!("Failed to read {}: {}", path.display(), e),
+            }
+         }
+      }
+      _ => return eprintln!("{}", usage),
+   }
+
+   let style = if absolute {
+      playlist::PathStyle::Absolute
+   } else {
+      playlist::PathStyle::RelativeTo(current_dir.join(&out_path).parent().unwrap_or(&current_dir).to_owned())
+   };
+   let result = File::create(&out_path).and_then(|f| playlist::write(io::BufWriter::new(f), &entries, format, &style));
+   match result {
+      Ok(()) => println!("Wrote {} entries to {}", entries.len(), out_path.display()),
+      Err(e) => warn!("Failed to write {}: {}", out_path.display(), e),
+   }
+}
+
+// walnut cue <cue sheets>
+// Lists the tracks of each cue sheet, with their tags merged with those of the files they're in
+fn list_cue_tracks(args: &[OsString]) {
+   if args.is_empty() {
+      return eprintln!("Usage: walnut cue <cue sheets>");
+   }
+   let timestamp =
+      |x: std::time::Duration| format!("{}:{:02}.{:03}", x.as_secs() / 60, x.as_secs() % 60, x.subsec_millis());
+   for cue_path in args.iter().map(Path::new) {
+      let sheet = match File::open(cue_path)
+         .map_err(cue::CueError::from)
+         .and_then(cue::read_cue)
+      {
+         Ok(v) => v,
+         Err(e) => {
+            warn!("Failed to read {}: {}", cue_path.display(), e);
+            continue;
+         }
+      };
+      println!("{}", cue_path.display());
+      // The tracks of each file fall back on its tags
+      let physical: Vec<Option<probe::Metadata>> = sheet
+         .files
+         .iter()
+         .map(|file| {
+            let audio_path = cue_path.with_file_name(&file.name);
+            probe::probe_path(&audio_path)
+               .map_err(|e| warn!("Failed to read {}: {}", audio_path.display(), e))
+               .ok()
+         })
+         .collect();
+      let physical: Vec<Option<&dyn Tag>> = physical.iter().map(|x| x.as_ref().map(|x| x as &dyn Tag)).collect();
+      for track in sheet.virtual_tracks(&physical) {
+         println!(
+            "  {:02}  {} - {}  {} - {}",
+            track.track_number().unwrap_or_default(),
+            timestamp(track.start),
+            track.end.map(timestamp).unwrap_or_else(|| "end".to_owned()),
+            track.artist().unwrap_or("?"),
+            track.title().unwrap_or("?")
+         );
+      }
+   }
+}
+
+// walnut identify [--key <AcoustID API key>] [--all] <paths>
+// Suggests metadata for files without a title or artist, by their audio fingerprint. The key can also be given with
+// the ACOUSTID_API_KEY environment variable.
+#[cfg(feature = "acoustid")]
+fn identify_files(args: &[OsString], filter: &glob::PathFilter) {
+   let mut api_key = std::env::var("ACOUSTID_API_KEY").ok();
+   let mut all = false;
+   let mut paths = Vec::new();
+   let mut args = args.iter();
+   while let Some(arg) = args.next() {
+      match arg.to_str() {
+         Some("--key") => api_key = args.next().map(|x| x.to_string_lossy().into_owned()),
+         Some("--all") => all = true,
+         _ => paths.push(arg),
+      }
+   }
+   let api_key = match api_key {
+      Some(v) if !paths.is_empty() => v,
+      _ => return eprintln!("Usage: walnut identify [--key <AcoustID API key>] [--all] <paths>"),
+   };
+
+   let mut client = acoustid::Client::new(api_key);
+   for path in files_under(&paths, filter) {
+      if !all {
+         match probe::probe_path(&path) {
+            Ok(metadata) if metadata.title().is_some() && metadata.artist().is_some() => continue,
+            Ok(_) => (),
+            Err(e) => {
+               warn!("Failed to read {}: {}", path.display(), e);
+               continue;
+            }
+         }
+      }
+      let matches = match acoustid::fingerprint(&path).and_then(|x| client.lookup(&x)) {
+         Ok(v) => v,
+         Err(e) => {
+            warn!("Failed to identify {}: {}", path.display(), e);
+            continue;
+         }
+      };
+
+      println!("{}", path.display());
+      let suggestions: Vec<_> = matches
+         .iter()
+         .flat_map(|m| m.recordings.iter().map(move |r| (m.score, r)))
+         .take(3)
+         .collect();
+      if suggestions.is_empty() {
+         println!("  no match");
+      }
+      for (score, recording) in suggestions {
+         let mut line = format!(
+            "  {:3.0}%  {} - {}",
+            score * 100.0,
+            recording.artist.as_ref().map_or("?", String::as_str),
+            recording.title.as_ref().map_or("?", String::as_str)
+         );
+         if let Some(release) = recording.releases.first() {
+            line.push_str(&format!("  [{}", release.title.as_ref().map_or("?", String::as_str)));
+            if let Some(year) = release.year {
+               line.push_str(&format!(", {}", year));
+            }
+            if let (Some(number), Some(total)) = (release.track_number, release.track_total) {
+               line.push_str(&format!(", track {}/{}", number, total));
+            }
+            line.push(']');
+         }
+         println!("{}  {}", line, recording.id);
+      }
+   }
+}
+
+#[cfg(not(feature = "acoustid"))]
+fn identify_files(_: &[OsString], _: &glob::PathFilter) {
+   eprintln!("identify needs walnut to be built with the acoustid feature");
+}
+
+// walnut fix [--apply] [--fingerprint] [--min-score <0-100>] <paths>
+// Looks MPEG files up with MusicBrainz and shows the frames that would change; --apply writes them. Files are looked
+// up by the recording ID in their tag, then by fingerprint (with --fingerprint, which needs an AcoustID API key in
+// ACOUSTID_API_KEY), then by a search for their title, artist, and album.
+#[cfg(feature = "musicbrainz-lookup")]
+fn fix_files(args: &[OsString], filter: &glob::PathFilter) {
+   let mut apply = false;
+   let mut acoustid_client = None;
+   let mut min_score = 90;
+   let mut paths = Vec::new();
+   let mut args = args.iter();
+   while let Some(arg) = args.next() {
+      match arg.to_str() {
+         Some("--apply") => apply = true,
+         Some("--fingerprint") => match std::env::var("ACOUSTID_API_KEY") {
+            Ok(key) => acoustid_client = Some(acoustid::Client::new(key)),
+            Err(_) => return eprintln!("--fingerprint needs an AcoustID API key in ACOUSTID_API_KEY"),
+         },
+         Some("--min-score") => match args.next().and_then(|x| x.to_str()).and_then(|x| x.parse::<u8>().ok()) {
+            Some(v) if v <= 100 => min_score = v,
+            _ => return eprintln!("--min-score needs a score from 0 to 100"),
+         },
+         _ => paths.push(arg),
+      }
+   }
+   if paths.is_empty() {
+      return eprintln!("Usage: walnut fix [--apply] [--fingerprint] [--min-score <0-100>] <paths>");
+   }
+
+   let mut client = fix::Client::new(
+      concat!(
+         "walnut/",
+         env!("CARGO_PKG_VERSION"),
+         " ( https://github.com/DenialAdams/walnut )"
+      )
+      .to_owned(),
+   );
+   for path in files_under(&paths, filter) {
+      match probe::probe_path(&path) {
+         Ok(probe::Metadata::Mpeg(_)) => (),
+         Ok(_) => continue,
+         Err(e) => {
+            warn!("Failed to read {}: {}", path.display(), e);
+            continue;
+         }
+      }
+      let current = match id3::write::read_tag_for_update(&path) {
+         Ok(v) => v,
+         Err(e) => {
+            warn!("Failed to read the tag of {}: {}", path.display(), e);
+            continue;
+         }
+      };
+
+      let suggestion = suggest_fix(&mut client, acoustid_client.as_mut(), &path, &current, min_score);
+      let suggestion = match suggestion {
+         Ok(Some(v)) => v,
+         Ok(None) => {
+            println!("{}: no match", path.display());
+            continue;
+         }
+         Err(e) => {
+            warn!("Failed to look up {}: {}", path.display(), e);
+            continue;
+         }
+      };
+
+      let proposed = fix::propose(&current, &suggestion);
+      if diff::diff(&current, &proposed).is_empty() {
+         println!("{}: up to date", path.display());
+         continue;
+      }
+      println!(
+         "{}: recording {} ({}%)",
+         path.display(),
+         suggestion.recording_id,
+         suggestion.score
+      );
+      print_diff(&current, &proposed);
+      if apply {
+         if let Err(e) = id3::write::write_tag_to_file(&path, &proposed, id3::write::WriteOptions::default()) {
+            warn!("Failed to write {}: {}", path.display(), e);
+         }
+      }
+   }
+}
+
+// By the recording ID in the tag, then by fingerprint, then by a search
+#[cfg(feature = "musicbrainz-lookup")]
+fn suggest_fix(
+   client: &mut fix::Client,
+   acoustid_client: Option<&mut acoustid::Client>,
+   path: &Path,
+   current: &id3::Id3Tag,
+   min_score: u8,
+) -> Result<Option<fix::Suggestion>, Box<dyn std::error::Error>> {
+   if let Some(id) = fix::recording_id(current) {
+      return Ok(Some(client.recording(id, current.album())?));
+   }
+   if let Some(acoustid_client) = acoustid_client {
+      let matches = acoustid_client.lookup(&acoustid::fingerprint(path)?)?;
+      let id = matches
+         .into_iter()
+         .filter(|x| x.score * 100.0 >= f32::from(min_score))
+         .flat_map(|x| x.recordings)
+         .map(|x| x.id)
+         .next();
+      if let Some(id) = id {
+         return Ok(Some(client.recording(&id, current.album())?));
+      }
+   }
+   Ok(client.search(current)?.into_iter().find(|x| x.score >= min_score))
+}
+
+#[cfg(not(feature = "musicbrainz-lookup"))]
+fn fix_files(_: &[OsString], _: &glob::PathFilter) {
+   eprintln!("fix needs walnut to be built with the musicbrainz-lookup feature");
+}
+
+fn print_report(library: &report::Report) {
+   // Most common first
+   let print_counts = |heading: &str, counts: &BTreeMap<String, u64>| {
+      println!("{}:", heading);
+      let mut counts: Vec<_> = counts.iter().collect();
+      counts.sort_by(|a, b| b.1.cmp(a.1));
+      for (name, count) in counts {
+         println!("  {:>7}  {}", count, name);
+      }
+   };
+
+   println!("Files: {} ({} unreadable)", library.files, library.unreadable);
+   print_counts("Tags", &library.tag_versions);
+   print_counts("ID3v2.4 text encodings", &library.encodings);
+   print_counts("Genres", &library.genres);
+   println!("Missing title: {}", library.missing_title);
+   println!("Missing artist: {}", library.missing_artist);
+   println!("Missing album: {}", library.missing_album);
+   println!("Missing art: {}", library.missing_art);
+   match library.average_id3v2_size() {
+      Some(v) => println!("Average ID3v2 tag: {:.0} bytes", v),
+      None => println!("Average ID3v2 tag: -"),
+   }
+   println!("Artwork: {} bytes", library.artwork_bytes);
+}
+
+fn report_json(library: &report::Report) -> String {
+   let counts = |counts: &BTreeMap<String, u64>| {
+      let fields: Vec<String> = counts
+         .iter()
+         .map(|(name, count)| format!("{}:{}", json_string(name), count))
+         .collect();
+      format!("{{{}}}", fields.join(","))
+   };
+   format!(
+      "{{\"files\":{},\"unreadable\":{},\"tag_versions\":{},\"encodings\":{},\"genres\":{},\"missing_title\":{},\
+       \"missing_artist\":{},\"missing_album\":{},\"missing_art\":{},\"id3v2_tags\":{},\"id3v2_bytes\":{},\
+       \"average_id3v2_size\":{},\"artwork_bytes\":{}}}",
+      library.files,
+      library.unreadable,
+      counts(&library.tag_versions),
+      counts(&library.encodings),
+      counts(&library.genres),
+      library.missing_title,
+      library.missing_artist,
+      library.missing_album,
+      library.missing_art,
+      library.id3v2_tags,
+      library.id3v2_bytes,
+      library
+         .average_id3v2_size()
+         .map(|x| format!("{:.1}", x))
+         .unwrap_or_else(|| "null".to_owned()),
+      library.artwork_bytes
+   )
+}
+
+fn json_string(value: &str) -> String {
+   let mut quoted = String::with_capacity(value.len() + 2);
+   quoted.push('"');
+   for c in value.chars() {
+      match c {
+         '"' => quoted.push_str("\\\""),
+         '\\' => quoted.push_str("\\\\"),
+         '\n' => quoted.push_str("\\n"),
+         '\r' => quoted.push_str("\\r"),
+         '\t' => quoted.push_str("\\t"),
+         c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+         c => quoted.push(c),
+      }
+   }
+   quoted.push('"');
+   quoted
+}
+
+// --include <glob> and --exclude <glob> can be given any number of times to any command
+fn take_path_filter(args: &mut Vec<OsString>) -> Result<glob::PathFilter, String> {
+   let mut filter = glob::PathFilter::default();
+   let mut i = 0;
+   while i < args.len() {
+      let patterns = match args[i].to_str() {
+         Some("--include") => &mut filter.include,
+         Some("--exclude") => &mut filter.exclude,
+         _ => {
+            i += 1;
+            continue;
+         }
+      };
+      let flag = args.remove(i);
+      if i == args.len() {
+         return Err(format!("{} needs a pattern, e.g. \"**/*.mp3\"", flag.to_string_lossy()));
+      }
+      let pattern = args.remove(i);
+      match pattern.to_string_lossy().parse() {
+         Ok(v) => patterns.push(v),
+         Err(e) => return Err(format!("Invalid pattern {}: {}", pattern.to_string_lossy(), e)),
+      }
+   }
+   Ok(filter)
+}
+
+// Every file under the paths, leaving out what the --include and --exclude patterns and the .walnutignore files of
+// the directories searched say to
+fn files_under<P: AsRef<Path>>(paths: &[P], filter: &glob::PathFilter) -> Vec<PathBuf> {
+   let mut files = Vec::new();
+   for root in paths {
+      let root = root.as_ref();
+      // The ignore files of the directories above the entry, with their depths
+      let mut ignored: Vec<(usize, PathBuf, Vec<glob::Glob>)> = Vec::new();
+      let entries = WalkDir::new(root).into_iter().filter_entry(|entry| {
+         while ignored.last().map_or(false, |x| x.0 >= entry.depth()) {
+            ignored.pop();
+         }
+         let is_dir = entry.file_type().is_dir();
+         if entry.depth() > 0 || !is_dir {
+            let relative = if entry.depth() == 0 {
+               Path::new(entry.file_name())
+            } else {
+               entry.path().strip_prefix(root).unwrap_or_else(|_| entry.path())
+            };
+            if filter.excludes(relative, is_dir) {
+               return false;
+            }
+            let is_ignored = ignored.iter().any(|(_, dir, globs)| {
+               let relative = entry.path().strip_prefix(dir).unwrap_or_else(|_| entry.path());
+               globs.iter().any(|x| x.matches(relative))
+            });
+            if is_ignored {
+               return false;
+            }
+         }
+         if is_dir {
+            let ignore_path = entry.path().join(glob::IGNORE_FILE);
+            match fs::read_to_string(&ignore_path) {
+               Ok(text) => match glob::parse_ignore_file(&text) {
+                  Ok(globs) => ignored.push((entry.depth(), entry.path().to_owned(), globs)),
+                  Err(e) => warn!("Failed to read {}: {}", ignore_path.display(), e),
+               },
+               Err(ref e) if e.kind() == io::ErrorKind::NotFound => (),
+               Err(e) => warn!("Failed to read {}: {}", ignore_path.display(), e),
+            }
+         }
+         true
+      });
+      for entry in entries {
+         match entry {
+            Ok(v) => {
+               if v.file_type().is_file() {
+                  files.push(v.into_path());
+               }
+            }
+            Err(e) => warn!("Failed to open file/directory: {}", e),
+         }
+      }
+   }
+   files
+}
+
+// Like `files_under`, leaving out what isn't audio (see `probe::guess_format`)
+fn audio_files_under<P: AsRef<Path>>(paths: &[P], filter: &glob::PathFilter) -> Vec<PathBuf> {
+   files_under(paths, filter)
+      .into_iter()
+      .filter(|x| probe::guess_format(x).is_some())
+      .collect()
 }
 
 fn print_file(f: &mut File) -> bool {
-   match id3::parse_source(f) {
+   let tag_ok = print_tag(f);
+   print_audio(f);
+   tag_ok
+}
+
+fn print_audio(f: &mut File) {
+   match mpeg::read_properties(f) {
+      Ok(p) => println!(
+         "{:?} {:?}, {}kbps ({:?}), {}Hz, {:?}, {:.1}s",
+         p.version,
+         p.layer,
+         p.bitrate,
+         p.bitrate_mode,
+         p.sample_rate,
+         p.channel_mode,
+         p.duration().as_millis() as f64 / 1000.0
+      ),
+      Err(e) => warn!("Failed to read audio properties: {}", e),
+   }
+}
+
+fn print_tag(f: &mut File) -> bool {
+   match id3::parse_source(f, id3::ParseOptions::default()) {
       Ok(parser) => {
          println!("ID3v24");
          for frame in parser {
             match frame {
-               Err(e) => warn!(
-                  "Failed to parse frame {}: {:?}",
-                  String::from_utf8_lossy(&e.name),
-                  e.reason
-               ),
-               Ok(frame) => match frame.data {
-                  id3::v24::FrameData::COMM(x) => println!("Comment: {:?}", x),
-                  id3::v24::FrameData::PRIV(x) => println!("Private: {:?}", x),
-                  id3::v24::FrameData::RVRB(x) => println!("Reverb: {:?}", x),
-                  id3::v24::FrameData::TALB(x) => println!("Album: {:?}", x),
-                  id3::v24::FrameData::TBPM(x) => println!("BPM: {:?}", x),
-                  id3::v24::FrameData::TCOM(x) => println!("Composer: {:?}", x),
-                  id3::v24::FrameData::TCON(x) => println!("Genre: {:?}", x),
-                  id3::v24::FrameData::TCOP(x) => println!("Copyright: {:?}", x),
-                  id3::v24::FrameData::TDEN(x) => println!("Encoding Date: {:?}", x),
-                  id3::v24::FrameData::TDOR(x) => println!("Original Release Date: {:?}", x),
-                  id3::v24::FrameData::TDLY(x) => println!("Delay: {:?}ms", x),
-                  id3::v24::FrameData::TDRC(x) => println!("Recording Date: {:?}", x),
-                  id3::v24::FrameData::TDRL(x) => println!("Release Date: {:?}", x),
-                  id3::v24::FrameData::TDTG(x) => println!("Tagging Date: {:?}", x),
-                  id3::v24::FrameData::TENC(x) => println!("Encoded by: {:?}", x),
-                  id3::v24::FrameData::TEXT(x) => println!("Lyricist/Text Writer: {:?}", x),
-                  id3::v24::FrameData::TIPL(x) => println!("Involved People: {:?}", x),
-                  id3::v24::FrameData::TIT1(x) => println!("Content group description: {:?}", x),
-                  id3::v24::FrameData::TIT2(x) => println!("Title: {:?}", x),
-                  id3::v24::FrameData::TIT3(x) => println!("Substitle/description refinement: {:?}", x),
-                  id3::v24::FrameData::TLEN(x) => println!("Length: {:?}ms", x),
-                  id3::v24::FrameData::TMCL(x) => println!("Musician Credits: {:?}", x),
-                  id3::v24::FrameData::TMOO(x) => println!("Mood: {:?}", x),
-                  id3::v24::FrameData::TOAL(x) => println!("Original Album Title: {:?}", x),
-                  id3::v24::FrameData::TOFN(x) => println!("Original filename: {:?}", x),
-                  id3::v24::FrameData::TOLY(x) => println!("Original Lyricist/Text Writer: {:?}", x),
-                  id3::v24::FrameData::TOPE(x) => println!("Original Artist: {:?}", x),
-                  id3::v24::FrameData::TOWN(x) => println!("File Owner/Licensee: {:?}", x),
-                  id3::v24::FrameData::TPE1(x) => println!("Artist: {:?}", x),
-                  id3::v24::FrameData::TPE2(x) => println!("Album Artist: {:?}", x),
-                  id3::v24::FrameData::TPE3(x) => println!("Conductor: {:?}", x),
-                  id3::v24::FrameData::TPE4(x) => println!("Interpreted, remixed, or otherwise modified by: {:?}", x),
-                  id3::v24::FrameData::TPOS(x) => println!("CD: {:?}", x),
-                  id3::v24::FrameData::TPRO(x) => println!("Production Copyright: {:?}", x),
-                  id3::v24::FrameData::TPUB(x) => println!("Publisher: {:?}", x),
-                  id3::v24::FrameData::TRCK(x) => println!("Track: {:?}", x),
-                  id3::v24::FrameData::TRSN(x) => println!("Internet Radio Station Name: {:?}", x),
-                  id3::v24::FrameData::TRSO(x) => println!("Internet Radio Station Owner: {:?}", x),
-                  id3::v24::FrameData::TSOA(x) => println!("Album for sorting: {:?}", x),
-                  id3::v24::FrameData::TSOP(x) => println!("Artist name for sorting: {:?}", x),
-                  id3::v24::FrameData::TSOT(x) => println!("Title for sorting: {:?}", x),
-                  id3::v24::FrameData::TSRC(x) => println!("ISRC: {:?}", x),
-                  id3::v24::FrameData::TSSE(x) => println!("Encoding settings: {:?}", x),
-                  id3::v24::FrameData::TSST(x) => println!("Set Subtitle: {:?}", x),
-                  id3::v24::FrameData::TXXX(x) => println!("User defined text: {:?}", x),
-                  id3::v24::FrameData::USLT(x) => println!("Lyrics: {:?}", x),
-                  id3::v24::FrameData::WCOM(x) => println!("Commercial Information URL: {:?}", x),
-                  id3::v24::FrameData::WCOP(x) => println!("Copyright/Legal Info URL: {:?}", x),
-                  id3::v24::FrameData::WOAF(x) => println!("Audio File URL: {:?}", x),
-                  id3::v24::FrameData::WOAR(x) => println!("Artist/Performer URL: {:?}", x),
-                  id3::v24::FrameData::WOAS(x) => println!("Audio Source URL: {:?}", x),
-                  id3::v24::FrameData::WORS(x) => println!("Internet Radio Station URL: {:?}", x),
-                  id3::v24::FrameData::WPAY(x) => println!("Payment URL: {:?}", x),
-                  id3::v24::FrameData::WPUB(x) => println!("Publisher URL: {:?}", x),
-                  id3::v24::FrameData::Unknown(u) => println!("Unknown frame: {}", String::from_utf8_lossy(&u.name)),
+               Err(e) => warn!("Failed to parse frame {}", e),
+               Ok(frame) => match frame.data.id() {
+                  Some(id) => println!("{}: {}", id.description(), describe(&frame.data)),
+                  None => println!("Unknown frame: {}", String::from_utf8_lossy(&frame.data.name())),
                },
             }
          }
@@ -136,7 +1926,9 @@ fn print_file(f: &mut File) -> bool {
             id3::TagParseError::NoTag => {
                println!("No ID3");
             }
-            id3::TagParseError::TagTooSmall => {
+            id3::TagParseError::TagTooSmall
+            | id3::TagParseError::InvalidSize
+            | id3::TagParseError::CrcMismatch { .. } => {
                println!("Malformed ID3 input");
             }
             id3::TagParseError::UnsupportedVersion(ver) => {
@@ -150,3 +1942,23 @@ fn print_file(f: &mut File) -> bool {
       }
    }
 }
+
+fn describe(data: &id3::v24::FrameData) -> String {
+   match data {
+      id3::v24::FrameData::RVRB(x) => x.to_string(),
+      id3::v24::FrameData::TCOP(x) | id3::v24::FrameData::TPRO(x) => join(x),
+      id3::v24::FrameData::TDEN(x)
+      | id3::v24::FrameData::TDOR(x)
+      | id3::v24::FrameData::TDRC(x)
+      | id3::v24::FrameData::TDRL(x)
+      | id3::v24::FrameData::TDTG(x) => join(x),
+      id3::v24::FrameData::MVIN(x) | id3::v24::FrameData::TPOS(x) | id3::v24::FrameData::TRCK(x) => join(x),
+      // Frames we know of but don't decode yet
+      id3::v24::FrameData::Unknown(x) => format!("{} bytes", x.data.len()),
+      x => format!("{:?}", x),
+   }
+}
+
+fn join<T: Display>(values: &[T]) -> String {
+   values.iter().map(|x| x.to_string()).collect::<Vec<_>>().join(", ")
+}
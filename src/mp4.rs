@@ -0,0 +1,422 @@
+use crate::genre;
+use crate::tag::{self, Tag};
+use byteorder::{BigEndian, ByteOrder};
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom};
+
+// Well-known data types of the `data` atom
+const TYPE_UTF8: u32 = 1;
+const TYPE_UTF16: u32 = 2;
+const TYPE_JPEG: u32 = 13;
+const TYPE_PNG: u32 = 14;
+const TYPE_SIGNED_INT: u32 = 21;
+const TYPE_UNSIGNED_INT: u32 = 22;
+const TYPE_BMP: u32 = 27;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ItemKey {
+   /// e.g. `*b"\xa9nam"` for the title (0xA9 is © in Mac OS Roman)
+   Atom([u8; 4]),
+   /// A `----` atom, identified by its reverse-DNS mean and its name, e.g. "com.apple.iTunes" and "iTunNORM"
+   Freeform { mean: String, name: String },
+}
+
+#[derive(Clone, Debug)]
+pub enum Data {
+   Text(String),
+   Integer(i64),
+   Jpeg(Vec<u8>),
+   Png(Vec<u8>),
+   Bmp(Vec<u8>),
+   /// Anything else, including the implicitly typed (type 0) binary data of trkn and disk
+   Other {
+      type_indicator: u32,
+      data: Vec<u8>,
+   },
+}
+
+#[derive(Clone, Debug)]
+pub struct Item {
+   pub key: ItemKey,
+   pub values: Vec<Data>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Metadata {
+   pub items: Vec<Item>,
+}
+
+impl Metadata {
+   pub fn get(&self, atom: &[u8; 4]) -> Option<&Item> {
+      self.items.iter().find(|x| x.key == ItemKey::Atom(*atom))
+   }
+
+   pub fn get_freeform(&self, mean: &str, name: &str) -> Option<&Item> {
+      self.items.iter().find(|x| match &x.key {
+         ItemKey::Freeform { mean: m, name: n } => m == mean && n.eq_ignore_ascii_case(name),
+         _ => false,
+      })
+   }
+
+   /// The first text value of the given atom
+   pub fn text(&self, atom: &[u8; 4]) -> Option<&str> {
+      self.get(atom)?.values.iter().find_map(|x| match x {
+         Data::Text(text) => Some(text.as_str()),
+         _ => None,
+      })
+   }
+
+   pub fn integer(&self, atom: &[u8; 4]) -> Option<i64> {
+      self.get(atom)?.values.iter().find_map(|x| match x {
+         Data::Integer(v) => Some(*v),
+         // Some taggers (and gnre, always) write integers without a type
+         Data::Other { data, .. } if !data.is_empty() && data.len() <= 4 => {
+            Some(data.iter().fold(0, |acc, x| acc << 8 | i64::from(*x)))
+         }
+         _ => None,
+      })
+   }
+
+   /// The compilation flag (cpil)
+   pub fn compilation(&self) -> Option<bool> {
+      self.integer(b"cpil").map(|x| x != 0)
+   }
+
+   /// Cover art from the covr atom
+   pub fn pictures(&self) -> impl Iterator<Item = &Data> {
+      self.get(b"covr").into_iter().flat_map(|x| x.values.iter())
+   }
+
+   // trkn and disk hold 2 reserved bytes, the number, then the total
+   fn number_pair(&self, atom: &[u8; 4]) -> (Option<u32>, Option<u32>) {
+      let data = self.get(atom).and_then(|item| {
+         item.values.iter().find_map(|x| match x {
+            Data::Other { data, .. } if data.len() >= 6 => Some(data),
+            _ => None,
+         })
+      });
+      match data {
+         Some(data) => {
+            let non_zero = |x: u16| if x == 0 { None } else { Some(u32::from(x)) };
+            (
+               non_zero(BigEndian::read_u16(&data[2..4])),
+               non_zero(BigEndian::read_u16(&data[4..6])),
+            )
+         }
+         None => (None, None),
+      }
+   }
+}
+
+impl Tag for Metadata {
+   fn title(&self) -> Option<&str> {
+      self.text(b"\xa9nam")
+   }
+
+   fn artist(&self) -> Option<&str> {
+      self.text(b"\xa9ART")
+   }
+
+   fn album(&self) -> Option<&str> {
+      self.text(b"\xa9alb")
+   }
+
+   fn album_artist(&self) -> Option<&str> {
+      self.text(b"aART")
+   }
+
+   fn genre(&self) -> Option<&str> {
+      // gnre holds the ID3v1 genre index plus one
+      self.text(b"\xa9gen").or_else(|| match self.integer(b"gnre") {
+         Some(v) if v > 0 && v <= 256 => genre::name((v - 1) as u8),
+         _ => None,
+      })
+   }
+
+   fn year(&self) -> Option<u16> {
+      self.text(b"\xa9day").and_then(tag::parse_year)
+   }
+
+   fn track_number(&self) -> Option<u32> {
+      self.number_pair(b"trkn").0
+   }
+
+   fn track_total(&self) -> Option<u32> {
+      self.number_pair(b"trkn").1
+   }
+
+   fn disc_number(&self) -> Option<u32> {
+      self.number_pair(b"disk").0
+   }
+
+   fn disc_total(&self) -> Option<u32> {
+      self.number_pair(b"disk").1
+   }
+
+   fn comment(&self) -> Option<&str> {
+      self.text(b"\xa9cmt")
+   }
+}
+
+#[derive(Debug)]
+pub enum Mp4ParseError {
+   NotMp4,
+   InvalidAtom,
+   Io(io::Error),
+}
+
+impl fmt::Display for Mp4ParseError {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      match self {
+         Mp4ParseError::NotMp4 => write!(f, "not an MP4 file"),
+         Mp4ParseError::InvalidAtom => write!(f, "atom size is invalid"),
+         Mp4ParseError::Io(e) => write!(f, "I/O error: {}", e),
+      }
+   }
+}
+
+impl Error for Mp4ParseError {
+   fn source(&self) -> Option<&(dyn Error + 'static)> {
+      match self {
+         Mp4ParseError::Io(e) => Some(e),
+         _ => None,
+      }
+   }
+}
+
+impl From<io::Error> for Mp4ParseError {
+   fn from(e: io::Error) -> Mp4ParseError {
+      Mp4ParseError::Io(e)
+   }
+}
+
+/// Reads the iTunes metadata in moov/udta/meta/ilst. Every other atom (including the audio) is skipped by seeking.
+pub fn read_metadata<S: Read + Seek>(source: &mut S) -> Result<Metadata, Mp4ParseError> {
+   let file_len = source.seek(SeekFrom::End(0))?;
+   source.seek(SeekFrom::Start(0))?;
+
+   let mut header = [0u8; 8];
+   source.read_exact(&mut header)?;
+   if &header[4..8] != b"ftyp" {
+      return Err(Mp4ParseError::NotMp4);
+   }
+   source.seek(SeekFrom::Start(0))?;
+
+   let mut end = file_len;
+   for name in [b"moov", b"udta", b"meta"].iter() {
+      end = match find_atom(source, end, name)? {
+         Some(v) => v,
+         None => return Ok(Metadata::default()),
+      };
+   }
+
+   // meta is a full atom, with 4 bytes of version and flags before its children. QuickTime files leave these out.
+   let meta_start = source.seek(SeekFrom::Current(0))?;
+   let mut first_child = [0u8; 8];
+   source.read_exact(&mut first_child)?;
+   let children_start = if &first_child[4..8] == b"hdlr" {
+      meta_start
+   } else {
+      meta_start + 4
+   };
+   source.seek(SeekFrom::Start(children_start))?;
+
+   let ilst_end = match find_atom(source, end, b"ilst")? {
+      Some(v) => v,
+      None => return Ok(Metadata::default()),
+   };
+   let ilst_start = source.seek(SeekFrom::Current(0))?;
+   let mut ilst = Vec::new();
+   source.take(ilst_end - ilst_start).read_to_end(&mut ilst)?;
+
+   let mut items = Vec::new();
+   for (name, content) in Atoms::new(&ilst) {
+      items.push(parse_item(name, content?)?);
+   }
+   Ok(Metadata { items })
+}
+
+/// Where the content of the top level mdat atom, which holds the audio, starts and ends
+pub fn find_mdat<S: Read + Seek>(source: &mut S) -> Result<Option<(u64, u64)>, Mp4ParseError> {
+   let file_len = source.seek(SeekFrom::End(0))?;
+   source.seek(SeekFrom::Start(0))?;
+   match find_atom(source, file_len, b"mdat")? {
+      Some(end) => Ok(Some((source.seek(SeekFrom::Current(0))?, end))),
+      None => Ok(None),
+   }
+}
+
+// Leaves the source at the start of the atom's content, and returns where the content ends
+fn find_atom<S: Read + Seek>(source: &mut S, end: u64, name: &[u8; 4]) -> Result<Option<u64>, Mp4ParseError> {
+   loop {
+      let start = source.seek(SeekFrom::Current(0))?;
+      if start + 8 > end {
+         return Ok(None);
+      }
+      let mut header = [0u8; 8];
+      source.read_exact(&mut header)?;
+      let size = match BigEndian::read_u32(&header[0..4]) {
+         // Extends to the end of its parent
+         0 => end - start,
+         1 => {
+            let mut large_size = [0u8; 8];
+            source.read_exact(&mut large_size)?;
+            BigEndian::read_u64(&large_size)
+         }
+         v => u64::from(v),
+      };
+      // A 64 bit size from a corrupt file can overflow
+      let atom_end = match start.checked_add(size) {
+         Some(v) if size >= 8 && v <= end => v,
+         _ => return Err(Mp4ParseError::InvalidAtom),
+      };
+
+      if &header[4..8] == name {
+         return Ok(Some(atom_end));
+      }
+      source.seek(SeekFrom::Start(atom_end))?;
+   }
+}
+
+// Iterates over the atoms in an in-memory buffer, yielding their names and contents
+struct Atoms<'a> {
+   rest: &'a [u8],
+}
+
+impl<'a> Atoms<'a> {
+   fn new(bytes: &'a [u8]) -> Atoms<'a> {
+      Atoms { rest: bytes }
+   }
+}
+
+impl<'a> Iterator for Atoms<'a> {
+   type Item = ([u8; 4], Result<&'a [u8], Mp4ParseError>);
+
+   fn next(&mut self) -> Option<([u8; 4], Result<&'a [u8], Mp4ParseError>)> {
+      if self.rest.len() < 8 {
+         return None;
+      }
+      let mut name = [0u8; 4];
+      name.copy_from_slice(&self.rest[4..8]);
+      let size = BigEndian::read_u32(&self.rest[0..4]) as usize;
+      let size = if size == 0 { self.rest.len() } else { size };
+
+      if size < 8 || size > self.rest.len() {
+         self.rest = &[];
+         return Some((name, Err(Mp4ParseError::InvalidAtom)));
+      }
+      let content = &self.rest[8..size];
+      self.rest = &self.rest[size..];
+      Some((name, Ok(content)))
+   }
+}
+
+fn parse_item(name: [u8; 4], content: &[u8]) -> Result<Item, Mp4ParseError> {
+   let mut mean = None;
+   let mut freeform_name = None;
+   let mut values = Vec::new();
+
+   for (child_name, child) in Atoms::new(content) {
+      let child = child?;
+      match &child_name {
+         b"data" => values.push(parse_data(child)?),
+         // Both have 4 bytes of version and flags before the string
+         b"mean" => mean = child.get(4..).map(|x| String::from_utf8_lossy(x).into_owned()),
+         b"name" => freeform_name = child.get(4..).map(|x| String::from_utf8_lossy(x).into_owned()),
+         _ => (),
+      }
+   }
+
+   let key = if &name == b"----" {
+      ItemKey::Freeform {
+         mean: mean.unwrap_or_default(),
+         name: freeform_name.unwrap_or_default(),
+      }
+   } else {
+      ItemKey::Atom(name)
+   };
+   Ok(Item { key, values })
+}
+
+// 4 bytes of type, 4 bytes of locale, then the value
+fn parse_data(data: &[u8]) -> Result<Data, Mp4ParseError> {
+   if data.len() < 8 {
+      return Err(Mp4ParseError::InvalidAtom);
+   }
+   let type_indicator = BigEndian::read_u32(&data[0..4]) & 0x00_ff_ff_ff;
+   let value = &data[8..];
+
+   Ok(match type_indicator {
+      TYPE_UTF8 => Data::Text(String::from_utf8_lossy(value).into_owned()),
+      TYPE_UTF16 => {
+         let units: Vec<u16> = value.chunks_exact(2).map(BigEndian::read_u16).collect();
+         Data::Text(String::from_utf16_lossy(&units))
+      }
+      TYPE_JPEG => Data::Jpeg(value.to_vec()),
+      TYPE_PNG => Data::Png(value.to_vec()),
+      TYPE_BMP => Data::Bmp(value.to_vec()),
+      TYPE_SIGNED_INT | TYPE_UNSIGNED_INT if !value.is_empty() && value.len() <= 8 => {
+         let unsigned = value.iter().fold(0u64, |acc, x| acc << 8 | u64::from(*x));
+         let bits = value.len() * 8;
+         let integer = if type_indicator == TYPE_SIGNED_INT && bits < 64 && unsigned >> (bits - 1) == 1 {
+            // Sign extend
+            (unsigned | !0u64 << bits) as i64
+         } else {
+            unsigned as i64
+         };
+         Data::Integer(integer)
+      }
+      _ => Data::Other {
+         type_indicator,
+         data: value.to_vec(),
+      },
+   })
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+
+   #[cfg(test)]
+   fn atom(name: &[u8], content: &[u8]) -> Vec<u8> {
+      let mut atom = Vec::new();
+      atom.extend_from_slice(&(content.len() as u32 + 8).to_be_bytes());
+      atom.extend_from_slice(name);
+      atom.extend_from_slice(content);
+      atom
+   }
+
+   #[test]
+   fn ilst() {
+      let title = atom(b"\xa9nam", &atom(b"data", b"\x00\x00\x00\x01\x00\x00\x00\x00Title"));
+      let track = atom(
+         b"trkn",
+         &atom(b"data", b"\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x03\x00\x0c"),
+      );
+      let cpil = atom(b"cpil", &atom(b"data", b"\x00\x00\x00\x15\x00\x00\x00\x00\x01"));
+      let mut freeform = atom(b"mean", b"\x00\x00\x00\x00com.apple.iTunes");
+      freeform.extend(atom(b"name", b"\x00\x00\x00\x00iTunNORM"));
+      freeform.extend(atom(b"data", b"\x00\x00\x00\x01\x00\x00\x00\x00 00000001"));
+      let ilst = atom(b"ilst", &[title, track, cpil, atom(b"----", &freeform)].concat());
+
+      let mut meta_content = vec![0u8; 4];
+      meta_content.extend(atom(b"hdlr", &[0; 25]));
+      meta_content.extend(ilst);
+      let moov = atom(b"moov", &atom(b"udta", &atom(b"meta", &meta_content)));
+      let mut file = atom(b"ftyp", b"M4A \x00\x00\x00\x00");
+      file.extend(atom(b"mdat", &[0; 100]));
+      file.extend(moov);
+
+      let metadata = read_metadata(&mut io::Cursor::new(file)).unwrap();
+      assert_eq!(metadata.title(), Some("Title"));
+      assert_eq!((metadata.track_number(), metadata.track_total()), (Some(3), Some(12)));
+      assert_eq!(metadata.compilation(), Some(true));
+      assert!(metadata.get_freeform("com.apple.iTunes", "iTunNORM").is_some());
+
+      // A 64 bit size that runs past the end of the address space
+      let mut corrupt = atom(b"ftyp", b"M4A \x00\x00\x00\x00");
+      corrupt.extend_from_slice(b"\x00\x00\x00\x01free\xff\xff\xff\xff\xff\xff\xff\xff");
+      assert!(read_metadata(&mut io::Cursor::new(corrupt)).is_err());
+   }
+}
@@ -0,0 +1,833 @@
+use crate::id3::v24::FrameData;
+use crate::id3::{self, Id3Tag};
+use crate::strip::{self, StripOptions};
+use bitflags::bitflags;
+use byteorder::{BigEndian, ByteOrder};
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::time::Duration;
+
+// How far past the tag we look for the first frame, as some files have junk between the tag and the audio
+const SYNC_SEARCH_LIMIT: u64 = 64 * 1024;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Version {
+   Mpeg1,
+   Mpeg2,
+   Mpeg25,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Layer {
+   Layer1,
+   Layer2,
+   Layer3,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ChannelMode {
+   Stereo,
+   JointStereo,
+   DualChannel,
+   Mono,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BitrateMode {
+   Constant,
+   Variable,
+}
+
+/// The 4 byte header at the start of every MPEG audio frame
+#[derive(Copy, Clone, Debug)]
+pub struct FrameHeader {
+   pub version: Version,
+   pub layer: Layer,
+   /// Whether the header is followed by a CRC
+   pub protected: bool,
+   /// In kbit/s
+   pub bitrate: u32,
+   /// In Hz
+   pub sample_rate: u32,
+   pub padding: bool,
+   pub channel_mode: ChannelMode,
+}
+
+impl FrameHeader {
+   /// Returns `None` if the bytes aren't a valid frame header.
+   /// Free format streams (bitrate index 0) are not supported.
+   pub fn parse(bytes: [u8; 4]) -> Option<FrameHeader> {
+      let header = BigEndian::read_u32(&bytes);
+      if header & 0xff_e0_00_00 != 0xff_e0_00_00 {
+         return None;
+      }
+
+      let version = match (header >> 19) & 0b11 {
+         0b00 => Version::Mpeg25,
+         0b10 => Version::Mpeg2,
+         0b11 => Version::Mpeg1,
+         _ => return None,
+      };
+      let layer = match (header >> 17) & 0b11 {
+         0b01 => Layer::Layer3,
+         0b10 => Layer::Layer2,
+         0b11 => Layer::Layer1,
+         _ => return None,
+      };
+
+      let bitrate_index = ((header >> 12) & 0b1111) as usize;
+      if bitrate_index == 0 || bitrate_index == 0b1111 {
+         return None;
+      }
+      let bitrates = match (version, layer) {
+         (Version::Mpeg1, Layer::Layer1) => &V1_L1_BITRATES,
+         (Version::Mpeg1, Layer::Layer2) => &V1_L2_BITRATES,
+         (Version::Mpeg1, Layer::Layer3) => &V1_L3_BITRATES,
+         (_, Layer::Layer1) => &V2_L1_BITRATES,
+         (_, _) => &V2_L2_L3_BITRATES,
+      };
+
+      let sample_rate_index = ((header >> 10) & 0b11) as usize;
+      if sample_rate_index == 0b11 {
+         return None;
+      }
+      let sample_rate = match version {
+         Version::Mpeg1 => [44100, 48000, 32000][sample_rate_index],
+         Version::Mpeg2 => [22050, 24000, 16000][sample_rate_index],
+         Version::Mpeg25 => [11025, 12000, 8000][sample_rate_index],
+      };
+
+      let channel_mode = match (header >> 6) & 0b11 {
+         0b00 => ChannelMode::Stereo,
+         0b01 => ChannelMode::JointStereo,
+         0b10 => ChannelMode::DualChannel,
+         _ => ChannelMode::Mono,
+      };
+
+      Some(FrameHeader {
+         version,
+         layer,
+         protected: header & 0x00_01_00_00 == 0,
+         bitrate: bitrates[bitrate_index],
+         sample_rate,
+         padding: header & 0x00_00_02_00 != 0,
+         channel_mode,
+      })
+   }
+
+   /// Size of the whole frame in bytes, including this header
+   pub fn frame_len(&self) -> u32 {
+      let padding = if self.padding { 1 } else { 0 };
+      match self.layer {
+         Layer::Layer1 => (12 * self.bitrate * 1000 / self.sample_rate + padding) * 4,
+         _ => self.samples_per_frame() / 8 * self.bitrate * 1000 / self.sample_rate + padding,
+      }
+   }
+
+   pub fn samples_per_frame(&self) -> u32 {
+      match (self.version, self.layer) {
+         (_, Layer::Layer1) => 384,
+         (_, Layer::Layer2) => 1152,
+         (Version::Mpeg1, Layer::Layer3) => 1152,
+         (_, Layer::Layer3) => 576,
+      }
+   }
+
+   // Where the Xing/Info header would be, relative to the start of the frame.
+   // It sits after the side information, which depends on the version and channel count.
+   fn xing_offset(&self) -> usize {
+      match (self.version, self.channel_mode) {
+         (Version::Mpeg1, ChannelMode::Mono) => 4 + 17,
+         (Version::Mpeg1, _) => 4 + 32,
+         (_, ChannelMode::Mono) => 4 + 9,
+         (_, _) => 4 + 17,
+      }
+   }
+}
+
+const V1_L1_BITRATES: [u32; 15] = [0, 32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448];
+const V1_L2_BITRATES: [u32; 15] = [0, 32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384];
+const V1_L3_BITRATES: [u32; 15] = [0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320];
+const V2_L1_BITRATES: [u32; 15] = [0, 32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256];
+const V2_L2_L3_BITRATES: [u32; 15] = [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160];
+
+/// The Xing header that VBR encoders place in the first frame.
+/// LAME writes the same header for CBR files, but with an "Info" tag in place of "Xing".
+#[derive(Clone, Debug)]
+pub struct XingHeader {
+   /// False if this is an "Info" header
+   pub vbr: bool,
+   pub frames: Option<u32>,
+   pub bytes: Option<u32>,
+   /// Seek points, as fractions (out of 256) of the stream size at each percent of the duration
+   pub toc: Option<Vec<u8>>,
+   /// 0 (best) to 100 (worst)
+   pub quality: Option<u32>,
+   pub lame: Option<LameHeader>,
+}
+
+bitflags! {
+   struct XingFlags: u32 {
+      const FRAMES = 0b0001;
+      const BYTES = 0b0010;
+      const TOC = 0b0100;
+      const QUALITY = 0b1000;
+   }
+}
+
+impl XingHeader {
+   // `frame` starts at the frame header
+   fn parse(frame: &[u8], header: &FrameHeader) -> Option<XingHeader> {
+      let mut rest = frame.get(header.xing_offset()..)?;
+      let vbr = match rest.get(0..4)? {
+         b"Xing" => true,
+         b"Info" => false,
+         _ => return None,
+      };
+      let flags = XingFlags::from_bits_truncate(BigEndian::read_u32(rest.get(4..8)?));
+      rest = &rest[8..];
+
+      let frames = read_xing_field(&mut rest, flags.contains(XingFlags::FRAMES))?;
+      let bytes = read_xing_field(&mut rest, flags.contains(XingFlags::BYTES))?;
+      let toc = if flags.contains(XingFlags::TOC) {
+         let toc = rest.get(0..100)?.to_vec();
+         rest = &rest[100..];
+         Some(toc)
+      } else {
+         None
+      };
+      let quality = read_xing_field(&mut rest, flags.contains(XingFlags::QUALITY))?;
+
+      Some(XingHeader {
+         vbr,
+         frames,
+         bytes,
+         toc,
+         quality,
+         lame: LameHeader::parse(rest),
+      })
+   }
+}
+
+// Fields that aren't flagged as present take up no space
+fn read_xing_field(rest: &mut &[u8], present: bool) -> Option<Option<u32>> {
+   if !present {
+      return Some(None);
+   }
+   let value = BigEndian::read_u32(rest.get(0..4)?);
+   *rest = &rest[4..];
+   Some(Some(value))
+}
+
+/// The extension LAME (and FFmpeg, which mimics it) writes after the Xing/Info header
+#[derive(Clone, Debug)]
+pub struct LameHeader {
+   /// e.g. "LAME3.99r"
+   pub encoder: String,
+   pub revision: u8,
+   pub vbr_method: u8,
+   /// In Hz
+   pub lowpass: Option<u32>,
+   /// 1.0 is full scale
+   pub peak: Option<f32>,
+   /// In dB
+   pub track_gain: Option<f32>,
+   /// In dB
+   pub album_gain: Option<f32>,
+   /// Samples added to the start of the stream by the encoder
+   pub encoder_delay: u16,
+   /// Samples added to the end of the stream by the encoder
+   pub padding: u16,
+   /// Size of the stream in bytes, from the Xing frame to the last audio frame
+   pub music_length: u32,
+}
+
+impl LameHeader {
+   // `bytes` starts right after the Xing/Info header
+   fn parse(bytes: &[u8]) -> Option<LameHeader> {
+      let lame = bytes.get(0..36)?;
+      match &lame[0..4] {
+         b"LAME" | b"Lavf" | b"Lavc" => (),
+         _ => return None,
+      }
+
+      let mut track_gain = None;
+      let mut album_gain = None;
+      for field in [&lame[15..17], &lame[17..19]].iter() {
+         match parse_lame_gain(BigEndian::read_u16(field)) {
+            Some((LameGainKind::Track, gain)) => track_gain = Some(gain),
+            Some((LameGainKind::Album, gain)) => album_gain = Some(gain),
+            None => (),
+         }
+      }
+
+      // Fixed point, where 1.0 is 2^23
+      let peak = BigEndian::read_u32(&lame[11..15]) as f32 / (1 << 23) as f32;
+      let delay_and_padding = BigEndian::read_u24(&lame[21..24]);
+
+      Some(LameHeader {
+         encoder: String::from_utf8_lossy(&lame[0..9]).trim_end_matches('\0').to_string(),
+         revision: lame[9] >> 4,
+         vbr_method: lame[9] & 0x0f,
+         lowpass: if lame[10] == 0 {
+            None
+         } else {
+            Some(u32::from(lame[10]) * 100)
+         },
+         peak: if peak > 0.0 { Some(peak) } else { None },
+         track_gain,
+         album_gain,
+         encoder_delay: (delay_and_padding >> 12) as u16,
+         padding: (delay_and_padding & 0xfff) as u16,
+         music_length: BigEndian::read_u32(&lame[28..32]),
+      })
+   }
+}
+
+enum LameGainKind {
+   Track,
+   Album,
+}
+
+// 3 bits of name code, 3 bits of originator, a sign bit, and the gain in tenths of a dB
+fn parse_lame_gain(field: u16) -> Option<(LameGainKind, f32)> {
+   let kind = match field >> 13 {
+      1 => LameGainKind::Track,
+      2 => LameGainKind::Album,
+      _ => return None,
+   };
+   let originator = (field >> 10) & 0b111;
+   if originator == 0 {
+      // Not set
+      return None;
+   }
+   let magnitude = f32::from(field & 0x1ff) / 10.0;
+   let gain = if field & 0x200 != 0 { -magnitude } else { magnitude };
+   Some((kind, gain))
+}
+
+/// The header that the Fraunhofer encoder places in the first frame of VBR files
+#[derive(Clone, Debug)]
+pub struct VbriHeader {
+   pub version: u16,
+   /// Encoder delay in samples
+   pub delay: u16,
+   pub quality: u16,
+   pub bytes: u32,
+   pub frames: u32,
+}
+
+impl VbriHeader {
+   // `frame` starts at the frame header. Unlike Xing, VBRI is always 32 bytes past the frame header.
+   fn parse(frame: &[u8]) -> Option<VbriHeader> {
+      let vbri = frame.get(36..54)?;
+      if &vbri[0..4] != b"VBRI" {
+         return None;
+      }
+      Some(VbriHeader {
+         version: BigEndian::read_u16(&vbri[4..6]),
+         delay: BigEndian::read_u16(&vbri[6..8]),
+         quality: BigEndian::read_u16(&vbri[8..10]),
+         bytes: BigEndian::read_u32(&vbri[10..14]),
+         frames: BigEndian::read_u32(&vbri[14..18]),
+      })
+   }
+}
+
+#[derive(Clone, Debug)]
+pub struct AudioProperties {
+   pub version: Version,
+   pub layer: Layer,
+   /// Bitrate of the first frame in kbit/s
+   pub bitrate: u32,
+   /// In Hz
+   pub sample_rate: u32,
+   pub channel_mode: ChannelMode,
+   pub bitrate_mode: BitrateMode,
+   /// Position of the first audio frame in the file
+   pub first_frame_offset: u64,
+   /// Size of the audio, from the first frame to the end of the file (or the ID3v1 tag)
+   pub audio_len: u64,
+   pub samples_per_frame: u32,
+   pub xing: Option<XingHeader>,
+   pub vbri: Option<VbriHeader>,
+}
+
+impl AudioProperties {
+   /// Exact if the stream has a Xing or VBRI header with a frame count, otherwise estimated from the bitrate (see
+   /// `duration` for one that takes TLEN into account as well)
+   pub fn duration(&self) -> Duration {
+      self.frame_count_duration().unwrap_or_else(|| self.bitrate_duration())
+   }
+
+   /// From the frame count of a Xing or VBRI header, if there is one
+   pub fn frame_count_duration(&self) -> Option<Duration> {
+      let frames = match (&self.xing, &self.vbri) {
+         (
+            Some(XingHeader {
+               frames: Some(frames), ..
+            }),
+            _,
+         ) => *frames,
+         (_, Some(vbri)) => vbri.frames,
+         _ => return None,
+      };
+      let samples = u64::from(frames) * u64::from(self.samples_per_frame);
+      Some(rate_duration(samples, u64::from(self.sample_rate)))
+   }
+
+   /// The size of the audio over the bitrate of the first frame, which is only right if every frame has that bitrate
+   pub fn bitrate_duration(&self) -> Duration {
+      rate_duration(self.audio_len * 8, u64::from(self.bitrate) * 1000)
+   }
+}
+
+// How long `count` things take at `rate` a second, in whole seconds and then the rest, so large counts don't overflow
+fn rate_duration(count: u64, rate: u64) -> Duration {
+   Duration::from_secs(count / rate) + Duration::from_nanos(count % rate * 1_000_000_000 / rate)
+}
+
+/// Where a duration came from, from the most to the least trustworthy
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DurationSource {
+   /// The frame count of a Xing or VBRI header, which is exact
+   FrameCount,
+   /// The TLEN frame of the tag, which is as right as whatever wrote it
+   Tlen,
+   /// The size of the audio over the bitrate of the first frame, which is exact for CBR streams and can be far off for
+   /// VBR streams without a header
+   Bitrate,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SourcedDuration {
+   pub duration: Duration,
+   pub source: DurationSource,
+}
+
+/// The duration of an MPEG audio file, from the best source it has, in order: the frame count of a Xing or VBRI
+/// header, the TLEN frame of the tag, and the size of the audio over its bitrate. Returns `None` if there are no
+/// audio properties (e.g. no frames were found) and no TLEN frame.
+pub fn duration(properties: Option<&AudioProperties>, tag: Option<&Id3Tag>) -> Option<SourcedDuration> {
+   let sourced = |duration, source| SourcedDuration { duration, source };
+   let tlen = tag.and_then(tlen);
+   properties
+      .and_then(|x| x.frame_count_duration())
+      .map(|x| sourced(x, DurationSource::FrameCount))
+      .or_else(|| tlen.map(|x| sourced(x, DurationSource::Tlen)))
+      .or_else(|| properties.map(|x| sourced(x.bitrate_duration(), DurationSource::Bitrate)))
+}
+
+// The length the tag's TLEN frame gives, if it has one
+pub(crate) fn tlen(tag: &Id3Tag) -> Option<Duration> {
+   tag.frames.iter().find_map(|x| match &x.data {
+      FrameData::TLEN(x) => x.first().map(|ms| Duration::from_millis(*ms)),
+      _ => None,
+   })
+}
+
+#[derive(Debug)]
+pub enum MpegParseError {
+   NoAudio,
+   Io(io::Error),
+}
+
+impl fmt::Display for MpegParseError {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      match self {
+         MpegParseError::NoAudio => write!(f, "no MPEG audio frames found"),
+         MpegParseError::Io(e) => write!(f, "I/O error: {}", e),
+      }
+   }
+}
+
+impl Error for MpegParseError {
+   fn source(&self) -> Option<&(dyn Error + 'static)> {
+      match self {
+         MpegParseError::Io(e) => Some(e),
+         _ => None,
+      }
+   }
+}
+
+impl From<io::Error> for MpegParseError {
+   fn from(e: io::Error) -> MpegParseError {
+      MpegParseError::Io(e)
+   }
+}
+
+/// Skips over the ID3v2 tag at the start of the source (if any), then syncs to the first audio frame
+pub fn read_properties<S: Read + Seek>(source: &mut S) -> Result<AudioProperties, MpegParseError> {
+   let mut tag_header = Vec::new();
+   source.seek(SeekFrom::Start(0))?;
+   source.take(10).read_to_end(&mut tag_header)?;
+   let audio_start = id3::tag_len(&tag_header).unwrap_or(0);
+
+   source.seek(SeekFrom::Start(audio_start))?;
+   let mut audio = Vec::new();
+   source.take(SYNC_SEARCH_LIMIT).read_to_end(&mut audio)?;
+
+   let (offset, header) = find_first_frame(&audio).ok_or(MpegParseError::NoAudio)?;
+   let first_frame = &audio[offset..];
+   let xing = XingHeader::parse(first_frame, &header);
+   let vbri = VbriHeader::parse(first_frame);
+
+   let first_frame_offset = audio_start + offset as u64;
+   let mut audio_end = source.seek(SeekFrom::End(0))?;
+   if audio_end >= first_frame_offset + 128 {
+      let mut id3v1_header = [0u8; 3];
+      source.seek(SeekFrom::End(-128))?;
+      source.read_exact(&mut id3v1_header)?;
+      if &id3v1_header == b"TAG" {
+         audio_end -= 128;
+      }
+   }
+
+   Ok(AudioProperties {
+      version: header.version,
+      layer: header.layer,
+      bitrate: header.bitrate,
+      sample_rate: header.sample_rate,
+      channel_mode: header.channel_mode,
+      bitrate_mode: bitrate_mode(first_frame, &header, xing.as_ref(), vbri.as_ref()),
+      first_frame_offset,
+      audio_len: audio_end.saturating_sub(first_frame_offset),
+      samples_per_frame: header.samples_per_frame(),
+      xing,
+      vbri,
+   })
+}
+
+/// Something wrong with the frames of a stream (see `verify`)
+#[derive(Clone, Debug, PartialEq)]
+pub enum StreamProblem {
+   /// Bytes between two frames that aren't a frame, where the stream lost sync. Offsets are from the start of the file.
+   LostSync { offset: u64, len: u64 },
+   /// Bytes after the last frame that aren't a frame or a tag
+   TrailingData { offset: u64, len: u64 },
+   /// The last frame is cut short, as it is when a download stops early
+   TruncatedFrame { offset: u64, frame_len: u32, len: u64 },
+   /// The Xing or VBRI header counts a different number of frames than there are
+   FrameCountMismatch { header: u32, actual: u64 },
+}
+
+impl fmt::Display for StreamProblem {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      match self {
+         StreamProblem::LostSync { offset, len } => write!(f, "lost sync at {} for {} bytes", offset, len),
+         StreamProblem::TrailingData { offset, len } => {
+            write!(f, "{} bytes after the last frame at {} aren't audio", len, offset)
+         }
+         StreamProblem::TruncatedFrame { offset, frame_len, len } => write!(
+            f,
+            "the last frame at {} is truncated to {} of its {} bytes",
+            offset, len, frame_len
+         ),
+         StreamProblem::FrameCountMismatch { header, actual } => {
+            write!(f, "the VBR header counts {} frames, but there are {}", header, actual)
+         }
+      }
+   }
+}
+
+/// What `verify` found in a stream
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StreamCheck {
+   /// Whole frames, the Xing or VBRI frame included
+   pub frames: u64,
+   pub problems: Vec<StreamProblem>,
+}
+
+/// Walks every frame of the stream, from the first one after the ID3v2 tag to the tags at the end, looking for the
+/// damage that corrupt and truncated files have: bytes that aren't frames, a last frame that's cut short, and a frame
+/// count in the Xing or VBRI header that doesn't match the stream. Whether that header counts its own frame differs
+/// between encoders, so either count matches.
+pub fn verify<S: Read + Seek>(source: &mut S) -> Result<StreamCheck, MpegParseError> {
+   let layout = strip::find_tags(source, &StripOptions { ape: true })?;
+   let properties = read_properties(source)?;
+   let start = properties.first_frame_offset;
+   let mut audio = Vec::new();
+   source.seek(SeekFrom::Start(start))?;
+   source
+      .take(layout.audio_end.saturating_sub(start))
+      .read_to_end(&mut audio)?;
+
+   let mut check = StreamCheck::default();
+   let first = read_header_at(&audio, 0).ok_or(MpegParseError::NoAudio)?;
+   let mut pos = 0;
+   while pos < audio.len() {
+      let header = read_header_at(&audio, pos)
+         .filter(|x| x.version == first.version && x.layer == first.layer && x.sample_rate == first.sample_rate);
+      if let Some(header) = header {
+         let frame_len = header.frame_len();
+         let left = audio.len() - pos;
+         if frame_len as usize > left {
+            check.problems.push(StreamProblem::TruncatedFrame {
+               offset: start + pos as u64,
+               frame_len,
+               len: left as u64,
+            });
+            break;
+         }
+         check.frames += 1;
+         pos += frame_len as usize;
+         continue;
+      }
+
+      // The search starts past `pos`, as there may be a good frame here that doesn't match the stream
+      let offset = start + pos as u64;
+      match find_first_frame(&audio[pos + 1..]) {
+         Some((skipped, _)) => {
+            check.problems.push(StreamProblem::LostSync {
+               offset,
+               len: skipped as u64 + 1,
+            });
+            pos += skipped + 1;
+         }
+         None => {
+            check.problems.push(StreamProblem::TrailingData {
+               offset,
+               len: (audio.len() - pos) as u64,
+            });
+            break;
+         }
+      }
+   }
+
+   let counted = match (&properties.xing, &properties.vbri) {
+      (Some(XingHeader { frames: Some(x), .. }), _) => Some(*x),
+      (_, Some(vbri)) => Some(vbri.frames),
+      _ => None,
+   };
+   if let Some(counted) = counted {
+      let counted = u64::from(counted);
+      if counted != check.frames && counted + 1 != check.frames {
+         check.problems.push(StreamProblem::FrameCountMismatch {
+            header: counted as u32,
+            actual: check.frames,
+         });
+      }
+   }
+   Ok(check)
+}
+
+fn read_header_at(audio: &[u8], pos: usize) -> Option<FrameHeader> {
+   let mut bytes = [0u8; 4];
+   bytes.copy_from_slice(audio.get(pos..pos.checked_add(4)?)?);
+   FrameHeader::parse(bytes)
+}
+
+// A lone sync word is easy to hit by chance, so we also want the following frame to line up
+fn find_first_frame(audio: &[u8]) -> Option<(usize, FrameHeader)> {
+   for pos in 0..audio.len() {
+      if audio[pos] != 0xff {
+         continue;
+      }
+      let header = match read_header_at(audio, pos) {
+         Some(v) => v,
+         None => continue,
+      };
+      let next_pos = pos + header.frame_len() as usize;
+      if next_pos + 4 > audio.len() {
+         // We can't check, so take what we've got
+         return Some((pos, header));
+      }
+      if let Some(next) = read_header_at(audio, next_pos) {
+         if next.version == header.version && next.layer == header.layer && next.sample_rate == header.sample_rate {
+            return Some((pos, header));
+         }
+      }
+   }
+   None
+}
+
+// `audio` starts at the first frame
+fn bitrate_mode(
+   audio: &[u8],
+   first: &FrameHeader,
+   xing: Option<&XingHeader>,
+   vbri: Option<&VbriHeader>,
+) -> BitrateMode {
+   // Encoders mark VBR streams with a header in the first frame
+   if let Some(xing) = xing {
+      return if xing.vbr {
+         BitrateMode::Variable
+      } else {
+         BitrateMode::Constant
+      };
+   }
+   if vbri.is_some() {
+      return BitrateMode::Variable;
+   }
+
+   // Otherwise, see if the bitrate changes in the frames we have
+   let mut pos = 0;
+   while let Some(header) = read_header_at(audio, pos) {
+      if header.bitrate != first.bitrate {
+         return BitrateMode::Variable;
+      }
+      pos += header.frame_len() as usize;
+   }
+   BitrateMode::Constant
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+
+   #[test]
+   fn frame_header() {
+      // MPEG-1 Layer III, 128kbit/s, 44.1kHz, joint stereo
+      let header = FrameHeader::parse([0xff, 0xfb, 0x90, 0x44]).unwrap();
+      assert_eq!(header.version, Version::Mpeg1);
+      assert_eq!(header.layer, Layer::Layer3);
+      assert_eq!(header.bitrate, 128);
+      assert_eq!(header.sample_rate, 44100);
+      assert_eq!(header.channel_mode, ChannelMode::JointStereo);
+      assert_eq!(header.frame_len(), 417);
+   }
+
+   #[test]
+   fn properties_after_tag() {
+      let mut file = Vec::from(&b"ID3\x04\x00\x00\x00\x00\x00\x05\0\0\0\0\0"[..]);
+      for _ in 0..3 {
+         let frame_start = file.len();
+         file.extend_from_slice(&[0xff, 0xfb, 0x90, 0x44]);
+         file.resize(frame_start + 417, 0);
+      }
+
+      let properties = read_properties(&mut io::Cursor::new(file)).unwrap();
+      assert_eq!(properties.first_frame_offset, 15);
+      assert_eq!(properties.bitrate_mode, BitrateMode::Constant);
+      // 3 frames of 417 bytes at 128kbit/s
+      assert_eq!(properties.duration().as_micros(), 78_187);
+      let bitrate = duration(Some(&properties), None).unwrap();
+      assert_eq!(bitrate.source, DurationSource::Bitrate);
+
+      let tag = id3::TagBuilder::new()
+         .frame(FrameData::from_text(*b"TLEN", "80").unwrap())
+         .build()
+         .unwrap();
+      let tlen = SourcedDuration {
+         duration: Duration::from_millis(80),
+         source: DurationSource::Tlen,
+      };
+      assert_eq!(duration(Some(&properties), Some(&tag)), Some(tlen));
+      assert_eq!(duration(None, Some(&tag)), Some(tlen));
+      assert_eq!(duration(None, None), None);
+   }
+
+   #[test]
+   fn xing_duration() {
+      let mut frame = vec![0xff, 0xfb, 0x90, 0x44];
+      frame.resize(36, 0);
+      frame.extend_from_slice(b"Xing\x00\x00\x00\x01\x00\x00\x01\x00");
+      frame.resize(417, 0);
+
+      let header = FrameHeader::parse([0xff, 0xfb, 0x90, 0x44]).unwrap();
+      let xing = XingHeader::parse(&frame, &header).unwrap();
+      assert!(xing.vbr);
+      assert_eq!(xing.frames, Some(256));
+      assert!(xing.lame.is_none());
+   }
+
+   #[test]
+   fn largest_frame_count() {
+      let mut file = vec![0xff, 0xfb, 0x90, 0x44];
+      file.resize(36, 0);
+      file.extend_from_slice(b"Xing\x00\x00\x00\x01\xff\xff\xff\xff");
+      file.resize(417, 0);
+
+      let properties = read_properties(&mut io::Cursor::new(file)).unwrap();
+      // u32::MAX frames of 1152 samples at 44.1kHz
+      assert_eq!(
+         properties.frame_count_duration(),
+         Some(Duration::new(112_195_064, 32_653_061))
+      );
+   }
+
+   #[test]
+   fn stream_problems() {
+      let frame = |xing: bool| {
+         let mut frame = vec![0xff, 0xfb, 0x90, 0x44];
+         if xing {
+            frame.resize(36, 0);
+            frame.extend_from_slice(b"Xing\x00\x00\x00\x01\x00\x00\x01\x00");
+         }
+         frame.resize(417, 0);
+         frame
+      };
+      let mut file = Vec::from(&b"ID3\x04\x00\x00\x00\x00\x00\x05\0\0\0\0\0"[..]);
+      file.extend(frame(true));
+      file.extend(frame(false));
+      assert_eq!(verify(&mut io::Cursor::new(&file)).unwrap().frames, 2);
+
+      file.extend_from_slice(&[1; 10]);
+      file.extend(frame(false));
+      file.extend(frame(false));
+      file.extend_from_slice(&frame(false)[..100]);
+      let check = verify(&mut io::Cursor::new(&file)).unwrap();
+      assert_eq!(check.frames, 4);
+      assert_eq!(
+         check.problems,
+         [
+            StreamProblem::LostSync { offset: 849, len: 10 },
+            StreamProblem::TruncatedFrame {
+               offset: 1693,
+               frame_len: 417,
+               len: 100
+            },
+            StreamProblem::FrameCountMismatch { header: 256, actual: 4 },
+         ]
+      );
+   }
+
+   #[test]
+   fn sample_rate_change() {
+      let frame = |header: [u8; 4], len: usize| {
+         let mut frame = header.to_vec();
+         frame.resize(len, 0);
+         frame
+      };
+      let mut file = Vec::new();
+      for _ in 0..3 {
+         file.extend(frame([0xff, 0xfb, 0x90, 0x44], 417));
+      }
+      // 48kHz frames can't follow 44.1kHz ones
+      file.extend(frame([0xff, 0xfb, 0x94, 0x44], 384));
+      file.extend(frame([0xff, 0xfb, 0x94, 0x44], 384));
+      let check = verify(&mut io::Cursor::new(&file)).unwrap();
+      assert_eq!(check.frames, 3);
+      assert_eq!(
+         check.problems,
+         [
+            StreamProblem::LostSync { offset: 1251, len: 384 },
+            StreamProblem::TrailingData { offset: 1635, len: 384 },
+         ]
+      );
+   }
+
+   #[test]
+   fn lame_header() {
+      let mut lame = Vec::from(&b"LAME3.99r"[..]);
+      lame.extend_from_slice(&[0x24, 0xc4]);
+      // A peak of 0.5, as 2^22
+      lame.extend_from_slice(&[0x00, 0x40, 0x00, 0x00]);
+      // Track gain of -6.5dB, set by the user
+      lame.extend_from_slice(&[0b0010_1110, 0x41, 0, 0]);
+      lame.extend_from_slice(&[0, 0]);
+      // 576 samples of delay, 1104 of padding
+      lame.extend_from_slice(&[0x24, 0x04, 0x50]);
+      lame.resize(36, 0);
+
+      let header = LameHeader::parse(&lame).unwrap();
+      assert_eq!(header.encoder, "LAME3.99r");
+      assert_eq!((header.revision, header.vbr_method), (2, 4));
+      assert_eq!(header.lowpass, Some(19600));
+      assert_eq!(header.peak, Some(0.5));
+      assert_eq!(header.track_gain, Some(-6.5));
+      assert_eq!(header.album_gain, None);
+      assert_eq!((header.encoder_delay, header.padding), (576, 1104));
+   }
+}
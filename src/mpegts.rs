@@ -0,0 +1,275 @@
+use crate::id3::{self, Id3Tag, TagParseError};
+use byteorder::{BigEndian, ByteOrder};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read};
+use std::time::Duration;
+
+pub const PACKET_LEN: usize = 188;
+
+const SYNC_BYTE: u8 = 0x47;
+const PAT_PID: u16 = 0;
+// Metadata carried in PES packets, which is how HLS carries timed ID3
+const STREAM_TYPE_METADATA: u8 = 0x15;
+
+/// An ID3 tag from a metadata stream of a transport stream, with when it applies
+#[derive(Clone, Debug)]
+pub struct TimedTag {
+   /// The stream it came from
+   pub pid: u16,
+   /// The presentation timestamp of the PES packet that held it, in 90kHz ticks
+   pub pts: Option<u64>,
+   pub tag: Id3Tag,
+}
+
+impl TimedTag {
+   /// The presentation timestamp as a time
+   pub fn time(&self) -> Option<Duration> {
+      self
+         .pts
+         .map(|x| Duration::from_secs(x / 90_000) + Duration::from_nanos(x % 90_000 * 1_000_000_000 / 90_000))
+   }
+}
+
+#[derive(Debug)]
+pub enum TsParseError {
+   /// The first packet doesn't start with the sync byte
+   NotTs,
+   Io(io::Error),
+}
+
+impl fmt::Display for TsParseError {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      match self {
+         TsParseError::NotTs => write!(f, "not an MPEG transport stream"),
+         TsParseError::Io(e) => write!(f, "I/O error: {}", e),
+      }
+   }
+}
+
+impl Error for TsParseError {
+   fn source(&self) -> Option<&(dyn Error + 'static)> {
+      match self {
+         TsParseError::Io(e) => Some(e),
+         _ => None,
+      }
+   }
+}
+
+impl From<io::Error> for TsParseError {
+   fn from(e: io::Error) -> TsParseError {
+      TsParseError::Io(e)
+   }
+}
+
+/// The tags that failed to parse, with the PID of the stream each was on
+pub type FailedTags = Vec<(u16, TagParseError)>;
+
+/// Reads a whole transport stream, e.g. an HLS segment, and returns the ID3 tags of its metadata streams in the order
+/// they come (see `timed_tags`)
+pub fn read_timed_tags<S: Read>(source: &mut S) -> Result<(Vec<TimedTag>, FailedTags), TsParseError> {
+   let mut bytes = Vec::new();
+   source.read_to_end(&mut bytes)?;
+   timed_tags(&bytes)
+}
+
+/// Finds the metadata streams (stream type 0x15) through the program tables, and reads the ID3 tags out of their
+/// PES packets. Tables are expected to fit in a packet, as they always do in practice. Tags that fail to parse are
+/// returned apart from the rest.
+pub fn timed_tags(bytes: &[u8]) -> Result<(Vec<TimedTag>, FailedTags), TsParseError> {
+   if bytes.first() != Some(&SYNC_BYTE) {
+      return Err(TsParseError::NotTs);
+   }
+   let mut pmt_pids = Vec::new();
+   let mut metadata_pids = Vec::new();
+   // The PES packet being put together on each metadata stream
+   let mut pes: HashMap<u16, Vec<u8>> = HashMap::new();
+   let mut tags = Vec::new();
+   let mut failed = Vec::new();
+
+   let mut pos = 0;
+   while pos + PACKET_LEN <= bytes.len() {
+      if bytes[pos] != SYNC_BYTE {
+         // Lost sync, so look for the next packet
+         pos += 1;
+         continue;
+      }
+      let packet = &bytes[pos..pos + PACKET_LEN];
+      pos += PACKET_LEN;
+
+      let unit_start = packet[1] & 0x40 != 0;
+      let pid = BigEndian::read_u16(&packet[1..3]) & 0x1fff;
+      let payload = match packet[3] >> 4 & 0x03 {
+         0b01 => &packet[4..],
+         0b11 => match packet.get(5 + usize::from(packet[4])..) {
+            Some(v) => v,
+            None => continue,
+         },
+         _ => continue,
+      };
+
+      if pid == PAT_PID && unit_start {
+         pmt_pids = program_map_pids(section(payload));
+      } else if pmt_pids.contains(&pid) && unit_start {
+         for pid in metadata_stream_pids(section(payload)) {
+            if !metadata_pids.contains(&pid) {
+               metadata_pids.push(pid);
+            }
+         }
+      } else if metadata_pids.contains(&pid) {
+         if unit_start {
+            if let Some(packet) = pes.remove(&pid) {
+               read_pes(pid, &packet, &mut tags, &mut failed);
+            }
+            pes.insert(pid, payload.to_vec());
+         } else if let Some(packet) = pes.get_mut(&pid) {
+            packet.extend_from_slice(payload);
+         }
+      }
+   }
+
+   // Whatever is left is complete, as nothing follows it
+   let mut pids: Vec<u16> = pes.keys().cloned().collect();
+   pids.sort();
+   for pid in pids {
+      read_pes(pid, &pes[&pid], &mut tags, &mut failed);
+   }
+   Ok((tags, failed))
+}
+
+// Skips the pointer field in front of a table section
+fn section(payload: &[u8]) -> &[u8] {
+   let start = 1 + usize::from(payload.first().cloned().unwrap_or(0));
+   let section = payload.get(start..).unwrap_or(&[]);
+   if section.len() < 3 {
+      return &[];
+   }
+   let len = 3 + usize::from(BigEndian::read_u16(&section[1..3]) & 0x0fff);
+   &section[..len.min(section.len())]
+}
+
+// The program association table maps program numbers to the PIDs of their program map tables. Program 0 is the
+// network information table instead.
+fn program_map_pids(section: &[u8]) -> Vec<u16> {
+   if section.first() != Some(&0x00) || section.len() < 12 {
+      return Vec::new();
+   }
+   // The CRC at the end isn't checked
+   section[8..section.len() - 4]
+      .chunks_exact(4)
+      .filter(|x| BigEndian::read_u16(&x[0..2]) != 0)
+      .map(|x| BigEndian::read_u16(&x[2..4]) & 0x1fff)
+      .collect()
+}
+
+fn metadata_stream_pids(section: &[u8]) -> Vec<u16> {
+   if section.first() != Some(&0x02) || section.len() < 16 {
+      return Vec::new();
+   }
+   let end = section.len() - 4;
+   let mut pos = 12 + usize::from(BigEndian::read_u16(&section[10..12]) & 0x0fff);
+   let mut pids = Vec::new();
+   while pos + 5 <= end {
+      let stream = &section[pos..pos + 5];
+      if stream[0] == STREAM_TYPE_METADATA {
+         pids.push(BigEndian::read_u16(&stream[1..3]) & 0x1fff);
+      }
+      pos += 5 + usize::from(BigEndian::read_u16(&stream[3..5]) & 0x0fff);
+   }
+   pids
+}
+
+fn read_pes(pid: u16, packet: &[u8], tags: &mut Vec<TimedTag>, failed: &mut FailedTags) {
+   if packet.len() < 9 || &packet[0..3] != b"\x00\x00\x01" {
+      return;
+   }
+   // 0 means the packet runs to the next one
+   let len = usize::from(BigEndian::read_u16(&packet[4..6]));
+   let packet = if len == 0 {
+      packet
+   } else {
+      &packet[..(6 + len).min(packet.len())]
+   };
+   let pts = if packet[7] & 0x80 != 0 && packet.len() >= 14 {
+      Some(read_timestamp(&packet[9..14]))
+   } else {
+      None
+   };
+
+   // Several tags can follow one another
+   let mut rest = packet.get(9 + usize::from(packet[8])..).unwrap_or(&[]);
+   while let Some(tag_len) = id3::tag_len(rest) {
+      let (bytes, after) = rest.split_at((tag_len as usize).min(rest.len()));
+      match Id3Tag::read(&mut &bytes[..], id3::ParseOptions::default()) {
+         Ok(tag) => tags.push(TimedTag { pid, pts, tag }),
+         Err(e) => failed.push((pid, e)),
+      }
+      rest = after;
+   }
+}
+
+// 33 bits, with marker bits after the first 3, 15, and 15
+fn read_timestamp(bytes: &[u8]) -> u64 {
+   u64::from(bytes[0] >> 1 & 0x07) << 30
+      | u64::from(bytes[1]) << 22
+      | u64::from(bytes[2] >> 1) << 15
+      | u64::from(bytes[3]) << 7
+      | u64::from(bytes[4] >> 1)
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+   #[cfg(test)]
+   use crate::tag::Tag;
+
+   // Short payloads are padded out with an adaptation field, as muxers do
+   #[cfg(test)]
+   fn packet(pid: u16, unit_start: bool, payload: &[u8]) -> Vec<u8> {
+      let stuffing = PACKET_LEN - 5 - payload.len();
+      let mut packet = vec![
+         SYNC_BYTE,
+         (pid >> 8) as u8 | if unit_start { 0x40 } else { 0 },
+         pid as u8,
+         0x30,
+      ];
+      packet.push(stuffing as u8);
+      if stuffing > 0 {
+         packet.push(0);
+         packet.resize(5 + stuffing, 0xff);
+      }
+      packet.extend_from_slice(payload);
+      packet
+   }
+
+   #[test]
+   fn pes_tags() {
+      // Program 1's map is on PID 0x1000, and lists an audio stream and a metadata stream on PID 0x102
+      let mut stream = packet(
+         0,
+         true,
+         b"\x00\x00\xb0\x0d\x00\x01\xc1\x00\x00\x00\x01\xf0\x00\x00\x00\x00\x00",
+      );
+      stream.extend(packet(
+         0x1000,
+         true,
+         b"\x00\x02\xb0\x1c\x00\x01\xc1\x00\x00\xe1\x00\xf0\x00\x0f\xe1\x01\xf0\x00\x15\xe1\x02\xf0\x05\x26\x03ID3\x00\x00\x00\x00",
+      ));
+      stream.extend(packet(0x101, true, b"\x00\x00\x01\xc0\x00\x00"));
+
+      let tag = b"ID3\x04\x00\x00\x00\x00\x00\x12TIT2\x00\x00\x00\x08\x00\x00\x03Now on\x00";
+      // PTS 900000, or 10 seconds
+      let mut pes = b"\x00\x00\x01\xbd\x00\x24\x84\x80\x05\x21\x00\x37\x77\x41".to_vec();
+      pes.extend_from_slice(&tag[..20]);
+      stream.extend(packet(0x102, true, &pes));
+      stream.extend(packet(0x102, false, &tag[20..]));
+
+      let (tags, failed) = timed_tags(&stream).unwrap();
+      assert_eq!((tags.len(), failed.len()), (1, 0));
+      assert_eq!((tags[0].pid, tags[0].pts), (0x102, Some(900_000)));
+      assert_eq!(tags[0].time(), Some(Duration::from_secs(10)));
+      assert_eq!(tags[0].tag.title(), Some("Now on"));
+      assert!(timed_tags(b"ID3").is_err());
+   }
+}
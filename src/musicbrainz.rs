@@ -0,0 +1,303 @@
+use crate::asf;
+use crate::id3::v24::FrameData;
+use crate::id3::Id3Tag;
+use crate::mp4;
+use crate::probe::Metadata;
+use crate::vorbis::VorbisComment;
+
+/// The owner of the UFID frame that Picard writes the recording ID to
+pub const UFID_OWNER: &str = "http://musicbrainz.org";
+
+// The mean of the freeform MP4 atoms that Picard writes
+const MP4_MEAN: &str = "com.apple.iTunes";
+
+/// An identifier written by MusicBrainz Picard (and the taggers that follow it)
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Identifier {
+   /// The recording, which Picard calls the track ID everywhere but ID3
+   Recording,
+   /// The track on a particular release
+   ReleaseTrack,
+   /// The release, which Picard calls the album ID
+   Release,
+   ReleaseGroup,
+   /// One per credited artist
+   Artist,
+   /// One per credited album artist
+   AlbumArtist,
+   /// One per work (song) the recording is of
+   Work,
+   /// The disc ID of the CD the file was ripped from
+   Disc,
+   AcoustId,
+   /// The Chromaprint fingerprint, which isn't a UUID
+   AcoustIdFingerprint,
+}
+
+impl Identifier {
+   pub const ALL: [Identifier; 10] = [
+      Identifier::Recording,
+      Identifier::ReleaseTrack,
+      Identifier::Release,
+      Identifier::ReleaseGroup,
+      Identifier::Artist,
+      Identifier::AlbumArtist,
+      Identifier::Work,
+      Identifier::Disc,
+      Identifier::AcoustId,
+      Identifier::AcoustIdFingerprint,
+   ];
+
+   /// The description of the TXXX frame, which is also the name of the MP4 freeform atom. `None` for the recording,
+   /// which is in a UFID frame in ID3 (see `UFID_OWNER`), but is "MusicBrainz Track Id" in MP4.
+   pub fn txxx_description(self) -> Option<&'static str> {
+      Some(match self {
+         Identifier::Recording => return None,
+         Identifier::ReleaseTrack => "MusicBrainz Release Track Id",
+         Identifier::Release => "MusicBrainz Album Id",
+         Identifier::ReleaseGroup => "MusicBrainz Release Group Id",
+         Identifier::Artist => "MusicBrainz Artist Id",
+         Identifier::AlbumArtist => "MusicBrainz Album Artist Id",
+         Identifier::Work => "MusicBrainz Work Id",
+         Identifier::Disc => "MusicBrainz Disc Id",
+         Identifier::AcoustId => "Acoustid Id",
+         Identifier::AcoustIdFingerprint => "Acoustid Fingerprint",
+      })
+   }
+
+   /// The name of the freeform atom (with the mean "com.apple.iTunes")
+   pub fn mp4_name(self) -> &'static str {
+      match self {
+         Identifier::Recording => "MusicBrainz Track Id",
+         x => x.txxx_description().expect("only the recording is left out of TXXX"),
+      }
+   }
+
+   /// The key of the Vorbis comment, as used by FLAC, Ogg Vorbis, and Opus
+   pub fn vorbis_key(self) -> &'static str {
+      match self {
+         Identifier::Recording => "MUSICBRAINZ_TRACKID",
+         Identifier::ReleaseTrack => "MUSICBRAINZ_RELEASETRACKID",
+         Identifier::Release => "MUSICBRAINZ_ALBUMID",
+         Identifier::ReleaseGroup => "MUSICBRAINZ_RELEASEGROUPID",
+         Identifier::Artist => "MUSICBRAINZ_ARTISTID",
+         Identifier::AlbumArtist => "MUSICBRAINZ_ALBUMARTISTID",
+         Identifier::Work => "MUSICBRAINZ_WORKID",
+         Identifier::Disc => "MUSICBRAINZ_DISCID",
+         Identifier::AcoustId => "ACOUSTID_ID",
+         Identifier::AcoustIdFingerprint => "ACOUSTID_FINGERPRINT",
+      }
+   }
+
+   /// The name of the ASF (WMA) attribute
+   pub fn asf_name(self) -> &'static str {
+      match self {
+         Identifier::Recording => "MusicBrainz/Track Id",
+         Identifier::ReleaseTrack => "MusicBrainz/Release Track Id",
+         Identifier::Release => "MusicBrainz/Album Id",
+         Identifier::ReleaseGroup => "MusicBrainz/Release Group Id",
+         Identifier::Artist => "MusicBrainz/Artist Id",
+         Identifier::AlbumArtist => "MusicBrainz/Album Artist Id",
+         Identifier::Work => "MusicBrainz/Work Id",
+         Identifier::Disc => "MusicBrainz/Disc Id",
+         Identifier::AcoustId => "Acoustid/Id",
+         Identifier::AcoustIdFingerprint => "Acoustid/Fingerprint",
+      }
+   }
+
+   fn is_multiple(self) -> bool {
+      match self {
+         Identifier::Artist | Identifier::AlbumArtist | Identifier::Work => true,
+         _ => false,
+      }
+   }
+}
+
+/// Every value of the identifier, in the order they're written. Formats without a convention for MusicBrainz
+/// identifiers (AIFF and WAV files without an ID3 tag) have none.
+pub fn get_all(metadata: &Metadata, id: Identifier) -> Vec<&str> {
+   let values = match metadata {
+      Metadata::Mpeg(tag) => id3_values(tag, id),
+      Metadata::Wav(x) => x.id3.as_ref().map(|x| id3_values(x, id)).unwrap_or_default(),
+      Metadata::Aiff(x) => x.id3.as_ref().map(|x| id3_values(x, id)).unwrap_or_default(),
+      Metadata::Dsd(x) => x.id3.as_ref().map(|x| id3_values(x, id)).unwrap_or_default(),
+      Metadata::Flac(x) => x.comments.as_ref().map(|x| vorbis_values(x, id)).unwrap_or_default(),
+      Metadata::Ogg(x) => vorbis_values(&x.comments, id),
+      Metadata::Mp4(x) => mp4_values(x, id),
+      Metadata::Asf(x) => asf_values(x, id),
+      Metadata::Adts(x) => id3_values(&x.tag, id),
+   };
+   // ID3v2.3 has no way to hold several values in a frame, so Picard joins them with slashes, which UUIDs can't have
+   let values: Vec<&str> = if id.is_multiple() {
+      values.into_iter().flat_map(|x| x.split('/')).collect()
+   } else {
+      values
+   };
+   values.into_iter().map(str::trim).filter(|x| !x.is_empty()).collect()
+}
+
+/// The first value of the identifier
+pub fn get(metadata: &Metadata, id: Identifier) -> Option<&str> {
+   get_all(metadata, id).into_iter().next()
+}
+
+/// All of the identifiers of a file
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MusicBrainzIds {
+   pub recording: Option<String>,
+   pub release_track: Option<String>,
+   pub release: Option<String>,
+   pub release_group: Option<String>,
+   pub artists: Vec<String>,
+   pub album_artists: Vec<String>,
+   pub works: Vec<String>,
+   pub disc: Option<String>,
+   pub acoustid: Option<String>,
+   pub acoustid_fingerprint: Option<String>,
+}
+
+impl MusicBrainzIds {
+   pub fn read(metadata: &Metadata) -> MusicBrainzIds {
+      let one = |id| get(metadata, id).map(str::to_owned);
+      let all = |id| get_all(metadata, id).into_iter().map(str::to_owned).collect();
+      MusicBrainzIds {
+         recording: one(Identifier::Recording),
+         release_track: one(Identifier::ReleaseTrack),
+         release: one(Identifier::Release),
+         release_group: one(Identifier::ReleaseGroup),
+         artists: all(Identifier::Artist),
+         album_artists: all(Identifier::AlbumArtist),
+         works: all(Identifier::Work),
+         disc: one(Identifier::Disc),
+         acoustid: one(Identifier::AcoustId),
+         acoustid_fingerprint: one(Identifier::AcoustIdFingerprint),
+      }
+   }
+
+   /// Whether the file has been tagged from MusicBrainz at all
+   pub fn is_empty(&self) -> bool {
+      *self == MusicBrainzIds::default()
+   }
+}
+
+fn id3_values(tag: &Id3Tag, id: Identifier) -> Vec<&str> {
+   let description = match id.txxx_description() {
+      Some(x) => x,
+      None => return ufid(tag, UFID_OWNER).into_iter().collect(),
+   };
+   tag.frames
+      .iter()
+      .filter_map(|frame| match &frame.data {
+         FrameData::TXXX(txxx) if txxx.description.eq_ignore_ascii_case(description) => Some(&txxx.text),
+         _ => None,
+      })
+      .flatten()
+      .map(|x| x.as_ref())
+      .collect()
+}
+
+/// The identifier of the UFID frame with the given owner, if it's text. We don't decode UFID frames, so they're read
+/// from their raw bytes: the owner, a null, then the identifier.
+pub fn ufid<'a>(tag: &'a Id3Tag, owner: &str) -> Option<&'a str> {
+   tag.frames.iter().find_map(|frame| match &frame.data {
+      FrameData::Unknown(x) if &x.name == b"UFID" => {
+         let null = x.data.iter().position(|b| *b == 0)?;
+         if &x.data[..null] != owner.as_bytes() {
+            return None;
+         }
+         std::str::from_utf8(&x.data[null + 1..]).ok()
+      }
+      _ => None,
+   })
+}
+
+fn vorbis_values(comments: &VorbisComment, id: Identifier) -> Vec<&str> {
+   comments.get_all(id.vorbis_key()).collect()
+}
+
+fn mp4_values(metadata: &mp4::Metadata, id: Identifier) -> Vec<&str> {
+   metadata
+      .get_freeform(MP4_MEAN, id.mp4_name())
+      .map(|item| {
+         item
+            .values
+            .iter()
+            .filter_map(|x| match x {
+               mp4::Data::Text(text) => Some(text.as_str()),
+               // Freeform atoms are often written as binary data that is really UTF-8
+               mp4::Data::Other { data, .. } => std::str::from_utf8(data).ok(),
+               _ => None,
+            })
+            .collect()
+      })
+      .unwrap_or_default()
+}
+
+fn asf_values(metadata: &asf::Metadata, id: Identifier) -> Vec<&str> {
+   metadata
+      .attributes
+      .iter()
+      .filter(|(k, _)| k.eq_ignore_ascii_case(id.asf_name()))
+      .filter_map(|(_, v)| match v {
+         asf::Value::Text(text) => Some(text.as_str()),
+         _ => None,
+      })
+      .collect()
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+
+   #[test]
+   fn read_ids() {
+      use crate::id3::v24::{Frame, Unknown};
+      use std::borrow::Cow;
+
+      let recording = "5c2d1b7f-7e4c-4f45-9ca2-6f3c1c4a6f0e";
+      let artists = "0383dadf-2a4e-4d10-a46a-e9e041da8eb3/69c4cc43-8163-41c5-ac81-30946d27bb69";
+      let mut ufid = UFID_OWNER.as_bytes().to_vec();
+      ufid.push(0);
+      ufid.extend_from_slice(recording.as_bytes());
+      let tag = Id3Tag {
+         frames: vec![
+            Frame::new(FrameData::Unknown(Unknown {
+               name: *b"UFID",
+               data: Cow::Owned(ufid),
+            })),
+            Frame::new(FrameData::user_text("MusicBrainz Artist Id", artists)),
+            Frame::new(FrameData::user_text(
+               "MUSICBRAINZ ALBUM ID",
+               "f5093c06-23e3-404f-aeaa-40f72885ee3a",
+            )),
+         ],
+      };
+      let ids = MusicBrainzIds::read(&Metadata::Mpeg(tag));
+      assert_eq!(ids.recording.as_ref().map(String::as_str), Some(recording));
+      assert_eq!(ids.artists.len(), 2);
+      assert_eq!(
+         ids.release.as_ref().map(String::as_str),
+         Some("f5093c06-23e3-404f-aeaa-40f72885ee3a")
+      );
+      assert_eq!(ids.release_group, None);
+
+      let comments = VorbisComment {
+         vendor: String::new(),
+         comments: vec![
+            ("musicbrainz_trackid".to_owned(), recording.to_owned()),
+            (
+               "ACOUSTID_ID".to_owned(),
+               "e4a5b6c7-1111-2222-3333-444455556666".to_owned(),
+            ),
+         ],
+      };
+      let ogg = Metadata::Ogg(crate::ogg::Metadata {
+         codec: crate::ogg::Codec::Opus,
+         comments,
+         pictures: Vec::new(),
+      });
+      assert_eq!(get(&ogg, Identifier::Recording), Some(recording));
+      assert!(get(&ogg, Identifier::AcoustId).is_some());
+      assert!(get_all(&ogg, Identifier::Artist).is_empty());
+   }
+}
@@ -0,0 +1,379 @@
+use crate::flac::Picture;
+use crate::tag::Tag;
+use crate::vorbis::VorbisComment;
+use byteorder::{ByteOrder, LittleEndian};
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Codec {
+   Vorbis,
+   Opus,
+}
+
+#[derive(Clone, Debug)]
+pub struct Metadata {
+   pub codec: Codec,
+   pub comments: VorbisComment,
+   /// Decoded from METADATA_BLOCK_PICTURE comments
+   pub pictures: Vec<Picture>,
+}
+
+impl Tag for Metadata {
+   fn title(&self) -> Option<&str> {
+      self.comments.title()
+   }
+
+   fn artist(&self) -> Option<&str> {
+      self.comments.artist()
+   }
+
+   fn album(&self) -> Option<&str> {
+      self.comments.album()
+   }
+
+   fn album_artist(&self) -> Option<&str> {
+      self.comments.album_artist()
+   }
+
+   fn genre(&self) -> Option<&str> {
+      self.comments.genre()
+   }
+
+   fn year(&self) -> Option<u16> {
+      self.comments.year()
+   }
+
+   fn track_number(&self) -> Option<u32> {
+      self.comments.track_number()
+   }
+
+   fn track_total(&self) -> Option<u32> {
+      self.comments.track_total()
+   }
+
+   fn disc_number(&self) -> Option<u32> {
+      self.comments.disc_number()
+   }
+
+   fn disc_total(&self) -> Option<u32> {
+      self.comments.disc_total()
+   }
+
+   fn comment(&self) -> Option<&str> {
+      self.comments.comment()
+   }
+
+   fn artist_values(&self) -> Vec<&str> {
+      self.comments.artist_values()
+   }
+
+   fn genre_values(&self) -> Vec<&str> {
+      self.comments.genre_values()
+   }
+}
+
+#[derive(Debug)]
+pub enum OggParseError {
+   NotOgg,
+   UnknownCodec,
+   InvalidComment,
+   Io(io::Error),
+}
+
+impl fmt::Display for OggParseError {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      match self {
+         OggParseError::NotOgg => write!(f, "not an Ogg stream"),
+         OggParseError::UnknownCodec => write!(f, "stream is neither Vorbis nor Opus"),
+         OggParseError::InvalidComment => write!(f, "missing or truncated comment header"),
+         OggParseError::Io(e) => write!(f, "I/O error: {}", e),
+      }
+   }
+}
+
+impl Error for OggParseError {
+   fn source(&self) -> Option<&(dyn Error + 'static)> {
+      match self {
+         OggParseError::Io(e) => Some(e),
+         _ => None,
+      }
+   }
+}
+
+impl From<io::Error> for OggParseError {
+   fn from(e: io::Error) -> OggParseError {
+      OggParseError::Io(e)
+   }
+}
+
+/// Reads the comment header of the first Vorbis or Opus stream in the source
+pub fn read_metadata<S: Read>(source: &mut S) -> Result<Metadata, OggParseError> {
+   // The identification header is always the first packet and the comment header the second
+   let packets = read_packets(source, 2)?;
+
+   let (codec, comment_bytes) = if packets[0].starts_with(b"\x01vorbis") {
+      if !packets[1].starts_with(b"\x03vorbis") {
+         return Err(OggParseError::InvalidComment);
+      }
+      (Codec::Vorbis, &packets[1][7..])
+   } else if packets[0].starts_with(b"OpusHead") {
+      if !packets[1].starts_with(b"OpusTags") {
+         return Err(OggParseError::InvalidComment);
+      }
+      (Codec::Opus, &packets[1][8..])
+   } else {
+      return Err(OggParseError::UnknownCodec);
+   };
+
+   let comments = VorbisComment::parse(comment_bytes).ok_or(OggParseError::InvalidComment)?;
+   let pictures = comments
+      .get_all("METADATA_BLOCK_PICTURE")
+      .filter_map(decode_base64)
+      .filter_map(|x| Picture::parse(&x))
+      .collect();
+
+   Ok(Metadata {
+      codec,
+      comments,
+      pictures,
+   })
+}
+
+/// Passes the audio of the stream `read_metadata` reads to `f`, a segment at a time. The page headers and the header
+/// packets (the comments among them) are left out, so the audio is the same however the file is tagged. A truncated
+/// last page ends the audio.
+pub fn read_audio<S: Read, F: FnMut(&[u8])>(source: &mut S, mut f: F) -> Result<(), OggParseError> {
+   let mut stream = None;
+   let mut header_packets = 0;
+   loop {
+      let page = match read_page(source) {
+         Ok(Some(v)) => v,
+         Ok(None) if stream.is_none() => return Err(OggParseError::NotOgg),
+         Ok(None) => return Ok(()),
+         Err(OggParseError::Io(ref e)) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+         Err(e) => return Err(e),
+      };
+      if stream.is_none() {
+         // Vorbis has a setup header after the comments, Opus doesn't
+         header_packets = match page.codec() {
+            Some(Codec::Vorbis) => 3,
+            Some(Codec::Opus) => 2,
+            None => 0,
+         };
+      }
+      if !is_selected(&page, &mut stream)? {
+         continue;
+      }
+      for segment in page.segments() {
+         if header_packets == 0 {
+            f(segment);
+         } else if segment.len() < 255 {
+            header_packets -= 1;
+         }
+      }
+   }
+}
+
+struct Page {
+   serial: u32,
+   // The first page of a logical stream
+   beginning_of_stream: bool,
+   lacing: Vec<u8>,
+   body: Vec<u8>,
+}
+
+impl Page {
+   // Packets are split into segments of up to 255 bytes, and a segment shorter than that ends the packet
+   fn segments(&self) -> impl Iterator<Item = &[u8]> {
+      let mut pos = 0;
+      self.lacing.iter().map(move |len| {
+         let segment = &self.body[pos..pos + *len as usize];
+         pos += *len as usize;
+         segment
+      })
+   }
+
+   // The codec whose identification header the page starts with
+   fn codec(&self) -> Option<Codec> {
+      if self.body.starts_with(b"\x01vorbis") {
+         Some(Codec::Vorbis)
+      } else if self.body.starts_with(b"OpusHead") {
+         Some(Codec::Opus)
+      } else {
+         None
+      }
+   }
+}
+
+// None at the end of the source
+fn read_page<S: Read>(source: &mut S) -> Result<Option<Page>, OggParseError> {
+   let mut header = [0u8; 27];
+   if let Err(e) = source.read_exact(&mut header) {
+      return match e.kind() {
+         io::ErrorKind::UnexpectedEof => Ok(None),
+         _ => Err(e.into()),
+      };
+   }
+   if &header[0..4] != b"OggS" {
+      return Err(OggParseError::NotOgg);
+   }
+   let mut lacing = vec![0u8; header[26] as usize];
+   source.read_exact(&mut lacing)?;
+   let mut body = vec![0u8; lacing.iter().map(|x| *x as usize).sum()];
+   source.read_exact(&mut body)?;
+   Ok(Some(Page {
+      serial: LittleEndian::read_u32(&header[14..18]),
+      beginning_of_stream: header[5] & 0x02 != 0,
+      lacing,
+      body,
+   }))
+}
+
+// Multiplexed files (Theora video with its sound, or an Ogg Skeleton index in front of the audio) start with the first
+// page of every logical stream, each holding the stream's identification header. The first Vorbis or Opus stream is
+// the one read, and the pages of the others are skipped.
+fn is_selected(page: &Page, stream: &mut Option<u32>) -> Result<bool, OggParseError> {
+   if stream.is_none() {
+      if page.codec().is_some() {
+         *stream = Some(page.serial);
+      } else if page.beginning_of_stream {
+         return Ok(false);
+      } else {
+         return Err(OggParseError::UnknownCodec);
+      }
+   }
+   Ok(*stream == Some(page.serial))
+}
+
+// A packet can continue onto the next page
+fn read_packets<S: Read>(source: &mut S, count: usize) -> Result<Vec<Vec<u8>>, OggParseError> {
+   let mut packets = Vec::new();
+   let mut packet = Vec::new();
+   let mut stream = None;
+   let mut any_page = false;
+
+   while packets.len() < count {
+      let page = match read_page(source)? {
+         Some(v) => v,
+         None if !any_page => return Err(OggParseError::NotOgg),
+         None if stream.is_none() => return Err(OggParseError::UnknownCodec),
+         None => return Err(OggParseError::InvalidComment),
+      };
+      any_page = true;
+      if !is_selected(&page, &mut stream)? {
+         continue;
+      }
+
+      for segment in page.segments() {
+         packet.extend_from_slice(segment);
+         if segment.len() < 255 {
+            packets.push(std::mem::replace(&mut packet, Vec::new()));
+         }
+      }
+   }
+
+   Ok(packets)
+}
+
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+   let mut output = Vec::with_capacity(text.len() / 4 * 3);
+   let mut buffer: u32 = 0;
+   let mut bits = 0;
+   for c in text.bytes().filter(|c| !c.is_ascii_whitespace()) {
+      let value = match c {
+         b'A'..=b'Z' => c - b'A',
+         b'a'..=b'z' => c - b'a' + 26,
+         b'0'..=b'9' => c - b'0' + 52,
+         b'+' => 62,
+         b'/' => 63,
+         b'=' => break,
+         _ => return None,
+      };
+      buffer = buffer << 6 | u32::from(value);
+      bits += 6;
+      if bits >= 8 {
+         bits -= 8;
+         output.push((buffer >> bits) as u8);
+      }
+   }
+   Some(output)
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+
+   #[cfg(test)]
+   fn page(serial: u8, lacing: &[u8], body: &[u8]) -> Vec<u8> {
+      let mut page = Vec::from(&b"OggS"[..]);
+      page.resize(14, 0);
+      page.extend_from_slice(&[serial, 0, 0, 0]);
+      page.resize(26, 0);
+      page.push(lacing.len() as u8);
+      page.extend_from_slice(lacing);
+      page.extend_from_slice(body);
+      page
+   }
+
+   #[test]
+   fn opus_tags_across_pages() {
+      let mut tags = Vec::from(&b"OpusTags\x00\x00\x00\x00\x01\x00\x00\x00"[..]);
+      tags.extend_from_slice(&[250, 0, 0, 0]);
+      tags.extend_from_slice(b"TITLE=");
+      tags.resize(tags.len() + 244, b'a');
+
+      let mut file = page(1, &[19], b"OpusHead\x01\x02\x00\x00\x80\xbb\x00\x00\x00\x00\x00");
+      // A page from another stream in the middle
+      file.extend(page(2, &[3], b"abc"));
+      file.extend(page(1, &[255], &tags[..255]));
+      file.extend(page(1, &[(tags.len() - 255) as u8], &tags[255..]));
+
+      let metadata = read_metadata(&mut io::Cursor::new(file)).unwrap();
+      assert_eq!(metadata.codec, Codec::Opus);
+      assert_eq!(metadata.comments.get("title").map(str::len), Some(244));
+   }
+
+   #[test]
+   fn multiplexed() {
+      let beginning = |serial: u8, lacing: &[u8], body: &[u8]| {
+         let mut page = page(serial, lacing, body);
+         page[5] = 0x02;
+         page
+      };
+      // Theora video, then Vorbis sound
+      let mut file = beginning(1, &[7], b"\x80theora");
+      file.extend(beginning(2, &[7], b"\x01vorbis"));
+      file.extend(page(1, &[7], b"\x81theora"));
+      file.extend(page(
+         2,
+         &[22, 12],
+         b"\x03vorbis\x00\x00\x00\x00\x01\x00\x00\x00\x03\x00\x00\x00a=b\x05vorbissetup",
+      ));
+      file.extend(page(1, &[5], b"video"));
+      file.extend(page(2, &[5], b"sound"));
+
+      let metadata = read_metadata(&mut io::Cursor::new(&file)).unwrap();
+      assert_eq!(metadata.codec, Codec::Vorbis);
+      assert_eq!(metadata.comments.get("a"), Some("b"));
+      let mut audio = Vec::new();
+      read_audio(&mut io::Cursor::new(&file), |x| audio.extend_from_slice(x)).unwrap();
+      assert_eq!(audio, b"sound");
+
+      match read_metadata(&mut io::Cursor::new(&file[..file.len() / 2])) {
+         Err(OggParseError::InvalidComment) => (),
+         x => panic!("expected a truncated comment, got {:?}", x.map(|x| x.codec)),
+      }
+      let video_only = [beginning(1, &[7], b"\x80theora"), page(1, &[7], b"\x81theora")].concat();
+      match read_metadata(&mut io::Cursor::new(video_only)) {
+         Err(OggParseError::UnknownCodec) => (),
+         x => panic!("expected an unknown codec, got {:?}", x.map(|x| x.codec)),
+      }
+   }
+
+   #[test]
+   fn base64() {
+      assert_eq!(decode_base64("aGVsbG8=").unwrap(), b"hello");
+   }
+}
@@ -0,0 +1,253 @@
+use crate::rename::Rename;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+/// Where `organize` puts files when no pattern is given
+pub const DEFAULT_PATTERN: &str = "{album_artist}/{album}/{track:02} - {title}.{ext}";
+
+/// One change to the file system, as recorded in the journal
+#[derive(Clone, Debug, PartialEq)]
+pub enum JournalEntry {
+   CreatedDir(PathBuf),
+   Moved { from: PathBuf, to: PathBuf },
+   Copied { from: PathBuf, to: PathBuf },
+}
+
+impl JournalEntry {
+   // One tab separated line. Tabs, newlines, and backslashes in paths are escaped.
+   fn to_line(&self) -> String {
+      match self {
+         JournalEntry::CreatedDir(dir) => format!("mkdir\t{}", escape(dir)),
+         JournalEntry::Moved { from, to } => format!("move\t{}\t{}", escape(from), escape(to)),
+         JournalEntry::Copied { from, to } => format!("copy\t{}\t{}", escape(from), escape(to)),
+      }
+   }
+
+   fn from_line(line: &str) -> Option<JournalEntry> {
+      let fields: Vec<PathBuf> = line.split('\t').skip(1).map(unescape).collect();
+      Some(match (line.split('\t').next()?, fields.as_slice()) {
+         ("mkdir", [dir]) => JournalEntry::CreatedDir(dir.clone()),
+         ("move", [from, to]) => JournalEntry::Moved {
+            from: from.clone(),
+            to: to.clone(),
+         },
+         ("copy", [from, to]) => JournalEntry::Copied {
+            from: from.clone(),
+            to: to.clone(),
+         },
+         _ => return None,
+      })
+   }
+}
+
+fn escape(path: &Path) -> String {
+   path
+      .to_string_lossy()
+      .replace('\\', "\\\\")
+      .replace('\t', "\\t")
+      .replace('\n', "\\n")
+}
+
+fn unescape(text: &str) -> PathBuf {
+   let mut path = String::with_capacity(text.len());
+   let mut chars = text.chars();
+   while let Some(c) = chars.next() {
+      match c {
+         '\\' => match chars.next() {
+            Some('t') => path.push('\t'),
+            Some('n') => path.push('\n'),
+            Some(x) => path.push(x),
+            None => path.push('\\'),
+         },
+         _ => path.push(c),
+      }
+   }
+   PathBuf::from(path)
+}
+
+#[derive(Debug)]
+pub enum OrganizeError {
+   /// The path isn't valid UTF-8, so the journal couldn't record it
+   UnjournalablePath(PathBuf),
+   Io(io::Error),
+}
+
+impl fmt::Display for OrganizeError {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      match self {
+         OrganizeError::UnjournalablePath(x) => write!(f, "{} is not valid UTF-8", x.display()),
+         OrganizeError::Io(e) => write!(f, "I/O error: {}", e),
+      }
+   }
+}
+
+impl Error for OrganizeError {
+   fn source(&self) -> Option<&(dyn Error + 'static)> {
+      match self {
+         OrganizeError::Io(e) => Some(e),
+         _ => None,
+      }
+   }
+}
+
+impl From<io::Error> for OrganizeError {
+   fn from(e: io::Error) -> OrganizeError {
+      OrganizeError::Io(e)
+   }
+}
+
+/// Moves (or copies, if `copy` is set) one file into place, creating the directories it goes in. Every change is
+/// written to the journal as soon as it's made, so that even a run that fails partway can be undone.
+pub fn organize_file<W: Write>(plan: &Rename, copy: bool, journal: &mut W) -> Result<(), OrganizeError> {
+   // Paths that aren't UTF-8 can't be journaled faithfully, and an undo that can't find the file is worse than
+   // leaving it where it is
+   for path in [&plan.from, &plan.to].iter() {
+      if path.to_str().is_none() {
+         return Err(OrganizeError::UnjournalablePath(path.to_path_buf()));
+      }
+   }
+   if plan.to.exists() {
+      return Err(io::Error::new(io::ErrorKind::AlreadyExists, "destination already exists").into());
+   }
+
+   let mut missing_dirs: Vec<&Path> = plan.to.ancestors().skip(1).take_while(|x| !x.exists()).collect();
+   missing_dirs.retain(|x| !x.as_os_str().is_empty());
+   for dir in missing_dirs.into_iter().rev() {
+      fs::create_dir(dir)?;
+      record(journal, &JournalEntry::CreatedDir(dir.to_owned()))?;
+   }
+
+   let entry = if copy {
+      fs::copy(&plan.from, &plan.to)?;
+      JournalEntry::Copied {
+         from: plan.from.clone(),
+         to: plan.to.clone(),
+      }
+   } else {
+      move_file(&plan.from, &plan.to)?;
+      JournalEntry::Moved {
+         from: plan.from.clone(),
+         to: plan.to.clone(),
+      }
+   };
+   record(journal, &entry)?;
+   Ok(())
+}
+
+fn record<W: Write>(journal: &mut W, entry: &JournalEntry) -> io::Result<()> {
+   writeln!(journal, "{}", entry.to_line())?;
+   journal.flush()
+}
+
+// A rename can't cross file systems, so fall back to copying and then removing the original. Any other error is
+// returned as it is.
+fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+   match fs::rename(from, to) {
+      Ok(()) => Ok(()),
+      Err(ref e) if crosses_devices(e) => {
+         // Either way, there should only be one of the file afterwards
+         if let Err(e) = fs::copy(from, to) {
+            let _ = fs::remove_file(to);
+            return Err(e);
+         }
+         if let Err(e) = fs::remove_file(from) {
+            let _ = fs::remove_file(to);
+            return Err(e);
+         }
+         Ok(())
+      }
+      Err(e) => Err(e),
+   }
+}
+
+// EXDEV, or ERROR_NOT_SAME_DEVICE on Windows
+fn crosses_devices(e: &io::Error) -> bool {
+   #[cfg(windows)]
+   const CROSS_DEVICE: i32 = 17;
+   #[cfg(not(windows))]
+   const CROSS_DEVICE: i32 = 18;
+   e.raw_os_error() == Some(CROSS_DEVICE)
+}
+
+/// Reverses every change in the journal, newest first: moved files are moved back, copies are deleted, and
+/// created directories are removed if they're empty. Returns the entries that couldn't be undone.
+pub fn undo<R: BufRead>(journal: R) -> io::Result<Vec<(JournalEntry, io::Error)>> {
+   let mut entries = Vec::new();
+   for line in journal.lines() {
+      let line = line?;
+      match JournalEntry::from_line(&line) {
+         Some(entry) => entries.push(entry),
+         None if line.is_empty() => (),
+         None => return Err(io::Error::new(io::ErrorKind::InvalidData, "malformed journal line")),
+      }
+   }
+
+   let mut failures = Vec::new();
+   for entry in entries.into_iter().rev() {
+      let result = match &entry {
+         // Something else has been put in the directory since, so it stays
+         JournalEntry::CreatedDir(dir) if is_non_empty_dir(dir) => Ok(()),
+         JournalEntry::CreatedDir(dir) => fs::remove_dir(dir),
+         JournalEntry::Moved { from, .. } if from.exists() => Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            "the original location is taken",
+         )),
+         JournalEntry::Moved { from, to } => match from.parent() {
+            Some(parent) => fs::create_dir_all(parent).and_then(|_| move_file(to, from)),
+            None => move_file(to, from),
+         },
+         JournalEntry::Copied { to, .. } => fs::remove_file(to),
+      };
+      if let Err(e) = result {
+         failures.push((entry, e));
+      }
+   }
+   Ok(failures)
+}
+
+fn is_non_empty_dir(dir: &Path) -> bool {
+   fs::read_dir(dir).map(|mut x| x.next().is_some()).unwrap_or(false)
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+
+   #[test]
+   fn journal_round_trip() {
+      let dir = std::env::temp_dir().join(format!("walnut-organize-{}", std::process::id()));
+      let _ = fs::remove_dir_all(&dir);
+      fs::create_dir_all(&dir).unwrap();
+      let song = dir.join("song.mp3");
+      fs::write(&song, b"audio").unwrap();
+
+      let mut journal = Vec::new();
+      let plan = Rename {
+         from: song.clone(),
+         to: dir.join("Artist").join("Album").join("01 - Song.mp3"),
+      };
+      organize_file(&plan, false, &mut journal).unwrap();
+      assert!(!song.exists());
+      assert_eq!(fs::read(&plan.to).unwrap(), b"audio");
+
+      let text = String::from_utf8(journal.clone()).unwrap();
+      let entries: Vec<JournalEntry> = text.lines().filter_map(JournalEntry::from_line).collect();
+      assert_eq!(entries.len(), 3);
+      assert_eq!(entries[1], JournalEntry::CreatedDir(dir.join("Artist").join("Album")));
+
+      let failures = undo(&journal[..]).unwrap();
+      assert!(failures.is_empty());
+      assert_eq!(fs::read(&song).unwrap(), b"audio");
+      assert!(!dir.join("Artist").exists());
+      fs::remove_dir_all(&dir).unwrap();
+
+      let escaped = JournalEntry::Copied {
+         from: PathBuf::from("a\tb\\c"),
+         to: PathBuf::from("d\ne"),
+      };
+      assert_eq!(JournalEntry::from_line(&escaped.to_line()), Some(escaped));
+   }
+}
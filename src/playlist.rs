@@ -0,0 +1,257 @@
+use crate::id3::Id3Tag;
+use crate::mpeg;
+use crate::probe::{self, ProbeError};
+use crate::tag::Tag;
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PlaylistFormat {
+   /// Extended M3U, in UTF-8
+   M3u,
+   Pls,
+   Xspf,
+}
+
+impl PlaylistFormat {
+   /// From a file extension like "m3u8", without the dot
+   pub fn from_extension(extension: &str) -> Option<PlaylistFormat> {
+      Some(match extension.to_ascii_lowercase().as_str() {
+         "m3u" | "m3u8" => PlaylistFormat::M3u,
+         "pls" => PlaylistFormat::Pls,
+         "xspf" => PlaylistFormat::Xspf,
+         _ => return None,
+      })
+   }
+}
+
+/// How the playlist refers to its files
+#[derive(Clone, Debug, PartialEq)]
+pub enum PathStyle {
+   Absolute,
+   /// Relative to this directory, which should be where the playlist will be. Paths on another drive are left
+   /// absolute.
+   RelativeTo(PathBuf),
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PlaylistEntry {
+   /// Absolute, so that it can be made relative
+   pub path: PathBuf,
+   pub title: Option<String>,
+   pub artist: Option<String>,
+   pub album: Option<String>,
+   pub duration: Option<Duration>,
+}
+
+impl PlaylistEntry {
+   /// From tags alone, such as those in the scan cache. The duration is only known if the tag has a TLEN frame.
+   pub fn from_tag(path: PathBuf, tag: &Id3Tag) -> PlaylistEntry {
+      PlaylistEntry {
+         duration: mpeg::tlen(tag),
+         ..PlaylistEntry::from_metadata(path, tag)
+      }
+   }
+
+   /// Reads the file's tags and duration (see `probe::probe_with_duration`)
+   pub fn read(path: PathBuf) -> Result<PlaylistEntry, ProbeError> {
+      let (metadata, duration) = probe::probe_with_duration(&path)?;
+      Ok(PlaylistEntry {
+         duration,
+         ..PlaylistEntry::from_metadata(path, &metadata)
+      })
+   }
+
+   fn from_metadata(path: PathBuf, tag: &dyn Tag) -> PlaylistEntry {
+      PlaylistEntry {
+         path,
+         title: tag.title().map(str::to_owned),
+         artist: tag.artist().map(str::to_owned),
+         album: tag.album().map(str::to_owned),
+         duration: None,
+      }
+   }
+
+   // "Artist - Title", or whatever part of it is known
+   fn display_title(&self) -> Option<String> {
+      match (&self.artist, &self.title) {
+         (Some(artist), Some(title)) => Some(format!("{} - {}", artist, title)),
+         (None, Some(title)) => Some(title.clone()),
+         _ => None,
+      }
+   }
+}
+
+pub fn write<W: Write>(
+   mut dest: W,
+   entries: &[PlaylistEntry],
+   format: PlaylistFormat,
+   style: &PathStyle,
+) -> io::Result<()> {
+   let location = |entry: &PlaylistEntry| match style {
+      PathStyle::Absolute => entry.path.clone(),
+      PathStyle::RelativeTo(base) => relative_path(&entry.path, base).unwrap_or_else(|| entry.path.clone()),
+   };
+
+   match format {
+      PlaylistFormat::M3u => {
+         writeln!(dest, "#EXTM3U")?;
+         for entry in entries.iter() {
+            // -1 is M3U for an unknown length
+            let seconds = entry.duration.map(|x| x.as_secs() as i64).unwrap_or(-1);
+            let title = entry.display_title().unwrap_or_else(|| file_stem(&entry.path));
+            writeln!(dest, "#EXTINF:{},{}", seconds, one_line(&title))?;
+            writeln!(dest, "{}", location(entry).display())?;
+         }
+      }
+      PlaylistFormat::Pls => {
+         writeln!(dest, "[playlist]")?;
+         for (i, entry) in entries.iter().enumerate() {
+            writeln!(dest, "File{}={}", i + 1, location(entry).display())?;
+            if let Some(title) = entry.display_title() {
+               writeln!(dest, "Title{}={}", i + 1, one_line(&title))?;
+            }
+            let seconds = entry.duration.map(|x| x.as_secs() as i64).unwrap_or(-1);
+            writeln!(dest, "Length{}={}", i + 1, seconds)?;
+         }
+         writeln!(dest, "NumberOfEntries={}", entries.len())?;
+         writeln!(dest, "Version=2")?;
+      }
+      PlaylistFormat::Xspf => {
+         writeln!(dest, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+         writeln!(dest, "<playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">")?;
+         writeln!(dest, "  <trackList>")?;
+         for entry in entries.iter() {
+            writeln!(dest, "    <track>")?;
+            writeln!(
+               dest,
+               "      <location>{}</location>",
+               xml_escape(&uri(&location(entry)))
+            )?;
+            let elements = [
+               ("title", &entry.title),
+               ("creator", &entry.artist),
+               ("album", &entry.album),
+            ];
+            for (element, value) in elements.iter() {
+               if let Some(value) = value {
+                  writeln!(dest, "      <{0}>{1}</{0}>", element, xml_escape(value))?;
+               }
+            }
+            if let Some(duration) = entry.duration {
+               writeln!(dest, "      <duration>{}</duration>", duration.as_millis())?;
+            }
+            writeln!(dest, "    </track>")?;
+         }
+         writeln!(dest, "  </trackList>")?;
+         writeln!(dest, "</playlist>")?;
+      }
+   }
+   dest.flush()
+}
+
+/// `path` relative to the directory `base`. Both should be absolute. `None` if they don't share a root, like paths on
+/// different Windows drives.
+pub fn relative_path(path: &Path, base: &Path) -> Option<PathBuf> {
+   let path: Vec<Component> = path.components().collect();
+   let base: Vec<Component> = base.components().collect();
+   if path.first() != base.first() {
+      return None;
+   }
+   let common = path.iter().zip(base.iter()).take_while(|(a, b)| a == b).count();
+   let mut relative = PathBuf::new();
+   for _ in common..base.len() {
+      relative.push("..");
+   }
+   for component in path[common..].iter() {
+      relative.push(component);
+   }
+   Some(relative)
+}
+
+fn file_stem(path: &Path) -> String {
+   path.file_stem().unwrap_or_default().to_string_lossy().into_owned()
+}
+
+// Titles are one line in M3U and PLS
+fn one_line(text: &str) -> String {
+   text.replace(|c| c == '\r' || c == '\n', " ")
+}
+
+// A file URI for absolute paths, and a relative reference otherwise
+fn uri(path: &Path) -> String {
+   let text = path.to_string_lossy().replace('\\', "/");
+   let mut encoded = String::new();
+   if path.is_absolute() {
+      encoded.push_str("file://");
+      // Windows paths like C:/music need a slash of their own
+      if !text.starts_with('/') {
+         encoded.push('/');
+      }
+   }
+   for byte in text.bytes() {
+      match byte {
+         b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => encoded.push(char::from(byte)),
+         // In a relative reference, a colon could be taken for the end of a scheme
+         b':' if path.is_absolute() => encoded.push(':'),
+         _ => encoded.push_str(&format!("%{:02X}", byte)),
+      }
+   }
+   encoded
+}
+
+fn xml_escape(text: &str) -> String {
+   let mut escaped = String::with_capacity(text.len());
+   for c in text.chars() {
+      match c {
+         '&' => escaped.push_str("&amp;"),
+         '<' => escaped.push_str("&lt;"),
+         '>' => escaped.push_str("&gt;"),
+         '"' => escaped.push_str("&quot;"),
+         '\'' => escaped.push_str("&apos;"),
+         c => escaped.push(c),
+      }
+   }
+   escaped
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+
+   #[test]
+   fn write_playlists() {
+      let entries = [
+         PlaylistEntry {
+            path: PathBuf::from("/music/Boards of Canada/Geogaddi/01 Ready Lets Go.mp3"),
+            title: Some("Ready Lets Go".to_owned()),
+            artist: Some("Boards of Canada".to_owned()),
+            album: Some("Geogaddi".to_owned()),
+            duration: Some(Duration::from_millis(59_500)),
+         },
+         PlaylistEntry {
+            path: PathBuf::from("/music/Unknown & Co/track.flac"),
+            ..PlaylistEntry::default()
+         },
+      ];
+      let style = PathStyle::RelativeTo(PathBuf::from("/music/playlists"));
+
+      let mut m3u = Vec::new();
+      write(&mut m3u, &entries, PlaylistFormat::M3u, &style).unwrap();
+      assert_eq!(
+         String::from_utf8(m3u).unwrap(),
+         "#EXTM3U\n\
+          #EXTINF:59,Boards of Canada - Ready Lets Go\n\
+          ../Boards of Canada/Geogaddi/01 Ready Lets Go.mp3\n\
+          #EXTINF:-1,track\n\
+          ../Unknown & Co/track.flac\n"
+      );
+
+      let mut xspf = Vec::new();
+      write(&mut xspf, &entries[1..], PlaylistFormat::Xspf, &PathStyle::Absolute).unwrap();
+      let xspf = String::from_utf8(xspf).unwrap();
+      assert!(xspf.contains("<location>file:///music/Unknown%20%26%20Co/track.flac</location>"));
+      assert!(!xspf.contains("<title>"));
+   }
+}
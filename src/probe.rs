@@ -0,0 +1,408 @@
+use crate::adts::{self, AdtsParseError};
+use crate::aiff::{self, AiffParseError};
+use crate::asf::{self, AsfParseError};
+use crate::dsd::{self, DsdParseError};
+use crate::flac::{self, FlacParseError};
+use crate::id3::{self, Id3Tag, TagParseError};
+use crate::mp4::{self, Mp4ParseError};
+use crate::mpeg;
+use crate::ogg::{self, OggParseError};
+use crate::riff::{self, RiffParseError};
+use crate::tag::Tag;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::Duration;
+
+const ASF_HEADER: &[u8] = b"\x30\x26\xb2\x75\x8e\x66\xcf\x11\xa6\xd9\x00\xaa\x00\x62\xce\x6c";
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Format {
+   Mpeg,
+   Flac,
+   Ogg,
+   Mp4,
+   Wav,
+   Aiff,
+   Dsd,
+   Asf,
+   /// Raw AAC in ADTS frames
+   Adts,
+}
+
+impl Format {
+   /// From a file extension like "mp3" or "M4A", without the dot
+   pub fn from_extension(extension: &str) -> Option<Format> {
+      Some(match extension.to_ascii_lowercase().as_str() {
+         "mp3" | "mp2" | "mp1" | "mpga" | "mpa" => Format::Mpeg,
+         "flac" => Format::Flac,
+         "ogg" | "oga" | "opus" | "spx" => Format::Ogg,
+         "m4a" | "m4b" | "m4p" | "m4r" | "mp4" => Format::Mp4,
+         "wav" | "wave" => Format::Wav,
+         "aif" | "aiff" | "aifc" => Format::Aiff,
+         "dsf" | "dff" => Format::Dsd,
+         "wma" | "asf" => Format::Asf,
+         "aac" | "adts" => Format::Adts,
+         _ => return None,
+      })
+   }
+
+   pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Format> {
+      path
+         .as_ref()
+         .extension()
+         .and_then(|x| x.to_str())
+         .and_then(Format::from_extension)
+   }
+}
+
+/// The metadata of any supported format. Use the `Tag` impl for the common fields,
+/// or match on the variant to get at everything the format holds.
+#[derive(Clone, Debug)]
+pub enum Metadata {
+   /// MPEG audio, with its ID3v2 tag (empty if there is none)
+   Mpeg(Id3Tag),
+   Flac(flac::Metadata),
+   Ogg(ogg::Metadata),
+   Mp4(mp4::Metadata),
+   Wav(riff::Metadata),
+   Aiff(aiff::Metadata),
+   Dsd(dsd::Metadata),
+   Asf(asf::Metadata),
+   Adts(adts::Metadata),
+}
+
+impl Metadata {
+   pub fn format(&self) -> Format {
+      match self {
+         Metadata::Mpeg(_) => Format::Mpeg,
+         Metadata::Flac(_) => Format::Flac,
+         Metadata::Ogg(_) => Format::Ogg,
+         Metadata::Mp4(_) => Format::Mp4,
+         Metadata::Wav(_) => Format::Wav,
+         Metadata::Aiff(_) => Format::Aiff,
+         Metadata::Dsd(_) => Format::Dsd,
+         Metadata::Asf(_) => Format::Asf,
+         Metadata::Adts(_) => Format::Adts,
+      }
+   }
+
+   pub fn tag(&self) -> &dyn Tag {
+      match self {
+         Metadata::Mpeg(x) => x,
+         Metadata::Flac(x) => x,
+         Metadata::Ogg(x) => x,
+         Metadata::Mp4(x) => x,
+         Metadata::Wav(x) => x,
+         Metadata::Aiff(x) => x,
+         Metadata::Dsd(x) => x,
+         Metadata::Asf(x) => x,
+         Metadata::Adts(x) => x,
+      }
+   }
+}
+
+impl Tag for Metadata {
+   fn title(&self) -> Option<&str> {
+      self.tag().title()
+   }
+
+   fn artist(&self) -> Option<&str> {
+      self.tag().artist()
+   }
+
+   fn album(&self) -> Option<&str> {
+      self.tag().album()
+   }
+
+   fn album_artist(&self) -> Option<&str> {
+      self.tag().album_artist()
+   }
+
+   fn genre(&self) -> Option<&str> {
+      self.tag().genre()
+   }
+
+   fn year(&self) -> Option<u16> {
+      self.tag().year()
+   }
+
+   fn track_number(&self) -> Option<u32> {
+      self.tag().track_number()
+   }
+
+   fn track_total(&self) -> Option<u32> {
+      self.tag().track_total()
+   }
+
+   fn disc_number(&self) -> Option<u32> {
+      self.tag().disc_number()
+   }
+
+   fn disc_total(&self) -> Option<u32> {
+      self.tag().disc_total()
+   }
+
+   fn comment(&self) -> Option<&str> {
+      self.tag().comment()
+   }
+
+   fn artist_values(&self) -> Vec<&str> {
+      self.tag().artist_values()
+   }
+
+   fn genre_values(&self) -> Vec<&str> {
+      self.tag().genre_values()
+   }
+}
+
+#[derive(Debug)]
+pub enum ProbeError {
+   UnknownFormat,
+   Id3(TagParseError),
+   Flac(FlacParseError),
+   Ogg(OggParseError),
+   Mp4(Mp4ParseError),
+   Wav(RiffParseError),
+   Aiff(AiffParseError),
+   Dsd(DsdParseError),
+   Asf(AsfParseError),
+   Adts(AdtsParseError),
+   Io(io::Error),
+}
+
+impl fmt::Display for ProbeError {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      match self {
+         ProbeError::UnknownFormat => write!(f, "unrecognized file format"),
+         ProbeError::Id3(e) => write!(f, "{}", e),
+         ProbeError::Flac(e) => write!(f, "{}", e),
+         ProbeError::Ogg(e) => write!(f, "{}", e),
+         ProbeError::Mp4(e) => write!(f, "{}", e),
+         ProbeError::Wav(e) => write!(f, "{}", e),
+         ProbeError::Aiff(e) => write!(f, "{}", e),
+         ProbeError::Dsd(e) => write!(f, "{}", e),
+         ProbeError::Asf(e) => write!(f, "{}", e),
+         ProbeError::Adts(e) => write!(f, "{}", e),
+         ProbeError::Io(e) => write!(f, "I/O error: {}", e),
+      }
+   }
+}
+
+impl Error for ProbeError {
+   fn source(&self) -> Option<&(dyn Error + 'static)> {
+      match self {
+         ProbeError::UnknownFormat => None,
+         ProbeError::Id3(e) => Some(e),
+         ProbeError::Flac(e) => Some(e),
+         ProbeError::Ogg(e) => Some(e),
+         ProbeError::Mp4(e) => Some(e),
+         ProbeError::Wav(e) => Some(e),
+         ProbeError::Aiff(e) => Some(e),
+         ProbeError::Dsd(e) => Some(e),
+         ProbeError::Asf(e) => Some(e),
+         ProbeError::Adts(e) => Some(e),
+         ProbeError::Io(e) => Some(e),
+      }
+   }
+}
+
+impl From<TagParseError> for ProbeError {
+   fn from(e: TagParseError) -> ProbeError {
+      ProbeError::Id3(e)
+   }
+}
+
+impl From<FlacParseError> for ProbeError {
+   fn from(e: FlacParseError) -> ProbeError {
+      ProbeError::Flac(e)
+   }
+}
+
+impl From<OggParseError> for ProbeError {
+   fn from(e: OggParseError) -> ProbeError {
+      ProbeError::Ogg(e)
+   }
+}
+
+impl From<Mp4ParseError> for ProbeError {
+   fn from(e: Mp4ParseError) -> ProbeError {
+      ProbeError::Mp4(e)
+   }
+}
+
+impl From<RiffParseError> for ProbeError {
+   fn from(e: RiffParseError) -> ProbeError {
+      ProbeError::Wav(e)
+   }
+}
+
+impl From<AiffParseError> for ProbeError {
+   fn from(e: AiffParseError) -> ProbeError {
+      ProbeError::Aiff(e)
+   }
+}
+
+impl From<DsdParseError> for ProbeError {
+   fn from(e: DsdParseError) -> ProbeError {
+      ProbeError::Dsd(e)
+   }
+}
+
+impl From<AsfParseError> for ProbeError {
+   fn from(e: AsfParseError) -> ProbeError {
+      ProbeError::Asf(e)
+   }
+}
+
+impl From<AdtsParseError> for ProbeError {
+   fn from(e: AdtsParseError) -> ProbeError {
+      ProbeError::Adts(e)
+   }
+}
+
+impl From<io::Error> for ProbeError {
+   fn from(e: io::Error) -> ProbeError {
+      ProbeError::Io(e)
+   }
+}
+
+/// Identifies the format by its magic bytes. The file extension is not consulted.
+pub fn sniff<S: Read + Seek>(source: &mut S) -> Result<Format, ProbeError> {
+   let mut header = Vec::new();
+   source.seek(SeekFrom::Start(0))?;
+   source.take(16).read_to_end(&mut header)?;
+
+   // A leading ID3 tag can be in front of MPEG audio, ADTS, or a FLAC stream
+   if let Some(tag_len) = id3::tag_len(&header) {
+      let mut marker = Vec::new();
+      source.seek(SeekFrom::Start(tag_len))?;
+      source.take(4).read_to_end(&mut marker)?;
+      return Ok(if marker == b"fLaC" {
+         Format::Flac
+      } else if is_adts_sync(&marker) {
+         Format::Adts
+      } else {
+         Format::Mpeg
+      });
+   }
+
+   sniff_header(&header).ok_or(ProbeError::UnknownFormat)
+}
+
+fn sniff_header(header: &[u8]) -> Option<Format> {
+   let magic = header.get(0..4)?;
+   let subtype = header.get(8..12);
+   Some(match magic {
+      b"fLaC" => Format::Flac,
+      b"OggS" => Format::Ogg,
+      b"RIFF" if subtype == Some(b"WAVE") => Format::Wav,
+      b"FORM" if subtype == Some(b"AIFF") || subtype == Some(b"AIFC") => Format::Aiff,
+      b"DSD " => Format::Dsd,
+      b"FRM8" => Format::Dsd,
+      _ if header.get(4..8) == Some(b"ftyp") => Format::Mp4,
+      _ if header.starts_with(ASF_HEADER) => Format::Asf,
+      _ if is_adts_sync(magic) => Format::Adts,
+      // The 11 bit frame sync of untagged MPEG audio
+      _ if magic[0] == 0xff && magic[1] & 0xe0 == 0xe0 => Format::Mpeg,
+      _ => return None,
+   })
+}
+
+/// Identifies the format by its magic bytes, or by the extension of the path when they aren't recognized, e.g. MPEG
+/// audio with junk in front of the first frame
+pub fn detect<S: Read + Seek>(source: &mut S, path: &Path) -> Result<Format, ProbeError> {
+   match sniff(source) {
+      Err(ProbeError::UnknownFormat) => Format::from_path(path).ok_or(ProbeError::UnknownFormat),
+      result => result,
+   }
+}
+
+/// The format of a file that looks like audio walnut can read, for picking the files out of a directory. A known
+/// extension is taken at its word, in any case; files with any other extension (or none) have their magic bytes
+/// sniffed.
+pub fn guess_format<P: AsRef<Path>>(path: P) -> Option<Format> {
+   let path = path.as_ref();
+   Format::from_path(path).or_else(|| File::open(path).ok().and_then(|mut f| sniff(&mut f).ok()))
+}
+
+// The 12 bit sync word of ADTS is followed by a layer of 0, which MPEG audio doesn't use
+fn is_adts_sync(bytes: &[u8]) -> bool {
+   bytes.len() >= 2 && bytes[0] == 0xff && bytes[1] & 0xf6 == 0xf0
+}
+
+/// Sniffs the format of the source and reads its metadata
+pub fn probe<S: Read + Seek>(source: &mut S) -> Result<Metadata, ProbeError> {
+   let format = sniff(source)?;
+   read_metadata(source, format)
+}
+
+/// Like `probe`, but falls back on the file's extension when its magic bytes aren't recognized (see `detect`)
+pub fn probe_path<P: AsRef<Path>>(path: P) -> Result<Metadata, ProbeError> {
+   let path = path.as_ref();
+   let mut f = File::open(path)?;
+   let format = detect(&mut f, path)?;
+   read_metadata(&mut f, format)
+}
+
+/// Reads the file's tags, and its duration where the format makes that cheap to find (MPEG audio, ADTS, and FLAC)
+pub(crate) fn probe_with_duration(path: &Path) -> Result<(Metadata, Option<Duration>), ProbeError> {
+   let mut f = File::open(path)?;
+   let metadata = probe(&mut f)?;
+   let duration = match &metadata {
+      Metadata::Mpeg(tag) => {
+         f.seek(SeekFrom::Start(0))?;
+         let properties = mpeg::read_properties(&mut f).ok();
+         mpeg::duration(properties.as_ref(), Some(tag)).map(|x| x.duration)
+      }
+      Metadata::Flac(x) => Some(x.stream_info.duration()),
+      Metadata::Adts(x) => x.properties.map(|x| x.duration()),
+      _ => None,
+   };
+   Ok((metadata, duration))
+}
+
+fn read_metadata<S: Read + Seek>(source: &mut S, format: Format) -> Result<Metadata, ProbeError> {
+   source.seek(SeekFrom::Start(0))?;
+
+   Ok(match format {
+      Format::Mpeg => match Id3Tag::read(source, id3::ParseOptions::default()) {
+         Ok(tag) => Metadata::Mpeg(tag),
+         Err(TagParseError::NoTag) => Metadata::Mpeg(Id3Tag::default()),
+         Err(e) => return Err(e.into()),
+      },
+      Format::Flac => Metadata::Flac(flac::read_metadata(source)?),
+      Format::Ogg => Metadata::Ogg(ogg::read_metadata(source)?),
+      Format::Mp4 => Metadata::Mp4(mp4::read_metadata(source)?),
+      Format::Wav => Metadata::Wav(riff::read_metadata(source)?),
+      Format::Aiff => Metadata::Aiff(aiff::read_metadata(source)?),
+      Format::Dsd => Metadata::Dsd(dsd::read_metadata(source)?),
+      Format::Asf => Metadata::Asf(asf::read_metadata(source)?),
+      Format::Adts => Metadata::Adts(adts::read_metadata(source)?),
+   })
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+
+   #[test]
+   fn sniff_formats() {
+      let flac_behind_id3 = b"ID3\x04\x00\x00\x00\x00\x00\x02\x00\x00fLaC";
+      assert_eq!(sniff(&mut io::Cursor::new(&flac_behind_id3[..])).unwrap(), Format::Flac);
+      assert_eq!(sniff_header(b"\xff\xfb\x90\x00"), Some(Format::Mpeg));
+      assert_eq!(sniff_header(b"\xff\xf1\x50\x80"), Some(Format::Adts));
+      assert_eq!(sniff_header(b"\x00\x00\x00\x20ftypM4A "), Some(Format::Mp4));
+      assert_eq!(sniff_header(b"RIFF\x00\x00\x00\x00AVI "), None);
+
+      assert_eq!(Format::from_path("a/b.MP3"), Some(Format::Mpeg));
+      assert_eq!(Format::from_path("a/b.Opus"), Some(Format::Ogg));
+      assert_eq!(Format::from_path("a/b.mp3.jpg"), None);
+      let junk_before_frames = b"\x00\x00\x00\x00\xff\xfb\x90\x00";
+      let mut source = io::Cursor::new(&junk_before_frames[..]);
+      assert_eq!(detect(&mut source, Path::new("x.Mp3")).unwrap(), Format::Mpeg);
+      assert!(detect(&mut source, Path::new("x.txt")).is_err());
+   }
+}
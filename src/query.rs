@@ -0,0 +1,533 @@
+use crate::id3::v24::FrameData;
+use crate::id3::write::encode_frame_data;
+use crate::id3::Id3Tag;
+use crate::tag::Tag;
+use std::cmp::Ordering;
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+/// A condition on a file's tags, parsed from expressions like `artist = 'Boards of Canada' AND year >= 2000`.
+///
+/// Comparisons are `=`, `!=`, `<`, `<=`, `>`, `>=`, and `~` (contains), and combine with `AND`, `OR`, `NOT`, and
+/// parentheses. Values can be quoted with single or double quotes (doubling the quote to include it), or left bare
+/// if they're one word. Text is compared without regard to case, and numbers as numbers.
+///
+/// Fields are `path`, `title`, `artist`, `album`, `albumartist`, `genre`, `year`, `track`, `tracktotal`, `disc`,
+/// `disctotal`, and `comment`, or any text or URL frame by its ID (`TBPM`), or a TXXX frame by its description
+/// (`TXXX:MOOD`). A field with several values matches if any of them does. A file without the field only matches
+/// `!=`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Query {
+   Compare { field: Field, op: Op, value: String },
+   Not(Box<Query>),
+   And(Box<Query>, Box<Query>),
+   Or(Box<Query>, Box<Query>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Field {
+   Path,
+   Title,
+   Artist,
+   Album,
+   AlbumArtist,
+   Genre,
+   Year,
+   Track,
+   TrackTotal,
+   Disc,
+   DiscTotal,
+   Comment,
+   Frame([u8; 4]),
+   UserText(String),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Op {
+   Eq,
+   Ne,
+   Lt,
+   Le,
+   Gt,
+   Ge,
+   Contains,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum QueryError {
+   UnknownField(String),
+   UnterminatedString {
+      position: usize,
+   },
+   /// Something that doesn't belong where it is, at a byte offset into the query
+   Unexpected {
+      position: usize,
+      found: String,
+   },
+   /// The query ends where more was expected
+   UnexpectedEnd,
+}
+
+impl fmt::Display for QueryError {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      match self {
+         QueryError::UnknownField(x) => write!(f, "unknown field {:?}", x),
+         QueryError::UnterminatedString { position } => write!(f, "quoted value at {} never ends", position),
+         QueryError::Unexpected { position, found } => write!(f, "unexpected {:?} at {}", found, position),
+         QueryError::UnexpectedEnd => write!(f, "query ends too soon"),
+      }
+   }
+}
+
+impl Error for QueryError {}
+
+impl FromStr for Query {
+   type Err = QueryError;
+
+   fn from_str(s: &str) -> Result<Query, QueryError> {
+      let mut parser = Parser {
+         tokens: tokenize(s)?,
+         next: 0,
+      };
+      let query = parser.or()?;
+      match parser.tokens.get(parser.next) {
+         Some((position, token)) => Err(QueryError::Unexpected {
+            position: *position,
+            found: token.to_string(),
+         }),
+         None => Ok(query),
+      }
+   }
+}
+
+impl Query {
+   /// Whether the file at `path` with the given tag is one the query asks for
+   pub fn matches(&self, path: &Path, tag: &Id3Tag) -> bool {
+      match self {
+         Query::Compare { field, op, value } => {
+            let values = field.values(path, tag);
+            match op {
+               Op::Ne => !values.iter().any(|x| compare(x, value) == Ordering::Equal),
+               Op::Contains => {
+                  let value = value.to_lowercase();
+                  values.iter().any(|x| x.to_lowercase().contains(&value))
+               }
+               _ => values.iter().any(|x| {
+                  let ordering = compare(x, value);
+                  match op {
+                     Op::Eq => ordering == Ordering::Equal,
+                     Op::Lt => ordering == Ordering::Less,
+                     Op::Le => ordering != Ordering::Greater,
+                     Op::Gt => ordering == Ordering::Greater,
+                     _ => ordering != Ordering::Less,
+                  }
+               }),
+            }
+         }
+         Query::Not(x) => !x.matches(path, tag),
+         Query::And(a, b) => a.matches(path, tag) && b.matches(path, tag),
+         Query::Or(a, b) => a.matches(path, tag) || b.matches(path, tag),
+      }
+   }
+}
+
+impl FromStr for Field {
+   type Err = QueryError;
+
+   fn from_str(s: &str) -> Result<Field, QueryError> {
+      Ok(match s.to_ascii_lowercase().as_str() {
+         "path" => Field::Path,
+         "title" => Field::Title,
+         "artist" => Field::Artist,
+         "album" => Field::Album,
+         "albumartist" | "album_artist" => Field::AlbumArtist,
+         "genre" => Field::Genre,
+         "year" => Field::Year,
+         "track" => Field::Track,
+         "tracktotal" | "track_total" => Field::TrackTotal,
+         "disc" => Field::Disc,
+         "disctotal" | "disc_total" => Field::DiscTotal,
+         "comment" => Field::Comment,
+         _ if s.starts_with("TXXX:") => Field::UserText(s["TXXX:".len()..].to_owned()),
+         _ => match s.as_bytes() {
+            [a, b, c, d] if s.bytes().all(|x| x.is_ascii_uppercase() || x.is_ascii_digit()) => {
+               Field::Frame([*a, *b, *c, *d])
+            }
+            _ => return Err(QueryError::UnknownField(s.to_owned())),
+         },
+      })
+   }
+}
+
+impl Field {
+   /// The values of the field in the tag of the file at `path`, which are none if the tag doesn't have it
+   pub fn values(&self, path: &Path, tag: &Id3Tag) -> Vec<String> {
+      let text = |x: Option<&str>| x.map(str::to_owned).into_iter().collect();
+      let number = |x: Option<u32>| x.map(|x| x.to_string()).into_iter().collect();
+      match self {
+         Field::Path => vec![path.to_string_lossy().into_owned()],
+         Field::Title => text(tag.title()),
+         Field::Artist => text(tag.artist()),
+         Field::Album => text(tag.album()),
+         Field::AlbumArtist => text(tag.album_artist()),
+         Field::Genre => text(tag.genre()),
+         Field::Year => number(tag.year().map(u32::from)),
+         Field::Track => number(tag.track_number()),
+         Field::TrackTotal => number(tag.track_total()),
+         Field::Disc => number(tag.disc_number()),
+         Field::DiscTotal => number(tag.disc_total()),
+         Field::Comment => text(tag.comment()),
+         Field::Frame(name) => tag
+            .frames
+            .iter()
+            .filter(|x| x.data.name() == *name)
+            .flat_map(|x| frame_texts(&x.data))
+            .collect(),
+         Field::UserText(description) => tag
+            .frames
+            .iter()
+            .filter_map(|x| match &x.data {
+               FrameData::TXXX(x) if x.description.eq_ignore_ascii_case(description) => Some(x),
+               _ => None,
+            })
+            .flat_map(|x| x.text.iter().map(|x| x.to_string()))
+            .collect(),
+      }
+   }
+}
+
+/// A line of text filled in from a file's tags, like `{artist} - {title}`. The fields are those of a `Query`, so
+/// `{TBPM}` and `{TXXX:MOOD}` work too. A field with several values has them joined by "; ", and a field the file
+/// doesn't have is left empty. Write `{{` and `}}` for literal braces.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Template {
+   segments: Vec<Segment>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Segment {
+   Literal(String),
+   Field(Field),
+}
+
+impl FromStr for Template {
+   type Err = QueryError;
+
+   fn from_str(s: &str) -> Result<Template, QueryError> {
+      let mut segments = Vec::new();
+      let mut literal = String::new();
+      let mut chars = s.char_indices().peekable();
+      while let Some((position, c)) = chars.next() {
+         match c {
+            '{' if chars.peek().map(|x| x.1) == Some('{') => {
+               chars.next();
+               literal.push('{');
+            }
+            '}' if chars.peek().map(|x| x.1) == Some('}') => {
+               chars.next();
+               literal.push('}');
+            }
+            '{' => {
+               let end = s[position..].find('}').ok_or(QueryError::UnexpectedEnd)? + position;
+               let field = s[position + 1..end].parse()?;
+               while chars.next().map_or(false, |x| x.0 < end) {}
+               if !literal.is_empty() {
+                  segments.push(Segment::Literal(std::mem::replace(&mut literal, String::new())));
+               }
+               segments.push(Segment::Field(field));
+            }
+            '}' => {
+               return Err(QueryError::Unexpected {
+                  position,
+                  found: "}".to_owned(),
+               })
+            }
+            _ => literal.push(c),
+         }
+      }
+      if !literal.is_empty() {
+         segments.push(Segment::Literal(literal));
+      }
+      Ok(Template { segments })
+   }
+}
+
+impl Template {
+   pub fn render(&self, path: &Path, tag: &Id3Tag) -> String {
+      let mut line = String::new();
+      for segment in self.segments.iter() {
+         match segment {
+            Segment::Literal(x) => line.push_str(x),
+            Segment::Field(field) => line.push_str(&field.values(path, tag).join("; ")),
+         }
+      }
+      line
+   }
+}
+
+// The values of a text or URL frame, as they'd be written. The frames we write hold ISO-8859-1 or UTF-8.
+pub(crate) fn frame_texts(data: &FrameData) -> Vec<String> {
+   let name = data.name();
+   if name[0] != b'T' && name[0] != b'W' || name == *b"TXXX" || name == *b"WXXX" {
+      return Vec::new();
+   }
+   let bytes = encode_frame_data(data);
+   let text = match (name[0], bytes.split_first()) {
+      (b'W', _) if name != *b"WFED" => bytes.iter().map(|x| char::from(*x)).collect(),
+      (_, Some((0, text))) => text.iter().map(|x| char::from(*x)).collect(),
+      (_, Some((3, text))) => String::from_utf8_lossy(text).into_owned(),
+      _ => return Vec::new(),
+   };
+   text.trim_end_matches('\0').split('\0').map(str::to_owned).collect()
+}
+
+// Numerically if both are numbers, otherwise as text without regard to case
+fn compare(a: &str, b: &str) -> Ordering {
+   match (a.trim().parse::<f64>(), b.trim().parse::<f64>()) {
+      (Ok(a), Ok(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+      _ => a.to_lowercase().cmp(&b.to_lowercase()),
+   }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+   /// A field, a keyword, or a bare value
+   Word(String),
+   Quoted(String),
+   Op(Op),
+   Open,
+   Close,
+}
+
+impl fmt::Display for Token {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      match self {
+         Token::Word(x) | Token::Quoted(x) => write!(f, "{}", x),
+         Token::Op(op) => write!(
+            f,
+            "{}",
+            match op {
+               Op::Eq => "=",
+               Op::Ne => "!=",
+               Op::Lt => "<",
+               Op::Le => "<=",
+               Op::Gt => ">",
+               Op::Ge => ">=",
+               Op::Contains => "~",
+            }
+         ),
+         Token::Open => write!(f, "("),
+         Token::Close => write!(f, ")"),
+      }
+   }
+}
+
+// With the byte offset of each token
+fn tokenize(text: &str) -> Result<Vec<(usize, Token)>, QueryError> {
+   let mut tokens = Vec::new();
+   let mut chars = text.char_indices().peekable();
+   while let Some((position, c)) = chars.next() {
+      let token = match c {
+         _ if c.is_whitespace() => continue,
+         '(' => Token::Open,
+         ')' => Token::Close,
+         '~' => Token::Op(Op::Contains),
+         '=' => Token::Op(Op::Eq),
+         '!' | '<' | '>' => {
+            let or_equal = chars.peek().map(|x| x.1) == Some('=');
+            if or_equal {
+               chars.next();
+            }
+            Token::Op(match (c, or_equal) {
+               ('!', true) => Op::Ne,
+               ('<', false) => Op::Lt,
+               ('<', true) => Op::Le,
+               ('>', false) => Op::Gt,
+               ('>', true) => Op::Ge,
+               _ => {
+                  return Err(QueryError::Unexpected {
+                     position,
+                     found: c.to_string(),
+                  })
+               }
+            })
+         }
+         '\'' | '"' => {
+            let mut value = String::new();
+            loop {
+               match chars.next() {
+                  Some((_, x)) if x == c && chars.peek().map(|x| x.1) == Some(c) => {
+                     chars.next();
+                     value.push(c);
+                  }
+                  Some((_, x)) if x == c => break,
+                  Some((_, x)) => value.push(x),
+                  None => return Err(QueryError::UnterminatedString { position }),
+               }
+            }
+            Token::Quoted(value)
+         }
+         _ => {
+            let mut word = c.to_string();
+            while let Some((_, x)) = chars.peek() {
+               if x.is_whitespace() || "()~=!<>'\"".contains(*x) {
+                  break;
+               }
+               word.push(*x);
+               chars.next();
+            }
+            Token::Word(word)
+         }
+      };
+      tokens.push((position, token));
+   }
+   Ok(tokens)
+}
+
+// Recursive descent, with AND binding tighter than OR
+struct Parser {
+   tokens: Vec<(usize, Token)>,
+   next: usize,
+}
+
+impl Parser {
+   fn advance(&mut self) -> Result<(usize, Token), QueryError> {
+      let token = self.tokens.get(self.next).cloned().ok_or(QueryError::UnexpectedEnd)?;
+      self.next += 1;
+      Ok(token)
+   }
+
+   fn keyword(&mut self, keyword: &str) -> bool {
+      match self.tokens.get(self.next) {
+         Some((_, Token::Word(x))) if x.eq_ignore_ascii_case(keyword) => {
+            self.next += 1;
+            true
+         }
+         _ => false,
+      }
+   }
+
+   fn or(&mut self) -> Result<Query, QueryError> {
+      let mut query = self.and()?;
+      while self.keyword("OR") {
+         query = Query::Or(Box::new(query), Box::new(self.and()?));
+      }
+      Ok(query)
+   }
+
+   fn and(&mut self) -> Result<Query, QueryError> {
+      let mut query = self.unary()?;
+      while self.keyword("AND") {
+         query = Query::And(Box::new(query), Box::new(self.unary()?));
+      }
+      Ok(query)
+   }
+
+   fn unary(&mut self) -> Result<Query, QueryError> {
+      if self.keyword("NOT") {
+         return Ok(Query::Not(Box::new(self.unary()?)));
+      }
+      let unexpected = |position, token: Token| QueryError::Unexpected {
+         position,
+         found: token.to_string(),
+      };
+      match self.advance()? {
+         (_, Token::Open) => {
+            let query = self.or()?;
+            match self.advance()? {
+               (_, Token::Close) => Ok(query),
+               (position, token) => Err(unexpected(position, token)),
+            }
+         }
+         (_, Token::Word(field)) => {
+            let field = field.parse()?;
+            let op = match self.advance()? {
+               (_, Token::Op(op)) => op,
+               (position, token) => return Err(unexpected(position, token)),
+            };
+            let value = match self.advance()? {
+               (_, Token::Word(x)) | (_, Token::Quoted(x)) => x,
+               (position, token) => return Err(unexpected(position, token)),
+            };
+            Ok(Query::Compare { field, op, value })
+         }
+         (position, token) => Err(unexpected(position, token)),
+      }
+   }
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+   #[cfg(test)]
+   use crate::id3::TagBuilder;
+
+   #[test]
+   fn parse_and_match() {
+      let mut tag = TagBuilder::new()
+         .artist("Boards of Canada")
+         .album("Geogaddi")
+         .user_text("MOOD", "Eerie")
+         .build()
+         .unwrap();
+      tag.frames.push(crate::id3::v24::Frame::new(
+         FrameData::from_text(*b"TDRC", "2002").unwrap(),
+      ));
+      let path = Path::new("music/Geogaddi/01.mp3");
+      let matches = |query: &str| query.parse::<Query>().unwrap().matches(path, &tag);
+
+      assert!(matches("artist = 'boards of canada' AND year >= 2000"));
+      assert!(!matches("artist = 'Boards of Canada' AND year < 2000"));
+      assert!(matches(
+         "NOT (album = \"Music Has the Right to Children\" OR year = 1998)"
+      ));
+      assert!(matches("TXXX:MOOD ~ eer and path ~ Geogaddi"));
+      assert!(matches("TALB = geogaddi"));
+      // No title, so only != matches
+      assert!(matches("title != 'Alpha and Omega'"));
+      assert!(!matches("title < zzz"));
+
+      assert_eq!(
+         "artist = 'x".parse::<Query>(),
+         Err(QueryError::UnterminatedString { position: 9 })
+      );
+      assert_eq!("artist =".parse::<Query>(), Err(QueryError::UnexpectedEnd));
+      assert_eq!(
+         "artist = x y".parse::<Query>(),
+         Err(QueryError::Unexpected {
+            position: 11,
+            found: "y".to_owned()
+         })
+      );
+      assert_eq!(
+         "mood = x".parse::<Query>(),
+         Err(QueryError::UnknownField("mood".to_owned()))
+      );
+   }
+
+   #[test]
+   fn templates() {
+      let tag = TagBuilder::new()
+         .artist("Boards of Canada")
+         .title("Dawn Chorus")
+         .user_text("MOOD", "Eerie")
+         .build()
+         .unwrap();
+      let render = |template: &str| template.parse::<Template>().map(|x| x.render(Path::new("a.mp3"), &tag));
+
+      assert_eq!(
+         render("{artist} - {title} [{TXXX:MOOD}] {{{album}}} {path}"),
+         Ok("Boards of Canada - Dawn Chorus [Eerie] {} a.mp3".to_owned())
+      );
+      assert_eq!(render("{artist"), Err(QueryError::UnexpectedEnd));
+      assert_eq!(
+         render("artist}"),
+         Err(QueryError::Unexpected {
+            position: 6,
+            found: "}".to_owned()
+         })
+      );
+   }
+}
@@ -0,0 +1,206 @@
+use crate::id3::v1::Id3v1Tag;
+use crate::id3::{self, Id3Tag, ParseOptions, TagParseError};
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+
+/// The tags of a file that was read a few ranges at a time, rather than downloaded
+#[derive(Clone, Debug, Default)]
+pub struct RemoteTags {
+   pub id3v2: Option<Id3Tag>,
+   pub id3v1: Option<Id3v1Tag>,
+   /// The length of the whole file, if the server said
+   pub len: Option<u64>,
+   /// How many bytes were fetched, all ranges added up
+   pub fetched: u64,
+}
+
+/// A range of bytes to fetch
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ByteRange {
+   From {
+      start: u64,
+      len: u64,
+   },
+   /// The last bytes of the file
+   Last(u64),
+}
+
+impl ByteRange {
+   pub fn len(self) -> u64 {
+      match self {
+         ByteRange::From { len, .. } => len,
+         ByteRange::Last(len) => len,
+      }
+   }
+
+   pub fn is_empty(self) -> bool {
+      self.len() == 0
+   }
+
+   /// The value of an HTTP Range header asking for these bytes
+   pub fn header_value(self) -> String {
+      match self {
+         ByteRange::From { start, len } => format!("bytes={}-{}", start, start + len - 1),
+         ByteRange::Last(len) => format!("bytes=-{}", len),
+      }
+   }
+}
+
+/// What came back for a range. There can be fewer bytes than were asked for, at the end of the file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Fetched {
+   pub bytes: Vec<u8>,
+   /// The length of the whole file, if known
+   pub total_len: Option<u64>,
+}
+
+#[derive(Debug)]
+pub enum RemoteError {
+   Http(String),
+   /// The server sent the whole file instead of the range asked for
+   RangesUnsupported,
+   Id3(TagParseError),
+}
+
+impl fmt::Display for RemoteError {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      match self {
+         RemoteError::Http(message) => write!(f, "download failed: {}", message),
+         RemoteError::RangesUnsupported => write!(f, "the server doesn't support range requests"),
+         RemoteError::Id3(e) => write!(f, "{}", e),
+      }
+   }
+}
+
+impl Error for RemoteError {
+   fn source(&self) -> Option<&(dyn Error + 'static)> {
+      match self {
+         RemoteError::Id3(e) => Some(e),
+         _ => None,
+      }
+   }
+}
+
+impl From<TagParseError> for RemoteError {
+   fn from(e: TagParseError) -> RemoteError {
+      RemoteError::Id3(e)
+   }
+}
+
+/// Reads the ID3v2 and ID3v1 tags of a file with as few bytes as it takes: the ID3v2 header, then the rest of the tag
+/// it describes, then the last 128 bytes. `fetch` gets each range, e.g. over HTTP as `read_tags` does. Only a tag at
+/// the very start of the file is found.
+pub fn read_tags_with<F>(mut fetch: F) -> Result<RemoteTags, RemoteError>
+where
+   F: FnMut(ByteRange) -> Result<Fetched, RemoteError>,
+{
+   let mut tags = RemoteTags::default();
+   let mut fetch = |tags: &mut RemoteTags, range| -> Result<Vec<u8>, RemoteError> {
+      let fetched = fetch(range)?;
+      tags.fetched += fetched.bytes.len() as u64;
+      tags.len = tags.len.or(fetched.total_len);
+      Ok(fetched.bytes)
+   };
+
+   let mut bytes = fetch(&mut tags, ByteRange::From { start: 0, len: 10 })?;
+   if let Some(tag_len) = id3::tag_len(&bytes) {
+      let rest = fetch(
+         &mut tags,
+         ByteRange::From {
+            start: 10,
+            len: tag_len - 10,
+         },
+      )?;
+      bytes.extend(rest);
+      tags.id3v2 = Some(Id3Tag::read(&mut &bytes[..], ParseOptions::default())?);
+   }
+
+   let v1_len = Id3v1Tag::LEN;
+   if tags.len.map_or(true, |x| x >= v1_len + bytes.len() as u64) {
+      let bytes = fetch(&mut tags, ByteRange::Last(v1_len))?;
+      tags.id3v1 = <&[u8; 128]>::try_from(&bytes[..]).ok().and_then(Id3v1Tag::parse);
+   }
+   Ok(tags)
+}
+
+/// Reads the tags of the file at the URL with range requests (see `read_tags_with`), so that little more than the
+/// tags is downloaded. The server has to support them.
+#[cfg(feature = "remote")]
+pub fn read_tags(url: &str) -> Result<RemoteTags, RemoteError> {
+   read_tags_with(|range| fetch_range(url, range))
+}
+
+#[cfg(feature = "remote")]
+fn fetch_range(url: &str, range: ByteRange) -> Result<Fetched, RemoteError> {
+   use std::io::Read;
+   use std::time::Duration;
+
+   let response = ureq::get(url)
+      .set("User-Agent", concat!("walnut/", env!("CARGO_PKG_VERSION")))
+      .set("Range", &range.header_value())
+      .timeout(Duration::from_secs(60))
+      .call();
+   if let Some(e) = response.synthetic_error() {
+      return Err(RemoteError::Http(e.to_string()));
+   }
+   let total_len = response.header("Content-Range").and_then(content_range_len);
+   match response.status() {
+      206 => (),
+      // Asked for more than there is, as with the last 128 bytes of a shorter file
+      416 => {
+         return Ok(Fetched {
+            bytes: Vec::new(),
+            total_len,
+         })
+      }
+      200 => return Err(RemoteError::RangesUnsupported),
+      _ => return Err(RemoteError::Http(response.status_line().to_owned())),
+   }
+
+   let mut bytes = Vec::new();
+   response
+      .into_reader()
+      .take(range.len())
+      .read_to_end(&mut bytes)
+      .map_err(|e| RemoteError::Http(e.to_string()))?;
+   Ok(Fetched { bytes, total_len })
+}
+
+// The length after the slash of a Content-Range header like "bytes 0-9/5000", which is "*" if it isn't known
+#[cfg_attr(not(feature = "remote"), allow(dead_code))]
+fn content_range_len(header: &str) -> Option<u64> {
+   header.rsplit('/').next()?.trim().parse().ok()
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+
+   #[test]
+   fn fetch_ranges() {
+      let mut file = b"ID3\x04\x00\x00\x00\x00\x00\x0dTIT2\x00\x00\x00\x03\x00\x00\x00Hi".to_vec();
+      file.extend_from_slice(&[0; 1000]);
+      file.extend_from_slice(&Id3v1Tag::default().to_bytes());
+      let mut ranges = Vec::new();
+      let tags = read_tags_with(|range| {
+         ranges.push(range.header_value());
+         let (start, len) = match range {
+            ByteRange::From { start, len } => (start as usize, len as usize),
+            ByteRange::Last(len) => (file.len() - len as usize, len as usize),
+         };
+         Ok(Fetched {
+            bytes: file[start..start + len].to_vec(),
+            total_len: Some(file.len() as u64),
+         })
+      })
+      .unwrap();
+
+      assert_eq!(ranges, ["bytes=0-9", "bytes=10-22", "bytes=-128"]);
+      assert_eq!(tags.fetched, 23 + 128);
+      assert_eq!(tags.id3v2.unwrap().frames.len(), 1);
+      assert_eq!(tags.id3v1, Some(Id3v1Tag::default()));
+      assert_eq!(content_range_len("bytes 0-9/1151"), Some(1151));
+      assert_eq!(content_range_len("bytes 0-9/*"), None);
+   }
+}
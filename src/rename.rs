@@ -0,0 +1,355 @@
+use crate::tag::Tag;
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Field {
+   Artist,
+   AlbumArtist,
+   Album,
+   Title,
+   Genre,
+   Year,
+   Track,
+   TrackTotal,
+   Disc,
+   DiscTotal,
+   /// The extension of the file being renamed, without the dot
+   Extension,
+}
+
+impl FromStr for Field {
+   type Err = PatternError;
+
+   fn from_str(s: &str) -> Result<Field, PatternError> {
+      Ok(match s {
+         "artist" => Field::Artist,
+         "album_artist" => Field::AlbumArtist,
+         "album" => Field::Album,
+         "title" => Field::Title,
+         "genre" => Field::Genre,
+         "year" => Field::Year,
+         "track" => Field::Track,
+         "track_total" => Field::TrackTotal,
+         "disc" => Field::Disc,
+         "disc_total" => Field::DiscTotal,
+         "ext" => Field::Extension,
+         _ => return Err(PatternError::UnknownField(s.to_owned())),
+      })
+   }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Segment {
+   Literal(String),
+   /// Numbers are padded with zeros to the width
+   Field(Field, Option<usize>),
+}
+
+/// A file name built from tags, like `{artist}/{album}/{track:02} - {title}.{ext}`.
+///
+/// The fields are `artist`, `album_artist`, `album`, `title`, `genre`, `year`, `track`, `track_total`, `disc`,
+/// `disc_total`, and `ext`. Slashes in the pattern make directories, but slashes in the tags don't.
+/// Write `{{` and `}}` for literal braces.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pattern {
+   segments: Vec<Segment>,
+}
+
+impl FromStr for Pattern {
+   type Err = PatternError;
+
+   fn from_str(s: &str) -> Result<Pattern, PatternError> {
+      let mut segments = Vec::new();
+      let mut literal = String::new();
+      let mut chars = s.chars().peekable();
+      while let Some(c) = chars.next() {
+         match c {
+            '{' if chars.peek() == Some(&'{') => {
+               chars.next();
+               literal.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+               chars.next();
+               literal.push('}');
+            }
+            '{' => {
+               let mut spec = String::new();
+               loop {
+                  match chars.next() {
+                     Some('}') => break,
+                     Some(x) => spec.push(x),
+                     None => return Err(PatternError::UnmatchedBrace),
+                  }
+               }
+               let mut parts = spec.splitn(2, ':');
+               let field: Field = parts.next().unwrap().parse()?;
+               let width = match parts.next() {
+                  Some(width) => match (field, width.parse()) {
+                     (Field::Year, Ok(width))
+                     | (Field::Track, Ok(width))
+                     | (Field::TrackTotal, Ok(width))
+                     | (Field::Disc, Ok(width))
+                     | (Field::DiscTotal, Ok(width)) => Some(width),
+                     _ => return Err(PatternError::InvalidWidth(spec.clone())),
+                  },
+                  None => None,
+               };
+               if !literal.is_empty() {
+                  segments.push(Segment::Literal(std::mem::replace(&mut literal, String::new())));
+               }
+               segments.push(Segment::Field(field, width));
+            }
+            '}' => return Err(PatternError::UnmatchedBrace),
+            _ => literal.push(c),
+         }
+      }
+      if !literal.is_empty() {
+         segments.push(Segment::Literal(literal));
+      }
+      Ok(Pattern { segments })
+   }
+}
+
+impl Pattern {
+   /// The relative path for a file with the given tag and extension. Fields the tag doesn't have become
+   /// "Unknown", and every field is made safe to use as (part of) a file name.
+   pub fn render(&self, tag: &dyn Tag, extension: &str, sanitize: Sanitize) -> PathBuf {
+      let mut path = String::new();
+      for segment in self.segments.iter() {
+         match segment {
+            Segment::Literal(x) => path.push_str(x),
+            Segment::Field(field, width) => {
+               let value = field_value(tag, *field, extension, width.unwrap_or(0));
+               path.push_str(&sanitize.replace_chars(&value.unwrap_or_else(|| "Unknown".to_owned())));
+            }
+         }
+      }
+      // The pattern's own separators are always written as slashes
+      path
+         .split('/')
+         .filter(|x| !x.is_empty())
+         .map(|x| sanitize.component(x))
+         .collect()
+   }
+}
+
+fn field_value(tag: &dyn Tag, field: Field, extension: &str, width: usize) -> Option<String> {
+   let number = |x: Option<u32>| x.map(|x| format!("{:0width$}", x, width = width));
+   match field {
+      Field::Artist => tag.artist().map(str::to_owned),
+      Field::AlbumArtist => tag.album_artist().or_else(|| tag.artist()).map(str::to_owned),
+      Field::Album => tag.album().map(str::to_owned),
+      Field::Title => tag.title().map(str::to_owned),
+      Field::Genre => tag.genre().map(str::to_owned),
+      Field::Year => number(tag.year().map(u32::from)),
+      Field::Track => number(tag.track_number()),
+      Field::TrackTotal => number(tag.track_total()),
+      Field::Disc => number(tag.disc_number()),
+      Field::DiscTotal => number(tag.disc_total()),
+      Field::Extension => Some(extension.to_owned()),
+   }
+   .filter(|x| !x.trim().is_empty())
+}
+
+/// Which file system's rules file names have to follow
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Sanitize {
+   /// Only slashes and nulls are replaced
+   Unix,
+   /// Also replaces `<>:"\|?*` and control characters, trims trailing dots and spaces,
+   /// and avoids reserved names like CON and COM1
+   Windows,
+}
+
+impl Sanitize {
+   /// The rules of the system we're running on
+   pub fn native() -> Sanitize {
+      if cfg!(windows) {
+         Sanitize::Windows
+      } else {
+         Sanitize::Unix
+      }
+   }
+
+   /// Makes the text safe to use as a single path component
+   pub fn component(self, text: &str) -> String {
+      let mut name = self.replace_chars(text);
+      if self == Sanitize::Windows {
+         name = name.trim_end_matches(|c| c == '.' || c == ' ').to_owned();
+         let stem = name.split('.').next().unwrap_or("").to_ascii_uppercase();
+         let is_reserved = match stem.as_str() {
+            "CON" | "PRN" | "AUX" | "NUL" => true,
+            x if x.len() == 4 && (x.starts_with("COM") || x.starts_with("LPT")) => x.as_bytes()[3].is_ascii_digit(),
+            _ => false,
+         };
+         if is_reserved {
+            name.insert(0, '_');
+         }
+      }
+      match name.as_str() {
+         "" | "." | ".." => "_".to_owned(),
+         _ => name,
+      }
+   }
+
+   fn replace_chars(self, text: &str) -> String {
+      text
+         .chars()
+         .map(|c| match c {
+            '/' | '\0' => '_',
+            '<' | '>' | ':' | '"' | '\\' | '|' | '?' | '*' if self == Sanitize::Windows => '_',
+            _ if self == Sanitize::Windows && c.is_control() => '_',
+            _ => c,
+         })
+         .collect()
+   }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rename {
+   pub from: PathBuf,
+   pub to: PathBuf,
+}
+
+/// Turns (from, to) pairs into renames that don't collide with each other or with existing files, by adding
+/// " (2)", " (3)", and so on to the file name. Files that are already where they should be are left out.
+pub fn plan_renames(targets: Vec<(PathBuf, PathBuf)>) -> Vec<Rename> {
+   let mut taken = HashSet::new();
+   let mut renames = Vec::new();
+   for (from, to) in targets {
+      if from == to {
+         taken.insert(to);
+         continue;
+      }
+      let mut candidate = to.clone();
+      let mut n = 2;
+      while taken.contains(&candidate) || (candidate != from && candidate.exists()) {
+         candidate = numbered(&to, n);
+         n += 1;
+      }
+      taken.insert(candidate.clone());
+      if candidate != from {
+         renames.push(Rename { from, to: candidate });
+      }
+   }
+   renames
+}
+
+// "a/b.mp3" to "a/b (2).mp3"
+fn numbered(path: &Path, n: u32) -> PathBuf {
+   let stem = path
+      .file_stem()
+      .map(|x| x.to_string_lossy().into_owned())
+      .unwrap_or_default();
+   let name = match path.extension() {
+      Some(ext) => format!("{} ({}).{}", stem, n, ext.to_string_lossy()),
+      None => format!("{} ({})", stem, n),
+   };
+   path.with_file_name(name)
+}
+
+/// Moves the file, creating the directories it goes in. Fails rather than overwrite a file.
+pub fn rename_file(rename: &Rename) -> io::Result<()> {
+   if rename.to.exists() {
+      return Err(io::Error::new(
+         io::ErrorKind::AlreadyExists,
+         "destination already exists",
+      ));
+   }
+   if let Some(parent) = rename.to.parent() {
+      fs::create_dir_all(parent)?;
+   }
+   fs::rename(&rename.from, &rename.to)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PatternError {
+   UnknownField(String),
+   /// A width on a field that isn't a number, or one that isn't a number itself
+   InvalidWidth(String),
+   /// A field with no closing brace, or a closing brace with no field
+   UnmatchedBrace,
+}
+
+impl fmt::Display for PatternError {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      match self {
+         PatternError::UnknownField(x) => write!(f, "unknown field {{{}}}", x),
+         PatternError::InvalidWidth(x) => write!(f, "invalid width in {{{}}}", x),
+         PatternError::UnmatchedBrace => write!(f, "unmatched brace; write {{{{ or }}}} for a literal brace"),
+      }
+   }
+}
+
+impl Error for PatternError {}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+   #[cfg(test)]
+   use crate::id3::{Id3Tag, TagBuilder};
+
+   #[test]
+   fn render_pattern() {
+      let tag: Id3Tag = TagBuilder::new()
+         .artist("AC/DC")
+         .album("Back in Black")
+         .title("What Do You Do for Money Honey?")
+         .track(4, Some(10))
+         .build()
+         .unwrap();
+      let pattern: Pattern = "{artist}/{album}/{track:02} - {title}.{ext}".parse().unwrap();
+      assert_eq!(
+         pattern.render(&tag, "mp3", Sanitize::Unix),
+         Path::new("AC_DC/Back in Black/04 - What Do You Do for Money Honey?.mp3")
+      );
+      assert_eq!(
+         pattern.render(&tag, "mp3", Sanitize::Windows),
+         Path::new("AC_DC/Back in Black/04 - What Do You Do for Money Honey_.mp3")
+      );
+
+      let pattern: Pattern = "{{{genre}}}".parse().unwrap();
+      assert_eq!(pattern.render(&tag, "mp3", Sanitize::Unix), Path::new("{Unknown}"));
+
+      assert_eq!(
+         "{artits}".parse::<Pattern>(),
+         Err(PatternError::UnknownField("artits".to_owned()))
+      );
+      assert_eq!(
+         "{title:02}".parse::<Pattern>(),
+         Err(PatternError::InvalidWidth("title:02".to_owned()))
+      );
+      assert_eq!("{artist".parse::<Pattern>(), Err(PatternError::UnmatchedBrace));
+      assert_eq!("artist}".parse::<Pattern>(), Err(PatternError::UnmatchedBrace));
+      assert_eq!(Sanitize::Windows.component("con.mp3"), "_con.mp3");
+      assert_eq!(Sanitize::Windows.component("Trailing. "), "Trailing");
+   }
+
+   #[test]
+   fn collisions() {
+      let renames = plan_renames(vec![
+         (PathBuf::from("x/1.mp3"), PathBuf::from("x/song.mp3")),
+         (PathBuf::from("x/2.mp3"), PathBuf::from("x/song.mp3")),
+         (PathBuf::from("x/3.mp3"), PathBuf::from("x/3.mp3")),
+      ]);
+      assert_eq!(
+         renames,
+         [
+            Rename {
+               from: PathBuf::from("x/1.mp3"),
+               to: PathBuf::from("x/song.mp3"),
+            },
+            Rename {
+               from: PathBuf::from("x/2.mp3"),
+               to: PathBuf::from("x/song (2).mp3"),
+            },
+         ]
+      );
+   }
+}
@@ -0,0 +1,215 @@
+use crate::id3::v24::{Frame, FrameData};
+use crate::mpeg::LameHeader;
+use std::fmt;
+
+/// The description of the comment iTunes keeps its Sound Check values in
+pub const ITUNNORM: &str = "iTunNORM";
+
+/// Where a gain value was found
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Source {
+   /// A `REPLAYGAIN_*` TXXX frame, as written by foobar2000 and most taggers
+   Txxx,
+   /// An RVA2 frame with a "track" or "album" identification
+   Rva2,
+   /// The LAME header of the first MPEG frame
+   Lame,
+   /// An iTunNORM comment, as iTunes writes for Sound Check
+   ITunNorm,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Gain {
+   /// In dB
+   pub gain: f32,
+   /// 1.0 is full scale
+   pub peak: Option<f32>,
+   pub source: Source,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ReplayGain {
+   pub track: Option<Gain>,
+   pub album: Option<Gain>,
+}
+
+/// Looks for ReplayGain values in TXXX frames, then RVA2 frames, then the LAME header (if given). The track gain
+/// falls back on an iTunNORM comment, which iTunes only writes for tracks.
+pub fn replaygain(frames: &[Frame], lame: Option<&LameHeader>) -> ReplayGain {
+   let lame_track = lame.and_then(|lame| lame_gain(lame.track_gain, lame.peak));
+   // LAME only records the peak of the track
+   let lame_album = lame.and_then(|lame| lame_gain(lame.album_gain, None));
+
+   ReplayGain {
+      track: txxx_gain(frames, "TRACK")
+         .or_else(|| rva2_gain(frames, "track"))
+         .or(lame_track)
+         .or_else(|| itunnorm_gain(frames)),
+      album: txxx_gain(frames, "ALBUM")
+         .or_else(|| rva2_gain(frames, "album"))
+         .or(lame_album),
+   }
+}
+
+fn txxx_gain(frames: &[Frame], kind: &str) -> Option<Gain> {
+   let gain = txxx_value(frames, &format!("REPLAYGAIN_{}_GAIN", kind))?;
+   let peak = txxx_value(frames, &format!("REPLAYGAIN_{}_PEAK", kind));
+
+   Some(Gain {
+      gain: parse_decibels(gain)?,
+      peak: peak.and_then(|x| x.trim().parse().ok()),
+      source: Source::Txxx,
+   })
+}
+
+fn txxx_value<'a>(frames: &'a [Frame], description: &str) -> Option<&'a str> {
+   frames.iter().find_map(|frame| match &frame.data {
+      FrameData::TXXX(txxx) if txxx.description.eq_ignore_ascii_case(description) => {
+         txxx.text.first().map(|x| x.as_ref())
+      }
+      _ => None,
+   })
+}
+
+// e.g. "-6.48 dB"
+fn parse_decibels(text: &str) -> Option<f32> {
+   let text = text.trim();
+   let number = if text.to_ascii_lowercase().ends_with("db") {
+      &text[..text.len() - 2]
+   } else {
+      text
+   };
+   number.trim().parse().ok()
+}
+
+fn rva2_gain(frames: &[Frame], identification: &str) -> Option<Gain> {
+   frames.iter().find_map(|frame| match &frame.data {
+      FrameData::RVA2(rva2) if rva2.identification.eq_ignore_ascii_case(identification) => {
+         // We only care about the master volume
+         rva2.channels.iter().find(|x| x.channel == 1).map(|master| Gain {
+            gain: master.adjustment,
+            peak: master.peak,
+            source: Source::Rva2,
+         })
+      }
+      _ => None,
+   })
+}
+
+fn lame_gain(gain: Option<f32>, peak: Option<f32>) -> Option<Gain> {
+   gain.map(|gain| Gain {
+      gain,
+      peak,
+      source: Source::Lame,
+   })
+}
+
+fn itunnorm_gain(frames: &[Frame]) -> Option<Gain> {
+   let sound_check = frames.iter().find_map(|frame| match &frame.data {
+      FrameData::COMM(x) if x.description == ITUNNORM => x.text.first().and_then(|x| SoundCheck::parse(x)),
+      _ => None,
+   })?;
+   Some(Gain {
+      gain: sound_check.gain(),
+      peak: Some(sound_check.peak()),
+      source: Source::ITunNorm,
+   })
+}
+
+/// The ten fields of an iTunNORM comment, which iTunes writes for Sound Check. Only some are understood: the first
+/// two are the loudness of the left and right channels as 1000 times the power ratio to the reference level (so
+/// louder tracks have larger values), the next two the same with a base of 2500, and the 7th and 8th the peaks of each
+/// channel out of 32768. Sound Check and ReplayGain aim for about the same level, so the gains convert directly.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SoundCheck {
+   pub fields: [u32; 10],
+}
+
+impl SoundCheck {
+   /// Parses the value of the comment: ten hex numbers, separated by spaces
+   pub fn parse(text: &str) -> Option<SoundCheck> {
+      let mut fields = [0; 10];
+      let mut values = text.split_whitespace();
+      for field in fields.iter_mut() {
+         *field = u32::from_str_radix(values.next()?, 16).ok()?;
+      }
+      Some(SoundCheck { fields })
+   }
+
+   /// From a ReplayGain gain in dB and peak (1.0 being full scale). The fields that aren't understood are left 0.
+   pub fn from_replaygain(gain: f32, peak: Option<f32>) -> SoundCheck {
+      let ratio = 10f64.powf(-f64::from(gain) / 10.0);
+      let field = |x: f64| x.round().max(0.0).min(f64::from(u32::max_value())) as u32;
+      let peak = field(f64::from(peak.unwrap_or(0.0)) * 32768.0);
+      let (base_1000, base_2500) = (field(ratio * 1000.0), field(ratio * 2500.0));
+      SoundCheck {
+         fields: [base_1000, base_1000, base_2500, base_2500, 0, 0, peak, peak, 0, 0],
+      }
+   }
+
+   /// The gain in dB that brings the louder channel to the reference level
+   pub fn gain(&self) -> f32 {
+      let loudest = self.fields[0].max(self.fields[1]).max(1);
+      (-10.0 * (f64::from(loudest) / 1000.0).log10()) as f32
+   }
+
+   /// The peak of the louder channel, 1.0 being full scale
+   pub fn peak(&self) -> f32 {
+      self.fields[6].max(self.fields[7]) as f32 / 32768.0
+   }
+}
+
+/// The value of an iTunNORM comment, as iTunes writes it
+impl fmt::Display for SoundCheck {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      for field in self.fields.iter() {
+         write!(f, " {:08X}", field)?;
+      }
+      Ok(())
+   }
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+
+   #[test]
+   fn txxx_before_rva2() {
+      let mut tag = Vec::from(&b"ID3\x04\x00\x00\x00\x00\x00\x3f"[..]);
+      tag.extend_from_slice(b"TXXX\x00\x00\x00\x1f\x00\x00\x03REPLAYGAIN_TRACK_GAIN\x00-6.50 dB");
+      // Album: +2dB on the master volume, with a 16 bit peak of 0.5
+      tag.extend_from_slice(b"RVA2\x00\x00\x00\x0c\x00\x00album\x00\x01\x04\x00\x10\x40\x00");
+      let frames: Vec<_> = crate::id3::parse_slice(&tag, Default::default())
+         .unwrap()
+         .map(Result::unwrap)
+         .collect();
+
+      let replaygain = replaygain(&frames, None);
+      let track = replaygain.track.unwrap();
+      assert_eq!((track.gain, track.source), (-6.5, Source::Txxx));
+      let album = replaygain.album.unwrap();
+      assert_eq!((album.gain, album.peak, album.source), (2.0, Some(0.5), Source::Rva2));
+   }
+
+   #[test]
+   fn sound_check() {
+      let value = " 0000044E 00000400 00000AB3 00000A00 0001EDB0 0001EDB0 00004000 00007E75 00002B95 00002B95";
+      let sound_check = SoundCheck::parse(value).unwrap();
+      assert_eq!(sound_check.to_string(), value);
+      assert!((sound_check.gain() - -0.4218).abs() < 0.001);
+      assert!((sound_check.peak() - 0.9879).abs() < 0.001);
+      assert_eq!(SoundCheck::parse("0000044E 0000044E"), None);
+
+      let converted = SoundCheck::from_replaygain(-6.5, Some(0.5));
+      assert_eq!(&converted.fields[..4], [4467, 4467, 11167, 11167]);
+      assert!((converted.gain() - -6.5).abs() < 0.001);
+      assert_eq!(converted.peak(), 0.5);
+
+      let tag = crate::id3::TagBuilder::new()
+         .frame(FrameData::comment("eng", ITUNNORM, value).unwrap())
+         .build()
+         .unwrap();
+      let track = replaygain(&tag.frames, None).track.unwrap();
+      assert_eq!((track.gain, track.source), (sound_check.gain(), Source::ITunNorm));
+   }
+}
@@ -0,0 +1,266 @@
+use crate::art;
+use crate::genre;
+use crate::id3::{self, ParseOptions, Strictness};
+use crate::probe::{self, Metadata, ProbeError};
+use crate::tag::Tag;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::{Read, Seek, SeekFrom};
+
+/// Statistics about a collection of files, built up one file at a time with `add`
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Report {
+   pub files: u64,
+   /// Files whose metadata couldn't be read. Only their tag versions and ID3v2 tag sizes are counted.
+   pub unreadable: u64,
+   /// Files by the kinds of tags they have, e.g. "ID3v2.4", "ID3v1", or "Vorbis comments". A file with more than one
+   /// kind of tag counts towards each; a file with none counts towards "none".
+   pub tag_versions: BTreeMap<String, u64>,
+   /// Text frames of ID3v2.4 tags by their text encoding
+   pub encodings: BTreeMap<String, u64>,
+   /// With ID3v2.3 style references like "(17)" resolved. A file with several genres counts towards each.
+   pub genres: BTreeMap<String, u64>,
+   pub missing_title: u64,
+   pub missing_artist: u64,
+   pub missing_album: u64,
+   pub missing_art: u64,
+   /// Tags at the start of the file, including their padding
+   pub id3v2_tags: u64,
+   pub id3v2_bytes: u64,
+   /// The size of every embedded picture, added up
+   pub artwork_bytes: u64,
+}
+
+impl Report {
+   /// Adds a file to the report, and returns its metadata for anything else the caller wants from it. A file whose
+   /// metadata can't be read is still counted (see `unreadable`), and the error is returned so that the caller can say
+   /// which file it was.
+   pub fn add<S: Read + Seek>(&mut self, source: &mut S) -> Result<Metadata, ProbeError> {
+      self.files += 1;
+      let mut kinds = self.add_id3(source)?;
+
+      let metadata = match probe::probe(source) {
+         Ok(v) => v,
+         Err(e) => {
+            self.unreadable += 1;
+            self.count_kinds(&kinds);
+            return Err(e);
+         }
+      };
+      kinds.extend(tag_kinds(&metadata));
+      self.count_kinds(&kinds);
+
+      if metadata.title().is_none() {
+         self.missing_title += 1;
+      }
+      if metadata.artist().is_none() {
+         self.missing_artist += 1;
+      }
+      if metadata.album().is_none() {
+         self.missing_album += 1;
+      }
+      for name in metadata.genre().map(genre::parse_references).unwrap_or_default() {
+         *self.genres.entry(name.into_owned()).or_insert(0) += 1;
+      }
+      let pictures = art::pictures(&metadata);
+      if pictures.is_empty() {
+         self.missing_art += 1;
+      }
+      self.artwork_bytes += pictures.iter().map(|x| x.data.len() as u64).sum::<u64>();
+      Ok(metadata)
+   }
+
+   /// Adds the counts of another report, e.g. one built on another thread
+   pub fn merge(&mut self, other: Report) {
+      let merge_counts = |counts: &mut BTreeMap<String, u64>, other: BTreeMap<String, u64>| {
+         for (name, count) in other {
+            *counts.entry(name).or_insert(0) += count;
+         }
+      };
+      self.files += other.files;
+      self.unreadable += other.unreadable;
+      merge_counts(&mut self.tag_versions, other.tag_versions);
+      merge_counts(&mut self.encodings, other.encodings);
+      merge_counts(&mut self.genres, other.genres);
+      self.missing_title += other.missing_title;
+      self.missing_artist += other.missing_artist;
+      self.missing_album += other.missing_album;
+      self.missing_art += other.missing_art;
+      self.id3v2_tags += other.id3v2_tags;
+      self.id3v2_bytes += other.id3v2_bytes;
+      self.artwork_bytes += other.artwork_bytes;
+   }
+
+   /// The average size of an ID3v2 tag, in bytes
+   pub fn average_id3v2_size(&self) -> Option<f64> {
+      if self.id3v2_tags == 0 {
+         None
+      } else {
+         Some(self.id3v2_bytes as f64 / self.id3v2_tags as f64)
+      }
+   }
+
+   // Counts what can be told from the ID3 tags' bytes, which works even for versions we can't parse. Returns the
+   // kinds of tag found.
+   fn add_id3<S: Read + Seek>(&mut self, source: &mut S) -> Result<Vec<&'static str>, ProbeError> {
+      let mut kinds = Vec::new();
+      let file_len = source.seek(SeekFrom::End(0))?;
+      let mut header = Vec::new();
+      source.seek(SeekFrom::Start(0))?;
+      source.take(10).read_to_end(&mut header)?;
+
+      if let Some(len) = id3::tag_len(&header) {
+         kinds.push(match header[3] {
+            2 => "ID3v2.2",
+            3 => "ID3v2.3",
+            4 => "ID3v2.4",
+            _ => "ID3v2 (unknown version)",
+         });
+         self.id3v2_tags += 1;
+         self.id3v2_bytes += len.min(file_len);
+
+         if header[3] == 4 {
+            let mut tag = Vec::new();
+            source.seek(SeekFrom::Start(0))?;
+            source.take(len).read_to_end(&mut tag)?;
+            let options = ParseOptions {
+               strictness: Strictness::Lenient,
+               ..ParseOptions::default()
+            };
+            for frame in id3::parse_raw(&tag, options)
+               .into_iter()
+               .flatten()
+               .filter_map(Result::ok)
+            {
+               let has_encoding = frame.name[0] == b'T' || [b"COMM", b"USLT"].contains(&&frame.name);
+               if has_encoding {
+                  let encoding = match frame.raw_bytes().first() {
+                     Some(0) => "ISO-8859-1",
+                     Some(1) => "UTF-16",
+                     Some(2) => "UTF-16BE",
+                     Some(3) => "UTF-8",
+                     _ => "invalid",
+                  };
+                  *self.encodings.entry(encoding.to_owned()).or_insert(0) += 1;
+               }
+            }
+         }
+      }
+
+      if file_len >= 128 {
+         let mut marker = [0u8; 3];
+         source.seek(SeekFrom::Start(file_len - 128))?;
+         source.read_exact(&mut marker)?;
+         if &marker == b"TAG" {
+            kinds.push("ID3v1");
+         }
+      }
+      Ok(kinds)
+   }
+
+   fn count_kinds(&mut self, kinds: &[&'static str]) {
+      if kinds.is_empty() {
+         *self.tag_versions.entry("none".to_owned()).or_insert(0) += 1;
+      }
+      for kind in kinds.iter() {
+         *self.tag_versions.entry((*kind).to_owned()).or_insert(0) += 1;
+      }
+   }
+}
+
+// The kinds of tag inside the file, which the ID3 tags at either end of it don't cover
+fn tag_kinds(metadata: &Metadata) -> Vec<&'static str> {
+   let mut kinds = Vec::new();
+   let chunk_id3 = match metadata {
+      Metadata::Mpeg(_) => None,
+      Metadata::Adts(x) => {
+         if !x.timed_tags.is_empty() {
+            kinds.push("Timed ID3");
+         }
+         None
+      }
+      Metadata::Flac(x) => {
+         if x.comments.is_some() {
+            kinds.push("Vorbis comments");
+         }
+         None
+      }
+      Metadata::Ogg(_) => {
+         kinds.push("Vorbis comments");
+         None
+      }
+      Metadata::Mp4(_) => {
+         kinds.push("MP4");
+         None
+      }
+      Metadata::Wav(x) => {
+         if !x.info.is_empty() {
+            kinds.push("RIFF INFO");
+         }
+         x.id3.as_ref()
+      }
+      Metadata::Aiff(x) => {
+         if x.name.is_some() || x.author.is_some() || x.copyright.is_some() || !x.annotations.is_empty() {
+            kinds.push("AIFF text chunks");
+         }
+         x.id3.as_ref()
+      }
+      Metadata::Dsd(x) => x.id3.as_ref(),
+      Metadata::Asf(_) => {
+         kinds.push("ASF");
+         None
+      }
+   };
+   if chunk_id3.is_some() {
+      kinds.push("ID3v2 chunk");
+   }
+   kinds
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+   #[cfg(test)]
+   use crate::id3::{write, TagBuilder};
+   #[cfg(test)]
+   use std::io::Cursor;
+
+   #[test]
+   fn aggregate() {
+      let tag = TagBuilder::new()
+         .title("\u{30bf}\u{30a4}\u{30c8}\u{30eb}")
+         .genre("(17)Rock")
+         .apic(write::Picture::front_cover("image/png", &b"\x89PNG"[..]))
+         .build()
+         .unwrap();
+      let mut tagged = write::encode_tag(&tag, 100).unwrap();
+      let tag_len = tagged.len() as u64;
+      tagged.extend_from_slice(&[0xff, 0xfb, 0x90, 0x64]);
+      let mut untagged = vec![0xff, 0xfb, 0x90, 0x64];
+      untagged.resize(200, 0);
+      untagged.extend_from_slice(b"TAG");
+      untagged.resize(328, 0);
+
+      let mut report = Report::default();
+      report.add(&mut Cursor::new(&tagged)).unwrap();
+      report.add(&mut Cursor::new(&untagged)).unwrap();
+      assert!(report
+         .add(&mut Cursor::new(b"ID3\x02\x00\x00\x00\x00\x00\x00"))
+         .is_err());
+
+      assert_eq!(report.files, 3);
+      assert_eq!(report.unreadable, 1);
+      let versions: Vec<(&str, u64)> = report.tag_versions.iter().map(|(k, v)| (k.as_str(), *v)).collect();
+      assert_eq!(versions, [("ID3v1", 1), ("ID3v2.2", 1), ("ID3v2.4", 1)]);
+      assert_eq!(report.encodings.get("UTF-8"), Some(&1));
+      assert_eq!(report.encodings.get("ISO-8859-1"), Some(&1));
+      // The reference and the refinement name the same genre
+      assert_eq!(report.genres.get("Rock"), Some(&1));
+      assert_eq!(report.missing_title, 1);
+      assert_eq!(report.missing_art, 1);
+      assert_eq!(report.artwork_bytes, 4);
+      assert_eq!(report.average_id3v2_size(), Some((tag_len + 10) as f64 / 2.0));
+   }
+}
@@ -0,0 +1,226 @@
+use crate::id3::{self, Id3Tag, TagParseError};
+use crate::tag::{self, Tag};
+use byteorder::{ByteOrder, LittleEndian};
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom};
+
+/// The tags of a WAV file. When both are present, the ID3 tag takes precedence through the `Tag` trait.
+#[derive(Clone, Debug, Default)]
+pub struct Metadata {
+   /// The sub-chunks of the LIST/INFO chunk, e.g. INAM (title) and IART (artist)
+   pub info: Vec<([u8; 4], String)>,
+   /// From an `id3 ` or `ID3 ` chunk
+   pub id3: Option<Id3Tag>,
+   /// Why the ID3 chunk couldn't be read, e.g. it holds an ID3v2.2 tag. The other chunks are read regardless.
+   pub id3_error: Option<TagParseError>,
+}
+
+impl Metadata {
+   pub fn info(&self, id: &[u8; 4]) -> Option<&str> {
+      self.info.iter().find(|(k, _)| k == id).map(|(_, v)| v.as_str())
+   }
+}
+
+#[derive(Debug)]
+pub enum RiffParseError {
+   NotRiff,
+   InvalidChunk,
+   Io(io::Error),
+}
+
+impl fmt::Display for RiffParseError {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      match self {
+         RiffParseError::NotRiff => write!(f, "not a RIFF WAVE file"),
+         RiffParseError::InvalidChunk => write!(f, "chunk extends past the end of its parent"),
+         RiffParseError::Io(e) => write!(f, "I/O error: {}", e),
+      }
+   }
+}
+
+impl Error for RiffParseError {
+   fn source(&self) -> Option<&(dyn Error + 'static)> {
+      match self {
+         RiffParseError::Io(e) => Some(e),
+         _ => None,
+      }
+   }
+}
+
+impl From<io::Error> for RiffParseError {
+   fn from(e: io::Error) -> RiffParseError {
+      RiffParseError::Io(e)
+   }
+}
+
+/// Reads the INFO and ID3 chunks of a WAV file. Every other chunk (including the audio) is skipped by seeking.
+pub fn read_metadata<S: Read + Seek>(source: &mut S) -> Result<Metadata, RiffParseError> {
+   let mut header = [0u8; 12];
+   source.seek(SeekFrom::Start(0))?;
+   source.read_exact(&mut header)?;
+   if &header[0..4] != b"RIFF" || &header[8..12] != b"WAVE" {
+      return Err(RiffParseError::NotRiff);
+   }
+   // The size covers everything after itself
+   let end = 8 + u64::from(LittleEndian::read_u32(&header[4..8]));
+
+   let mut metadata = Metadata::default();
+   let mut pos = 12;
+   while pos + 8 <= end {
+      let mut chunk_header = [0u8; 8];
+      source.read_exact(&mut chunk_header)?;
+      let size = u64::from(LittleEndian::read_u32(&chunk_header[4..8]));
+      if pos + 8 + size > end {
+         return Err(RiffParseError::InvalidChunk);
+      }
+
+      match &chunk_header[0..4] {
+         b"LIST" => {
+            let mut chunk = Vec::new();
+            source.take(size).read_to_end(&mut chunk)?;
+            if chunk.starts_with(b"INFO") {
+               metadata.info = parse_info(&chunk[4..])?;
+            }
+         }
+         b"id3 " | b"ID3 " => match Id3Tag::read(&mut source.take(size), id3::ParseOptions::default()) {
+            Ok(tag) => metadata.id3 = Some(tag),
+            Err(e) => metadata.id3_error = Some(e),
+         },
+         _ => (),
+      }
+
+      // Chunks are padded to an even size
+      pos += 8 + size + (size & 1);
+      source.seek(SeekFrom::Start(pos))?;
+   }
+
+   Ok(metadata)
+}
+
+fn parse_info(mut rest: &[u8]) -> Result<Vec<([u8; 4], String)>, RiffParseError> {
+   let mut info = Vec::new();
+   while rest.len() >= 8 {
+      let mut id = [0u8; 4];
+      id.copy_from_slice(&rest[0..4]);
+      let size = LittleEndian::read_u32(&rest[4..8]) as usize;
+      let value = rest.get(8..8 + size).ok_or(RiffParseError::InvalidChunk)?;
+      info.push((id, decode_text(value)));
+      rest = rest.get(8 + size + (size & 1)..).unwrap_or(&[]);
+   }
+   Ok(info)
+}
+
+/// Text chunks have no declared encoding. Most are ASCII or UTF-8, and the rest are usually Latin-1.
+pub(crate) fn decode_text(bytes: &[u8]) -> String {
+   let end = bytes.iter().position(|x| *x == 0).unwrap_or(bytes.len());
+   let bytes = &bytes[..end];
+   match std::str::from_utf8(bytes) {
+      Ok(text) => text.to_string(),
+      Err(_) => bytes.iter().map(|x| char::from(*x)).collect(),
+   }
+}
+
+impl Tag for Metadata {
+   fn title(&self) -> Option<&str> {
+      self.id3.as_ref().and_then(Tag::title).or_else(|| self.info(b"INAM"))
+   }
+
+   fn artist(&self) -> Option<&str> {
+      self.id3.as_ref().and_then(Tag::artist).or_else(|| self.info(b"IART"))
+   }
+
+   fn album(&self) -> Option<&str> {
+      self.id3.as_ref().and_then(Tag::album).or_else(|| self.info(b"IPRD"))
+   }
+
+   fn album_artist(&self) -> Option<&str> {
+      self.id3.as_ref().and_then(Tag::album_artist)
+   }
+
+   fn genre(&self) -> Option<&str> {
+      self.id3.as_ref().and_then(Tag::genre).or_else(|| self.info(b"IGNR"))
+   }
+
+   fn year(&self) -> Option<u16> {
+      self
+         .id3
+         .as_ref()
+         .and_then(Tag::year)
+         .or_else(|| self.info(b"ICRD").and_then(tag::parse_year))
+   }
+
+   fn track_number(&self) -> Option<u32> {
+      self
+         .id3
+         .as_ref()
+         .and_then(Tag::track_number)
+         .or_else(|| self.info(b"ITRK").and_then(|x| tag::parse_number_pair(x).0))
+   }
+
+   fn track_total(&self) -> Option<u32> {
+      self
+         .id3
+         .as_ref()
+         .and_then(Tag::track_total)
+         .or_else(|| self.info(b"ITRK").and_then(|x| tag::parse_number_pair(x).1))
+   }
+
+   fn disc_number(&self) -> Option<u32> {
+      self.id3.as_ref().and_then(Tag::disc_number)
+   }
+
+   fn disc_total(&self) -> Option<u32> {
+      self.id3.as_ref().and_then(Tag::disc_total)
+   }
+
+   fn comment(&self) -> Option<&str> {
+      self.id3.as_ref().and_then(Tag::comment).or_else(|| self.info(b"ICMT"))
+   }
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+
+   #[test]
+   fn info_and_id3_chunks() {
+      let mut info = Vec::from(&b"INFO"[..]);
+      info.extend_from_slice(b"INAM\x06\x00\x00\x00Title\x00");
+      // Odd sized, so followed by a pad byte
+      info.extend_from_slice(b"IART\x03\x00\x00\x00Bj\xf6\x00");
+      info.extend_from_slice(b"ICRD\x0a\x00\x00\x002001-02-03");
+
+      // An ID3 tag with only a TALB frame
+      let mut id3 = Vec::from(&b"ID3\x04\x00\x00\x00\x00\x00\x11"[..]);
+      id3.extend_from_slice(b"TALB\x00\x00\x00\x07\x00\x00\x03Album\x00");
+
+      let mut file = Vec::from(&b"RIFF\x00\x00\x00\x00WAVE"[..]);
+      file.extend_from_slice(b"data\x01\x00\x00\x00\x00\x00");
+      file.extend_from_slice(b"LIST");
+      file.extend_from_slice(&(info.len() as u32).to_le_bytes());
+      file.extend(info);
+      file.extend_from_slice(b"id3 ");
+      file.extend_from_slice(&(id3.len() as u32).to_le_bytes());
+      let id3_start = file.len();
+      file.extend(id3);
+      let riff_size = file.len() as u32 - 8;
+      file[4..8].copy_from_slice(&riff_size.to_le_bytes());
+
+      let metadata = read_metadata(&mut io::Cursor::new(&file)).unwrap();
+      assert_eq!(metadata.title(), Some("Title"));
+      assert_eq!(metadata.artist(), Some("Bjö"));
+      assert_eq!(metadata.year(), Some(2001));
+      assert_eq!(metadata.album(), Some("Album"));
+
+      // An ID3v2.2 tag can't be read, but the INFO chunk still is
+      file[id3_start + 3] = 2;
+      let metadata = read_metadata(&mut io::Cursor::new(file)).unwrap();
+      assert_eq!(metadata.title(), Some("Title"));
+      assert!(metadata.id3.is_none());
+      match metadata.id3_error {
+         Some(TagParseError::UnsupportedVersion(2)) => (),
+         e => panic!("expected an unsupported version, got {:?}", e),
+      }
+   }
+}
@@ -0,0 +1,511 @@
+use crate::art;
+use crate::cache::ScanCache;
+use crate::copy;
+use crate::id3::v24::FrameData;
+use crate::id3::{Id3Tag, TagBuilder};
+use crate::mpeg;
+use crate::probe::{self, ProbeError};
+use crate::report::Report;
+use crate::tag::{Separators, Tag};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+#[derive(Clone, Debug, Default)]
+pub struct ScanOptions {
+   /// How many files are read at once. 0 means one per CPU.
+   pub threads: usize,
+   pub progress: Option<Progress>,
+}
+
+/// Called as each file is done, with how many are done and how many there are. It's called from the threads doing the
+/// scan, so two calls can come in out of order.
+#[derive(Clone)]
+pub struct Progress(Arc<dyn Fn(usize, usize) + Send + Sync>);
+
+impl Progress {
+   pub fn new<F: Fn(usize, usize) + Send + Sync + 'static>(f: F) -> Progress {
+      Progress(Arc::new(f))
+   }
+}
+
+impl fmt::Debug for Progress {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      write!(f, "Progress")
+   }
+}
+
+impl ScanOptions {
+   fn thread_count(&self, files: usize) -> usize {
+      let threads = if self.threads == 0 {
+         num_cpus::get()
+      } else {
+         self.threads
+      };
+      threads.min(files).max(1)
+   }
+}
+
+/// Calls `f` on every path, spread over a pool of threads, and returns what it returned in the order of the paths
+pub fn map<T, F>(paths: Vec<PathBuf>, options: &ScanOptions, f: F) -> Vec<T>
+where
+   T: Send + 'static,
+   F: Fn(&Path) -> T + Send + Sync + 'static,
+{
+   let len = paths.len();
+   let mut results: Vec<Option<T>> = (0..len).map(|_| None).collect();
+   let per_thread = fold(paths, options, move |found: &mut Vec<(usize, T)>, index, path| {
+      found.push((index, f(path)))
+   });
+   for (index, result) in per_thread.into_iter().flatten() {
+      results[index] = Some(result);
+   }
+   results.into_iter().map(|x| x.expect("every path is scanned")).collect()
+}
+
+/// Folds every path into an accumulator, spread over a pool of threads. Each thread has its own accumulator, and
+/// takes the next path as soon as it's done with the last, so which thread saw which path is up to chance; the
+/// accumulators are returned for the caller to combine. `f` is also given the index of the path.
+pub fn fold<A, F>(paths: Vec<PathBuf>, options: &ScanOptions, f: F) -> Vec<A>
+where
+   A: Default + Send + 'static,
+   F: Fn(&mut A, usize, &Path) + Send + Sync + 'static,
+{
+   let threads = options.thread_count(paths.len());
+   let paths = Arc::new(paths);
+   let f = Arc::new(f);
+   let next = Arc::new(AtomicUsize::new(0));
+   let done = Arc::new(AtomicUsize::new(0));
+   let workers: Vec<_> = (0..threads)
+      .map(|_| {
+         let (paths, f, next, done) = (Arc::clone(&paths), Arc::clone(&f), Arc::clone(&next), Arc::clone(&done));
+         let progress = options.progress.clone();
+         thread::spawn(move || {
+            let mut acc = A::default();
+            loop {
+               let index = next.fetch_add(1, Ordering::Relaxed);
+               match paths.get(index) {
+                  Some(path) => f(&mut acc, index, path),
+                  None => return acc,
+               }
+               if let Some(progress) = &progress {
+                  (progress.0)(done.fetch_add(1, Ordering::Relaxed) + 1, paths.len());
+               }
+            }
+         })
+      })
+      .collect();
+   workers
+      .into_iter()
+      // A panic on any thread is a panic of the scan
+      .map(|x| x.join().unwrap_or_else(|e| std::panic::resume_unwind(e)))
+      .collect()
+}
+
+/// Builds a report of the files in parallel. Files that couldn't be read are returned with why, in the order of the
+/// paths.
+pub fn report(paths: Vec<PathBuf>, options: &ScanOptions) -> (Report, Vec<(PathBuf, ProbeError)>) {
+   let per_thread = fold(
+      paths,
+      options,
+      |(report, failed): &mut (Report, Vec<(usize, PathBuf, ProbeError)>), index, path| {
+         let result = File::open(path)
+            .map_err(ProbeError::from)
+            .and_then(|mut f| report.add(&mut f).map(|_| ()));
+         if let Err(e) = result {
+            failed.push((index, path.to_owned(), e));
+         }
+      },
+   );
+
+   let mut total = Report::default();
+   let mut failed = Vec::new();
+   for (report, thread_failed) in per_thread {
+      total.merge(report);
+      failed.extend(thread_failed);
+   }
+   failed.sort_by_key(|x| x.0);
+   (total, failed.into_iter().map(|(_, path, e)| (path, e)).collect())
+}
+
+/// Like `report`, but files that are in the cache and haven't changed aren't read again. The cache is updated with
+/// the files that were read, tags and all.
+pub fn report_cached(
+   paths: Vec<PathBuf>,
+   options: &ScanOptions,
+   cache: &mut ScanCache,
+) -> (Report, Vec<(PathBuf, ProbeError)>) {
+   let mut total = Report::default();
+   let mut changed = Vec::new();
+   for path in paths {
+      match fs::metadata(&path).ok().and_then(|x| cache.get(&path, &x)) {
+         Some(report) => total.merge(report.clone()),
+         None => changed.push(path),
+      }
+   }
+
+   // Each file gets a report of its own, to be cached along with its tags
+   let scanned = map(changed.clone(), options, |path| {
+      let mut report = Report::default();
+      let result = File::open(path)
+         .and_then(|f| f.metadata().map(|metadata| (f, metadata)))
+         .map_err(ProbeError::from)
+         .and_then(|(mut f, metadata)| report.add(&mut f).map(|x| (metadata, copy::to_id3(&x))));
+      (report, result)
+   });
+   let mut failed = Vec::new();
+   for (path, (report, result)) in changed.into_iter().zip(scanned) {
+      total.merge(report.clone());
+      match result {
+         Ok((metadata, tag)) => cache.insert(path, &metadata, report, tag),
+         // Not cached, so that the error comes up again next time
+         Err(e) => failed.push((path, e)),
+      }
+   }
+   (total, failed)
+}
+
+/// A file in a `Library`
+#[derive(Clone, Debug)]
+pub struct Track {
+   pub path: PathBuf,
+   /// As ID3v2.4 frames (see `copy::to_id3`)
+   pub tag: Id3Tag,
+   pub duration: Option<Duration>,
+   /// Whether the file has any embedded pictures
+   pub has_art: bool,
+}
+
+impl Track {
+   /// Reads the file's tags and duration (see `probe::probe_with_duration`), falling back on the TLEN frame for
+   /// the other formats
+   pub fn read(path: PathBuf) -> Result<Track, ProbeError> {
+      let (metadata, duration) = probe::probe_with_duration(&path)?;
+      let tag = copy::to_id3(&metadata);
+      Ok(Track {
+         has_art: !art::pictures(&metadata).is_empty(),
+         duration: duration.or_else(|| mpeg::tlen(&tag)),
+         path,
+         tag,
+      })
+   }
+
+   /// From a tag alone, so the duration comes from its TLEN frame. Whether the file has art is given rather than
+   /// looked for in the tag, as tags kept without their pictures (like those in the scan cache) would never have any.
+   pub fn from_tag(path: PathBuf, tag: Id3Tag, has_art: bool) -> Track {
+      Track {
+         duration: mpeg::tlen(&tag),
+         path,
+         tag,
+         has_art,
+      }
+   }
+}
+
+/// The tracks of one disc of an album
+#[derive(Clone, Debug)]
+pub struct Album {
+   /// The album artist (TPE2), or if the tracks don't have one, the artist (TPE1), as players group them
+   pub album_artist: Option<String>,
+   pub album: Option<String>,
+   pub disc: Option<u32>,
+   /// In order of track number, then path. Tracks without a number come last.
+   pub tracks: Vec<Track>,
+}
+
+impl Album {
+   /// The total a track gives (TRCK), or failing that, the highest track number
+   pub fn track_total(&self) -> Option<u32> {
+      self
+         .tracks
+         .iter()
+         .filter_map(|x| x.tag.track_total())
+         .max()
+         .or_else(|| self.tracks.iter().filter_map(|x| x.tag.track_number()).max())
+   }
+
+   /// The track numbers from 1 to the total that no track has. A total past `MAX_TRACK_TOTAL` is taken to be junk, and
+   /// the highest track number is used instead.
+   pub fn missing_tracks(&self) -> Vec<u32> {
+      let numbers: Vec<u32> = self.tracks.iter().filter_map(|x| x.tag.track_number()).collect();
+      let total = match self.track_total() {
+         Some(x) if x <= MAX_TRACK_TOTAL => x,
+         _ => numbers
+            .iter()
+            .cloned()
+            .filter(|x| *x <= MAX_TRACK_TOTAL)
+            .max()
+            .unwrap_or(0),
+      };
+      (1..=total).filter(|x| !numbers.contains(x)).collect()
+   }
+
+   /// The durations of the tracks added up, or `None` if any isn't known
+   pub fn duration(&self) -> Option<Duration> {
+      self.tracks.iter().map(|x| x.duration).sum()
+   }
+
+   /// Whether any track has embedded art
+   pub fn has_art(&self) -> bool {
+      self.tracks.iter().any(|x| x.has_art)
+   }
+}
+
+/// Scanned tracks grouped into albums, by album artist, album, and disc (see `Album`)
+#[derive(Clone, Debug, Default)]
+pub struct Library {
+   /// In order of album artist, album, and disc. Tracks with no album artist or album are grouped as if that were a
+   /// value of its own, and come first.
+   pub albums: Vec<Album>,
+}
+
+impl Library {
+   pub fn new<I: IntoIterator<Item = Track>>(tracks: I) -> Library {
+      let mut albums: BTreeMap<(Option<String>, Option<String>, Option<u32>), Vec<Track>> = BTreeMap::new();
+      for track in tracks {
+         let album_artist = track
+            .tag
+            .album_artist()
+            .or_else(|| track.tag.artist())
+            .map(str::to_owned);
+         let key = (
+            album_artist,
+            track.tag.album().map(str::to_owned),
+            track.tag.disc_number(),
+         );
+         albums.entry(key).or_default().push(track);
+      }
+      Library {
+         albums: albums
+            .into_iter()
+            .map(|((album_artist, album, disc), mut tracks)| {
+               tracks.sort_by(|a, b| {
+                  let number = |x: &Track| x.tag.track_number().unwrap_or(u32::max_value());
+                  number(a).cmp(&number(b)).then_with(|| a.path.cmp(&b.path))
+               });
+               Album {
+                  album_artist,
+                  album,
+                  disc,
+                  tracks,
+               }
+            })
+            .collect(),
+      }
+   }
+
+   /// Reads the files in parallel (see `Track::read`). Files that couldn't be read are returned with why, in the
+   /// order of the paths.
+   pub fn scan(paths: Vec<PathBuf>, options: &ScanOptions) -> (Library, Vec<(PathBuf, ProbeError)>) {
+      let mut tracks = Vec::new();
+      let mut failed = Vec::new();
+      for (path, result) in paths
+         .clone()
+         .into_iter()
+         .zip(map(paths, options, |x| Track::read(x.to_owned())))
+      {
+         match result {
+            Ok(v) => tracks.push(v),
+            Err(e) => failed.push((path, e)),
+         }
+      }
+      (Library::new(tracks), failed)
+   }
+}
+
+/// The album artist of compilations
+pub const VARIOUS_ARTISTS: &str = "Various Artists";
+
+/// More tracks than any album has, so `Album::missing_tracks` doesn't list billions of tracks for a TRCK like
+/// "1/4000000000"
+pub const MAX_TRACK_TOTAL: u32 = 999;
+
+/// An album whose tracks have different artists, but that isn't tagged as a compilation (see `find_compilations`)
+#[derive(Clone, Debug, PartialEq)]
+pub struct Compilation {
+   pub album: String,
+   /// The tracks, by path
+   pub paths: Vec<PathBuf>,
+   /// The artists of the tracks, each once, in the order they first come
+   pub artists: Vec<String>,
+}
+
+impl Compilation {
+   /// Tags a track of the album as a compilation, with "Various Artists" as the album artist (TPE2) and the
+   /// compilation flag (TCMP) set, as iTunes and most players group them
+   pub fn update_tag(&self, tag: &mut Id3Tag) {
+      let update = TagBuilder::new()
+         .album_artist(VARIOUS_ARTISTS)
+         .frame(FrameData::TCMP(true))
+         .build()
+         .expect("the frames are valid");
+      tag.apply_update(update);
+   }
+}
+
+/// Finds the albums that are likely compilations: tracks in the same directory with the same album (TALB) but
+/// different artists (TPE1). Only the first artist of a track counts, as split by the separators, so that featured
+/// artists don't make an album a compilation. Albums already tagged as compilations are left out, and so are albums
+/// whose tracks agree on some other album artist, since players already group those under it.
+pub fn find_compilations<'a, I>(tracks: I, separators: &Separators) -> Vec<Compilation>
+where
+   I: IntoIterator<Item = (&'a Path, &'a Id3Tag)>,
+{
+   let mut albums: BTreeMap<(&Path, &str), Vec<(&Path, &Id3Tag)>> = BTreeMap::new();
+   for (path, tag) in tracks {
+      if let Some(album) = tag.album() {
+         let directory = path.parent().unwrap_or_else(|| Path::new(""));
+         albums.entry((directory, album)).or_default().push((path, tag));
+      }
+   }
+
+   let mut compilations = Vec::new();
+   for ((_, album), mut tracks) in albums {
+      tracks.sort_by_key(|x| x.0);
+      let mut artists: Vec<&str> = Vec::new();
+      for (_, tag) in tracks.iter() {
+         if let Some(artist) = tag.artists(separators).first() {
+            if !artists.iter().any(|x| x.to_lowercase() == artist.to_lowercase()) {
+               artists.push(artist);
+            }
+         }
+      }
+      if artists.len() < 2 {
+         continue;
+      }
+
+      let album_artist = tracks[0].1.album_artist();
+      let shares_album_artist = album_artist.is_some() && tracks.iter().all(|x| x.1.album_artist() == album_artist);
+      let is_tagged = tracks.iter().all(|(_, tag)| {
+         tag.frames.iter().any(|x| match x.data {
+            FrameData::TCMP(x) => x,
+            _ => false,
+         })
+      });
+      if is_tagged || (shares_album_artist && album_artist != Some(VARIOUS_ARTISTS)) {
+         continue;
+      }
+      compilations.push(Compilation {
+         album: album.to_owned(),
+         paths: tracks.iter().map(|x| x.0.to_owned()).collect(),
+         artists: artists.into_iter().map(str::to_owned).collect(),
+      });
+   }
+   compilations
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+
+   #[test]
+   fn parallel_map() {
+      let paths: Vec<PathBuf> = (0..100).map(|x| PathBuf::from(x.to_string())).collect();
+      let calls = Arc::new(AtomicUsize::new(0));
+      let options = ScanOptions {
+         threads: 4,
+         progress: Some(Progress::new({
+            let calls = Arc::clone(&calls);
+            move |done, total| {
+               assert!(done >= 1 && done <= total && total == 100);
+               calls.fetch_add(1, Ordering::Relaxed);
+            }
+         })),
+      };
+      let mapped = map(paths.clone(), &options, |path| path.to_owned());
+      assert_eq!(mapped, paths);
+      assert_eq!(calls.load(Ordering::Relaxed), 100);
+
+      let counts = fold(paths, &options, |count: &mut usize, _, _| *count += 1);
+      assert!(counts.len() <= 4);
+      assert_eq!(counts.iter().sum::<usize>(), 100);
+
+      let (report, failed) = report(vec![PathBuf::from("/nonexistent")], &ScanOptions::default());
+      assert_eq!(report.files, 0);
+      assert_eq!(failed.len(), 1);
+   }
+
+   #[test]
+   fn compilations() {
+      let track = |album: &str, artist: &str| TagBuilder::new().album(album).artist(artist).build().unwrap();
+      let mut tags = vec![
+         ("hits/1.mp3", track("Hits", "A")),
+         ("hits/2.mp3", track("Hits", "B")),
+         ("solo/1.mp3", track("Solo", "A")),
+         ("solo/2.mp3", track("Solo", "a feat. B")),
+         ("other/1.mp3", track("Hits", "C")),
+      ];
+      let found = |tags: &[(&str, Id3Tag)]| {
+         find_compilations(tags.iter().map(|x| (Path::new(x.0), &x.1)), &Separators::default())
+      };
+      let compilations = found(&tags);
+      assert_eq!(compilations.len(), 1);
+      assert_eq!(compilations[0].album, "Hits");
+      assert_eq!(
+         compilations[0].paths,
+         [Path::new("hits/1.mp3"), Path::new("hits/2.mp3")]
+      );
+      assert_eq!(compilations[0].artists, ["A", "B"]);
+
+      let compilation = compilations[0].clone();
+      for (_, tag) in tags.iter_mut().take(2) {
+         compilation.update_tag(tag);
+      }
+      assert_eq!(tags[0].1.album_artist(), Some(VARIOUS_ARTISTS));
+      assert!(found(&tags).is_empty());
+
+      // Tagged as a compilation, though without an album artist
+      let tagged = |artist: &str| {
+         TagBuilder::new()
+            .album("Mix")
+            .artist(artist)
+            .frame(FrameData::TCMP(true))
+            .build()
+            .unwrap()
+      };
+      assert!(found(&[("mix/1.mp3", tagged("A")), ("mix/2.mp3", tagged("B"))]).is_empty());
+   }
+
+   #[test]
+   fn albums() {
+      let track = |path: &str, album: &str, number: &str, ms: &str, has_art: bool| {
+         let tag = TagBuilder::new()
+            .artist("Artist")
+            .album(album)
+            .frame(FrameData::from_text(*b"TRCK", number).unwrap())
+            .frame(FrameData::from_text(*b"TLEN", ms).unwrap())
+            .build()
+            .unwrap();
+         Track::from_tag(PathBuf::from(path), tag, has_art)
+      };
+      let library = Library::new(vec![
+         track("b/4.mp3", "B", "4/5", "1000", false),
+         track("a/1.mp3", "A", "1", "1000", true),
+         track("b/1.mp3", "B", "1/5", "2500", false),
+      ]);
+      assert_eq!(library.albums.len(), 2);
+      let album = &library.albums[1];
+      assert_eq!(
+         (album.album_artist.as_ref().unwrap().as_str(), album.disc),
+         ("Artist", None)
+      );
+      assert_eq!(album.tracks[0].path, Path::new("b/1.mp3"));
+      assert_eq!(album.track_total(), Some(5));
+      assert_eq!(album.missing_tracks(), [2, 3, 5]);
+      assert_eq!(album.duration(), Some(Duration::from_millis(3500)));
+      assert!(!album.has_art());
+      assert!(library.albums[0].has_art());
+      assert!(library.albums[0].missing_tracks().is_empty());
+
+      let library = Library::new(vec![
+         track("c/1.mp3", "C", "1/4000000000", "1000", false),
+         track("c/3.mp3", "C", "3/4000000000", "1000", false),
+      ]);
+      assert_eq!(library.albums[0].missing_tracks(), [2]);
+   }
+}
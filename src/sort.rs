@@ -0,0 +1,286 @@
+use crate::id3::v24::{Frame, FrameData};
+use crate::id3::Id3Tag;
+use crate::query::frame_texts;
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use unicode_normalization::char::{decompose_canonical, is_combining_mark};
+
+/// Each sort frame, with the frame whose values it sorts
+pub const SORT_FRAMES: [([u8; 4], [u8; 4]); 5] = [
+   (*b"TSOP", *b"TPE1"),
+   (*b"TSO2", *b"TPE2"),
+   (*b"TSOA", *b"TALB"),
+   (*b"TSOT", *b"TIT2"),
+   (*b"TSOC", *b"TCOM"),
+];
+
+/// The language whose rules sort keys follow: which leading articles are moved, and how letters are transliterated
+/// and collated
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Locale {
+   English,
+   German,
+   French,
+   Spanish,
+   Italian,
+   Dutch,
+   Swedish,
+}
+
+impl Default for Locale {
+   fn default() -> Locale {
+      Locale::English
+   }
+}
+
+impl Locale {
+   /// The leading words that are moved to the end, e.g. "The Beatles" sorts as "Beatles, The". An article ending in
+   /// an apostrophe is elided onto the word after it, as in "L'Arc".
+   pub fn articles(self) -> &'static [&'static str] {
+      match self {
+         Locale::English => &["The", "An", "A"],
+         Locale::German => &["Der", "Die", "Das"],
+         Locale::French => &["Les", "Le", "La", "L'"],
+         Locale::Spanish => &["Los", "Las", "El", "La"],
+         Locale::Italian => &["Il", "Lo", "La", "Gli", "Le", "I", "L'"],
+         Locale::Dutch => &["De", "Het"],
+         // Swedish articles are suffixes
+         Locale::Swedish => &[],
+      }
+   }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct UnknownLocale(pub String);
+
+impl fmt::Display for UnknownLocale {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      write!(f, "unknown locale {:?}", self.0)
+   }
+}
+
+impl Error for UnknownLocale {}
+
+impl FromStr for Locale {
+   type Err = UnknownLocale;
+
+   /// Parses a language code, e.g. "de" or "de-AT". The region is ignored.
+   fn from_str(s: &str) -> Result<Locale, UnknownLocale> {
+      let language = s.split(|x| x == '-' || x == '_').next().unwrap_or("");
+      Ok(match &language.to_ascii_lowercase()[..] {
+         "en" => Locale::English,
+         "de" => Locale::German,
+         "fr" => Locale::French,
+         "es" => Locale::Spanish,
+         "it" => Locale::Italian,
+         "nl" => Locale::Dutch,
+         "sv" => Locale::Swedish,
+         _ => return Err(UnknownLocale(s.to_owned())),
+      })
+   }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SortOptions {
+   pub locale: Locale,
+   /// Whether letters are transliterated to ASCII (see `transliterate`), for players that sort by code point
+   pub transliterate: bool,
+}
+
+impl Default for SortOptions {
+   fn default() -> SortOptions {
+      SortOptions {
+         locale: Locale::default(),
+         transliterate: true,
+      }
+   }
+}
+
+// The text after a leading article, and the article as written
+fn split_article(text: &str, locale: Locale) -> Option<(&str, &str)> {
+   locale.articles().iter().find_map(|article| {
+      let prefix = text.get(..article.len())?;
+      let rest = &text[article.len()..];
+      if !prefix.eq_ignore_ascii_case(article) {
+         return None;
+      }
+      let rest = if article.ends_with('\'') {
+         rest
+      } else if rest.starts_with(' ') {
+         rest.trim_start()
+      } else {
+         return None;
+      };
+      if rest.is_empty() {
+         None
+      } else {
+         Some((rest, prefix))
+      }
+   })
+}
+
+/// Spells letters in plain ASCII where there's an obvious way to: accents are dropped, so "Björk" becomes "Bjork",
+/// ligatures and letters like "ß" are spelled out, and in German umlauts become "ae", "oe", and "ue". Letters without
+/// an ASCII spelling, such as those of other scripts, are kept.
+pub fn transliterate(text: &str, locale: Locale) -> Cow<'_, str> {
+   if text.is_ascii() {
+      return Cow::Borrowed(text);
+   }
+   let mut transliterated = String::with_capacity(text.len());
+   for c in text.chars() {
+      let spelled = match (c, locale) {
+         ('ä', Locale::German) => "ae",
+         ('ö', Locale::German) => "oe",
+         ('ü', Locale::German) => "ue",
+         ('Ä', Locale::German) => "Ae",
+         ('Ö', Locale::German) => "Oe",
+         ('Ü', Locale::German) => "Ue",
+         ('ß', _) => "ss",
+         ('æ', _) => "ae",
+         ('Æ', _) => "AE",
+         ('œ', _) => "oe",
+         ('Œ', _) => "OE",
+         ('ø', _) => "o",
+         ('Ø', _) => "O",
+         ('ł', _) => "l",
+         ('Ł', _) => "L",
+         ('đ', _) | ('ð', _) => "d",
+         ('Đ', _) | ('Ð', _) => "D",
+         ('þ', _) => "th",
+         ('Þ', _) => "Th",
+         ('ı', _) => "i",
+         _ => {
+            decompose_canonical(c, |x| {
+               if !is_combining_mark(x) {
+                  transliterated.push(x);
+               }
+            });
+            continue;
+         }
+      };
+      transliterated.push_str(spelled);
+   }
+   Cow::Owned(transliterated)
+}
+
+/// The value of a sort frame for a display value: a leading article is moved to the end, so "The Beatles" becomes
+/// "Beatles, The", and if `options.transliterate`, the letters are transliterated (see `transliterate`)
+pub fn sort_name<'a>(text: &'a str, options: &SortOptions) -> Cow<'a, str> {
+   let name = match split_article(text, options.locale) {
+      Some((rest, article)) => Cow::Owned(format!("{}, {}", rest, article)),
+      None => Cow::Borrowed(text),
+   };
+   if !options.transliterate {
+      return name;
+   }
+   match name {
+      Cow::Borrowed(x) => transliterate(x, options.locale),
+      Cow::Owned(x) => Cow::Owned(transliterate(&x, options.locale).into_owned()),
+   }
+}
+
+/// A key that puts display values in the order a person would expect when the keys are compared as plain strings,
+/// e.g. for sorting a library in a listing. Articles and punctuation are ignored, case and accents only break ties,
+/// and numbers compare by value, so "Track 2" comes before "Track 10". Letters go where the locale puts them: "ä" sorts
+/// as "a" in English, as "ae" in German, and after "z" in Swedish.
+pub fn collation_key(text: &str, locale: Locale) -> String {
+   let words = split_article(text, locale).map_or(text, |x| x.0);
+   let mut key = String::with_capacity(words.len());
+   let mut digits = String::new();
+   for c in words.chars().flat_map(char::to_lowercase) {
+      if c.is_ascii_digit() {
+         digits.push(c);
+         continue;
+      }
+      if !digits.is_empty() {
+         key.push_str(&format!("{:0>20}", digits));
+         digits.clear();
+      }
+      // Characters after "z", in the order of the Swedish alphabet
+      match (c, locale) {
+         ('å', Locale::Swedish) => key.push('{'),
+         ('ä', Locale::Swedish) | ('æ', Locale::Swedish) => key.push('|'),
+         ('ö', Locale::Swedish) | ('ø', Locale::Swedish) => key.push('}'),
+         (c, _) if c.is_alphanumeric() || c == ' ' => {
+            let mut buffer = [0; 4];
+            key.push_str(&transliterate(c.encode_utf8(&mut buffer), locale));
+         }
+         _ => (),
+      }
+   }
+   if !digits.is_empty() {
+      key.push_str(&format!("{:0>20}", digits));
+   }
+   // The text itself breaks ties. The null sorts before anything else, so a key that starts another sorts first.
+   key.push('\0');
+   key.push_str(text);
+   key
+}
+
+/// Adds the sort frames the tag doesn't have, from the values of the frames they sort (see `SORT_FRAMES` and
+/// `sort_name`). Existing sort frames are left alone, since they may have been set by hand. Returns the names of the
+/// frames that were added.
+pub fn add_sort_frames(tag: &mut Id3Tag, options: &SortOptions) -> Vec<[u8; 4]> {
+   let mut added = Vec::new();
+   for (sort_frame, source) in SORT_FRAMES.iter() {
+      if tag.frames.iter().any(|x| x.data.name() == *sort_frame) {
+         continue;
+      }
+      let values: Vec<String> = match tag.frames.iter().find(|x| x.data.name() == *source) {
+         Some(frame) => frame_texts(&frame.data)
+            .iter()
+            .map(|x| sort_name(x, options).into_owned())
+            .collect(),
+         None => continue,
+      };
+      if values.iter().all(|x| x.is_empty()) {
+         continue;
+      }
+      if let Ok(data) = FrameData::from_text(*sort_frame, &values.join("\0")) {
+         tag.frames.push(Frame::new(data));
+         added.push(*sort_frame);
+      }
+   }
+   added
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+
+   #[test]
+   fn sort_keys() {
+      let options = SortOptions::default();
+      assert_eq!(sort_name("The Beatles", &options), "Beatles, The");
+      assert_eq!(sort_name("Björk", &options), "Bjork");
+      assert_eq!(sort_name("Theatre of Tragedy", &options), "Theatre of Tragedy");
+      assert_eq!(sort_name("A", &options), "A");
+      let german = SortOptions {
+         locale: Locale::German,
+         transliterate: true,
+      };
+      assert_eq!(sort_name("Die Ärzte", &german), "Aerzte, Die");
+      let french = SortOptions {
+         locale: "fr-CA".parse().unwrap(),
+         transliterate: false,
+      };
+      assert_eq!(sort_name("L'Été", &french), "Été, L'");
+
+      let mut titles = vec!["Track 10", "track 2", "The Track 3", "Zebra", "Äpple"];
+      titles.sort_by_key(|x| collation_key(x, Locale::English));
+      assert_eq!(titles, ["Äpple", "track 2", "The Track 3", "Track 10", "Zebra"]);
+      titles.sort_by_key(|x| collation_key(x, Locale::Swedish));
+      assert_eq!(titles, ["The Track 3", "track 2", "Track 10", "Zebra", "Äpple"]);
+
+      let mut tag = crate::id3::TagBuilder::new()
+         .artist("The Beatles")
+         .title("Help!")
+         .frame(FrameData::from_text(*b"TSOT", "Help").unwrap())
+         .build()
+         .unwrap();
+      assert_eq!(add_sort_frames(&mut tag, &options), [*b"TSOP"]);
+      assert_eq!(frame_texts(&tag.frames[3].data), ["Beatles, The"]);
+   }
+}
@@ -0,0 +1,213 @@
+use crate::id3;
+use byteorder::{ByteOrder, LittleEndian};
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+const ID3V1_LEN: u64 = 128;
+const APE_FOOTER_LEN: u64 = 32;
+// In the APE footer flags
+const APE_HAS_HEADER: u32 = 1 << 31;
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct StripOptions {
+   /// Also remove APEv1/APEv2 tags. They're left alone by default, as some players keep ReplayGain there.
+   pub ape: bool,
+}
+
+/// Where the tags of a file are, and so where the audio is
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TagLayout {
+   /// The first byte after any ID3v2 tags at the start
+   pub audio_start: u64,
+   /// The first byte of the tags at the end, or the file length if there are none
+   pub audio_end: u64,
+   /// How many ID3v2 tags were found, at the start and at the end
+   pub id3v2_tags: u32,
+   pub id3v1_tag: bool,
+   pub ape_tag: bool,
+}
+
+impl TagLayout {
+   pub fn has_tags(&self) -> bool {
+      self.id3v2_tags > 0 || self.id3v1_tag || self.ape_tag
+   }
+}
+
+/// Finds the ID3v2 tags at the start of the source, and the ID3v2, ID3v1, and (if `options.ape` is set) APE tags
+/// at the end. The tags at the end can come in any order.
+pub fn find_tags<S: Read + Seek>(source: &mut S, options: &StripOptions) -> io::Result<TagLayout> {
+   let file_len = source.seek(SeekFrom::End(0))?;
+   let mut layout = TagLayout {
+      audio_end: file_len,
+      ..TagLayout::default()
+   };
+
+   // Some taggers prepend a new tag without removing the old one
+   let mut header = [0u8; 10];
+   while layout.audio_start + 10 <= file_len {
+      source.seek(SeekFrom::Start(layout.audio_start))?;
+      source.read_exact(&mut header)?;
+      match id3::tag_len(&header) {
+         Some(len) if header[3] >= 2 && header[3] <= 4 && layout.audio_start + len <= file_len => {
+            layout.audio_start += len;
+            layout.id3v2_tags += 1;
+         }
+         _ => break,
+      }
+   }
+
+   loop {
+      let end = layout.audio_end;
+      // Tags that would reach back into the audio start (or the tags before it) aren't really tags
+      let available = end - layout.audio_start;
+      let fits = |len: &u64| *len <= available;
+      if !layout.id3v1_tag && available >= ID3V1_LEN && marker_at(source, end - ID3V1_LEN, b"TAG")? {
+         layout.audio_end -= ID3V1_LEN;
+         layout.id3v1_tag = true;
+      } else if let Some(len) = appended_id3v2_len(source, end, available)?.filter(fits) {
+         layout.audio_end -= len;
+         layout.id3v2_tags += 1;
+      } else if let (true, Some(len)) = (options.ape, ape_len(source, end, available)?.filter(fits)) {
+         layout.audio_end -= len;
+         layout.ape_tag = true;
+      } else {
+         break;
+      }
+   }
+
+   Ok(layout)
+}
+
+/// Removes the tags that `find_tags` finds, leaving only the audio. The file is replaced as with `rewrite_file`, and
+/// a file with no tags isn't touched.
+pub fn remove_tags<P: AsRef<Path>>(path: P, options: &StripOptions) -> io::Result<TagLayout> {
+   let path = path.as_ref();
+   let mut source = File::open(path)?;
+   let layout = find_tags(&mut source, options)?;
+   if !layout.has_tags() {
+      return Ok(layout);
+   }
+
+   rewrite_file(path, source, |source, temp| {
+      source.seek(SeekFrom::Start(layout.audio_start))?;
+      io::copy(&mut source.take(layout.audio_end - layout.audio_start), temp)?;
+      Ok(())
+   })?;
+   Ok(layout)
+}
+
+/// Replaces the file at `path` (opened as `source`) with whatever `write` writes. The new contents go to a temporary
+/// file next to the original, which then replaces it, so the file is never left half written.
+pub(crate) fn rewrite_file<F>(path: &Path, mut source: File, write: F) -> io::Result<()>
+where
+   F: FnOnce(&mut File, &mut File) -> io::Result<()>,
+{
+   let temp_path = temp_path(path);
+   let result: io::Result<()> = try {
+      let mut temp = File::create(&temp_path)?;
+      write(&mut source, &mut temp)?;
+      temp.flush()?;
+      temp.sync_all()?;
+      fs::set_permissions(&temp_path, source.metadata()?.permissions())?;
+      drop(source);
+      fs::rename(&temp_path, path)?;
+   };
+   if let Err(e) = result {
+      let _ = fs::remove_file(&temp_path);
+      return Err(e);
+   }
+   Ok(())
+}
+
+// In the same directory, so that the rename can't cross file systems
+fn temp_path(path: &Path) -> PathBuf {
+   let mut name = std::ffi::OsString::from(".");
+   name.push(path.file_name().unwrap_or_default());
+   name.push(".walnut-tmp");
+   path.with_file_name(name)
+}
+
+pub(crate) fn marker_at<S: Read + Seek>(source: &mut S, pos: u64, marker: &[u8]) -> io::Result<bool> {
+   let mut buf = [0u8; 8];
+   let buf = &mut buf[..marker.len()];
+   source.seek(SeekFrom::Start(pos))?;
+   source.read_exact(buf)?;
+   Ok(buf == marker)
+}
+
+// The length of an ID3v2 tag that ends with a footer, including its header and footer
+pub(crate) fn appended_id3v2_len<S: Read + Seek>(source: &mut S, end: u64, available: u64) -> io::Result<Option<u64>> {
+   if available < 20 {
+      return Ok(None);
+   }
+   let mut footer = [0u8; 10];
+   source.seek(SeekFrom::Start(end - 10))?;
+   source.read_exact(&mut footer)?;
+   if &footer[0..3] != b"3DI" {
+      return Ok(None);
+   }
+   // The footer is a copy of the header, apart from the identifier
+   footer[0..3].copy_from_slice(b"ID3");
+   Ok(id3::tag_len(&footer))
+}
+
+// The length of an APE tag that ends with a footer, including its header if it has one
+pub(crate) fn ape_len<S: Read + Seek>(source: &mut S, end: u64, available: u64) -> io::Result<Option<u64>> {
+   if available < APE_FOOTER_LEN {
+      return Ok(None);
+   }
+   let mut footer = [0u8; APE_FOOTER_LEN as usize];
+   source.seek(SeekFrom::Start(end - APE_FOOTER_LEN))?;
+   source.read_exact(&mut footer)?;
+   if &footer[0..8] != b"APETAGEX" {
+      return Ok(None);
+   }
+   // The size covers the items and the footer, but not the header
+   let size = u64::from(LittleEndian::read_u32(&footer[12..16]));
+   let flags = LittleEndian::read_u32(&footer[20..24]);
+   let header_len = if flags & APE_HAS_HEADER != 0 { APE_FOOTER_LEN } else { 0 };
+   Ok(Some(size.max(APE_FOOTER_LEN) + header_len))
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+   #[cfg(test)]
+   use std::io::Cursor;
+
+   #[test]
+   fn tags_around_audio() {
+      let id3v2 = b"ID3\x04\x00\x00\x00\x00\x00\x10TIT2\x00\x00\x00\x06\x00\x00\x03Title";
+      let audio = [0xffu8; 100];
+      let mut ape = b"APETAGEX\xd0\x07\x00\x00\x20\x00\x00\x00".to_vec();
+      ape.extend_from_slice(&[0; 16]);
+      let mut id3v1 = b"TAG".to_vec();
+      id3v1.resize(128, 0);
+
+      let mut file = id3v2.to_vec();
+      file.extend_from_slice(&audio);
+      file.extend_from_slice(&ape);
+      file.extend_from_slice(&id3v1);
+
+      let layout = find_tags(&mut Cursor::new(&file), &StripOptions::default()).unwrap();
+      assert_eq!(
+         layout,
+         TagLayout {
+            audio_start: id3v2.len() as u64,
+            audio_end: (id3v2.len() + audio.len() + ape.len()) as u64,
+            id3v2_tags: 1,
+            id3v1_tag: true,
+            ape_tag: false,
+         }
+      );
+
+      let layout = find_tags(&mut Cursor::new(&file), &StripOptions { ape: true }).unwrap();
+      assert_eq!(layout.audio_end, (id3v2.len() + audio.len()) as u64);
+      assert!(layout.ape_tag);
+
+      let layout = find_tags(&mut Cursor::new(&audio[..]), &StripOptions { ape: true }).unwrap();
+      assert!(!layout.has_tags());
+      assert_eq!((layout.audio_start, layout.audio_end), (0, audio.len() as u64));
+   }
+}
@@ -0,0 +1,138 @@
+/// The metadata that every tag format can hold, whatever it calls it
+pub trait Tag {
+   fn title(&self) -> Option<&str>;
+   fn artist(&self) -> Option<&str>;
+   fn album(&self) -> Option<&str>;
+   fn album_artist(&self) -> Option<&str>;
+   fn genre(&self) -> Option<&str>;
+   fn year(&self) -> Option<u16>;
+   fn track_number(&self) -> Option<u32>;
+   fn track_total(&self) -> Option<u32>;
+   fn disc_number(&self) -> Option<u32>;
+   fn disc_total(&self) -> Option<u32>;
+   fn comment(&self) -> Option<&str>;
+
+   /// Every artist value as stored. Formats that can hold several (ID3v2.4, Vorbis comments, and APEv2) give them
+   /// all; the rest give the one `artist` does.
+   fn artist_values(&self) -> Vec<&str> {
+      self.artist().into_iter().collect()
+   }
+
+   /// Every genre value as stored, as in `artist_values`
+   fn genre_values(&self) -> Vec<&str> {
+      self.genre().into_iter().collect()
+   }
+
+   /// Every artist, with each value split on the separators, as taggers before ID3v2.4 had to fit several artists
+   /// into one
+   fn artists(&self, separators: &Separators) -> Vec<&str> {
+      separators.split_all(self.artist_values())
+   }
+
+   /// Every genre, split as in `artists`
+   fn genres(&self, separators: &Separators) -> Vec<&str> {
+      separators.split_all(self.genre_values())
+   }
+}
+
+/// What to split a single artist or genre value on. Separators are matched without regard to ASCII case, and the
+/// parts are trimmed, with empty ones left out.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Separators {
+   pub separators: Vec<String>,
+   /// Names that have a separator in them but aren't to be split, like "AC/DC"
+   pub exceptions: Vec<String>,
+}
+
+impl Default for Separators {
+   fn default() -> Separators {
+      Separators {
+         separators: [";", "/", " feat. ", " ft. ", " featuring "]
+            .iter()
+            .map(|x| x.to_string())
+            .collect(),
+         exceptions: vec!["AC/DC".to_owned()],
+      }
+   }
+}
+
+impl Separators {
+   pub fn split<'a>(&self, text: &'a str) -> Vec<&'a str> {
+      // ASCII lowercasing leaves every character where it was, so positions in it are positions in the text
+      let lower = text.to_ascii_lowercase();
+      let separators: Vec<String> = self
+         .separators
+         .iter()
+         .filter(|x| !x.is_empty())
+         .map(|x| x.to_ascii_lowercase())
+         .collect();
+      let exceptions: Vec<String> = self.exceptions.iter().map(|x| x.to_ascii_lowercase()).collect();
+
+      let mut parts = Vec::new();
+      let mut start = 0;
+      let mut pos = 0;
+      while let Some(c) = text[pos..].chars().next() {
+         let rest = &lower[pos..];
+         if let Some(exception) = exceptions.iter().find(|x| !x.is_empty() && rest.starts_with(&x[..])) {
+            pos += exception.len();
+         } else if let Some(separator) = separators.iter().find(|x| rest.starts_with(&x[..])) {
+            parts.push(&text[start..pos]);
+            pos += separator.len();
+            start = pos;
+         } else {
+            pos += c.len_utf8();
+         }
+      }
+      parts.push(&text[start..]);
+      parts.into_iter().map(str::trim).filter(|x| !x.is_empty()).collect()
+   }
+
+   /// Splits each value, and leaves out parts already found
+   pub fn split_all<'a>(&self, values: Vec<&'a str>) -> Vec<&'a str> {
+      let mut parts: Vec<&str> = Vec::new();
+      for part in values.into_iter().flat_map(|x| self.split(x)) {
+         if !parts.contains(&part) {
+            parts.push(part);
+         }
+      }
+      parts
+   }
+}
+
+// Splits text like "3/12" into the number and the total
+pub(crate) fn parse_number_pair(text: &str) -> (Option<u32>, Option<u32>) {
+   let mut parts = text.splitn(2, '/');
+   let number = parts.next().and_then(|x| x.trim().parse().ok());
+   let total = parts.next().and_then(|x| x.trim().parse().ok());
+   (number, total)
+}
+
+// Pulls the year out of dates like "2019-01-25"
+pub(crate) fn parse_year(text: &str) -> Option<u16> {
+   text.trim().get(0..4).and_then(|x| x.parse().ok())
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+
+   #[test]
+   fn split_artists() {
+      let separators = Separators::default();
+      assert_eq!(
+         separators.split("Daft Punk Feat. Pharrell Williams; Nile Rodgers"),
+         ["Daft Punk", "Pharrell Williams", "Nile Rodgers"]
+      );
+      assert_eq!(separators.split("ac/dc / Sigur R\u{f3}s"), ["ac/dc", "Sigur R\u{f3}s"]);
+      assert_eq!(separators.split("Simon & Garfunkel"), ["Simon & Garfunkel"]);
+      assert_eq!(
+         separators.split_all(vec!["Rock/Pop", "Pop;", "Jazz"]),
+         ["Rock", "Pop", "Jazz"]
+      );
+      let ampersand = Separators {
+         separators: vec![" & ".to_owned()],
+         exceptions: Vec::new(),
+      };
+      assert_eq!(ampersand.split("Simon & Garfunkel"), ["Simon", "Garfunkel"]);
+   }
+}
@@ -0,0 +1,100 @@
+use crate::id3::{self, ParseOptions, TagParseError};
+use std::fmt;
+use std::io::Read;
+
+/// The kinds of problem `validate` reports. Scripts can match on their names, which don't change.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ErrorCategory {
+   NoTag,
+   UnsupportedVersion,
+   /// The tag header or extended header is wrong, so none of the frames could be read
+   Malformed,
+   /// A frame failed to parse. The other frames were still read.
+   Frame,
+   Io,
+}
+
+impl fmt::Display for ErrorCategory {
+   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+      match self {
+         ErrorCategory::NoTag => write!(f, "NoTag"),
+         ErrorCategory::UnsupportedVersion => write!(f, "UnsupportedVersion"),
+         ErrorCategory::Malformed => write!(f, "Malformed"),
+         ErrorCategory::Frame => write!(f, "Frame"),
+         ErrorCategory::Io => write!(f, "Io"),
+      }
+   }
+}
+
+impl<'a> From<&'a TagParseError> for ErrorCategory {
+   fn from(e: &TagParseError) -> ErrorCategory {
+      match e {
+         TagParseError::NoTag => ErrorCategory::NoTag,
+         TagParseError::UnsupportedVersion(_) => ErrorCategory::UnsupportedVersion,
+         TagParseError::TagTooSmall | TagParseError::InvalidSize | TagParseError::CrcMismatch { .. } => {
+            ErrorCategory::Malformed
+         }
+         TagParseError::Io(_) => ErrorCategory::Io,
+      }
+   }
+}
+
+/// One thing that went wrong reading a file's tag
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileError {
+   pub category: ErrorCategory,
+   /// The frame that failed, for `ErrorCategory::Frame`
+   pub frame: Option<[u8; 4]>,
+   pub message: String,
+}
+
+/// Reads the ID3v2 tag at the start of the source as the parsers would, and collects everything that went wrong
+/// instead of logging it. A file with nothing wrong gives no errors.
+pub fn validate<S: Read>(source: &mut S, options: ParseOptions) -> Vec<FileError> {
+   let parser = match id3::parse_source(source, options) {
+      Ok(v) => v,
+      Err(e) => {
+         return vec![FileError {
+            category: ErrorCategory::from(&e),
+            frame: None,
+            message: e.to_string(),
+         }]
+      }
+   };
+   parser
+      .filter_map(|x| x.err())
+      .map(|e| FileError {
+         category: ErrorCategory::Frame,
+         frame: Some(e.name),
+         message: e.reason.to_string(),
+      })
+      .collect()
+}
+
+mod test {
+   #[cfg(test)]
+   use super::*;
+
+   #[test]
+   fn categories() {
+      let validate = |bytes: &[u8]| validate(&mut &bytes[..], ParseOptions::default());
+
+      let tag = b"ID3\x04\x00\x00\x00\x00\x00\x19TIT2\x00\x00\x00\x03\x00\x00\x00HiTRCK\x00\x00\x00\x02\x00\x00\x00x";
+      assert_eq!(
+         validate(tag),
+         [FileError {
+            category: ErrorCategory::Frame,
+            frame: Some(*b"TRCK"),
+            message: "invalid track: invalid track number: invalid digit found in string".to_owned(),
+         }]
+      );
+      let categories = |bytes: &[u8]| validate(bytes).iter().map(|x| x.category).collect::<Vec<_>>();
+      assert_eq!(categories(&tag[..20]), [ErrorCategory::Io]);
+      assert_eq!(
+         categories(b"ID3\x03\x00\x00\x00\x00\x00\x00"),
+         [ErrorCategory::UnsupportedVersion]
+      );
+      assert_eq!(categories(&[0xff; 100]), [ErrorCategory::NoTag]);
+      assert!(validate(b"ID3\x04\x00\x00\x00\x00\x00\x0dTIT2\x00\x00\x00\x03\x00\x00\x00Hi").is_empty());
+   }
+}
@@ -0,0 +1,124 @@
+use crate::tag::{self, Tag};
+use byteorder::{ByteOrder, LittleEndian};
+
+/// The key/value comments used by FLAC, Vorbis, and Opus.
+/// Keys are case insensitive and may repeat.
+#[derive(Clone, Debug, Default)]
+pub struct VorbisComment {
+   pub vendor: String,
+   pub comments: Vec<(String, String)>,
+}
+
+impl VorbisComment {
+   /// Returns `None` if the comment block is truncated
+   pub fn parse(bytes: &[u8]) -> Option<VorbisComment> {
+      let mut rest = bytes;
+      let vendor = read_string(&mut rest)?;
+
+      let count = LittleEndian::read_u32(rest.get(0..4)?);
+      rest = &rest[4..];
+
+      // We don't trust the count enough to allocate it up front
+      let mut comments = Vec::new();
+      for _ in 0..count {
+         let comment = read_string(&mut rest)?;
+         let mut parts = comment.splitn(2, '=');
+         let key = parts.next().unwrap_or("").to_string();
+         let value = parts.next().unwrap_or("").to_string();
+         comments.push((key, value));
+      }
+
+      Some(VorbisComment { vendor, comments })
+   }
+
+   /// The first value of the given key
+   pub fn get(&self, key: &str) -> Option<&str> {
+      self
+         .comments
+         .iter()
+         .find(|(k, _)| k.eq_ignore_ascii_case(key))
+         .map(|(_, v)| v.as_str())
+   }
+
+   pub fn get_all<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+      self
+         .comments
+         .iter()
+         .filter(move |(k, _)| k.eq_ignore_ascii_case(key))
+         .map(|(_, v)| v.as_str())
+   }
+
+   // TRACKNUMBER is often written as "3/12" instead of using TRACKTOTAL
+   fn number_pair(&self, number_key: &str, total_keys: &[&str]) -> (Option<u32>, Option<u32>) {
+      let (number, total) = self.get(number_key).map_or((None, None), tag::parse_number_pair);
+      let total = total.or_else(|| {
+         total_keys
+            .iter()
+            .filter_map(|key| self.get(key))
+            .find_map(|x| x.trim().parse().ok())
+      });
+      (number, total)
+   }
+}
+
+// Strings are prefixed by their length as a 32 bit little endian integer
+fn read_string(rest: &mut &[u8]) -> Option<String> {
+   let len = LittleEndian::read_u32(rest.get(0..4)?) as usize;
+   let bytes = rest.get(4..4usize.checked_add(len)?)?;
+   *rest = &rest[4 + len..];
+   Some(String::from_utf8_lossy(bytes).into_owned())
+}
+
+impl Tag for VorbisComment {
+   fn title(&self) -> Option<&str> {
+      self.get("TITLE")
+   }
+
+   fn artist(&self) -> Option<&str> {
+      self.get("ARTIST")
+   }
+
+   fn album(&self) -> Option<&str> {
+      self.get("ALBUM")
+   }
+
+   fn album_artist(&self) -> Option<&str> {
+      self.get("ALBUMARTIST").or_else(|| self.get("ALBUM ARTIST"))
+   }
+
+   fn genre(&self) -> Option<&str> {
+      self.get("GENRE")
+   }
+
+   fn year(&self) -> Option<u16> {
+      self.get("DATE").or_else(|| self.get("YEAR")).and_then(tag::parse_year)
+   }
+
+   fn track_number(&self) -> Option<u32> {
+      self.number_pair("TRACKNUMBER", &[]).0
+   }
+
+   fn track_total(&self) -> Option<u32> {
+      self.number_pair("TRACKNUMBER", &["TRACKTOTAL", "TOTALTRACKS"]).1
+   }
+
+   fn disc_number(&self) -> Option<u32> {
+      self.number_pair("DISCNUMBER", &[]).0
+   }
+
+   fn disc_total(&self) -> Option<u32> {
+      self.number_pair("DISCNUMBER", &["DISCTOTAL", "TOTALDISCS"]).1
+   }
+
+   fn comment(&self) -> Option<&str> {
+      self.get("COMMENT").or_else(|| self.get("DESCRIPTION"))
+   }
+
+   fn artist_values(&self) -> Vec<&str> {
+      self.get_all("ARTIST").collect()
+   }
+
+   fn genre_values(&self) -> Vec<&str> {
+      self.get_all("GENRE").collect()
+   }
+}
@@ -0,0 +1,56 @@
+//! walnut for the web. Build with `wasm-pack build wasm` and then, from JavaScript:
+//!
+//! ```js
+//! const tag = parseTag(new Uint8Array(await file.arrayBuffer()));
+//! console.log(tag.format, tag.artist, tag.title);
+//! ```
+
+use js_sys::{Array, Object, Reflect, Uint8Array};
+use std::io::Cursor;
+use walnut::art;
+use walnut::probe;
+use walnut::tag::Tag;
+use wasm_bindgen::prelude::*;
+
+/// Reads the metadata of a whole file, in any format walnut knows. The result is an object with the format ("mpeg",
+/// "flac", ...), the common fields (`title`, `artist`, `album`, `albumArtist`, `genre`, `year`, `track`,
+/// `trackTotal`, `disc`, `discTotal`, and `comment`, each left undefined if the file doesn't have it), and
+/// `pictures`, an array of `{ type, mimeType, description, data }` with the data as a Uint8Array. Throws an Error if
+/// the file can't be read.
+#[wasm_bindgen(js_name = parseTag)]
+pub fn parse_tag(bytes: &[u8]) -> Result<JsValue, JsValue> {
+   let metadata = probe::probe(&mut Cursor::new(bytes)).map_err(|e| js_sys::Error::new(&e.to_string()))?;
+
+   let tag = Object::new();
+   let format = format!("{:?}", metadata.format()).to_lowercase();
+   set(&tag, "format", format.into())?;
+   let text = |x: Option<&str>| x.map_or(JsValue::UNDEFINED, JsValue::from);
+   set(&tag, "title", text(metadata.title()))?;
+   set(&tag, "artist", text(metadata.artist()))?;
+   set(&tag, "album", text(metadata.album()))?;
+   set(&tag, "albumArtist", text(metadata.album_artist()))?;
+   set(&tag, "genre", text(metadata.genre()))?;
+   set(&tag, "comment", text(metadata.comment()))?;
+   let number = |x: Option<u32>| x.map_or(JsValue::UNDEFINED, JsValue::from);
+   set(&tag, "year", number(metadata.year().map(u32::from)))?;
+   set(&tag, "track", number(metadata.track_number()))?;
+   set(&tag, "trackTotal", number(metadata.track_total()))?;
+   set(&tag, "disc", number(metadata.disc_number()))?;
+   set(&tag, "discTotal", number(metadata.disc_total()))?;
+
+   let pictures = Array::new();
+   for artwork in art::pictures(&metadata) {
+      let picture = Object::new();
+      set(&picture, "type", artwork.picture_type.into())?;
+      set(&picture, "mimeType", artwork.mime_type.into())?;
+      set(&picture, "description", artwork.description.into())?;
+      set(&picture, "data", Uint8Array::from(&artwork.data[..]).into())?;
+      pictures.push(&picture);
+   }
+   set(&tag, "pictures", pictures.into())?;
+   Ok(tag.into())
+}
+
+fn set(object: &Object, key: &str, value: JsValue) -> Result<(), JsValue> {
+   Reflect::set(object, &JsValue::from(key), &value).map(|_| ())
+}